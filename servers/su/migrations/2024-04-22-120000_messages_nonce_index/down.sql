@@ -0,0 +1 @@
+DROP INDEX IF EXISTS idx_messages_process_epoch_nonce;
@@ -0,0 +1 @@
+CREATE INDEX idx_messages_process_epoch_nonce ON messages(process_id, epoch, nonce);
@@ -0,0 +1 @@
+CREATE INDEX idx_messages_message_tags ON messages USING GIN ((message_data -> 'message' -> 'tags') jsonb_path_ops);
@@ -0,0 +1,6 @@
+CREATE TABLE api_tokens (
+    row_id SERIAL PRIMARY KEY,
+    token_hash VARCHAR(255) NOT NULL UNIQUE,
+    owner VARCHAR(255),
+    process_ids JSONB NOT NULL DEFAULT '[]'
+);
@@ -0,0 +1,2 @@
+DROP INDEX IF EXISTS idx_processes_owner_address;
+DROP INDEX IF EXISTS idx_messages_owner_address;
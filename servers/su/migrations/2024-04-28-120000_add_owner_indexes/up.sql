@@ -0,0 +1,2 @@
+CREATE INDEX idx_processes_owner_address ON processes ((process_data -> 'owner' ->> 'address'), row_id);
+CREATE INDEX idx_messages_owner_address ON messages ((message_data -> 'message' -> 'owner' ->> 'address'), row_id);
@@ -0,0 +1,2 @@
+ALTER TABLE processes DROP COLUMN IF EXISTS bundle_checksum;
+ALTER TABLE messages DROP COLUMN IF EXISTS bundle_checksum;
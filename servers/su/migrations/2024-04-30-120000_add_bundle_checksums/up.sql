@@ -0,0 +1,3 @@
+-- existing rows keep a NULL checksum and are not verified
+ALTER TABLE processes ADD COLUMN bundle_checksum VARCHAR(64);
+ALTER TABLE messages ADD COLUMN bundle_checksum VARCHAR(64);
@@ -0,0 +1,4 @@
+CREATE TABLE store_meta (
+    key VARCHAR(255) PRIMARY KEY,
+    value TEXT NOT NULL
+);
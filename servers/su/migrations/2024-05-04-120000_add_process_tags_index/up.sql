@@ -0,0 +1 @@
+CREATE INDEX idx_processes_tags ON processes USING GIN ((process_data -> 'tags') jsonb_path_ops);
@@ -0,0 +1,2 @@
+ALTER TABLE processes DROP COLUMN IF EXISTS bundle_location;
+ALTER TABLE messages DROP COLUMN IF EXISTS bundle_location;
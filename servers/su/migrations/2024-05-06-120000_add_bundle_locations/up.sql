@@ -0,0 +1,3 @@
+-- the blob store key of an offloaded bundle, the bundle column is then empty
+ALTER TABLE processes ADD COLUMN bundle_location VARCHAR;
+ALTER TABLE messages ADD COLUMN bundle_location VARCHAR;
@@ -0,0 +1,5 @@
+CREATE TABLE event_outbox (
+    row_id SERIAL PRIMARY KEY,
+    process_id VARCHAR NOT NULL,
+    event JSONB NOT NULL
+);
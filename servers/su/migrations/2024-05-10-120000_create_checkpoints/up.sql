@@ -0,0 +1,11 @@
+CREATE TABLE checkpoints (
+    row_id SERIAL PRIMARY KEY,
+    process_id VARCHAR NOT NULL,
+    epoch INTEGER NOT NULL,
+    nonce INTEGER NOT NULL,
+    checkpoint_id VARCHAR NOT NULL,
+    cu VARCHAR,
+    timestamp BIGINT NOT NULL,
+    UNIQUE (process_id, checkpoint_id)
+);
+CREATE INDEX idx_checkpoints_process_slot ON checkpoints (process_id, epoch, nonce);
@@ -0,0 +1,12 @@
+CREATE TABLE attestations (
+    row_id SERIAL PRIMARY KEY,
+    process_id VARCHAR NOT NULL,
+    epoch INTEGER NOT NULL,
+    nonce INTEGER NOT NULL,
+    hash_chain TEXT NOT NULL,
+    root VARCHAR NOT NULL,
+    leaf_count BIGINT NOT NULL,
+    attestation_id VARCHAR NOT NULL,
+    timestamp BIGINT NOT NULL,
+    UNIQUE (process_id, epoch, nonce)
+);
@@ -0,0 +1,15 @@
+CREATE TABLE cold_segments (
+    row_id SERIAL PRIMARY KEY,
+    process_id VARCHAR NOT NULL,
+    from_epoch INTEGER NOT NULL,
+    from_nonce INTEGER NOT NULL,
+    to_epoch INTEGER NOT NULL,
+    to_nonce INTEGER NOT NULL,
+    from_timestamp BIGINT NOT NULL,
+    to_timestamp BIGINT NOT NULL,
+    message_count INTEGER NOT NULL,
+    segment_key VARCHAR NOT NULL,
+    segment_size BIGINT NOT NULL
+);
+
+CREATE INDEX idx_cold_segments_process_timestamp ON cold_segments (process_id, from_timestamp);
@@ -0,0 +1,11 @@
+CREATE TABLE crons (
+    row_id SERIAL PRIMARY KEY,
+    process_id VARCHAR NOT NULL,
+    cron_interval VARCHAR NOT NULL,
+    interval_ms BIGINT NOT NULL,
+    tags JSONB NOT NULL,
+    next_run BIGINT NOT NULL,
+    UNIQUE (process_id, cron_interval)
+);
+
+CREATE INDEX idx_crons_next_run ON crons (next_run);
@@ -0,0 +1,36 @@
+CREATE TABLE assignments (
+    row_id SERIAL PRIMARY KEY,
+    process_id VARCHAR NOT NULL,
+    assignment_id VARCHAR NOT NULL UNIQUE,
+    message_id VARCHAR NOT NULL,
+    epoch INTEGER NOT NULL,
+    nonce INTEGER NOT NULL,
+    timestamp BIGINT NOT NULL,
+    hash_chain TEXT NOT NULL,
+    block_height VARCHAR
+);
+
+CREATE INDEX idx_assignments_process_slot ON assignments (process_id, epoch, nonce);
+CREATE INDEX idx_assignments_message ON assignments (message_id);
+
+-- messages already moved to cold segments are not copied
+INSERT INTO assignments (
+    process_id, assignment_id, message_id, epoch, nonce, timestamp, hash_chain, block_height
+)
+SELECT
+    m.process_id,
+    m.assignment_id,
+    m.message_id,
+    m.epoch,
+    m.nonce,
+    m.timestamp,
+    m.hash_chain,
+    (
+        SELECT t->>'value'
+        FROM jsonb_array_elements(m.message_data->'assignment'->'tags') AS t
+        WHERE t->>'name' = 'Block-Height'
+        LIMIT 1
+    )
+FROM messages m
+WHERE m.assignment_id IS NOT NULL
+ON CONFLICT (assignment_id) DO NOTHING;
@@ -0,0 +1,11 @@
+CREATE TABLE assignment_decisions (
+    row_id SERIAL PRIMARY KEY,
+    process_id VARCHAR NOT NULL,
+    scheduler_url VARCHAR NOT NULL,
+    strategy VARCHAR NOT NULL,
+    region VARCHAR,
+    candidates JSONB NOT NULL,
+    timestamp BIGINT NOT NULL
+);
+
+CREATE INDEX idx_assignment_decisions_process ON assignment_decisions (process_id, row_id);
@@ -0,0 +1,9 @@
+CREATE TABLE upload_receipts (
+    row_id SERIAL PRIMARY KEY,
+    bundle_id VARCHAR NOT NULL UNIQUE,
+    timestamp BIGINT NOT NULL,
+    version VARCHAR NOT NULL,
+    public_key TEXT NOT NULL,
+    signature TEXT NOT NULL,
+    deadline_height BIGINT NOT NULL
+);
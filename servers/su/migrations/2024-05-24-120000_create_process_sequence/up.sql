@@ -0,0 +1,13 @@
+CREATE TABLE process_sequence (
+    process_id VARCHAR PRIMARY KEY,
+    epoch INTEGER NOT NULL,
+    nonce INTEGER NOT NULL,
+    hash_chain TEXT NOT NULL,
+    assignment_id VARCHAR NOT NULL
+);
+
+-- the latest assignment of every process sequenced so far
+INSERT INTO process_sequence (process_id, epoch, nonce, hash_chain, assignment_id)
+SELECT DISTINCT ON (process_id) process_id, epoch, nonce, hash_chain, assignment_id
+FROM assignments
+ORDER BY process_id, epoch DESC, nonce DESC;
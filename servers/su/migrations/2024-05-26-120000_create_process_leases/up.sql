@@ -0,0 +1,8 @@
+-- the su instance sequencing each process when several share the database
+CREATE TABLE process_leases (
+    process_id VARCHAR PRIMARY KEY,
+    holder VARCHAR NOT NULL,
+    expires_at TIMESTAMPTZ NOT NULL
+);
+
+CREATE INDEX idx_process_leases_holder ON process_leases (holder);
@@ -0,0 +1 @@
+-- the rows seeded from messages are not told apart from the others
@@ -0,0 +1,23 @@
+-- the latest message of every process, assignments has no row for those saved
+-- before assignment ids were stored. Messages in the old json shape only have
+-- their assignment id in the bundle, the su reads their latest message instead
+INSERT INTO process_sequence (process_id, epoch, nonce, hash_chain, assignment_id)
+SELECT process_id, epoch, nonce, hash_chain, assignment_id
+FROM (
+    SELECT DISTINCT ON (process_id)
+        process_id,
+        epoch,
+        nonce,
+        hash_chain,
+        COALESCE(assignment_id, message_data->'assignment'->>'id') AS assignment_id
+    FROM messages
+    ORDER BY process_id, epoch DESC, nonce DESC
+) latest
+WHERE assignment_id IS NOT NULL
+ON CONFLICT (process_id) DO UPDATE SET
+    epoch = EXCLUDED.epoch,
+    nonce = EXCLUDED.nonce,
+    hash_chain = EXCLUDED.hash_chain,
+    assignment_id = EXCLUDED.assignment_id
+WHERE (process_sequence.epoch, process_sequence.nonce)
+    < (EXCLUDED.epoch, EXCLUDED.nonce);
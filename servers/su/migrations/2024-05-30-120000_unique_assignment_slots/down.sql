@@ -0,0 +1,2 @@
+DROP INDEX IF EXISTS idx_assignments_process_slot;
+CREATE INDEX idx_assignments_process_slot ON assignments (process_id, epoch, nonce);
@@ -0,0 +1,3 @@
+-- a slot is taken once, a second save of it fails instead of forking the schedule
+DROP INDEX IF EXISTS idx_assignments_process_slot;
+CREATE UNIQUE INDEX idx_assignments_process_slot ON assignments (process_id, epoch, nonce);
@@ -0,0 +1,2 @@
+DROP TABLE IF EXISTS cold_messages;
+ALTER TABLE cold_segments DROP COLUMN IF EXISTS bundles_key;
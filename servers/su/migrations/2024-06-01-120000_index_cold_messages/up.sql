@@ -0,0 +1,19 @@
+-- NULL for the json segments written before the bundles had a file of their own
+ALTER TABLE cold_segments ADD COLUMN bundles_key VARCHAR;
+
+-- every tiered message by id, for the duplicate check and lookups by id
+CREATE TABLE cold_messages (
+    row_id SERIAL PRIMARY KEY,
+    segment_row_id INTEGER NOT NULL REFERENCES cold_segments (row_id) ON DELETE CASCADE,
+    position INTEGER NOT NULL,
+    process_id VARCHAR NOT NULL,
+    message_id VARCHAR NOT NULL,
+    assignment_id VARCHAR,
+    epoch INTEGER NOT NULL,
+    nonce INTEGER NOT NULL,
+    carries_message BOOLEAN NOT NULL
+);
+
+CREATE INDEX idx_cold_messages_message_id ON cold_messages (message_id);
+CREATE INDEX idx_cold_messages_assignment_id ON cold_messages (assignment_id);
+CREATE INDEX idx_cold_messages_segment ON cold_messages (segment_row_id);
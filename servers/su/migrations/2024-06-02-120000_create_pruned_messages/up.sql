@@ -0,0 +1,11 @@
+-- messages whose rows retention deleted, a retried write of one is still a duplicate
+CREATE TABLE pruned_messages (
+    row_id SERIAL PRIMARY KEY,
+    message_id VARCHAR NOT NULL,
+    assignment_id VARCHAR,
+    process_id VARCHAR NOT NULL,
+    epoch INTEGER NOT NULL,
+    nonce INTEGER NOT NULL
+);
+
+CREATE INDEX idx_pruned_messages_message_id ON pruned_messages (message_id);
@@ -0,0 +1,2 @@
+DROP TABLE IF EXISTS event_outbox_dead;
+ALTER TABLE event_outbox DROP COLUMN IF EXISTS attempts;
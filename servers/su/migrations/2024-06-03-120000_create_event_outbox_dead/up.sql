@@ -0,0 +1,11 @@
+-- publish attempts of the oldest event, counted while the publisher rejects it
+ALTER TABLE event_outbox ADD COLUMN attempts INTEGER NOT NULL DEFAULT 0;
+
+-- events the publisher kept rejecting, moved out of the outbox so the rest are relayed
+CREATE TABLE event_outbox_dead (
+    row_id SERIAL PRIMARY KEY,
+    process_id VARCHAR NOT NULL,
+    event JSONB NOT NULL,
+    error TEXT NOT NULL,
+    dead_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
@@ -0,0 +1,24 @@
+-- the peaks of the merkle tree of an attestation, the next one only adds the new leaves
+ALTER TABLE attestations ADD COLUMN frontier JSONB;
+
+-- rows saved before messages had an assignment_id column carry the id in their data
+INSERT INTO assignments (
+    process_id, assignment_id, message_id, epoch, nonce, timestamp, hash_chain, block_height
+)
+SELECT
+    m.process_id,
+    m.message_data->'assignment'->>'id',
+    m.message_id,
+    m.epoch,
+    m.nonce,
+    m.timestamp,
+    m.hash_chain,
+    (
+        SELECT t->>'value'
+        FROM jsonb_array_elements(m.message_data->'assignment'->'tags') AS t
+        WHERE t->>'name' = 'Block-Height'
+        LIMIT 1
+    )
+FROM messages m
+WHERE m.assignment_id IS NULL AND m.message_data->'assignment'->>'id' IS NOT NULL
+ON CONFLICT DO NOTHING;
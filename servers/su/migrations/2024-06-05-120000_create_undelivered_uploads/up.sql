@@ -0,0 +1,7 @@
+-- bundles the uploader gave up on, handed to it again until one gets through
+CREATE TABLE undelivered_uploads (
+    item_id VARCHAR PRIMARY KEY,
+    bundle BYTEA NOT NULL,
+    error TEXT NOT NULL,
+    failed_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
@@ -0,0 +1,6 @@
+-- the wallet whose scheduler signs the assignments of a process, fixed at its spawn
+CREATE TABLE process_wallets (
+    scheduler VARCHAR NOT NULL,
+    process_id VARCHAR NOT NULL UNIQUE,
+    PRIMARY KEY (scheduler, process_id)
+);
@@ -0,0 +1,435 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use reqwest::Url;
+use ring::constant_time::verify_slices_are_equal;
+use serde::Serialize;
+use serde_json::json;
+
+use super::archive;
+use super::config::AoConfig;
+use super::core::backfill;
+use super::core::fleet;
+use super::core::flows::Deps;
+use super::core::location;
+use super::core::router;
+use super::core::runtime::{self, RuntimeUpdate};
+use super::rebalance::{self, RebalanceRequest};
+
+// how many admin actions the audit trail keeps
+const AUDIT_ENTRIES: usize = 1000;
+
+#[derive(Debug)]
+pub enum AdminError {
+    // no admin token configured or the wrong one sent
+    Unauthorized(String),
+    Failed(String),
+}
+
+impl From<String> for AdminError {
+    fn from(error: String) -> Self {
+        AdminError::Failed(error)
+    }
+}
+
+#[derive(Serialize, Clone)]
+struct AuditEntry {
+    timestamp: u64,
+    action: String,
+    detail: Option<String>,
+    ok: bool,
+}
+
+/*
+    Operational endpoints, only reachable with the
+    ADMIN_TOKEN sent as a bearer token. Without one
+    configured the admin api is disabled. Every call,
+    including rejected ones, goes into an in memory
+    audit trail that is also logged.
+*/
+pub struct AdminApi {
+    deps: Arc<Deps>,
+    audit: Mutex<VecDeque<AuditEntry>>,
+}
+
+impl AdminApi {
+    pub fn new(deps: Arc<Deps>) -> Self {
+        AdminApi {
+            deps,
+            audit: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    // the effective configuration with secrets removed
+    pub fn config(&self, token: Option<String>) -> Result<String, AdminError> {
+        self.authorize(&token, "config")?;
+        let config = AoConfig::new(Some(self.deps.config.mode()))
+            .map_err(|e| AdminError::Failed(format!("{:?}", e)))?;
+        self.record("config", None, true);
+        Ok(config.redacted().to_string())
+    }
+
+    // adds a scheduler for the router to assign processes to
+    pub async fn register_scheduler(
+        &self,
+        token: Option<String>,
+        url: String,
+    ) -> Result<String, AdminError> {
+        self.authorize(&token, "register_scheduler")?;
+        let result = self.try_register_scheduler(&url).await;
+        self.record("register_scheduler", Some(url.clone()), result.is_ok());
+        let created = result?;
+        Ok(json!({ "url": url, "created": created }).to_string())
+    }
+
+    async fn try_register_scheduler(&self, url: &String) -> Result<bool, String> {
+        if self.deps.config.mode() != "router" {
+            return Err("Schedulers can only be registered on a router".to_string());
+        }
+        Url::parse(url).map_err(|e| format!("Invalid scheduler url: {}", e))?;
+        router::register_scheduler(&self.deps, url).await
+    }
+
+    // routes a process to another su after it was imported there
+    pub async fn move_process(
+        &self,
+        token: Option<String>,
+        process_id: String,
+        url: String,
+    ) -> Result<String, AdminError> {
+        self.authorize(&token, "move_process")?;
+        let result = match self.deps.config.mode().as_str() {
+            "router" => router::move_process(&self.deps, &process_id, &url).await,
+            _ => Err("Processes can only be moved on a router".to_string()),
+        };
+        self.record(
+            "move_process",
+            Some(format!("{} {}", process_id, url)),
+            result.is_ok(),
+        );
+        let moved = result?;
+        Ok(json!({ "process_id": process_id, "url": url, "moved": moved }).to_string())
+    }
+
+    /*
+        turns writes to a process away while a router moves
+        it, answers once no write is in flight with the count
+        of its messages the export has to match
+    */
+    pub async fn fence_process(
+        &self,
+        token: Option<String>,
+        process_id: String,
+    ) -> Result<String, AdminError> {
+        self.authorize(&token, "fence_process")?;
+        let result = self.try_fence_process(&process_id).await;
+        self.record("fence_process", Some(process_id.clone()), result.is_ok());
+        let count = result?;
+        Ok(json!({ "process_id": process_id, "fenced": true, "count": count }).to_string())
+    }
+
+    async fn try_fence_process(&self, process_id: &str) -> Result<i64, String> {
+        if self.deps.config.mode() == "router" {
+            return Err("Processes are fenced on the su that schedules them".to_string());
+        }
+        self.deps.data_store.get_process(process_id).await?;
+        self.deps.scheduler.fence(process_id).await?;
+        match self.deps.data_store.get_message_count(process_id).await {
+            Ok(message_count) => Ok(message_count.count),
+            Err(e) => {
+                self.deps.scheduler.unfence(process_id);
+                Err(e.into())
+            }
+        }
+    }
+
+    // takes writes to a process again after a move was abandoned
+    pub fn unfence_process(
+        &self,
+        token: Option<String>,
+        process_id: String,
+    ) -> Result<String, AdminError> {
+        self.authorize(&token, "unfence_process")?;
+        let released = self.deps.scheduler.unfence(&process_id);
+        self.record("unfence_process", Some(process_id.clone()), true);
+        Ok(json!({ "process_id": process_id, "released": released }).to_string())
+    }
+
+    /*
+        the export file of a process, a router moving the
+        process reads it and imports it on another su
+    */
+    pub async fn export_process(
+        &self,
+        token: Option<String>,
+        process_id: String,
+    ) -> Result<String, AdminError> {
+        self.authorize(&token, "export_process")?;
+        let result = archive::export_archive(&*self.deps.data_store, &process_id)
+            .await
+            .and_then(|bytes| String::from_utf8(bytes).map_err(|e| format!("{}", e)));
+        self.record("export_process", Some(process_id), result.is_ok());
+        Ok(result?)
+    }
+
+    pub async fn import_process(
+        &self,
+        token: Option<String>,
+        archive: Vec<u8>,
+    ) -> Result<String, AdminError> {
+        self.authorize(&token, "import_process")?;
+        let result =
+            archive::import_archive(&*self.deps.data_store, &self.deps.scheduler, &archive).await;
+        self.record(
+            "import_process",
+            result.as_ref().ok().cloned(),
+            result.is_ok(),
+        );
+        Ok(json!({ "result": result? }).to_string())
+    }
+
+    // processes without a new message for idle_ms, candidates for a move
+    pub async fn idle_processes(
+        &self,
+        token: Option<String>,
+        idle_ms: u64,
+        limit: i64,
+    ) -> Result<String, AdminError> {
+        self.authorize(&token, "idle_processes")?;
+        let before = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as i64)
+            .unwrap_or(0)
+            - idle_ms as i64;
+        let result = self.deps.data_store.get_idle_processes(before, limit).await;
+        self.record("idle_processes", None, result.is_ok());
+        let process_ids = result.map_err(|e| AdminError::Failed(format!("{:?}", e)))?;
+        Ok(json!({ "process_ids": process_ids }).to_string())
+    }
+
+    // moves idle processes from overloaded schedulers, router mode only
+    pub async fn rebalance(
+        &self,
+        token: Option<String>,
+        request: RebalanceRequest,
+    ) -> Result<String, AdminError> {
+        self.authorize(&token, "rebalance")?;
+        let detail = format!("{:?}", request);
+        let result = rebalance::rebalance(&self.deps, request).await;
+        self.record("rebalance", Some(detail), result.is_ok());
+        Ok(result?)
+    }
+
+    // throughput, storage and lag of this su, polled by the router
+    pub async fn stats(&self, token: Option<String>) -> Result<String, AdminError> {
+        self.authorize(&token, "stats")?;
+        let result = fleet::scheduler_stats(&self.deps).await;
+        self.record("stats", None, result.is_ok());
+        Ok(json!(result?).to_string())
+    }
+
+    /*
+        router mode, every scheduler with its process count,
+        weight and the stats of its latest poll, along with
+        totals over the schedulers that answered
+    */
+    pub async fn fleet(&self, token: Option<String>) -> Result<String, AdminError> {
+        self.authorize(&token, "fleet")?;
+        let result = self.try_fleet().await;
+        self.record("fleet", None, result.is_ok());
+        Ok(result?)
+    }
+
+    async fn try_fleet(&self) -> Result<String, String> {
+        if self.deps.config.mode() != "router" {
+            return Err("The fleet overview is only available on a router".to_string());
+        }
+        let schedulers = self.deps.data_store.get_all_schedulers().await?;
+        let mut messages_last_minute = 0;
+        let mut storage_bytes = 0;
+        let mut reachable = 0;
+        let mut entries = vec![];
+        for scheduler in schedulers.iter() {
+            let report = self.deps.fleet.report(&scheduler.url);
+            if let Some(stats) = report.as_ref().and_then(|r| r.stats.as_ref()) {
+                messages_last_minute += stats.messages_last_minute;
+                storage_bytes += stats.storage_bytes;
+                reachable += 1;
+            }
+            entries.push(json!({
+                "url": scheduler.url,
+                "process_count": scheduler.process_count,
+                "weight": self.deps.runtime.scheduler_weight(&scheduler.url),
+                "region": self.deps.runtime.scheduler_region(&scheduler.url),
+                "report": report,
+            }));
+        }
+        Ok(json!({
+            "schedulers": entries,
+            "totals": {
+                "schedulers": schedulers.len(),
+                "reachable": reachable,
+                "messages_last_minute": messages_last_minute,
+                "storage_bytes": storage_bytes,
+            },
+        })
+        .to_string())
+    }
+
+    // router mode, the latest assignment decisions, of one process when given
+    pub async fn assignment_decisions(
+        &self,
+        token: Option<String>,
+        process_id: Option<String>,
+        limit: i64,
+    ) -> Result<String, AdminError> {
+        self.authorize(&token, "assignment_decisions")?;
+        let result = match self.deps.config.mode().as_str() {
+            "router" => self
+                .deps
+                .data_store
+                .get_assignment_decisions(&process_id, limit)
+                .await
+                .map_err(|e| format!("{:?}", e)),
+            _ => Err("Assignment decisions are only kept on a router".to_string()),
+        };
+        self.record("assignment_decisions", process_id, result.is_ok());
+        Ok(json!({ "decisions": result? }).to_string())
+    }
+
+    pub fn flush_cache(&self, token: Option<String>) -> Result<String, AdminError> {
+        self.authorize(&token, "flush_cache")?;
+        self.deps.cache.clear();
+        self.deps.route_cache.clear();
+        self.record("flush_cache", None, true);
+        Ok(json!({ "flushed": true }).to_string())
+    }
+
+    /*
+        rebuilds a process from arweave in the background,
+        it outlives the request so the outcome is logged
+    */
+    pub fn backfill(
+        &self,
+        token: Option<String>,
+        process_id: String,
+    ) -> Result<String, AdminError> {
+        self.authorize(&token, "backfill")?;
+        let deps = self.deps.clone();
+        let id = process_id.clone();
+        tokio::spawn(async move {
+            match backfill::backfill_process(deps.clone(), id.clone()).await {
+                Ok(m) => deps.logger.log(m),
+                Err(e) => deps
+                    .logger
+                    .error(format!("backfill of process {} failed: {}", id, e)),
+            }
+        });
+        self.record("backfill", Some(process_id.clone()), true);
+        Ok(json!({ "process_id": process_id, "started": true }).to_string())
+    }
+
+    // publishes the Scheduler-Location record now instead of waiting for the refresh
+    pub async fn publish_location(&self, token: Option<String>) -> Result<String, AdminError> {
+        self.authorize(&token, "publish_location")?;
+        let result = location::publish_location(&self.deps).await;
+        self.record(
+            "publish_location",
+            result.as_ref().ok().map(|ids| ids.join(",")),
+            result.is_ok(),
+        );
+        let ids = result?;
+        Ok(json!({ "ids": ids, "url": self.deps.config.scheduler_location_url() }).to_string())
+    }
+
+    // the settings that can change without a restart
+    pub fn runtime(&self, token: Option<String>) -> Result<String, AdminError> {
+        self.authorize(&token, "runtime")?;
+        self.record("runtime", None, true);
+        Ok(json!(self.deps.runtime.settings()).to_string())
+    }
+
+    pub fn update_runtime(
+        &self,
+        token: Option<String>,
+        update: RuntimeUpdate,
+    ) -> Result<String, AdminError> {
+        self.authorize(&token, "update_runtime")?;
+        let detail = format!("{:?}", update);
+        let result = runtime::apply(&self.deps, update);
+        self.record("update_runtime", Some(detail), result.is_ok());
+        Ok(json!(result?).to_string())
+    }
+
+    // same as a SIGHUP, re-reads the config file and the scheduler list
+    pub async fn reload_runtime(&self, token: Option<String>) -> Result<String, AdminError> {
+        self.authorize(&token, "reload_runtime")?;
+        let result = super::reload_runtime(self.deps.clone()).await;
+        self.record("reload_runtime", None, result.is_ok());
+        Ok(result?)
+    }
+
+    // sampled uploads the gateway never confirmed, oldest first
+    pub fn unconfirmed_uploads(&self, token: Option<String>) -> Result<String, AdminError> {
+        self.authorize(&token, "unconfirmed_uploads")?;
+        self.record("unconfirmed_uploads", None, true);
+        Ok(json!({
+            "stats": self.deps.confirmations.stats(),
+            "uploads": self.deps.confirmations.unconfirmed(),
+        })
+        .to_string())
+    }
+
+    // the most recent admin actions, oldest first
+    pub fn audit(&self, token: Option<String>) -> Result<String, AdminError> {
+        self.authorize(&token, "audit")?;
+        let entries: Vec<AuditEntry> = match self.audit.lock() {
+            Ok(audit) => audit.iter().cloned().collect(),
+            Err(_) => return Err(AdminError::Failed("audit trail unavailable".to_string())),
+        };
+        self.record("audit", None, true);
+        Ok(json!({ "entries": entries }).to_string())
+    }
+
+    fn authorize(&self, token: &Option<String>, action: &str) -> Result<(), AdminError> {
+        let result = match (self.deps.config.admin_token(), token) {
+            (Some(expected), Some(given))
+                if verify_slices_are_equal(expected.as_bytes(), given.as_bytes()).is_ok() =>
+            {
+                return Ok(())
+            }
+            (None, _) => Err(AdminError::Unauthorized(
+                "Admin api is not enabled".to_string(),
+            )),
+            _ => Err(AdminError::Unauthorized("Invalid admin token".to_string())),
+        };
+        self.record(action, Some("unauthorized".to_string()), false);
+        result
+    }
+
+    fn record(&self, action: &str, detail: Option<String>, ok: bool) {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or(0);
+        self.deps.logger.log(format!(
+            "admin {} {} ok={}",
+            action,
+            detail.clone().unwrap_or_default(),
+            ok
+        ));
+
+        if let Ok(mut audit) = self.audit.lock() {
+            if audit.len() >= AUDIT_ENTRIES {
+                audit.pop_front();
+            }
+            audit.push_back(AuditEntry {
+                timestamp,
+                action: action.to_string(),
+                detail,
+                ok,
+            });
+        }
+    }
+}
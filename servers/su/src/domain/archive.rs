@@ -0,0 +1,437 @@
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+
+use bytes::Bytes;
+use serde::{Deserialize, Serialize};
+
+use super::clients::store::StoreClient;
+use super::config::AoConfig;
+use super::core::bytes::DataBundle;
+use super::core::dal::{BundleRef, DataStore, Message, Process, StoreErrorType};
+use super::core::scheduler::{ChainCheck, ProcessScheduler, ScheduleGuard};
+
+// messages read from or written to the store at a time
+const BATCH_SIZE: usize = 500;
+
+/*
+    One line of an export file. The first line is the
+    process, the rest are its messages in schedule order.
+    Rows are kept as stored, with their bundle, so the
+    import writes back exactly what the export read.
+*/
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum ArchiveLine {
+    Process { process: Process, bundle: String },
+    Message { message: Message, bundle: String },
+}
+
+fn write_line<W: Write>(out: &mut W, line: &ArchiveLine) -> Result<(), String> {
+    let json = serde_json::to_string(line).map_err(|e| format!("{:?}", e))?;
+    writeln!(out, "{}", json).map_err(|e| format!("failed to write export: {}", e))
+}
+
+/*
+    Dumps a process and all of its messages to out_path
+    so the process can be moved to another su
+*/
+pub async fn export_process(process_id: &str, out_path: &str) -> Result<String, String> {
+    let data_store = StoreClient::new().map_err(|e| format!("{:?}", e))?;
+    let file = File::create(out_path).map_err(|e| format!("failed to create export: {}", e))?;
+    let mut out = BufWriter::new(file);
+    let count = export_to(&data_store, process_id, &mut out).await?;
+    out.flush()
+        .map_err(|e| format!("failed to write export: {}", e))?;
+
+    Ok(format!(
+        "exported process {} with {} messages to {}",
+        process_id, count, out_path
+    ))
+}
+
+// the export file of a process in memory, for a router moving it
+pub async fn export_archive(
+    data_store: &dyn DataStore,
+    process_id: &str,
+) -> Result<Vec<u8>, String> {
+    let mut out = vec![];
+    export_to(data_store, process_id, &mut out).await?;
+    Ok(out)
+}
+
+async fn export_to<W: Write>(
+    data_store: &dyn DataStore,
+    process_id: &str,
+    out: &mut W,
+) -> Result<usize, String> {
+    let process = data_store.get_process(process_id).await?;
+    let process_bundle = data_store
+        .get_stored_bundle(&BundleRef::Process(process_id.to_string()))
+        .await?;
+
+    write_line(
+        out,
+        &ArchiveLine::Process {
+            process,
+            bundle: base64_url::encode(&process_bundle),
+        },
+    )?;
+
+    let mut after = None;
+    let mut count = 0;
+    loop {
+        let batch = data_store
+            .get_message_bundles(process_id, &after, BATCH_SIZE as i64)
+            .await?;
+        if batch.is_empty() {
+            break;
+        }
+        for (message, bundle) in batch.into_iter() {
+            after = Some((message.epoch()?, message.nonce()?));
+            write_line(
+                out,
+                &ArchiveLine::Message {
+                    message,
+                    bundle: base64_url::encode(&bundle),
+                },
+            )?;
+            count += 1;
+        }
+    }
+    Ok(count)
+}
+
+fn read_lines<R: BufRead>(input: R) -> impl Iterator<Item = Result<ArchiveLine, String>> {
+    input.lines().map(|line| {
+        let line = line.map_err(|e| format!("failed to read import: {}", e))?;
+        serde_json::from_str(&line).map_err(|e| format!("invalid import line: {}", e))
+    })
+}
+
+fn open_import(in_path: &str) -> Result<BufReader<File>, String> {
+    let file = File::open(in_path).map_err(|e| format!("failed to open import: {}", e))?;
+    Ok(BufReader::new(file))
+}
+
+fn decode_bundle(bundle: &str) -> Result<Bytes, String> {
+    let bytes = Bytes::from(base64_url::decode(bundle).map_err(|e| format!("{:?}", e))?);
+    DataBundle::from_shared(&bytes).map_err(|e| format!("invalid bundle: {:?}", e))?;
+    Ok(bytes)
+}
+
+/*
+    Restores a process exported by export_process. The
+    whole file is validated before anything is written,
+    then the process and its messages are saved in
+    batches. Running it again for the same file carries
+    on after what an earlier run saved, a process that
+    exists with another spawn or schedule is refused.
+*/
+pub async fn import_process(in_path: &str) -> Result<String, String> {
+    let config = AoConfig::new(Some("su".to_string())).map_err(|e| format!("{:?}", e))?;
+    let data_store = StoreClient::new().map_err(|e| format!("{:?}", e))?;
+    data_store.check_compatibility(config.auto_migrate)?;
+    import_from(&data_store, None, || open_import(in_path)).await
+}
+
+/*
+    an export file sent by a router moving the process
+    here, saved under the process lock of the running su
+*/
+pub async fn import_archive(
+    data_store: &dyn DataStore,
+    scheduler: &ProcessScheduler,
+    archive: &[u8],
+) -> Result<String, String> {
+    import_from(data_store, Some(scheduler), || Ok(archive)).await
+}
+
+// open is called twice, once to validate and once to write
+async fn import_from<R: BufRead>(
+    data_store: &dyn DataStore,
+    scheduler: Option<&ProcessScheduler>,
+    open: impl Fn() -> Result<R, String>,
+) -> Result<String, String> {
+    let mut lines = read_lines(open()?);
+    let (process, process_bundle) = match lines.next() {
+        Some(Ok(ArchiveLine::Process { process, bundle })) => (process, decode_bundle(&bundle)?),
+        Some(Err(e)) => return Err(e),
+        _ => return Err("import must start with the process".to_string()),
+    };
+
+    let mut chain = ChainCheck::new(&process.process_id)?;
+    let mut count = 0;
+    for line in lines {
+        match line? {
+            ArchiveLine::Message { message, bundle } => {
+                decode_bundle(&bundle)?;
+                chain.check(&message)?;
+                count += 1;
+            }
+            ArchiveLine::Process { .. } => {
+                return Err("import contains more than one process".to_string())
+            }
+        }
+    }
+
+    let process_id = process.process_id.clone();
+    let mut saved = match data_store.get_process(&process_id).await {
+        Err(StoreErrorType::NotFound(_)) => {
+            let _guard = lock(scheduler, &process_id).await?;
+            data_store.save_process(&process, &process_bundle).await?;
+            None
+        }
+        Ok(_) => saved_schedule(data_store, &process_id, &process_bundle).await?,
+        Err(e) => return Err(format!("{:?}", e)),
+    };
+
+    let mut batch = vec![];
+    for line in read_lines(open()?).skip(1) {
+        if let ArchiveLine::Message { message, bundle } = line? {
+            // skip what is stored, its last message has to be in the file as stored
+            if let Some((epoch, nonce, hash_chain)) = &saved {
+                let at = (message.epoch()?, message.nonce()?);
+                if at > (*epoch, *nonce)
+                    || (at == (*epoch, *nonce) && message.hash_chain()? != *hash_chain)
+                {
+                    return Err(diverged(&process_id));
+                }
+                if at == (*epoch, *nonce) {
+                    saved = None;
+                }
+                continue;
+            }
+            batch.push((message, decode_bundle(&bundle)?));
+        }
+        if batch.len() >= BATCH_SIZE {
+            save_batch(data_store, scheduler, &process_id, &batch).await?;
+            batch.clear();
+        }
+    }
+    if !batch.is_empty() {
+        save_batch(data_store, scheduler, &process_id, &batch).await?;
+    }
+    if saved.is_some() {
+        return Err(diverged(&process_id));
+    }
+
+    Ok(format!(
+        "imported process {} with {} messages",
+        process_id, count
+    ))
+}
+
+/*
+    on a running su the process lock is taken while the
+    import saves, so a write in between takes its slot
+    from the store and not from a cached schedule
+*/
+async fn lock(
+    scheduler: Option<&ProcessScheduler>,
+    process_id: &str,
+) -> Result<Option<ScheduleGuard>, String> {
+    let mut guard = match scheduler {
+        Some(scheduler) => scheduler.lock(process_id.to_string()).await?,
+        None => return Ok(None),
+    };
+    // whatever was cached about the schedule is re-read afterwards
+    guard.synced = false;
+    Ok(Some(guard))
+}
+
+async fn save_batch(
+    data_store: &dyn DataStore,
+    scheduler: Option<&ProcessScheduler>,
+    process_id: &str,
+    batch: &[(Message, Bytes)],
+) -> Result<(), String> {
+    let guard = lock(scheduler, process_id).await?;
+    if let Some(guard) = guard.as_ref() {
+        guard.check_held()?;
+    }
+    data_store.save_messages(batch).await?;
+    Ok(())
+}
+
+/*
+    the process is in the store already, from an earlier
+    run of the same import. The epoch, nonce and hash
+    chain of its last message if it has the same spawn
+*/
+async fn saved_schedule(
+    data_store: &dyn DataStore,
+    process_id: &str,
+    process_bundle: &[u8],
+) -> Result<Option<(i32, i32, String)>, String> {
+    let stored = data_store
+        .get_stored_bundle(&BundleRef::Process(process_id.to_string()))
+        .await?;
+    if stored != process_bundle {
+        return Err(format!(
+            "process {} already exists in this store",
+            process_id
+        ));
+    }
+    match data_store.get_latest_message(process_id).await? {
+        Some(latest) => Ok(Some((
+            latest.epoch()?,
+            latest.nonce()?,
+            latest.hash_chain()?,
+        ))),
+        None => Ok(None),
+    }
+}
+
+fn diverged(process_id: &str) -> String {
+    format!(
+        "process {} already exists in this store with another schedule",
+        process_id
+    )
+}
+
+/*
+    Walks the stored schedule of a process making the
+    checks an import makes, the nonces have no gaps, the
+    hash chain links up and every bundle matches its
+    checksum and parses. Nothing is written.
+*/
+pub fn audit_process(process_id: &str) -> Result<String, String> {
+    let data_store = StoreClient::new().map_err(|e| format!("{:?}", e))?;
+    data_store.get_process(process_id)?;
+    let process_bundle =
+        data_store.get_stored_bundle(&BundleRef::Process(process_id.to_string()))?;
+    DataBundle::from_bytes(&process_bundle)
+        .map_err(|e| format!("invalid bundle of process {}: {:?}", process_id, e))?;
+
+    let mut chain = ChainCheck::new(process_id)?;
+    let mut after = None;
+    let mut count = 0;
+    loop {
+        let batch = data_store.get_message_bundles(process_id, &after, BATCH_SIZE as i64)?;
+        if batch.is_empty() {
+            break;
+        }
+        for (message, bundle) in batch.iter() {
+            let (epoch, nonce) = (message.epoch()?, message.nonce()?);
+            after = Some((epoch, nonce));
+            chain.check(message)?;
+            DataBundle::from_bytes(bundle)
+                .map_err(|e| format!("invalid bundle at nonce {}: {:?}", nonce, e))?;
+            count += 1;
+        }
+    }
+
+    Ok(format!(
+        "process {} has {} messages and its schedule is intact",
+        process_id, count
+    ))
+}
+
+/*
+    Checks the hash chain of a process from its stored
+    assignments alone, no bundle is loaded so it is quick
+    on long schedules and covers messages in cold segments.
+*/
+pub fn audit_assignments(process_id: &str) -> Result<String, String> {
+    let data_store = StoreClient::new().map_err(|e| format!("{:?}", e))?;
+    data_store.get_process(process_id)?;
+
+    let mut chain = ChainCheck::new(process_id)?;
+    let mut after = None;
+    let mut count = 0;
+    loop {
+        let batch = data_store.get_assignments(process_id, &after, BATCH_SIZE as i64)?;
+        if batch.is_empty() {
+            break;
+        }
+        for assignment in batch.iter() {
+            after = Some((assignment.epoch, assignment.nonce));
+            chain.check_assignment(assignment)?;
+            count += 1;
+        }
+    }
+
+    Ok(format!(
+        "process {} has {} assignments and its hash chain is intact",
+        process_id, count
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::core::dal::Assignment;
+    use crate::domain::core::scheduler::gen_hash_chain;
+
+    fn message(process_id: &str, nonce: i32, hash_chain: &str, assignment_id: &str) -> Message {
+        serde_json::from_value(serde_json::json!({
+            "message": null,
+            "assignment": {
+                "id": assignment_id,
+                "owner": { "address": "", "key": "" },
+                "tags": [
+                    { "name": "Process", "value": process_id },
+                    { "name": "Epoch", "value": "0" },
+                    { "name": "Nonce", "value": nonce.to_string() },
+                    { "name": "Hash-Chain", "value": hash_chain },
+                    { "name": "Timestamp", "value": "0" },
+                    { "name": "Block-Height", "value": "0" },
+                    { "name": "Message", "value": assignment_id },
+                ],
+                "signature": "",
+                "anchor": null,
+                "target": null,
+            },
+        }))
+        .expect("valid message")
+    }
+
+    #[test]
+    fn test_chain_check() {
+        let process_id = base64_url::encode(&[1u8; 32]);
+        let first_assignment = base64_url::encode(&[2u8; 32]);
+        let second_assignment = base64_url::encode(&[3u8; 32]);
+
+        let first_chain = gen_hash_chain(&process_id, None).unwrap();
+        let second_chain = gen_hash_chain(&first_chain, Some(&first_assignment)).unwrap();
+
+        let mut chain = ChainCheck::new(&process_id).unwrap();
+        assert!(chain
+            .check(&message(&process_id, 0, &first_chain, &first_assignment))
+            .is_ok());
+        assert!(chain
+            .check(&message(&process_id, 1, &second_chain, &second_assignment))
+            .is_ok());
+
+        // a gap in the nonces
+        let mut chain = ChainCheck::new(&process_id).unwrap();
+        assert!(chain
+            .check(&message(&process_id, 1, &first_chain, &first_assignment))
+            .is_err());
+
+        // a hash chain that does not link up
+        let mut chain = ChainCheck::new(&process_id).unwrap();
+        assert!(chain
+            .check(&message(&process_id, 0, &second_chain, &first_assignment))
+            .is_err());
+    }
+
+    #[test]
+    fn test_assignment_chain_check() {
+        let process_id = base64_url::encode(&[1u8; 32]);
+        let assignment_id = base64_url::encode(&[2u8; 32]);
+        let hash_chain = gen_hash_chain(&process_id, None).unwrap();
+
+        let assignment =
+            Assignment::from_message(&message(&process_id, 0, &hash_chain, &assignment_id))
+                .unwrap();
+        assert_eq!(assignment.message_id, assignment_id);
+        assert_eq!(assignment.block_height.as_deref(), Some("0"));
+        assert_eq!(assignment.leaf().hash_chain, hash_chain);
+
+        let mut chain = ChainCheck::new(&process_id).unwrap();
+        assert!(chain.check_assignment(&assignment).is_ok());
+        // the same assignment again is a repeated nonce
+        assert!(chain.check_assignment(&assignment).is_err());
+    }
+}
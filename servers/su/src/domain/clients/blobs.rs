@@ -0,0 +1,185 @@
+use std::fs;
+use std::future::Future;
+use std::path::PathBuf;
+use std::sync::{mpsc, Arc, OnceLock};
+
+use tokio::runtime::{Builder, Handle};
+
+use super::backup::S3Client;
+use crate::domain::config::AoConfig;
+use crate::domain::core::dal::BlobStore;
+
+/*
+    Bundles saved as files under a directory, sharded
+    by the first characters of the key so no single
+    directory grows too large
+*/
+pub struct DiskBlobs {
+    root: PathBuf,
+}
+
+impl DiskBlobs {
+    pub fn new(root: &str) -> Result<Self, String> {
+        fs::create_dir_all(root).map_err(|e| format!("Invalid BUNDLE_STORAGE_PATH: {}", e))?;
+        Ok(DiskBlobs {
+            root: PathBuf::from(root),
+        })
+    }
+
+    fn path(&self, key: &str) -> PathBuf {
+        let shard = key.get(..2).unwrap_or("__");
+        self.root.join(shard).join(key)
+    }
+}
+
+impl BlobStore for DiskBlobs {
+    fn put(&self, key: &str, data: &[u8]) -> Result<(), String> {
+        let path = self.path(key);
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).map_err(|e| format!("{:?}", e))?;
+        }
+        // written aside and renamed so a crash never leaves half a bundle
+        let partial = path.with_extension("partial");
+        fs::write(&partial, data).map_err(|e| format!("{:?}", e))?;
+        fs::rename(&partial, &path).map_err(|e| format!("{:?}", e))
+    }
+
+    fn get(&self, key: &str) -> Result<Vec<u8>, String> {
+        fs::read(self.path(key)).map_err(|e| format!("failed to read blob {}: {}", key, e))
+    }
+
+    fn delete(&self, key: &str) -> Result<(), String> {
+        match fs::remove_file(self.path(key)) {
+            Ok(()) => Ok(()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(format!("failed to delete blob {}: {}", key, e)),
+        }
+    }
+}
+
+/*
+    the store is synchronous and called from inside the
+    actix runtimes, which cannot block on a future. S3
+    requests run on a runtime of their own and the store
+    waits for the answer on a channel instead
+*/
+fn blob_runtime() -> &'static Handle {
+    static RUNTIME: OnceLock<Handle> = OnceLock::new();
+    RUNTIME.get_or_init(|| {
+        let (tx, rx) = mpsc::channel();
+        std::thread::spawn(move || {
+            let runtime = Builder::new_current_thread()
+                .enable_all()
+                .build()
+                .expect("Failed to start the blob runtime");
+            let _ = tx.send(runtime.handle().clone());
+            runtime.block_on(std::future::pending::<()>());
+        });
+        rx.recv().expect("Failed to start the blob runtime")
+    })
+}
+
+fn wait_for<T: Send + 'static>(
+    future: impl Future<Output = Result<T, String>> + Send + 'static,
+) -> Result<T, String> {
+    let (tx, rx) = mpsc::channel();
+    blob_runtime().spawn(async move {
+        let _ = tx.send(future.await);
+    });
+    rx.recv().map_err(|e| format!("{:?}", e))?
+}
+
+pub struct S3Blobs {
+    s3: S3Client,
+    prefix: String,
+}
+
+impl S3Blobs {
+    fn key(&self, key: &str) -> String {
+        format!("{}/{}", self.prefix, key)
+    }
+}
+
+impl BlobStore for S3Blobs {
+    fn put(&self, key: &str, data: &[u8]) -> Result<(), String> {
+        let s3 = self.s3.clone();
+        let key = self.key(key);
+        let data = data.to_vec();
+        wait_for(async move { s3.put(&key, data).await })
+    }
+
+    fn get(&self, key: &str) -> Result<Vec<u8>, String> {
+        let s3 = self.s3.clone();
+        let key = self.key(key);
+        wait_for(async move {
+            s3.get(&key)
+                .await?
+                .ok_or(format!("blob {} does not exist", key))
+        })
+    }
+
+    fn delete(&self, key: &str) -> Result<(), String> {
+        let s3 = self.s3.clone();
+        let key = self.key(key);
+        wait_for(async move { s3.delete(&key).await })
+    }
+}
+
+// None keeps every bundle in the database
+pub fn from_config(config: &AoConfig) -> Result<Option<Arc<dyn BlobStore>>, String> {
+    match config.bundle_storage.as_str() {
+        "database" => Ok(None),
+        "disk" => {
+            let path = config
+                .bundle_storage_path
+                .clone()
+                .ok_or("BUNDLE_STORAGE_PATH is required for disk storage".to_string())?;
+            Ok(Some(Arc::new(DiskBlobs::new(&path)?)))
+        }
+        "s3" => {
+            let bucket = config
+                .bundle_s3_bucket
+                .clone()
+                .ok_or("BUNDLE_S3_BUCKET is required for s3 storage".to_string())?;
+            let region = config.bundle_s3_region.clone();
+            let endpoint = config
+                .bundle_s3_endpoint
+                .clone()
+                .unwrap_or(format!("https://s3.{}.amazonaws.com", region));
+            let s3 = S3Client::new(
+                &endpoint,
+                bucket,
+                region,
+                config.bundle_s3_access_key.clone().unwrap_or_default(),
+                config.bundle_s3_secret_key.clone().unwrap_or_default(),
+            )?;
+            Ok(Some(Arc::new(S3Blobs {
+                s3,
+                prefix: config.bundle_s3_prefix.trim_matches('/').to_string(),
+            })))
+        }
+        other => Err(format!("Unknown BUNDLE_STORAGE {}", other)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_disk_blobs() {
+        let root = std::env::temp_dir().join(format!("su-blobs-{}", std::process::id()));
+        let blobs = DiskBlobs::new(root.to_str().unwrap()).unwrap();
+
+        blobs.put("abcdef", b"bundle").unwrap();
+        assert_eq!(blobs.get("abcdef").unwrap(), b"bundle".to_vec());
+        assert!(root.join("ab").join("abcdef").exists());
+        assert!(blobs.get("missing").is_err());
+
+        blobs.delete("abcdef").unwrap();
+        assert!(blobs.get("abcdef").is_err());
+        assert!(blobs.delete("missing").is_ok());
+
+        let _ = fs::remove_dir_all(root);
+    }
+}
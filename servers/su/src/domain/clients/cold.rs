@@ -0,0 +1,325 @@
+use std::sync::Arc;
+
+use bytes::Bytes;
+use parquet::basic::{Compression, ZstdLevel};
+use parquet::data_type::{ByteArray, ByteArrayType, Int32Type, Int64Type};
+use parquet::file::properties::WriterProperties;
+use parquet::file::reader::{FileReader, SerializedFileReader};
+use parquet::file::writer::SerializedFileWriter;
+use parquet::record::{Field, Row, RowAccessor};
+use parquet::schema::parser::parse_message_type;
+use serde::{Deserialize, Serialize};
+
+use crate::domain::core::dal::TagFilter;
+
+// zstd level of the segments, history is written once and read rarely
+const COMPRESSION_LEVEL: i32 = 9;
+
+/*
+    rows per row group, reading one bundle back only
+    inflates the group it is in
+*/
+const ROW_GROUP_SIZE: usize = 500;
+
+const ROWS_SCHEMA: &str = "
+    message cold_rows {
+        REQUIRED BYTE_ARRAY message_id (UTF8);
+        OPTIONAL BYTE_ARRAY assignment_id (UTF8);
+        REQUIRED BYTE_ARRAY message_data (UTF8);
+        REQUIRED INT32 epoch;
+        REQUIRED INT32 nonce;
+        REQUIRED INT64 timestamp;
+        REQUIRED BYTE_ARRAY hash_chain (UTF8);
+    }
+";
+
+const BUNDLES_SCHEMA: &str = "
+    message cold_bundles {
+        REQUIRED BYTE_ARRAY bundle;
+    }
+";
+
+/*
+    A message row moved out of the database. A segment
+    is two parquet files, the rows and their bundles in
+    the same order, so pages and filters read the rows
+    without inflating a single bundle.
+*/
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ColdRow {
+    pub message_id: String,
+    pub assignment_id: Option<String>,
+    pub message_data: serde_json::Value,
+    pub epoch: i32,
+    pub nonce: i32,
+    pub timestamp: i64,
+    pub hash_chain: String,
+    /*
+        base64url, only in the json segments written before
+        the bundles had a file of their own, empty when
+        retention already pruned it
+    */
+    #[serde(default)]
+    pub bundle: String,
+}
+
+impl ColdRow {
+    // every filter is one of the message's tags
+    pub fn has_tags(&self, tags: &[TagFilter]) -> bool {
+        let message_tags = self.message_data["message"]["tags"]
+            .as_array()
+            .cloned()
+            .unwrap_or_default();
+        tags.iter().all(|filter| {
+            message_tags
+                .iter()
+                .any(|t| t["name"] == filter.name.as_str() && t["value"] == filter.value.as_str())
+        })
+    }
+
+    /*
+        the row holds the data item itself, not only an
+        assignment of one sequenced before. rows in the old
+        json shape always do
+    */
+    pub fn carries_message(&self) -> bool {
+        self.message_data.get("assignment").is_none() || !self.message_data["message"].is_null()
+    }
+}
+
+fn err(e: impl std::fmt::Display) -> String {
+    format!("{}", e)
+}
+
+fn properties() -> Result<Arc<WriterProperties>, String> {
+    let level = ZstdLevel::try_new(COMPRESSION_LEVEL).map_err(err)?;
+    Ok(Arc::new(
+        WriterProperties::builder()
+            .set_compression(Compression::ZSTD(level))
+            .build(),
+    ))
+}
+
+fn text(values: impl Iterator<Item = String>) -> Vec<ByteArray> {
+    values.map(|v| ByteArray::from(v.into_bytes())).collect()
+}
+
+pub fn encode_rows(rows: &[ColdRow]) -> Result<Vec<u8>, String> {
+    let schema = Arc::new(parse_message_type(ROWS_SCHEMA).map_err(err)?);
+    let mut file = vec![];
+    let mut writer = SerializedFileWriter::new(&mut file, schema, properties()?).map_err(err)?;
+    for group in rows.chunks(ROW_GROUP_SIZE) {
+        let mut row_group = writer.next_row_group().map_err(err)?;
+        let mut index = 0;
+        while let Some(mut column) = row_group.next_column().map_err(err)? {
+            match index {
+                0 => {
+                    let values = text(group.iter().map(|r| r.message_id.clone()));
+                    column
+                        .typed::<ByteArrayType>()
+                        .write_batch(&values, None, None)
+                }
+                1 => {
+                    let values = text(group.iter().filter_map(|r| r.assignment_id.clone()));
+                    let levels: Vec<i16> = group
+                        .iter()
+                        .map(|r| r.assignment_id.is_some() as i16)
+                        .collect();
+                    column
+                        .typed::<ByteArrayType>()
+                        .write_batch(&values, Some(&levels), None)
+                }
+                2 => {
+                    let values = text(group.iter().map(|r| r.message_data.to_string()));
+                    column
+                        .typed::<ByteArrayType>()
+                        .write_batch(&values, None, None)
+                }
+                3 => {
+                    let values: Vec<i32> = group.iter().map(|r| r.epoch).collect();
+                    column.typed::<Int32Type>().write_batch(&values, None, None)
+                }
+                4 => {
+                    let values: Vec<i32> = group.iter().map(|r| r.nonce).collect();
+                    column.typed::<Int32Type>().write_batch(&values, None, None)
+                }
+                5 => {
+                    let values: Vec<i64> = group.iter().map(|r| r.timestamp).collect();
+                    column.typed::<Int64Type>().write_batch(&values, None, None)
+                }
+                _ => {
+                    let values = text(group.iter().map(|r| r.hash_chain.clone()));
+                    column
+                        .typed::<ByteArrayType>()
+                        .write_batch(&values, None, None)
+                }
+            }
+            .map_err(err)?;
+            column.close().map_err(err)?;
+            index += 1;
+        }
+        row_group.close().map_err(err)?;
+    }
+    writer.close().map_err(err)?;
+    Ok(file)
+}
+
+// the bundles in the order of the rows, empty when pruned
+pub fn encode_bundles(bundles: &[Vec<u8>]) -> Result<Vec<u8>, String> {
+    let schema = Arc::new(parse_message_type(BUNDLES_SCHEMA).map_err(err)?);
+    let mut file = vec![];
+    let mut writer = SerializedFileWriter::new(&mut file, schema, properties()?).map_err(err)?;
+    for group in bundles.chunks(ROW_GROUP_SIZE) {
+        let mut row_group = writer.next_row_group().map_err(err)?;
+        if let Some(mut column) = row_group.next_column().map_err(err)? {
+            let values: Vec<ByteArray> = group.iter().map(|b| ByteArray::from(b.clone())).collect();
+            column
+                .typed::<ByteArrayType>()
+                .write_batch(&values, None, None)
+                .map_err(err)?;
+            column.close().map_err(err)?;
+        }
+        row_group.close().map_err(err)?;
+    }
+    writer.close().map_err(err)?;
+    Ok(file)
+}
+
+fn reader(file: Vec<u8>) -> Result<SerializedFileReader<Bytes>, String> {
+    SerializedFileReader::new(Bytes::from(file)).map_err(|e| format!("invalid segment: {}", e))
+}
+
+fn to_row(row: Row) -> Result<ColdRow, String> {
+    let assignment_id = match row.get_column_iter().nth(1) {
+        Some((_, Field::Str(assignment_id))) => Some(assignment_id.clone()),
+        _ => None,
+    };
+    Ok(ColdRow {
+        message_id: row.get_string(0).map_err(err)?.clone(),
+        assignment_id,
+        message_data: serde_json::from_str(row.get_string(2).map_err(err)?)
+            .map_err(|e| format!("invalid segment row: {}", e))?,
+        epoch: row.get_int(3).map_err(err)?,
+        nonce: row.get_int(4).map_err(err)?,
+        timestamp: row.get_long(5).map_err(err)?,
+        hash_chain: row.get_string(6).map_err(err)?.clone(),
+        bundle: String::new(),
+    })
+}
+
+pub fn decode_rows(file: Vec<u8>) -> Result<Vec<ColdRow>, String> {
+    let reader = reader(file)?;
+    let rows = reader.get_row_iter(None).map_err(err)?;
+    rows.map(|row| to_row(row.map_err(err)?)).collect()
+}
+
+pub fn decode_bundles(file: Vec<u8>) -> Result<Vec<Vec<u8>>, String> {
+    let reader = reader(file)?;
+    let rows = reader.get_row_iter(None).map_err(err)?;
+    rows.map(|row| Ok(row.map_err(err)?.get_bytes(0).map_err(err)?.data().to_vec()))
+        .collect()
+}
+
+// the bundle at a position, inflating only its row group
+pub fn decode_bundle(file: Vec<u8>, position: usize) -> Result<Vec<u8>, String> {
+    let reader = reader(file)?;
+    let row_group = reader
+        .get_row_group(position / ROW_GROUP_SIZE)
+        .map_err(err)?;
+    let row = row_group
+        .get_row_iter(None)
+        .map_err(err)?
+        .nth(position % ROW_GROUP_SIZE)
+        .ok_or(format!("segment has no bundle {}", position))?
+        .map_err(err)?;
+    Ok(row.get_bytes(0).map_err(err)?.data().to_vec())
+}
+
+// segments written before parquet, json rows with their bundles
+pub fn decode_json(segment: &[u8]) -> Result<Vec<ColdRow>, String> {
+    let lines = zstd::decode_all(segment).map_err(|e| format!("invalid segment: {}", e))?;
+    lines
+        .split(|b| *b == b'\n')
+        .filter(|l| !l.is_empty())
+        .map(|l| serde_json::from_slice(l).map_err(|e| format!("invalid segment row: {}", e)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn row(nonce: i32) -> ColdRow {
+        ColdRow {
+            message_id: format!("message-{}", nonce),
+            assignment_id: match nonce % 7 {
+                0 => None,
+                _ => Some(format!("assignment-{}", nonce)),
+            },
+            message_data: json!({
+                "message": { "tags": [{ "name": "Action", "value": "Transfer" }] },
+                "assignment": {},
+            }),
+            epoch: 0,
+            nonce,
+            timestamp: 1700000000000 + i64::from(nonce),
+            hash_chain: "chain".to_string(),
+            bundle: String::new(),
+        }
+    }
+
+    #[test]
+    fn test_segment_round_trip() {
+        let rows: Vec<ColdRow> = (0..1200).map(row).collect();
+        let file = encode_rows(&rows).unwrap();
+        assert_eq!(decode_rows(file).unwrap(), rows);
+        assert!(decode_rows(b"not a segment".to_vec()).is_err());
+
+        let bundles: Vec<Vec<u8>> = (0..1200)
+            .map(|n: i32| match n % 5 {
+                0 => vec![],
+                _ => format!("bundle-{}", n).into_bytes(),
+            })
+            .collect();
+        let file = encode_bundles(&bundles).unwrap();
+        assert_eq!(decode_bundles(file.clone()).unwrap(), bundles);
+        assert_eq!(decode_bundle(file.clone(), 777).unwrap(), b"bundle-777");
+        assert!(decode_bundle(file.clone(), 1000).unwrap().is_empty());
+        assert!(decode_bundle(file, 1200).is_err());
+
+        let filter = |value: &str| TagFilter {
+            name: "Action".to_string(),
+            value: value.to_string(),
+        };
+        let transfer = vec![filter("Transfer")];
+        let eval = vec![filter("Eval")];
+        assert!(rows[0].has_tags(&transfer));
+        assert!(!rows[0].has_tags(&eval));
+        assert!(rows[0].has_tags(&[]));
+    }
+
+    #[test]
+    fn test_json_segment() {
+        let mut rows: Vec<ColdRow> = (0..10).map(row).collect();
+        rows[3].bundle = base64_url::encode(b"bundle");
+        let mut lines = vec![];
+        for row in rows.iter() {
+            serde_json::to_writer(&mut lines, row).unwrap();
+            lines.push(b'\n');
+        }
+        let segment = zstd::encode_all(&lines[..], COMPRESSION_LEVEL).unwrap();
+        assert_eq!(decode_json(&segment).unwrap(), rows);
+        assert!(decode_json(b"not a segment").is_err());
+    }
+
+    #[test]
+    fn test_carries_message() {
+        let mut assigned = row(1);
+        assert!(assigned.carries_message());
+        assigned.message_data = json!({ "message": null, "assignment": {} });
+        assert!(!assigned.carries_message());
+        assigned.message_data = json!({ "message": {}, "owner": {} });
+        assert!(assigned.carries_message());
+    }
+}
@@ -0,0 +1,346 @@
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use bundlr_sdk::tags::Tag;
+use bytes::Bytes;
+use reqwest::{Client, Url};
+use tokio::sync::mpsc;
+use tokio::time::{sleep, timeout_at, Duration, Instant};
+
+use crate::domain::clients::uploader::UploadDeps;
+use crate::domain::core::breaker::CircuitBreaker;
+use crate::domain::core::bytes::{DataBundle, DataItem};
+use crate::domain::core::dal::{
+    Config, DataStore, DomainEvent, EventBus, Gateway, Signer, Uploader, UploaderErrorType,
+};
+use crate::domain::core::deadline::Deadline;
+use crate::domain::core::transaction::Transaction;
+use crate::domain::Log;
+
+// post attempts of a bundle before it is given up
+const MAX_ATTEMPTS: u32 = 100;
+/*
+    how long a bundle may wait for the upload breaker to
+    close, counted across all of its attempts
+*/
+const MAX_BREAKER_WAIT: Duration = Duration::from_secs(60 * 60);
+
+/*
+    UPLOAD_MODE direct, an alternative to the upload node.
+    Items handed to the uploader are queued and gathered
+    into an ANS-104 bundle until DIRECT_BUNDLE_MAX_ITEMS,
+    DIRECT_BUNDLE_MAX_BYTES or DIRECT_BUNDLE_INTERVAL_MS is
+    reached. The bundle goes out as an arweave transaction
+    signed by the su wallet, its fee quoted by the gateway,
+    posted to ARWEAVE_NODE_URL or the gateway. The queue
+    holds DIRECT_QUEUE_MAX_ITEMS, an upload past that is
+    refused. The items of a bundle given up on are saved
+    to undelivered_uploads and handed over again later.
+*/
+pub struct DirectUploader {
+    node_url: Url,
+    // the id and the signed bytes of each item
+    queue: mpsc::Sender<(String, Bytes)>,
+    pending: Arc<AtomicUsize>,
+    given_up: Arc<AtomicU64>,
+    client: Client,
+    breaker: Arc<CircuitBreaker>,
+    timeout: Duration,
+}
+
+struct Batcher {
+    node_url: Url,
+    signer: Arc<dyn Signer>,
+    gateway: Arc<dyn Gateway>,
+    logger: Arc<dyn Log>,
+    events: Arc<EventBus>,
+    data_store: Arc<dyn DataStore>,
+    pending: Arc<AtomicUsize>,
+    given_up: Arc<AtomicU64>,
+    client: Client,
+    breaker: Arc<CircuitBreaker>,
+    timeout: Duration,
+    max_items: usize,
+    max_bytes: usize,
+    interval: Duration,
+}
+
+impl DirectUploader {
+    pub fn new(
+        config: &dyn Config,
+        signer: Arc<dyn Signer>,
+        deps: UploadDeps,
+    ) -> Result<Self, UploaderErrorType> {
+        let UploadDeps {
+            logger,
+            events,
+            data_store,
+            gateway,
+            client,
+            breaker,
+            timeout,
+        } = deps;
+        let node_url = config
+            .arweave_node_url()
+            .unwrap_or_else(|| config.gateway_url());
+        let node_url =
+            Url::parse(&node_url).map_err(|e| UploaderErrorType::UploadError(format!("{}", e)))?;
+        let pending = Arc::new(AtomicUsize::new(0));
+        let given_up = Arc::new(AtomicU64::new(0));
+        let (queue, receiver) = mpsc::channel(config.direct_queue_max_items().max(1));
+
+        let batcher = Batcher {
+            node_url: node_url.clone(),
+            signer,
+            gateway,
+            logger,
+            events,
+            data_store,
+            pending: pending.clone(),
+            given_up: given_up.clone(),
+            client: client.clone(),
+            breaker: breaker.clone(),
+            timeout,
+            max_items: config.direct_bundle_max_items().max(1),
+            max_bytes: config.direct_bundle_max_bytes(),
+            interval: Duration::from_millis(config.direct_bundle_interval_ms()),
+        };
+        tokio::spawn(batcher.run(receiver));
+
+        Ok(DirectUploader {
+            node_url,
+            queue,
+            pending,
+            given_up,
+            client,
+            breaker,
+            timeout,
+        })
+    }
+}
+
+impl Batcher {
+    async fn run(self, mut receiver: mpsc::Receiver<(String, Bytes)>) {
+        while let Some(first) = receiver.recv().await {
+            let mut size = first.1.len();
+            let mut items = vec![first];
+            let until = Instant::now() + self.interval;
+            while items.len() < self.max_items && size < self.max_bytes {
+                match timeout_at(until, receiver.recv()).await {
+                    Ok(Some(item)) => {
+                        size += item.1.len();
+                        items.push(item);
+                    }
+                    _ => break,
+                }
+            }
+            let count = items.len();
+            self.post_bundle(items).await;
+            self.pending.fetch_sub(count, Ordering::SeqCst);
+        }
+    }
+
+    async fn post_bundle(&self, items: Vec<(String, Bytes)>) {
+        let ids: Vec<String> = items.iter().map(|(id, _)| id.clone()).collect();
+        let mut tx = match self.build(&items).await {
+            Ok(tx) => tx,
+            Err(e) => {
+                self.give_up(&items, &format!("Failed to build bundle: {}", e))
+                    .await;
+                return;
+            }
+        };
+
+        let (mut attempts, mut waited) = (0, Duration::ZERO);
+        let mut error = String::new();
+        while attempts < MAX_ATTEMPTS && waited < MAX_BREAKER_WAIT {
+            // an open breaker does not use up attempts, the bundle waits for it
+            if let Err(e) = self.breaker.allow() {
+                error = e;
+                sleep(Duration::from_secs(1)).await;
+                waited += Duration::from_secs(1);
+                continue;
+            }
+            attempts += 1;
+
+            match self.post(&tx).await {
+                Ok(()) => {
+                    self.logger.log(format!(
+                        "posted bundle {} of {} items for {} winston",
+                        tx.id(),
+                        ids.len(),
+                        tx.reward
+                    ));
+                    for id in ids.into_iter() {
+                        self.events.publish(DomainEvent::UploadConfirmed { id });
+                    }
+                    return;
+                }
+                Err(e) => {
+                    self.logger
+                        .error(format!("Failed to post bundle {}: {}", tx.id(), e));
+                    error = e;
+                    sleep(Duration::from_secs(1)).await;
+                }
+            }
+
+            // the anchor is only good for 50 blocks, sign again with a fresh one
+            if attempts % 20 == 0 {
+                if let Err(e) = self.resign(&mut tx).await {
+                    self.logger
+                        .error(format!("Failed to sign bundle again: {}", e));
+                }
+            }
+        }
+        self.give_up(
+            &items,
+            &format!("Gave up posting bundle {} - {}", tx.id(), error),
+        )
+        .await;
+    }
+
+    // keeps the items for the redelivery to hand over again
+    async fn give_up(&self, items: &[(String, Bytes)], error: &str) {
+        self.given_up
+            .fetch_add(items.len() as u64, Ordering::SeqCst);
+        let ids: Vec<&String> = items.iter().map(|(id, _)| id).collect();
+        self.logger.error(format!("{}, items {:?}", error, ids));
+        for (id, bytes) in items.iter() {
+            if let Err(e) = self.data_store.save_undelivered(id, bytes, error).await {
+                self.logger
+                    .error(format!("Failed to save undelivered item {}: {:?}", id, e));
+            }
+        }
+    }
+
+    async fn build(&self, items: &[(String, Bytes)]) -> Result<Transaction, String> {
+        let mut bundle = DataBundle::new(vec![]);
+        for (_, bytes) in items.iter() {
+            bundle.add_item(DataItem::from_bytes(bytes.clone()).map_err(|e| format!("{:?}", e))?);
+        }
+        let data = bundle.to_bytes().map_err(|e| format!("{:?}", e))?;
+        let tags = vec![
+            Tag::new("Bundle-Format", "binary"),
+            Tag::new("Bundle-Version", "2.0.0"),
+        ];
+        let mut tx = Transaction::new(self.signer.get_public_key(), vec![], tags, 0, data);
+        self.resign(&mut tx).await?;
+        Ok(tx)
+    }
+
+    async fn resign(&self, tx: &mut Transaction) -> Result<(), String> {
+        tx.reward = self.gateway.price(tx.data.len()).await?;
+        tx.last_tx = base64_url::decode(&self.gateway.tx_anchor().await?)
+            .map_err(|e| format!("Invalid anchor: {}", e))?;
+        tx.sign(&*self.signer).await
+    }
+
+    // the header first, then each chunk unless the data was inline
+    async fn post(&self, tx: &Transaction) -> Result<(), String> {
+        self.send("tx", tx.to_json()).await?;
+        if !tx.inline() {
+            for chunk in tx.chunks.iter() {
+                self.send("chunk", tx.chunk_json(chunk)).await?;
+            }
+        }
+        Ok(())
+    }
+
+    async fn send(&self, path: &str, body: serde_json::Value) -> Result<(), String> {
+        let url = self.node_url.join(path).map_err(|e| format!("{}", e))?;
+        let response = self
+            .client
+            .post(url)
+            .json(&body)
+            .timeout(self.timeout)
+            .send()
+            .await;
+        self.breaker.record(match &response {
+            Ok(resp) => !resp.status().is_server_error(),
+            Err(_) => false,
+        });
+        let response = response.map_err(|e| format!("{}", e))?;
+        // 208 is a transaction or chunk the node already has
+        match response.status().is_success() {
+            true => Ok(()),
+            false => Err(format!(
+                "{} returned {}: {}",
+                path,
+                response.status(),
+                response.text().await.unwrap_or_default()
+            )),
+        }
+    }
+}
+
+#[async_trait]
+impl Uploader for DirectUploader {
+    fn upload(&self, tx: Bytes) -> Result<(), UploaderErrorType> {
+        let (item, _) = DataItem::from_info_bytes(&tx)
+            .map_err(|e| UploaderErrorType::UploadError(format!("{:?}", e)))?;
+        self.pending.fetch_add(1, Ordering::SeqCst);
+        self.queue.try_send((item.id(), tx)).map_err(|e| {
+            self.pending.fetch_sub(1, Ordering::SeqCst);
+            UploaderErrorType::UploadError(match e {
+                mpsc::error::TrySendError::Full(_) => "The upload queue is full".to_string(),
+                mpsc::error::TrySendError::Closed(_) => "The bundling task stopped".to_string(),
+            })
+        })
+    }
+
+    async fn ping(&self) -> Result<(), UploaderErrorType> {
+        let url = self
+            .node_url
+            .join("info")
+            .map_err(|e| UploaderErrorType::UploadError(format!("{}", e)))?;
+        let request = match Deadline::current().remaining() {
+            Some(remaining) => self.client.get(url).timeout(remaining.min(self.timeout)),
+            None => self.client.get(url).timeout(self.timeout),
+        };
+
+        self.breaker
+            .allow()
+            .map_err(UploaderErrorType::UploadError)?;
+        let response = request.send().await;
+        self.breaker.record(match &response {
+            Ok(resp) => !resp.status().is_server_error(),
+            Err(_) => false,
+        });
+        let response = response?;
+        match response.status().is_success() {
+            true => Ok(()),
+            false => Err(UploaderErrorType::UploadError(format!(
+                "Arweave node returned {}",
+                response.status()
+            ))),
+        }
+    }
+
+    // the wallet itself pays for the transactions
+    async fn balance(&self, address: &str) -> Result<u64, UploaderErrorType> {
+        let url = self
+            .node_url
+            .join(&format!("wallet/{}/balance", address))
+            .map_err(|e| UploaderErrorType::UploadError(format!("{}", e)))?;
+        let response = self.client.get(url).timeout(self.timeout).send().await?;
+        if !response.status().is_success() {
+            return Err(UploaderErrorType::UploadError(format!(
+                "Arweave node returned {} for the balance",
+                response.status()
+            )));
+        }
+        let body = response.text().await?;
+        body.trim()
+            .parse::<u64>()
+            .map_err(|e| UploaderErrorType::UploadError(format!("Invalid balance {}: {}", body, e)))
+    }
+
+    fn pending_uploads(&self) -> usize {
+        self.pending.load(Ordering::SeqCst)
+    }
+
+    fn given_up_uploads(&self) -> u64 {
+        self.given_up.load(Ordering::SeqCst)
+    }
+}
@@ -1,8 +1,10 @@
 use crate::domain::config::AoConfig;
+use crate::domain::core::breaker::CircuitBreaker;
 use crate::domain::core::dal::{Gateway, NetworkInfo, TxStatus};
+use crate::domain::core::deadline::Deadline;
 use arweave_rs::network::NetworkInfoClient;
 use async_trait::async_trait;
-use reqwest::{Client, Url};
+use reqwest::{Client, RequestBuilder, Response, Url};
 use std::sync::Arc;
 use tokio::sync::Mutex;
 use tokio::time::{sleep, Duration};
@@ -11,12 +13,16 @@ pub struct ArweaveGateway {
     // Use Mutex to safely share and update state across tasks
     height: Arc<Mutex<String>>,
     current: Arc<Mutex<String>>,
+    client: Client,
+    breaker: Arc<CircuitBreaker>,
+    timeout: Duration,
 }
 
 #[derive(Debug)]
 pub enum GatewayErrorType {
-    CheckHeadError(String),
-    StatusError(String),
+    CheckHead(String),
+    Status(String),
+    Raw(String),
 }
 
 impl From<GatewayErrorType> for String {
@@ -25,8 +31,20 @@ impl From<GatewayErrorType> for String {
     }
 }
 
+// give up on the gateway once the request being served has
+fn with_deadline(request: RequestBuilder, timeout: Duration) -> RequestBuilder {
+    match Deadline::current().remaining() {
+        Some(remaining) => request.timeout(remaining.min(timeout)),
+        None => request.timeout(timeout),
+    }
+}
+
 impl ArweaveGateway {
-    pub async fn new() -> Result<Self, String> {
+    pub async fn new(
+        client: Client,
+        breaker: Arc<CircuitBreaker>,
+        timeout: Duration,
+    ) -> Result<Self, String> {
         let network_info = ArweaveGateway::network_info_fetch().await?;
 
         let height = Arc::new(Mutex::new(network_info.height.clone()));
@@ -35,6 +53,9 @@ impl ArweaveGateway {
         let gateway = ArweaveGateway {
             height: height.clone(),
             current: current.clone(),
+            client,
+            breaker,
+            timeout,
         };
 
         // Spawn a background task to refresh network info every 1 minute
@@ -63,7 +84,7 @@ impl ArweaveGateway {
         for attempt in 0..5 {
             match network_client.network_info().await {
                 Ok(network_info) => {
-                    let height = network_info.height.clone();
+                    let height = network_info.height;
                     let current = network_info.current.to_string();
 
                     return Ok(NetworkInfo {
@@ -92,6 +113,36 @@ impl ArweaveGateway {
         // This line should not be reachable due to the return statements inside the loop
         Err("Unexpected error in network_info function".to_string())
     }
+
+    // a 404 is an answer, only errors and 5xx count against the gateway
+    async fn send(&self, request: RequestBuilder) -> Result<Response, String> {
+        self.breaker.allow()?;
+        let result = with_deadline(request, self.timeout).send().await;
+        self.breaker.record(match &result {
+            Ok(response) => !response.status().is_server_error(),
+            Err(_) => false,
+        });
+        result.map_err(|e| format!("{}", e))
+    }
+
+    // a plain text answer of the gateway at path
+    async fn text(&self, path: &str) -> Result<String, String> {
+        let config = AoConfig::new(Some("su".to_string())).expect("Failed to read configuration");
+        let url = Url::parse(&config.gateway_url).map_err(|e| format!("{}", e))?;
+
+        let request = self
+            .client
+            .get(url.join(path).map_err(|e| format!("{}", e))?);
+        let response = self.send(request).await?;
+        if !response.status().is_success() {
+            return Err(format!(
+                "Gateway returned {} for {}",
+                response.status(),
+                path
+            ));
+        }
+        response.text().await.map_err(|e| format!("{}", e))
+    }
 }
 
 #[async_trait]
@@ -105,16 +156,16 @@ impl Gateway for ArweaveGateway {
             Err(e) => return Err(format!("{}", e)),
         };
 
-        let client = Client::new();
+        let client = &self.client;
 
-        let response = client
-            .head(
-                url.join(&format!("{}", tx_id))
-                    .map_err(|e| GatewayErrorType::CheckHeadError(e.to_string()))?,
-            )
-            .send()
+        let request = client.head(
+            url.join(&tx_id.to_string())
+                .map_err(|e| GatewayErrorType::CheckHead(e.to_string()))?,
+        );
+        let response = self
+            .send(request)
             .await
-            .map_err(|e| GatewayErrorType::CheckHeadError(e.to_string()))?;
+            .map_err(|e| GatewayErrorType::CheckHead(e.to_string()))?;
 
         let response_status = response.status();
 
@@ -140,25 +191,25 @@ impl Gateway for ArweaveGateway {
             Err(e) => return Err(format!("{}", e)),
         };
 
-        let client = Client::new();
+        let client = &self.client;
 
-        let response = client
-            .get(
-                url.join(&format!("tx/{}/status", tx_id))
-                    .map_err(|e| GatewayErrorType::StatusError(e.to_string()))?,
-            )
-            .send()
+        let request = client.get(
+            url.join(&format!("tx/{}/status", tx_id))
+                .map_err(|e| GatewayErrorType::Status(e.to_string()))?,
+        );
+        let response = self
+            .send(request)
             .await
-            .map_err(|e| GatewayErrorType::StatusError(e.to_string()))?;
+            .map_err(|e| GatewayErrorType::Status(e.to_string()))?;
 
         if response.status().is_success() {
             let body: serde_json::Value = response
                 .json()
                 .await
-                .map_err(|e| GatewayErrorType::StatusError(e.to_string()))?;
+                .map_err(|e| GatewayErrorType::Status(e.to_string()))?;
 
             let status: TxStatus = serde_json::from_value(body).map_err(|e| {
-                GatewayErrorType::StatusError(format!("Failed to deserialize tx status: {}", e))
+                GatewayErrorType::Status(format!("Failed to deserialize tx status: {}", e))
             })?;
 
             Ok(status)
@@ -169,4 +220,90 @@ impl Gateway for ArweaveGateway {
             ))
         }
     }
+
+    async fn raw(&self, tx_id: &String) -> Result<Vec<u8>, String> {
+        let config = AoConfig::new(Some("su".to_string())).expect("Failed to read configuration");
+        let gateway_url = config.gateway_url;
+
+        let url = match Url::parse(&gateway_url) {
+            Ok(u) => u,
+            Err(e) => return Err(format!("{}", e)),
+        };
+
+        let client = &self.client;
+
+        let request = client.get(
+            url.join(&format!("raw/{}", tx_id))
+                .map_err(|e| GatewayErrorType::Raw(e.to_string()))?,
+        );
+        let response = self
+            .send(request)
+            .await
+            .map_err(|e| GatewayErrorType::Raw(e.to_string()))?;
+
+        if response.status().is_success() {
+            let body = response
+                .bytes()
+                .await
+                .map_err(|e| GatewayErrorType::Raw(e.to_string()))?;
+            Ok(body.to_vec())
+        } else {
+            Err(format!(
+                "Failed to get raw data. Status code: {}",
+                response.status()
+            ))
+        }
+    }
+
+    async fn graphql(
+        &self,
+        query: &str,
+        variables: serde_json::Value,
+    ) -> Result<serde_json::Value, String> {
+        let config = AoConfig::new(Some("su".to_string())).expect("Failed to read configuration");
+        let url = Url::parse(&config.gateway_url).map_err(|e| format!("{}", e))?;
+
+        let client = &self.client;
+        let request = client
+            .post(url.join("graphql").map_err(|e| format!("{}", e))?)
+            .json(&serde_json::json!({ "query": query, "variables": variables }));
+        let response = self.send(request).await?;
+        if !response.status().is_success() {
+            return Err(format!(
+                "Graphql query failed. Status code: {}",
+                response.status()
+            ));
+        }
+
+        let mut body: serde_json::Value = response.json().await.map_err(|e| format!("{}", e))?;
+        if let Some(errors) = body.get("errors") {
+            return Err(format!("Graphql query failed: {}", errors));
+        }
+        Ok(body["data"].take())
+    }
+
+    async fn price(&self, bytes: usize) -> Result<u64, String> {
+        let body = self.text(&format!("price/{}", bytes)).await?;
+        body.trim()
+            .parse::<u64>()
+            .map_err(|e| format!("Invalid price {}: {}", body, e))
+    }
+
+    async fn tx_anchor(&self) -> Result<String, String> {
+        Ok(self.text("tx_anchor").await?.trim().to_string())
+    }
+
+    async fn ping(&self) -> Result<(), String> {
+        let config = AoConfig::new(Some("su".to_string())).expect("Failed to read configuration");
+        let url = Url::parse(&config.gateway_url).map_err(|e| format!("{}", e))?;
+
+        let client = &self.client;
+        let request = client.get(url.join("info").map_err(|e| format!("{}", e))?);
+        let response = self.send(request).await?;
+
+        match response.status().is_success() {
+            true => Ok(()),
+            false => Err(format!("Gateway returned {}", response.status())),
+        }
+    }
 }
@@ -0,0 +1,71 @@
+use std::fs;
+
+use reqwest::{Certificate, Client, ClientBuilder, Identity};
+use tokio::time::Duration;
+
+use crate::domain::core::dal::Config;
+
+// pings on idle connections so load balancers do not drop them
+const KEEP_ALIVE_INTERVAL: Duration = Duration::from_secs(30);
+
+fn builder(config: &dyn Config) -> ClientBuilder {
+    Client::builder()
+        .connect_timeout(Duration::from_millis(config.http_connect_timeout_ms()))
+        .timeout(Duration::from_millis(config.http_request_timeout_ms()))
+        .pool_idle_timeout(Duration::from_millis(config.http_pool_idle_timeout_ms()))
+        .pool_max_idle_per_host(config.http_pool_max_idle_per_host())
+        .tcp_keepalive(KEEP_ALIVE_INTERVAL)
+        .http2_adaptive_window(true)
+        .http2_keep_alive_interval(KEEP_ALIVE_INTERVAL)
+        .http2_keep_alive_while_idle(true)
+}
+
+/*
+    The one client the su calls the gateway and the upload
+    node with. Connections are pooled and kept alive
+    between requests instead of opened for each one,
+    HTTP/2 is used with servers that offer it.
+*/
+pub fn http_client(config: &dyn Config) -> Result<Client, String> {
+    builder(config).build().map_err(|e| format!("{:?}", e))
+}
+
+fn read_pem(name: &str, path: &str) -> Result<Vec<u8>, String> {
+    fs::read(path).map_err(|e| format!("{} {}: {}", name, path, e))
+}
+
+/*
+    The client a router calls its schedulers with. It also
+    trusts SCHEDULER_TLS_CA_PATH and, for schedulers that
+    require mutual TLS, presents SCHEDULER_TLS_CERT_PATH.
+*/
+pub fn scheduler_client(config: &dyn Config) -> Result<Client, String> {
+    let mut builder = builder(config);
+    if let Some(ca_path) = config.scheduler_tls_ca_path() {
+        let pem = read_pem("SCHEDULER_TLS_CA_PATH", &ca_path)?;
+        let ca = Certificate::from_pem(&pem)
+            .map_err(|e| format!("SCHEDULER_TLS_CA_PATH {}: {}", ca_path, e))?;
+        builder = builder.add_root_certificate(ca);
+    }
+    match (
+        config.scheduler_tls_cert_path(),
+        config.scheduler_tls_key_path(),
+    ) {
+        (Some(cert_path), Some(key_path)) => {
+            let cert = read_pem("SCHEDULER_TLS_CERT_PATH", &cert_path)?;
+            let key = read_pem("SCHEDULER_TLS_KEY_PATH", &key_path)?;
+            // native-tls only loads keys in PKCS#8
+            let identity = Identity::from_pkcs8_pem(&cert, &key)
+                .map_err(|e| format!("SCHEDULER_TLS_KEY_PATH {}: {}", key_path, e))?;
+            builder = builder.identity(identity);
+        }
+        (None, None) => (),
+        _ => {
+            return Err(
+                "SCHEDULER_TLS_CERT_PATH and SCHEDULER_TLS_KEY_PATH must be set together"
+                    .to_string(),
+            )
+        }
+    }
+    builder.build().map_err(|e| format!("{:?}", e))
+}
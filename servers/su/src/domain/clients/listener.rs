@@ -0,0 +1,120 @@
+use std::io::{self, ErrorKind};
+use std::net::{Ipv6Addr, SocketAddr, TcpListener};
+use std::sync::Arc;
+
+use socket2::{Domain, Protocol, Socket, Type};
+use tokio::io::{copy_bidirectional, AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpSocket, TcpStream};
+use tokio::time::{sleep, timeout, Duration};
+
+use crate::domain::core::dal::{Config, Log};
+use crate::domain::core::proxies::{parse_proxy_header, TrustedProxies};
+
+// connections waiting to be accepted, as actix sets it
+const BACKLOG: i32 = 2048;
+
+// a balancer sends the header as soon as it connects
+const HEADER_TIMEOUT: Duration = Duration::from_secs(10);
+
+/*
+    The listener of the http server. Without BIND_ADDRESS
+    it is the ipv6 wildcard with IPV6_V6ONLY off, so ipv4
+    clients are accepted whatever the host default, hosts
+    with ipv6 disabled fall back to ipv4 only. With
+    PROXY_PROTOCOL the server listens on loopback behind a
+    relay that reads the header of every connection.
+*/
+pub fn listen(
+    config: &dyn Config,
+    port: u16,
+    proxies: Arc<TrustedProxies>,
+    logger: Arc<dyn Log>,
+) -> io::Result<TcpListener> {
+    let listener = match config.bind_address() {
+        Some(address) => TcpListener::bind((address.as_str(), port))?,
+        None => match dual_stack(port) {
+            Ok(l) => l,
+            Err(_) => TcpListener::bind(("0.0.0.0", port))?,
+        },
+    };
+    if !config.proxy_protocol() {
+        return Ok(listener);
+    }
+
+    let server = TcpListener::bind(("127.0.0.1", 0))?;
+    let server_addr = server.local_addr()?;
+    listener.set_nonblocking(true)?;
+    let listener = tokio::net::TcpListener::from_std(listener)?;
+    tokio::spawn(async move {
+        loop {
+            let (client, peer) = match listener.accept().await {
+                Ok(accepted) => accepted,
+                Err(e) => {
+                    // out of file descriptors, give connections time to close
+                    logger.error(format!("failed to accept a connection - {}", e));
+                    sleep(Duration::from_millis(100)).await;
+                    continue;
+                }
+            };
+            let proxies = proxies.clone();
+            tokio::spawn(async move {
+                let _ = relay(client, peer, server_addr, &proxies).await;
+            });
+        }
+    });
+    Ok(server)
+}
+
+fn dual_stack(port: u16) -> io::Result<TcpListener> {
+    let socket = Socket::new(Domain::IPV6, Type::STREAM, Some(Protocol::TCP))?;
+    socket.set_only_v6(false)?;
+    socket.set_reuse_address(true)?;
+    socket.bind(&SocketAddr::from((Ipv6Addr::UNSPECIFIED, port)).into())?;
+    socket.listen(BACKLOG)?;
+    Ok(socket.into())
+}
+
+/*
+    passes a connection on to the server once its header
+    is read. The server sees the relay as the peer, the
+    port of the relay is recorded with the client address
+    for as long as the connection lasts
+*/
+async fn relay(
+    mut client: TcpStream,
+    peer: SocketAddr,
+    server: SocketAddr,
+    proxies: &TrustedProxies,
+) -> io::Result<()> {
+    let (source, early) = timeout(HEADER_TIMEOUT, read_header(&mut client))
+        .await
+        .map_err(|_| io::Error::new(ErrorKind::TimedOut, "no PROXY header"))??;
+
+    let socket = TcpSocket::new_v4()?;
+    socket.bind(SocketAddr::from(([127, 0, 0, 1], 0)))?;
+    let port = socket.local_addr()?.port();
+    proxies.relay(port, peer.ip(), source.map(|s| s.ip()));
+    let relayed = async {
+        let mut upstream = socket.connect(server).await?;
+        upstream.write_all(&early).await?;
+        copy_bidirectional(&mut client, &mut upstream).await
+    }
+    .await;
+    proxies.end_relay(port);
+    relayed.map(|_| ())
+}
+
+// the client address of the header and what was read past it
+async fn read_header(client: &mut TcpStream) -> io::Result<(Option<SocketAddr>, Vec<u8>)> {
+    let mut buf = Vec::with_capacity(256);
+    loop {
+        let parsed =
+            parse_proxy_header(&buf).map_err(|e| io::Error::new(ErrorKind::InvalidData, e))?;
+        if let Some((len, source)) = parsed {
+            return Ok((source, buf.split_off(len)));
+        }
+        if client.read_buf(&mut buf).await? == 0 {
+            return Err(ErrorKind::UnexpectedEof.into());
+        }
+    }
+}
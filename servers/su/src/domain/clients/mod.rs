@@ -8,12 +8,42 @@ mod schema;
 // uploader to a service like irys
 pub mod uploader;
 
+// bundles posted straight to an arweave node
+pub mod direct;
+
 // database layer
 pub mod store;
 
 // arweave gateway
 pub mod gateway;
 
+// the http listener, dual stack and behind the PROXY protocol
+pub mod listener;
+
+// pooled http client shared by the outgoing requests
+pub mod http;
+
+// certificates of the https listener
+pub mod tls;
+
+// bundle binaries kept outside the database
+pub mod blobs;
+
+// compressed segments of tiered message history
+pub mod cold;
+
+// database snapshots kept in an S3 bucket
+pub mod backup;
+
+// posts sequencing events to configured urls
+pub mod webhooks;
+
+// kafka and nats publishers for the event outbox
+pub mod publisher;
+
+// follows the event stream of a primary su
+pub mod standby;
+
 // wallet implementation
 pub mod wallet;
 
@@ -0,0 +1,221 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use reqwest::{Client, Url};
+use serde_json::json;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufStream};
+use tokio::net::TcpStream;
+use tokio::sync::Mutex;
+use tokio::time::{timeout, Duration};
+
+use crate::domain::core::dal::{Config, EventPublisher, OutboxEvent};
+
+// how long the broker has to confirm a batch
+const CONFIRM_TIMEOUT: Duration = Duration::from_secs(10);
+
+/*
+    Publishes to a nats server over its text protocol on
+    the subject <topic>.<process id>. A PING after the
+    batch is answered once the server has processed every
+    PUB before it, which confirms the batch
+*/
+pub struct NatsPublisher {
+    url: Url,
+    topic: String,
+    conn: Mutex<Option<BufStream<TcpStream>>>,
+}
+
+impl NatsPublisher {
+    pub fn new(url: &str, topic: String) -> Result<Self, String> {
+        let url = Url::parse(url).map_err(|e| format!("Invalid EVENT_PUBLISHER_URL: {}", e))?;
+        if url.scheme() != "nats" {
+            return Err("EVENT_PUBLISHER_URL must be a nats:// url".to_string());
+        }
+        Ok(NatsPublisher {
+            url,
+            topic,
+            conn: Mutex::new(None),
+        })
+    }
+
+    async fn connect(&self) -> Result<BufStream<TcpStream>, String> {
+        let host = self.url.host_str().ok_or("nats url has no host")?;
+        let port = self.url.port().unwrap_or(4222);
+        let stream = TcpStream::connect((host, port))
+            .await
+            .map_err(|e| e.to_string())?;
+        let mut conn = BufStream::new(stream);
+
+        let info = read_line(&mut conn).await?;
+        if !info.starts_with("INFO") {
+            return Err(format!("unexpected greeting from nats: {}", info));
+        }
+
+        let mut options = json!({ "verbose": false, "pedantic": false, "name": "su" });
+        match (self.url.username(), self.url.password()) {
+            ("", _) => (),
+            (user, Some(pass)) => {
+                options["user"] = json!(user);
+                options["pass"] = json!(pass);
+            }
+            (token, None) => options["auth_token"] = json!(token),
+        }
+        conn.write_all(format!("CONNECT {}\r\n", options).as_bytes())
+            .await
+            .map_err(|e| e.to_string())?;
+        Ok(conn)
+    }
+
+    async fn send(
+        &self,
+        conn: &mut BufStream<TcpStream>,
+        events: &[OutboxEvent],
+    ) -> Result<(), String> {
+        for event in events {
+            let header = format!(
+                "PUB {}.{} {}\r\n",
+                self.topic,
+                event.process_id,
+                event.event.len()
+            );
+            conn.write_all(header.as_bytes())
+                .await
+                .map_err(|e| e.to_string())?;
+            conn.write_all(event.event.as_bytes())
+                .await
+                .map_err(|e| e.to_string())?;
+            conn.write_all(b"\r\n").await.map_err(|e| e.to_string())?;
+        }
+        conn.write_all(b"PING\r\n")
+            .await
+            .map_err(|e| e.to_string())?;
+        conn.flush().await.map_err(|e| e.to_string())?;
+
+        loop {
+            let line = read_line(conn).await?;
+            match line.as_str() {
+                "PONG" => return Ok(()),
+                "PING" => conn
+                    .write_all(b"PONG\r\n")
+                    .await
+                    .map_err(|e| e.to_string())?,
+                l if l.starts_with("-ERR") => return Err(format!("nats error {}", l)),
+                _ => (),
+            }
+        }
+    }
+}
+
+async fn read_line(conn: &mut BufStream<TcpStream>) -> Result<String, String> {
+    let mut line = String::new();
+    match conn.read_line(&mut line).await {
+        Ok(0) => Err("nats closed the connection".to_string()),
+        Ok(_) => Ok(line.trim_end().to_string()),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+#[async_trait]
+impl EventPublisher for NatsPublisher {
+    async fn publish(&self, events: &[OutboxEvent]) -> Result<(), String> {
+        let mut guard = self.conn.lock().await;
+        let mut conn = match guard.take() {
+            Some(c) => c,
+            None => self.connect().await?,
+        };
+        // the connection is only kept when the batch was confirmed
+        match timeout(CONFIRM_TIMEOUT, self.send(&mut conn, events)).await {
+            Ok(Ok(())) => {
+                *guard = Some(conn);
+                Ok(())
+            }
+            Ok(Err(e)) => Err(e),
+            Err(_) => Err("nats did not confirm the batch in time".to_string()),
+        }
+    }
+}
+
+/*
+    Publishes to kafka through a REST Proxy, keyed by
+    process id so the events of a process stay on one
+    partition and in order
+*/
+pub struct KafkaRestPublisher {
+    client: Client,
+    url: Url,
+}
+
+impl KafkaRestPublisher {
+    pub fn new(url: &str, topic: &str) -> Result<Self, String> {
+        let base = Url::parse(url).map_err(|e| format!("Invalid EVENT_PUBLISHER_URL: {}", e))?;
+        let url = base
+            .join(&format!("topics/{}", topic))
+            .map_err(|e| format!("Invalid EVENT_PUBLISHER_TOPIC: {}", e))?;
+        let client = Client::builder()
+            .timeout(CONFIRM_TIMEOUT)
+            .build()
+            .map_err(|e| format!("{:?}", e))?;
+        Ok(KafkaRestPublisher { client, url })
+    }
+}
+
+#[async_trait]
+impl EventPublisher for KafkaRestPublisher {
+    async fn publish(&self, events: &[OutboxEvent]) -> Result<(), String> {
+        let mut records = vec![];
+        for event in events {
+            let value: serde_json::Value =
+                serde_json::from_str(&event.event).map_err(|e| e.to_string())?;
+            records.push(json!({ "key": event.process_id, "value": value }));
+        }
+
+        let response = self
+            .client
+            .post(self.url.clone())
+            .header("Content-Type", "application/vnd.kafka.json.v2+json")
+            .body(json!({ "records": records }).to_string())
+            .send()
+            .await
+            .map_err(|e| e.to_string())?;
+        if !response.status().is_success() {
+            return Err(format!("kafka rest proxy status {}", response.status()));
+        }
+
+        // the proxy answers 200 even when single records failed
+        let body: serde_json::Value = response.json().await.map_err(|e| e.to_string())?;
+        let failed = body["offsets"]
+            .as_array()
+            .map(|offsets| {
+                offsets
+                    .iter()
+                    .filter(|o| !o["error_code"].is_null())
+                    .count()
+            })
+            .unwrap_or(0);
+        match failed {
+            0 => Ok(()),
+            n => Err(format!("kafka rejected {} of {} events", n, events.len())),
+        }
+    }
+}
+
+// None when EVENT_PUBLISHER is none
+pub fn from_config(config: &dyn Config) -> Result<Option<Arc<dyn EventPublisher>>, String> {
+    let url = || {
+        config
+            .event_publisher_url()
+            .ok_or("EVENT_PUBLISHER_URL is required for an event publisher".to_string())
+    };
+    match config.event_publisher().as_str() {
+        "none" => Ok(None),
+        "nats" => Ok(Some(Arc::new(NatsPublisher::new(
+            &url()?,
+            config.event_publisher_topic(),
+        )?))),
+        "kafka" => Ok(Some(Arc::new(KafkaRestPublisher::new(
+            &url()?,
+            &config.event_publisher_topic(),
+        )?))),
+        other => Err(format!("Unknown EVENT_PUBLISHER {}", other)),
+    }
+}
@@ -6,6 +6,8 @@ table! {
         process_id -> Varchar,
         process_data -> Jsonb,
         bundle -> Bytea,
+        bundle_checksum -> Nullable<Varchar>,
+        bundle_location -> Nullable<Varchar>,
     }
 }
 
@@ -21,6 +23,8 @@ table! {
         timestamp -> BigInt,
         bundle -> Bytea,
         hash_chain -> Text,
+        bundle_checksum -> Nullable<Varchar>,
+        bundle_location -> Nullable<Varchar>,
     }
 }
 
@@ -40,4 +44,213 @@ table! {
     }
 }
 
-allow_tables_to_appear_in_same_query!(processes, messages, schedulers, process_schedulers,);
+table! {
+    api_tokens (row_id) {
+        row_id -> Int4,
+        token_hash -> Varchar,
+        owner -> Nullable<Varchar>,
+        process_ids -> Jsonb,
+    }
+}
+
+table! {
+    store_meta (key) {
+        key -> Varchar,
+        value -> Text,
+    }
+}
+
+table! {
+    event_outbox (row_id) {
+        row_id -> Int4,
+        process_id -> Varchar,
+        event -> Jsonb,
+        attempts -> Int4,
+    }
+}
+
+table! {
+    event_outbox_dead (row_id) {
+        row_id -> Int4,
+        process_id -> Varchar,
+        event -> Jsonb,
+        error -> Text,
+        dead_at -> Timestamptz,
+    }
+}
+
+table! {
+    checkpoints (row_id) {
+        row_id -> Int4,
+        process_id -> Varchar,
+        epoch -> Int4,
+        nonce -> Int4,
+        checkpoint_id -> Varchar,
+        cu -> Nullable<Varchar>,
+        timestamp -> BigInt,
+    }
+}
+
+table! {
+    attestations (row_id) {
+        row_id -> Int4,
+        process_id -> Varchar,
+        epoch -> Int4,
+        nonce -> Int4,
+        hash_chain -> Text,
+        root -> Varchar,
+        leaf_count -> BigInt,
+        attestation_id -> Varchar,
+        timestamp -> BigInt,
+        frontier -> Nullable<Jsonb>,
+    }
+}
+
+table! {
+    cold_segments (row_id) {
+        row_id -> Int4,
+        process_id -> Varchar,
+        from_epoch -> Int4,
+        from_nonce -> Int4,
+        to_epoch -> Int4,
+        to_nonce -> Int4,
+        from_timestamp -> BigInt,
+        to_timestamp -> BigInt,
+        message_count -> Int4,
+        segment_key -> Varchar,
+        segment_size -> BigInt,
+        bundles_key -> Nullable<Varchar>,
+    }
+}
+
+table! {
+    cold_messages (row_id) {
+        row_id -> Int4,
+        segment_row_id -> Int4,
+        position -> Int4,
+        process_id -> Varchar,
+        message_id -> Varchar,
+        assignment_id -> Nullable<Varchar>,
+        epoch -> Int4,
+        nonce -> Int4,
+        carries_message -> Bool,
+    }
+}
+
+table! {
+    pruned_messages (row_id) {
+        row_id -> Int4,
+        message_id -> Varchar,
+        assignment_id -> Nullable<Varchar>,
+        process_id -> Varchar,
+        epoch -> Int4,
+        nonce -> Int4,
+    }
+}
+
+table! {
+    crons (row_id) {
+        row_id -> Int4,
+        process_id -> Varchar,
+        cron_interval -> Varchar,
+        interval_ms -> BigInt,
+        tags -> Jsonb,
+        next_run -> BigInt,
+    }
+}
+
+table! {
+    assignments (row_id) {
+        row_id -> Int4,
+        process_id -> Varchar,
+        assignment_id -> Varchar,
+        message_id -> Varchar,
+        epoch -> Int4,
+        nonce -> Int4,
+        timestamp -> BigInt,
+        hash_chain -> Text,
+        block_height -> Nullable<Varchar>,
+    }
+}
+
+table! {
+    assignment_decisions (row_id) {
+        row_id -> Int4,
+        process_id -> Varchar,
+        scheduler_url -> Varchar,
+        strategy -> Varchar,
+        region -> Nullable<Varchar>,
+        candidates -> Jsonb,
+        timestamp -> BigInt,
+    }
+}
+
+table! {
+    upload_receipts (row_id) {
+        row_id -> Int4,
+        bundle_id -> Varchar,
+        timestamp -> BigInt,
+        version -> Varchar,
+        public_key -> Text,
+        signature -> Text,
+        deadline_height -> BigInt,
+    }
+}
+
+table! {
+    process_sequence (process_id) {
+        process_id -> Varchar,
+        epoch -> Int4,
+        nonce -> Int4,
+        hash_chain -> Text,
+        assignment_id -> Varchar,
+    }
+}
+
+table! {
+    process_leases (process_id) {
+        process_id -> Varchar,
+        holder -> Varchar,
+        expires_at -> Timestamptz,
+    }
+}
+
+table! {
+    process_wallets (scheduler, process_id) {
+        scheduler -> Varchar,
+        process_id -> Varchar,
+    }
+}
+
+table! {
+    undelivered_uploads (item_id) {
+        item_id -> Varchar,
+        bundle -> Bytea,
+        error -> Text,
+        failed_at -> Timestamptz,
+    }
+}
+
+allow_tables_to_appear_in_same_query!(
+    processes,
+    messages,
+    schedulers,
+    process_schedulers,
+    api_tokens,
+    store_meta,
+    event_outbox,
+    event_outbox_dead,
+    checkpoints,
+    attestations,
+    cold_segments,
+    cold_messages,
+    pruned_messages,
+    crons,
+    assignments,
+    assignment_decisions,
+    upload_receipts,
+    process_sequence,
+    process_leases,
+    undelivered_uploads,
+    process_wallets,
+);
@@ -0,0 +1,84 @@
+use std::sync::Arc;
+
+use reqwest::{Client, Url};
+use tokio::time::{sleep, Duration};
+
+use crate::domain::core::cache::ReadCache;
+use crate::domain::core::dal::DomainEvent;
+use crate::domain::Log;
+
+const MAX_BACKOFF_SECS: u64 = 30;
+
+/*
+    Follows the event stream of a primary su and applies
+    it to the local ReadCache. Reconnects with a backoff
+    whenever the stream drops, clearing the latest messages
+    since any events missed in between are gone.
+*/
+pub fn spawn_standby_feed(
+    events_url: &str,
+    token: Option<String>,
+    cache: Arc<ReadCache>,
+    logger: Arc<dyn Log>,
+) -> Result<(), String> {
+    let url = Url::parse(events_url).map_err(|e| format!("Invalid PRIMARY_EVENTS_URL: {}", e))?;
+
+    tokio::spawn(async move {
+        let client = Client::new();
+        let mut backoff = 1;
+        loop {
+            match follow(&client, &url, &token, &cache).await {
+                Ok(applied) => {
+                    logger.log(format!(
+                        "primary event stream ended after {} events",
+                        applied
+                    ));
+                    if applied > 0 {
+                        backoff = 1;
+                    }
+                }
+                Err(e) => logger.error(format!("primary event stream error - {}", e)),
+            }
+            cache.clear_latest();
+            sleep(Duration::from_secs(backoff)).await;
+            backoff = (backoff * 2).min(MAX_BACKOFF_SECS);
+        }
+    });
+
+    Ok(())
+}
+
+async fn follow(
+    client: &Client,
+    url: &Url,
+    token: &Option<String>,
+    cache: &ReadCache,
+) -> Result<u64, String> {
+    let mut request = client.get(url.clone());
+    if let Some(t) = token {
+        request = request.bearer_auth(t);
+    }
+
+    let mut response = request.send().await.map_err(|e| e.to_string())?;
+    if !response.status().is_success() {
+        return Err(format!("status {}", response.status()));
+    }
+
+    let mut buffer: Vec<u8> = vec![];
+    let mut applied = 0;
+    while let Some(chunk) = response.chunk().await.map_err(|e| e.to_string())? {
+        buffer.extend_from_slice(&chunk);
+        while let Some(end) = buffer.iter().position(|b| *b == b'\n') {
+            let line: Vec<u8> = buffer.drain(..=end).collect();
+            match serde_json::from_slice::<DomainEvent>(&line) {
+                Ok(event) => {
+                    cache.apply(&event);
+                    applied += 1;
+                }
+                Err(e) => return Err(format!("invalid event - {}", e)),
+            }
+        }
+    }
+
+    Ok(applied)
+}
@@ -1,24 +1,73 @@
 use std::env::VarError;
+use std::sync::mpsc;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
 
-use diesel::pg::PgConnection;
+use async_trait::async_trait;
+use bytes::Bytes;
+
+use diesel::dsl::sql;
+use diesel::migration::MigrationSource;
+use diesel::pg::{Pg, PgConnection};
 use diesel::prelude::*;
 use diesel::r2d2::ConnectionManager;
+use diesel::r2d2::CustomizeConnection;
 use diesel::r2d2::Pool;
+use diesel::sql_types::{BigInt, Bool, Int4, Jsonb, Nullable, Text};
 use diesel_migrations::{embed_migrations, EmbeddedMigrations, MigrationHarness};
+use sha2::{Digest, Sha256};
+use tokio::sync::oneshot;
 
+use super::super::core::bytes::DataItem;
 use super::super::core::dal::{
-    DataStore, JsonErrorType, Message, PaginatedMessages, Process, ProcessScheduler, Scheduler,
-    StoreErrorType,
+    ApiToken, Assignment, AssignmentDecision, Attestation, BlobStore, BundleRef, Checkpoint,
+    ColdSegment, CronDefinition, DataStore, DomainEvent, JsonErrorType, Message, MessageCount,
+    MessageQuery, OutboxEvent, PaginatedMessages, PaginatedProcesses, Process, ProcessScheduler,
+    ProcessSequence, PrunableMessage, Scheduler, SortOrder, StoreErrorType, StoreStats,
+    UploadReceipt,
 };
+use super::super::core::deadline::Deadline;
+use super::blobs;
+use super::cold::{self, ColdRow};
 use crate::domain::config::AoConfig;
 
 pub const MIGRATIONS: EmbeddedMigrations = embed_migrations!("./migrations");
 
+/*
+    postgres advisory lock key held while the schema is
+    checked and migrated, any su sharing the database
+    waits for it so only one of them applies migrations
+*/
+const MIGRATION_LOCK_ID: i64 = 0x5375_4d69_6772_6174;
+
+// the unique index on the slots of the assignments
+const SLOT_INDEX: &str = "idx_assignments_process_slot";
+
+// how long a read waits on the replica before using the primary
+const REPLICA_WAIT_MS: u64 = 1000;
+
+type PooledConn = diesel::r2d2::PooledConnection<ConnectionManager<PgConnection>>;
+
+// messages with their bundles, in schedule order
+type MessageBundles = Vec<(Message, Vec<u8>)>;
+
+use diesel::result::DatabaseErrorKind;
 use diesel::result::Error as DieselError; // Import Diesel's Error
 
 impl From<DieselError> for StoreErrorType {
     fn from(diesel_error: DieselError) -> Self {
-        StoreErrorType::DatabaseError(format!("{:?}", diesel_error))
+        match diesel_error {
+            DieselError::DatabaseError(DatabaseErrorKind::ClosedConnection, _) => {
+                StoreErrorType::ConnectionError(format!("{:?}", diesel_error))
+            }
+            DieselError::DatabaseError(DatabaseErrorKind::UniqueViolation, ref info)
+                if info.constraint_name() == Some(SLOT_INDEX) =>
+            {
+                StoreErrorType::SlotTaken(format!("{:?}", diesel_error))
+            }
+            _ => StoreErrorType::DatabaseError(format!("{:?}", diesel_error)),
+        }
     }
 }
 
@@ -48,7 +97,7 @@ impl From<VarError> for StoreErrorType {
 
 impl From<diesel::prelude::ConnectionError> for StoreErrorType {
     fn from(error: diesel::prelude::ConnectionError) -> Self {
-        StoreErrorType::DatabaseError(format!("data store connection error: {}", error))
+        StoreErrorType::ConnectionError(format!("data store connection error: {}", error))
     }
 }
 
@@ -58,72 +107,832 @@ impl From<std::num::ParseIntError> for StoreErrorType {
     }
 }
 
+#[derive(Clone)]
 pub struct StoreClient {
     pool: Pool<ConnectionManager<PgConnection>>,
+    // reads that can lag behind the primary, None sends them to the primary
+    read_pool: Option<Pool<ConnectionManager<PgConnection>>>,
+    verify_checksums: bool,
+    blobs: Option<Arc<dyn BlobStore>>,
+    offload_min_size: usize,
+    // sequencing events are also written to event_outbox for a publisher
+    outbox: bool,
+    // None saves every message in a transaction of its own
+    group_commit: Option<GroupCommit>,
+    // CLUSTER_NODE_URL, a message is only saved while no other instance holds the lease
+    lease_holder: Option<String>,
+}
+
+// a message saved by a write, waiting for the next group commit
+struct PendingSave {
+    message: Message,
+    bundle: Bytes,
+    done: oneshot::Sender<Result<String, StoreErrorType>>,
+}
+
+/*
+    With STORE_GROUP_COMMIT_MS the messages concurrent
+    writes save are gathered for that long, or until there
+    are STORE_GROUP_COMMIT_MAX of them, and committed in
+    one transaction by a thread of their own, so a busy su
+    pays for one commit instead of one per message. A write
+    only hears back once its transaction committed. If one
+    message fails the transaction, e.g. a duplicate, each is
+    saved on its own so the others still go through.
+*/
+#[derive(Clone)]
+struct GroupCommit {
+    queue: mpsc::Sender<PendingSave>,
+}
+
+impl GroupCommit {
+    fn spawn(store: StoreClient, window: Duration, max: usize) -> Result<Self, StoreErrorType> {
+        let (queue, pending) = mpsc::channel();
+        thread::Builder::new()
+            .name("group-commit".to_string())
+            .spawn(move || {
+                while let Some(batch) = gather(&pending, window, max) {
+                    store.commit_group(batch);
+                }
+            })
+            .map_err(|e| StoreErrorType::DatabaseError(format!("{}", e)))?;
+        Ok(GroupCommit { queue })
+    }
+
+    async fn save(&self, message: Message, bundle: Bytes) -> Result<String, StoreErrorType> {
+        let stopped = || StoreErrorType::DatabaseError("group commit thread stopped".to_string());
+        let (done, result) = oneshot::channel();
+        self.queue
+            .send(PendingSave {
+                message,
+                bundle,
+                done,
+            })
+            .map_err(|_| stopped())?;
+        result.await.map_err(|_| stopped())?
+    }
+}
+
+/*
+    waits for the first item, then takes what else arrives
+    within window of it up to max. None once every sender
+    is gone
+*/
+fn gather<T>(queue: &mpsc::Receiver<T>, window: Duration, max: usize) -> Option<Vec<T>> {
+    let first = queue.recv().ok()?;
+    let until = Instant::now() + window;
+    let mut batch = vec![first];
+    while batch.len() < max {
+        match queue.recv_timeout(until.saturating_duration_since(Instant::now())) {
+            Ok(item) => batch.push(item),
+            Err(_) => break,
+        }
+    }
+    Some(batch)
+}
+
+// compares dotted numeric versions, pre-release suffixes are ignored
+fn is_newer_version(version: &str, than: &str) -> bool {
+    let parse = |v: &str| -> Vec<u64> {
+        v.split(['-', '+'])
+            .next()
+            .unwrap_or("")
+            .split('.')
+            .map(|part| part.parse().unwrap_or(0))
+            .collect()
+    };
+    parse(version) > parse(than)
+}
+
+/*
+    runs op while holding the migration lock. the lock
+    belongs to the session so it is released explicitly,
+    the connection goes back to the pool afterwards.
+    migrations are exempt from the statement timeout
+*/
+fn with_migration_lock<T>(
+    conn: &mut PgConnection,
+    op: impl FnOnce(&mut PgConnection) -> Result<T, StoreErrorType>,
+) -> Result<T, StoreErrorType> {
+    let statement_timeout: String =
+        diesel::select(sql::<Text>("current_setting('statement_timeout')")).get_result(conn)?;
+    diesel::sql_query("SET statement_timeout = 0").execute(conn)?;
+    diesel::sql_query("SELECT pg_advisory_lock($1)")
+        .bind::<BigInt, _>(MIGRATION_LOCK_ID)
+        .execute(conn)?;
+    let result = op(conn);
+    let unlocked = diesel::sql_query("SELECT pg_advisory_unlock($1)")
+        .bind::<BigInt, _>(MIGRATION_LOCK_ID)
+        .execute(conn);
+    let reset = diesel::sql_query("SELECT set_config('statement_timeout', $1, false)")
+        .bind::<Text, _>(statement_timeout)
+        .execute(conn);
+    let result = result?;
+    unlocked?;
+    reset?;
+    Ok(result)
+}
+
+fn bundle_checksum_of(bundle: &[u8]) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(bundle);
+    base64_url::encode(&hasher.finalize().to_vec())
+}
+
+/*
+    the inclusive timestamps a page of get_messages covers,
+    the cursors exclude the message they point at
+*/
+fn window_bounds(
+    from_cursor: Option<i64>,
+    to_cursor: Option<i64>,
+    from_timestamp: Option<i64>,
+    to_timestamp: Option<i64>,
+    sort: &SortOrder,
+) -> (Option<i64>, Option<i64>) {
+    let (lower_cursor, upper_cursor) = match sort {
+        SortOrder::Asc => (from_cursor.map(|f| f.saturating_add(1)), to_cursor),
+        SortOrder::Desc => (to_cursor, from_cursor.map(|f| f.saturating_sub(1))),
+    };
+    let lowest = [lower_cursor, from_timestamp].into_iter().flatten().max();
+    let highest = [upper_cursor, to_timestamp].into_iter().flatten().min();
+    (lowest, highest)
+}
+
+/*
+    what goes in the bundle column, offloaded rows keep
+    an empty one. they are always saved with the current
+    message shape so reads never need the bundle itself
+*/
+fn stored_bundle<'a>(bundle_in: &'a [u8], location: &Option<String>) -> &'a [u8] {
+    match location {
+        Some(_) => &[],
+        None => bundle_in,
+    }
+}
+
+// bounds every statement run on a pooled connection
+#[derive(Debug)]
+struct StatementTimeout(u64);
+
+impl CustomizeConnection<PgConnection, diesel::r2d2::Error> for StatementTimeout {
+    fn on_acquire(&self, conn: &mut PgConnection) -> Result<(), diesel::r2d2::Error> {
+        diesel::sql_query(format!("SET statement_timeout = {}", self.0))
+            .execute(conn)
+            .map(|_| ())
+            .map_err(diesel::r2d2::Error::QueryError)
+    }
+}
+
+fn pool_builder(config: &AoConfig) -> diesel::r2d2::Builder<ConnectionManager<PgConnection>> {
+    let mut builder = Pool::builder()
+        .test_on_check_out(true)
+        .max_size(config.database_pool_size)
+        .connection_timeout(Duration::from_millis(config.database_connect_timeout_ms));
+    if config.database_statement_timeout_ms > 0 {
+        builder = builder.connection_customizer(Box::new(StatementTimeout(
+            config.database_statement_timeout_ms,
+        )));
+    }
+    builder
 }
 
 impl StoreClient {
     pub fn new() -> Result<Self, StoreErrorType> {
         let config = AoConfig::new(Some("su".to_string())).expect("Failed to read configuration");
-        let database_url = config.database_url;
+        let database_url = config.database_url.clone();
         let manager = ConnectionManager::<PgConnection>::new(database_url);
-        let pool = Pool::builder()
-            .test_on_check_out(true)
-            .build(manager)
-            .map_err(|_| {
-                StoreErrorType::DatabaseError("Failed to initialize connection pool.".to_string())
-            })?;
+        let pool = pool_builder(&config).build(manager).map_err(|_| {
+            StoreErrorType::DatabaseError("Failed to initialize connection pool.".to_string())
+        })?;
+
+        // the replica is not required to be up for the su to start
+        let read_pool = config.database_read_url.clone().map(|read_url| {
+            pool_builder(&config)
+                .connection_timeout(Duration::from_millis(REPLICA_WAIT_MS))
+                .build_unchecked(ConnectionManager::<PgConnection>::new(read_url))
+        });
+
+        let blobs = blobs::from_config(&config).map_err(StoreErrorType::DatabaseError)?;
+
+        let mut store = StoreClient {
+            pool,
+            read_pool,
+            verify_checksums: config.verify_bundle_checksums,
+            blobs,
+            offload_min_size: config.bundle_offload_min_size,
+            outbox: config.event_publisher != "none",
+            group_commit: None,
+            lease_holder: config.cluster_node_url.clone(),
+        };
+        if config.store_group_commit_ms > 0 {
+            store.group_commit = Some(GroupCommit::spawn(
+                store.clone(),
+                Duration::from_millis(config.store_group_commit_ms),
+                config.store_group_commit_max.max(1),
+            )?);
+        }
+        Ok(store)
+    }
+
+    // saves check the lease of the process is held under this name
+    pub fn with_lease_holder(mut self, holder: String) -> Self {
+        self.lease_holder = Some(holder);
+        self
+    }
 
-        Ok(StoreClient { pool })
+    /*
+        written in the same transaction as the row the
+        event is about, so a saved message is never
+        missing from the outbox
+    */
+    fn push_outbox(
+        &self,
+        conn: &mut PgConnection,
+        process_id_in: &str,
+        event_in: &DomainEvent,
+    ) -> Result<(), StoreErrorType> {
+        use super::schema::event_outbox::dsl::*;
+
+        if !self.outbox {
+            return Ok(());
+        }
+        diesel::insert_into(event_outbox)
+            .values((
+                process_id.eq(process_id_in),
+                event.eq(serde_json::to_value(event_in)?),
+            ))
+            .execute(conn)?;
+        Ok(())
     }
 
-    pub fn get_conn(
+    // the assignment of a message, saved with the message
+    fn insert_assignment(
         &self,
-    ) -> Result<diesel::r2d2::PooledConnection<ConnectionManager<PgConnection>>, StoreErrorType>
-    {
-        self.pool.get().map_err(|_| {
-            StoreErrorType::DatabaseError("Failed to get connection from pool.".to_string())
+        conn: &mut PgConnection,
+        message: &Message,
+    ) -> Result<(), StoreErrorType> {
+        use super::schema::assignments::dsl::*;
+
+        let assignment = Assignment::from_message(message)?;
+        if let Some(holder_in) = &self.lease_holder {
+            self.check_lease(conn, &assignment.process_id, holder_in)?;
+        }
+        diesel::insert_into(assignments)
+            .values(NewAssignment {
+                process_id: &assignment.process_id,
+                assignment_id: &assignment.assignment_id,
+                message_id: &assignment.message_id,
+                epoch: assignment.epoch,
+                nonce: assignment.nonce,
+                timestamp: assignment.timestamp,
+                hash_chain: &assignment.hash_chain,
+                block_height: assignment.block_height.as_deref(),
+            })
+            .execute(conn)?;
+        self.advance_sequence(conn, &assignment.sequence())
+    }
+
+    /*
+        fails the transaction saving a message when another
+        instance holds the lease of the process, the row is
+        locked so the lease cannot change hands before the
+        transaction commits
+    */
+    fn check_lease(
+        &self,
+        conn: &mut PgConnection,
+        process_id_in: &str,
+        holder_in: &str,
+    ) -> Result<(), StoreErrorType> {
+        let lease: Option<DbLease> = diesel::sql_query(
+            "SELECT holder, expires_at > now() AS live FROM process_leases
+            WHERE process_id = $1 FOR SHARE",
+        )
+        .bind::<Text, _>(process_id_in)
+        .get_result(conn)
+        .optional()?;
+        match lease {
+            Some(lease) if lease.live && lease.holder != holder_in => {
+                Err(StoreErrorType::LeaseLost(format!(
+                    "Process {} is sequenced by {}, retry the write",
+                    process_id_in, lease.holder
+                )))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /*
+        moves the snapshot of the process forward to the
+        slot just saved. The row is compared and set in the
+        transaction saving the message, a writer that lost
+        the process lock and built a slot already taken
+        fails here instead of forking the schedule
+    */
+    fn advance_sequence(
+        &self,
+        conn: &mut PgConnection,
+        sequence: &ProcessSequence,
+    ) -> Result<(), StoreErrorType> {
+        let updated = diesel::sql_query(
+            "INSERT INTO process_sequence (process_id, epoch, nonce, hash_chain, assignment_id)
+            VALUES ($1, $2, $3, $4, $5)
+            ON CONFLICT (process_id) DO UPDATE SET
+                epoch = EXCLUDED.epoch,
+                nonce = EXCLUDED.nonce,
+                hash_chain = EXCLUDED.hash_chain,
+                assignment_id = EXCLUDED.assignment_id
+            WHERE (process_sequence.epoch, process_sequence.nonce)
+                < (EXCLUDED.epoch, EXCLUDED.nonce)",
+        )
+        .bind::<Text, _>(&sequence.process_id)
+        .bind::<Int4, _>(sequence.epoch)
+        .bind::<Int4, _>(sequence.nonce)
+        .bind::<Text, _>(&sequence.hash_chain)
+        .bind::<Text, _>(&sequence.assignment_id)
+        .execute(conn)?;
+        match updated {
+            1 => Ok(()),
+            _ => Err(StoreErrorType::SlotTaken(format!(
+                "Epoch {} nonce {} of process {} is already sequenced, retry the write",
+                sequence.epoch, sequence.nonce, sequence.process_id
+            ))),
+        }
+    }
+
+    // the crons a spawn declared, saved with the process
+    fn insert_crons(
+        &self,
+        conn: &mut PgConnection,
+        process: &Process,
+    ) -> Result<(), StoreErrorType> {
+        use super::schema::crons::dsl::*;
+
+        // the flow rejects invalid intervals, a restored process keeps the valid ones
+        let definitions = CronDefinition::from_process(process).unwrap_or_default();
+        for definition in definitions.iter() {
+            diesel::insert_into(crons)
+                .values(NewCron {
+                    process_id: &definition.process_id,
+                    cron_interval: &definition.interval,
+                    interval_ms: definition.interval_ms,
+                    tags: serde_json::to_value(&definition.tags)?,
+                    next_run: definition.next_run,
+                })
+                .on_conflict((process_id, cron_interval))
+                .do_nothing()
+                .execute(conn)?;
+        }
+        Ok(())
+    }
+
+    pub fn get_conn(&self) -> Result<PooledConn, StoreErrorType> {
+        /*
+            queries of a request past its deadline are not
+            started, and waiting on the pool for one stops
+            when the deadline does. the connection error of
+            an exhausted pool is kept so failover still sees it
+        */
+        let deadline = Deadline::current();
+        deadline.check().map_err(StoreErrorType::DatabaseError)?;
+        let conn = match deadline.remaining() {
+            Some(remaining) => self.pool.get_timeout(remaining),
+            None => self.pool.get(),
+        };
+        conn.map_err(|_| {
+            StoreErrorType::ConnectionError("Failed to get connection from pool.".to_string())
         })
     }
 
+    /*
+        diesel blocks the thread it runs on, so store calls
+        made from async code run on the blocking pool and
+        never stall the async workers. the request deadline
+        is task local and has to be carried over
+    */
+    async fn blocking<T, F>(&self, op: F) -> Result<T, StoreErrorType>
+    where
+        T: Send + 'static,
+        F: FnOnce(&StoreClient) -> Result<T, StoreErrorType> + Send + 'static,
+    {
+        let store = self.clone();
+        let deadline = Deadline::current();
+        tokio::task::spawn_blocking(move || deadline.sync_scope(|| op(&store)))
+            .await
+            .map_err(|e| StoreErrorType::DatabaseError(format!("store task failed: {}", e)))?
+    }
+
+    /*
+        a connection to the read replica if one is set, the
+        primary is used when none frees up within REPLICA_WAIT_MS
+        so an unreachable replica only slows reads down
+    */
+    fn get_read_conn(&self) -> Result<PooledConn, StoreErrorType> {
+        let read_pool = match &self.read_pool {
+            Some(p) => p,
+            None => return self.get_conn(),
+        };
+        let deadline = Deadline::current();
+        deadline.check().map_err(StoreErrorType::DatabaseError)?;
+        let wait = Duration::from_millis(REPLICA_WAIT_MS);
+        let conn = match deadline.remaining() {
+            Some(remaining) => read_pool.get_timeout(remaining.min(wait)),
+            None => read_pool.get(),
+        };
+        match conn {
+            Ok(c) => Ok(c),
+            Err(_) => self.get_conn(),
+        }
+    }
+
+    /*
+        single row lookups run on the replica first, a row
+        it does not have yet may have just been written so
+        the primary is asked before reporting NotFound
+    */
+    fn lookup<T>(
+        &self,
+        query: impl Fn(&mut PgConnection) -> Result<T, StoreErrorType>,
+    ) -> Result<T, StoreErrorType> {
+        if self.read_pool.is_some() {
+            match query(&mut *self.get_read_conn()?) {
+                Err(StoreErrorType::NotFound(_)) => (),
+                result => return result,
+            }
+        }
+        query(&mut *self.get_conn()?)
+    }
+
+    /*
+        rows saved before checksums were added have none
+        and are not verified. offloaded bundles are not in
+        the row, they are verified when they are loaded
+    */
+    fn verify_bundle(
+        &self,
+        bundle_ref: BundleRef,
+        bundle_in: &[u8],
+        checksum: &Option<String>,
+        location: &Option<String>,
+    ) -> Result<(), StoreErrorType> {
+        match checksum {
+            Some(c)
+                if self.verify_checksums
+                    && location.is_none()
+                    && *c != bundle_checksum_of(bundle_in) =>
+            {
+                Err(StoreErrorType::IntegrityError(bundle_ref))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /*
+        bundles of at least offload_min_size are written to
+        the blob store under their checksum before the row
+        is, the row then keeps an empty bundle and the key
+    */
+    fn offload_bundle(
+        &self,
+        bundle_in: &[u8],
+        checksum: &str,
+    ) -> Result<Option<String>, StoreErrorType> {
+        match &self.blobs {
+            Some(blobs) if bundle_in.len() >= self.offload_min_size => {
+                blobs
+                    .put(checksum, bundle_in)
+                    .map_err(StoreErrorType::DatabaseError)?;
+                Ok(Some(checksum.to_string()))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /*
+        the bundle of a row, fetched from the blob store if
+        it was offloaded. a row without a bundle or a key was
+        pruned by retention, its bundle is only on arweave
+    */
+    fn load_bundle(
+        &self,
+        bundle_in: Vec<u8>,
+        location: &Option<String>,
+    ) -> Result<Vec<u8>, StoreErrorType> {
+        match (location, &self.blobs) {
+            (None, _) if bundle_in.is_empty() => Err(StoreErrorType::NotFound(
+                "Bundle was pruned, it is only on arweave".to_string(),
+            )),
+            (None, _) => Ok(bundle_in),
+            (Some(key), Some(blobs)) => blobs.get(key).map_err(StoreErrorType::DatabaseError),
+            (Some(key), None) => Err(StoreErrorType::DatabaseError(format!(
+                "bundle {} is offloaded but BUNDLE_STORAGE is database",
+                key
+            ))),
+        }
+    }
+
+    /*
+        Refuses a store that was migrated or written by a
+        newer binary, so rolling back the binary does not
+        write rows the newer schema does not expect. Pending
+        migrations are applied when auto_migrate is set,
+        otherwise they also stop the server. On success this
+        binary's version is recorded as the last writer.
+    */
+    pub fn check_compatibility(&self, auto_migrate: bool) -> Result<String, StoreErrorType> {
+        let conn = &mut self.get_conn()?;
+        with_migration_lock(conn, |conn| self.check_schema(conn, auto_migrate))
+    }
+
+    fn check_schema(
+        &self,
+        conn: &mut PgConnection,
+        auto_migrate: bool,
+    ) -> Result<String, StoreErrorType> {
+        let migration_error = |e: Box<dyn std::error::Error + Send + Sync>| {
+            StoreErrorType::DatabaseError(e.to_string())
+        };
+
+        let known: Vec<String> = MigrationSource::<Pg>::migrations(&MIGRATIONS)
+            .map_err(migration_error)?
+            .iter()
+            .map(|m| m.name().version().to_string())
+            .collect();
+        let unknown: Vec<String> = conn
+            .applied_migrations()
+            .map_err(migration_error)?
+            .iter()
+            .map(|v| v.to_string())
+            .filter(|v| !known.contains(v))
+            .collect();
+        if !unknown.is_empty() {
+            return Err(StoreErrorType::DatabaseError(format!(
+                "Store has migrations this binary does not know about {:?}, it was migrated by a newer version",
+                unknown
+            )));
+        }
+
+        let binary_version = env!("CARGO_PKG_VERSION");
+        if let Some(stored_version) = self.get_meta(conn, "binary_version")? {
+            if is_newer_version(&stored_version, binary_version) {
+                return Err(StoreErrorType::DatabaseError(format!(
+                    "Store was last written by version {}, this binary is {}",
+                    stored_version, binary_version
+                )));
+            }
+        }
+
+        let pending = conn
+            .pending_migrations(MIGRATIONS)
+            .map_err(migration_error)?
+            .len();
+        let migrated = match (pending, auto_migrate) {
+            (0, _) => "Store schema is up to date".to_string(),
+            (_, true) => {
+                conn.run_pending_migrations(MIGRATIONS)
+                    .map_err(migration_error)?;
+                format!("Applied {} pending migrations", pending)
+            }
+            (_, false) => {
+                return Err(StoreErrorType::DatabaseError(format!(
+                    "Store has {} pending migrations and AUTO_MIGRATE is off",
+                    pending
+                )))
+            }
+        };
+
+        self.set_meta(conn, "binary_version", binary_version)?;
+        Ok(format!("{}, running version {}", migrated, binary_version))
+    }
+
+    /*
+        true before the first process is written, also
+        when the schema has not been migrated at all
+    */
+    pub fn is_empty(&self) -> Result<bool, StoreErrorType> {
+        let conn = &mut self.get_conn()?;
+        let exists: bool = diesel::select(sql::<Bool>(
+            "EXISTS (SELECT 1 FROM information_schema.tables WHERE table_name = 'processes')",
+        ))
+        .get_result(conn)?;
+        if !exists {
+            return Ok(true);
+        }
+        let has_processes: bool =
+            diesel::select(sql::<Bool>("EXISTS (SELECT 1 FROM processes)")).get_result(conn)?;
+        Ok(!has_processes)
+    }
+
+    fn get_meta(
+        &self,
+        conn: &mut PgConnection,
+        key_in: &str,
+    ) -> Result<Option<String>, StoreErrorType> {
+        use super::schema::store_meta::dsl::*;
+
+        // the table itself is created by a migration that may still be pending
+        let exists: bool = diesel::select(sql::<Bool>(
+            "EXISTS (SELECT 1 FROM information_schema.tables WHERE table_name = 'store_meta')",
+        ))
+        .get_result(conn)?;
+        if !exists {
+            return Ok(None);
+        }
+
+        match store_meta
+            .filter(key.eq(key_in))
+            .select(value)
+            .first::<String>(conn)
+        {
+            Ok(v) => Ok(Some(v)),
+            Err(DieselError::NotFound) => Ok(None),
+            Err(e) => Err(StoreErrorType::from(e)),
+        }
+    }
+
+    fn set_meta(
+        &self,
+        conn: &mut PgConnection,
+        key_in: &str,
+        value_in: &str,
+    ) -> Result<(), StoreErrorType> {
+        use super::schema::store_meta::dsl::*;
+
+        diesel::insert_into(store_meta)
+            .values((key.eq(key_in), value.eq(value_in)))
+            .on_conflict(key)
+            .do_update()
+            .set(value.eq(value_in))
+            .execute(conn)?;
+        Ok(())
+    }
+
     /*
         run at server startup to modify the database as needed
     */
     pub fn run_migrations(&self) -> Result<String, StoreErrorType> {
         let conn = &mut self.get_conn()?;
-        match conn.run_pending_migrations(MIGRATIONS) {
+        with_migration_lock(conn, |conn| match conn.run_pending_migrations(MIGRATIONS) {
             Ok(m) => Ok(format!("Migrations applied... {:?}", m)),
             Err(e) => Err(StoreErrorType::DatabaseError(format!(
                 "Error applying migrations: {}",
-                e.to_string()
+                e
             ))),
-        }
+        })
     }
 }
 
-impl DataStore for StoreClient {
-    fn save_process(&self, process: &Process, bundle_in: &[u8]) -> Result<String, StoreErrorType> {
+/*
+    the blocking implementations, DataStore runs them on
+    the blocking pool. the cli commands call them directly
+*/
+impl StoreClient {
+    pub fn save_process(
+        &self,
+        process: &Process,
+        bundle_in: &[u8],
+    ) -> Result<String, StoreErrorType> {
         use super::schema::processes::dsl::*;
         let conn = &mut self.get_conn()?;
 
+        let checksum = bundle_checksum_of(bundle_in);
+        let location = self.offload_bundle(bundle_in, &checksum)?;
         let new_process = NewProcess {
             process_id: &process.process_id,
             process_data: serde_json::to_value(process).expect("Failed to serialize Process"),
-            bundle: bundle_in,
+            bundle: stored_bundle(bundle_in, &location),
+            bundle_checksum: checksum,
+            bundle_location: location,
         };
 
-        match diesel::insert_into(processes)
-            .values(&new_process)
-            .on_conflict(process_id)
-            .do_nothing()
-            .execute(conn)
-        {
-            Ok(_) => Ok("saved".to_string()),
-            Err(e) => Err(StoreErrorType::from(e)),
+        conn.transaction::<_, StoreErrorType, _>(|conn| {
+            let inserted = diesel::insert_into(processes)
+                .values(&new_process)
+                .on_conflict(process_id)
+                .do_nothing()
+                .execute(conn)?;
+            if inserted > 0 {
+                let event = DomainEvent::ProcessCreated {
+                    process: process.clone(),
+                };
+                self.push_outbox(conn, &process.process_id, &event)?;
+                self.insert_crons(conn, process)?;
+                self.insert_process_wallet(conn, &process.process_id, bundle_in)?;
+            }
+            Ok("saved".to_string())
+        })
+    }
+
+    // the wallet that signed the bundle of the spawn
+    fn insert_process_wallet(
+        &self,
+        conn: &mut PgConnection,
+        process_id_in: &str,
+        bundle_in: &[u8],
+    ) -> Result<String, StoreErrorType> {
+        use super::schema::process_wallets::dsl::*;
+
+        let (signed, _) = DataItem::from_info_bytes(bundle_in)
+            .map_err(|e| StoreErrorType::DatabaseError(format!("Invalid bundle: {:?}", e)))?;
+        let wallet = signed.owner_address();
+        diesel::insert_into(process_wallets)
+            .values((scheduler.eq(&wallet), process_id.eq(process_id_in)))
+            .on_conflict_do_nothing()
+            .execute(conn)?;
+        Ok(wallet)
+    }
+
+    /*
+        a process spawned before the wallets were recorded
+        is looked up in the bundle of its spawn once
+    */
+    pub fn get_process_wallet(&self, process_id_in: &str) -> Result<String, StoreErrorType> {
+        use super::schema::process_wallets::dsl::*;
+
+        let stored: Option<String> = process_wallets
+            .filter(process_id.eq(process_id_in))
+            .select(scheduler)
+            .first(&mut self.get_conn()?)
+            .optional()?;
+        if let Some(stored) = stored {
+            return Ok(stored);
         }
+        let bundle_in = self.get_stored_bundle(&BundleRef::Process(process_id_in.to_string()))?;
+        let conn = &mut self.get_conn()?;
+        self.insert_process_wallet(conn, process_id_in, &bundle_in)
+    }
+
+    /*
+        a process and its boot message are saved in one
+        transaction, a retry after the commit saves neither
+        again since the process row is already there
+    */
+    pub fn save_process_with_boot(
+        &self,
+        process: &Process,
+        bundle_in: &[u8],
+        boot: &Message,
+        boot_bundle: &[u8],
+    ) -> Result<String, StoreErrorType> {
+        use super::schema::{messages, processes};
+        let conn = &mut self.get_conn()?;
+
+        let checksum = bundle_checksum_of(bundle_in);
+        let location = self.offload_bundle(bundle_in, &checksum)?;
+        let new_process = NewProcess {
+            process_id: &process.process_id,
+            process_data: serde_json::to_value(process)?,
+            bundle: stored_bundle(bundle_in, &location),
+            bundle_checksum: checksum,
+            bundle_location: location,
+        };
+
+        let boot_checksum = bundle_checksum_of(boot_bundle);
+        let boot_location = self.offload_bundle(boot_bundle, &boot_checksum)?;
+        let new_message = NewMessage {
+            process_id: &boot.process_id()?,
+            message_id: &boot.message_id()?,
+            assignment_id: &boot.assignment_id()?,
+            message_data: serde_json::to_value(boot)?,
+            epoch: &boot.epoch()?,
+            nonce: &boot.nonce()?,
+            timestamp: &boot.timestamp()?,
+            bundle: stored_bundle(boot_bundle, &boot_location),
+            hash_chain: &boot.hash_chain()?,
+            bundle_checksum: boot_checksum,
+            bundle_location: boot_location,
+        };
+
+        conn.transaction::<_, StoreErrorType, _>(|conn| {
+            let inserted = diesel::insert_into(processes::table)
+                .values(&new_process)
+                .on_conflict(processes::process_id)
+                .do_nothing()
+                .execute(conn)?;
+            if inserted == 0 {
+                return Ok("saved".to_string());
+            }
+            let event = DomainEvent::ProcessCreated {
+                process: process.clone(),
+            };
+            self.push_outbox(conn, &process.process_id, &event)?;
+            self.insert_crons(conn, process)?;
+            self.insert_process_wallet(conn, &process.process_id, bundle_in)?;
+
+            diesel::insert_into(messages::table)
+                .values(&new_message)
+                .execute(conn)?;
+            self.insert_assignment(conn, boot)?;
+            let event = DomainEvent::MessageSequenced {
+                message: boot.clone(),
+            };
+            self.push_outbox(conn, &process.process_id, &event)?;
+            Ok("saved".to_string())
+        })
     }
 
-    fn get_process(&self, process_id_in: &str) -> Result<Process, StoreErrorType> {
+    pub fn get_process(&self, process_id_in: &str) -> Result<Process, StoreErrorType> {
         use super::schema::processes::dsl::*;
         let conn = &mut self.get_conn()?;
 
@@ -134,6 +943,12 @@ impl DataStore for StoreClient {
 
         match db_process_result {
             Ok(Some(db_process)) => {
+                self.verify_bundle(
+                    BundleRef::Process(db_process.process_id.clone()),
+                    &db_process.bundle,
+                    &db_process.bundle_checksum,
+                    &db_process.bundle_location,
+                )?;
                 let process: Process = serde_json::from_value(db_process.process_data.clone())?;
                 Ok(process)
             }
@@ -142,14 +957,74 @@ impl DataStore for StoreClient {
         }
     }
 
+    pub fn get_processes(
+        &self,
+        from: &Option<String>,
+        limit: &Option<i32>,
+        owner: &Option<String>,
+        module: &Option<String>,
+    ) -> Result<PaginatedProcesses, StoreErrorType> {
+        use super::schema::processes::dsl::*;
+        let conn = &mut self.get_read_conn()?;
+        let mut query = processes.into_boxed();
+
+        // the cursor is the row_id of the last process on the previous page
+        if let Some(from_str) = from {
+            let from_row_id = from_str.parse::<i32>().map_err(StoreErrorType::from)?;
+            query = query.filter(row_id.gt(from_row_id));
+        }
+
+        if let Some(owner_address) = owner {
+            query = query.filter(
+                sql::<Bool>("process_data -> 'owner' ->> 'address' = ")
+                    .bind::<Text, _>(owner_address.clone()),
+            );
+        }
+
+        if let Some(module_id) = module {
+            let module_json = serde_json::json!([{ "name": "Module", "value": module_id }]);
+            query = query
+                .filter(sql::<Bool>("process_data -> 'tags' @> ").bind::<Jsonb, _>(module_json));
+        }
+
+        // fetch one extra record to determine if a next page exists
+        let limit_val = limit.unwrap_or(1000) as i64;
+        let db_processes: Vec<DbProcess> =
+            query.order(row_id.asc()).limit(limit_val + 1).load(conn)?;
+
+        let has_next_page = db_processes.len() as i64 > limit_val;
+        let mut processes_mapped: Vec<(Process, String)> = vec![];
+        for db_process in db_processes.into_iter().take(limit_val as usize) {
+            self.verify_bundle(
+                BundleRef::Process(db_process.process_id.clone()),
+                &db_process.bundle,
+                &db_process.bundle_checksum,
+                &db_process.bundle_location,
+            )?;
+            let process: Process = serde_json::from_value(db_process.process_data)?;
+            processes_mapped.push((process, db_process.row_id.to_string()));
+        }
+
+        Ok(PaginatedProcesses::from_processes(
+            processes_mapped,
+            has_next_page,
+        ))
+    }
+
     /*
         If we are trying to write an actual data item
         not just an assignment we need to check that it
         doesnt already exist.
     */
-    fn check_existing_message(&self, message: &Message) -> Result<(), StoreErrorType> {
+    pub fn check_existing_message(&self, message: &Message) -> Result<(), StoreErrorType> {
         match &message.message {
             Some(m) => {
+                // tiered and pruned history is looked up by id
+                if self.is_moved_message(&m.id)? {
+                    return Err(StoreErrorType::MessageExists(
+                        "Message already exists".to_string(),
+                    ));
+                }
                 match self.get_message(&m.id) {
                     Ok(parsed) => {
                         /*
@@ -167,6 +1042,10 @@ impl DataStore for StoreClient {
                     }
                     // The message wasnt found at all so it can be written
                     Err(StoreErrorType::NotFound(_)) => Ok(()),
+                    // The store is unreachable, let the caller retry
+                    Err(StoreErrorType::ConnectionError(e)) => {
+                        Err(StoreErrorType::ConnectionError(e))
+                    }
                     // Some other error happened
                     Err(_) => Err(StoreErrorType::DatabaseError(
                         "Error checking message".to_string(),
@@ -177,12 +1056,28 @@ impl DataStore for StoreClient {
         }
     }
 
-    fn save_message(&self, message: &Message, bundle_in: &[u8]) -> Result<String, StoreErrorType> {
+    pub fn save_message(
+        &self,
+        message: &Message,
+        bundle_in: &[u8],
+    ) -> Result<String, StoreErrorType> {
+        self.save_message_with_cron(message, bundle_in, None)
+    }
+
+    // cron, the interval whose next_run moves with the save
+    fn save_message_with_cron(
+        &self,
+        message: &Message,
+        bundle_in: &[u8],
+        cron: Option<(&str, i64)>,
+    ) -> Result<String, StoreErrorType> {
         use super::schema::messages::dsl::*;
         let conn = &mut self.get_conn()?;
 
         self.check_existing_message(message)?;
 
+        let checksum = bundle_checksum_of(bundle_in);
+        let location = self.offload_bundle(bundle_in, &checksum)?;
         let new_message = NewMessage {
             process_id: &message.process_id()?,
             message_id: &message.message_id()?,
@@ -191,79 +1086,362 @@ impl DataStore for StoreClient {
             epoch: &message.epoch()?,
             nonce: &message.nonce()?,
             timestamp: &message.timestamp()?,
-            bundle: bundle_in,
+            bundle: stored_bundle(bundle_in, &location),
             hash_chain: &message.hash_chain()?,
+            bundle_checksum: checksum,
+            bundle_location: location,
         };
 
-        match diesel::insert_into(messages)
-            .values(&new_message)
-            .execute(conn)
-        {
-            Ok(row_count) => {
-                if row_count == 0 {
-                    Err(StoreErrorType::DatabaseError(
-                        "Error saving message".to_string(),
-                    )) // Return a custom error for duplicates
-                } else {
-                    Ok("saved".to_string())
+        conn.transaction::<_, StoreErrorType, _>(|conn| {
+            let row_count = diesel::insert_into(messages)
+                .values(&new_message)
+                .execute(conn)?;
+            if row_count == 0 {
+                // Return a custom error for duplicates
+                return Err(StoreErrorType::DatabaseError(
+                    "Error saving message".to_string(),
+                ));
+            }
+            self.insert_assignment(conn, message)?;
+            if let Some((interval_in, next_run_in)) = cron {
+                use super::schema::crons;
+                diesel::update(
+                    crons::table
+                        .filter(crons::process_id.eq(new_message.process_id))
+                        .filter(crons::cron_interval.eq(interval_in)),
+                )
+                .set(crons::next_run.eq(next_run_in))
+                .execute(conn)?;
+            }
+            let event = DomainEvent::MessageSequenced {
+                message: message.clone(),
+            };
+            self.push_outbox(conn, new_message.process_id, &event)?;
+            Ok("saved".to_string())
+        })
+    }
+
+    pub fn save_cron_message(
+        &self,
+        message: &Message,
+        bundle_in: &[u8],
+        interval_in: &str,
+        next_run_in: i64,
+    ) -> Result<String, StoreErrorType> {
+        self.save_message_with_cron(message, bundle_in, Some((interval_in, next_run_in)))
+    }
+
+    fn commit_group(&self, batch: Vec<PendingSave>) {
+        if batch.len() > 1 {
+            let saves: Vec<(Message, Bytes)> = batch
+                .iter()
+                .map(|p| (p.message.clone(), p.bundle.clone()))
+                .collect();
+            if let Ok(saved) = self.save_messages(&saves) {
+                for pending in batch.into_iter() {
+                    let _ = pending.done.send(Ok(saved.clone()));
                 }
+                return;
             }
-            Err(e) => Err(StoreErrorType::from(e)),
+        }
+        for pending in batch.into_iter() {
+            let saved = self.save_message(&pending.message, &pending.bundle);
+            let _ = pending.done.send(saved);
         }
     }
 
-    fn get_messages(
+    // all or nothing, used for the items of a bundle
+    pub fn save_messages(
         &self,
-        process_id_in: &str,
-        from: &Option<String>,
-        to: &Option<String>,
-        limit: &Option<i32>,
-    ) -> Result<PaginatedMessages, StoreErrorType> {
+        messages_in: &[(Message, Bytes)],
+    ) -> Result<String, StoreErrorType> {
         use super::schema::messages::dsl::*;
-        let conn = &mut self.get_conn()?;
-        let mut query = messages.filter(process_id.eq(process_id_in)).into_boxed();
 
-        // Apply 'from' timestamp filtering if 'from' is provided
-        if let Some(from_timestamp_str) = from {
-            let from_timestamp = from_timestamp_str
-                .parse::<i64>()
-                .map_err(StoreErrorType::from)?;
-            query = query.filter(timestamp.gt(from_timestamp));
+        let mut locations = vec![];
+        for (message, bundle_in) in messages_in.iter() {
+            self.check_existing_message(message)?;
+            let checksum = bundle_checksum_of(bundle_in);
+            let location = self.offload_bundle(bundle_in, &checksum)?;
+            locations.push((checksum, location));
         }
 
-        // Apply 'to' timestamp filtering if 'to' is provided
-        if let Some(to_timestamp_str) = to {
-            let to_timestamp = to_timestamp_str
-                .parse::<i64>()
-                .map_err(StoreErrorType::from)?;
-            query = query.filter(timestamp.le(to_timestamp));
-        }
+        let conn = &mut self.get_conn()?;
+        conn.transaction::<_, StoreErrorType, _>(|conn| {
+            for ((message, bundle_in), (checksum, location)) in
+                messages_in.iter().zip(locations.iter())
+            {
+                let new_message = NewMessage {
+                    process_id: &message.process_id()?,
+                    message_id: &message.message_id()?,
+                    assignment_id: &message.assignment_id()?,
+                    message_data: serde_json::to_value(message)?,
+                    epoch: &message.epoch()?,
+                    nonce: &message.nonce()?,
+                    timestamp: &message.timestamp()?,
+                    bundle: stored_bundle(bundle_in, location),
+                    hash_chain: &message.hash_chain()?,
+                    bundle_checksum: checksum.clone(),
+                    bundle_location: location.clone(),
+                };
+                diesel::insert_into(messages)
+                    .values(&new_message)
+                    .execute(conn)?;
+                self.insert_assignment(conn, message)?;
+                let event = DomainEvent::MessageSequenced {
+                    message: message.clone(),
+                };
+                self.push_outbox(conn, new_message.process_id, &event)?;
+            }
+            Ok(())
+        })?;
 
-        // Apply limit, converting Option<i32> to i64 and adding 1 to check for the next page
-        let limit_val = limit.unwrap_or(5000) as i64; // Default limit if none is provided
-        let db_messages_result: Result<Vec<DbMessage>, DieselError> = query
-            .order(timestamp.asc())
-            .limit(limit_val + 1) // Fetch one extra record to determine if a next page exists
-            .load(conn);
+        Ok("saved".to_string())
+    }
+
+    pub fn get_stored_bundle(&self, bundle_ref: &BundleRef) -> Result<Vec<u8>, StoreErrorType> {
+        use super::schema::{messages, processes};
+        let conn = &mut self.get_conn()?;
+
+        let stored: Option<(Vec<u8>, Option<String>)> = match bundle_ref {
+            BundleRef::Process(process_id_in) => processes::table
+                .filter(processes::process_id.eq(process_id_in))
+                .select((processes::bundle, processes::bundle_location))
+                .first(conn)
+                .optional()?,
+            BundleRef::Message(row_id_in) => messages::table
+                .filter(messages::row_id.eq(row_id_in))
+                .select((messages::bundle, messages::bundle_location))
+                .first(conn)
+                .optional()?,
+            BundleRef::Assignment(assignment_id_in) => messages::table
+                .filter(messages::assignment_id.eq(assignment_id_in))
+                .select((messages::bundle, messages::bundle_location))
+                .first(conn)
+                .optional()?,
+        };
+
+        match stored {
+            Some((bundle_in, location)) => self.load_bundle(bundle_in, &location),
+            None => Err(StoreErrorType::NotFound("Bundle not found".to_string())),
+        }
+    }
+
+    /*
+        write back a repaired bundle, it is only accepted
+        if it matches the checksum saved with the original
+    */
+    pub fn restore_bundle(
+        &self,
+        bundle_ref: &BundleRef,
+        bundle_in: &[u8],
+    ) -> Result<String, StoreErrorType> {
+        use super::schema::{messages, processes};
+        let conn = &mut self.get_conn()?;
+        let checksum = bundle_checksum_of(bundle_in);
+
+        // an offloaded bundle is repaired in the blob store, the row stays as is
+        let location: Option<Option<String>> = match bundle_ref {
+            BundleRef::Process(process_id_in) => processes::table
+                .filter(processes::process_id.eq(process_id_in))
+                .filter(processes::bundle_checksum.eq(&checksum))
+                .select(processes::bundle_location)
+                .first(conn)
+                .optional()?,
+            BundleRef::Message(row_id_in) => messages::table
+                .filter(messages::row_id.eq(row_id_in))
+                .filter(messages::bundle_checksum.eq(&checksum))
+                .select(messages::bundle_location)
+                .first(conn)
+                .optional()?,
+            BundleRef::Assignment(assignment_id_in) => messages::table
+                .filter(messages::assignment_id.eq(assignment_id_in))
+                .filter(messages::bundle_checksum.eq(&checksum))
+                .select(messages::bundle_location)
+                .first(conn)
+                .optional()?,
+        };
+        if let (Some(Some(key)), Some(blobs)) = (&location, &self.blobs) {
+            blobs
+                .put(key, bundle_in)
+                .map_err(StoreErrorType::DatabaseError)?;
+            return Ok("restored".to_string());
+        }
+
+        let updated = match bundle_ref {
+            BundleRef::Process(process_id_in) => diesel::update(
+                processes::table
+                    .filter(processes::process_id.eq(process_id_in))
+                    .filter(processes::bundle_checksum.eq(&checksum)),
+            )
+            .set(processes::bundle.eq(bundle_in))
+            .execute(conn)?,
+            BundleRef::Message(row_id_in) => diesel::update(
+                messages::table
+                    .filter(messages::row_id.eq(row_id_in))
+                    .filter(messages::bundle_checksum.eq(&checksum)),
+            )
+            .set(messages::bundle.eq(bundle_in))
+            .execute(conn)?,
+            BundleRef::Assignment(assignment_id_in) => diesel::update(
+                messages::table
+                    .filter(messages::assignment_id.eq(assignment_id_in))
+                    .filter(messages::bundle_checksum.eq(&checksum)),
+            )
+            .set(messages::bundle.eq(bundle_in))
+            .execute(conn)?,
+        };
+
+        match updated {
+            0 => Err(StoreErrorType::IntegrityError(bundle_ref.clone())),
+            _ => Ok("restored".to_string()),
+        }
+    }
+
+    pub fn get_messages(
+        &self,
+        process_id_in: &str,
+        message_query: &MessageQuery,
+    ) -> Result<PaginatedMessages, StoreErrorType> {
+        use super::schema::messages::dsl::*;
+        let MessageQuery {
+            from,
+            to,
+            limit,
+            tags,
+            from_timestamp,
+            to_timestamp,
+            sort,
+        } = message_query;
+        let conn = &mut self.get_read_conn()?;
+        let mut query = messages.filter(process_id.eq(process_id_in)).into_boxed();
+
+        /*
+            'from' is the cursor the page starts after and
+            'to' is where it ends, so in descending order
+            the comparisons flip
+        */
+        let from_cursor = from
+            .as_ref()
+            .map(|f| f.parse::<i64>())
+            .transpose()
+            .map_err(StoreErrorType::from)?;
+        let to_cursor = to
+            .as_ref()
+            .map(|t| t.parse::<i64>())
+            .transpose()
+            .map_err(StoreErrorType::from)?;
+
+        if let Some(from_cursor) = from_cursor {
+            query = match sort {
+                SortOrder::Asc => query.filter(timestamp.gt(from_cursor)),
+                SortOrder::Desc => query.filter(timestamp.lt(from_cursor)),
+            };
+        }
+
+        if let Some(to_cursor) = to_cursor {
+            query = match sort {
+                SortOrder::Asc => query.filter(timestamp.le(to_cursor)),
+                SortOrder::Desc => query.filter(timestamp.ge(to_cursor)),
+            };
+        }
+
+        /*
+            Apply the wall clock window, unlike the from/to
+            cursors both ends of the window are inclusive
+        */
+        if let Some(from_timestamp_val) = from_timestamp {
+            query = query.filter(timestamp.ge(*from_timestamp_val));
+        }
+
+        if let Some(to_timestamp_val) = to_timestamp {
+            query = query.filter(timestamp.le(*to_timestamp_val));
+        }
+
+        /*
+            Only keep messages whose tags contain every
+            filter, this uses the gin index on the tags
+        */
+        for tag_filter in tags.iter() {
+            let tag_json = serde_json::json!([{
+                "name": tag_filter.name,
+                "value": tag_filter.value,
+            }]);
+            query = query.filter(
+                sql::<Bool>("message_data -> 'message' -> 'tags' @> ").bind::<Jsonb, _>(tag_json),
+            );
+        }
+
+        query = match sort {
+            SortOrder::Asc => query.order(timestamp.asc()),
+            SortOrder::Desc => query.order(timestamp.desc()),
+        };
+
+        // Apply limit, converting Option<i32> to i64 and adding 1 to check for the next page
+        let limit_val = limit.unwrap_or(5000) as i64; // Default limit if none is provided
+        let db_messages_result: Result<Vec<DbMessage>, DieselError> = query
+            .limit(limit_val + 1) // Fetch one extra record to determine if a next page exists
+            .load(conn);
 
         match db_messages_result {
             Ok(db_messages) => {
-                let has_next_page = db_messages.len() as i64 > limit_val;
-                // Take only up to the limit if there's an extra indicating a next page
-                let messages_o = if has_next_page {
-                    &db_messages[..(limit_val as usize)]
-                } else {
-                    &db_messages[..]
-                };
-
                 let mut messages_mapped: Vec<Message> = vec![];
-                for db_message in messages_o.iter() {
+                for db_message in db_messages.iter() {
+                    self.verify_bundle(
+                        BundleRef::Message(db_message.row_id),
+                        &db_message.bundle,
+                        &db_message.bundle_checksum,
+                        &db_message.bundle_location,
+                    )?;
                     let json = serde_json::from_value(db_message.message_data.clone())?;
                     let bytes: Vec<u8> = db_message.bundle.clone();
                     let mapped = Message::from_val(&json, bytes)?;
                     messages_mapped.push(mapped);
                 }
 
+                /*
+                    tiered history is older than every row left,
+                    ascending pages read it before the rows and
+                    descending ones after them
+                */
+                let (lowest, highest) =
+                    window_bounds(from_cursor, to_cursor, *from_timestamp, *to_timestamp, sort);
+                let in_window =
+                    |ts: i64| lowest.map_or(true, |l| ts >= l) && highest.map_or(true, |h| ts <= h);
+                let wants_cold = match sort {
+                    SortOrder::Asc => true,
+                    SortOrder::Desc => messages_mapped.len() as i64 <= limit_val,
+                };
+                if wants_cold {
+                    let mut cold: Vec<Message> = vec![];
+                    for segment in self.get_cold_segments(conn, process_id_in, sort)? {
+                        if cold.len() as i64 > limit_val {
+                            break;
+                        }
+                        let overlaps = lowest.map_or(true, |l| segment.to_timestamp >= l)
+                            && highest.map_or(true, |h| segment.from_timestamp <= h);
+                        if !overlaps {
+                            continue;
+                        }
+                        let mut rows = self.read_segment(&segment)?;
+                        if let SortOrder::Desc = sort {
+                            rows.reverse();
+                        }
+                        for row in rows.iter() {
+                            if in_window(row.timestamp) && row.has_tags(tags) {
+                                cold.push(Message::from_val(&row.message_data, vec![])?);
+                            }
+                        }
+                    }
+                    messages_mapped = match sort {
+                        SortOrder::Asc => cold.into_iter().chain(messages_mapped).collect(),
+                        SortOrder::Desc => messages_mapped.into_iter().chain(cold).collect(),
+                    };
+                }
+
+                let has_next_page = messages_mapped.len() as i64 > limit_val;
+                // Take only up to the limit if there's an extra indicating a next page
+                messages_mapped.truncate(limit_val as usize);
+
                 let paginated = PaginatedMessages::from_messages(messages_mapped, has_next_page)?;
                 Ok(paginated)
             }
@@ -271,33 +1449,333 @@ impl DataStore for StoreClient {
         }
     }
 
-    fn get_message(&self, tx_id: &str) -> Result<Message, StoreErrorType> {
+    // the tiered segments of a process, oldest first for ascending reads
+    fn get_cold_segments(
+        &self,
+        conn: &mut PgConnection,
+        process_id_in: &str,
+        sort: &SortOrder,
+    ) -> Result<Vec<DbColdSegment>, StoreErrorType> {
+        use super::schema::cold_segments::dsl::*;
+        let query = cold_segments
+            .filter(process_id.eq(process_id_in))
+            .select(DbColdSegment::as_select());
+        let segments = match sort {
+            SortOrder::Asc => query.order(from_timestamp.asc()).load(conn)?,
+            SortOrder::Desc => query.order(from_timestamp.desc()).load(conn)?,
+        };
+        Ok(segments)
+    }
+
+    fn get_blob(&self, key: &str) -> Result<Vec<u8>, StoreErrorType> {
+        let blobs = self
+            .blobs
+            .as_ref()
+            .ok_or(StoreErrorType::DatabaseError(format!(
+                "segment {} is in cold storage but BUNDLE_STORAGE is database",
+                key
+            )))?;
+        blobs.get(key).map_err(StoreErrorType::DatabaseError)
+    }
+
+    // the rows of a segment without their bundles, unless it is a json one
+    fn read_segment(&self, segment: &DbColdSegment) -> Result<Vec<ColdRow>, StoreErrorType> {
+        let file = self.get_blob(&segment.segment_key)?;
+        match segment.bundles_key {
+            Some(_) => cold::decode_rows(file),
+            None => cold::decode_json(&file),
+        }
+        .map_err(StoreErrorType::DatabaseError)
+    }
+
+    /*
+        messages signed by an owner across every process,
+        assignments of other data items are not included.
+        the cursor is the row_id like the process listing
+    */
+    pub fn get_messages_by_owner(
+        &self,
+        owner_address: &str,
+        from: &Option<String>,
+        limit: &Option<i32>,
+    ) -> Result<PaginatedMessages, StoreErrorType> {
+        use super::schema::messages::dsl::*;
+        let conn = &mut self.get_read_conn()?;
+        let mut query = messages
+            .filter(
+                sql::<Bool>("message_data -> 'message' -> 'owner' ->> 'address' = ")
+                    .bind::<Text, _>(owner_address.to_string()),
+            )
+            .into_boxed();
+
+        if let Some(from_str) = from {
+            let from_row_id = from_str.parse::<i32>().map_err(StoreErrorType::from)?;
+            query = query.filter(row_id.gt(from_row_id));
+        }
+
+        let limit_val = limit.unwrap_or(5000) as i64;
+        let db_messages: Vec<DbMessage> =
+            query.order(row_id.asc()).limit(limit_val + 1).load(conn)?;
+
+        let has_next_page = db_messages.len() as i64 > limit_val;
+        let mut messages_mapped: Vec<(Message, String)> = vec![];
+        for db_message in db_messages.into_iter().take(limit_val as usize) {
+            self.verify_bundle(
+                BundleRef::Message(db_message.row_id),
+                &db_message.bundle,
+                &db_message.bundle_checksum,
+                &db_message.bundle_location,
+            )?;
+            let json = serde_json::from_value(db_message.message_data)?;
+            let mapped = Message::from_val(&json, db_message.bundle)?;
+            messages_mapped.push((mapped, db_message.row_id.to_string()));
+        }
+
+        Ok(PaginatedMessages::from_cursored_messages(
+            messages_mapped,
+            has_next_page,
+        ))
+    }
+
+    pub fn get_message(&self, tx_id: &str) -> Result<Message, StoreErrorType> {
         use super::schema::messages::dsl::*;
+        self.lookup(|conn| {
+            /*
+                get the oldest match. in the case of a message that has
+                later assignments, it should be the original message itself.
+            */
+            let db_message_result: Result<Option<DbMessage>, DieselError> = messages
+                .filter(message_id.eq(tx_id).or(assignment_id.eq(tx_id)))
+                .order(timestamp.asc())
+                .first(conn)
+                .optional();
+
+            match db_message_result {
+                Ok(Some(db_message)) => {
+                    self.verify_bundle(
+                        BundleRef::Message(db_message.row_id),
+                        &db_message.bundle,
+                        &db_message.bundle_checksum,
+                        &db_message.bundle_location,
+                    )?;
+                    let message_val: serde_json::Value =
+                        serde_json::from_value(db_message.message_data.clone())?;
+                    let message: Message =
+                        Message::from_val(&message_val, db_message.bundle.clone())?;
+                    /*
+                        a later assignment of a message whose
+                        original row was tiered, the original
+                        is older and is the match
+                    */
+                    match message.message {
+                        Some(_) => Ok(message),
+                        None => Ok(self.get_cold_message(conn, tx_id)?.unwrap_or(message)),
+                    }
+                }
+                Ok(None) => self
+                    .get_cold_message(conn, tx_id)?
+                    .ok_or(StoreErrorType::NotFound("Message not found".to_string())),
+                Err(e) => Err(StoreErrorType::from(e)),
+            }
+        })
+    }
+
+    // a message no longer in the messages table, tiered or its row pruned
+    fn is_moved_message(&self, message_id_in: &str) -> Result<bool, StoreErrorType> {
+        use super::schema::{cold_messages, pruned_messages};
         let conn = &mut self.get_conn()?;
+        let cold = cold_messages::table
+            .filter(cold_messages::message_id.eq(message_id_in))
+            .filter(cold_messages::carries_message.eq(true));
+        let pruned = pruned_messages::table.filter(pruned_messages::message_id.eq(message_id_in));
+        Ok(
+            diesel::select(diesel::dsl::exists(cold).or(diesel::dsl::exists(pruned)))
+                .get_result(conn)?,
+        )
+    }
 
-        /*
-            get the oldest match. in the case of a message that has
-            later assignments, it should be the original message itself.
-        */
-        let db_message_result: Result<Option<DbMessage>, DieselError> = messages
-            .filter(message_id.eq(tx_id).or(assignment_id.eq(tx_id)))
-            .order(timestamp.asc())
+    /*
+        a tiered message by message or assignment id, the
+        oldest match like the rows. Its row comes from the
+        rows file and its bundle from the one row group of
+        the bundles file it is in
+    */
+    fn get_cold_message(
+        &self,
+        conn: &mut PgConnection,
+        tx_id: &str,
+    ) -> Result<Option<Message>, StoreErrorType> {
+        use super::schema::{cold_messages, cold_segments};
+        let found: Option<(i32, DbColdSegment)> = cold_messages::table
+            .inner_join(
+                cold_segments::table.on(cold_segments::row_id.eq(cold_messages::segment_row_id)),
+            )
+            .filter(
+                cold_messages::message_id
+                    .eq(tx_id)
+                    .or(cold_messages::assignment_id.eq(tx_id)),
+            )
+            .order((cold_messages::epoch.asc(), cold_messages::nonce.asc()))
+            .select((cold_messages::position, DbColdSegment::as_select()))
             .first(conn)
-            .optional();
+            .optional()?;
+        let (position, segment) = match found {
+            Some(found) => found,
+            None => return Ok(None),
+        };
 
-        match db_message_result {
-            Ok(Some(db_message)) => {
-                let message_val: serde_json::Value =
-                    serde_json::from_value(db_message.message_data.clone())?;
-                let message: Message = Message::from_val(&message_val, db_message.bundle.clone())?;
-                Ok(message)
+        let row = self
+            .read_segment(&segment)?
+            .into_iter()
+            .nth(position as usize)
+            .ok_or(StoreErrorType::DatabaseError(format!(
+                "segment {} has no row {}",
+                segment.segment_key, position
+            )))?;
+        let bundle_in = match &segment.bundles_key {
+            Some(key) => cold::decode_bundle(self.get_blob(key)?, position as usize)
+                .map_err(StoreErrorType::DatabaseError)?,
+            None => base64_url::decode(&row.bundle)
+                .map_err(|e| StoreErrorType::DatabaseError(format!("{:?}", e)))?,
+        };
+        Ok(Some(Message::from_val(&row.message_data, bundle_in)?))
+    }
+
+    pub fn get_message_by_nonce(
+        &self,
+        process_id_in: &str,
+        epoch_in: &i32,
+        nonce_in: &i32,
+    ) -> Result<Message, StoreErrorType> {
+        use super::schema::messages::dsl::*;
+        self.lookup(|conn| {
+            let db_message_result: Result<Option<DbMessage>, DieselError> = messages
+                .filter(
+                    process_id
+                        .eq(process_id_in)
+                        .and(epoch.eq(epoch_in))
+                        .and(nonce.eq(nonce_in)),
+                )
+                .first(conn)
+                .optional();
+
+            match db_message_result {
+                Ok(Some(db_message)) => {
+                    self.verify_bundle(
+                        BundleRef::Message(db_message.row_id),
+                        &db_message.bundle,
+                        &db_message.bundle_checksum,
+                        &db_message.bundle_location,
+                    )?;
+                    let message_val: serde_json::Value =
+                        serde_json::from_value(db_message.message_data.clone())?;
+                    let message: Message =
+                        Message::from_val(&message_val, db_message.bundle.clone())?;
+                    Ok(message)
+                }
+                Ok(None) => Err(StoreErrorType::NotFound("Message not found".to_string())),
+                Err(e) => Err(StoreErrorType::from(e)),
             }
-            Ok(None) => Err(StoreErrorType::NotFound("Message not found".to_string())), // Adjust this error type as needed
-            Err(e) => Err(StoreErrorType::from(e)),
+        })
+    }
+
+    /*
+        messages of a process by nonce, both ends inclusive,
+        for a bulk read. tiered segments hold the lowest
+        nonces so they are read before the rows, the cursor
+        of every edge is its nonce
+    */
+    pub fn get_messages_by_nonce_range(
+        &self,
+        process_id_in: &str,
+        from_nonce_in: i32,
+        to_nonce_in: Option<i32>,
+        limit: i64,
+    ) -> Result<PaginatedMessages, StoreErrorType> {
+        use super::schema::messages::dsl::*;
+        let conn = &mut self.get_read_conn()?;
+
+        let mut result: Vec<(Message, String)> = vec![];
+        for segment in
+            self.get_cold_segments_by_nonce(conn, process_id_in, from_nonce_in, to_nonce_in)?
+        {
+            if result.len() as i64 > limit {
+                break;
+            }
+            for row in self.read_segment(&segment)?.iter() {
+                if row.nonce >= from_nonce_in && to_nonce_in.map_or(true, |t| row.nonce <= t) {
+                    let message = Message::from_val(&row.message_data, vec![])?;
+                    result.push((message, row.nonce.to_string()));
+                }
+            }
+        }
+
+        // one extra to know if there is a next page
+        let remaining = limit + 1 - result.len() as i64;
+        if remaining > 0 {
+            let mut query = messages
+                .filter(process_id.eq(process_id_in))
+                .filter(nonce.ge(from_nonce_in))
+                .into_boxed();
+            if let Some(to_nonce_in) = to_nonce_in {
+                query = query.filter(nonce.le(to_nonce_in));
+            }
+            let db_messages: Vec<DbMessage> = query
+                .order((epoch.asc(), nonce.asc()))
+                .limit(remaining)
+                .load(conn)?;
+            for db_message in db_messages.iter() {
+                self.verify_bundle(
+                    BundleRef::Message(db_message.row_id),
+                    &db_message.bundle,
+                    &db_message.bundle_checksum,
+                    &db_message.bundle_location,
+                )?;
+                let json = serde_json::from_value(db_message.message_data.clone())?;
+                let message = Message::from_val(&json, db_message.bundle.clone())?;
+                result.push((message, db_message.nonce.to_string()));
+            }
+        }
+
+        let has_next_page = result.len() as i64 > limit;
+        result.truncate(limit as usize);
+        Ok(PaginatedMessages::from_cursored_messages(
+            result,
+            has_next_page,
+        ))
+    }
+
+    // the tiered segments overlapping a nonce range, lowest first
+    fn get_cold_segments_by_nonce(
+        &self,
+        conn: &mut PgConnection,
+        process_id_in: &str,
+        from_nonce_in: i32,
+        to_nonce_in: Option<i32>,
+    ) -> Result<Vec<DbColdSegment>, StoreErrorType> {
+        use super::schema::cold_segments::dsl::*;
+        let mut query = cold_segments
+            .filter(process_id.eq(process_id_in))
+            .filter(to_nonce.ge(from_nonce_in))
+            .into_boxed();
+        if let Some(to_nonce_in) = to_nonce_in {
+            query = query.filter(from_nonce.le(to_nonce_in));
         }
+        Ok(query
+            .select(DbColdSegment::as_select())
+            .order((from_epoch.asc(), from_nonce.asc()))
+            .load(conn)?)
     }
 
-    fn get_latest_message(&self, process_id_in: &str) -> Result<Option<Message>, StoreErrorType> {
+    /*
+        the bundle is not verified here, the scheduler only
+        needs the json fields and a corrupted bundle should
+        not stop the process from being written to
+    */
+    pub fn get_latest_message(
+        &self,
+        process_id_in: &str,
+    ) -> Result<Option<Message>, StoreErrorType> {
         use super::schema::messages::dsl::*;
         let conn = &mut self.get_conn()?;
 
@@ -312,7 +1790,7 @@ impl DataStore for StoreClient {
                 // Deserialize the message_data into Message
                 let message_val: serde_json::Value =
                     serde_json::from_value(db_message.message_data)
-                        .map_err(|e| StoreErrorType::from(e))?;
+                        .map_err(StoreErrorType::from)?;
 
                 let message: Message = Message::from_val(&message_val, db_message.bundle.clone())?;
 
@@ -323,7 +1801,504 @@ impl DataStore for StoreClient {
         }
     }
 
-    fn save_process_scheduler(
+    pub fn get_process_sequence(
+        &self,
+        process_id_in: &str,
+    ) -> Result<Option<ProcessSequence>, StoreErrorType> {
+        use super::schema::process_sequence::dsl::*;
+        let conn = &mut self.get_conn()?;
+
+        let row: Option<(i32, i32, String, String)> = process_sequence
+            .filter(process_id.eq(process_id_in))
+            .select((epoch, nonce, hash_chain, assignment_id))
+            .first(conn)
+            .optional()?;
+        Ok(row.map(
+            |(epoch_out, nonce_out, hash_chain_out, assignment_id_out)| ProcessSequence {
+                process_id: process_id_in.to_string(),
+                epoch: epoch_out,
+                nonce: nonce_out,
+                hash_chain: hash_chain_out,
+                assignment_id: assignment_id_out,
+            },
+        ))
+    }
+
+    pub fn acquire_lease(
+        &self,
+        process_id_in: &str,
+        holder_in: &str,
+        ttl_ms: u64,
+    ) -> Result<String, StoreErrorType> {
+        let conn = &mut self.get_conn()?;
+
+        diesel::sql_query(
+            "INSERT INTO process_leases (process_id, holder, expires_at)
+            VALUES ($1, $2, now() + $3::float8 * interval '1 millisecond')
+            ON CONFLICT (process_id) DO UPDATE SET
+                holder = EXCLUDED.holder,
+                expires_at = EXCLUDED.expires_at
+            WHERE process_leases.holder = EXCLUDED.holder
+                OR process_leases.expires_at <= now()",
+        )
+        .bind::<Text, _>(process_id_in)
+        .bind::<Text, _>(holder_in)
+        .bind::<BigInt, _>(ttl_ms as i64)
+        .execute(conn)?;
+
+        let lease: DbLease = diesel::sql_query(
+            "SELECT holder, expires_at > now() AS live FROM process_leases WHERE process_id = $1",
+        )
+        .bind::<Text, _>(process_id_in)
+        .get_result(conn)?;
+        Ok(lease.holder)
+    }
+
+    pub fn get_lease_holder(&self, process_id_in: &str) -> Result<Option<String>, StoreErrorType> {
+        let conn = &mut self.get_conn()?;
+
+        let lease: Option<DbLease> = diesel::sql_query(
+            "SELECT holder, expires_at > now() AS live FROM process_leases WHERE process_id = $1",
+        )
+        .bind::<Text, _>(process_id_in)
+        .get_result(conn)
+        .optional()?;
+        Ok(lease.filter(|l| l.live).map(|l| l.holder))
+    }
+
+    pub fn release_leases(&self, holder_in: &str) -> Result<(), StoreErrorType> {
+        use super::schema::process_leases::dsl::*;
+        let conn = &mut self.get_conn()?;
+
+        diesel::delete(process_leases.filter(holder.eq(holder_in))).execute(conn)?;
+        Ok(())
+    }
+
+    pub fn get_read_nonce(&self, process_id_in: &str) -> Result<Option<i32>, StoreErrorType> {
+        use super::schema::process_sequence::dsl::*;
+        let conn = &mut self.get_read_conn()?;
+
+        let row: Option<i32> = process_sequence
+            .filter(process_id.eq(process_id_in))
+            .select(nonce)
+            .first(conn)
+            .optional()?;
+        Ok(row)
+    }
+
+    pub fn get_message_bundles(
+        &self,
+        process_id_in: &str,
+        after: &Option<(i32, i32)>,
+        limit: i64,
+    ) -> Result<MessageBundles, StoreErrorType> {
+        use super::schema::messages::dsl::*;
+        let conn = &mut self.get_conn()?;
+
+        // tiered history comes first, a page never mixes a segment and rows
+        if let Some(tiered) = self.get_tiered_bundles(conn, process_id_in, after, limit)? {
+            return Ok(tiered);
+        }
+
+        let mut query = messages.filter(process_id.eq(process_id_in)).into_boxed();
+        if let Some((after_epoch, after_nonce)) = after {
+            query = query.filter(
+                epoch
+                    .gt(after_epoch)
+                    .or(epoch.eq(after_epoch).and(nonce.gt(after_nonce))),
+            );
+        }
+
+        let db_messages: Vec<DbMessage> = query
+            .order((epoch.asc(), nonce.asc()))
+            .limit(limit)
+            .load(conn)?;
+
+        let mut result = vec![];
+        for db_message in db_messages.into_iter() {
+            let bundle_in = self.load_bundle(db_message.bundle, &db_message.bundle_location)?;
+            self.verify_bundle(
+                BundleRef::Message(db_message.row_id),
+                &bundle_in,
+                &db_message.bundle_checksum,
+                &None,
+            )?;
+            let message = Message::from_val(&db_message.message_data, bundle_in.clone())?;
+            result.push((message, bundle_in));
+        }
+        Ok(result)
+    }
+
+    fn get_tiered_bundles(
+        &self,
+        conn: &mut PgConnection,
+        process_id_in: &str,
+        after: &Option<(i32, i32)>,
+        limit: i64,
+    ) -> Result<Option<MessageBundles>, StoreErrorType> {
+        use super::schema::cold_segments::dsl::*;
+        let mut query = cold_segments
+            .filter(process_id.eq(process_id_in))
+            .into_boxed();
+        if let Some((after_epoch, after_nonce)) = after {
+            query = query.filter(
+                to_epoch
+                    .gt(after_epoch)
+                    .or(to_epoch.eq(after_epoch).and(to_nonce.gt(after_nonce))),
+            );
+        }
+        let segment: Option<DbColdSegment> = query
+            .select(DbColdSegment::as_select())
+            .order((from_epoch.asc(), from_nonce.asc()))
+            .first(conn)
+            .optional()?;
+        let segment = match segment {
+            Some(segment) => segment,
+            None => return Ok(None),
+        };
+
+        let rows = self.read_segment(&segment)?;
+        let bundles = match &segment.bundles_key {
+            Some(key) => {
+                cold::decode_bundles(self.get_blob(key)?).map_err(StoreErrorType::DatabaseError)?
+            }
+            None => rows
+                .iter()
+                .map(|row| base64_url::decode(&row.bundle))
+                .collect::<Result<_, _>>()
+                .map_err(|e| StoreErrorType::DatabaseError(format!("{:?}", e)))?,
+        };
+
+        let mut result = vec![];
+        for (row, bundle_in) in rows.into_iter().zip(bundles) {
+            let is_after = match after {
+                Some(a) => (row.epoch, row.nonce) > *a,
+                None => true,
+            };
+            if !is_after {
+                continue;
+            }
+            if bundle_in.is_empty() {
+                return Err(StoreErrorType::NotFound(
+                    "Bundle was pruned, it is only on arweave".to_string(),
+                ));
+            }
+            let message = Message::from_val(&row.message_data, bundle_in.clone())?;
+            result.push((message, bundle_in));
+            if result.len() as i64 >= limit {
+                break;
+            }
+        }
+        Ok(Some(result).filter(|r| !r.is_empty()))
+    }
+
+    // processes with at least min_messages sequenced before the timestamp
+    pub fn get_tierable_processes(
+        &self,
+        before: i64,
+        min_messages: i64,
+        limit: i64,
+    ) -> Result<Vec<String>, StoreErrorType> {
+        use super::schema::messages::dsl::*;
+        let conn = &mut self.get_read_conn()?;
+
+        let tierable: Vec<String> = messages
+            .filter(timestamp.lt(before))
+            .group_by(process_id)
+            .select(process_id)
+            .having(diesel::dsl::count_star().ge(min_messages))
+            .order(process_id.asc())
+            .limit(limit)
+            .load(conn)?;
+        Ok(tierable)
+    }
+
+    /*
+        Moves the oldest segment_size messages of a process
+        to one segment in the blob store, if they were all
+        sequenced before the timestamp. The segment files
+        are written first and the rows are only deleted
+        once it and the ids of its messages are recorded.
+        The latest message always stays.
+    */
+    pub fn tier_process(
+        &self,
+        process_id_in: &str,
+        before: i64,
+        segment_size: i64,
+    ) -> Result<Option<ColdSegment>, StoreErrorType> {
+        use super::schema::{cold_segments, messages};
+        let blobs = self.blobs.as_ref().ok_or(StoreErrorType::DatabaseError(
+            "cold storage needs BUNDLE_STORAGE disk or s3".to_string(),
+        ))?;
+        let conn = &mut self.get_conn()?;
+
+        let mut db_messages: Vec<DbMessage> = messages::table
+            .filter(messages::process_id.eq(process_id_in))
+            .order((messages::epoch.asc(), messages::nonce.asc()))
+            .limit(segment_size + 1)
+            .load(conn)?;
+        if db_messages.len() as i64 <= segment_size {
+            return Ok(None);
+        }
+        db_messages.truncate(segment_size as usize);
+        let (first, last) = match (db_messages.first(), db_messages.last()) {
+            (Some(first), Some(last)) if last.timestamp < before => (first, last),
+            _ => return Ok(None),
+        };
+
+        let mut rows = vec![];
+        let mut bundles = vec![];
+        for db_message in db_messages.iter() {
+            let bundle_in =
+                match self.load_bundle(db_message.bundle.clone(), &db_message.bundle_location) {
+                    Ok(bundle_in) => bundle_in,
+                    Err(StoreErrorType::NotFound(_)) => vec![],
+                    Err(e) => return Err(e),
+                };
+            rows.push(ColdRow {
+                message_id: db_message.message_id.clone(),
+                assignment_id: db_message.assignment_id.clone(),
+                message_data: db_message.message_data.clone(),
+                epoch: db_message.epoch,
+                nonce: db_message.nonce,
+                timestamp: db_message.timestamp,
+                hash_chain: db_message.hash_chain.clone(),
+                bundle: String::new(),
+            });
+            bundles.push(bundle_in);
+        }
+        let key = format!("cold-{}-{}-{}", process_id_in, first.epoch, first.nonce);
+        let segment_size = self.put_segment(blobs, &key, &rows, &bundles)?;
+
+        let tiered = ColdSegment {
+            process_id: process_id_in.to_string(),
+            from_nonce: first.nonce,
+            to_nonce: last.nonce,
+            message_count: db_messages.len() as i32,
+            segment_size,
+        };
+        let row_ids: Vec<i32> = db_messages.iter().map(|m| m.row_id).collect();
+        conn.transaction::<_, StoreErrorType, _>(|conn| {
+            let segment_row_id: i32 = diesel::insert_into(cold_segments::table)
+                .values(&NewColdSegment {
+                    process_id: process_id_in,
+                    from_epoch: first.epoch,
+                    from_nonce: first.nonce,
+                    to_epoch: last.epoch,
+                    to_nonce: last.nonce,
+                    from_timestamp: first.timestamp,
+                    to_timestamp: last.timestamp,
+                    message_count: tiered.message_count,
+                    segment_key: &rows_key(&key),
+                    segment_size: tiered.segment_size,
+                    bundles_key: Some(&bundles_key(&key)),
+                })
+                .returning(cold_segments::row_id)
+                .get_result(conn)?;
+            self.index_cold_rows(conn, process_id_in, segment_row_id, &rows)?;
+            diesel::delete(messages::table.filter(messages::row_id.eq_any(&row_ids)))
+                .execute(conn)?;
+            Ok(())
+        })?;
+
+        // the segment has its own copy of the offloaded bundles
+        for location in db_messages
+            .iter()
+            .filter_map(|m| m.bundle_location.as_ref())
+        {
+            let _ = blobs.delete(location);
+        }
+        Ok(Some(tiered))
+    }
+
+    // writes the two files of a segment, their total size
+    fn put_segment(
+        &self,
+        blobs: &Arc<dyn BlobStore>,
+        key: &str,
+        rows: &[ColdRow],
+        bundles: &[Vec<u8>],
+    ) -> Result<i64, StoreErrorType> {
+        let rows_file = cold::encode_rows(rows).map_err(StoreErrorType::DatabaseError)?;
+        let bundles_file = cold::encode_bundles(bundles).map_err(StoreErrorType::DatabaseError)?;
+        blobs
+            .put(&rows_key(key), &rows_file)
+            .map_err(StoreErrorType::DatabaseError)?;
+        blobs
+            .put(&bundles_key(key), &bundles_file)
+            .map_err(StoreErrorType::DatabaseError)?;
+        Ok((rows_file.len() + bundles_file.len()) as i64)
+    }
+
+    fn index_cold_rows(
+        &self,
+        conn: &mut PgConnection,
+        process_id_in: &str,
+        segment_row_id_in: i32,
+        rows: &[ColdRow],
+    ) -> Result<(), StoreErrorType> {
+        use super::schema::cold_messages;
+        let ids: Vec<NewColdMessage> = rows
+            .iter()
+            .enumerate()
+            .map(|(position, row)| NewColdMessage {
+                segment_row_id: segment_row_id_in,
+                position: position as i32,
+                process_id: process_id_in,
+                message_id: &row.message_id,
+                // rows saved before the column have it in their data
+                assignment_id: row
+                    .assignment_id
+                    .as_deref()
+                    .or(row.message_data["assignment"]["id"].as_str()),
+                epoch: row.epoch,
+                nonce: row.nonce,
+                carries_message: row.carries_message(),
+            })
+            .collect();
+        // postgres takes at most 65535 parameters in one statement
+        for chunk in ids.chunks(1000) {
+            diesel::insert_into(cold_messages::table)
+                .values(chunk)
+                .execute(conn)?;
+        }
+        Ok(())
+    }
+
+    /*
+        json segments hold messages tiered before their
+        assignments had a table, the ones already there are
+        left as they are
+    */
+    fn backfill_assignments(
+        &self,
+        conn: &mut PgConnection,
+        rows: &[ColdRow],
+    ) -> Result<(), StoreErrorType> {
+        use super::schema::assignments::dsl::*;
+        let mut backfilled = vec![];
+        for row in rows.iter() {
+            let message: Message = serde_json::from_value(row.message_data.clone())?;
+            backfilled.push(Assignment::from_message(&message)?);
+        }
+        let new_assignments: Vec<NewAssignment> = backfilled
+            .iter()
+            .map(|assignment| NewAssignment {
+                process_id: &assignment.process_id,
+                assignment_id: &assignment.assignment_id,
+                message_id: &assignment.message_id,
+                epoch: assignment.epoch,
+                nonce: assignment.nonce,
+                timestamp: assignment.timestamp,
+                hash_chain: &assignment.hash_chain,
+                block_height: assignment.block_height.as_deref(),
+            })
+            .collect();
+        for chunk in new_assignments.chunks(1000) {
+            diesel::insert_into(assignments)
+                .values(chunk)
+                .on_conflict_do_nothing()
+                .execute(conn)?;
+        }
+        Ok(())
+    }
+
+    /*
+        rewrites json segments tiered before the id index
+        to parquet and indexes their ids and assignments,
+        until then their messages are not seen by the
+        duplicate check or listed with the schedule
+    */
+    pub fn index_cold_segments(&self, limit: i64) -> Result<i64, StoreErrorType> {
+        use super::schema::cold_segments;
+        let blobs = match self.blobs.as_ref() {
+            Some(blobs) => blobs,
+            None => return Ok(0),
+        };
+        let conn = &mut self.get_conn()?;
+
+        let legacy: Vec<(i32, String, DbColdSegment)> = cold_segments::table
+            .filter(cold_segments::bundles_key.is_null())
+            .order(cold_segments::row_id.asc())
+            .limit(limit)
+            .select((
+                cold_segments::row_id,
+                cold_segments::process_id,
+                DbColdSegment::as_select(),
+            ))
+            .load(conn)?;
+
+        for (segment_row_id, process_id_in, segment) in legacy.iter() {
+            let mut rows = self.read_segment(segment)?;
+            let mut bundles = vec![];
+            for row in rows.iter_mut() {
+                let bundle_in = base64_url::decode(&row.bundle)
+                    .map_err(|e| StoreErrorType::DatabaseError(format!("{:?}", e)))?;
+                bundles.push(bundle_in);
+                row.bundle = String::new();
+            }
+            let segment_size = self.put_segment(blobs, &segment.segment_key, &rows, &bundles)?;
+            conn.transaction::<_, StoreErrorType, _>(|conn| {
+                diesel::update(cold_segments::table.find(segment_row_id))
+                    .set((
+                        cold_segments::segment_key.eq(rows_key(&segment.segment_key)),
+                        cold_segments::bundles_key.eq(bundles_key(&segment.segment_key)),
+                        cold_segments::segment_size.eq(segment_size),
+                    ))
+                    .execute(conn)?;
+                self.index_cold_rows(conn, process_id_in, *segment_row_id, &rows)?;
+                self.backfill_assignments(conn, &rows)?;
+                Ok(())
+            })?;
+            let _ = blobs.delete(&segment.segment_key);
+        }
+        Ok(legacy.len() as i64)
+    }
+
+    pub fn get_idle_processes(
+        &self,
+        before: i64,
+        limit: i64,
+    ) -> Result<Vec<String>, StoreErrorType> {
+        use super::schema::messages::dsl::*;
+        let conn = &mut self.get_read_conn()?;
+
+        let idle: Vec<String> = messages
+            .group_by(process_id)
+            .select(process_id)
+            .having(diesel::dsl::max(timestamp).lt(before))
+            .order(process_id.asc())
+            .limit(limit)
+            .load(conn)?;
+        Ok(idle)
+    }
+
+    pub fn get_message_count(&self, process_id_in: &str) -> Result<MessageCount, StoreErrorType> {
+        use super::schema::messages::dsl::*;
+        let conn = &mut self.get_read_conn()?;
+
+        // both aggregates are served from the process_id indexes
+        let (count, max_nonce): (i64, Option<i32>) = messages
+            .filter(process_id.eq(process_id_in))
+            .select((diesel::dsl::count_star(), diesel::dsl::max(nonce)))
+            .first(conn)?;
+
+        // the latest message is never tiered so max_nonce comes from the rows
+        let tiered: Option<i64> = super::schema::cold_segments::table
+            .filter(super::schema::cold_segments::process_id.eq(process_id_in))
+            .select(diesel::dsl::sum(
+                super::schema::cold_segments::message_count,
+            ))
+            .first(conn)?;
+
+        Ok(MessageCount {
+            count: count + tiered.unwrap_or(0),
+            max_nonce,
+        })
+    }
+
+    pub fn save_process_scheduler(
         &self,
         process_scheduler: &ProcessScheduler,
     ) -> Result<String, StoreErrorType> {
@@ -346,133 +2321,1303 @@ impl DataStore for StoreClient {
         }
     }
 
-    fn get_process_scheduler(
-        &self,
-        process_id_in: &str,
-    ) -> Result<ProcessScheduler, StoreErrorType> {
-        use super::schema::process_schedulers::dsl::*;
-        let conn = &mut self.get_conn()?;
+    pub fn get_process_scheduler(
+        &self,
+        process_id_in: &str,
+    ) -> Result<ProcessScheduler, StoreErrorType> {
+        use super::schema::process_schedulers::dsl::*;
+        let conn = &mut self.get_conn()?;
+
+        let db_process_result: Result<Option<DbProcessScheduler>, DieselError> = process_schedulers
+            .filter(process_id.eq(process_id_in))
+            .first(conn)
+            .optional();
+
+        match db_process_result {
+            Ok(Some(db_process_scheduler)) => {
+                let process_scheduler: ProcessScheduler = ProcessScheduler {
+                    row_id: Some(db_process_scheduler.row_id),
+                    process_id: db_process_scheduler.process_id,
+                    scheduler_row_id: db_process_scheduler.scheduler_row_id,
+                };
+                Ok(process_scheduler)
+            }
+            Ok(None) => Err(StoreErrorType::NotFound(
+                "Process scheduler not found".to_string(),
+            )),
+            Err(e) => Err(StoreErrorType::from(e)),
+        }
+    }
+
+    pub fn move_process_scheduler(
+        &self,
+        process_id_in: &str,
+        from_row_id: &i32,
+        to_row_id: &i32,
+    ) -> Result<String, StoreErrorType> {
+        use super::schema::process_schedulers;
+        use super::schema::schedulers;
+        let conn = &mut self.get_conn()?;
+
+        conn.transaction::<_, StoreErrorType, _>(|conn| {
+            let moved = diesel::update(
+                process_schedulers::table
+                    .filter(process_schedulers::process_id.eq(process_id_in))
+                    .filter(process_schedulers::scheduler_row_id.eq(from_row_id)),
+            )
+            .set(process_schedulers::scheduler_row_id.eq(to_row_id))
+            .execute(conn)?;
+            if moved == 0 {
+                return Err(StoreErrorType::NotFound(
+                    "Process scheduler not found".to_string(),
+                ));
+            }
+
+            diesel::update(schedulers::table.filter(schedulers::row_id.eq(from_row_id)))
+                .set(schedulers::process_count.eq(schedulers::process_count - 1))
+                .execute(conn)?;
+            diesel::update(schedulers::table.filter(schedulers::row_id.eq(to_row_id)))
+                .set(schedulers::process_count.eq(schedulers::process_count + 1))
+                .execute(conn)?;
+            Ok("moved".to_string())
+        })
+    }
+
+    pub fn save_assignment_decision(
+        &self,
+        decision: &AssignmentDecision,
+    ) -> Result<String, StoreErrorType> {
+        use super::schema::assignment_decisions::dsl::*;
+        let conn = &mut self.get_conn()?;
+
+        diesel::insert_into(assignment_decisions)
+            .values(NewAssignmentDecision {
+                process_id: &decision.process_id,
+                scheduler_url: &decision.scheduler_url,
+                strategy: &decision.strategy,
+                region: decision.region.as_deref(),
+                candidates: serde_json::to_value(&decision.candidates)?,
+                timestamp: decision.timestamp,
+            })
+            .execute(conn)?;
+        Ok("saved".to_string())
+    }
+
+    pub fn get_assignment_decisions(
+        &self,
+        process_id_in: &Option<String>,
+        limit: i64,
+    ) -> Result<Vec<AssignmentDecision>, StoreErrorType> {
+        use super::schema::assignment_decisions::dsl::*;
+        let conn = &mut self.get_read_conn()?;
+
+        let mut query = assignment_decisions.into_boxed();
+        if let Some(process_id_in) = process_id_in {
+            query = query.filter(process_id.eq(process_id_in));
+        }
+        let db_decisions: Vec<DbAssignmentDecision> = query
+            .select(DbAssignmentDecision::as_select())
+            .order(row_id.desc())
+            .limit(limit)
+            .load(conn)?;
+        let mut decisions = vec![];
+        for d in db_decisions.into_iter() {
+            decisions.push(AssignmentDecision {
+                process_id: d.process_id,
+                scheduler_url: d.scheduler_url,
+                strategy: d.strategy,
+                region: d.region,
+                candidates: serde_json::from_value(d.candidates)?,
+                timestamp: d.timestamp,
+            });
+        }
+        Ok(decisions)
+    }
+
+    pub fn save_scheduler(&self, scheduler: &Scheduler) -> Result<String, StoreErrorType> {
+        use super::schema::schedulers::dsl::*;
+        let conn = &mut self.get_conn()?;
+
+        let new_scheduler = NewScheduler {
+            url: &scheduler.url,
+            process_count: &scheduler.process_count,
+        };
+
+        match diesel::insert_into(schedulers)
+            .values(&new_scheduler)
+            .on_conflict(url)
+            .do_nothing()
+            .execute(conn)
+        {
+            Ok(_) => Ok("saved".to_string()),
+            Err(e) => Err(StoreErrorType::from(e)),
+        }
+    }
+
+    pub fn update_scheduler(&self, scheduler: &Scheduler) -> Result<String, StoreErrorType> {
+        use super::schema::schedulers::dsl::*;
+        let conn = &mut self.get_conn()?;
+
+        // Ensure scheduler.row_id is Some(value) before calling this function
+        match diesel::update(schedulers.filter(row_id.eq(scheduler.row_id.unwrap())))
+            .set((
+                process_count.eq(scheduler.process_count),
+                url.eq(&scheduler.url),
+            ))
+            .execute(conn)
+        {
+            Ok(_) => Ok("updated".to_string()),
+            Err(e) => Err(StoreErrorType::from(e)),
+        }
+    }
+
+    pub fn get_scheduler(&self, row_id_in: &i32) -> Result<Scheduler, StoreErrorType> {
+        use super::schema::schedulers::dsl::*;
+        let conn = &mut self.get_conn()?;
+
+        let db_scheduler_result: Result<Option<DbScheduler>, DieselError> = schedulers
+            .filter(row_id.eq(row_id_in))
+            .first(conn)
+            .optional();
+
+        match db_scheduler_result {
+            Ok(Some(db_scheduler)) => {
+                let scheduler: Scheduler = Scheduler {
+                    row_id: Some(db_scheduler.row_id),
+                    url: db_scheduler.url,
+                    process_count: db_scheduler.process_count,
+                };
+                Ok(scheduler)
+            }
+            Ok(None) => Err(StoreErrorType::NotFound("Scheduler not found".to_string())),
+            Err(e) => Err(StoreErrorType::from(e)),
+        }
+    }
+
+    pub fn get_scheduler_by_url(&self, url_in: &str) -> Result<Scheduler, StoreErrorType> {
+        use super::schema::schedulers::dsl::*;
+        let conn = &mut self.get_conn()?;
+
+        let db_scheduler_result: Result<Option<DbScheduler>, DieselError> =
+            schedulers.filter(url.eq(url_in)).first(conn).optional();
+
+        match db_scheduler_result {
+            Ok(Some(db_scheduler)) => {
+                let scheduler: Scheduler = Scheduler {
+                    row_id: Some(db_scheduler.row_id),
+                    url: db_scheduler.url,
+                    process_count: db_scheduler.process_count,
+                };
+                Ok(scheduler)
+            }
+            Ok(None) => Err(StoreErrorType::NotFound("Scheduler not found".to_string())),
+            Err(e) => Err(StoreErrorType::from(e)),
+        }
+    }
+
+    pub fn get_all_schedulers(&self) -> Result<Vec<Scheduler>, StoreErrorType> {
+        use super::schema::schedulers::dsl::*;
+        let conn = &mut self.get_conn()?;
+
+        match schedulers.order(row_id.asc()).load::<DbScheduler>(conn) {
+            Ok(db_schedulers) => {
+                let schedulers_out: Vec<Scheduler> = db_schedulers
+                    .into_iter()
+                    .map(|db_scheduler| Scheduler {
+                        row_id: Some(db_scheduler.row_id),
+                        url: db_scheduler.url,
+                        process_count: db_scheduler.process_count,
+                    })
+                    .collect();
+                Ok(schedulers_out)
+            }
+            Err(e) => Err(StoreErrorType::from(e)),
+        }
+    }
+
+    pub fn get_store_stats(&self) -> Result<StoreStats, StoreErrorType> {
+        use super::schema::{messages, processes, schedulers};
+        let conn = &mut self.get_read_conn()?;
+
+        let process_count: i64 = processes::table.count().get_result(conn)?;
+        let message_count: i64 = messages::table.count().get_result(conn)?;
+        let scheduler_count: i64 = schedulers::table.count().get_result(conn)?;
+        let latest_message_timestamp: Option<i64> = messages::table
+            .select(diesel::dsl::max(messages::timestamp))
+            .first(conn)?;
+
+        Ok(StoreStats {
+            process_count,
+            message_count,
+            scheduler_count,
+            latest_message_timestamp,
+        })
+    }
+
+    // bytes on disk of the whole database, indexes included
+    pub fn get_storage_size(&self) -> Result<i64, StoreErrorType> {
+        let conn = &mut self.get_read_conn()?;
+        let size: i64 = diesel::select(diesel::dsl::sql::<diesel::sql_types::BigInt>(
+            "pg_database_size(current_database())",
+        ))
+        .get_result(conn)?;
+        Ok(size)
+    }
+
+    pub fn get_prunable_messages(
+        &self,
+        before: Option<i64>,
+        keep_latest: Option<i64>,
+        after: i32,
+        limit: i64,
+    ) -> Result<Vec<PrunableMessage>, StoreErrorType> {
+        let conn = &mut self.get_read_conn()?;
+
+        // rows without an assignment id cannot be looked up on arweave
+        let prunable: Vec<DbPrunableMessage> = diesel::sql_query(
+            "SELECT row_id, assignment_id, octet_length(bundle)::bigint AS stored_size, \
+             bundle_location FROM ( \
+               SELECT row_id, assignment_id, bundle, bundle_location, timestamp, \
+                 row_number() OVER (PARTITION BY process_id ORDER BY epoch DESC, nonce DESC) \
+                   AS position \
+               FROM messages \
+             ) ranked \
+             WHERE position > 1 AND row_id > $1 AND assignment_id IS NOT NULL \
+               AND (octet_length(bundle) > 0 OR bundle_location IS NOT NULL) \
+               AND (timestamp < $2 OR position > $3) \
+             ORDER BY row_id ASC LIMIT $4",
+        )
+        .bind::<Int4, _>(after)
+        .bind::<BigInt, _>(before.unwrap_or(i64::MIN))
+        .bind::<BigInt, _>(keep_latest.unwrap_or(i64::MAX))
+        .bind::<BigInt, _>(limit)
+        .load(conn)?;
+
+        Ok(prunable
+            .into_iter()
+            .map(|p| PrunableMessage {
+                row_id: p.row_id,
+                assignment_id: p.assignment_id,
+                stored_size: p.stored_size,
+                bundle_location: p.bundle_location,
+            })
+            .collect())
+    }
+
+    /*
+        rows are changed before their offloaded bundles are
+        deleted, a failure in between leaves an unused blob
+        instead of a row pointing at a missing one. A row
+        deleted with its data item leaves the id behind so
+        the duplicate check still sees it
+    */
+    pub fn prune_messages(
+        &self,
+        prunable: &[PrunableMessage],
+        delete_rows: bool,
+    ) -> Result<Vec<String>, StoreErrorType> {
+        use super::schema::messages::dsl::*;
+        use super::schema::pruned_messages;
+        let conn = &mut self.get_conn()?;
+
+        let row_ids: Vec<i32> = prunable.iter().map(|p| p.row_id).collect();
+        match delete_rows {
+            true => conn.transaction::<_, StoreErrorType, _>(|conn| {
+                diesel::insert_into(pruned_messages::table)
+                    .values(
+                        messages
+                            .filter(row_id.eq_any(&row_ids))
+                            .filter(sql::<Bool>(
+                                "jsonb_typeof(message_data -> 'message') = 'object'",
+                            ))
+                            .select((message_id, assignment_id, process_id, epoch, nonce)),
+                    )
+                    .into_columns((
+                        pruned_messages::message_id,
+                        pruned_messages::assignment_id,
+                        pruned_messages::process_id,
+                        pruned_messages::epoch,
+                        pruned_messages::nonce,
+                    ))
+                    .execute(conn)?;
+                Ok(diesel::delete(messages.filter(row_id.eq_any(&row_ids))).execute(conn)?)
+            })?,
+            false => diesel::update(messages.filter(row_id.eq_any(&row_ids)))
+                .set((
+                    bundle.eq(Vec::<u8>::new()),
+                    bundle_location.eq(None::<String>),
+                    bundle_checksum.eq(None::<String>),
+                ))
+                .execute(conn)?,
+        };
+
+        let mut blob_errors = vec![];
+        if let Some(blobs) = &self.blobs {
+            for key in prunable.iter().filter_map(|p| p.bundle_location.as_ref()) {
+                if let Err(e) = blobs.delete(key) {
+                    blob_errors.push(e);
+                }
+            }
+        }
+        Ok(blob_errors)
+    }
+
+    pub fn save_api_token(&self, api_token: &ApiToken) -> Result<String, StoreErrorType> {
+        use super::schema::api_tokens::dsl::*;
+        let conn = &mut self.get_conn()?;
+
+        let new_api_token = NewApiToken {
+            token_hash: &api_token.token_hash,
+            owner: api_token.owner.as_deref(),
+            process_ids: serde_json::to_value(&api_token.process_ids)?,
+        };
+
+        match diesel::insert_into(api_tokens)
+            .values(&new_api_token)
+            .execute(conn)
+        {
+            Ok(_) => Ok("saved".to_string()),
+            Err(e) => Err(StoreErrorType::from(e)),
+        }
+    }
+
+    pub fn get_api_token(&self, token_hash_in: &str) -> Result<ApiToken, StoreErrorType> {
+        use super::schema::api_tokens::dsl::*;
+        let conn = &mut self.get_conn()?;
+
+        let db_api_token_result: Result<Option<DbApiToken>, DieselError> = api_tokens
+            .filter(token_hash.eq(token_hash_in))
+            .first(conn)
+            .optional();
+
+        match db_api_token_result {
+            Ok(Some(db_api_token)) => Ok(ApiToken {
+                row_id: Some(db_api_token.row_id),
+                token_hash: db_api_token.token_hash,
+                owner: db_api_token.owner,
+                process_ids: serde_json::from_value(db_api_token.process_ids)?,
+            }),
+            Ok(None) => Err(StoreErrorType::NotFound("Api token not found".to_string())),
+            Err(e) => Err(StoreErrorType::from(e)),
+        }
+    }
+
+    pub fn delete_api_token(&self, token_hash_in: &str) -> Result<String, StoreErrorType> {
+        use super::schema::api_tokens::dsl::*;
+        let conn = &mut self.get_conn()?;
+
+        match diesel::delete(api_tokens.filter(token_hash.eq(token_hash_in))).execute(conn) {
+            Ok(0) => Err(StoreErrorType::NotFound("Api token not found".to_string())),
+            Ok(_) => Ok("deleted".to_string()),
+            Err(e) => Err(StoreErrorType::from(e)),
+        }
+    }
+
+    pub fn ping(&self) -> Result<(), StoreErrorType> {
+        let conn = &mut self.get_conn()?;
+        diesel::sql_query("SELECT 1").execute(conn)?;
+        Ok(())
+    }
+
+    // oldest first, they stay until deleted after publishing
+    pub fn get_outbox(&self, limit: i64) -> Result<Vec<OutboxEvent>, StoreErrorType> {
+        use super::schema::event_outbox::dsl::*;
+        let conn = &mut self.get_conn()?;
+
+        let rows: Vec<(i32, String, serde_json::Value)> = event_outbox
+            .select((row_id, process_id, event))
+            .order(row_id.asc())
+            .limit(limit)
+            .load(conn)?;
+        Ok(rows
+            .into_iter()
+            .map(|(row_id_out, process_id_out, event_out)| OutboxEvent {
+                row_id: row_id_out,
+                process_id: process_id_out,
+                event: event_out.to_string(),
+            })
+            .collect())
+    }
+
+    pub fn delete_outbox(&self, row_ids: &[i32]) -> Result<(), StoreErrorType> {
+        use super::schema::event_outbox::dsl::*;
+        let conn = &mut self.get_conn()?;
+
+        diesel::delete(event_outbox.filter(row_id.eq_any(row_ids))).execute(conn)?;
+        Ok(())
+    }
+
+    /*
+        counts a rejected publish of an event, at the last
+        attempt it is moved to event_outbox_dead. true when
+        it was moved
+    */
+    pub fn fail_outbox(
+        &self,
+        row_id_in: i32,
+        max_attempts: u32,
+        error_in: &str,
+    ) -> Result<bool, StoreErrorType> {
+        use super::schema::event_outbox::dsl::*;
+        let conn = &mut self.get_conn()?;
+
+        conn.transaction::<bool, StoreErrorType, _>(|conn| {
+            let failed: Option<(String, serde_json::Value, i32)> =
+                diesel::update(event_outbox.filter(row_id.eq(row_id_in)))
+                    .set(attempts.eq(attempts + 1))
+                    .returning((process_id, event, attempts))
+                    .get_result(conn)
+                    .optional()?;
+            let (process_id_out, event_out, attempts_out) = match failed {
+                Some(failed) => failed,
+                None => return Ok(false),
+            };
+            if attempts_out < max_attempts as i32 {
+                return Ok(false);
+            }
+
+            {
+                use super::schema::event_outbox_dead::dsl as dead;
+                diesel::insert_into(dead::event_outbox_dead)
+                    .values((
+                        dead::process_id.eq(&process_id_out),
+                        dead::event.eq(&event_out),
+                        dead::error.eq(error_in),
+                    ))
+                    .execute(conn)?;
+            }
+            diesel::delete(event_outbox.filter(row_id.eq(row_id_in))).execute(conn)?;
+            Ok(true)
+        })
+    }
+
+    pub fn save_undelivered(
+        &self,
+        item_id_in: &str,
+        bundle_in: &[u8],
+        error_in: &str,
+    ) -> Result<(), StoreErrorType> {
+        use super::schema::undelivered_uploads::dsl::*;
+        let conn = &mut self.get_conn()?;
+
+        diesel::insert_into(undelivered_uploads)
+            .values((
+                item_id.eq(item_id_in),
+                bundle.eq(bundle_in),
+                error.eq(error_in),
+            ))
+            .on_conflict(item_id)
+            .do_update()
+            .set((error.eq(error_in), failed_at.eq(diesel::dsl::now)))
+            .execute(conn)?;
+        Ok(())
+    }
+
+    // the oldest failures first
+    pub fn get_undelivered(&self, limit: i64) -> Result<Vec<(String, Vec<u8>)>, StoreErrorType> {
+        use super::schema::undelivered_uploads::dsl::*;
+        let conn = &mut self.get_conn()?;
+
+        Ok(undelivered_uploads
+            .order(failed_at.asc())
+            .limit(limit)
+            .select((item_id, bundle))
+            .load(conn)?)
+    }
+
+    pub fn delete_undelivered(&self, item_id_in: &str) -> Result<(), StoreErrorType> {
+        use super::schema::undelivered_uploads::dsl::*;
+        let conn = &mut self.get_conn()?;
+
+        diesel::delete(undelivered_uploads.filter(item_id.eq(item_id_in))).execute(conn)?;
+        Ok(())
+    }
+
+    pub fn save_checkpoint(&self, checkpoint: &Checkpoint) -> Result<String, StoreErrorType> {
+        use super::schema::checkpoints::dsl::*;
+        let conn = &mut self.get_conn()?;
+
+        let new_checkpoint = NewCheckpoint {
+            process_id: &checkpoint.process_id,
+            epoch: checkpoint.epoch,
+            nonce: checkpoint.nonce,
+            checkpoint_id: &checkpoint.checkpoint_id,
+            cu: checkpoint.cu.as_deref(),
+            timestamp: checkpoint.timestamp,
+        };
+
+        match diesel::insert_into(checkpoints)
+            .values(&new_checkpoint)
+            .on_conflict_do_nothing()
+            .execute(conn)
+        {
+            Ok(0) => Err(StoreErrorType::MessageExists(
+                "Checkpoint already registered".to_string(),
+            )),
+            Ok(_) => Ok("saved".to_string()),
+            Err(e) => Err(StoreErrorType::from(e)),
+        }
+    }
+
+    pub fn get_checkpoints(
+        &self,
+        process_id_in: &str,
+        before: &Option<(i32, i32)>,
+        limit: i64,
+    ) -> Result<Vec<Checkpoint>, StoreErrorType> {
+        use super::schema::checkpoints::dsl::*;
+        let conn = &mut self.get_read_conn()?;
+
+        let mut query = checkpoints
+            .filter(process_id.eq(process_id_in))
+            .into_boxed();
+        if let Some((epoch_in, nonce_in)) = before {
+            query = query.filter(
+                epoch
+                    .lt(*epoch_in)
+                    .or(epoch.eq(*epoch_in).and(nonce.le(*nonce_in))),
+            );
+        }
+
+        let db_checkpoints: Vec<DbCheckpoint> = query
+            .order((epoch.desc(), nonce.desc(), row_id.desc()))
+            .limit(limit)
+            .load(conn)?;
+        Ok(db_checkpoints
+            .into_iter()
+            .map(|c| Checkpoint {
+                process_id: c.process_id,
+                epoch: c.epoch,
+                nonce: c.nonce,
+                checkpoint_id: c.checkpoint_id,
+                cu: c.cu,
+                timestamp: c.timestamp,
+            })
+            .collect())
+    }
+
+    pub fn get_assignments(
+        &self,
+        process_id_in: &str,
+        after: &Option<(i32, i32)>,
+        limit: i64,
+    ) -> Result<Vec<Assignment>, StoreErrorType> {
+        use super::schema::assignments::dsl::*;
+        let conn = &mut self.get_read_conn()?;
+
+        let mut query = assignments
+            .filter(process_id.eq(process_id_in))
+            .into_boxed();
+        if let Some((epoch_in, nonce_in)) = after {
+            query = query.filter(
+                epoch
+                    .gt(*epoch_in)
+                    .or(epoch.eq(*epoch_in).and(nonce.gt(*nonce_in))),
+            );
+        }
+
+        let db_assignments: Vec<DbAssignment> = query
+            .select(DbAssignment::as_select())
+            .order((epoch.asc(), nonce.asc()))
+            .limit(limit)
+            .load(conn)?;
+        Ok(db_assignments.into_iter().map(Assignment::from).collect())
+    }
+
+    // the assignments of a message id or of an assignment id, the original first
+    pub fn find_assignments(&self, id_in: &str) -> Result<Vec<Assignment>, StoreErrorType> {
+        use super::schema::assignments::dsl::*;
+        let conn = &mut self.get_read_conn()?;
+
+        let db_assignments: Vec<DbAssignment> = assignments
+            .select(DbAssignment::as_select())
+            .filter(message_id.eq(id_in).or(assignment_id.eq(id_in)))
+            .order((timestamp.asc(), row_id.asc()))
+            .limit(100)
+            .load(conn)?;
+        Ok(db_assignments.into_iter().map(Assignment::from).collect())
+    }
+
+    pub fn save_attestation(&self, attestation: &Attestation) -> Result<String, StoreErrorType> {
+        use super::schema::attestations::dsl::*;
+        let conn = &mut self.get_conn()?;
+
+        let new_attestation = NewAttestation {
+            process_id: &attestation.process_id,
+            epoch: attestation.epoch,
+            nonce: attestation.nonce,
+            hash_chain: &attestation.hash_chain,
+            root: &attestation.root,
+            leaf_count: attestation.leaf_count,
+            attestation_id: &attestation.attestation_id,
+            timestamp: attestation.timestamp,
+            frontier: serde_json::to_value(&attestation.frontier)?,
+        };
+
+        match diesel::insert_into(attestations)
+            .values(&new_attestation)
+            .on_conflict_do_nothing()
+            .execute(conn)
+        {
+            Ok(0) => Err(StoreErrorType::MessageExists(
+                "Attestation already saved".to_string(),
+            )),
+            Ok(_) => Ok("saved".to_string()),
+            Err(e) => Err(StoreErrorType::from(e)),
+        }
+    }
+
+    pub fn save_upload_receipt(&self, receipt: &UploadReceipt) -> Result<String, StoreErrorType> {
+        use super::schema::upload_receipts::dsl::*;
+        let conn = &mut self.get_conn()?;
+
+        diesel::insert_into(upload_receipts)
+            .values(NewUploadReceipt {
+                bundle_id: &receipt.id,
+                timestamp: receipt.timestamp as i64,
+                version: &receipt.version,
+                public_key: &receipt.public,
+                signature: &receipt.signature,
+                deadline_height: receipt.deadline_height as i64,
+            })
+            .on_conflict_do_nothing()
+            .execute(conn)?;
+        Ok("saved".to_string())
+    }
+
+    pub fn get_upload_receipt(&self, bundle_id_in: &str) -> Result<UploadReceipt, StoreErrorType> {
+        use super::schema::upload_receipts::dsl::*;
+        let conn = &mut self.get_read_conn()?;
+
+        let db_receipt: Option<DbUploadReceipt> = upload_receipts
+            .filter(bundle_id.eq(bundle_id_in))
+            .select(DbUploadReceipt::as_select())
+            .first(conn)
+            .optional()?;
+        match db_receipt {
+            Some(r) => Ok(UploadReceipt {
+                id: r.bundle_id,
+                timestamp: r.timestamp as u64,
+                version: r.version,
+                public: r.public_key,
+                signature: r.signature,
+                deadline_height: r.deadline_height as u64,
+            }),
+            None => Err(StoreErrorType::NotFound(
+                "Upload receipt not found".to_string(),
+            )),
+        }
+    }
+
+    pub fn get_attestations(
+        &self,
+        process_id_in: &str,
+        limit: i64,
+    ) -> Result<Vec<Attestation>, StoreErrorType> {
+        use super::schema::attestations::dsl::*;
+        let conn = &mut self.get_read_conn()?;
+
+        let db_attestations: Vec<DbAttestation> = attestations
+            .filter(process_id.eq(process_id_in))
+            .order((epoch.desc(), nonce.desc()))
+            .limit(limit)
+            .load(conn)?;
+        Ok(db_attestations
+            .into_iter()
+            .map(|a| Attestation {
+                process_id: a.process_id,
+                epoch: a.epoch,
+                nonce: a.nonce,
+                hash_chain: a.hash_chain,
+                root: a.root,
+                leaf_count: a.leaf_count,
+                attestation_id: a.attestation_id,
+                timestamp: a.timestamp,
+                frontier: a
+                    .frontier
+                    .and_then(|f| serde_json::from_value(f).ok())
+                    .unwrap_or_default(),
+            })
+            .collect())
+    }
+
+    pub fn get_due_crons(
+        &self,
+        now: i64,
+        limit: i64,
+    ) -> Result<Vec<CronDefinition>, StoreErrorType> {
+        use super::schema::crons::dsl::*;
+        let conn = &mut self.get_conn()?;
+
+        let db_crons: Vec<DbCron> = crons
+            .select(DbCron::as_select())
+            .filter(next_run.le(now))
+            .order(next_run.asc())
+            .limit(limit)
+            .load(conn)?;
+        cron_definitions(db_crons)
+    }
+
+    pub fn get_crons(&self, process_id_in: &str) -> Result<Vec<CronDefinition>, StoreErrorType> {
+        use super::schema::crons::dsl::*;
+        let conn = &mut self.get_read_conn()?;
+
+        let db_crons: Vec<DbCron> = crons
+            .select(DbCron::as_select())
+            .filter(process_id.eq(process_id_in))
+            .order(interval_ms.asc())
+            .load(conn)?;
+        cron_definitions(db_crons)
+    }
+
+    pub fn update_cron_next_run(
+        &self,
+        process_id_in: &str,
+        interval_in: &str,
+        next_run_in: i64,
+    ) -> Result<(), StoreErrorType> {
+        use super::schema::crons::dsl::*;
+        let conn = &mut self.get_conn()?;
+
+        diesel::update(
+            crons
+                .filter(process_id.eq(process_id_in))
+                .filter(cron_interval.eq(interval_in)),
+        )
+        .set(next_run.eq(next_run_in))
+        .execute(conn)?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl DataStore for StoreClient {
+    async fn save_process(
+        &self,
+        process: &Process,
+        bundle_in: &Bytes,
+    ) -> Result<String, StoreErrorType> {
+        let (process, bundle_in) = (process.clone(), bundle_in.clone());
+        self.blocking(move |store| store.save_process(&process, &bundle_in))
+            .await
+    }
+
+    async fn save_process_with_boot(
+        &self,
+        process: &Process,
+        bundle_in: &Bytes,
+        boot: &Message,
+        boot_bundle: &Bytes,
+    ) -> Result<String, StoreErrorType> {
+        let (process, bundle_in) = (process.clone(), bundle_in.clone());
+        let (boot, boot_bundle) = (boot.clone(), boot_bundle.clone());
+        self.blocking(move |store| {
+            store.save_process_with_boot(&process, &bundle_in, &boot, &boot_bundle)
+        })
+        .await
+    }
+
+    async fn get_process(&self, process_id_in: &str) -> Result<Process, StoreErrorType> {
+        let process_id_in = process_id_in.to_string();
+        self.blocking(move |store| store.get_process(&process_id_in))
+            .await
+    }
+
+    async fn get_process_wallet(&self, process_id_in: &str) -> Result<String, StoreErrorType> {
+        let process_id_in = process_id_in.to_string();
+        self.blocking(move |store| store.get_process_wallet(&process_id_in))
+            .await
+    }
+
+    async fn get_processes(
+        &self,
+        from: &Option<String>,
+        limit: &Option<i32>,
+        owner: &Option<String>,
+        module: &Option<String>,
+    ) -> Result<PaginatedProcesses, StoreErrorType> {
+        let (from, limit, owner, module) = (from.clone(), *limit, owner.clone(), module.clone());
+        self.blocking(move |store| store.get_processes(&from, &limit, &owner, &module))
+            .await
+    }
+
+    async fn check_existing_message(&self, message: &Message) -> Result<(), StoreErrorType> {
+        let message = message.clone();
+        self.blocking(move |store| store.check_existing_message(&message))
+            .await
+    }
+
+    async fn save_message(
+        &self,
+        message: &Message,
+        bundle_in: &Bytes,
+    ) -> Result<String, StoreErrorType> {
+        let (message, bundle_in) = (message.clone(), bundle_in.clone());
+        if let Some(group_commit) = &self.group_commit {
+            return group_commit.save(message, bundle_in).await;
+        }
+        self.blocking(move |store| store.save_message(&message, &bundle_in))
+            .await
+    }
+
+    async fn save_messages(
+        &self,
+        messages_in: &[(Message, Bytes)],
+    ) -> Result<String, StoreErrorType> {
+        let messages_in = messages_in.to_vec();
+        self.blocking(move |store| store.save_messages(&messages_in))
+            .await
+    }
+
+    async fn save_cron_message(
+        &self,
+        message: &Message,
+        bundle_in: &Bytes,
+        interval_in: &str,
+        next_run_in: i64,
+    ) -> Result<String, StoreErrorType> {
+        let (message, bundle_in) = (message.clone(), bundle_in.clone());
+        let interval_in = interval_in.to_string();
+        self.blocking(move |store| {
+            store.save_cron_message(&message, &bundle_in, &interval_in, next_run_in)
+        })
+        .await
+    }
+
+    async fn get_stored_bundle(&self, bundle_ref: &BundleRef) -> Result<Vec<u8>, StoreErrorType> {
+        let bundle_ref = bundle_ref.clone();
+        self.blocking(move |store| store.get_stored_bundle(&bundle_ref))
+            .await
+    }
+
+    async fn restore_bundle(
+        &self,
+        bundle_ref: &BundleRef,
+        bundle_in: &[u8],
+    ) -> Result<String, StoreErrorType> {
+        let (bundle_ref, bundle_in) = (bundle_ref.clone(), bundle_in.to_vec());
+        self.blocking(move |store| store.restore_bundle(&bundle_ref, &bundle_in))
+            .await
+    }
+
+    async fn get_messages(
+        &self,
+        process_id_in: &str,
+        query: &MessageQuery,
+    ) -> Result<PaginatedMessages, StoreErrorType> {
+        let (process_id_in, query) = (process_id_in.to_string(), query.clone());
+        self.blocking(move |store| store.get_messages(&process_id_in, &query))
+            .await
+    }
+
+    async fn get_messages_by_owner(
+        &self,
+        owner_address: &str,
+        from: &Option<String>,
+        limit: &Option<i32>,
+    ) -> Result<PaginatedMessages, StoreErrorType> {
+        let (owner_address, from, limit) = (owner_address.to_string(), from.clone(), *limit);
+        self.blocking(move |store| store.get_messages_by_owner(&owner_address, &from, &limit))
+            .await
+    }
+
+    async fn get_message(&self, tx_id: &str) -> Result<Message, StoreErrorType> {
+        let tx_id = tx_id.to_string();
+        self.blocking(move |store| store.get_message(&tx_id)).await
+    }
+
+    async fn get_message_by_nonce(
+        &self,
+        process_id_in: &str,
+        epoch_in: &i32,
+        nonce_in: &i32,
+    ) -> Result<Message, StoreErrorType> {
+        let (process_id_in, epoch_in, nonce_in) = (process_id_in.to_string(), *epoch_in, *nonce_in);
+        self.blocking(move |store| store.get_message_by_nonce(&process_id_in, &epoch_in, &nonce_in))
+            .await
+    }
+
+    async fn get_messages_by_nonce_range(
+        &self,
+        process_id_in: &str,
+        from_nonce_in: i32,
+        to_nonce_in: Option<i32>,
+        limit: i64,
+    ) -> Result<PaginatedMessages, StoreErrorType> {
+        let process_id_in = process_id_in.to_string();
+        self.blocking(move |store| {
+            store.get_messages_by_nonce_range(&process_id_in, from_nonce_in, to_nonce_in, limit)
+        })
+        .await
+    }
+
+    async fn get_latest_message(
+        &self,
+        process_id_in: &str,
+    ) -> Result<Option<Message>, StoreErrorType> {
+        let process_id_in = process_id_in.to_string();
+        self.blocking(move |store| store.get_latest_message(&process_id_in))
+            .await
+    }
+
+    async fn get_process_sequence(
+        &self,
+        process_id_in: &str,
+    ) -> Result<Option<ProcessSequence>, StoreErrorType> {
+        let process_id_in = process_id_in.to_string();
+        self.blocking(move |store| store.get_process_sequence(&process_id_in))
+            .await
+    }
+
+    async fn acquire_lease(
+        &self,
+        process_id_in: &str,
+        holder_in: &str,
+        ttl_ms: u64,
+    ) -> Result<String, StoreErrorType> {
+        let process_id_in = process_id_in.to_string();
+        let holder_in = holder_in.to_string();
+        self.blocking(move |store| store.acquire_lease(&process_id_in, &holder_in, ttl_ms))
+            .await
+    }
+
+    async fn get_lease_holder(
+        &self,
+        process_id_in: &str,
+    ) -> Result<Option<String>, StoreErrorType> {
+        let process_id_in = process_id_in.to_string();
+        self.blocking(move |store| store.get_lease_holder(&process_id_in))
+            .await
+    }
+
+    async fn release_leases(&self, holder_in: &str) -> Result<(), StoreErrorType> {
+        let holder_in = holder_in.to_string();
+        self.blocking(move |store| store.release_leases(&holder_in))
+            .await
+    }
+
+    async fn get_read_nonce(&self, process_id_in: &str) -> Result<Option<i32>, StoreErrorType> {
+        let process_id_in = process_id_in.to_string();
+        self.blocking(move |store| store.get_read_nonce(&process_id_in))
+            .await
+    }
+
+    async fn get_message_bundles(
+        &self,
+        process_id_in: &str,
+        after: &Option<(i32, i32)>,
+        limit: i64,
+    ) -> Result<MessageBundles, StoreErrorType> {
+        let (process_id_in, after) = (process_id_in.to_string(), *after);
+        self.blocking(move |store| store.get_message_bundles(&process_id_in, &after, limit))
+            .await
+    }
+
+    async fn get_idle_processes(
+        &self,
+        before: i64,
+        limit: i64,
+    ) -> Result<Vec<String>, StoreErrorType> {
+        self.blocking(move |store| store.get_idle_processes(before, limit))
+            .await
+    }
+
+    async fn get_message_count(&self, process_id_in: &str) -> Result<MessageCount, StoreErrorType> {
+        let process_id_in = process_id_in.to_string();
+        self.blocking(move |store| store.get_message_count(&process_id_in))
+            .await
+    }
+
+    async fn save_process_scheduler(
+        &self,
+        process_scheduler: &ProcessScheduler,
+    ) -> Result<String, StoreErrorType> {
+        let process_scheduler = process_scheduler.clone();
+        self.blocking(move |store| store.save_process_scheduler(&process_scheduler))
+            .await
+    }
+
+    async fn get_process_scheduler(
+        &self,
+        process_id_in: &str,
+    ) -> Result<ProcessScheduler, StoreErrorType> {
+        let process_id_in = process_id_in.to_string();
+        self.blocking(move |store| store.get_process_scheduler(&process_id_in))
+            .await
+    }
+
+    async fn move_process_scheduler(
+        &self,
+        process_id_in: &str,
+        from_row_id: &i32,
+        to_row_id: &i32,
+    ) -> Result<String, StoreErrorType> {
+        let (process_id_in, from_row_id, to_row_id) =
+            (process_id_in.to_string(), *from_row_id, *to_row_id);
+        self.blocking(move |store| {
+            store.move_process_scheduler(&process_id_in, &from_row_id, &to_row_id)
+        })
+        .await
+    }
+
+    async fn save_assignment_decision(
+        &self,
+        decision: &AssignmentDecision,
+    ) -> Result<String, StoreErrorType> {
+        let decision = decision.clone();
+        self.blocking(move |store| store.save_assignment_decision(&decision))
+            .await
+    }
+
+    async fn get_assignment_decisions(
+        &self,
+        process_id_in: &Option<String>,
+        limit: i64,
+    ) -> Result<Vec<AssignmentDecision>, StoreErrorType> {
+        let process_id_in = process_id_in.clone();
+        self.blocking(move |store| store.get_assignment_decisions(&process_id_in, limit))
+            .await
+    }
+
+    async fn save_scheduler(&self, scheduler: &Scheduler) -> Result<String, StoreErrorType> {
+        let scheduler = scheduler.clone();
+        self.blocking(move |store| store.save_scheduler(&scheduler))
+            .await
+    }
+
+    async fn update_scheduler(&self, scheduler: &Scheduler) -> Result<String, StoreErrorType> {
+        let scheduler = scheduler.clone();
+        self.blocking(move |store| store.update_scheduler(&scheduler))
+            .await
+    }
+
+    async fn get_scheduler(&self, row_id_in: &i32) -> Result<Scheduler, StoreErrorType> {
+        let row_id_in = *row_id_in;
+        self.blocking(move |store| store.get_scheduler(&row_id_in))
+            .await
+    }
+
+    async fn get_scheduler_by_url(&self, url_in: &str) -> Result<Scheduler, StoreErrorType> {
+        let url_in = url_in.to_string();
+        self.blocking(move |store| store.get_scheduler_by_url(&url_in))
+            .await
+    }
+
+    async fn get_all_schedulers(&self) -> Result<Vec<Scheduler>, StoreErrorType> {
+        self.blocking(|store| store.get_all_schedulers()).await
+    }
+
+    async fn get_store_stats(&self) -> Result<StoreStats, StoreErrorType> {
+        self.blocking(|store| store.get_store_stats()).await
+    }
+
+    async fn get_storage_size(&self) -> Result<i64, StoreErrorType> {
+        self.blocking(|store| store.get_storage_size()).await
+    }
+
+    async fn get_tierable_processes(
+        &self,
+        before: i64,
+        min_messages: i64,
+        limit: i64,
+    ) -> Result<Vec<String>, StoreErrorType> {
+        self.blocking(move |store| store.get_tierable_processes(before, min_messages, limit))
+            .await
+    }
+
+    async fn tier_process(
+        &self,
+        process_id_in: &str,
+        before: i64,
+        segment_size: i64,
+    ) -> Result<Option<ColdSegment>, StoreErrorType> {
+        let process_id_in = process_id_in.to_string();
+        self.blocking(move |store| store.tier_process(&process_id_in, before, segment_size))
+            .await
+    }
+
+    async fn index_cold_segments(&self, limit: i64) -> Result<i64, StoreErrorType> {
+        self.blocking(move |store| store.index_cold_segments(limit))
+            .await
+    }
+
+    async fn get_prunable_messages(
+        &self,
+        before: Option<i64>,
+        keep_latest: Option<i64>,
+        after: i32,
+        limit: i64,
+    ) -> Result<Vec<PrunableMessage>, StoreErrorType> {
+        self.blocking(move |store| store.get_prunable_messages(before, keep_latest, after, limit))
+            .await
+    }
+
+    async fn prune_messages(
+        &self,
+        messages: &[PrunableMessage],
+        delete_rows: bool,
+    ) -> Result<Vec<String>, StoreErrorType> {
+        let messages = messages.to_vec();
+        self.blocking(move |store| store.prune_messages(&messages, delete_rows))
+            .await
+    }
+
+    async fn save_api_token(&self, api_token: &ApiToken) -> Result<String, StoreErrorType> {
+        let api_token = api_token.clone();
+        self.blocking(move |store| store.save_api_token(&api_token))
+            .await
+    }
+
+    async fn get_api_token(&self, token_hash_in: &str) -> Result<ApiToken, StoreErrorType> {
+        let token_hash_in = token_hash_in.to_string();
+        self.blocking(move |store| store.get_api_token(&token_hash_in))
+            .await
+    }
+
+    async fn delete_api_token(&self, token_hash_in: &str) -> Result<String, StoreErrorType> {
+        let token_hash_in = token_hash_in.to_string();
+        self.blocking(move |store| store.delete_api_token(&token_hash_in))
+            .await
+    }
+
+    async fn ping(&self) -> Result<(), StoreErrorType> {
+        self.blocking(|store| store.ping()).await
+    }
+
+    async fn get_outbox(&self, limit: i64) -> Result<Vec<OutboxEvent>, StoreErrorType> {
+        self.blocking(move |store| store.get_outbox(limit)).await
+    }
 
-        let db_process_result: Result<Option<DbProcessScheduler>, DieselError> = process_schedulers
-            .filter(process_id.eq(process_id_in))
-            .first(conn)
-            .optional();
+    async fn delete_outbox(&self, row_ids: &[i32]) -> Result<(), StoreErrorType> {
+        let row_ids = row_ids.to_vec();
+        self.blocking(move |store| store.delete_outbox(&row_ids))
+            .await
+    }
 
-        match db_process_result {
-            Ok(Some(db_process_scheduler)) => {
-                let process_scheduler: ProcessScheduler = ProcessScheduler {
-                    row_id: Some(db_process_scheduler.row_id),
-                    process_id: db_process_scheduler.process_id,
-                    scheduler_row_id: db_process_scheduler.scheduler_row_id,
-                };
-                Ok(process_scheduler)
-            }
-            Ok(None) => Err(StoreErrorType::NotFound(
-                "Process scheduler not found".to_string(),
-            )),
-            Err(e) => Err(StoreErrorType::from(e)),
-        }
+    async fn fail_outbox(
+        &self,
+        row_id: i32,
+        max_attempts: u32,
+        error: &str,
+    ) -> Result<bool, StoreErrorType> {
+        let error = error.to_string();
+        self.blocking(move |store| store.fail_outbox(row_id, max_attempts, &error))
+            .await
     }
 
-    fn save_scheduler(&self, scheduler: &Scheduler) -> Result<String, StoreErrorType> {
-        use super::schema::schedulers::dsl::*;
-        let conn = &mut self.get_conn()?;
+    async fn save_undelivered(
+        &self,
+        item_id: &str,
+        bundle: &[u8],
+        error: &str,
+    ) -> Result<(), StoreErrorType> {
+        let (item_id, bundle, error) = (item_id.to_string(), bundle.to_vec(), error.to_string());
+        self.blocking(move |store| store.save_undelivered(&item_id, &bundle, &error))
+            .await
+    }
 
-        let new_scheduler = NewScheduler {
-            url: &scheduler.url,
-            process_count: &scheduler.process_count,
-        };
+    async fn get_undelivered(&self, limit: i64) -> Result<Vec<(String, Vec<u8>)>, StoreErrorType> {
+        self.blocking(move |store| store.get_undelivered(limit))
+            .await
+    }
 
-        match diesel::insert_into(schedulers)
-            .values(&new_scheduler)
-            .on_conflict(url)
-            .do_nothing()
-            .execute(conn)
-        {
-            Ok(_) => Ok("saved".to_string()),
-            Err(e) => Err(StoreErrorType::from(e)),
-        }
+    async fn delete_undelivered(&self, item_id: &str) -> Result<(), StoreErrorType> {
+        let item_id = item_id.to_string();
+        self.blocking(move |store| store.delete_undelivered(&item_id))
+            .await
     }
 
-    fn update_scheduler(&self, scheduler: &Scheduler) -> Result<String, StoreErrorType> {
-        use super::schema::schedulers::dsl::*;
-        let conn = &mut self.get_conn()?;
+    async fn save_checkpoint(&self, checkpoint: &Checkpoint) -> Result<String, StoreErrorType> {
+        let checkpoint = checkpoint.clone();
+        self.blocking(move |store| store.save_checkpoint(&checkpoint))
+            .await
+    }
 
-        // Ensure scheduler.row_id is Some(value) before calling this function
-        match diesel::update(schedulers.filter(row_id.eq(scheduler.row_id.unwrap())))
-            .set((
-                process_count.eq(scheduler.process_count),
-                url.eq(&scheduler.url),
-            ))
-            .execute(conn)
-        {
-            Ok(_) => Ok("updated".to_string()),
-            Err(e) => Err(StoreErrorType::from(e)),
-        }
+    async fn get_checkpoints(
+        &self,
+        process_id_in: &str,
+        before: &Option<(i32, i32)>,
+        limit: i64,
+    ) -> Result<Vec<Checkpoint>, StoreErrorType> {
+        let (process_id_in, before) = (process_id_in.to_string(), *before);
+        self.blocking(move |store| store.get_checkpoints(&process_id_in, &before, limit))
+            .await
     }
 
-    fn get_scheduler(&self, row_id_in: &i32) -> Result<Scheduler, StoreErrorType> {
-        use super::schema::schedulers::dsl::*;
-        let conn = &mut self.get_conn()?;
+    async fn get_assignments(
+        &self,
+        process_id_in: &str,
+        after: &Option<(i32, i32)>,
+        limit: i64,
+    ) -> Result<Vec<Assignment>, StoreErrorType> {
+        let (process_id_in, after) = (process_id_in.to_string(), *after);
+        self.blocking(move |store| store.get_assignments(&process_id_in, &after, limit))
+            .await
+    }
 
-        let db_scheduler_result: Result<Option<DbScheduler>, DieselError> = schedulers
-            .filter(row_id.eq(row_id_in))
-            .first(conn)
-            .optional();
+    async fn find_assignments(&self, id_in: &str) -> Result<Vec<Assignment>, StoreErrorType> {
+        let id_in = id_in.to_string();
+        self.blocking(move |store| store.find_assignments(&id_in))
+            .await
+    }
 
-        match db_scheduler_result {
-            Ok(Some(db_scheduler)) => {
-                let scheduler: Scheduler = Scheduler {
-                    row_id: Some(db_scheduler.row_id),
-                    url: db_scheduler.url,
-                    process_count: db_scheduler.process_count,
-                };
-                Ok(scheduler)
-            }
-            Ok(None) => Err(StoreErrorType::NotFound("Scheduler not found".to_string())),
-            Err(e) => Err(StoreErrorType::from(e)),
-        }
+    async fn save_attestation(&self, attestation: &Attestation) -> Result<String, StoreErrorType> {
+        let attestation = attestation.clone();
+        self.blocking(move |store| store.save_attestation(&attestation))
+            .await
     }
 
-    fn get_scheduler_by_url(&self, url_in: &String) -> Result<Scheduler, StoreErrorType> {
-        use super::schema::schedulers::dsl::*;
-        let conn = &mut self.get_conn()?;
+    async fn save_upload_receipt(&self, receipt: &UploadReceipt) -> Result<String, StoreErrorType> {
+        let receipt = receipt.clone();
+        self.blocking(move |store| store.save_upload_receipt(&receipt))
+            .await
+    }
 
-        let db_scheduler_result: Result<Option<DbScheduler>, DieselError> =
-            schedulers.filter(url.eq(url_in)).first(conn).optional();
+    async fn get_upload_receipt(
+        &self,
+        bundle_id_in: &str,
+    ) -> Result<UploadReceipt, StoreErrorType> {
+        let bundle_id_in = bundle_id_in.to_string();
+        self.blocking(move |store| store.get_upload_receipt(&bundle_id_in))
+            .await
+    }
 
-        match db_scheduler_result {
-            Ok(Some(db_scheduler)) => {
-                let scheduler: Scheduler = Scheduler {
-                    row_id: Some(db_scheduler.row_id),
-                    url: db_scheduler.url,
-                    process_count: db_scheduler.process_count,
-                };
-                Ok(scheduler)
-            }
-            Ok(None) => Err(StoreErrorType::NotFound("Scheduler not found".to_string())),
-            Err(e) => Err(StoreErrorType::from(e)),
-        }
+    async fn get_attestations(
+        &self,
+        process_id_in: &str,
+        limit: i64,
+    ) -> Result<Vec<Attestation>, StoreErrorType> {
+        let process_id_in = process_id_in.to_string();
+        self.blocking(move |store| store.get_attestations(&process_id_in, limit))
+            .await
     }
 
-    fn get_all_schedulers(&self) -> Result<Vec<Scheduler>, StoreErrorType> {
-        use super::schema::schedulers::dsl::*;
-        let conn = &mut self.get_conn()?;
+    async fn get_due_crons(
+        &self,
+        now: i64,
+        limit: i64,
+    ) -> Result<Vec<CronDefinition>, StoreErrorType> {
+        self.blocking(move |store| store.get_due_crons(now, limit))
+            .await
+    }
 
-        match schedulers.order(row_id.asc()).load::<DbScheduler>(conn) {
-            Ok(db_schedulers) => {
-                let schedulers_out: Vec<Scheduler> = db_schedulers
-                    .into_iter()
-                    .map(|db_scheduler| Scheduler {
-                        row_id: Some(db_scheduler.row_id),
-                        url: db_scheduler.url,
-                        process_count: db_scheduler.process_count,
-                    })
-                    .collect();
-                Ok(schedulers_out)
-            }
-            Err(e) => Err(StoreErrorType::from(e)),
-        }
+    async fn get_crons(&self, process_id_in: &str) -> Result<Vec<CronDefinition>, StoreErrorType> {
+        let process_id_in = process_id_in.to_string();
+        self.blocking(move |store| store.get_crons(&process_id_in))
+            .await
+    }
+
+    async fn update_cron_next_run(
+        &self,
+        process_id_in: &str,
+        interval_in: &str,
+        next_run_in: i64,
+    ) -> Result<(), StoreErrorType> {
+        let (process_id_in, interval_in) = (process_id_in.to_string(), interval_in.to_string());
+        self.blocking(move |store| {
+            store.update_cron_next_run(&process_id_in, &interval_in, next_run_in)
+        })
+        .await
     }
 }
 
@@ -484,6 +3629,8 @@ pub struct DbProcess {
     pub process_id: String,
     pub process_data: serde_json::Value,
     pub bundle: Vec<u8>,
+    pub bundle_checksum: Option<String>,
+    pub bundle_location: Option<String>,
 }
 
 #[derive(Queryable, Selectable)]
@@ -491,6 +3638,8 @@ pub struct DbProcess {
 #[diesel(check_for_backend(diesel::pg::Pg))]
 pub struct DbMessage {
     pub row_id: i32,
+    // loaded with the row, the message carries it
+    #[allow(dead_code)]
     pub process_id: String,
     pub message_id: String,
     pub assignment_id: Option<String>,
@@ -500,6 +3649,75 @@ pub struct DbMessage {
     pub timestamp: i64,
     pub bundle: Vec<u8>,
     pub hash_chain: String,
+    pub bundle_checksum: Option<String>,
+    pub bundle_location: Option<String>,
+}
+
+#[derive(Queryable, Selectable)]
+#[diesel(table_name = super::schema::cold_segments)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct DbColdSegment {
+    pub from_timestamp: i64,
+    pub to_timestamp: i64,
+    pub segment_key: String,
+    pub bundles_key: Option<String>,
+}
+
+fn rows_key(key: &str) -> String {
+    format!("{}.rows.parquet", key)
+}
+
+fn bundles_key(key: &str) -> String {
+    format!("{}.bundles.parquet", key)
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = super::schema::cold_segments)]
+pub struct NewColdSegment<'a> {
+    pub process_id: &'a str,
+    pub from_epoch: i32,
+    pub from_nonce: i32,
+    pub to_epoch: i32,
+    pub to_nonce: i32,
+    pub from_timestamp: i64,
+    pub to_timestamp: i64,
+    pub message_count: i32,
+    pub segment_key: &'a str,
+    pub segment_size: i64,
+    pub bundles_key: Option<&'a str>,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = super::schema::cold_messages)]
+pub struct NewColdMessage<'a> {
+    pub segment_row_id: i32,
+    pub position: i32,
+    pub process_id: &'a str,
+    pub message_id: &'a str,
+    pub assignment_id: Option<&'a str>,
+    pub epoch: i32,
+    pub nonce: i32,
+    pub carries_message: bool,
+}
+
+#[derive(QueryableByName)]
+pub struct DbPrunableMessage {
+    #[diesel(sql_type = Int4)]
+    pub row_id: i32,
+    #[diesel(sql_type = Text)]
+    pub assignment_id: String,
+    #[diesel(sql_type = BigInt)]
+    pub stored_size: i64,
+    #[diesel(sql_type = Nullable<Text>)]
+    pub bundle_location: Option<String>,
+}
+
+#[derive(QueryableByName)]
+pub struct DbLease {
+    #[diesel(sql_type = Text)]
+    pub holder: String,
+    #[diesel(sql_type = Bool)]
+    pub live: bool,
 }
 
 #[derive(Insertable)]
@@ -514,6 +3732,8 @@ pub struct NewMessage<'a> {
     pub nonce: &'a i32,
     pub timestamp: &'a i64,
     pub hash_chain: &'a str,
+    pub bundle_checksum: String,
+    pub bundle_location: Option<String>,
 }
 
 #[derive(Insertable)]
@@ -522,6 +3742,8 @@ pub struct NewProcess<'a> {
     pub process_id: &'a str,
     pub process_data: serde_json::Value,
     pub bundle: &'a [u8],
+    pub bundle_checksum: String,
+    pub bundle_location: Option<String>,
 }
 
 #[derive(Queryable, Selectable)]
@@ -555,3 +3777,246 @@ pub struct NewProcessScheduler<'a> {
     pub process_id: &'a str,
     pub scheduler_row_id: &'a i32,
 }
+
+#[derive(Queryable, Selectable)]
+#[diesel(table_name = super::schema::api_tokens)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct DbApiToken {
+    pub row_id: i32,
+    pub token_hash: String,
+    pub owner: Option<String>,
+    pub process_ids: serde_json::Value,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = super::schema::api_tokens)]
+pub struct NewApiToken<'a> {
+    pub token_hash: &'a str,
+    pub owner: Option<&'a str>,
+    pub process_ids: serde_json::Value,
+}
+
+#[derive(Queryable, Selectable)]
+#[diesel(table_name = super::schema::checkpoints)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct DbCheckpoint {
+    // loaded with the row, never read
+    #[allow(dead_code)]
+    pub row_id: i32,
+    pub process_id: String,
+    pub epoch: i32,
+    pub nonce: i32,
+    pub checkpoint_id: String,
+    pub cu: Option<String>,
+    pub timestamp: i64,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = super::schema::checkpoints)]
+pub struct NewCheckpoint<'a> {
+    pub process_id: &'a str,
+    pub epoch: i32,
+    pub nonce: i32,
+    pub checkpoint_id: &'a str,
+    pub cu: Option<&'a str>,
+    pub timestamp: i64,
+}
+
+#[derive(Queryable, Selectable)]
+#[diesel(table_name = super::schema::attestations)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct DbAttestation {
+    // loaded with the row, never read
+    #[allow(dead_code)]
+    pub row_id: i32,
+    pub process_id: String,
+    pub epoch: i32,
+    pub nonce: i32,
+    pub hash_chain: String,
+    pub root: String,
+    pub leaf_count: i64,
+    pub attestation_id: String,
+    pub timestamp: i64,
+    pub frontier: Option<serde_json::Value>,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = super::schema::attestations)]
+pub struct NewAttestation<'a> {
+    pub process_id: &'a str,
+    pub epoch: i32,
+    pub nonce: i32,
+    pub hash_chain: &'a str,
+    pub root: &'a str,
+    pub leaf_count: i64,
+    pub attestation_id: &'a str,
+    pub timestamp: i64,
+    pub frontier: serde_json::Value,
+}
+
+#[derive(Queryable, Selectable)]
+#[diesel(table_name = super::schema::assignments)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct DbAssignment {
+    pub process_id: String,
+    pub assignment_id: String,
+    pub message_id: String,
+    pub epoch: i32,
+    pub nonce: i32,
+    pub timestamp: i64,
+    pub hash_chain: String,
+    pub block_height: Option<String>,
+}
+
+impl From<DbAssignment> for Assignment {
+    fn from(a: DbAssignment) -> Self {
+        Assignment {
+            process_id: a.process_id,
+            assignment_id: a.assignment_id,
+            message_id: a.message_id,
+            epoch: a.epoch,
+            nonce: a.nonce,
+            timestamp: a.timestamp,
+            hash_chain: a.hash_chain,
+            block_height: a.block_height,
+        }
+    }
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = super::schema::assignments)]
+pub struct NewAssignment<'a> {
+    pub process_id: &'a str,
+    pub assignment_id: &'a str,
+    pub message_id: &'a str,
+    pub epoch: i32,
+    pub nonce: i32,
+    pub timestamp: i64,
+    pub hash_chain: &'a str,
+    pub block_height: Option<&'a str>,
+}
+
+#[derive(Queryable, Selectable)]
+#[diesel(table_name = super::schema::upload_receipts)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct DbUploadReceipt {
+    pub bundle_id: String,
+    pub timestamp: i64,
+    pub version: String,
+    pub public_key: String,
+    pub signature: String,
+    pub deadline_height: i64,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = super::schema::upload_receipts)]
+pub struct NewUploadReceipt<'a> {
+    pub bundle_id: &'a str,
+    pub timestamp: i64,
+    pub version: &'a str,
+    pub public_key: &'a str,
+    pub signature: &'a str,
+    pub deadline_height: i64,
+}
+
+#[derive(Queryable, Selectable)]
+#[diesel(table_name = super::schema::assignment_decisions)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct DbAssignmentDecision {
+    pub process_id: String,
+    pub scheduler_url: String,
+    pub strategy: String,
+    pub region: Option<String>,
+    pub candidates: serde_json::Value,
+    pub timestamp: i64,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = super::schema::assignment_decisions)]
+pub struct NewAssignmentDecision<'a> {
+    pub process_id: &'a str,
+    pub scheduler_url: &'a str,
+    pub strategy: &'a str,
+    pub region: Option<&'a str>,
+    pub candidates: serde_json::Value,
+    pub timestamp: i64,
+}
+
+#[derive(Queryable, Selectable)]
+#[diesel(table_name = super::schema::crons)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct DbCron {
+    pub process_id: String,
+    pub cron_interval: String,
+    pub interval_ms: i64,
+    pub tags: serde_json::Value,
+    pub next_run: i64,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = super::schema::crons)]
+pub struct NewCron<'a> {
+    pub process_id: &'a str,
+    pub cron_interval: &'a str,
+    pub interval_ms: i64,
+    pub tags: serde_json::Value,
+    pub next_run: i64,
+}
+
+fn cron_definitions(db_crons: Vec<DbCron>) -> Result<Vec<CronDefinition>, StoreErrorType> {
+    let mut definitions = vec![];
+    for c in db_crons.into_iter() {
+        definitions.push(CronDefinition {
+            process_id: c.process_id,
+            interval: c.cron_interval,
+            interval_ms: c.interval_ms,
+            tags: serde_json::from_value(c.tags)?,
+            next_run: c.next_run,
+        });
+    }
+    Ok(definitions)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_window_bounds() {
+        let asc = window_bounds(Some(100), Some(200), Some(150), None, &SortOrder::Asc);
+        assert_eq!(asc, (Some(150), Some(200)));
+        let asc = window_bounds(Some(100), None, None, None, &SortOrder::Asc);
+        assert_eq!(asc, (Some(101), None));
+        let desc = window_bounds(Some(200), Some(100), None, Some(150), &SortOrder::Desc);
+        assert_eq!(desc, (Some(100), Some(150)));
+        assert_eq!(
+            window_bounds(None, None, None, None, &SortOrder::Desc),
+            (None, None)
+        );
+    }
+
+    #[test]
+    fn test_gather() {
+        let (queue, pending) = mpsc::channel();
+        for i in 0..5 {
+            queue.send(i).unwrap();
+        }
+        let window = Duration::from_millis(20);
+        assert_eq!(gather(&pending, window, 3), Some(vec![0, 1, 2]));
+        assert_eq!(gather(&pending, window, 3), Some(vec![3, 4]));
+
+        queue.send(5).unwrap();
+        drop(queue);
+        assert_eq!(gather(&pending, window, 3), Some(vec![5]));
+        assert_eq!(gather(&pending, window, 3), None);
+    }
+
+    #[test]
+    fn test_is_newer_version() {
+        assert!(is_newer_version("0.2.0", "0.1.9"));
+        assert!(is_newer_version("0.1.10", "0.1.9"));
+        assert!(!is_newer_version("0.1.0", "0.1.0"));
+        assert!(!is_newer_version("0.1.0-beta", "0.1.0"));
+        assert!(!is_newer_version("0.1.0", "1.0.0"));
+    }
+}
@@ -0,0 +1,45 @@
+use openssl::ssl::{SslAcceptor, SslAcceptorBuilder, SslFiletype, SslMethod, SslVerifyMode};
+use openssl::x509::X509Name;
+
+use crate::domain::core::dal::Config;
+
+/*
+    With TLS_CERT_PATH and TLS_KEY_PATH the listener serves
+    https itself, HTTP/2 and HTTP/1.1 are negotiated with
+    ALPN. With TLS_CLIENT_CA_PATH as well every client has
+    to present a certificate signed by that CA, the way a
+    scheduler only the router should reach is locked down.
+*/
+pub fn server_tls(config: &dyn Config) -> Result<Option<SslAcceptorBuilder>, String> {
+    let (cert_path, key_path) = match (config.tls_cert_path(), config.tls_key_path()) {
+        (Some(cert_path), Some(key_path)) => (cert_path, key_path),
+        (None, None) => return Ok(None),
+        _ => return Err("TLS_CERT_PATH and TLS_KEY_PATH must be set together".to_string()),
+    };
+
+    let mut builder =
+        SslAcceptor::mozilla_intermediate_v5(SslMethod::tls()).map_err(|e| format!("{}", e))?;
+    builder
+        .set_certificate_chain_file(&cert_path)
+        .map_err(|e| format!("TLS_CERT_PATH {}: {}", cert_path, e))?;
+    builder
+        .set_private_key_file(&key_path, SslFiletype::PEM)
+        .map_err(|e| format!("TLS_KEY_PATH {}: {}", key_path, e))?;
+    builder.check_private_key().map_err(|e| {
+        format!(
+            "TLS_KEY_PATH {} does not match the certificate: {}",
+            key_path, e
+        )
+    })?;
+
+    if let Some(ca_path) = config.tls_client_ca_path() {
+        builder
+            .set_ca_file(&ca_path)
+            .map_err(|e| format!("TLS_CLIENT_CA_PATH {}: {}", ca_path, e))?;
+        let names = X509Name::load_client_ca_file(&ca_path)
+            .map_err(|e| format!("TLS_CLIENT_CA_PATH {}: {}", ca_path, e))?;
+        builder.set_client_ca_list(names);
+        builder.set_verify(SslVerifyMode::PEER | SslVerifyMode::FAIL_IF_NO_PEER_CERT);
+    }
+    Ok(Some(builder))
+}
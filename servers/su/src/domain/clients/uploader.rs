@@ -1,28 +1,55 @@
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
 use std::sync::Arc;
 
-use reqwest::{Client, Url};
-
-extern crate serde;
-use serde::{Deserialize, Serialize};
+use async_trait::async_trait;
+use bytes::Bytes;
+use reqwest::{Client, Response, StatusCode, Url};
+use serde::Deserialize;
 
 use tokio::spawn;
 use tokio::time::{sleep, Duration};
 
-use crate::domain::core::dal::{Uploader, UploaderErrorType};
+use crate::domain::core::breaker::CircuitBreaker;
+use crate::domain::core::bytes::DataItem;
+use crate::domain::core::dal::{
+    DataStore, DomainEvent, EventBus, Gateway, UploadReceipt, Uploader, UploaderErrorType,
+};
+use crate::domain::core::deadline::Deadline;
 use crate::domain::Log;
 
+// upload attempts of a bundle before it is given up
+const MAX_ATTEMPTS: u32 = 100;
+/*
+    how long a bundle may wait for the upload breaker to
+    close, counted across all of its attempts
+*/
+const MAX_BREAKER_WAIT: Duration = Duration::from_secs(60 * 60);
+
+// what both uploaders are handed, UPLOAD_MODE picks one
+pub struct UploadDeps {
+    pub logger: Arc<dyn Log>,
+    pub events: Arc<EventBus>,
+    pub data_store: Arc<dyn DataStore>,
+    pub gateway: Arc<dyn Gateway>,
+    pub client: Client,
+    pub breaker: Arc<CircuitBreaker>,
+    pub timeout: Duration,
+}
+
 pub struct UploaderClient {
     node_url: Url,
     logger: Arc<dyn Log>,
-}
-
-#[derive(Debug, Deserialize, Serialize)]
-struct IrysResponse {
-    id: String,
-    timestamp: u64,
-    version: String,
-    public: String,
-    signature: String,
+    events: Arc<EventBus>,
+    data_store: Arc<dyn DataStore>,
+    gateway: Arc<dyn Gateway>,
+    pending: Arc<AtomicUsize>,
+    given_up: Arc<AtomicU64>,
+    client: Client,
+    breaker: Arc<CircuitBreaker>,
+    timeout: Duration,
+    chunked_above: usize,
+    chunk_size: usize,
 }
 
 impl From<reqwest::Error> for UploaderErrorType {
@@ -38,7 +65,21 @@ impl From<serde_json::Error> for UploaderErrorType {
 }
 
 impl UploaderClient {
-    pub fn new(node_url: &str, logger: Arc<dyn Log>) -> Result<Self, UploaderErrorType> {
+    pub fn new(
+        node_url: &str,
+        deps: UploadDeps,
+        chunked_above: usize,
+        chunk_size: usize,
+    ) -> Result<Self, UploaderErrorType> {
+        let UploadDeps {
+            logger,
+            events,
+            data_store,
+            gateway,
+            client,
+            breaker,
+            timeout,
+        } = deps;
         let url = match Url::parse(node_url) {
             Ok(u) => u,
             Err(e) => return Err(UploaderErrorType::UploadError(format!("{}", e))),
@@ -47,51 +88,310 @@ impl UploaderClient {
         Ok(UploaderClient {
             node_url: url,
             logger,
+            events,
+            data_store,
+            gateway,
+            pending: Arc::new(AtomicUsize::new(0)),
+            given_up: Arc::new(AtomicU64::new(0)),
+            client,
+            breaker,
+            timeout,
+            chunked_above,
+            chunk_size: chunk_size.max(1),
         })
     }
 }
 
+#[derive(Deserialize)]
+struct ChunkSession {
+    id: String,
+    min: usize,
+    max: usize,
+}
+
+/*
+    An item over UPLOAD_CHUNKED_ABOVE_BYTES goes through the
+    chunk api of the upload node instead of one request, a
+    session is opened, the item posted in pieces at their
+    offsets and the session finished, which answers with
+    the usual receipt. A failed attempt keeps the session
+    and only sends the chunks the node does not have yet.
+*/
+struct ChunkedUpload {
+    node_url: Url,
+    chunk_size: usize,
+    session: Option<String>,
+    sent: HashSet<usize>,
+}
+
+impl ChunkedUpload {
+    fn new(node_url: Url, chunk_size: usize) -> Self {
+        ChunkedUpload {
+            node_url,
+            chunk_size,
+            session: None,
+            sent: HashSet::new(),
+        }
+    }
+
+    fn url(&self, path: &str) -> Url {
+        self.node_url
+            .join(&format!("chunks/arweave/{}", path))
+            .expect("Failed to join URL")
+    }
+
+    // the response of the step that failed, or of finishing the session
+    async fn send(
+        &mut self,
+        client: &Client,
+        data: &Bytes,
+        timeout: Duration,
+    ) -> Result<Response, reqwest::Error> {
+        let session = match &self.session {
+            Some(session) => session.clone(),
+            None => {
+                let response = client
+                    .get(self.url("-1/-1"))
+                    .timeout(timeout)
+                    .send()
+                    .await?;
+                if !response.status().is_success() {
+                    return Ok(response);
+                }
+                let created: ChunkSession = response.json().await?;
+                // the node bounds the size of a chunk
+                if created.min <= created.max {
+                    self.chunk_size = self.chunk_size.clamp(created.min, created.max);
+                }
+                self.session = Some(created.id.clone());
+                created.id
+            }
+        };
+
+        for offset in (0..data.len()).step_by(self.chunk_size) {
+            if self.sent.contains(&offset) {
+                continue;
+            }
+            let end = (offset + self.chunk_size).min(data.len());
+            let response = client
+                .post(self.url(&format!("{}/{}", session, offset)))
+                .header("Content-Type", "application/octet-stream")
+                .body(data.slice(offset..end))
+                .timeout(timeout)
+                .send()
+                .await?;
+            if !response.status().is_success() {
+                self.expire(&response);
+                return Ok(response);
+            }
+            self.sent.insert(offset);
+        }
+
+        let response = client
+            .post(self.url(&format!("{}/-1", session)))
+            .header("Content-Type", "application/octet-stream")
+            .timeout(timeout)
+            .send()
+            .await?;
+        self.expire(&response);
+        Ok(response)
+    }
+
+    // a session the node no longer knows is started over
+    fn expire(&mut self, response: &Response) {
+        if response.status() == StatusCode::NOT_FOUND {
+            self.session = None;
+            self.sent.clear();
+        }
+    }
+}
+
+/*
+    a receipt is only kept if the upload node signed it and
+    its deadline is still ahead of the network, otherwise
+    the node made a promise it cannot keep
+*/
+async fn check_receipt(
+    gateway: &dyn Gateway,
+    receipt: UploadReceipt,
+) -> Result<UploadReceipt, String> {
+    receipt.verify()?;
+    let height = gateway.network_info().await?.height;
+    let height = height
+        .parse::<u64>()
+        .map_err(|e| format!("Invalid network height {}: {}", height, e))?;
+    receipt.check_deadline(height)?;
+    Ok(receipt)
+}
+
+#[async_trait]
 impl Uploader for UploaderClient {
-    fn upload(&self, tx: Vec<u8>) -> Result<(), UploaderErrorType> {
+    fn upload(&self, tx: Bytes) -> Result<(), UploaderErrorType> {
+        let (item, _) = DataItem::from_info_bytes(&tx)
+            .map_err(|e| UploaderErrorType::UploadError(format!("{:?}", e)))?;
+        let id = item.id();
         let node_url_clone = self.node_url.clone();
-        let tx_clone = tx.clone();
+        let mut chunked = match tx.len() > self.chunked_above && self.chunked_above > 0 {
+            true => Some(ChunkedUpload::new(self.node_url.clone(), self.chunk_size)),
+            false => None,
+        };
+        let tx_clone = tx;
         let logger_clone = Arc::clone(&self.logger);
+        let events_clone = Arc::clone(&self.events);
+        let data_store = Arc::clone(&self.data_store);
+        let gateway = Arc::clone(&self.gateway);
+        let pending_clone = Arc::clone(&self.pending);
+        let given_up = Arc::clone(&self.given_up);
+        let client = self.client.clone();
+        let breaker = Arc::clone(&self.breaker);
+        let timeout = self.timeout;
 
+        pending_clone.fetch_add(1, Ordering::SeqCst);
         spawn(async move {
-            let client = Client::new();
+            let (mut attempts, mut waited) = (0, Duration::ZERO);
+            let mut error = String::new();
+            let mut delivered = false;
+            while attempts < MAX_ATTEMPTS && waited < MAX_BREAKER_WAIT {
+                // an open breaker does not use up attempts, the upload waits for it
+                if let Err(e) = breaker.allow() {
+                    error = e;
+                    sleep(Duration::from_secs(1)).await;
+                    waited += Duration::from_secs(1);
+                    continue;
+                }
+                attempts += 1;
 
-            for _attempt in 0..100 {
-                let response = client
-                    .post(
-                        node_url_clone
-                            .join(&format!("tx/{}", "arweave".to_string()))
-                            .expect("Failed to join URL"), // Handle URL joining error
-                    )
-                    .header("Content-Type", "application/octet-stream")
-                    .body(tx_clone.clone())
-                    .send()
-                    .await;
+                let response = match chunked.as_mut() {
+                    Some(upload) => upload.send(&client, &tx_clone, timeout).await,
+                    None => {
+                        client
+                            .post(
+                                node_url_clone
+                                    .join(&format!("tx/{}", "arweave"))
+                                    .expect("Failed to join URL"), // Handle URL joining error
+                            )
+                            .header("Content-Type", "application/octet-stream")
+                            .body(tx_clone.clone())
+                            .timeout(timeout)
+                            .send()
+                            .await
+                    }
+                };
+                breaker.record(match &response {
+                    Ok(resp) => !resp.status().is_server_error(),
+                    Err(_) => false,
+                });
 
                 match response {
                     Ok(resp) if resp.status().is_success() => {
                         // Handle success
                         logger_clone.log("Upload successful".to_string());
+                        let receipt = match resp.json::<UploadReceipt>().await {
+                            Ok(receipt) => check_receipt(&*gateway, receipt).await,
+                            Err(e) => Err(format!("{}", e)),
+                        };
+                        match receipt {
+                            Ok(receipt) => {
+                                // stored first so whoever hears the event can read it
+                                if let Err(e) = data_store.save_upload_receipt(&receipt).await {
+                                    logger_clone.error(format!("Failed to save receipt: {:?}", e));
+                                }
+                                events_clone
+                                    .publish(DomainEvent::UploadConfirmed { id: receipt.id })
+                            }
+                            Err(e) => logger_clone.error(format!("Invalid upload receipt: {}", e)),
+                        }
+                        delivered = true;
                         break; // Exit the loop on success
                     }
                     Ok(resp) => {
                         // Handle non-success HTTP status
                         logger_clone.error(format!("Non-success status: {}", resp.status()));
+                        error = format!("Upload node returned {}", resp.status());
                         sleep(Duration::from_secs(1)).await;
                     }
                     Err(e) => {
                         // Handle request error
                         logger_clone.error(format!("Request error: {}", e));
+                        error = format!("Request error: {}", e);
                         sleep(Duration::from_secs(1)).await;
                     }
                 }
             }
+            // kept for the redelivery to hand over again
+            if !delivered {
+                given_up.fetch_add(1, Ordering::SeqCst);
+                let error = format!("Gave up uploading {} - {}", id, error);
+                logger_clone.error(error.clone());
+                if let Err(e) = data_store.save_undelivered(&id, &tx_clone, &error).await {
+                    logger_clone.error(format!("Failed to save undelivered item {}: {:?}", id, e));
+                }
+            }
+            pending_clone.fetch_sub(1, Ordering::SeqCst);
         });
 
         Ok(())
     }
+
+    async fn ping(&self) -> Result<(), UploaderErrorType> {
+        let url = self
+            .node_url
+            .join("info")
+            .map_err(|e| UploaderErrorType::UploadError(format!("{}", e)))?;
+        let request = match Deadline::current().remaining() {
+            Some(remaining) => self.client.get(url).timeout(remaining.min(self.timeout)),
+            None => self.client.get(url).timeout(self.timeout),
+        };
+
+        self.breaker
+            .allow()
+            .map_err(UploaderErrorType::UploadError)?;
+        let response = request.send().await;
+        self.breaker.record(match &response {
+            Ok(resp) => !resp.status().is_server_error(),
+            Err(_) => false,
+        });
+        let response = response?;
+        match response.status().is_success() {
+            true => Ok(()),
+            false => Err(UploaderErrorType::UploadError(format!(
+                "Upload node returned {}",
+                response.status()
+            ))),
+        }
+    }
+
+    // the account the upload node charges, funded separately from the wallet
+    async fn balance(&self, address: &str) -> Result<u64, UploaderErrorType> {
+        let mut url = self
+            .node_url
+            .join("account/balance/arweave")
+            .map_err(|e| UploaderErrorType::UploadError(format!("{}", e)))?;
+        url.query_pairs_mut().append_pair("address", address);
+
+        let response = self.client.get(url).timeout(self.timeout).send().await?;
+        if !response.status().is_success() {
+            return Err(UploaderErrorType::UploadError(format!(
+                "Upload node returned {} for the balance",
+                response.status()
+            )));
+        }
+        let body: serde_json::Value = response.json().await?;
+        let balance = match &body["balance"] {
+            serde_json::Value::String(balance) => balance.parse::<u64>().ok(),
+            balance => balance.as_u64(),
+        };
+        balance.ok_or(UploaderErrorType::UploadError(format!(
+            "Invalid balance {}",
+            body
+        )))
+    }
+
+    fn pending_uploads(&self) -> usize {
+        self.pending.load(Ordering::SeqCst)
+    }
+
+    fn given_up_uploads(&self) -> u64 {
+        self.given_up.load(Ordering::SeqCst)
+    }
 }
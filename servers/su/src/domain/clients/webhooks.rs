@@ -0,0 +1,211 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+use async_trait::async_trait;
+use reqwest::{Client, StatusCode, Url};
+use ring::hmac;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::mpsc;
+use tokio::time::{sleep, Duration};
+
+use crate::domain::core::dal::{Config, DomainEvent, EventBus, EventDelivery, Log};
+
+// events held for a webhook that is down before new ones are dropped
+const QUEUE_DEPTH: usize = 10000;
+const MAX_BACKOFF_SECS: u64 = 60;
+
+struct Webhook {
+    client: Client,
+    url: Url,
+    secret: Option<String>,
+    max_attempts: u32,
+    logger: Arc<dyn Log>,
+    pending: Arc<AtomicUsize>,
+}
+
+// the events queued for every url and not yet delivered or dropped
+pub struct Webhooks {
+    pending: Arc<AtomicUsize>,
+}
+
+/*
+    Posts every spawned process and sequenced message to
+    each WEBHOOK_URLS entry as the json of the event. Each
+    url gets its own queue, delivered in order, so a slow
+    or failing one does not hold up the others.
+*/
+pub fn spawn_webhooks(
+    bus: &EventBus,
+    config: &dyn Config,
+    logger: Arc<dyn Log>,
+) -> Result<Option<Arc<dyn EventDelivery>>, String> {
+    let client = Client::builder()
+        .timeout(Duration::from_millis(config.webhook_timeout_ms()))
+        .build()
+        .map_err(|e| format!("{:?}", e))?;
+
+    let pending = Arc::new(AtomicUsize::new(0));
+    let mut queues = vec![];
+    for url in config.webhook_urls() {
+        let webhook = Webhook {
+            client: client.clone(),
+            url: Url::parse(&url).map_err(|e| format!("{}: {}", url, e))?,
+            secret: config.webhook_secret(),
+            max_attempts: config.webhook_max_attempts().max(1),
+            logger: logger.clone(),
+            pending: pending.clone(),
+        };
+        let (sender, queue) = mpsc::channel(QUEUE_DEPTH);
+        tokio::spawn(webhook.deliver_all(queue));
+        queues.push((url, sender));
+    }
+    if queues.is_empty() {
+        return Ok(None);
+    }
+
+    let mut receiver = bus.subscribe();
+    let queued = pending.clone();
+    tokio::spawn(async move {
+        loop {
+            let event = match receiver.recv().await {
+                Ok(e @ DomainEvent::MessageSequenced { .. })
+                | Ok(e @ DomainEvent::ProcessCreated { .. }) => e,
+                Ok(_) => continue,
+                Err(RecvError::Lagged(skipped)) => {
+                    logger.error(format!("webhooks skipped {} events", skipped));
+                    continue;
+                }
+                Err(RecvError::Closed) => break,
+            };
+            let body = match serde_json::to_string(&event) {
+                Ok(b) => b,
+                Err(_) => continue,
+            };
+            for (url, sender) in &queues {
+                queued.fetch_add(1, Ordering::SeqCst);
+                if sender.try_send(body.clone()).is_err() {
+                    queued.fetch_sub(1, Ordering::SeqCst);
+                    logger.error(format!("webhook queue full, dropped event for {}", url));
+                }
+            }
+        }
+    });
+
+    Ok(Some(Arc::new(Webhooks { pending })))
+}
+
+#[async_trait]
+impl EventDelivery for Webhooks {
+    async fn flush(&self, timeout: Duration) -> Result<(), String> {
+        let started = Instant::now();
+        while self.pending.load(Ordering::SeqCst) > 0 && started.elapsed() < timeout {
+            sleep(Duration::from_millis(100)).await;
+        }
+        match self.pending.load(Ordering::SeqCst) {
+            0 => Ok(()),
+            left => Err(format!("{} events left in the webhook queues", left)),
+        }
+    }
+}
+
+impl Webhook {
+    async fn deliver_all(self, mut queue: mpsc::Receiver<String>) {
+        while let Some(body) = queue.recv().await {
+            self.deliver(body).await;
+            self.pending.fetch_sub(1, Ordering::SeqCst);
+        }
+    }
+
+    async fn deliver(&self, body: String) {
+        let mut backoff = 1;
+        for attempt in 1..=self.max_attempts {
+            let error = match self.post(&body).await {
+                Ok(status) if status.is_success() => return,
+                Ok(status) if !is_retryable(status) => {
+                    self.logger.error(format!(
+                        "webhook {} rejected event with status {}",
+                        self.url, status
+                    ));
+                    return;
+                }
+                Ok(status) => format!("status {}", status),
+                Err(e) => e,
+            };
+            if attempt == self.max_attempts {
+                self.logger.error(format!(
+                    "webhook {} failed after {} attempts, dropped event - {}",
+                    self.url, attempt, error
+                ));
+                return;
+            }
+            sleep(Duration::from_secs(backoff)).await;
+            backoff = (backoff * 2).min(MAX_BACKOFF_SECS);
+        }
+    }
+
+    async fn post(&self, body: &str) -> Result<StatusCode, String> {
+        let mut request = self
+            .client
+            .post(self.url.clone())
+            .header("Content-Type", "application/json");
+        if let Some(secret) = &self.secret {
+            let timestamp = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_err(|e| e.to_string())?
+                .as_millis()
+                .to_string();
+            request = request.header("X-SU-Timestamp", &timestamp).header(
+                "X-SU-Signature",
+                format!("sha256={}", sign(secret, &timestamp, body)),
+            );
+        }
+        let response = request
+            .body(body.to_string())
+            .send()
+            .await
+            .map_err(|e| e.to_string())?;
+        Ok(response.status())
+    }
+}
+
+/*
+    hex hmac-sha256 of "<timestamp>.<body>", the receiver
+    recomputes it and can refuse old timestamps so a
+    captured request cannot be replayed
+*/
+fn sign(secret: &str, timestamp: &str, body: &str) -> String {
+    let key = hmac::Key::new(hmac::HMAC_SHA256, secret.as_bytes());
+    hex::encode(hmac::sign(&key, format!("{}.{}", timestamp, body).as_bytes()).as_ref())
+}
+
+// other client errors would fail the same way again
+fn is_retryable(status: StatusCode) -> bool {
+    status.is_server_error()
+        || status == StatusCode::TOO_MANY_REQUESTS
+        || status == StatusCode::REQUEST_TIMEOUT
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sign() {
+        assert_eq!(
+            sign(
+                "secret",
+                "1714000000000",
+                r#"{"type":"UploadConfirmed","id":"abc"}"#
+            ),
+            "ec620725ace8e5b32d61e11a1e37bf8bbe2b3dbbabd1093bd78010c685364b97"
+        );
+    }
+
+    #[test]
+    fn test_is_retryable() {
+        assert!(is_retryable(StatusCode::BAD_GATEWAY));
+        assert!(is_retryable(StatusCode::TOO_MANY_REQUESTS));
+        assert!(!is_retryable(StatusCode::NOT_FOUND));
+    }
+}
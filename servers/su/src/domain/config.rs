@@ -1,35 +1,882 @@
 use std::env;
+use std::fs;
+use std::str::FromStr;
+use std::sync::Mutex;
 
 use dotenv::dotenv;
+use reqwest::header::HeaderName;
+use reqwest::{Method, Url};
+use serde::Serialize;
 
+use crate::domain::clients::http::scheduler_client;
+use crate::domain::clients::tls::server_tls;
+use crate::domain::core::resolver::RedirectPolicy;
+use crate::domain::core::runtime::parse_level;
 use crate::domain::Config;
 
-#[derive(Debug)]
+// name and value pairs, in the order they were given
+type Settings = Vec<(String, String)>;
+
+#[derive(Debug, Serialize)]
 pub struct AoConfig {
     pub database_url: String,
+    pub database_read_url: Option<String>,
+    pub database_pool_size: u32,
+    pub database_connect_timeout_ms: u64,
+    pub database_statement_timeout_ms: u64,
     pub su_wallet_path: String,
     pub gateway_url: String,
     pub upload_node_url: String,
     pub mode: String,
     pub scheduler_list_path: String,
+    pub ingest_queue_depth: usize,
+    pub ingest_workers: usize,
+    pub redirect_url_template: Option<String>,
+    pub redirect_internal_url_template: Option<String>,
+    pub redirect_internal_hosts: Vec<String>,
+    pub redirect_port_override: Option<u16>,
+    pub redirect_status: u16,
+    pub redirect_status_routes: Vec<String>,
+    pub redirect_body: bool,
+    pub store_failover_grace_ms: u64,
+    pub store_failover_queue_size: usize,
+    pub write_restricted: bool,
+    pub bind_address: Option<String>,
+    pub trusted_proxies: Vec<String>,
+    pub cache_max_entries: usize,
+    pub event_stream_token: Option<String>,
+    pub primary_events_url: Option<String>,
+    pub verify_bundle_checksums: bool,
+    pub rate_limit_per_second: f64,
+    pub rate_limit_burst: u32,
+    pub schedule_lock_deadline_ms: u64,
+    pub schedule_lock_idle_ms: u64,
+    pub spawn_allowed_owners: Vec<String>,
+    pub spawn_denied_owners: Vec<String>,
+    pub spawn_allowed_modules: Vec<String>,
+    pub spawn_denied_modules: Vec<String>,
+    pub request_deadline_ms: u64,
+    pub max_item_size: usize,
+    pub max_process_size: usize,
+    pub auto_migrate: bool,
+    pub max_inflight_writes: usize,
+    pub write_queue_depth: usize,
+    pub process_queue_depth: usize,
+    pub shutdown_timeout_ms: u64,
+    pub admin_token: Option<String>,
+    pub backup_s3_bucket: Option<String>,
+    pub backup_s3_endpoint: Option<String>,
+    pub backup_s3_region: String,
+    pub backup_s3_prefix: String,
+    pub backup_s3_access_key: Option<String>,
+    pub backup_s3_secret_key: Option<String>,
+    pub backup_interval_ms: u64,
+    pub backup_retain: usize,
+    pub backup_restore_on_start: bool,
+    pub bundle_storage: String,
+    pub bundle_storage_path: Option<String>,
+    pub bundle_s3_bucket: Option<String>,
+    pub bundle_s3_endpoint: Option<String>,
+    pub bundle_s3_region: String,
+    pub bundle_s3_prefix: String,
+    pub bundle_s3_access_key: Option<String>,
+    pub bundle_s3_secret_key: Option<String>,
+    pub bundle_offload_min_size: usize,
+    pub webhook_urls: Vec<String>,
+    pub webhook_secret: Option<String>,
+    pub webhook_max_attempts: u32,
+    pub webhook_timeout_ms: u64,
+    pub event_publisher: String,
+    pub event_publisher_url: Option<String>,
+    pub event_publisher_topic: String,
+    pub compress_responses: bool,
+    pub accept_compressed_requests: bool,
+    pub tag_policy_path: Option<String>,
+    pub strict_scheduler_tag: bool,
+    pub scheduler_location_owner: Option<String>,
+    pub scheduler_location_url: Option<String>,
+    pub scheduler_location_ttl_ms: u64,
+    pub tenant_wallet_paths: Vec<String>,
+    pub upload_verify_sample: u64,
+    pub upload_verify_after_ms: u64,
+    pub upload_verify_timeout_ms: u64,
+    pub upload_repair_after_ms: u64,
+    pub attestation_interval_ms: u64,
+    pub signed_read_max_age_ms: u64,
+    pub sign_reads: bool,
+    pub log_level: String,
+    pub route_cache_ttl_ms: u64,
+    pub route_cache_max_entries: usize,
+    pub assignment_strategy: String,
+    pub hash_virtual_nodes: u32,
+    pub scheduler_admin_token: Option<String>,
+    pub rebalance_max_moves: usize,
+    pub rebalance_idle_ms: u64,
+    pub rebalance_tolerance: f64,
+    pub max_import_size: usize,
+    pub scheduler_stats_interval_ms: u64,
+    pub read_only: bool,
+    pub retention_days: u64,
+    pub retention_max_messages: u64,
+    pub retention_delete_rows: bool,
+    pub retention_interval_ms: u64,
+    pub cold_storage_after_days: u64,
+    pub cold_segment_size: u64,
+    pub cold_storage_interval_ms: u64,
+    pub http_connect_timeout_ms: u64,
+    pub http_request_timeout_ms: u64,
+    pub http_pool_idle_timeout_ms: u64,
+    pub http_pool_max_idle_per_host: usize,
+    pub keep_alive_ms: u64,
+    pub gateway_timeout_ms: u64,
+    pub gateway_breaker_failures: u32,
+    pub gateway_breaker_open_ms: u64,
+    pub upload_timeout_ms: u64,
+    pub upload_breaker_failures: u32,
+    pub upload_breaker_open_ms: u64,
+    pub scheduler_timeout_ms: u64,
+    pub scheduler_breaker_failures: u32,
+    pub scheduler_breaker_open_ms: u64,
+    pub tls_cert_path: Option<String>,
+    pub tls_key_path: Option<String>,
+    pub tls_client_ca_path: Option<String>,
+    pub scheduler_tls_ca_path: Option<String>,
+    pub scheduler_tls_cert_path: Option<String>,
+    pub scheduler_tls_key_path: Option<String>,
+    pub cors_allowed_origins: Vec<String>,
+    pub cors_allowed_methods: Vec<String>,
+    pub cors_allowed_headers: Vec<String>,
+    pub cors_max_age_secs: u64,
+    pub boot_message_tags: Vec<String>,
+    pub cron_mode: String,
+    pub cron_interval_ms: u64,
+    pub cron_max_catch_up: u32,
+    pub module_validation: bool,
+    pub module_required_tags: Vec<String>,
+    pub module_cache_max_entries: usize,
+    pub router_region_header: String,
+    pub upload_receipt_wait_ms: u64,
+    pub upload_mode: String,
+    pub arweave_node_url: Option<String>,
+    pub direct_bundle_max_items: usize,
+    pub direct_bundle_max_bytes: usize,
+    pub direct_bundle_interval_ms: u64,
+    pub funding_warn_winston: u64,
+    pub funding_stop_winston: u64,
+    pub funding_check_interval_ms: u64,
+    pub upload_chunked_above_bytes: u64,
+    pub upload_chunk_bytes: u64,
+    pub store_group_commit_ms: u64,
+    pub store_group_commit_max: usize,
+    pub min_nonce_wait_ms: u64,
+    pub cluster_node_url: Option<String>,
+    pub process_lease_ms: u64,
+    pub outbox_max_attempts: u32,
+    pub cron_min_interval_ms: u64,
+    pub direct_queue_max_items: usize,
+    pub proxy_protocol: bool,
+    pub subscribe_max_per_process: usize,
+    pub subscribe_max_total: usize,
+    pub min_nonce_max_waiters: usize,
+}
+
+/*
+    optional settings fall back to a default
+    when they are unset or cannot be parsed
+*/
+fn env_or<T: FromStr>(name: &str, default: T) -> T {
+    match env::var(name) {
+        Ok(val) => match val.parse::<T>() {
+            Ok(v) => v,
+            Err(_) => {
+                invalid_setting(name, &val);
+                default
+            }
+        },
+        Err(_) => default,
+    }
+}
+
+// unset, empty or unparsable optional settings are None
+fn env_opt<T: FromStr>(name: &str) -> Option<T> {
+    match env::var(name) {
+        Ok(val) if !val.is_empty() => match val.parse::<T>() {
+            Ok(v) => Some(v),
+            Err(_) => {
+                invalid_setting(name, &val);
+                None
+            }
+        },
+        _ => None,
+    }
+}
+
+// settings the last AoConfig::new could not parse
+static INVALID_SETTINGS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+fn invalid_setting(name: &str, val: &str) {
+    if let Ok(mut invalid) = INVALID_SETTINGS.lock() {
+        invalid.push(format!(
+            "{}={} is not valid, the default is used",
+            name, val
+        ));
+    }
+}
+
+pub fn invalid_settings() -> Vec<String> {
+    INVALID_SETTINGS
+        .lock()
+        .map(|invalid| invalid.clone())
+        .unwrap_or_default()
+}
+
+// comma separated list settings
+fn env_list(name: &str) -> Vec<String> {
+    match env::var(name) {
+        Ok(val) => val
+            .split(',')
+            .map(|v| v.trim().to_string())
+            .filter(|v| !v.is_empty())
+            .collect(),
+        Err(_) => vec![],
+    }
+}
+
+// a database url with its password removed
+// userinfo and query values can carry credentials
+fn redact_url(url_in: &str) -> String {
+    match Url::parse(url_in) {
+        Ok(mut url) => {
+            if !url.username().is_empty() {
+                let _ = url.set_username("REDACTED");
+            }
+            if url.password().is_some() {
+                let _ = url.set_password(Some("REDACTED"));
+            }
+            if url.query().is_some() {
+                let keys: Vec<String> = url.query_pairs().map(|(k, _)| k.into_owned()).collect();
+                url.query_pairs_mut()
+                    .clear()
+                    .extend_pairs(keys.iter().map(|k| (k.as_str(), "REDACTED")));
+            }
+            url.to_string()
+        }
+        Err(_) => "REDACTED".to_string(),
+    }
+}
+
+fn redact_settings(value: serde_json::Value) -> serde_json::Value {
+    use serde_json::Value;
+    let settings = match value {
+        Value::Object(settings) => settings,
+        _ => return Value::Null,
+    };
+    let redact_urls = |value: Value| match value {
+        Value::String(url) => Value::String(redact_url(&url)),
+        Value::Array(urls) => Value::Array(
+            urls.iter()
+                .map(|u| Value::String(redact_url(u.as_str().unwrap_or_default())))
+                .collect(),
+        ),
+        other => other,
+    };
+    settings
+        .into_iter()
+        .map(|(name, value)| {
+            let shown = if PUBLIC_SETTINGS.contains(&name.as_str()) {
+                value
+            } else if URL_SETTINGS.contains(&name.as_str()) {
+                redact_urls(value)
+            } else if value.is_null() {
+                value
+            } else {
+                Value::String("REDACTED".to_string())
+            };
+            (name, shown)
+        })
+        .collect()
+}
+
+/*
+    settings printed as they are by redacted(), any other
+    setting is a secret or a url and a new one stays
+    hidden until it is added here
+*/
+const PUBLIC_SETTINGS: &[&str] = &[
+    "database_pool_size",
+    "database_connect_timeout_ms",
+    "database_statement_timeout_ms",
+    "su_wallet_path",
+    "mode",
+    "scheduler_list_path",
+    "ingest_queue_depth",
+    "ingest_workers",
+    "redirect_internal_hosts",
+    "redirect_port_override",
+    "redirect_status",
+    "redirect_status_routes",
+    "redirect_body",
+    "store_failover_grace_ms",
+    "store_failover_queue_size",
+    "write_restricted",
+    "bind_address",
+    "trusted_proxies",
+    "proxy_protocol",
+    "subscribe_max_per_process",
+    "subscribe_max_total",
+    "cache_max_entries",
+    "verify_bundle_checksums",
+    "rate_limit_per_second",
+    "rate_limit_burst",
+    "schedule_lock_deadline_ms",
+    "schedule_lock_idle_ms",
+    "spawn_allowed_owners",
+    "spawn_denied_owners",
+    "spawn_allowed_modules",
+    "spawn_denied_modules",
+    "request_deadline_ms",
+    "max_item_size",
+    "max_process_size",
+    "auto_migrate",
+    "max_inflight_writes",
+    "write_queue_depth",
+    "process_queue_depth",
+    "shutdown_timeout_ms",
+    "backup_s3_bucket",
+    "backup_s3_region",
+    "backup_s3_prefix",
+    "backup_interval_ms",
+    "backup_retain",
+    "backup_restore_on_start",
+    "bundle_storage",
+    "bundle_storage_path",
+    "bundle_s3_bucket",
+    "bundle_s3_region",
+    "bundle_s3_prefix",
+    "bundle_offload_min_size",
+    "webhook_max_attempts",
+    "webhook_timeout_ms",
+    "event_publisher",
+    "event_publisher_topic",
+    "outbox_max_attempts",
+    "compress_responses",
+    "accept_compressed_requests",
+    "tag_policy_path",
+    "strict_scheduler_tag",
+    "scheduler_location_owner",
+    "scheduler_location_ttl_ms",
+    "tenant_wallet_paths",
+    "upload_verify_sample",
+    "upload_verify_after_ms",
+    "upload_verify_timeout_ms",
+    "upload_repair_after_ms",
+    "attestation_interval_ms",
+    "signed_read_max_age_ms",
+    "sign_reads",
+    "log_level",
+    "route_cache_ttl_ms",
+    "route_cache_max_entries",
+    "assignment_strategy",
+    "hash_virtual_nodes",
+    "rebalance_max_moves",
+    "rebalance_idle_ms",
+    "rebalance_tolerance",
+    "max_import_size",
+    "scheduler_stats_interval_ms",
+    "read_only",
+    "retention_days",
+    "retention_max_messages",
+    "retention_delete_rows",
+    "retention_interval_ms",
+    "cold_storage_after_days",
+    "cold_segment_size",
+    "cold_storage_interval_ms",
+    "http_connect_timeout_ms",
+    "http_request_timeout_ms",
+    "http_pool_idle_timeout_ms",
+    "http_pool_max_idle_per_host",
+    "keep_alive_ms",
+    "gateway_timeout_ms",
+    "gateway_breaker_failures",
+    "gateway_breaker_open_ms",
+    "upload_timeout_ms",
+    "upload_breaker_failures",
+    "upload_breaker_open_ms",
+    "scheduler_timeout_ms",
+    "scheduler_breaker_failures",
+    "scheduler_breaker_open_ms",
+    "tls_cert_path",
+    "tls_key_path",
+    "tls_client_ca_path",
+    "scheduler_tls_ca_path",
+    "scheduler_tls_cert_path",
+    "scheduler_tls_key_path",
+    "cors_allowed_origins",
+    "cors_allowed_methods",
+    "cors_allowed_headers",
+    "cors_max_age_secs",
+    "boot_message_tags",
+    "cron_mode",
+    "cron_interval_ms",
+    "cron_min_interval_ms",
+    "cron_max_catch_up",
+    "module_validation",
+    "module_required_tags",
+    "module_cache_max_entries",
+    "router_region_header",
+    "upload_receipt_wait_ms",
+    "upload_mode",
+    "direct_bundle_max_items",
+    "direct_bundle_max_bytes",
+    "direct_bundle_interval_ms",
+    "direct_queue_max_items",
+    "funding_warn_winston",
+    "funding_stop_winston",
+    "funding_check_interval_ms",
+    "upload_chunked_above_bytes",
+    "upload_chunk_bytes",
+    "store_group_commit_ms",
+    "store_group_commit_max",
+    "min_nonce_wait_ms",
+    "min_nonce_max_waiters",
+    "process_lease_ms",
+];
+
+// urls printed without the credentials they may embed
+const URL_SETTINGS: &[&str] = &[
+    "database_url",
+    "database_read_url",
+    "gateway_url",
+    "upload_node_url",
+    "redirect_url_template",
+    "redirect_internal_url_template",
+    "primary_events_url",
+    "backup_s3_endpoint",
+    "bundle_s3_endpoint",
+    "webhook_urls",
+    "event_publisher_url",
+    "scheduler_location_url",
+    "arweave_node_url",
+    "cluster_node_url",
+];
+
+// settings the server does not start without
+const REQUIRED_SETTINGS: [&str; 5] = [
+    "DATABASE_URL",
+    "SU_WALLET_PATH",
+    "GATEWAY_URL",
+    "UPLOAD_NODE_URL",
+    "SCHEDULER_LIST_PATH",
+];
+
+/*
+    Configuration comes in layers. A flag on the command
+    line wins over the environment, which wins over the
+    toml file at --config or SU_CONFIG_PATH, which wins
+    over .env and the defaults. Flags and the file are
+    applied as environment variables so a setting has one
+    name everywhere, FOO_BAR in the environment is foo_bar
+    in the file and --foo-bar on the command line. Returns
+    the arguments left once the flags are taken out.
+*/
+pub fn apply_layers(args: Vec<String>) -> Result<Vec<String>, String> {
+    let (args, flags) = split_flags(args)?;
+    for (name, value) in flags.iter() {
+        match name.as_str() {
+            "CONFIG" => env::set_var("SU_CONFIG_PATH", value),
+            _ => env::set_var(name, value),
+        }
+    }
+
+    if let Some(path) = config_path() {
+        for (name, value) in read_config_file(&path)? {
+            if env::var_os(&name).is_none() {
+                env::set_var(&name, value);
+            }
+        }
+    }
+    Ok(args)
+}
+
+fn config_path() -> Option<String> {
+    env::var("SU_CONFIG_PATH").ok().filter(|p| !p.is_empty())
+}
+
+// the settings of the config file as it is now, none without one
+pub fn config_file_settings() -> Result<Settings, String> {
+    match config_path() {
+        Some(path) => read_config_file(&path),
+        None => Ok(vec![]),
+    }
+}
+
+// --name value and --name=value flags, the rest stays positional
+fn split_flags(args: Vec<String>) -> Result<(Vec<String>, Settings), String> {
+    let mut positional = vec![];
+    let mut flags = vec![];
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        let flag = match arg.strip_prefix("--") {
+            Some(flag) if flag != "help" => flag.to_string(),
+            _ => {
+                positional.push(arg);
+                continue;
+            }
+        };
+        let (name, value) = match flag.split_once('=') {
+            Some((name, value)) => (name.to_string(), value.to_string()),
+            None => match args.next() {
+                Some(value) => (flag.clone(), value),
+                None => return Err(format!("--{} needs a value", flag)),
+            },
+        };
+        flags.push((name.replace('-', "_").to_uppercase(), value));
+    }
+    Ok((positional, flags))
+}
+
+fn read_config_file(path: &str) -> Result<Settings, String> {
+    let contents =
+        fs::read_to_string(path).map_err(|e| format!("failed to read config {}: {}", path, e))?;
+    parse_config_file(&contents).map_err(|e| format!("invalid config {}: {}", path, e))
+}
+
+// the settings of a toml file as environment variables, lists are joined with commas
+fn parse_config_file(contents: &str) -> Result<Settings, String> {
+    let table: toml::Table = contents.parse().map_err(|e| format!("{}", e))?;
+    let scalar = |key: &str, value: &toml::Value| match value {
+        toml::Value::String(s) => Ok(s.clone()),
+        toml::Value::Integer(_) | toml::Value::Float(_) | toml::Value::Boolean(_) => {
+            Ok(value.to_string())
+        }
+        _ => Err(format!(
+            "{} must be a string, number, boolean or a list of them",
+            key
+        )),
+    };
+
+    let mut settings = vec![];
+    for (key, value) in table.iter() {
+        let value = match value {
+            toml::Value::Array(items) => items
+                .iter()
+                .map(|item| scalar(key, item))
+                .collect::<Result<Vec<String>, String>>()?
+                .join(","),
+            _ => scalar(key, value)?,
+        };
+        settings.push((key.to_uppercase(), value));
+    }
+    Ok(settings)
+}
+
+/*
+    The effective configuration with secrets removed and
+    every problem found with it, an error listing them
+    when there are any. mode defaults to su.
+*/
+// CORS_* values the cors middleware would refuse to start with
+fn invalid_cors(origins: &[String], methods: &[String], headers: &[String]) -> Vec<String> {
+    let mut problems = vec![];
+    for origin in origins.iter().filter(|o| o.as_str() != "*") {
+        match Url::parse(origin) {
+            Ok(url) if url.has_host() && url.path() == "/" && !origin.ends_with('/') => (),
+            _ => problems.push(format!(
+                "CORS_ALLOWED_ORIGINS {} must be a scheme and host like https://app.example.com",
+                origin
+            )),
+        }
+    }
+    for method in methods.iter().filter(|m| m.as_str() != "*") {
+        if Method::from_bytes(method.as_bytes()).is_err() {
+            problems.push(format!("CORS_ALLOWED_METHODS {} is not a method", method));
+        }
+    }
+    for header in headers.iter().filter(|h| h.as_str() != "*") {
+        if HeaderName::from_bytes(header.as_bytes()).is_err() {
+            problems.push(format!(
+                "CORS_ALLOWED_HEADERS {} is not a header name",
+                header
+            ));
+        }
+    }
+    problems
+}
+
+pub fn check_cors(config: &dyn Config) -> Result<(), String> {
+    let problems = invalid_cors(
+        &config.cors_allowed_origins(),
+        &config.cors_allowed_methods(),
+        &config.cors_allowed_headers(),
+    );
+    match problems.is_empty() {
+        true => Ok(()),
+        false => Err(problems.join("\n")),
+    }
+}
+
+pub fn check_config(mode: Option<&str>) -> Result<String, String> {
+    dotenv().ok();
+    let mut problems: Vec<String> = REQUIRED_SETTINGS
+        .iter()
+        .filter(|name| env::var(name).is_err())
+        .map(|name| format!("{} is required", name))
+        .collect();
+    if !problems.is_empty() {
+        return Err(problems.join("\n"));
+    }
+
+    let mode = mode.unwrap_or("su");
+    if mode != "su" && mode != "router" {
+        problems.push(format!("mode {} must be su or router", mode));
+    }
+    let config = AoConfig::new(Some(mode.to_string())).map_err(|e| format!("{:?}", e))?;
+    problems.extend(invalid_settings());
+    if let Err(e) = parse_level(&config.log_level) {
+        problems.push(format!("LOG_LEVEL {}", e));
+    }
+    if let Err(e) = RedirectPolicy::new(&config) {
+        problems.push(e);
+    }
+    if !["least-loaded", "consistent-hash"].contains(&config.assignment_strategy.as_str()) {
+        problems.push(format!(
+            "ASSIGNMENT_STRATEGY {} must be least-loaded or consistent-hash",
+            config.assignment_strategy
+        ));
+    }
+    if !["bundler", "direct"].contains(&config.upload_mode.as_str()) {
+        problems.push(format!(
+            "UPLOAD_MODE {} must be bundler or direct",
+            config.upload_mode
+        ));
+    }
+    if !["off", "sequence", "virtual"].contains(&config.cron_mode.as_str()) {
+        problems.push(format!(
+            "CRON_MODE {} must be off, sequence or virtual",
+            config.cron_mode
+        ));
+    }
+    if config.cold_storage_after_days > 0 && config.bundle_storage == "database" {
+        problems.push("COLD_STORAGE_AFTER_DAYS needs BUNDLE_STORAGE disk or s3".to_string());
+    }
+    if let Err(e) = server_tls(&config) {
+        problems.push(e);
+    }
+    if let Err(e) = check_cors(&config) {
+        problems.push(e);
+    }
+    if let Err(e) = scheduler_client(&config) {
+        problems.push(e);
+    }
+    if fs::metadata(&config.su_wallet_path).is_err() {
+        problems.push(format!(
+            "SU_WALLET_PATH {} does not exist",
+            config.su_wallet_path
+        ));
+    }
+    if let Some(path) = config_path() {
+        let known = serde_json::to_value(&config).unwrap_or_default();
+        for (name, _) in read_config_file(&path)? {
+            if known.get(name.to_lowercase()).is_none() {
+                problems.push(format!(
+                    "{} in {} is not a setting",
+                    name.to_lowercase(),
+                    path
+                ));
+            }
+        }
+    }
+
+    let effective = serde_json::to_string_pretty(&config.redacted()).unwrap_or_default();
+    match problems.is_empty() {
+        true => Ok(format!("{}\nconfiguration is valid", effective)),
+        false => Err(format!("{}\n{}", effective, problems.join("\n"))),
+    }
 }
 
 impl AoConfig {
     pub fn new(mode: Option<String>) -> Result<Self, env::VarError> {
         dotenv().ok();
+        if let Ok(mut invalid) = INVALID_SETTINGS.lock() {
+            invalid.clear();
+        }
         let mode_out = match mode {
             Some(m) => m,
             None => env::var("MODE")?,
         };
         Ok(AoConfig {
             database_url: env::var("DATABASE_URL")?,
+            database_read_url: env_opt("DATABASE_READ_URL"),
+            database_pool_size: env_or("DATABASE_POOL_SIZE", 10),
+            database_connect_timeout_ms: env_or("DATABASE_CONNECT_TIMEOUT_MS", 30000),
+            database_statement_timeout_ms: env_or("DATABASE_STATEMENT_TIMEOUT_MS", 0),
             su_wallet_path: env::var("SU_WALLET_PATH")?,
             gateway_url: env::var("GATEWAY_URL")?,
             upload_node_url: env::var("UPLOAD_NODE_URL")?,
             mode: mode_out,
             scheduler_list_path: env::var("SCHEDULER_LIST_PATH")?,
+            ingest_queue_depth: env_or("INGEST_QUEUE_DEPTH", 256),
+            ingest_workers: env_or("INGEST_WORKERS", 4),
+            redirect_url_template: env_opt("REDIRECT_URL_TEMPLATE"),
+            redirect_internal_url_template: env_opt("REDIRECT_INTERNAL_URL_TEMPLATE"),
+            redirect_internal_hosts: env_list("REDIRECT_INTERNAL_HOSTS"),
+            redirect_port_override: env_opt("REDIRECT_PORT_OVERRIDE"),
+            redirect_status: env_or("REDIRECT_STATUS", 307),
+            redirect_status_routes: env_list("REDIRECT_STATUS_ROUTES"),
+            redirect_body: env_or("REDIRECT_BODY", false),
+            store_failover_grace_ms: env_or("STORE_FAILOVER_GRACE_MS", 2000),
+            store_failover_queue_size: env_or("STORE_FAILOVER_QUEUE_SIZE", 100),
+            write_restricted: env_or("WRITE_RESTRICTED", false),
+            bind_address: env_opt("BIND_ADDRESS"),
+            trusted_proxies: env_list("TRUSTED_PROXIES"),
+            cache_max_entries: env_or("CACHE_MAX_ENTRIES", 10000),
+            event_stream_token: env_opt("EVENT_STREAM_TOKEN"),
+            primary_events_url: env_opt("PRIMARY_EVENTS_URL"),
+            verify_bundle_checksums: env_or("VERIFY_BUNDLE_CHECKSUMS", true),
+            rate_limit_per_second: env_or("RATE_LIMIT_PER_SECOND", 0.0),
+            rate_limit_burst: env_or("RATE_LIMIT_BURST", 20),
+            schedule_lock_deadline_ms: env_or("SCHEDULE_LOCK_DEADLINE_MS", 60000),
+            schedule_lock_idle_ms: env_or("SCHEDULE_LOCK_IDLE_MS", 600000),
+            spawn_allowed_owners: env_list("SPAWN_ALLOWED_OWNERS"),
+            spawn_denied_owners: env_list("SPAWN_DENIED_OWNERS"),
+            spawn_allowed_modules: env_list("SPAWN_ALLOWED_MODULES"),
+            spawn_denied_modules: env_list("SPAWN_DENIED_MODULES"),
+            request_deadline_ms: env_or("REQUEST_DEADLINE_MS", 30000),
+            max_item_size: env_or("MAX_ITEM_SIZE", 10485760),
+            max_process_size: env_or("MAX_PROCESS_SIZE", 52428800),
+            auto_migrate: env_or("AUTO_MIGRATE", true),
+            max_inflight_writes: env_or("MAX_INFLIGHT_WRITES", 64),
+            write_queue_depth: env_or("WRITE_QUEUE_DEPTH", 256),
+            process_queue_depth: env_or("PROCESS_QUEUE_DEPTH", 1000),
+            shutdown_timeout_ms: env_or("SHUTDOWN_TIMEOUT_MS", 30000),
+            admin_token: env_opt("ADMIN_TOKEN"),
+            backup_s3_bucket: env_opt("BACKUP_S3_BUCKET"),
+            backup_s3_endpoint: env_opt("BACKUP_S3_ENDPOINT"),
+            backup_s3_region: env_or("BACKUP_S3_REGION", "us-east-1".to_string()),
+            backup_s3_prefix: env_or("BACKUP_S3_PREFIX", "su-backups".to_string()),
+            backup_s3_access_key: env_opt("BACKUP_S3_ACCESS_KEY"),
+            backup_s3_secret_key: env_opt("BACKUP_S3_SECRET_KEY"),
+            backup_interval_ms: env_or("BACKUP_INTERVAL_MS", 3600000),
+            backup_retain: env_or("BACKUP_RETAIN", 24),
+            backup_restore_on_start: env_or("BACKUP_RESTORE_ON_START", false),
+            bundle_storage: env_or("BUNDLE_STORAGE", "database".to_string()),
+            bundle_storage_path: env_opt("BUNDLE_STORAGE_PATH"),
+            bundle_s3_bucket: env_opt("BUNDLE_S3_BUCKET"),
+            bundle_s3_endpoint: env_opt("BUNDLE_S3_ENDPOINT"),
+            bundle_s3_region: env_or("BUNDLE_S3_REGION", "us-east-1".to_string()),
+            bundle_s3_prefix: env_or("BUNDLE_S3_PREFIX", "bundles".to_string()),
+            bundle_s3_access_key: env_opt("BUNDLE_S3_ACCESS_KEY"),
+            bundle_s3_secret_key: env_opt("BUNDLE_S3_SECRET_KEY"),
+            bundle_offload_min_size: env_or("BUNDLE_OFFLOAD_MIN_SIZE", 0),
+            webhook_urls: env_list("WEBHOOK_URLS"),
+            webhook_secret: env_opt("WEBHOOK_SECRET"),
+            webhook_max_attempts: env_or("WEBHOOK_MAX_ATTEMPTS", 8),
+            webhook_timeout_ms: env_or("WEBHOOK_TIMEOUT_MS", 10000),
+            event_publisher: env_or("EVENT_PUBLISHER", "none".to_string()),
+            event_publisher_url: env_opt("EVENT_PUBLISHER_URL"),
+            event_publisher_topic: env_or("EVENT_PUBLISHER_TOPIC", "su.events".to_string()),
+            compress_responses: env_or("COMPRESS_RESPONSES", true),
+            accept_compressed_requests: env_or("ACCEPT_COMPRESSED_REQUESTS", true),
+            tag_policy_path: env_opt("TAG_POLICY_PATH"),
+            strict_scheduler_tag: env_or("STRICT_SCHEDULER_TAG", false),
+            scheduler_location_owner: env_opt("SCHEDULER_LOCATION_OWNER"),
+            scheduler_location_url: env_opt("SCHEDULER_LOCATION_URL"),
+            scheduler_location_ttl_ms: env_or("SCHEDULER_LOCATION_TTL_MS", 86400000),
+            tenant_wallet_paths: env_list("TENANT_WALLET_PATHS"),
+            upload_verify_sample: env_or("UPLOAD_VERIFY_SAMPLE", 10),
+            upload_verify_after_ms: env_or("UPLOAD_VERIFY_AFTER_MS", 1800000),
+            upload_verify_timeout_ms: env_or("UPLOAD_VERIFY_TIMEOUT_MS", 21600000),
+            upload_repair_after_ms: env_or("UPLOAD_REPAIR_AFTER_MS", 7200000),
+            attestation_interval_ms: env_or("ATTESTATION_INTERVAL_MS", 0),
+            signed_read_max_age_ms: env_or("SIGNED_READ_MAX_AGE_MS", 300000),
+            sign_reads: env_or("SIGN_READS", false),
+            log_level: env_or("LOG_LEVEL", "info".to_string()),
+            route_cache_ttl_ms: env_or("ROUTE_CACHE_TTL_MS", 30000),
+            route_cache_max_entries: env_or("ROUTE_CACHE_MAX_ENTRIES", 100000),
+            assignment_strategy: env_or("ASSIGNMENT_STRATEGY", "least-loaded".to_string()),
+            hash_virtual_nodes: env_or("HASH_VIRTUAL_NODES", 128),
+            scheduler_admin_token: env_opt("SCHEDULER_ADMIN_TOKEN"),
+            rebalance_max_moves: env_or("REBALANCE_MAX_MOVES", 10),
+            rebalance_idle_ms: env_or("REBALANCE_IDLE_MS", 3600000),
+            rebalance_tolerance: env_or("REBALANCE_TOLERANCE", 0.1),
+            max_import_size: env_or("MAX_IMPORT_SIZE", 1073741824),
+            scheduler_stats_interval_ms: env_or("SCHEDULER_STATS_INTERVAL_MS", 30000),
+            read_only: env_or("READ_ONLY", false),
+            retention_days: env_or("RETENTION_DAYS", 0),
+            retention_max_messages: env_or("RETENTION_MAX_MESSAGES", 0),
+            retention_delete_rows: env_or("RETENTION_DELETE_ROWS", false),
+            retention_interval_ms: env_or("RETENTION_INTERVAL_MS", 3600000),
+            cold_storage_after_days: env_or("COLD_STORAGE_AFTER_DAYS", 0),
+            cold_segment_size: env_or("COLD_SEGMENT_SIZE", 10000),
+            cold_storage_interval_ms: env_or("COLD_STORAGE_INTERVAL_MS", 3600000),
+            http_connect_timeout_ms: env_or("HTTP_CONNECT_TIMEOUT_MS", 10000),
+            http_request_timeout_ms: env_or("HTTP_REQUEST_TIMEOUT_MS", 60000),
+            http_pool_idle_timeout_ms: env_or("HTTP_POOL_IDLE_TIMEOUT_MS", 90000),
+            http_pool_max_idle_per_host: env_or("HTTP_POOL_MAX_IDLE_PER_HOST", 32),
+            keep_alive_ms: env_or("KEEP_ALIVE_MS", 75000),
+            gateway_timeout_ms: env_or("GATEWAY_TIMEOUT_MS", 30000),
+            gateway_breaker_failures: env_or("GATEWAY_BREAKER_FAILURES", 5),
+            gateway_breaker_open_ms: env_or("GATEWAY_BREAKER_OPEN_MS", 30000),
+            upload_timeout_ms: env_or("UPLOAD_TIMEOUT_MS", 60000),
+            upload_breaker_failures: env_or("UPLOAD_BREAKER_FAILURES", 5),
+            upload_breaker_open_ms: env_or("UPLOAD_BREAKER_OPEN_MS", 30000),
+            scheduler_timeout_ms: env_or("SCHEDULER_TIMEOUT_MS", 10000),
+            scheduler_breaker_failures: env_or("SCHEDULER_BREAKER_FAILURES", 3),
+            scheduler_breaker_open_ms: env_or("SCHEDULER_BREAKER_OPEN_MS", 60000),
+            tls_cert_path: env_opt("TLS_CERT_PATH"),
+            tls_key_path: env_opt("TLS_KEY_PATH"),
+            tls_client_ca_path: env_opt("TLS_CLIENT_CA_PATH"),
+            scheduler_tls_ca_path: env_opt("SCHEDULER_TLS_CA_PATH"),
+            scheduler_tls_cert_path: env_opt("SCHEDULER_TLS_CERT_PATH"),
+            scheduler_tls_key_path: env_opt("SCHEDULER_TLS_KEY_PATH"),
+            cors_allowed_origins: env_list("CORS_ALLOWED_ORIGINS"),
+            cors_allowed_methods: env_list("CORS_ALLOWED_METHODS"),
+            cors_allowed_headers: env_list("CORS_ALLOWED_HEADERS"),
+            cors_max_age_secs: env_or("CORS_MAX_AGE_SECS", 3600),
+            boot_message_tags: env_list("BOOT_MESSAGE_TAGS"),
+            cron_mode: env_or("CRON_MODE", "off".to_string()),
+            cron_interval_ms: env_or("CRON_INTERVAL_MS", 1000),
+            cron_max_catch_up: env_or("CRON_MAX_CATCH_UP", 100),
+            module_validation: env_or("MODULE_VALIDATION", false),
+            module_required_tags: env_list("MODULE_REQUIRED_TAGS"),
+            module_cache_max_entries: env_or("MODULE_CACHE_MAX_ENTRIES", 10000),
+            router_region_header: env_or("ROUTER_REGION_HEADER", "X-Region".to_string()),
+            upload_receipt_wait_ms: env_or("UPLOAD_RECEIPT_WAIT_MS", 0),
+            upload_mode: env_or("UPLOAD_MODE", "bundler".to_string()),
+            arweave_node_url: env_opt("ARWEAVE_NODE_URL"),
+            direct_bundle_max_items: env_or("DIRECT_BUNDLE_MAX_ITEMS", 500),
+            direct_bundle_max_bytes: env_or("DIRECT_BUNDLE_MAX_BYTES", 10485760),
+            direct_bundle_interval_ms: env_or("DIRECT_BUNDLE_INTERVAL_MS", 5000),
+            funding_warn_winston: env_or("FUNDING_WARN_WINSTON", 0),
+            funding_stop_winston: env_or("FUNDING_STOP_WINSTON", 0),
+            funding_check_interval_ms: env_or("FUNDING_CHECK_INTERVAL_MS", 300000),
+            upload_chunked_above_bytes: env_or("UPLOAD_CHUNKED_ABOVE_BYTES", 52428800),
+            upload_chunk_bytes: env_or("UPLOAD_CHUNK_BYTES", 26214400),
+            store_group_commit_ms: env_or("STORE_GROUP_COMMIT_MS", 0),
+            store_group_commit_max: env_or("STORE_GROUP_COMMIT_MAX", 100),
+            min_nonce_wait_ms: env_or("MIN_NONCE_WAIT_MS", 5000),
+            cluster_node_url: env_opt("CLUSTER_NODE_URL"),
+            process_lease_ms: env_or("PROCESS_LEASE_MS", 10000),
+            outbox_max_attempts: env_or("OUTBOX_MAX_ATTEMPTS", 10),
+            cron_min_interval_ms: env_or("CRON_MIN_INTERVAL_MS", 1000),
+            direct_queue_max_items: env_or("DIRECT_QUEUE_MAX_ITEMS", 10000),
+            proxy_protocol: env_or("PROXY_PROTOCOL", false),
+            subscribe_max_per_process: env_or("SUBSCRIBE_MAX_PER_PROCESS", 100),
+            subscribe_max_total: env_or("SUBSCRIBE_MAX_TOTAL", 10000),
+            min_nonce_max_waiters: env_or("MIN_NONCE_MAX_WAITERS", 1000),
         })
     }
+
+    /*
+        the effective configuration with any secrets
+        removed, safe to print or attach to a bug report
+    */
+    pub fn redacted(&self) -> serde_json::Value {
+        match serde_json::to_value(self) {
+            Ok(value) => redact_settings(value),
+            Err(_) => serde_json::Value::Null,
+        }
+    }
 }
 
 impl Config for AoConfig {
@@ -48,4 +895,523 @@ impl Config for AoConfig {
     fn scheduler_list_path(&self) -> String {
         self.scheduler_list_path.clone()
     }
+    fn ingest_queue_depth(&self) -> usize {
+        self.ingest_queue_depth
+    }
+    fn ingest_workers(&self) -> usize {
+        self.ingest_workers
+    }
+    fn redirect_url_template(&self) -> Option<String> {
+        self.redirect_url_template.clone()
+    }
+    fn redirect_internal_url_template(&self) -> Option<String> {
+        self.redirect_internal_url_template.clone()
+    }
+    fn redirect_internal_hosts(&self) -> Vec<String> {
+        self.redirect_internal_hosts.clone()
+    }
+    fn redirect_port_override(&self) -> Option<u16> {
+        self.redirect_port_override
+    }
+    fn redirect_status(&self) -> u16 {
+        self.redirect_status
+    }
+    fn redirect_status_routes(&self) -> Vec<String> {
+        self.redirect_status_routes.clone()
+    }
+    fn redirect_body(&self) -> bool {
+        self.redirect_body
+    }
+    fn store_failover_grace_ms(&self) -> u64 {
+        self.store_failover_grace_ms
+    }
+    fn store_failover_queue_size(&self) -> usize {
+        self.store_failover_queue_size
+    }
+    fn write_restricted(&self) -> bool {
+        self.write_restricted
+    }
+    fn bind_address(&self) -> Option<String> {
+        self.bind_address.clone()
+    }
+    fn trusted_proxies(&self) -> Vec<String> {
+        self.trusted_proxies.clone()
+    }
+    fn cache_max_entries(&self) -> usize {
+        self.cache_max_entries
+    }
+    fn event_stream_token(&self) -> Option<String> {
+        self.event_stream_token.clone()
+    }
+    fn primary_events_url(&self) -> Option<String> {
+        self.primary_events_url.clone()
+    }
+    fn rate_limit_per_second(&self) -> f64 {
+        self.rate_limit_per_second
+    }
+    fn rate_limit_burst(&self) -> u32 {
+        self.rate_limit_burst
+    }
+    fn schedule_lock_deadline_ms(&self) -> u64 {
+        self.schedule_lock_deadline_ms
+    }
+    fn schedule_lock_idle_ms(&self) -> u64 {
+        self.schedule_lock_idle_ms
+    }
+    fn spawn_allowed_owners(&self) -> Vec<String> {
+        self.spawn_allowed_owners.clone()
+    }
+    fn spawn_denied_owners(&self) -> Vec<String> {
+        self.spawn_denied_owners.clone()
+    }
+    fn spawn_allowed_modules(&self) -> Vec<String> {
+        self.spawn_allowed_modules.clone()
+    }
+    fn spawn_denied_modules(&self) -> Vec<String> {
+        self.spawn_denied_modules.clone()
+    }
+    fn request_deadline_ms(&self) -> u64 {
+        self.request_deadline_ms
+    }
+    fn max_item_size(&self) -> usize {
+        self.max_item_size
+    }
+    fn max_process_size(&self) -> usize {
+        self.max_process_size
+    }
+    fn auto_migrate(&self) -> bool {
+        self.auto_migrate
+    }
+    fn max_inflight_writes(&self) -> usize {
+        self.max_inflight_writes
+    }
+    fn write_queue_depth(&self) -> usize {
+        self.write_queue_depth
+    }
+    fn process_queue_depth(&self) -> usize {
+        self.process_queue_depth
+    }
+    fn shutdown_timeout_ms(&self) -> u64 {
+        self.shutdown_timeout_ms
+    }
+    fn admin_token(&self) -> Option<String> {
+        self.admin_token.clone()
+    }
+    fn backup_s3_bucket(&self) -> Option<String> {
+        self.backup_s3_bucket.clone()
+    }
+    fn backup_s3_endpoint(&self) -> Option<String> {
+        self.backup_s3_endpoint.clone()
+    }
+    fn backup_s3_region(&self) -> String {
+        self.backup_s3_region.clone()
+    }
+    fn backup_s3_prefix(&self) -> String {
+        self.backup_s3_prefix.clone()
+    }
+    fn backup_s3_access_key(&self) -> Option<String> {
+        self.backup_s3_access_key.clone()
+    }
+    fn backup_s3_secret_key(&self) -> Option<String> {
+        self.backup_s3_secret_key.clone()
+    }
+    fn backup_interval_ms(&self) -> u64 {
+        self.backup_interval_ms
+    }
+    fn backup_retain(&self) -> usize {
+        self.backup_retain
+    }
+    fn backup_restore_on_start(&self) -> bool {
+        self.backup_restore_on_start
+    }
+    fn webhook_urls(&self) -> Vec<String> {
+        self.webhook_urls.clone()
+    }
+    fn webhook_secret(&self) -> Option<String> {
+        self.webhook_secret.clone()
+    }
+    fn webhook_max_attempts(&self) -> u32 {
+        self.webhook_max_attempts
+    }
+    fn webhook_timeout_ms(&self) -> u64 {
+        self.webhook_timeout_ms
+    }
+    fn event_publisher(&self) -> String {
+        self.event_publisher.clone()
+    }
+    fn event_publisher_url(&self) -> Option<String> {
+        self.event_publisher_url.clone()
+    }
+    fn event_publisher_topic(&self) -> String {
+        self.event_publisher_topic.clone()
+    }
+    fn compress_responses(&self) -> bool {
+        self.compress_responses
+    }
+    fn accept_compressed_requests(&self) -> bool {
+        self.accept_compressed_requests
+    }
+    fn tag_policy_path(&self) -> Option<String> {
+        self.tag_policy_path.clone()
+    }
+    fn strict_scheduler_tag(&self) -> bool {
+        self.strict_scheduler_tag
+    }
+    fn scheduler_location_owner(&self) -> Option<String> {
+        self.scheduler_location_owner.clone()
+    }
+    fn scheduler_location_url(&self) -> Option<String> {
+        self.scheduler_location_url.clone()
+    }
+    fn scheduler_location_ttl_ms(&self) -> u64 {
+        self.scheduler_location_ttl_ms
+    }
+    fn tenant_wallet_paths(&self) -> Vec<String> {
+        self.tenant_wallet_paths.clone()
+    }
+    fn upload_verify_sample(&self) -> u64 {
+        self.upload_verify_sample
+    }
+    fn upload_verify_after_ms(&self) -> u64 {
+        self.upload_verify_after_ms
+    }
+    fn upload_verify_timeout_ms(&self) -> u64 {
+        self.upload_verify_timeout_ms
+    }
+    fn upload_repair_after_ms(&self) -> u64 {
+        self.upload_repair_after_ms
+    }
+    fn attestation_interval_ms(&self) -> u64 {
+        self.attestation_interval_ms
+    }
+    fn signed_read_max_age_ms(&self) -> u64 {
+        self.signed_read_max_age_ms
+    }
+    fn sign_reads(&self) -> bool {
+        self.sign_reads
+    }
+    fn log_level(&self) -> String {
+        self.log_level.clone()
+    }
+    fn route_cache_ttl_ms(&self) -> u64 {
+        self.route_cache_ttl_ms
+    }
+    fn route_cache_max_entries(&self) -> usize {
+        self.route_cache_max_entries
+    }
+    fn assignment_strategy(&self) -> String {
+        self.assignment_strategy.clone()
+    }
+    fn hash_virtual_nodes(&self) -> u32 {
+        self.hash_virtual_nodes
+    }
+    fn scheduler_admin_token(&self) -> Option<String> {
+        self.scheduler_admin_token.clone()
+    }
+    fn rebalance_max_moves(&self) -> usize {
+        self.rebalance_max_moves
+    }
+    fn rebalance_idle_ms(&self) -> u64 {
+        self.rebalance_idle_ms
+    }
+    fn rebalance_tolerance(&self) -> f64 {
+        self.rebalance_tolerance
+    }
+    fn max_import_size(&self) -> usize {
+        self.max_import_size
+    }
+    fn scheduler_stats_interval_ms(&self) -> u64 {
+        self.scheduler_stats_interval_ms
+    }
+    fn read_only(&self) -> bool {
+        self.read_only
+    }
+    fn retention_days(&self) -> u64 {
+        self.retention_days
+    }
+    fn retention_max_messages(&self) -> u64 {
+        self.retention_max_messages
+    }
+    fn retention_delete_rows(&self) -> bool {
+        self.retention_delete_rows
+    }
+    fn retention_interval_ms(&self) -> u64 {
+        self.retention_interval_ms
+    }
+    fn cold_storage_after_days(&self) -> u64 {
+        self.cold_storage_after_days
+    }
+    fn cold_segment_size(&self) -> u64 {
+        self.cold_segment_size
+    }
+    fn cold_storage_interval_ms(&self) -> u64 {
+        self.cold_storage_interval_ms
+    }
+    fn http_connect_timeout_ms(&self) -> u64 {
+        self.http_connect_timeout_ms
+    }
+    fn http_request_timeout_ms(&self) -> u64 {
+        self.http_request_timeout_ms
+    }
+    fn http_pool_idle_timeout_ms(&self) -> u64 {
+        self.http_pool_idle_timeout_ms
+    }
+    fn http_pool_max_idle_per_host(&self) -> usize {
+        self.http_pool_max_idle_per_host
+    }
+    fn keep_alive_ms(&self) -> u64 {
+        self.keep_alive_ms
+    }
+    fn gateway_timeout_ms(&self) -> u64 {
+        self.gateway_timeout_ms
+    }
+    fn gateway_breaker_failures(&self) -> u32 {
+        self.gateway_breaker_failures
+    }
+    fn gateway_breaker_open_ms(&self) -> u64 {
+        self.gateway_breaker_open_ms
+    }
+    fn upload_timeout_ms(&self) -> u64 {
+        self.upload_timeout_ms
+    }
+    fn upload_breaker_failures(&self) -> u32 {
+        self.upload_breaker_failures
+    }
+    fn upload_breaker_open_ms(&self) -> u64 {
+        self.upload_breaker_open_ms
+    }
+    fn scheduler_timeout_ms(&self) -> u64 {
+        self.scheduler_timeout_ms
+    }
+    fn scheduler_breaker_failures(&self) -> u32 {
+        self.scheduler_breaker_failures
+    }
+    fn scheduler_breaker_open_ms(&self) -> u64 {
+        self.scheduler_breaker_open_ms
+    }
+    fn tls_cert_path(&self) -> Option<String> {
+        self.tls_cert_path.clone()
+    }
+    fn tls_key_path(&self) -> Option<String> {
+        self.tls_key_path.clone()
+    }
+    fn tls_client_ca_path(&self) -> Option<String> {
+        self.tls_client_ca_path.clone()
+    }
+    fn scheduler_tls_ca_path(&self) -> Option<String> {
+        self.scheduler_tls_ca_path.clone()
+    }
+    fn scheduler_tls_cert_path(&self) -> Option<String> {
+        self.scheduler_tls_cert_path.clone()
+    }
+    fn scheduler_tls_key_path(&self) -> Option<String> {
+        self.scheduler_tls_key_path.clone()
+    }
+    fn cors_allowed_origins(&self) -> Vec<String> {
+        self.cors_allowed_origins.clone()
+    }
+    fn cors_allowed_methods(&self) -> Vec<String> {
+        self.cors_allowed_methods.clone()
+    }
+    fn cors_allowed_headers(&self) -> Vec<String> {
+        self.cors_allowed_headers.clone()
+    }
+    fn cors_max_age_secs(&self) -> u64 {
+        self.cors_max_age_secs
+    }
+    fn boot_message_tags(&self) -> Vec<String> {
+        self.boot_message_tags.clone()
+    }
+    fn cron_mode(&self) -> String {
+        self.cron_mode.clone()
+    }
+    fn cron_interval_ms(&self) -> u64 {
+        self.cron_interval_ms
+    }
+    fn cron_max_catch_up(&self) -> u32 {
+        self.cron_max_catch_up
+    }
+    fn module_validation(&self) -> bool {
+        self.module_validation
+    }
+    fn module_required_tags(&self) -> Vec<String> {
+        self.module_required_tags.clone()
+    }
+    fn module_cache_max_entries(&self) -> usize {
+        self.module_cache_max_entries
+    }
+    fn router_region_header(&self) -> String {
+        self.router_region_header.clone()
+    }
+    fn upload_receipt_wait_ms(&self) -> u64 {
+        self.upload_receipt_wait_ms
+    }
+    fn upload_mode(&self) -> String {
+        self.upload_mode.clone()
+    }
+    fn arweave_node_url(&self) -> Option<String> {
+        self.arweave_node_url.clone()
+    }
+    fn direct_bundle_max_items(&self) -> usize {
+        self.direct_bundle_max_items
+    }
+    fn direct_bundle_max_bytes(&self) -> usize {
+        self.direct_bundle_max_bytes
+    }
+    fn direct_bundle_interval_ms(&self) -> u64 {
+        self.direct_bundle_interval_ms
+    }
+    fn funding_warn_winston(&self) -> u64 {
+        self.funding_warn_winston
+    }
+    fn funding_stop_winston(&self) -> u64 {
+        self.funding_stop_winston
+    }
+    fn funding_check_interval_ms(&self) -> u64 {
+        self.funding_check_interval_ms
+    }
+    fn upload_chunked_above_bytes(&self) -> u64 {
+        self.upload_chunked_above_bytes
+    }
+    fn upload_chunk_bytes(&self) -> u64 {
+        self.upload_chunk_bytes
+    }
+    fn store_group_commit_ms(&self) -> u64 {
+        self.store_group_commit_ms
+    }
+    fn store_group_commit_max(&self) -> usize {
+        self.store_group_commit_max
+    }
+    fn min_nonce_wait_ms(&self) -> u64 {
+        self.min_nonce_wait_ms
+    }
+    fn cluster_node_url(&self) -> Option<String> {
+        self.cluster_node_url.clone()
+    }
+    fn process_lease_ms(&self) -> u64 {
+        self.process_lease_ms
+    }
+    fn outbox_max_attempts(&self) -> u32 {
+        self.outbox_max_attempts
+    }
+    fn cron_min_interval_ms(&self) -> u64 {
+        self.cron_min_interval_ms
+    }
+    fn direct_queue_max_items(&self) -> usize {
+        self.direct_queue_max_items
+    }
+    fn proxy_protocol(&self) -> bool {
+        self.proxy_protocol
+    }
+    fn subscribe_max_per_process(&self) -> usize {
+        self.subscribe_max_per_process
+    }
+    fn subscribe_max_total(&self) -> usize {
+        self.subscribe_max_total
+    }
+    fn min_nonce_max_waiters(&self) -> usize {
+        self.min_nonce_max_waiters
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_invalid_cors() {
+        let list =
+            |values: &[&str]| -> Vec<String> { values.iter().map(|v| v.to_string()).collect() };
+        assert!(invalid_cors(
+            &list(&["*", "https://app.example.com", "http://localhost:3000"]),
+            &list(&["GET", "POST", "OPTIONS"]),
+            &list(&["*", "Content-Type", "X-Signed-Read"]),
+        )
+        .is_empty());
+        assert_eq!(
+            invalid_cors(
+                &list(&["app.example.com", "https://app.example.com/"]),
+                &list(&["GE T"]),
+                &list(&["Content Type"]),
+            )
+            .len(),
+            4
+        );
+    }
+
+    #[test]
+    fn test_split_flags() {
+        let args = vec![
+            "su",
+            "start",
+            "--config",
+            "su.toml",
+            "su",
+            "--max-item-size=100",
+            "9000",
+        ]
+        .into_iter()
+        .map(String::from)
+        .collect();
+        let (positional, flags) = split_flags(args).unwrap();
+        assert_eq!(positional, vec!["su", "start", "su", "9000"]);
+        assert_eq!(
+            flags,
+            vec![
+                ("CONFIG".to_string(), "su.toml".to_string()),
+                ("MAX_ITEM_SIZE".to_string(), "100".to_string()),
+            ]
+        );
+        assert!(split_flags(vec!["--mode".to_string()]).is_err());
+    }
+
+    #[test]
+    fn test_parse_config_file() {
+        let settings = parse_config_file(
+            r#"
+            gateway_url = "https://arweave.net"
+            max_item_size = 100
+            write_restricted = true
+            trusted_proxies = ["10.0.0.1", "10.0.0.2"]
+            "#,
+        )
+        .unwrap();
+        assert!(settings.contains(&("GATEWAY_URL".to_string(), "https://arweave.net".to_string())));
+        assert!(settings.contains(&("MAX_ITEM_SIZE".to_string(), "100".to_string())));
+        assert!(settings.contains(&("WRITE_RESTRICTED".to_string(), "true".to_string())));
+        assert!(settings.contains(&(
+            "TRUSTED_PROXIES".to_string(),
+            "10.0.0.1,10.0.0.2".to_string()
+        )));
+        assert!(parse_config_file("[backup]\nretain = 1").is_err());
+    }
+
+    #[test]
+    fn test_redact_settings() {
+        let redacted = redact_settings(serde_json::json!({
+            "mode": "su",
+            "database_url": "postgres://su:secret@db:5432/su",
+            "webhook_urls": ["https://hooks.example.com/in?token=abc", "https://key@example.com/"],
+            "event_publisher_url": "not a url",
+            "admin_token": "token",
+            "scheduler_admin_token": null,
+            "some_new_setting": "value",
+        }));
+        assert_eq!(redacted["mode"], "su");
+        assert_eq!(
+            redacted["database_url"],
+            "postgres://REDACTED:REDACTED@db:5432/su"
+        );
+        assert_eq!(
+            redacted["webhook_urls"],
+            serde_json::json!([
+                "https://hooks.example.com/in?token=REDACTED",
+                "https://REDACTED@example.com/"
+            ])
+        );
+        assert_eq!(redacted["event_publisher_url"], "REDACTED");
+        assert_eq!(redacted["admin_token"], "REDACTED");
+        assert!(redacted["scheduler_admin_token"].is_null());
+        assert_eq!(redacted["some_new_setting"], "REDACTED");
+    }
 }
@@ -0,0 +1,124 @@
+use bundlr_sdk::tags::Tag;
+
+use super::bytes::DataItem;
+use super::json::Process;
+
+/*
+    Read access of a process chosen by its owner at spawn.
+    A process tagged Read-Access: Private is only read for
+    its owner and the addresses in its Reader tags, anyone
+    else is refused. The su still uploads every bundle to
+    arweave, this only covers what the su itself serves.
+*/
+pub struct ReadAccess {
+    readers: Vec<String>,
+}
+
+impl ReadAccess {
+    // None for a public process
+    pub fn from_process(process: &Process) -> Option<ReadAccess> {
+        Self::from_tags(&process.tags, &process.owner.address)
+    }
+
+    pub fn from_tags(tags: &[Tag], owner: &str) -> Option<ReadAccess> {
+        let private = tags
+            .iter()
+            .any(|t| t.name == "Read-Access" && t.value.eq_ignore_ascii_case("private"));
+        if !private {
+            return None;
+        }
+        let mut readers = vec![owner.to_string()];
+        readers.extend(
+            tags.iter()
+                .filter(|t| t.name == "Reader")
+                .map(|t| t.value.clone()),
+        );
+        Some(ReadAccess { readers })
+    }
+
+    pub fn allows(&self, address: &str) -> bool {
+        self.readers.iter().any(|r| r == address)
+    }
+}
+
+/*
+    A signed read is a data item without data, tagged
+    Action: Read, Process and Timestamp, sent base64url
+    encoded in the X-Signed-Read header. Returns the
+    address that signed it.
+*/
+pub fn verify_signed_read(
+    encoded: &str,
+    process_id: &str,
+    now_ms: i64,
+    max_age_ms: i64,
+) -> Result<String, String> {
+    let bytes = base64_url::decode(encoded).map_err(|_| "Signed read is not base64url")?;
+    let item = DataItem::from_bytes(bytes).map_err(|e| format!("Invalid signed read: {:?}", e))?;
+    item.verify_signature()
+        .map_err(|_| "Invalid signed read signature".to_string())?;
+    check_read_tags(item.tags(), process_id, now_ms, max_age_ms)?;
+    Ok(item.owner_address())
+}
+
+// a signed read is only good for one process for a short while
+fn check_read_tags(
+    tags: &[Tag],
+    process_id: &str,
+    now_ms: i64,
+    max_age_ms: i64,
+) -> Result<(), String> {
+    let tag = |name: &str| {
+        tags.iter()
+            .find(|t| t.name == name)
+            .map(|t| t.value.as_str())
+    };
+    if tag("Action") != Some("Read") {
+        return Err("Signed read needs an Action: Read tag".to_string());
+    }
+    if tag("Process") != Some(process_id) {
+        return Err("Signed read is for another process".to_string());
+    }
+    let timestamp: i64 = tag("Timestamp")
+        .and_then(|t| t.parse().ok())
+        .ok_or("Signed read needs a Timestamp tag in milliseconds")?;
+    if (now_ms - timestamp).abs() > max_age_ms {
+        return Err("Signed read has expired".to_string());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tag(name: &str, value: &str) -> Tag {
+        Tag::new(name, value)
+    }
+
+    #[test]
+    fn test_read_access() {
+        assert!(ReadAccess::from_tags(&[tag("Read-Access", "public")], "owner").is_none());
+
+        let access = ReadAccess::from_tags(
+            &[tag("Read-Access", "Private"), tag("Reader", "cu-wallet")],
+            "owner",
+        )
+        .unwrap();
+        assert!(access.allows("owner") && access.allows("cu-wallet"));
+        assert!(!access.allows("someone"));
+    }
+
+    #[test]
+    fn test_read_tags() {
+        let tags = vec![
+            tag("Action", "Read"),
+            tag("Process", "process"),
+            tag("Timestamp", "1000"),
+        ];
+        assert!(check_read_tags(&tags, "process", 2000, 5000).is_ok());
+        assert!(check_read_tags(&tags, "other", 2000, 5000).is_err());
+        assert!(check_read_tags(&tags, "process", 7000, 5000).is_err());
+        assert!(check_read_tags(&tags[1..], "process", 2000, 5000).is_err());
+    }
+}
@@ -0,0 +1,183 @@
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use serde::Serialize;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+use super::dal::FlowError;
+
+/*
+    Admission control for writes. At most max_in_flight
+    writes build and sequence at once, up to queue_depth
+    more wait for a slot and anything beyond that is
+    rejected right away so load sheds instead of memory
+    growing with every waiting body. Once draining for
+    shutdown no new writes are admitted.
+*/
+pub struct WriteAdmission {
+    permits: Arc<Semaphore>,
+    waiting: AtomicUsize,
+    draining: AtomicBool,
+    max_in_flight: usize,
+    queue_depth: usize,
+}
+
+// holding this is a write slot, it is given back on drop
+pub struct WritePermit {
+    _permit: OwnedSemaphorePermit,
+}
+
+impl WriteAdmission {
+    pub fn new(max_in_flight: usize, queue_depth: usize) -> Self {
+        WriteAdmission {
+            permits: Arc::new(Semaphore::new(max_in_flight.max(1))),
+            waiting: AtomicUsize::new(0),
+            draining: AtomicBool::new(false),
+            max_in_flight: max_in_flight.max(1),
+            queue_depth,
+        }
+    }
+
+    pub async fn admit(&self) -> Result<WritePermit, FlowError> {
+        if self.draining.load(Ordering::SeqCst) {
+            return Err(FlowError::Unavailable(
+                "Server is shutting down, try again later".to_string(),
+            ));
+        }
+        if let Ok(permit) = self.permits.clone().try_acquire_owned() {
+            return Ok(WritePermit { _permit: permit });
+        }
+
+        // counted until admitted, even if the request goes away while waiting
+        let waiter = Waiter::enter(&self.waiting);
+        if waiter.position >= self.queue_depth {
+            return Err(FlowError::Unavailable(
+                "Write queue is full, try again later".to_string(),
+            ));
+        }
+        let permit = self.permits.clone().acquire_owned().await;
+        drop(waiter);
+
+        match permit {
+            Ok(p) => Ok(WritePermit { _permit: p }),
+            Err(_) => Err(FlowError::Unavailable(
+                "Write admission is closed".to_string(),
+            )),
+        }
+    }
+
+    /*
+        a slot for a write that already waited its turn in
+        a process queue, the process queues bound how many
+        wait here so it is not turned away for a full queue
+    */
+    pub async fn start(&self) -> Result<WritePermit, FlowError> {
+        if self.draining.load(Ordering::SeqCst) {
+            return Err(FlowError::Unavailable(
+                "Server is shutting down, try again later".to_string(),
+            ));
+        }
+        let waiter = Waiter::enter(&self.waiting);
+        let permit = self.permits.clone().acquire_owned().await;
+        drop(waiter);
+
+        match permit {
+            Ok(p) => Ok(WritePermit { _permit: p }),
+            Err(_) => Err(FlowError::Unavailable(
+                "Write admission is closed".to_string(),
+            )),
+        }
+    }
+
+    /*
+        stop admitting writes and wait for every admitted
+        one to finish, including those already queued
+    */
+    pub async fn drain(&self) {
+        self.draining.store(true, Ordering::SeqCst);
+        // the semaphore is fair, queued writes get their slot first
+        let _ = self.permits.acquire_many(self.max_in_flight as u32).await;
+    }
+
+    pub fn is_draining(&self) -> bool {
+        self.draining.load(Ordering::SeqCst)
+    }
+
+    pub fn stats(&self) -> AdmissionStats {
+        AdmissionStats {
+            writes_in_flight: self.max_in_flight - self.permits.available_permits(),
+            writes_waiting: self.waiting.load(Ordering::SeqCst),
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct AdmissionStats {
+    pub writes_in_flight: usize,
+    pub writes_waiting: usize,
+}
+
+struct Waiter<'a> {
+    waiting: &'a AtomicUsize,
+    position: usize,
+}
+
+impl<'a> Waiter<'a> {
+    fn enter(waiting: &'a AtomicUsize) -> Self {
+        let position = waiting.fetch_add(1, Ordering::SeqCst);
+        Waiter { waiting, position }
+    }
+}
+
+impl Drop for Waiter<'_> {
+    fn drop(&mut self) {
+        self.waiting.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_write_admission() {
+        let admission = Arc::new(WriteAdmission::new(1, 1));
+
+        let first = admission.admit().await.expect("first write admitted");
+
+        let queued = {
+            let admission = admission.clone();
+            tokio::spawn(async move { admission.admit().await.is_ok() })
+        };
+        tokio::task::yield_now().await;
+        while admission.waiting.load(Ordering::SeqCst) == 0 {
+            tokio::task::yield_now().await;
+        }
+
+        // one running and one waiting, the next is turned away
+        assert!(admission.admit().await.is_err());
+
+        drop(first);
+        assert!(queued.await.expect("queued task"));
+    }
+
+    #[tokio::test]
+    async fn test_drain_waits_for_writes() {
+        let admission = Arc::new(WriteAdmission::new(2, 1));
+        let write = admission.admit().await.expect("write admitted");
+
+        let drained = {
+            let admission = admission.clone();
+            tokio::spawn(async move { admission.drain().await })
+        };
+        while !admission.draining.load(Ordering::SeqCst) {
+            tokio::task::yield_now().await;
+        }
+
+        assert!(admission.admit().await.is_err());
+        assert!(!drained.is_finished());
+
+        drop(write);
+        drained.await.expect("drained");
+    }
+}
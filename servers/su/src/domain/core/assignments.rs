@@ -0,0 +1,72 @@
+use serde::{Deserialize, Serialize};
+
+use super::attestations::ScheduleLeaf;
+use super::json::{JsonErrorType, Message};
+
+/*
+    The su's assignment of a message, where it was put in
+    the schedule of a process. It is stored on its own
+    next to the message so the schedule can be listed and
+    its hash chain checked without loading any message,
+    and it outlives messages moved to cold segments.
+*/
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Assignment {
+    pub process_id: String,
+    pub assignment_id: String,
+    pub message_id: String,
+    pub epoch: i32,
+    pub nonce: i32,
+    pub timestamp: i64,
+    pub hash_chain: String,
+    // None for assignments made before it was tagged
+    pub block_height: Option<String>,
+}
+
+impl Assignment {
+    pub fn from_message(message: &Message) -> Result<Assignment, JsonErrorType> {
+        Ok(Assignment {
+            process_id: message.process_id()?,
+            assignment_id: message.assignment_id()?,
+            message_id: message.message_id()?,
+            epoch: message.epoch()?,
+            nonce: message.nonce()?,
+            timestamp: message.timestamp()?,
+            hash_chain: message.hash_chain()?,
+            block_height: message.block_height().ok(),
+        })
+    }
+
+    pub fn sequence(&self) -> ProcessSequence {
+        ProcessSequence {
+            process_id: self.process_id.clone(),
+            epoch: self.epoch,
+            nonce: self.nonce,
+            hash_chain: self.hash_chain.clone(),
+            assignment_id: self.assignment_id.clone(),
+        }
+    }
+
+    pub fn leaf(&self) -> ScheduleLeaf {
+        ScheduleLeaf {
+            epoch: self.epoch,
+            nonce: self.nonce,
+            message_id: self.message_id.clone(),
+            hash_chain: self.hash_chain.clone(),
+        }
+    }
+}
+
+/*
+    The latest slot taken in the schedule of a process,
+    saved in the transaction of every write so the next
+    slot is found without reading the messages
+*/
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProcessSequence {
+    pub process_id: String,
+    pub epoch: i32,
+    pub nonce: i32,
+    pub hash_chain: String,
+    pub assignment_id: String,
+}
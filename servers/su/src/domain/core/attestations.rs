@@ -0,0 +1,359 @@
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use bundlr_sdk::tags::Tag;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tokio::sync::broadcast::error::RecvError;
+use tokio::time::sleep;
+
+use super::dal::{DomainEvent, FlowError};
+use super::flows::{process_builder, Deps};
+
+// leaves read from the store per query
+const LEAF_PAGE: i64 = 10000;
+
+/*
+    A signed statement of the schedule of a process up to
+    (epoch, nonce). root is the merkle root over every
+    message sequenced so far, the su signs it with the
+    wallet of the process's scheduler and uploads it to
+    arweave as attestation_id. Anyone holding an earlier
+    attestation can rebuild the tree from the messages the
+    su serves and see the su did not rewrite history.
+*/
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Attestation {
+    pub process_id: String,
+    pub epoch: i32,
+    pub nonce: i32,
+    // the hash chain of the last attested message
+    pub hash_chain: String,
+    pub root: String,
+    pub leaf_count: i64,
+    pub attestation_id: String,
+    pub timestamp: i64,
+    // base64url peaks of the tree, for the next attestation to extend
+    #[serde(skip)]
+    pub frontier: Vec<String>,
+}
+
+impl Attestation {
+    pub fn tags(&self) -> Vec<Tag> {
+        let tag = |name: &str, value: String| Tag::new(name, &value);
+        vec![
+            tag("Data-Protocol", "ao".to_string()),
+            tag("Variant", "ao.TN.1".to_string()),
+            tag("Type", "Attestation".to_string()),
+            tag("Process", self.process_id.clone()),
+            tag("Epoch", self.epoch.to_string()),
+            tag("Nonce", self.nonce.to_string()),
+            tag("Hash-Chain", self.hash_chain.clone()),
+            tag("Merkle-Root", self.root.clone()),
+            tag("Leaf-Count", self.leaf_count.to_string()),
+            tag("Timestamp", self.timestamp.to_string()),
+        ]
+    }
+}
+
+// one sequenced message of the schedule
+#[derive(Debug, Clone)]
+pub struct ScheduleLeaf {
+    pub epoch: i32,
+    pub nonce: i32,
+    pub message_id: String,
+    pub hash_chain: String,
+}
+
+impl ScheduleLeaf {
+    // sha256 of 0x00 and "epoch:nonce:message_id:hash_chain"
+    pub fn hash(&self) -> Vec<u8> {
+        let mut hasher = Sha256::new();
+        hasher.update([0u8]);
+        hasher.update(
+            format!(
+                "{}:{}:{}:{}",
+                self.epoch, self.nonce, self.message_id, self.hash_chain
+            )
+            .as_bytes(),
+        );
+        hasher.finalize().to_vec()
+    }
+}
+
+fn node(left: &[u8], right: &[u8]) -> Vec<u8> {
+    let mut hasher = Sha256::new();
+    hasher.update([1u8]);
+    hasher.update(left);
+    hasher.update(right);
+    hasher.finalize().to_vec()
+}
+
+/*
+    The peaks of the tree over the leaves so far, largest
+    first, one for each bit set in the leaf count. A new
+    leaf merges with the peaks of its size and the root
+    hashes the peaks together from the right. It is the
+    root of hashing the leaves in pairs level by level, an
+    odd node at the end of a level moving up unchanged,
+    but an attestation only reads the leaves sequenced
+    since the last one.
+*/
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Frontier {
+    peaks: Vec<(Vec<u8>, i64)>,
+}
+
+impl Frontier {
+    pub fn push(&mut self, leaf: Vec<u8>) {
+        let mut peak = (leaf, 1);
+        while self.peaks.last().is_some_and(|(_, size)| *size == peak.1) {
+            if let Some((left, size)) = self.peaks.pop() {
+                peak = (node(&left, &peak.0), size * 2);
+            }
+        }
+        self.peaks.push(peak);
+    }
+
+    pub fn root(&self) -> Option<Vec<u8>> {
+        self.peaks
+            .iter()
+            .rev()
+            .map(|(hash, _)| hash.clone())
+            .reduce(|right, left| node(&left, &right))
+    }
+
+    pub fn leaf_count(&self) -> i64 {
+        self.peaks.iter().map(|(_, size)| size).sum()
+    }
+
+    pub fn encode(&self) -> Vec<String> {
+        self.peaks
+            .iter()
+            .map(|(hash, _)| base64_url::encode(hash))
+            .collect()
+    }
+
+    pub fn decode(peaks: &[String], leaf_count: i64) -> Result<Self, String> {
+        let sizes: Vec<i64> = (0..63)
+            .rev()
+            .map(|bit| 1i64 << bit)
+            .filter(|size| leaf_count & size != 0)
+            .collect();
+        if sizes.len() != peaks.len() {
+            return Err(format!(
+                "{} peaks do not make a tree of {} leaves",
+                peaks.len(),
+                leaf_count
+            ));
+        }
+        let mut frontier = Frontier::default();
+        for (peak, size) in peaks.iter().zip(sizes) {
+            let hash = base64_url::decode(peak).map_err(|e| format!("invalid peak: {:?}", e))?;
+            frontier.peaks.push((hash, size));
+        }
+        Ok(frontier)
+    }
+}
+
+/*
+    Signs and uploads an attestation of the schedule of the
+    process, none when nothing was sequenced since the
+    last one.
+*/
+pub async fn attest_process(
+    deps: &Arc<Deps>,
+    process_id: &str,
+) -> Result<Option<Attestation>, FlowError> {
+    let last = deps.data_store.get_attestations(process_id, 1).await?.pop();
+
+    // attestations made before the frontier was kept read every leaf once more
+    let (mut frontier, mut after) = match &last {
+        Some(last) if !last.frontier.is_empty() => (
+            Frontier::decode(&last.frontier, last.leaf_count).map_err(FlowError::Internal)?,
+            Some((last.epoch, last.nonce)),
+        ),
+        _ => (Frontier::default(), None),
+    };
+    let mut tip: Option<ScheduleLeaf> = None;
+    loop {
+        let page = deps
+            .data_store
+            .get_assignments(process_id, &after, LEAF_PAGE)
+            .await?;
+        for assignment in page.iter() {
+            let leaf = assignment.leaf();
+            frontier.push(leaf.hash());
+            after = Some((leaf.epoch, leaf.nonce));
+            tip = Some(leaf);
+        }
+        if (page.len() as i64) < LEAF_PAGE {
+            break;
+        }
+    }
+
+    let tip = match tip {
+        Some(tip) => tip,
+        None => return Ok(None),
+    };
+    if last.is_some_and(|a| (a.epoch, a.nonce) == (tip.epoch, tip.nonce)) {
+        return Ok(None);
+    }
+
+    let root = frontier.root().unwrap_or_default();
+    let mut attestation = Attestation {
+        process_id: process_id.to_string(),
+        epoch: tip.epoch,
+        nonce: tip.nonce,
+        hash_chain: tip.hash_chain,
+        root: base64_url::encode(&root),
+        leaf_count: frontier.leaf_count(),
+        attestation_id: String::new(),
+        timestamp: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as i64)
+            .unwrap_or(0),
+        frontier: frontier.encode(),
+    };
+
+    let builder = process_builder(deps, process_id).await?;
+    let item = builder
+        .build_attestation(&attestation)
+        .await
+        .map_err(|e| FlowError::Internal(format!("{:?}", e)))?;
+    let binary = item
+        .as_bytes()
+        .map_err(|e| FlowError::Internal(format!("{:?}", e)))?;
+    deps.uploader.upload(binary.into())?;
+
+    attestation.attestation_id = item.id();
+    deps.data_store.save_attestation(&attestation).await?;
+    Ok(Some(attestation))
+}
+
+/*
+    Collects the processes that sequenced messages and
+    attests each of them every ATTESTATION_INTERVAL_MS, an
+    idle process is not attested again.
+*/
+pub fn spawn_attestations(deps: Arc<Deps>) {
+    let interval = match deps.config.attestation_interval_ms() {
+        0 => return,
+        ms => Duration::from_millis(ms),
+    };
+
+    let active: Arc<Mutex<HashSet<String>>> = Arc::new(Mutex::new(HashSet::new()));
+    let mut receiver = deps.events.subscribe();
+    let collected = active.clone();
+    tokio::spawn(async move {
+        loop {
+            match receiver.recv().await {
+                Ok(DomainEvent::MessageSequenced { message }) => {
+                    if let (Ok(process_id), Ok(mut active)) =
+                        (message.process_id(), collected.lock())
+                    {
+                        active.insert(process_id);
+                    }
+                }
+                Ok(_) => (),
+                // missed processes are attested after their next message
+                Err(RecvError::Lagged(_)) => (),
+                Err(RecvError::Closed) => break,
+            }
+        }
+    });
+
+    tokio::spawn(async move {
+        loop {
+            sleep(interval).await;
+            let process_ids: Vec<String> = match active.lock() {
+                Ok(mut active) => active.drain().collect(),
+                Err(_) => continue,
+            };
+            for process_id in process_ids.iter() {
+                match attest_process(&deps, process_id).await {
+                    Ok(Some(attestation)) => deps.logger.log(format!(
+                        "attested {} up to nonce {} as {}",
+                        process_id, attestation.nonce, attestation.attestation_id
+                    )),
+                    Ok(None) => (),
+                    Err(e) => deps
+                        .logger
+                        .error(format!("attestation of {} failed - {:?}", process_id, e)),
+                }
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /*
+        pairs are hashed as sha256 of 0x01, left and right, an
+        odd node at the end of a level moves up unchanged
+    */
+    fn merkle_root(leaves: &[Vec<u8>]) -> Option<Vec<u8>> {
+        let mut level = leaves.to_vec();
+        while level.len() > 1 {
+            level = level
+                .chunks(2)
+                .map(|pair| match pair {
+                    [left, right] => node(left, right),
+                    [single] => single.clone(),
+                    _ => unreachable!(),
+                })
+                .collect();
+        }
+        level.pop()
+    }
+
+    fn leaf(nonce: i32) -> ScheduleLeaf {
+        ScheduleLeaf {
+            epoch: 0,
+            nonce,
+            message_id: format!("message-{}", nonce),
+            hash_chain: format!("chain-{}", nonce),
+        }
+    }
+
+    #[test]
+    fn test_merkle_root() {
+        assert!(merkle_root(&[]).is_none());
+
+        let hashes: Vec<Vec<u8>> = (0..3).map(|n| leaf(n).hash()).collect();
+        assert_eq!(merkle_root(&hashes[..1]).unwrap(), hashes[0]);
+        assert_eq!(
+            merkle_root(&hashes).unwrap(),
+            node(&node(&hashes[0], &hashes[1]), &hashes[2])
+        );
+
+        // rewriting any message changes the root
+        let mut rewritten = hashes.clone();
+        rewritten[1] = ScheduleLeaf {
+            message_id: "other".to_string(),
+            ..leaf(1)
+        }
+        .hash();
+        assert_ne!(merkle_root(&rewritten), merkle_root(&hashes));
+    }
+
+    #[test]
+    fn test_frontier() {
+        let hashes: Vec<Vec<u8>> = (0..70).map(|n| leaf(n).hash()).collect();
+        let mut frontier = Frontier::default();
+        assert!(frontier.root().is_none());
+        for (count, hash) in hashes.iter().enumerate() {
+            frontier.push(hash.clone());
+            assert_eq!(frontier.root(), merkle_root(&hashes[..=count]));
+            assert_eq!(frontier.leaf_count(), count as i64 + 1);
+
+            // picked up again from what an attestation stores
+            let decoded = Frontier::decode(&frontier.encode(), frontier.leaf_count()).unwrap();
+            assert_eq!(decoded, frontier);
+        }
+        assert!(Frontier::decode(&frontier.encode(), 64).is_err());
+    }
+}
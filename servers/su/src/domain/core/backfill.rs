@@ -0,0 +1,292 @@
+use std::sync::Arc;
+
+use bundlr_sdk::tags::Tag;
+use bytes::Bytes;
+use serde_json::json;
+
+use super::bytes::{DataBundle, DataItem};
+use super::dal::{Message, Process, StoreErrorType};
+use super::flows::Deps;
+use super::scheduler::ChainCheck;
+use super::tenants::scheduler_tag;
+
+// assignments fetched per graphql page
+const PAGE_SIZE: i32 = 100;
+
+// messages saved per transaction
+const SAVE_BATCH: usize = 100;
+
+const ASSIGNMENTS_QUERY: &str = r#"
+query($owners: [String!], $tags: [TagFilter!], $first: Int, $after: String) {
+  transactions(owners: $owners, tags: $tags, first: $first, after: $after) {
+    pageInfo { hasNextPage }
+    edges { cursor node { id tags { name value } bundledIn { id } } }
+  }
+}"#;
+
+const BUNDLE_QUERY: &str = r#"
+query($ids: [ID!]) {
+  transactions(ids: $ids) {
+    edges { node { id anchor signature owner { address key } tags { name value } bundledIn { id } } }
+  }
+}"#;
+
+// where an assignment of the process sits in its schedule
+struct UploadedAssignment {
+    epoch: i32,
+    nonce: i32,
+    bundle_id: String,
+}
+
+fn tag_value(node: &serde_json::Value, name: &str) -> Option<String> {
+    node["tags"]
+        .as_array()?
+        .iter()
+        .find(|tag| tag["name"] == name)
+        .and_then(|tag| tag["value"].as_str())
+        .map(|value| value.to_string())
+}
+
+async fn transaction(deps: &Arc<Deps>, id: &str) -> Result<serde_json::Value, String> {
+    let data = deps
+        .gateway
+        .graphql(BUNDLE_QUERY, json!({ "ids": [id] }))
+        .await?;
+    match data["transactions"]["edges"]
+        .as_array()
+        .and_then(|e| e.first())
+    {
+        Some(edge) => Ok(edge["node"].clone()),
+        None => Err(format!("{} not found on the gateway", id)),
+    }
+}
+
+/*
+    Only the data of a bundle is served by the gateway,
+    the signed data item this su uploaded is rebuilt from
+    its header fields and must hash to the same id and
+    carry a valid signature from this su's wallet.
+*/
+async fn fetch_bundle(
+    deps: &Arc<Deps>,
+    bundle_id: &str,
+    su_address: &str,
+) -> Result<(Bytes, DataBundle), String> {
+    let node = transaction(deps, bundle_id).await?;
+    if node["owner"]["address"].as_str() != Some(su_address) {
+        return Err(format!("bundle {} was not uploaded by this su", bundle_id));
+    }
+
+    let decode = |field: &str| -> Result<Vec<u8>, String> {
+        base64_url::decode(node[field].as_str().unwrap_or_default())
+            .map_err(|e| format!("invalid {} of bundle {}: {:?}", field, bundle_id, e))
+    };
+    let tags: Vec<Tag> = node["tags"]
+        .as_array()
+        .ok_or(format!("bundle {} has no tags", bundle_id))?
+        .iter()
+        .map(|tag| {
+            Tag::new(
+                tag["name"].as_str().unwrap_or_default(),
+                tag["value"].as_str().unwrap_or_default(),
+            )
+        })
+        .collect();
+    let owner = base64_url::decode(node["owner"]["key"].as_str().unwrap_or_default())
+        .map_err(|e| format!("invalid owner of bundle {}: {:?}", bundle_id, e))?;
+
+    let data = deps.gateway.raw(&bundle_id.to_string()).await?;
+    let mut item =
+        DataItem::new(vec![], data, tags.clone(), owner).map_err(|e| format!("{:?}", e))?;
+    item.set_anchor(decode("anchor")?);
+    item.signature = decode("signature")?;
+
+    if item.id() != bundle_id || item.verify_signature().is_err() {
+        return Err(format!("bundle {} could not be rebuilt", bundle_id));
+    }
+
+    let mut bundle = item.nested_bundle().map_err(|e| format!("{:?}", e))?;
+    bundle.tags = tags;
+    let binary = item.as_bytes().map_err(|e| format!("{:?}", e))?;
+    Ok((binary.into(), bundle))
+}
+
+async fn restore_process(
+    deps: &Arc<Deps>,
+    process_id: &str,
+    su_address: &str,
+) -> Result<(), String> {
+    let node = transaction(deps, process_id).await?;
+    let bundle_id = node["bundledIn"]["id"]
+        .as_str()
+        .ok_or(format!("process {} was not bundled by a su", process_id))?;
+
+    let (binary, bundle) = fetch_bundle(deps, bundle_id, su_address).await?;
+    let process = Process::from_bundle(&bundle)?;
+    if process.process_id != process_id {
+        return Err(format!("bundle {} does not hold the process", bundle_id));
+    }
+    deps.data_store.save_process(&process, &binary).await?;
+    Ok(())
+}
+
+// tries each hosted scheduler, returns the one that uploaded the process
+async fn restore_process_any(deps: &Arc<Deps>, process_id: &str) -> Result<String, String> {
+    let mut last_error = String::new();
+    for su_address in deps.tenants.addresses().into_iter() {
+        match restore_process(deps, process_id, &su_address).await {
+            Ok(()) => return Ok(su_address),
+            Err(e) => last_error = e,
+        }
+    }
+    Err(last_error)
+}
+
+// every assignment of the process this su uploaded, in schedule order
+async fn uploaded_assignments(
+    deps: &Arc<Deps>,
+    process_id: &str,
+    su_address: &str,
+) -> Result<Vec<UploadedAssignment>, String> {
+    let mut assignments = vec![];
+    let mut after: Option<String> = None;
+    loop {
+        let variables = json!({
+            "owners": [su_address],
+            "tags": [{ "name": "Process", "values": [process_id] }],
+            "first": PAGE_SIZE,
+            "after": after,
+        });
+        let data = deps.gateway.graphql(ASSIGNMENTS_QUERY, variables).await?;
+        let page = &data["transactions"];
+        let edges = page["edges"].as_array().cloned().unwrap_or_default();
+
+        for edge in edges.iter() {
+            let node = &edge["node"];
+            let position = (
+                tag_value(node, "Epoch").and_then(|e| e.parse().ok()),
+                tag_value(node, "Nonce").and_then(|n| n.parse().ok()),
+                node["bundledIn"]["id"].as_str(),
+            );
+            if let (Some(epoch), Some(nonce), Some(bundle_id)) = position {
+                assignments.push(UploadedAssignment {
+                    epoch,
+                    nonce,
+                    bundle_id: bundle_id.to_string(),
+                });
+            }
+        }
+
+        match (page["pageInfo"]["hasNextPage"].as_bool(), edges.last()) {
+            (Some(true), Some(last)) => after = last["cursor"].as_str().map(|c| c.to_string()),
+            _ => break,
+        }
+    }
+
+    assignments.sort_by_key(|a| (a.epoch, a.nonce));
+    assignments.dedup_by_key(|a| (a.epoch, a.nonce));
+    Ok(assignments)
+}
+
+// where the stored schedule of the process continues
+async fn stored_chain(deps: &Arc<Deps>, process_id: &str) -> Result<ChainCheck, String> {
+    match deps.data_store.get_latest_message(process_id).await? {
+        Some(latest) => ChainCheck::resume(&latest),
+        None => ChainCheck::new(process_id),
+    }
+}
+
+/*
+    saves a batch under the process lock, taken for the
+    batch only so the lock and lease are renewed as the
+    backfill goes. The batch must continue the schedule
+    as stored, a write that got in between stops it
+*/
+async fn save_batch(
+    deps: &Arc<Deps>,
+    process_id: &str,
+    position: (i32, i32),
+    batch: &[(Message, Bytes)],
+) -> Result<(), String> {
+    let mut schedule_info = deps.scheduler.lock(process_id.to_string()).await?;
+    // whatever was cached about the schedule is re-read afterwards
+    schedule_info.synced = false;
+    if stored_chain(deps, process_id).await?.next_position() != position {
+        return Err(format!(
+            "process {} was written to during the backfill, run it again",
+            process_id
+        ));
+    }
+    schedule_info.check_held()?;
+    deps.data_store.save_messages(batch).await?;
+    Ok(())
+}
+
+/*
+    Rebuilds the store of a process from arweave after
+    the database was lost. The process and every message
+    this su sequenced for it are fetched from the gateway,
+    validated against the hash chain and saved, starting
+    after whatever the store still has. It stops at the
+    first gap, an assignment the gateway has not indexed
+    yet can be picked up by running it again. Bundles are
+    fetched without the process lock, it is only held to
+    save each batch.
+*/
+pub async fn backfill_process(deps: Arc<Deps>, process_id: String) -> Result<String, String> {
+    let (su_address, mut chain) = {
+        let mut schedule_info = deps.scheduler.lock(process_id.clone()).await?;
+        schedule_info.synced = false;
+        let su_address = match deps.data_store.get_process(&process_id).await {
+            Ok(process) => deps.tenants.address_for(&scheduler_tag(&process.tags)),
+            Err(StoreErrorType::NotFound(_)) => restore_process_any(&deps, &process_id).await?,
+            Err(e) => return Err(format!("{:?}", e)),
+        };
+        (su_address, stored_chain(&deps, &process_id).await?)
+    };
+
+    let mut restored = 0;
+    let mut saved = chain.next_position();
+    let mut batch: Vec<(Message, Bytes)> = vec![];
+    let mut stopped = None;
+    for assignment in uploaded_assignments(&deps, &process_id, &su_address).await? {
+        if (assignment.epoch, assignment.nonce) < chain.next_position() {
+            continue;
+        }
+
+        let result = match fetch_bundle(&deps, &assignment.bundle_id, &su_address).await {
+            Ok((binary, bundle)) => Message::from_bundle(&bundle)
+                .map_err(|e| format!("{:?}", e))
+                .and_then(|message| chain.check(&message).map(|_| (message, binary))),
+            Err(e) => Err(e),
+        };
+        match result {
+            Ok(restored_message) => batch.push(restored_message),
+            Err(e) => {
+                stopped = Some(e);
+                break;
+            }
+        }
+
+        if batch.len() >= SAVE_BATCH {
+            save_batch(&deps, &process_id, saved, &batch).await?;
+            restored += batch.len();
+            saved = chain.next_position();
+            batch.clear();
+        }
+    }
+    if !batch.is_empty() {
+        save_batch(&deps, &process_id, saved, &batch).await?;
+        restored += batch.len();
+    }
+
+    let (epoch, nonce) = chain.next_position();
+    let summary = format!(
+        "restored {} messages of process {}, next is epoch {} nonce {}",
+        restored, process_id, epoch, nonce
+    );
+    match stopped {
+        Some(e) => Ok(format!("{}, stopped early: {}", summary, e)),
+        None => Ok(summary),
+    }
+}
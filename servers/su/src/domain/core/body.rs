@@ -0,0 +1,214 @@
+use bytes::{Bytes, BytesMut};
+
+use super::bytes::{BlobHasher, DataItem};
+use super::dal::Config;
+
+// a write body and the deep hash of its data if it was read in pieces
+#[derive(Clone)]
+pub struct Body {
+    pub bytes: Bytes,
+    pub data_hash: Option<Bytes>,
+}
+
+impl From<Bytes> for Body {
+    fn from(bytes: Bytes) -> Self {
+        Body {
+            bytes,
+            data_hash: None,
+        }
+    }
+}
+
+impl From<Vec<u8>> for Body {
+    fn from(bytes: Vec<u8>) -> Self {
+        Body::from(Bytes::from(bytes))
+    }
+}
+
+impl Body {
+    pub fn parse(self) -> Result<DataItem, String> {
+        let mut item = DataItem::from_bytes(self.bytes).map_err(|e| format!("{:?}", e))?;
+        if let Some(hash) = self.data_hash {
+            item.set_data_hash(hash);
+        }
+        Ok(item)
+    }
+}
+
+enum Header {
+    Pending,
+    Read,
+    // left to the parser to reject
+    Invalid,
+}
+
+/*
+    Reads the body of a write as it arrives. Once the
+    header of the item is in, its tags decide between
+    MAX_ITEM_SIZE and MAX_PROCESS_SIZE and the read stops
+    at the first chunk past that limit, so an oversized
+    item is never held in full. Its data is hashed along
+    the way and verifying the signature later does not
+    go over it again.
+*/
+pub struct BodyReader {
+    buffer: BytesMut,
+    header: Header,
+    hasher: BlobHasher,
+    expected: Option<usize>,
+    limit: usize,
+    max_item_size: usize,
+    max_process_size: usize,
+}
+
+impl BodyReader {
+    // expected is the Content-Length, when the client sent one
+    pub fn new(config: &dyn Config, expected: Option<usize>) -> Result<Self, String> {
+        BodyReader::with_limits(config.max_item_size(), config.max_process_size(), expected)
+    }
+
+    fn with_limits(
+        max_item_size: usize,
+        max_process_size: usize,
+        expected: Option<usize>,
+    ) -> Result<Self, String> {
+        let mut reader = BodyReader {
+            buffer: BytesMut::new(),
+            header: Header::Pending,
+            hasher: BlobHasher::new(),
+            expected,
+            limit: max_item_size.max(max_process_size),
+            max_item_size,
+            max_process_size,
+        };
+        reader.check_size()?;
+        reader.buffer.reserve(expected.unwrap_or(0));
+        Ok(reader)
+    }
+
+    pub fn push(&mut self, chunk: &[u8]) -> Result<(), String> {
+        self.buffer.extend_from_slice(chunk);
+        match self.header {
+            Header::Pending => self.read_header(),
+            Header::Read => self.hasher.update(chunk),
+            Header::Invalid => (),
+        }
+        self.check_size()
+    }
+
+    pub fn finish(self) -> Body {
+        let data_hash = match self.header {
+            Header::Read => Some(self.hasher.finish()),
+            _ => None,
+        };
+        Body {
+            bytes: self.buffer.freeze(),
+            data_hash,
+        }
+    }
+
+    fn read_header(&mut self) {
+        let length = match DataItem::info_length(&self.buffer) {
+            Ok(Some(length)) if length <= self.buffer.len() => length,
+            Ok(_) => return,
+            Err(_) => {
+                self.header = Header::Invalid;
+                return;
+            }
+        };
+        let item = match DataItem::from_info_bytes(&self.buffer[..length]) {
+            Ok((item, _)) => item,
+            Err(_) => {
+                self.header = Header::Invalid;
+                return;
+            }
+        };
+
+        let spawns_process = item
+            .tags()
+            .iter()
+            .any(|tag| tag.name == "Type" && tag.value == "Process");
+        self.limit = match spawns_process {
+            true => self.max_process_size,
+            false => self.max_item_size,
+        };
+        self.hasher.update(&self.buffer[length..]);
+        self.header = Header::Read;
+    }
+
+    fn check_size(&self) -> Result<(), String> {
+        let size = self.expected.unwrap_or(0).max(self.buffer.len());
+        match size > self.limit {
+            true => Err(format!(
+                "Data item of {} bytes exceeds the limit of {} bytes",
+                size, self.limit
+            )),
+            false => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::core::bytes::{deep_hash_sync, DeepHashChunk};
+    use bundlr_sdk::tags::Tag;
+
+    fn item(item_type: &str, size: usize) -> DataItem {
+        let tags = vec![Tag::new("Type", item_type)];
+        let data = (0..size).map(|i| (i % 251) as u8).collect();
+        let mut item = DataItem::new(vec![], data, tags, vec![3; 512]).unwrap();
+        item.signature = vec![4; 512];
+        item
+    }
+
+    fn read(reader: &mut BodyReader, bytes: &[u8]) -> Result<(), String> {
+        bytes.chunks(1000).try_for_each(|chunk| reader.push(chunk))
+    }
+
+    #[test]
+    fn test_blob_hasher() {
+        let data: Vec<u8> = (0..10000).map(|i| (i % 251) as u8).collect();
+        let mut hasher = BlobHasher::new();
+        for piece in data.chunks(777) {
+            hasher.update(piece);
+        }
+        let whole = deep_hash_sync(DeepHashChunk::Chunk(data.into())).unwrap();
+        assert_eq!(hasher.finish(), whole);
+    }
+
+    #[test]
+    fn test_read_body() {
+        let message = item("Message", 20000);
+        let bytes = message.as_bytes().unwrap();
+        let mut reader = BodyReader::with_limits(30000, 60000, None).unwrap();
+        read(&mut reader, &bytes).unwrap();
+        let body = reader.finish();
+        assert!(body.data_hash.is_some());
+        assert_eq!(body.bytes, bytes);
+
+        // the hash taken while reading stands in for the data
+        let parsed = body.parse().unwrap();
+        assert_eq!(
+            parsed.get_message().unwrap(),
+            message.get_message().unwrap()
+        );
+    }
+
+    #[test]
+    fn test_body_limits() {
+        let message = item("Message", 40000).as_bytes().unwrap();
+        let process = item("Process", 40000).as_bytes().unwrap();
+
+        let mut reader = BodyReader::with_limits(30000, 60000, None).unwrap();
+        let err = read(&mut reader, &message).unwrap_err();
+        assert!(err.contains("limit of 30000 bytes"));
+        // turned away at the first chunk past the limit
+        assert!(reader.buffer.len() <= 31000);
+
+        let mut reader = BodyReader::with_limits(30000, 60000, None).unwrap();
+        assert!(read(&mut reader, &process).is_ok());
+
+        assert!(BodyReader::with_limits(30000, 60000, Some(70000)).is_err());
+    }
+}
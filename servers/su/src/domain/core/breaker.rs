@@ -0,0 +1,263 @@
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::Serialize;
+
+use super::dal::Config;
+
+fn unix_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum BreakerState {
+    Closed,
+    Open,
+    // one trial request is let through to see if the upstream is back
+    HalfOpen,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct BreakerStats {
+    pub state: BreakerState,
+    pub consecutive_failures: u32,
+    // how many times the breaker opened
+    pub trips: u64,
+    // requests turned away while it was open
+    pub rejected: u64,
+}
+
+struct BreakerInner {
+    consecutive_failures: u32,
+    // when it opened, or when the latest trial was let through
+    opened_at: Option<u64>,
+    half_open: bool,
+    trips: u64,
+    rejected: u64,
+}
+
+/*
+    Stops calling an upstream after failures in a row, so
+    a slow or down dependency fails requests right away
+    instead of holding every one of them until it times
+    out. After open_ms one trial request goes through, it
+    closes the breaker again or keeps it open for another
+    open_ms. A threshold of 0 never opens it.
+*/
+pub struct CircuitBreaker {
+    name: String,
+    failure_threshold: u32,
+    open_ms: u64,
+    inner: Mutex<BreakerInner>,
+}
+
+impl CircuitBreaker {
+    pub fn new(name: &str, failure_threshold: u32, open_ms: u64) -> Self {
+        CircuitBreaker {
+            name: name.to_string(),
+            failure_threshold,
+            open_ms,
+            inner: Mutex::new(BreakerInner {
+                consecutive_failures: 0,
+                opened_at: None,
+                half_open: false,
+                trips: 0,
+                rejected: 0,
+            }),
+        }
+    }
+
+    pub fn allow(&self) -> Result<(), String> {
+        self.allow_at(unix_ms())
+    }
+
+    fn allow_at(&self, now: u64) -> Result<(), String> {
+        let mut inner = match self.inner.lock() {
+            Ok(inner) => inner,
+            Err(_) => return Ok(()),
+        };
+        let opened_at = match inner.opened_at {
+            Some(opened_at) => opened_at,
+            None => return Ok(()),
+        };
+        let retry_at = opened_at.saturating_add(self.open_ms);
+        if now >= retry_at {
+            inner.opened_at = Some(now);
+            inner.half_open = true;
+            return Ok(());
+        }
+        inner.rejected += 1;
+        Err(format!(
+            "{} circuit is open after {} failures, retry in {}ms",
+            self.name,
+            inner.consecutive_failures,
+            retry_at - now
+        ))
+    }
+
+    pub fn record(&self, success: bool) {
+        self.record_at(success, unix_ms())
+    }
+
+    fn record_at(&self, success: bool, now: u64) {
+        let mut inner = match self.inner.lock() {
+            Ok(inner) => inner,
+            Err(_) => return,
+        };
+        if success {
+            inner.consecutive_failures = 0;
+            inner.opened_at = None;
+            inner.half_open = false;
+            return;
+        }
+        inner.consecutive_failures = inner.consecutive_failures.saturating_add(1);
+        let trips = self.failure_threshold > 0
+            && (inner.half_open || inner.consecutive_failures == self.failure_threshold);
+        if trips {
+            inner.opened_at = Some(now);
+            inner.half_open = false;
+            inner.trips += 1;
+        }
+    }
+
+    pub fn stats(&self) -> BreakerStats {
+        self.stats_at(unix_ms())
+    }
+
+    fn stats_at(&self, now: u64) -> BreakerStats {
+        let inner = match self.inner.lock() {
+            Ok(inner) => inner,
+            Err(_) => {
+                return BreakerStats {
+                    state: BreakerState::Closed,
+                    consecutive_failures: 0,
+                    trips: 0,
+                    rejected: 0,
+                }
+            }
+        };
+        let state = match inner.opened_at {
+            None => BreakerState::Closed,
+            Some(_) if inner.half_open => BreakerState::HalfOpen,
+            Some(opened_at) if now >= opened_at.saturating_add(self.open_ms) => {
+                BreakerState::HalfOpen
+            }
+            Some(_) => BreakerState::Open,
+        };
+        BreakerStats {
+            state,
+            consecutive_failures: inner.consecutive_failures,
+            trips: inner.trips,
+            rejected: inner.rejected,
+        }
+    }
+}
+
+/*
+    One breaker per upstream, the gateway, the upload node
+    and in router mode each scheduler it polls.
+*/
+pub struct Breakers {
+    pub gateway: Arc<CircuitBreaker>,
+    pub uploader: Arc<CircuitBreaker>,
+    scheduler_failures: u32,
+    scheduler_open_ms: u64,
+    schedulers: Mutex<BTreeMap<String, Arc<CircuitBreaker>>>,
+}
+
+impl Breakers {
+    pub fn new(config: &dyn Config) -> Self {
+        Breakers {
+            gateway: Arc::new(CircuitBreaker::new(
+                "gateway",
+                config.gateway_breaker_failures(),
+                config.gateway_breaker_open_ms(),
+            )),
+            uploader: Arc::new(CircuitBreaker::new(
+                "upload node",
+                config.upload_breaker_failures(),
+                config.upload_breaker_open_ms(),
+            )),
+            scheduler_failures: config.scheduler_breaker_failures(),
+            scheduler_open_ms: config.scheduler_breaker_open_ms(),
+            schedulers: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    pub fn scheduler(&self, url: &str) -> Arc<CircuitBreaker> {
+        let new_breaker = || {
+            Arc::new(CircuitBreaker::new(
+                &format!("scheduler {}", url),
+                self.scheduler_failures,
+                self.scheduler_open_ms,
+            ))
+        };
+        match self.schedulers.lock() {
+            Ok(mut schedulers) => schedulers
+                .entry(url.to_string())
+                .or_insert_with(new_breaker)
+                .clone(),
+            Err(_) => new_breaker(),
+        }
+    }
+
+    pub fn stats(&self) -> serde_json::Value {
+        let schedulers: BTreeMap<String, BreakerStats> = match self.schedulers.lock() {
+            Ok(schedulers) => schedulers
+                .iter()
+                .map(|(url, breaker)| (url.clone(), breaker.stats()))
+                .collect(),
+            Err(_) => BTreeMap::new(),
+        };
+        serde_json::json!({
+            "gateway": self.gateway.stats(),
+            "upload_node": self.uploader.stats(),
+            "schedulers": schedulers,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_circuit_breaker() {
+        let breaker = CircuitBreaker::new("gateway", 3, 1000);
+        breaker.record_at(false, 0);
+        breaker.record_at(false, 0);
+        assert!(breaker.allow_at(10).is_ok());
+        assert_eq!(breaker.stats_at(10).state, BreakerState::Closed);
+
+        // the third failure in a row opens it
+        breaker.record_at(false, 100);
+        assert!(breaker.allow_at(500).is_err());
+        assert_eq!(breaker.stats_at(500).state, BreakerState::Open);
+
+        // one trial after open_ms, others wait while it runs
+        assert!(breaker.allow_at(1100).is_ok());
+        assert!(breaker.allow_at(1200).is_err());
+        assert_eq!(breaker.stats_at(1200).state, BreakerState::HalfOpen);
+
+        // a failed trial opens it for another open_ms
+        breaker.record_at(false, 1300);
+        assert!(breaker.allow_at(2200).is_err());
+        assert!(breaker.allow_at(2300).is_ok());
+        breaker.record_at(true, 2400);
+
+        let stats = breaker.stats_at(2400);
+        assert_eq!(stats.state, BreakerState::Closed);
+        assert_eq!((stats.trips, stats.rejected), (2, 3));
+
+        let never_opens = CircuitBreaker::new("gateway", 0, 1000);
+        for _ in 0..10 {
+            never_opens.record_at(false, 0);
+        }
+        assert!(never_opens.allow_at(1).is_ok());
+    }
+}
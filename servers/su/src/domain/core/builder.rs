@@ -1,9 +1,12 @@
+use std::collections::HashSet;
 use std::sync::Arc;
 
 use bundlr_sdk::tags::Tag;
+use bytes::Bytes;
 
+use super::attestations::Attestation;
 use super::bytes::{ByteErrorType, DataBundle, DataItem};
-use super::dal::{Gateway, Log, ScheduleProvider, Signer, TxStatus};
+use super::dal::{FlowError, Gateway, Log, ScheduleProvider, Signer, TxStatus};
 use super::json::Process;
 
 pub struct Builder<'a> {
@@ -12,14 +15,20 @@ pub struct Builder<'a> {
     logger: &'a Arc<dyn Log>,
 }
 
+/*
+    binary is the signed bundle as it is stored and
+    uploaded, shared rather than copied between the two
+*/
 pub struct BuildResult {
-    pub binary: Vec<u8>,
+    pub binary: Bytes,
     pub bundle: DataBundle,
 }
 
 #[derive(Debug)]
 pub enum BuilderErrorType {
     BuilderError(String),
+    // the gateway or signer could not be reached
+    Upstream(String),
 }
 
 impl From<ByteErrorType> for BuilderErrorType {
@@ -40,6 +49,15 @@ impl From<String> for BuilderErrorType {
     }
 }
 
+impl From<BuilderErrorType> for FlowError {
+    fn from(error: BuilderErrorType) -> Self {
+        match error {
+            BuilderErrorType::BuilderError(e) => FlowError::Validation(e),
+            BuilderErrorType::Upstream(e) => FlowError::Upstream(e),
+        }
+    }
+}
+
 impl<'a> Builder<'a> {
     pub fn new(
         gateway: Arc<dyn Gateway>,
@@ -60,34 +78,33 @@ impl<'a> Builder<'a> {
         schedule_info: &dyn ScheduleProvider,
         exclude: &Option<String>,
     ) -> Result<DataItem, BuilderErrorType> {
-        let network_info = self.gateway.network_info().await?;
-        let height = network_info.height.clone();
         let mut tags = vec![
-            Tag::new(&"Process".to_string(), &process_id),
-            Tag::new(&"Message".to_string(), &message_id),
-            Tag::new(&"Epoch".to_string(), &schedule_info.epoch()),
-            Tag::new(&"Nonce".to_string(), &schedule_info.nonce()),
-            Tag::new(&"Hash-Chain".to_string(), &schedule_info.hash_chain()),
-            Tag::new(&"Block-Height".to_string(), &height.to_string()),
-            Tag::new(&"Timestamp".to_string(), &schedule_info.timestamp()),
+            Tag::new("Process", &process_id),
+            Tag::new("Message", &message_id),
+            Tag::new("Epoch", &schedule_info.epoch()),
+            Tag::new("Nonce", &schedule_info.nonce()),
+            Tag::new("Hash-Chain", &schedule_info.hash_chain()),
+            Tag::new("Block-Height", &schedule_info.block_height()),
+            Tag::new("Timestamp", &schedule_info.timestamp()),
         ];
 
         /*
             exclude is a comma seperated value fed in as a query
             param. We add an Exclude tag for each value set.
         */
-        match exclude {
-            Some(csv) => {
-                for val in csv.split(',') {
-                    tags.push(Tag::new(&"Exclude".to_string(), &val))
-                }
+        if let Some(csv) = exclude {
+            for val in csv.split(',') {
+                tags.push(Tag::new("Exclude", val))
             }
-            None => (),
         }
 
         let mut assignment = DataItem::new(vec![], vec![], tags, self.signer.get_public_key())?;
         let assignment_message = assignment.get_message()?.to_vec();
-        let assignment_signature = self.signer.sign_tx(assignment_message).await?;
+        let assignment_signature = self
+            .signer
+            .sign_tx(assignment_message)
+            .await
+            .map_err(BuilderErrorType::Upstream)?;
 
         assignment.signature = assignment_signature;
 
@@ -99,15 +116,15 @@ impl<'a> Builder<'a> {
 
     async fn bundle_items(&self, items: Vec<DataItem>) -> Result<BuildResult, BuilderErrorType> {
         let bundle_tags = vec![
-            Tag::new(&"Bundle-Format".to_string(), &"binary".to_string()),
-            Tag::new(&"Bundle-Version".to_string(), &"2.0.0".to_string()),
+            Tag::new("Bundle-Format", "binary"),
+            Tag::new("Bundle-Version", "2.0.0"),
         ];
 
         let mut data_bundle = DataBundle::new(bundle_tags.clone());
 
-        items.iter().for_each(|item| {
-            data_bundle.add_item(item.clone());
-        });
+        for item in items.into_iter() {
+            data_bundle.add_item(item);
+        }
 
         let buffer = data_bundle.to_bytes()?;
 
@@ -115,18 +132,63 @@ impl<'a> Builder<'a> {
             DataItem::new(vec![], buffer, bundle_tags, self.signer.get_public_key())?;
         let bundle_message = bundle_data_item.get_message()?.to_vec();
 
-        let signature = self.signer.sign_tx(bundle_message).await?;
+        let signature = self
+            .signer
+            .sign_tx(bundle_message)
+            .await
+            .map_err(BuilderErrorType::Upstream)?;
 
         bundle_data_item.signature = signature;
 
         self.logger.log(format!("signature succeeded {}", ""));
 
         Ok(BuildResult {
-            binary: bundle_data_item.as_bytes()?,
+            binary: bundle_data_item.as_bytes()?.into(),
             bundle: data_bundle,
         })
     }
 
+    /*
+        Build the signed Scheduler-Location record announcing
+        the url this su serves from, CUs and MUs look up the
+        latest one owned by a process's Scheduler to find it
+    */
+    pub async fn build_scheduler_location(
+        &self,
+        url: &str,
+        ttl_ms: u64,
+    ) -> Result<DataItem, BuilderErrorType> {
+        let tags = vec![
+            Tag::new("Data-Protocol", "ao"),
+            Tag::new("Variant", "ao.TN.1"),
+            Tag::new("Type", "Scheduler-Location"),
+            Tag::new("Url", url),
+            Tag::new("Time-To-Live", &ttl_ms.to_string()),
+        ];
+        self.sign_record(tags).await
+    }
+
+    // Build the signed Attestation record of a process's schedule
+    pub async fn build_attestation(
+        &self,
+        attestation: &Attestation,
+    ) -> Result<DataItem, BuilderErrorType> {
+        self.sign_record(attestation.tags()).await
+    }
+
+    // a data item without data signed by the scheduler wallet
+    async fn sign_record(&self, tags: Vec<Tag>) -> Result<DataItem, BuilderErrorType> {
+        let mut record = DataItem::new(vec![], vec![], tags, self.signer.get_public_key())?;
+        let record_message = record.get_message()?.to_vec();
+        record.signature = self
+            .signer
+            .sign_tx(record_message)
+            .await
+            .map_err(BuilderErrorType::Upstream)?;
+
+        Ok(record)
+    }
+
     // Build a bundle containing only an assignment DataItem
     pub async fn build_assignment(
         &self,
@@ -155,17 +217,17 @@ impl<'a> Builder<'a> {
                     Err(e) => Err(e),
                 }
             }
-            Err(e) => Err(BuilderErrorType::from(e)),
+            Err(e) => Err(e),
         }
     }
 
     // Build a bundle containing both an assignment and message DataItem
     pub async fn build_message(
         &self,
-        tx: Vec<u8>,
+        message_item: DataItem,
         schedule_info: &dyn ScheduleProvider,
     ) -> Result<BuildResult, BuilderErrorType> {
-        let message_item = DataItem::from_bytes(tx)?;
+        message_item.verify_signature()?;
         match self
             .gen_assignment(
                 message_item.id(),
@@ -181,32 +243,108 @@ impl<'a> Builder<'a> {
         }
     }
 
-    pub async fn build_process(
+    /*
+        build_message in two steps for su bulk-load, the
+        assignments are signed one by one in schedule order
+        and their bundles in parallel afterwards
+    */
+    pub async fn assign_message(
+        &self,
+        message_item: &DataItem,
+        schedule_info: &dyn ScheduleProvider,
+    ) -> Result<DataItem, BuilderErrorType> {
+        self.gen_assignment(
+            message_item.id(),
+            message_item.target(),
+            schedule_info,
+            &None,
+        )
+        .await
+    }
+
+    pub async fn bundle_message(
+        &self,
+        assignment: DataItem,
+        message_item: DataItem,
+    ) -> Result<BuildResult, BuilderErrorType> {
+        self.bundle_items(vec![assignment, message_item]).await
+    }
+
+    /*
+        Assign a process its own spawn item as its first
+        message, for a process that handles its spawn on boot
+    */
+    pub async fn build_boot_message(
         &self,
-        tx: Vec<u8>,
+        process_item: DataItem,
         schedule_info: &dyn ScheduleProvider,
     ) -> Result<BuildResult, BuilderErrorType> {
-        let item = DataItem::from_bytes(tx)?;
+        match self
+            .gen_assignment(process_item.id(), process_item.id(), schedule_info, &None)
+            .await
+        {
+            Ok(a) => self.bundle_items(vec![a, process_item]).await,
+            Err(e) => Err(e),
+        }
+    }
+
+    /*
+        Build a cron message for a process, signed by the su
+        since no one else sent it, and its assignment
+    */
+    pub async fn build_cron_message(
+        &self,
+        process_id: &str,
+        tags: Vec<Tag>,
+        schedule_info: &dyn ScheduleProvider,
+    ) -> Result<BuildResult, BuilderErrorType> {
+        let target = base64_url::decode(process_id)
+            .map_err(|e| BuilderErrorType::BuilderError(format!("{:?}", e)))?;
+        let mut message_item = DataItem::new(target, vec![], tags, self.signer.get_public_key())?;
+        let message = message_item.get_message()?.to_vec();
+        message_item.signature = self
+            .signer
+            .sign_tx(message)
+            .await
+            .map_err(BuilderErrorType::Upstream)?;
 
+        match self
+            .gen_assignment(
+                message_item.id(),
+                process_id.to_string(),
+                schedule_info,
+                &None,
+            )
+            .await
+        {
+            Ok(a) => self.bundle_items(vec![a, message_item]).await,
+            Err(e) => Err(e),
+        }
+    }
+
+    pub async fn build_process(
+        &self,
+        item: DataItem,
+        schedule_info: &dyn ScheduleProvider,
+    ) -> Result<BuildResult, BuilderErrorType> {
         self.logger.log(format!(
             "attempting to verify data item id - {}",
             &item.id()
         ));
         self.logger.log(format!("owner - {}", &item.owner()));
         self.logger.log(format!("target - {}", &item.target()));
-        self.logger.log(format!("tags - {:?}", &item.tags()));
+        self.logger.log(format!("tags - {:?}", item.tags()));
+
+        item.verify_signature()?;
 
         self.logger
             .log(format!("verified data item id - {}", &item.id()));
 
-        let network_info = self.gateway.network_info().await?;
-        let height = network_info.height.clone();
-
         let tags = vec![
-            Tag::new(&"Bundle-Format".to_string(), &"binary".to_string()),
-            Tag::new(&"Bundle-Version".to_string(), &"2.0.0".to_string()),
-            Tag::new(&"Block-Height".to_string(), &height.to_string()),
-            Tag::new(&"Timestamp".to_string(), &schedule_info.timestamp()),
+            Tag::new("Bundle-Format", "binary"),
+            Tag::new("Bundle-Version", "2.0.0"),
+            Tag::new("Block-Height", &schedule_info.block_height()),
+            Tag::new("Timestamp", &schedule_info.timestamp()),
         ];
         self.logger.log(format!("generated tags - {:?}", &tags));
 
@@ -218,31 +356,101 @@ impl<'a> Builder<'a> {
         let mut new_data_item = DataItem::new(vec![], buffer, tags, pub_key)?;
         let message = new_data_item.get_message()?.to_vec();
 
-        let signature = self.signer.sign_tx(message).await?;
+        let signature = self
+            .signer
+            .sign_tx(message)
+            .await
+            .map_err(BuilderErrorType::Upstream)?;
 
         new_data_item.signature = signature;
 
         self.logger.log(format!("signature succeeded {}", ""));
 
         Ok(BuildResult {
-            binary: new_data_item.as_bytes()?,
+            binary: new_data_item.as_bytes()?.into(),
             bundle: data_bundle,
         })
     }
 
+    /*
+        unpack the nested data items of a bundle. every one
+        must be a message for a process, if any is not the
+        whole bundle is rejected
+    */
+    pub async fn parse_bundle(&self, item: &DataItem) -> Result<Vec<DataItem>, BuilderErrorType> {
+        item.verify_signature()?;
+        let bundle_item = item.clone();
+        let data_bundle =
+            match tokio::task::spawn_blocking(move || bundle_item.nested_bundle()).await {
+                Ok(parsed) => parsed?,
+                Err(e) => {
+                    return Err(BuilderErrorType::BuilderError(format!(
+                        "bundle parse error: {:?}",
+                        e
+                    )))
+                }
+            };
+
+        if data_bundle.items.is_empty() {
+            return Err(BuilderErrorType::BuilderError(
+                "Bundle contains no data items".to_string(),
+            ));
+        }
+
+        let mut seen = HashSet::new();
+        for (index, nested) in data_bundle.items.iter().enumerate() {
+            let tags = nested.tags();
+            let reason = if !tags.iter().any(|tag| tag.name == "Data-Protocol") {
+                Some("is missing the Data-Protocol tag")
+            } else if !tags
+                .iter()
+                .any(|tag| tag.name == "Type" && tag.value == "Message")
+            {
+                Some("is not a Message, only messages can be bundled")
+            } else if nested.target().is_empty() {
+                Some("has no target process")
+            } else if !seen.insert(nested.id()) {
+                Some("appears more than once")
+            } else {
+                None
+            };
+
+            if let Some(reason) = reason {
+                return Err(BuilderErrorType::BuilderError(format!(
+                    "Nested item {} ({}) {}, the bundle was rejected",
+                    index,
+                    nested.id(),
+                    reason
+                )));
+            }
+        }
+
+        self.logger.log(format!(
+            "unpacked bundle {} with {} items",
+            item.id(),
+            data_bundle.items.len()
+        ));
+
+        Ok(data_bundle.items)
+    }
+
     pub fn parse_data_item(&self, tx: Vec<u8>) -> Result<DataItem, BuilderErrorType> {
         Ok(DataItem::from_bytes(tx)?)
     }
 
     async fn verify_assignment(
         &self,
-        tx_id: &String,
+        tx_id: &str,
         process: &Process,
         base_layer: &Option<String>,
     ) -> Result<(), BuilderErrorType> {
         match base_layer {
             Some(_) => {
-                let status: TxStatus = self.gateway.status(&tx_id).await?;
+                let status: TxStatus = self
+                    .gateway
+                    .status(&tx_id.to_string())
+                    .await
+                    .map_err(BuilderErrorType::Upstream)?;
 
                 /*
                     If there is not a Settlement-Depth tag on the Process
@@ -254,10 +462,7 @@ impl<'a> Builder<'a> {
                     .iter()
                     .find(|tag| tag.name == "Settlement-Depth")
                 {
-                    Some(t) => match t.value.parse::<i32>() {
-                        Ok(parsed_value) => parsed_value,
-                        Err(_) => 20,
-                    },
+                    Some(t) => t.value.parse::<i32>().unwrap_or(20),
                     None => 20,
                 };
 
@@ -300,6 +505,30 @@ mod tests {
                 number_of_confirmations: 0,
             })
         }
+
+        async fn raw(&self, _tx_id: &String) -> Result<Vec<u8>, String> {
+            Ok(vec![])
+        }
+
+        async fn ping(&self) -> Result<(), String> {
+            Ok(())
+        }
+
+        async fn graphql(
+            &self,
+            _query: &str,
+            _variables: serde_json::Value,
+        ) -> Result<serde_json::Value, String> {
+            Ok(serde_json::Value::Null)
+        }
+
+        async fn price(&self, _bytes: usize) -> Result<u64, String> {
+            Ok(0)
+        }
+
+        async fn tx_anchor(&self) -> Result<String, String> {
+            Ok(String::new())
+        }
     }
 
     struct MockSigner;
@@ -339,6 +568,9 @@ mod tests {
         fn hash_chain(&self) -> String {
             "hash_chain".to_string()
         }
+        fn block_height(&self) -> String {
+            "000000001000".to_string()
+        }
     }
 
     #[tokio::test]
@@ -353,7 +585,8 @@ mod tests {
 
         let scheduler = MockScheduler {};
 
-        let result = builder.build_message(tx, &scheduler).await;
+        let item = DataItem::from_bytes(tx).expect("failed to build data item");
+        let result = builder.build_message(item, &scheduler).await;
 
         assert!(result.is_ok());
     }
@@ -0,0 +1,107 @@
+use serde::Deserialize;
+
+use super::dal::FlowError;
+
+// reads one request can carry
+pub const MAX_BULK_READS: usize = 100;
+// messages per read when it sets no limit
+const DEFAULT_LIMIT: i32 = 1000;
+const MAX_LIMIT: i32 = 5000;
+
+/*
+    One process and nonce range of a bulk read, both ends
+    are inclusive. A signed read is only good for the one
+    process it names, so each private process needs its own.
+*/
+#[derive(Deserialize, Debug)]
+pub struct RangeRead {
+    pub process_id: String,
+    #[serde(default)]
+    pub from_nonce: i32,
+    pub to_nonce: Option<i32>,
+    pub limit: Option<i32>,
+    pub signed_read: Option<String>,
+}
+
+impl RangeRead {
+    pub fn limit(&self) -> i64 {
+        i64::from(self.limit.unwrap_or(DEFAULT_LIMIT))
+    }
+}
+
+// the body a CU posts to read several processes at once
+#[derive(Deserialize, Debug)]
+pub struct BulkReadRequest {
+    pub reads: Vec<RangeRead>,
+}
+
+impl BulkReadRequest {
+    pub fn from_body(body: &[u8]) -> Result<BulkReadRequest, FlowError> {
+        let request: BulkReadRequest = serde_json::from_slice(body)
+            .map_err(|e| FlowError::Validation(format!("Invalid bulk read: {}", e)))?;
+        if request.reads.is_empty() {
+            return Err(FlowError::Validation(
+                "A bulk read needs at least one read".to_string(),
+            ));
+        }
+        if request.reads.len() > MAX_BULK_READS {
+            return Err(FlowError::Validation(format!(
+                "At most {} processes can be read at once",
+                MAX_BULK_READS
+            )));
+        }
+        for read in request.reads.iter() {
+            if read.from_nonce < 0 || read.to_nonce.is_some_and(|t| t < read.from_nonce) {
+                return Err(FlowError::Validation(format!(
+                    "Invalid nonce range for process {}",
+                    read.process_id
+                )));
+            }
+            if read.limit.is_some_and(|l| !(1..=MAX_LIMIT).contains(&l)) {
+                return Err(FlowError::Validation(format!(
+                    "Bulk read limit must be between 1 and {}",
+                    MAX_LIMIT
+                )));
+            }
+        }
+        Ok(request)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bulk_read_request() {
+        let request = BulkReadRequest::from_body(
+            br#"{"reads": [
+                {"process_id": "a", "from_nonce": 10, "to_nonce": 20},
+                {"process_id": "b", "limit": 50}
+            ]}"#,
+        )
+        .unwrap();
+        assert_eq!(request.reads.len(), 2);
+        assert_eq!(request.reads[0].limit(), i64::from(DEFAULT_LIMIT));
+        assert_eq!(
+            (request.reads[1].from_nonce, request.reads[1].to_nonce),
+            (0, None)
+        );
+        assert_eq!(request.reads[1].limit(), 50);
+
+        assert!(BulkReadRequest::from_body(br#"{"reads": []}"#).is_err());
+        assert!(BulkReadRequest::from_body(
+            br#"{"reads": [{"process_id": "a", "from_nonce": 5, "to_nonce": 4}]}"#
+        )
+        .is_err());
+        assert!(
+            BulkReadRequest::from_body(br#"{"reads": [{"process_id": "a", "limit": 0}]}"#).is_err()
+        );
+
+        let reads: Vec<String> = (0..=MAX_BULK_READS)
+            .map(|i| format!(r#"{{"process_id": "p{}"}}"#, i))
+            .collect();
+        let body = format!(r#"{{"reads": [{}]}}"#, reads.join(","));
+        assert!(BulkReadRequest::from_body(body.as_bytes()).is_err());
+    }
+}
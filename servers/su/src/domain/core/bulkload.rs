@@ -0,0 +1,414 @@
+use std::collections::HashSet;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use tokio::runtime::Handle;
+use tokio::time::sleep;
+
+use super::builder::Builder;
+use super::bytes::DataItem;
+use super::dal::{BundleRef, DataStore, Gateway, Log, Message, Process, StoreErrorType, Uploader};
+use super::scheduler::{gen_hash_chain, ScheduleInfo};
+use super::tenants::{scheduler_tag, Tenants};
+
+// messages assigned, saved in one transaction and uploaded at a time
+const BATCH_SIZE: usize = 1000;
+
+pub struct BulkLoadDeps {
+    pub data_store: Arc<dyn DataStore>,
+    pub gateway: Arc<dyn Gateway>,
+    pub uploader: Arc<dyn Uploader>,
+    pub tenants: Tenants,
+    pub logger: Arc<dyn Log>,
+    // BOOT_MESSAGE_TAGS, a spawn carrying one is its own first message
+    pub boot_message_tags: Vec<String>,
+    // the name the lease of the process is held under, the store checks it on save
+    pub lease_holder: String,
+    pub lease_ttl_ms: u64,
+}
+
+fn now_millis() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as i64)
+        .unwrap_or(0)
+}
+
+/*
+    runs f over inputs split across the cores, the results
+    come back in the order of the inputs
+*/
+fn parallel<T: Send, R: Send>(
+    inputs: Vec<T>,
+    f: impl Fn(T) -> Result<R, String> + Sync,
+) -> Result<Vec<R>, String> {
+    let workers = thread::available_parallelism().map_or(1, |n| n.get());
+    let size = inputs.len().div_ceil(workers).max(1);
+    let mut chunks = vec![];
+    let mut rest = inputs.into_iter().peekable();
+    while rest.peek().is_some() {
+        chunks.push(rest.by_ref().take(size).collect::<Vec<T>>());
+    }
+
+    let f = &f;
+    thread::scope(|scope| {
+        let handles: Vec<_> = chunks
+            .into_iter()
+            .map(|chunk| {
+                scope.spawn(move || chunk.into_iter().map(f).collect::<Result<Vec<R>, _>>())
+            })
+            .collect();
+        let mut results = vec![];
+        for handle in handles.into_iter() {
+            let chunk = handle
+                .join()
+                .map_err(|_| "a bulk-load worker panicked".to_string())??;
+            results.extend(chunk);
+        }
+        Ok(results)
+    })
+}
+
+// the files of dir in name order
+fn item_paths(dir: &str) -> Result<Vec<PathBuf>, String> {
+    let entries = fs::read_dir(dir).map_err(|e| format!("failed to read {}: {}", dir, e))?;
+    let mut paths = vec![];
+    for entry in entries {
+        let path = entry
+            .map_err(|e| format!("failed to read {}: {}", dir, e))?
+            .path();
+        if path.is_file() {
+            paths.push(path);
+        }
+    }
+    paths.sort();
+    Ok(paths)
+}
+
+fn read_item(path: &PathBuf) -> Result<DataItem, String> {
+    let bytes = fs::read(path).map_err(|e| format!("failed to read {}: {}", path.display(), e))?;
+    DataItem::from_bytes(bytes)
+        .map_err(|e| format!("{} is not a data item: {:?}", path.display(), e))
+}
+
+fn tag<'a>(item: &'a DataItem, name: &str) -> Option<&'a str> {
+    item.tags()
+        .iter()
+        .find(|tag| tag.name == name)
+        .map(|tag| tag.value.as_str())
+}
+
+// the checks a write makes, left out are the ones needing a running su
+fn check_item(item: &DataItem, item_type: &str) -> Result<(), String> {
+    item.verify_signature()
+        .map_err(|e| format!("invalid signature: {:?}", e))?;
+    if tag(item, "Data-Protocol").is_none() {
+        return Err("Data-Protocol tag not present".to_string());
+    }
+    match tag(item, "Type") == Some(item_type) {
+        true => Ok(()),
+        false => Err(format!("expected an item of Type {}", item_type)),
+    }
+}
+
+fn check_process(item: &DataItem, tenants: &Tenants) -> Result<(), String> {
+    check_item(item, "Process")?;
+    if tag(item, "Module").is_none() {
+        return Err("Module tag not present".to_string());
+    }
+    let scheduler = scheduler_tag(item.tags());
+    match tenants.hosts(&scheduler) {
+        true => Ok(()),
+        false => Err(format!(
+            "Scheduler tag {} is not a wallet of this su",
+            scheduler
+        )),
+    }
+}
+
+// the id and target of a message file
+fn check_message(path: &PathBuf) -> Result<(String, String), String> {
+    let item = read_item(path)?;
+    check_item(&item, "Message").map_err(|e| format!("{}: {}", path.display(), e))?;
+    Ok((item.id(), item.target()))
+}
+
+/*
+    the lease of the process, taken before anything is
+    written and renewed for each batch. A cluster instance
+    turns away writes to the process while it is held
+*/
+async fn hold_lease(deps: &BulkLoadDeps, process_id: &str) -> Result<(), String> {
+    let holder = deps
+        .data_store
+        .acquire_lease(process_id, &deps.lease_holder, deps.lease_ttl_ms)
+        .await?;
+    match holder == deps.lease_holder {
+        true => Ok(()),
+        false => Err(format!(
+            "process {} is sequenced by {}, stop it there first",
+            process_id, holder
+        )),
+    }
+}
+
+/*
+    where a load of the process that was interrupted
+    stopped, the stored schedule must be the start of
+    this load. None when the process is not stored yet
+*/
+async fn resume_point(
+    deps: &BulkLoadDeps,
+    process_id: &str,
+    message_ids: &[String],
+    boots: bool,
+    block_height: &str,
+) -> Result<Option<(ScheduleInfo, usize)>, String> {
+    match deps.data_store.get_process(process_id).await {
+        Err(StoreErrorType::NotFound(_)) => return Ok(None),
+        Ok(_) => (),
+        Err(e) => return Err(format!("{:?}", e)),
+    }
+    let mismatch = || {
+        format!(
+            "process {} already exists and its schedule is not the start of this load",
+            process_id
+        )
+    };
+
+    let latest = match deps.data_store.get_latest_message(process_id).await? {
+        Some(latest) => latest,
+        None if !boots => return Ok(Some((first_slot(process_id, block_height)?, 0))),
+        None => return Err(mismatch()),
+    };
+    // a boot message is the spawn itself at nonce 0, ahead of the files
+    let loaded = latest.nonce()? + 1 - boots as i32;
+    let expected = match loaded {
+        0 => Some(process_id),
+        n if n > 0 => message_ids.get(n as usize - 1).map(|id| id.as_str()),
+        _ => None,
+    };
+    if expected != Some(latest.message_id()?.as_str()) {
+        return Err(mismatch());
+    }
+
+    let schedule_info = ScheduleInfo {
+        epoch: latest.epoch()?,
+        nonce: latest.nonce()? + 1,
+        timestamp: now_millis(),
+        hash_chain: gen_hash_chain(&latest.hash_chain()?, Some(&latest.assignment_id()?))?,
+        block_height: block_height.to_string(),
+        synced: false,
+    };
+    Ok(Some((schedule_info, loaded as usize)))
+}
+
+fn first_slot(process_id: &str, block_height: &str) -> Result<ScheduleInfo, String> {
+    Ok(ScheduleInfo {
+        epoch: 0,
+        nonce: 0,
+        timestamp: now_millis(),
+        hash_chain: gen_hash_chain(process_id, None)?,
+        block_height: block_height.to_string(),
+        synced: false,
+    })
+}
+
+/*
+    the uploads of an interrupted load may not have gone
+    out, everything it saved is handed to the uploader
+    again. The upload node ignores the bundles it has
+*/
+async fn upload_stored(deps: &BulkLoadDeps, process_id: &str) -> Result<(), String> {
+    let process_bundle = deps
+        .data_store
+        .get_stored_bundle(&BundleRef::Process(process_id.to_string()))
+        .await?;
+    deps.uploader.upload(process_bundle.into())?;
+
+    let mut after = None;
+    loop {
+        let page = deps
+            .data_store
+            .get_message_bundles(process_id, &after, BATCH_SIZE as i64)
+            .await?;
+        let last = match page.last() {
+            Some((message, _)) => (message.epoch()?, message.nonce()?),
+            None => return Ok(()),
+        };
+        for (_, binary) in page.into_iter() {
+            deps.uploader.upload(binary.into())?;
+        }
+        after = Some(last);
+    }
+}
+
+/*
+    Sequences a new process from a directory of signed
+    data items, for a genesis or an airdrop too large to
+    post one by one. In file name order the first item
+    spawns the process and the rest are its messages.
+    Every item is checked before anything is written.
+    Then each batch of messages is assigned in order,
+    which the hash chain needs, bundled and signed on
+    every core, saved in one transaction along with its
+    outbox events and handed to the uploader. The lease
+    of the process is held throughout, and a load that
+    was interrupted carries on after the last message
+    it saved when it is run again.
+*/
+pub async fn bulk_load(deps: BulkLoadDeps, dir: &str) -> Result<String, String> {
+    let paths = item_paths(dir)?;
+    let (process_path, message_paths) = match paths.split_first() {
+        Some((first, rest)) => (first, rest.to_vec()),
+        None => return Err(format!("{} holds no data items", dir)),
+    };
+    let process_item = read_item(process_path)?;
+    check_process(&process_item, &deps.tenants)
+        .map_err(|e| format!("{}: {}", process_path.display(), e))?;
+    let process_id = process_item.id();
+
+    let messages = parallel(message_paths.clone(), |path| check_message(&path))?;
+    let mut seen = HashSet::new();
+    for ((id, target), path) in messages.iter().zip(message_paths.iter()) {
+        if *target != process_id {
+            return Err(format!(
+                "{}: message {} is not for process {}",
+                path.display(),
+                id,
+                process_id
+            ));
+        }
+        if !seen.insert(id) {
+            return Err(format!("{}: message {} is there twice", path.display(), id));
+        }
+    }
+    let message_ids: Vec<String> = messages.into_iter().map(|(id, _)| id).collect();
+
+    hold_lease(&deps, &process_id).await?;
+    let loaded = load(&deps, process_item, &message_ids, &message_paths).await;
+    if let Err(e) = deps.data_store.release_leases(&deps.lease_holder).await {
+        deps.logger.error(format!(
+            "Failed to release the lease of {}: {:?}",
+            process_id, e
+        ));
+    }
+    loaded
+}
+
+async fn load(
+    deps: &BulkLoadDeps,
+    process_item: DataItem,
+    message_ids: &[String],
+    message_paths: &[PathBuf],
+) -> Result<String, String> {
+    let process_id = process_item.id();
+    let signer = deps.tenants.signer_for(&scheduler_tag(process_item.tags()));
+    let builder = Builder::new(deps.gateway.clone(), signer, &deps.logger)?;
+    let boots = process_item
+        .tags()
+        .iter()
+        .any(|tag| deps.boot_message_tags.contains(&tag.name));
+    let block_height = deps.gateway.network_info().await?.height;
+
+    let resumed = resume_point(deps, &process_id, message_ids, boots, &block_height).await?;
+    let (mut schedule_info, loaded) = match resumed {
+        Some(resumed) => {
+            upload_stored(deps, &process_id).await?;
+            resumed
+        }
+        None => {
+            let mut schedule_info = first_slot(&process_id, &block_height)?;
+            let build_result = builder
+                .build_process(process_item.clone(), &schedule_info)
+                .await?;
+            let process = Process::from_bundle(&build_result.bundle)?;
+            if boots {
+                let boot_result = builder
+                    .build_boot_message(process_item, &schedule_info)
+                    .await?;
+                let boot = Message::from_bundle(&boot_result.bundle)?;
+                deps.data_store
+                    .save_process_with_boot(
+                        &process,
+                        &build_result.binary,
+                        &boot,
+                        &boot_result.binary,
+                    )
+                    .await?;
+                deps.uploader.upload(build_result.binary)?;
+                deps.uploader.upload(boot_result.binary)?;
+                schedule_info.advance(&boot.assignment_id()?)?;
+            } else {
+                deps.data_store
+                    .save_process(&process, &build_result.binary)
+                    .await?;
+                deps.uploader.upload(build_result.binary)?;
+            }
+            (schedule_info, 0)
+        }
+    };
+
+    // the signer is called from the worker threads
+    let handle = Handle::current();
+    for batch in message_paths[loaded..].chunks(BATCH_SIZE) {
+        hold_lease(deps, &process_id).await?;
+        let items = parallel(batch.to_vec(), |path| read_item(&path))?;
+        let mut assigned = vec![];
+        for item in items.into_iter() {
+            schedule_info.timestamp = now_millis();
+            let assignment = builder.assign_message(&item, &schedule_info).await?;
+            schedule_info.advance(&assignment.id())?;
+            assigned.push((assignment, item));
+        }
+
+        let saved = parallel(assigned, |(assignment, item)| {
+            let build_result = handle.block_on(builder.bundle_message(assignment, item))?;
+            Ok((
+                Message::from_bundle(&build_result.bundle)?,
+                build_result.binary,
+            ))
+        })?;
+        // the save fails if the lease ran out and was taken meanwhile
+        hold_lease(deps, &process_id).await?;
+        deps.data_store.save_messages(&saved).await?;
+        for (_, binary) in saved.into_iter() {
+            deps.uploader.upload(binary)?;
+        }
+    }
+
+    // the uploads were handed off batch by batch, wait for them to go out
+    while deps.uploader.pending_uploads() > 0 {
+        sleep(Duration::from_secs(1)).await;
+    }
+
+    Ok(format!(
+        "loaded process {} with {} messages, {} of them by this run, next is nonce {}",
+        process_id,
+        message_paths.len(),
+        message_paths.len() - loaded,
+        schedule_info.nonce
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parallel() {
+        let inputs: Vec<usize> = (0..1001).collect();
+        let doubled = parallel(inputs, |i| Ok(i * 2)).unwrap();
+        assert_eq!(doubled, (0..1001).map(|i| i * 2).collect::<Vec<_>>());
+        assert!(parallel(vec![], |i: usize| Ok(i)).unwrap().is_empty());
+
+        let failed = parallel((0..100).collect(), |i: usize| match i {
+            42 => Err("no 42".to_string()),
+            _ => Ok(i),
+        });
+        assert_eq!(failed.unwrap_err(), "no 42");
+    }
+}
@@ -5,7 +5,11 @@ use bytes::{BufMut, Bytes};
 use bundlr_sdk::{error::BundlrError, tags::*};
 
 use base64_url;
+use k256::ecdsa::{signature::hazmat::PrehashVerifier, Signature, VerifyingKey};
+use ring::signature::{UnparsedPublicKey, ED25519};
+use rsa::{BigUint, PaddingScheme, PublicKey, RsaPublicKey};
 use sha2::{Digest, Sha256, Sha384};
+use sha3::Keccak256;
 
 use ring::rand::SecureRandom;
 
@@ -22,7 +26,7 @@ impl From<BundlrError> for ByteErrorType {
 
 impl From<&str> for ByteErrorType {
     fn from(error: &str) -> Self {
-        ByteErrorType::ByteError(format!("Byte error: {}", error.to_string()))
+        ByteErrorType::ByteError(format!("Byte error: {}", error))
     }
 }
 
@@ -36,7 +40,7 @@ impl DataBundle {
     pub fn new(tags: Vec<Tag>) -> Self {
         DataBundle {
             items: Vec::new(),
-            tags: tags,
+            tags,
         }
     }
 
@@ -44,37 +48,103 @@ impl DataBundle {
         self.items.push(item);
     }
 
+    // every item is serialized once, straight into the bundle
     pub fn to_bytes(&self) -> Result<Vec<u8>, ByteErrorType> {
-        let mut headers = vec![0u8; 64 * self.items.len()];
-        let mut binaries = Vec::new();
+        let binaries = self
+            .items
+            .iter()
+            .map(|item| item.as_bytes())
+            .collect::<Result<Vec<Vec<u8>>, ByteErrorType>>()?;
+        let size = 32 + 64 * binaries.len() + binaries.iter().map(|b| b.len()).sum::<usize>();
+
+        let mut buffer = Vec::with_capacity(size);
+        buffer.extend_from_slice(&long_to_32_byte_array(self.items.len() as u64)?);
+        for (item, binary) in self.items.iter().zip(binaries.iter()) {
+            buffer.extend_from_slice(&long_to_32_byte_array(binary.len() as u64)?);
+            buffer.extend_from_slice(&item.raw_id());
+        }
+        for binary in binaries.iter() {
+            buffer.extend_from_slice(binary);
+        }
 
-        for (index, item) in self.items.iter().enumerate() {
-            let id = item.raw_id();
+        Ok(buffer)
+    }
 
-            let mut header = Vec::with_capacity(64);
-            header.extend_from_slice(&long_to_32_byte_array(item.as_bytes()?.len() as u64)?);
-            header.extend_from_slice(&id);
+    pub fn from_bytes(buffer: &[u8]) -> Result<Self, ByteErrorType> {
+        DataBundle::from_shared(&Bytes::copy_from_slice(buffer))
+    }
 
-            headers.splice(64 * index..64 * (index + 1), header.iter().cloned());
-            binaries.extend_from_slice(&item.as_bytes()?);
+    /*
+        parse an ANS-104 binary bundle, the id in each
+        item header must match the nested item itself.
+        The items share the buffer rather than copy it
+    */
+    pub fn from_shared(buffer: &Bytes) -> Result<Self, ByteErrorType> {
+        if buffer.len() < 32 {
+            return Err(ByteErrorType::ByteError(
+                "Bundle too short for item count".to_string(),
+            ));
         }
 
-        let mut buffer = Vec::new();
-        buffer.extend_from_slice(&long_to_32_byte_array(self.items.len() as u64)?);
-        buffer.extend_from_slice(&headers);
-        buffer.extend_from_slice(&binaries);
+        let item_count = byte_array_to_long(&buffer[0..32])? as usize;
+        let headers_end = item_count
+            .checked_mul(64)
+            .and_then(|h| h.checked_add(32))
+            .filter(|h| *h <= buffer.len())
+            .ok_or("Bundle too short for item headers")?;
+
+        let mut items = Vec::with_capacity(item_count);
+        let mut offset = headers_end;
+        for index in 0..item_count {
+            let header = &buffer[32 + 64 * index..32 + 64 * (index + 1)];
+            let size = byte_array_to_long(&header[0..32])? as usize;
+            let end = offset
+                .checked_add(size)
+                .filter(|e| *e <= buffer.len())
+                .ok_or("Bundle too short for item data")?;
+
+            let item = DataItem::from_bytes(buffer.slice(offset..end))?;
+            if !item.is_signed() {
+                return Err(ByteErrorType::ByteError(format!(
+                    "nested item {} is not signed",
+                    index
+                )));
+            }
+            if item.raw_id() != header[32..64] {
+                return Err(ByteErrorType::ByteError(format!(
+                    "nested item {} does not match its header id",
+                    index
+                )));
+            }
 
-        Ok(buffer)
+            items.push(item);
+            offset = end;
+        }
+
+        Ok(DataBundle {
+            items,
+            tags: Vec::new(),
+        })
+    }
+}
+
+fn byte_array_to_long(bytes: &[u8]) -> Result<u64, ByteErrorType> {
+    if bytes[8..].iter().any(|b| *b != 0) {
+        return Err(ByteErrorType::ByteError("length out of range".to_string()));
     }
+    let mut value: u64 = 0;
+    for byte in bytes[0..8].iter().rev() {
+        value = (value << 8) | *byte as u64;
+    }
+    Ok(value)
 }
 
 fn long_to_n_byte_array(n: usize, long: u64) -> Result<Vec<u8>, ByteErrorType> {
     let mut byte_array = vec![0u8; n];
     let mut value = long;
 
-    for index in 0..n {
-        let byte = (value & 0xFF) as u8;
-        byte_array[index] = byte;
+    for byte in byte_array.iter_mut() {
+        *byte = (value & 0xFF) as u8;
         value >>= 8;
     }
 
@@ -85,10 +155,11 @@ fn long_to_32_byte_array(value: u64) -> Result<Vec<u8>, ByteErrorType> {
     long_to_n_byte_array(32, value)
 }
 
+// shared with the buffer the item was parsed from, clones are cheap
 #[derive(Clone)]
 enum Data {
     None,
-    Bytes(Vec<u8>),
+    Bytes(Bytes),
 }
 
 #[derive(Clone)]
@@ -100,6 +171,8 @@ pub struct DataItem {
     anchor: Vec<u8>,
     tags: Vec<Tag>,
     data: Data,
+    // the deep hash of data when it was hashed as it was read
+    data_hash: Option<Bytes>,
 }
 
 #[derive(Clone)]
@@ -109,18 +182,36 @@ pub struct Config {
     pub sig_name: String,
 }
 
+/*
+    ANS-104 signature types, Ethereum is a secp256k1
+    key signing with the personal_sign prefix
+*/
 #[derive(PartialEq, Clone)]
 pub enum SignerMap {
     None = -1,
     Arweave = 1,
+    ED25519 = 2,
+    Ethereum = 3,
 }
 
 impl SignerMap {
     pub fn get_config(&self) -> Config {
-        Config {
-            sig_length: 512,
-            pub_length: 512,
-            sig_name: "arweave".to_owned(),
+        match self {
+            SignerMap::ED25519 => Config {
+                sig_length: 64,
+                pub_length: 32,
+                sig_name: "ed25519".to_owned(),
+            },
+            SignerMap::Ethereum => Config {
+                sig_length: 65,
+                pub_length: 65,
+                sig_name: "ethereum".to_owned(),
+            },
+            _ => Config {
+                sig_length: 512,
+                pub_length: 512,
+                sig_name: "arweave".to_owned(),
+            },
         }
     }
 }
@@ -129,6 +220,8 @@ impl SignerMap {
     pub fn as_u16(&self) -> u16 {
         match self {
             SignerMap::Arweave => 1,
+            SignerMap::ED25519 => 2,
+            SignerMap::Ethereum => 3,
             _ => u16::MAX,
         }
     }
@@ -138,11 +231,76 @@ impl From<u16> for SignerMap {
     fn from(t: u16) -> Self {
         match t {
             1 => SignerMap::Arweave,
+            2 => SignerMap::ED25519,
+            3 => SignerMap::Ethereum,
             _ => SignerMap::None,
         }
     }
 }
 
+fn keccak256(data: &[u8]) -> Vec<u8> {
+    let mut hasher = Keccak256::new();
+    hasher.update(data);
+    hasher.finalize().to_vec()
+}
+
+// EIP-55 checksummed address of an uncompressed secp256k1 key
+fn ethereum_address(public_key: &[u8]) -> String {
+    let key = match public_key.split_first() {
+        Some((_, k)) => k,
+        None => return String::new(),
+    };
+    let hex_address = hex::encode(&keccak256(key)[12..]);
+    let checksum = hex::encode(keccak256(hex_address.as_bytes()));
+    let checksummed: String = hex_address
+        .chars()
+        .zip(checksum.chars())
+        .map(|(c, h)| match h {
+            '8'..='9' | 'a'..='f' => c.to_ascii_uppercase(),
+            _ => c,
+        })
+        .collect();
+    format!("0x{}", checksummed)
+}
+
+// PSS verification finds the salt itself, nothing is drawn from this
+struct NoRng;
+
+impl rsa::rand_core::RngCore for NoRng {
+    fn next_u32(&mut self) -> u32 {
+        0
+    }
+    fn next_u64(&mut self) -> u64 {
+        0
+    }
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        dest.fill(0)
+    }
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rsa::rand_core::Error> {
+        dest.fill(0);
+        Ok(())
+    }
+}
+
+// an arweave RSA-PSS signature over the sha256 of message, owner is the modulus
+pub fn verify_arweave(
+    owner: &[u8],
+    message: &[u8],
+    signature: &[u8],
+) -> Result<bool, ByteErrorType> {
+    let public_key = RsaPublicKey::new(BigUint::from_bytes_be(owner), BigUint::from(65537u32))
+        .map_err(|e| ByteErrorType::ByteError(format!("invalid owner key: {}", e)))?;
+    let mut hasher = Sha256::new();
+    hasher.update(message);
+    Ok(public_key
+        .verify(
+            PaddingScheme::new_pss::<Sha256, _>(NoRng),
+            &hasher.finalize(),
+            signature,
+        )
+        .is_ok())
+}
+
 pub const LIST_AS_BUFFER: &[u8] = "list".as_bytes();
 pub const BLOB_AS_BUFFER: &[u8] = "blob".as_bytes();
 pub const DATAITEM_AS_BUFFER: &[u8] = "dataitem".as_bytes();
@@ -151,10 +309,13 @@ pub const ONE_AS_BUFFER: &[u8] = "1".as_bytes();
 pub enum DeepHashChunk {
     Chunk(Bytes),
     Chunks(Vec<DeepHashChunk>),
+    // the deep hash of a chunk, worked out by a BlobHasher
+    Hashed(Bytes),
 }
 
 pub fn deep_hash_sync(chunk: DeepHashChunk) -> Result<Bytes, ByteErrorType> {
     match chunk {
+        DeepHashChunk::Hashed(hash) => Ok(hash),
         DeepHashChunk::Chunk(b) => {
             let tag = [BLOB_AS_BUFFER, b.len().to_string().as_bytes()].concat();
             let c = [sha384hash(tag.into()), sha384hash(b)].concat();
@@ -182,6 +343,42 @@ pub fn deep_hash_chunks_sync(
     deep_hash_chunks_sync(chunks, new_acc)
 }
 
+/*
+    the deep hash of a blob fed in pieces as it arrives,
+    the same as deep_hash_sync of the whole blob
+*/
+pub struct BlobHasher {
+    hasher: Sha384,
+    length: usize,
+}
+
+impl Default for BlobHasher {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl BlobHasher {
+    pub fn new() -> Self {
+        BlobHasher {
+            hasher: Sha384::new(),
+            length: 0,
+        }
+    }
+
+    pub fn update(&mut self, bytes: &[u8]) {
+        self.hasher.update(bytes);
+        self.length += bytes.len();
+    }
+
+    pub fn finish(self) -> Bytes {
+        let tag = [BLOB_AS_BUFFER, self.length.to_string().as_bytes()].concat();
+        let data_hash = Bytes::copy_from_slice(&self.hasher.finalize());
+        let c = [sha384hash(tag.into()), data_hash].concat();
+        Bytes::copy_from_slice(&sha384hash(c.into()))
+    }
+}
+
 fn sha384hash(b: Bytes) -> Bytes {
     let mut hasher = Sha384::new();
     hasher.update(&b);
@@ -206,25 +403,29 @@ impl DataItem {
         Ok(DataItem {
             signature_type: SignerMap::Arweave,
             signature: vec![],
-            owner: owner,
+            owner,
             target,
             anchor,
             tags,
-            data: Data::Bytes(data),
+            data: Data::Bytes(data.into()),
+            data_hash: None,
         })
     }
 
-    pub fn get_message(&mut self) -> Result<Bytes, ByteErrorType> {
+    pub fn get_message(&self) -> Result<Bytes, ByteErrorType> {
         let encoded_tags = if !self.tags.is_empty() {
             self.tags.encode()?
         } else {
             Bytes::default()
         };
 
-        match &mut self.data {
+        match &self.data {
             Data::None => Ok(Bytes::new()),
             Data::Bytes(data) => {
-                let data_chunk = DeepHashChunk::Chunk(data.clone().into());
+                let data_chunk = match &self.data_hash {
+                    Some(hash) => DeepHashChunk::Hashed(hash.clone()),
+                    None => DeepHashChunk::Chunk(data.clone()),
+                };
                 let sig_type = &self.signature_type;
                 let sig_type_bytes = sig_type.as_u16().to_string().as_bytes().to_vec();
                 deep_hash_sync(DeepHashChunk::Chunks(vec![
@@ -245,7 +446,46 @@ impl DataItem {
         !self.signature.is_empty() && self.signature_type != SignerMap::None
     }
 
-    fn from_info_bytes(buffer: &[u8]) -> Result<(Self, usize), ByteErrorType> {
+    /*
+        how many bytes the header of an item in buffer takes,
+        None while too little of it arrived to tell
+    */
+    pub fn info_length(buffer: &[u8]) -> Result<Option<usize>, ByteErrorType> {
+        if buffer.len() < 2 {
+            return Ok(None);
+        }
+        let signer = SignerMap::from(u16::from_le_bytes([buffer[0], buffer[1]]));
+        let Config {
+            pub_length,
+            sig_length,
+            ..
+        } = signer.get_config();
+
+        // the target and then the anchor, each 32 bytes when present
+        let mut offset = 2 + sig_length + pub_length;
+        for _ in 0..2 {
+            match buffer.get(offset) {
+                None => return Ok(None),
+                Some(0) => offset += 1,
+                Some(1) => offset += 33,
+                Some(_) => return Err("invalid presence byte".into()),
+            }
+        }
+
+        let tags_length = match buffer.get(offset + 8..offset + 16) {
+            Some(bytes) => u64::from_le_bytes(
+                <[u8; 8]>::try_from(bytes).map_err(|e| ByteErrorType::ByteError(e.to_string()))?,
+            ),
+            None => return Ok(None),
+        };
+        usize::try_from(tags_length)
+            .ok()
+            .and_then(|length| length.checked_add(offset + 16))
+            .map(Some)
+            .ok_or("tag bytes error".into())
+    }
+
+    pub fn from_info_bytes(buffer: &[u8]) -> Result<(Self, usize), ByteErrorType> {
         if buffer.len() < 2 {
             return Err(ByteErrorType::ByteError(
                 "Buffer too short for signature type".to_string(),
@@ -276,9 +516,8 @@ impl DataItem {
 
         let target_start = 2 + sig_length + pub_length;
         let target_present = u8::from_le_bytes(
-            <[u8; 1]>::try_from(&buffer[target_start..target_start + 1]).map_err(|err| {
-                ByteErrorType::ByteError(format!("target bytes error - {}", err.to_string()))
-            })?,
+            <[u8; 1]>::try_from(&buffer[target_start..target_start + 1])
+                .map_err(|err| ByteErrorType::ByteError(format!("target bytes error - {}", err)))?,
         );
         let target = match target_present {
             0 => &[],
@@ -287,9 +526,8 @@ impl DataItem {
         };
         let anchor_start = target_start + 1 + target.len();
         let anchor_present = u8::from_le_bytes(
-            <[u8; 1]>::try_from(&buffer[anchor_start..anchor_start + 1]).map_err(|err| {
-                ByteErrorType::ByteError(format!("anchor bytes error - {}", err.to_string()))
-            })?,
+            <[u8; 1]>::try_from(&buffer[anchor_start..anchor_start + 1])
+                .map_err(|err| ByteErrorType::ByteError(format!("anchor bytes error - {}", err)))?,
         );
         let anchor = match anchor_present {
             0 => &[],
@@ -297,27 +535,26 @@ impl DataItem {
             b => {
                 return Err(ByteErrorType::ByteError(format!(
                     "anchor bytes error - {}",
-                    b.to_string()
+                    b
                 )))
             }
         };
 
         let tags_start = anchor_start + 1 + anchor.len();
         let number_of_tags = u64::from_le_bytes(
-            <[u8; 8]>::try_from(&buffer[tags_start..tags_start + 8]).map_err(|err| {
-                ByteErrorType::ByteError(format!("tag bytes error - {}", err.to_string()))
-            })?,
+            <[u8; 8]>::try_from(&buffer[tags_start..tags_start + 8])
+                .map_err(|err| ByteErrorType::ByteError(format!("tag bytes error - {}", err)))?,
         );
 
         let number_of_tags_bytes = u64::from_le_bytes(
-            <[u8; 8]>::try_from(&buffer[tags_start + 8..tags_start + 16]).map_err(|err| {
-                ByteErrorType::ByteError(format!("tag bytes error - {}", err.to_string()))
-            })?,
+            <[u8; 8]>::try_from(&buffer[tags_start + 8..tags_start + 16])
+                .map_err(|err| ByteErrorType::ByteError(format!("tag bytes error - {}", err)))?,
         );
 
-        let mut b = buffer.to_vec();
-        let mut tags_bytes =
-            &mut b[tags_start + 16..tags_start + 16 + number_of_tags_bytes as usize];
+        // only the tags are copied, the decoder needs them mutable
+        let mut b =
+            buffer[tags_start + 16..tags_start + 16 + number_of_tags_bytes as usize].to_vec();
+        let mut tags_bytes = &mut b[..];
 
         let tags = if number_of_tags_bytes > 0 {
             tags_bytes.decode()?
@@ -337,17 +574,18 @@ impl DataItem {
             anchor: anchor.to_vec(),
             tags,
             data: Data::None,
+            data_hash: None,
         };
 
         Ok((data_item, tags_start + 16 + number_of_tags_bytes as usize))
     }
 
-    pub fn from_bytes(buffer: Vec<u8>) -> Result<Self, ByteErrorType> {
+    pub fn from_bytes(buffer: impl Into<Bytes>) -> Result<Self, ByteErrorType> {
+        let buffer: Bytes = buffer.into();
         let (bundlr_tx, data_start) = DataItem::from_info_bytes(&buffer)?;
-        let data = &buffer[data_start..buffer.len()];
 
         Ok(DataItem {
-            data: Data::Bytes(data.to_vec()),
+            data: Data::Bytes(buffer.slice(data_start..)),
             ..bundlr_tx
         })
     }
@@ -375,9 +613,10 @@ impl DataItem {
             + encoded_tags.len() as u64
             + data.len() as u64;
 
-        let mut b = Vec::with_capacity(TryInto::<usize>::try_into(length).map_err(|err| {
-            ByteErrorType::ByteError(format!("data length error - {} ", err.to_string()))
-        })?);
+        let mut b =
+            Vec::with_capacity(TryInto::<usize>::try_into(length).map_err(|err| {
+                ByteErrorType::ByteError(format!("data length error - {} ", err))
+            })?);
 
         let sig_type: [u8; 2] = (self.signature_type.clone() as u16).to_le_bytes();
         let target_presence_byte = if self.target.is_empty() {
@@ -421,37 +660,131 @@ impl DataItem {
     }
 
     pub fn owner(&self) -> String {
-        let owner_base64 = base64_url::encode(&self.owner);
-        owner_base64
+        base64_url::encode(&self.owner)
+    }
+
+    /*
+        the address of the signer, an Ethereum address
+        for Ethereum keys and the arweave style hash of
+        the public key for everything else
+    */
+    pub fn owner_address(&self) -> String {
+        match self.signature_type {
+            SignerMap::Ethereum => ethereum_address(&self.owner),
+            _ => {
+                let mut hasher = Sha256::new();
+                hasher.update(&self.owner);
+                base64_url::encode(&hasher.finalize().to_vec())
+            }
+        }
+    }
+
+    // check the signature against the deep hash of the item
+    pub fn verify_signature(&self) -> Result<(), ByteErrorType> {
+        let message = self.get_message()?;
+        let valid = match self.signature_type {
+            SignerMap::Arweave => verify_arweave(&self.owner, &message, &self.signature)?,
+            SignerMap::ED25519 => UnparsedPublicKey::new(&ED25519, &self.owner)
+                .verify(&message, &self.signature)
+                .is_ok(),
+            SignerMap::Ethereum => {
+                let prefixed = [
+                    format!("\x19Ethereum Signed Message:\n{}", message.len()).as_bytes(),
+                    &message,
+                ]
+                .concat();
+                let verifying_key = VerifyingKey::from_sec1_bytes(&self.owner)
+                    .map_err(|e| ByteErrorType::ByteError(format!("invalid owner key: {}", e)))?;
+                // the trailing recovery byte is not needed with the key at hand
+                match self
+                    .signature
+                    .get(..64)
+                    .and_then(|sig| Signature::from_slice(sig).ok())
+                {
+                    Some(signature) => {
+                        let signature = signature.normalize_s().unwrap_or(signature);
+                        verifying_key
+                            .verify_prehash(&keccak256(&prefixed), &signature)
+                            .is_ok()
+                    }
+                    None => false,
+                }
+            }
+            SignerMap::None => false,
+        };
+
+        match valid {
+            true => Ok(()),
+            false => Err(ByteErrorType::ByteError(format!(
+                "invalid {} signature for data item {}",
+                self.signature_type.get_config().sig_name,
+                self.id()
+            ))),
+        }
     }
 
     pub fn target(&self) -> String {
-        let target_base64 = base64_url::encode(&self.target);
-        target_base64
+        base64_url::encode(&self.target)
+    }
+
+    // a data item whose data is itself an ANS-104 bundle
+    pub fn is_bundle(&self) -> bool {
+        let has_tag = |name: &str, value: &str| {
+            self.tags
+                .iter()
+                .any(|tag| tag.name == name && tag.value == value)
+        };
+        has_tag("Bundle-Format", "binary") && has_tag("Bundle-Version", "2.0.0")
+    }
+
+    pub fn nested_bundle(&self) -> Result<DataBundle, ByteErrorType> {
+        match &self.data {
+            Data::Bytes(d) => DataBundle::from_shared(d),
+            Data::None => Err(ByteErrorType::ByteError("no bundle data".to_string())),
+        }
+    }
+
+    pub fn replace_data(&mut self, data: impl Into<Bytes>) {
+        self.data = Data::Bytes(data.into());
+        self.data_hash = None;
+    }
+
+    // only for a hash taken over exactly this item's data
+    pub fn set_data_hash(&mut self, hash: Bytes) {
+        self.data_hash = Some(hash);
     }
 
-    pub fn tags(&self) -> Vec<Tag> {
-        self.tags.clone()
+    // new items get a random anchor, a rebuilt one needs the original
+    pub fn set_anchor(&mut self, anchor: Vec<u8>) {
+        self.anchor = anchor;
+    }
+
+    pub fn tags(&self) -> &[Tag] {
+        &self.tags
     }
 
     pub fn data(&self) -> Option<String> {
         match &self.data {
-            Data::Bytes(d) => match String::from_utf8(d.clone()) {
-                Ok(s) => Some(s),
-                Err(_) => None,
-            },
+            Data::Bytes(d) => std::str::from_utf8(d).ok().map(|s| s.to_string()),
+            Data::None => None,
+        }
+    }
+
+    // the raw data, sharing the item's buffer
+    pub fn data_bytes(&self) -> Option<Bytes> {
+        match &self.data {
+            Data::Bytes(d) => Some(d.clone()),
             Data::None => None,
         }
     }
 
     pub fn signature(&self) -> String {
-        let sig_base64 = base64_url::encode(&self.signature);
-        sig_base64
+        base64_url::encode(&self.signature)
     }
 
     pub fn anchor(&self) -> String {
-        match String::from_utf8(self.anchor.clone()) {
-            Ok(s) => s,
+        match std::str::from_utf8(&self.anchor) {
+            Ok(s) => s.to_string(),
             Err(_) => "".to_string(),
         }
     }
@@ -512,4 +845,118 @@ mod tests {
         let bundle_bytes = data_bundle.to_bytes();
         assert!(bundle_bytes.is_ok(), "Bundling failed");
     }
+
+    #[test]
+    fn test_bundle_from_bytes() {
+        let item_bytes = base64_url::decode(&ITEM_STR.to_string()).expect("failed to decode");
+        let data_item = DataItem::from_bytes(item_bytes).expect("failed to build data item");
+        let mut data_bundle = DataBundle::new(vec![]);
+        data_bundle.add_item(data_item.clone());
+        data_bundle.add_item(data_item.clone());
+        let bundle_bytes = data_bundle.to_bytes().expect("failed to bundle");
+
+        let parsed = DataBundle::from_bytes(&bundle_bytes).expect("failed to parse bundle");
+        assert_eq!(parsed.items.len(), 2);
+        assert_eq!(parsed.items[1].id(), data_item.id());
+
+        let mut corrupted = bundle_bytes.clone();
+        corrupted[32 + 32] ^= 1;
+        assert!(DataBundle::from_bytes(&corrupted).is_err());
+        assert!(DataBundle::from_bytes(&bundle_bytes[..100]).is_err());
+    }
+
+    #[test]
+    fn test_shared_bundle_data() {
+        let item_bytes = base64_url::decode(&ITEM_STR.to_string()).expect("failed to decode");
+        let data_item = DataItem::from_bytes(item_bytes).expect("failed to build data item");
+        let mut data_bundle = DataBundle::new(vec![]);
+        data_bundle.add_item(data_item.clone());
+        let bundle_bytes = Bytes::from(data_bundle.to_bytes().expect("failed to bundle"));
+
+        // the nested item points into the bundle instead of a copy of it
+        let parsed = DataBundle::from_shared(&bundle_bytes).expect("failed to parse bundle");
+        let data = parsed.items[0].data_bytes().expect("no data");
+        let range = bundle_bytes.as_ptr_range();
+        assert!(range.contains(&data.as_ptr()));
+        assert_eq!(data, data_item.data_bytes().expect("no data"));
+        assert!(parsed.items[0].verify_signature().is_ok());
+    }
+
+    #[test]
+    fn test_verify_arweave_signature() {
+        let item_bytes = base64_url::decode(&ITEM_STR.to_string()).expect("failed to decode");
+        let data_item = DataItem::from_bytes(item_bytes).expect("failed to build data item");
+        assert!(data_item.verify_signature().is_ok());
+
+        let mut tampered = data_item.clone();
+        tampered.replace_data(b"tampered".to_vec());
+        assert!(tampered.verify_signature().is_err());
+    }
+
+    #[test]
+    fn test_verify_ed25519_signature() {
+        use ring::signature::{Ed25519KeyPair, KeyPair};
+
+        let key_pair = Ed25519KeyPair::from_seed_unchecked(&[7u8; 32]).expect("invalid seed");
+        let mut data_item = DataItem::new(
+            vec![],
+            b"hello".to_vec(),
+            vec![],
+            key_pair.public_key().as_ref().to_vec(),
+        )
+        .expect("failed to build data item");
+        data_item.signature_type = SignerMap::ED25519;
+        let message = data_item.get_message().expect("failed to hash");
+        data_item.signature = key_pair.sign(&message).as_ref().to_vec();
+
+        let parsed = DataItem::from_bytes(data_item.as_bytes().expect("failed to serialize"))
+            .expect("failed to parse data item");
+        assert!(parsed.verify_signature().is_ok());
+
+        let mut tampered = parsed.clone();
+        tampered.replace_data(b"goodbye".to_vec());
+        assert!(tampered.verify_signature().is_err());
+    }
+
+    #[test]
+    fn test_verify_ethereum_signature() {
+        use k256::ecdsa::SigningKey;
+
+        let mut secret = [0u8; 32];
+        secret[31] = 1;
+        let signing_key = SigningKey::from_slice(&secret).expect("invalid key");
+        let public_key = signing_key
+            .verifying_key()
+            .to_encoded_point(false)
+            .as_bytes()
+            .to_vec();
+        let mut data_item = DataItem::new(vec![], b"hello".to_vec(), vec![], public_key)
+            .expect("failed to build data item");
+        data_item.signature_type = SignerMap::Ethereum;
+        assert_eq!(
+            data_item.owner_address(),
+            "0x7E5F4552091A69125d5DfCb7b8C2659029395Bdf"
+        );
+
+        let message = data_item.get_message().expect("failed to hash");
+        let prefixed = [
+            format!("\x19Ethereum Signed Message:\n{}", message.len()).as_bytes(),
+            &message,
+        ]
+        .concat();
+        let (signature, recovery_id) = signing_key
+            .sign_prehash_recoverable(&keccak256(&prefixed))
+            .expect("failed to sign");
+        let mut signature_bytes = signature.to_bytes().to_vec();
+        signature_bytes.push(recovery_id.to_byte() + 27);
+        data_item.signature = signature_bytes;
+
+        let parsed = DataItem::from_bytes(data_item.as_bytes().expect("failed to serialize"))
+            .expect("failed to parse data item");
+        assert!(parsed.verify_signature().is_ok());
+
+        let mut tampered = parsed.clone();
+        tampered.replace_data(b"goodbye".to_vec());
+        assert!(tampered.verify_signature().is_err());
+    }
 }
@@ -0,0 +1,125 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use dashmap::DashMap;
+use tokio::sync::broadcast::error::RecvError;
+
+use super::events::{DomainEvent, EventBus};
+use super::json::{Message, Process};
+
+/*
+    Read side cache of process metadata and the latest
+    message of each process. The primary fills it from its
+    own EventBus, a standby fills it from the primary's
+    event stream so it is already warm if read traffic
+    moves to it or it is promoted.
+*/
+pub struct ReadCache {
+    processes: DashMap<String, Process>,
+    latest: DashMap<String, Message>,
+    max_entries: AtomicUsize,
+}
+
+impl ReadCache {
+    /*
+        max_entries bounds each map, once full new
+        processes are served from the store instead
+    */
+    pub fn new(max_entries: usize) -> Self {
+        ReadCache {
+            processes: DashMap::new(),
+            latest: DashMap::new(),
+            max_entries: AtomicUsize::new(max_entries),
+        }
+    }
+
+    // when shrinking below what is held the cache starts over
+    pub fn set_max_entries(&self, max_entries: usize) {
+        self.max_entries.store(max_entries, Ordering::Relaxed);
+        if self.processes.len() > max_entries || self.latest.len() > max_entries {
+            self.clear();
+        }
+    }
+
+    fn max_entries(&self) -> usize {
+        self.max_entries.load(Ordering::Relaxed)
+    }
+
+    pub fn get_process(&self, process_id: &str) -> Option<Process> {
+        self.processes.get(process_id).map(|p| p.value().clone())
+    }
+
+    pub fn put_process(&self, process: &Process) {
+        if self.processes.len() < self.max_entries()
+            || self.processes.contains_key(&process.process_id)
+        {
+            self.processes
+                .insert(process.process_id.clone(), process.clone());
+        }
+    }
+
+    pub fn latest_message(&self, process_id: &str) -> Option<Message> {
+        self.latest.get(process_id).map(|m| m.value().clone())
+    }
+
+    // only moves forward, an older message never replaces a newer one
+    pub fn put_latest(&self, message: &Message) {
+        let (process_id, incoming) = match (message.process_id(), position(message)) {
+            (Ok(p), Some(pos)) => (p, pos),
+            _ => return,
+        };
+
+        if let Some(current) = self.latest.get(&process_id) {
+            if position(current.value()).is_some_and(|c| c >= incoming) {
+                return;
+            }
+        } else if self.latest.len() >= self.max_entries() {
+            return;
+        }
+
+        self.latest.insert(process_id, message.clone());
+    }
+
+    /*
+        after missing events the latest messages may be
+        stale, process metadata never changes so it stays
+    */
+    pub fn clear_latest(&self) {
+        self.latest.clear();
+    }
+
+    // everything is refilled from the store on the next read
+    pub fn clear(&self) {
+        self.processes.clear();
+        self.latest.clear();
+    }
+
+    pub fn apply(&self, event: &DomainEvent) {
+        match event {
+            DomainEvent::ProcessCreated { process } => self.put_process(process),
+            DomainEvent::MessageSequenced { message } => self.put_latest(message),
+            _ => (),
+        }
+    }
+}
+
+fn position(message: &Message) -> Option<(i32, i32)> {
+    match (message.epoch(), message.nonce()) {
+        (Ok(epoch), Ok(nonce)) => Some((epoch, nonce)),
+        _ => None,
+    }
+}
+
+// keeps the cache current from the local EventBus
+pub fn spawn_cache_sink(bus: &EventBus, cache: Arc<ReadCache>) {
+    let mut receiver = bus.subscribe();
+    tokio::spawn(async move {
+        loop {
+            match receiver.recv().await {
+                Ok(event) => cache.apply(&event),
+                Err(RecvError::Lagged(_)) => cache.clear_latest(),
+                Err(RecvError::Closed) => break,
+            }
+        }
+    });
+}
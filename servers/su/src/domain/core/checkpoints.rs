@@ -0,0 +1,74 @@
+use serde::{Deserialize, Serialize};
+
+use super::dal::FlowError;
+
+/*
+    A CU checkpoint of a process, the state of the process
+    after the message at (epoch, nonce) was evaluated is
+    stored in the arweave tx checkpoint_id. CUs sharing an
+    su register them so others can start from the latest
+    one instead of evaluating from genesis. The su does not
+    check the state itself, a CU loading a checkpoint has
+    to trust or verify whoever made it, cu says who that is.
+*/
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Checkpoint {
+    pub process_id: String,
+    pub epoch: i32,
+    pub nonce: i32,
+    pub checkpoint_id: String,
+    pub cu: Option<String>,
+    // when the su registered it
+    pub timestamp: i64,
+}
+
+// the body a CU posts to register a checkpoint
+#[derive(Deserialize)]
+pub struct CheckpointRequest {
+    #[serde(default)]
+    pub epoch: i32,
+    pub nonce: i32,
+    pub checkpoint_id: String,
+    pub cu: Option<String>,
+}
+
+impl CheckpointRequest {
+    pub fn from_body(body: &[u8]) -> Result<CheckpointRequest, FlowError> {
+        let request: CheckpointRequest = serde_json::from_slice(body)
+            .map_err(|e| FlowError::Validation(format!("Invalid checkpoint: {}", e)))?;
+        if request.epoch < 0 || request.nonce < 0 {
+            return Err(FlowError::Validation(
+                "Checkpoint epoch and nonce cannot be negative".to_string(),
+            ));
+        }
+        // an arweave tx id is 43 base64url characters
+        if request.checkpoint_id.len() != 43 || base64_url::decode(&request.checkpoint_id).is_err()
+        {
+            return Err(FlowError::Validation(
+                "Checkpoint checkpoint_id must be an arweave tx id".to_string(),
+            ));
+        }
+        Ok(request)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_checkpoint_request() {
+        let request = CheckpointRequest::from_body(
+            br#"{"nonce": 42, "checkpoint_id": "6oYAxVAnH8yKsZKpMgHSbRv7uVWey68PAqYuSXeZBbg"}"#,
+        )
+        .unwrap();
+        assert_eq!((request.epoch, request.nonce), (0, 42));
+        assert!(request.cu.is_none());
+
+        assert!(CheckpointRequest::from_body(br#"{"nonce": 1, "checkpoint_id": "abc"}"#).is_err());
+        assert!(CheckpointRequest::from_body(
+            br#"{"nonce": -1, "checkpoint_id": "6oYAxVAnH8yKsZKpMgHSbRv7uVWey68PAqYuSXeZBbg"}"#
+        )
+        .is_err());
+    }
+}
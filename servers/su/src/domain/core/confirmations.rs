@@ -0,0 +1,362 @@
+use std::collections::{HashSet, VecDeque};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use serde::Serialize;
+use serde_json::json;
+use tokio::time::sleep;
+
+use super::dal::{BundleRef, Gateway};
+use super::flows::Deps;
+
+// how often due uploads are looked up on the gateway
+const CHECK_INTERVAL: Duration = Duration::from_secs(60);
+// ids per gateway query
+const CHECK_BATCH: usize = 100;
+// unconfirmed uploads kept for the admin api
+const UNCONFIRMED_ENTRIES: usize = 1000;
+// sampling pauses while this many wait, e.g. the gateway is down
+const MAX_PENDING: usize = 10000;
+
+const CONFIRMED_QUERY: &str = r#"
+query($ids: [ID!], $first: Int) {
+  transactions(ids: $ids, first: $first) {
+    edges { node { id block { height } } }
+  }
+}"#;
+
+struct PendingUpload {
+    id: String,
+    bundle_ref: BundleRef,
+    uploaded_at: Instant,
+    repaired: bool,
+}
+
+#[derive(Serialize, Clone)]
+pub struct UnconfirmedUpload {
+    pub id: String,
+    // when the upload was handed off
+    pub uploaded_at: u64,
+    pub repaired: bool,
+}
+
+#[derive(Serialize)]
+pub struct ConfirmationStats {
+    pub sampled: u64,
+    pub pending: usize,
+    pub confirmed: u64,
+    pub repaired: u64,
+    pub unconfirmed: u64,
+}
+
+// what a gateway check found for the due uploads
+#[derive(Default)]
+pub struct Resolution {
+    // uploads to hand to the upload node again
+    pub repair: Vec<(String, BundleRef)>,
+    pub expired: Vec<UnconfirmedUpload>,
+}
+
+/*
+    A bundle handed to the upload node can still be lost if
+    the node never posts it to arweave, and nothing else
+    would notice. One in every `sample` uploads is looked
+    up on the gateway once `verify_after` passed. One still
+    not in a block after `repair_after` is uploaded again
+    from the stored binary, the same signed bundle so its
+    nonce does not change. One still missing after
+    `timeout` is counted as unconfirmed and logged.
+*/
+pub struct UploadConfirmations {
+    sample: u64,
+    verify_after: Duration,
+    repair_after: Option<Duration>,
+    timeout: Duration,
+    seen: AtomicU64,
+    confirmed: AtomicU64,
+    repaired: AtomicU64,
+    pending: Mutex<VecDeque<PendingUpload>>,
+    unconfirmed: Mutex<VecDeque<UnconfirmedUpload>>,
+    unconfirmed_count: AtomicU64,
+}
+
+impl UploadConfirmations {
+    // a repair_after_ms of 0 never uploads again
+    pub fn new(sample: u64, verify_after_ms: u64, repair_after_ms: u64, timeout_ms: u64) -> Self {
+        UploadConfirmations {
+            sample,
+            verify_after: Duration::from_millis(verify_after_ms),
+            repair_after: match repair_after_ms {
+                0 => None,
+                ms => Some(Duration::from_millis(ms.max(verify_after_ms))),
+            },
+            timeout: Duration::from_millis(timeout_ms.max(verify_after_ms)),
+            seen: AtomicU64::new(0),
+            confirmed: AtomicU64::new(0),
+            repaired: AtomicU64::new(0),
+            pending: Mutex::new(VecDeque::new()),
+            unconfirmed: Mutex::new(VecDeque::new()),
+            unconfirmed_count: AtomicU64::new(0),
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.sample > 0
+    }
+
+    // keeps every sample-th upload for checking
+    pub fn record(&self, id: String, bundle_ref: BundleRef, now: Instant) {
+        if !self.enabled() || self.seen.fetch_add(1, Ordering::SeqCst) % self.sample != 0 {
+            return;
+        }
+        if let Ok(mut pending) = self.pending.lock() {
+            if pending.len() >= MAX_PENDING {
+                return;
+            }
+            pending.push_back(PendingUpload {
+                id,
+                bundle_ref,
+                uploaded_at: now,
+                repaired: false,
+            });
+        }
+    }
+
+    // the oldest uploads old enough to be looked up
+    fn due(&self, now: Instant) -> Vec<String> {
+        match self.pending.lock() {
+            Ok(pending) => pending
+                .iter()
+                .take_while(|p| now.duration_since(p.uploaded_at) >= self.verify_after)
+                .take(CHECK_BATCH)
+                .map(|p| p.id.clone())
+                .collect(),
+            Err(_) => vec![],
+        }
+    }
+
+    /*
+        drops the confirmed uploads, checked ones past
+        repair_after are repaired once and ones past the
+        timeout become unconfirmed
+    */
+    fn resolve(&self, checked: &[String], confirmed: &HashSet<String>, now: Instant) -> Resolution {
+        let mut resolution = Resolution::default();
+        if let Ok(mut pending) = self.pending.lock() {
+            pending.retain_mut(|p| {
+                if !checked.contains(&p.id) {
+                    return true;
+                }
+                if confirmed.contains(&p.id) {
+                    self.confirmed.fetch_add(1, Ordering::SeqCst);
+                    return false;
+                }
+                let age = now.duration_since(p.uploaded_at);
+                if age >= self.timeout {
+                    resolution.expired.push(UnconfirmedUpload {
+                        id: p.id.clone(),
+                        uploaded_at: unix_ms().saturating_sub(age.as_millis() as u64),
+                        repaired: p.repaired,
+                    });
+                    return false;
+                }
+                if !p.repaired && self.repair_after.is_some_and(|after| age >= after) {
+                    p.repaired = true;
+                    self.repaired.fetch_add(1, Ordering::SeqCst);
+                    resolution.repair.push((p.id.clone(), p.bundle_ref.clone()));
+                }
+                true
+            });
+        }
+
+        self.unconfirmed_count
+            .fetch_add(resolution.expired.len() as u64, Ordering::SeqCst);
+        if let Ok(mut unconfirmed) = self.unconfirmed.lock() {
+            for upload in resolution.expired.iter() {
+                if unconfirmed.len() >= UNCONFIRMED_ENTRIES {
+                    unconfirmed.pop_front();
+                }
+                unconfirmed.push_back(upload.clone());
+            }
+        }
+        resolution
+    }
+
+    pub fn stats(&self) -> ConfirmationStats {
+        ConfirmationStats {
+            sampled: match self.enabled() {
+                true => self.seen.load(Ordering::SeqCst).div_ceil(self.sample),
+                false => 0,
+            },
+            pending: self.pending.lock().map(|p| p.len()).unwrap_or(0),
+            confirmed: self.confirmed.load(Ordering::SeqCst),
+            repaired: self.repaired.load(Ordering::SeqCst),
+            unconfirmed: self.unconfirmed_count.load(Ordering::SeqCst),
+        }
+    }
+
+    // the most recent unconfirmed uploads, oldest first
+    pub fn unconfirmed(&self) -> Vec<UnconfirmedUpload> {
+        match self.unconfirmed.lock() {
+            Ok(unconfirmed) => unconfirmed.iter().cloned().collect(),
+            Err(_) => vec![],
+        }
+    }
+}
+
+fn unix_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+// which of the ids the gateway has in a block
+pub async fn confirmed_ids(
+    gateway: &dyn Gateway,
+    ids: &[String],
+) -> Result<HashSet<String>, String> {
+    let data = gateway
+        .graphql(CONFIRMED_QUERY, json!({ "ids": ids, "first": ids.len() }))
+        .await?;
+    let edges = data["transactions"]["edges"]
+        .as_array()
+        .cloned()
+        .unwrap_or_default();
+    Ok(edges
+        .iter()
+        .filter(|edge| !edge["node"]["block"].is_null())
+        .filter_map(|edge| edge["node"]["id"].as_str().map(|id| id.to_string()))
+        .collect())
+}
+
+// hands the stored binary to the upload node again
+async fn repair(deps: &Arc<Deps>, id: &str, bundle_ref: &BundleRef) -> Result<(), String> {
+    let binary = deps
+        .data_store
+        .get_stored_bundle(bundle_ref)
+        .await
+        .map_err(|e| format!("{:?}", e))?;
+    deps.uploader.upload(binary.into())?;
+    deps.logger
+        .log(format!("uploading {} again, it is not on arweave yet", id));
+    Ok(())
+}
+
+/*
+    bundles the uploader gave up on are kept in the store,
+    they are handed to it again every CHECK_INTERVAL until
+    it takes them. One it gives up on again comes back
+*/
+pub fn spawn_redelivery(deps: Arc<Deps>) {
+    tokio::spawn(async move {
+        loop {
+            sleep(CHECK_INTERVAL).await;
+            let undelivered = match deps.data_store.get_undelivered(CHECK_BATCH as i64).await {
+                Ok(undelivered) => undelivered,
+                Err(e) => {
+                    deps.logger
+                        .error(format!("undelivered uploads not read - {:?}", e));
+                    continue;
+                }
+            };
+            for (id, bundle) in undelivered.into_iter() {
+                if let Err(e) = deps.uploader.upload(bundle.into()) {
+                    deps.logger
+                        .error(format!("upload {} still refused - {:?}", id, e));
+                    break;
+                }
+                if let Err(e) = deps.data_store.delete_undelivered(&id).await {
+                    deps.logger
+                        .error(format!("undelivered upload {} not cleared - {:?}", id, e));
+                }
+                deps.logger
+                    .log(format!("handed undelivered upload {} over again", id));
+            }
+        }
+    });
+}
+
+pub fn spawn_confirmation_checks(deps: Arc<Deps>) {
+    if !deps.confirmations.enabled() {
+        return;
+    }
+
+    tokio::spawn(async move {
+        loop {
+            sleep(CHECK_INTERVAL).await;
+            let due = deps.confirmations.due(Instant::now());
+            if due.is_empty() {
+                continue;
+            }
+            let confirmed = match confirmed_ids(&*deps.gateway, &due).await {
+                Ok(confirmed) => confirmed,
+                Err(e) => {
+                    deps.logger
+                        .error(format!("upload confirmation check failed - {}", e));
+                    continue;
+                }
+            };
+
+            let resolution = deps.confirmations.resolve(&due, &confirmed, Instant::now());
+            for (id, bundle_ref) in resolution.repair.iter() {
+                if let Err(e) = repair(&deps, id, bundle_ref).await {
+                    deps.logger
+                        .error(format!("upload {} could not be repaired - {}", id, e));
+                }
+            }
+            for upload in resolution.expired.iter() {
+                deps.logger.error(format!(
+                    "upload {} is still not confirmed on arweave",
+                    upload.id
+                ));
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_confirmations() {
+        let confirmations = UploadConfirmations::new(2, 1000, 3000, 5000);
+        let start = Instant::now();
+        for id in ["a", "b", "c", "d"] {
+            confirmations.record(id.to_string(), BundleRef::Process(id.to_string()), start);
+        }
+        // every second upload is sampled
+        assert_eq!(confirmations.stats().pending, 2);
+        assert!(confirmations.due(start).is_empty());
+
+        let later = start + Duration::from_millis(1000);
+        let due = confirmations.due(later);
+        assert_eq!(due, vec!["a".to_string(), "c".to_string()]);
+
+        let confirmed: HashSet<String> = ["a".to_string()].into_iter().collect();
+        let resolution = confirmations.resolve(&due, &confirmed, later);
+        assert!(resolution.repair.is_empty() && resolution.expired.is_empty());
+        assert_eq!(confirmations.stats().confirmed, 1);
+        assert_eq!(confirmations.stats().pending, 1);
+
+        // repaired once past repair_after
+        let repair_at = start + Duration::from_millis(3000);
+        let resolution = confirmations.resolve(&due, &HashSet::new(), repair_at);
+        assert_eq!(resolution.repair.len(), 1);
+        assert_eq!(resolution.repair[0].0, "c");
+        assert!(confirmations
+            .resolve(&due, &HashSet::new(), repair_at)
+            .repair
+            .is_empty());
+        assert_eq!(confirmations.stats().repaired, 1);
+
+        let resolution =
+            confirmations.resolve(&due, &HashSet::new(), start + Duration::from_millis(5000));
+        assert_eq!(resolution.expired.len(), 1);
+        assert!(confirmations.unconfirmed()[0].repaired);
+        assert_eq!(confirmations.stats().unconfirmed, 1);
+        assert_eq!(confirmations.stats().pending, 0);
+    }
+}
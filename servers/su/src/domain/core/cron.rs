@@ -0,0 +1,623 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use bundlr_sdk::tags::Tag;
+use serde::{Deserialize, Serialize};
+use tokio::time::sleep;
+
+use super::flows::{self, Deps};
+use super::json::{
+    AssignmentInner, Edge, Message, MessageInner, PageInfo, PaginatedMessages, Process, SortOrder,
+    TagFilter,
+};
+
+const CRON_INTERVAL_TAG: &str = "Cron-Interval";
+const CRON_TAG_PREFIX: &str = "Cron-Tag-";
+
+// due crons looked at per pass, the next pass picks up the rest
+const CRONS_PER_PASS: i64 = 100;
+
+fn unix_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+/*
+    "5-minutes" or "1-hour" to milliseconds. Months are 30
+    days and years 365, block intervals need a block clock
+    the su does not keep so they are turned away.
+*/
+pub fn parse_interval(value: &str) -> Result<i64, String> {
+    let invalid = || format!("Invalid {} {}", CRON_INTERVAL_TAG, value);
+    let (count, unit) = value.trim().split_once('-').ok_or_else(invalid)?;
+    let count: i64 = count.trim().parse().map_err(|_| invalid())?;
+    if count < 1 {
+        return Err(invalid());
+    }
+    let unit = unit.trim().to_lowercase();
+    let unit_ms: i64 = match unit.trim_end_matches('s') {
+        "millisecond" => 1,
+        "second" => 1000,
+        "minute" => 60 * 1000,
+        "hour" => 60 * 60 * 1000,
+        "day" => 24 * 60 * 60 * 1000,
+        "week" => 7 * 24 * 60 * 60 * 1000,
+        "month" => 30 * 24 * 60 * 60 * 1000,
+        "year" => 365 * 24 * 60 * 60 * 1000,
+        "block" => {
+            return Err(format!(
+                "{} {} is in blocks, only time intervals are supported",
+                CRON_INTERVAL_TAG, value
+            ))
+        }
+        _ => return Err(invalid()),
+    };
+    count.checked_mul(unit_ms).ok_or_else(invalid)
+}
+
+/*
+    One Cron-Interval of a process. Every interval_ms after
+    the spawn a message carrying the Cron-Tag-* tags of the
+    spawn, without the prefix, is due. next_run is the slot
+    of the next one in unix ms.
+*/
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CronDefinition {
+    pub process_id: String,
+    pub interval: String,
+    pub interval_ms: i64,
+    pub tags: Vec<Tag>,
+    pub next_run: i64,
+}
+
+impl CronDefinition {
+    // what a spawn declares, an invalid interval rejects the spawn
+    pub fn from_tags(process_id: &str, spawned_at: i64, tags: &[Tag]) -> Result<Vec<Self>, String> {
+        let cron_tags: Vec<Tag> = tags
+            .iter()
+            .filter_map(|tag| {
+                tag.name
+                    .strip_prefix(CRON_TAG_PREFIX)
+                    .filter(|name| !name.is_empty())
+                    .map(|name| Tag::new(name, &tag.value))
+            })
+            .collect();
+
+        let mut definitions: Vec<CronDefinition> = vec![];
+        for tag in tags.iter().filter(|tag| tag.name == CRON_INTERVAL_TAG) {
+            if definitions.iter().any(|d| d.interval == tag.value) {
+                continue;
+            }
+            let interval_ms = parse_interval(&tag.value)?;
+            definitions.push(CronDefinition {
+                process_id: process_id.to_string(),
+                interval: tag.value.clone(),
+                interval_ms,
+                tags: cron_tags.clone(),
+                next_run: spawned_at.saturating_add(interval_ms),
+            });
+        }
+        Ok(definitions)
+    }
+
+    /*
+        what a spawn declares, also turned away when an
+        interval is shorter than CRON_MIN_INTERVAL_MS
+    */
+    pub fn check_spawn(tags: &[Tag], min_interval_ms: u64) -> Result<(), String> {
+        for cron in Self::from_tags("", 0, tags)? {
+            if cron.interval_ms < min_interval_ms as i64 {
+                return Err(format!(
+                    "{} {} is shorter than the minimum of {}ms",
+                    CRON_INTERVAL_TAG, cron.interval, min_interval_ms
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn from_process(process: &Process) -> Result<Vec<Self>, String> {
+        Self::from_tags(&process.process_id, process.timestamp, &process.tags)
+    }
+
+    // the tags of the message due at slot
+    pub fn message_tags(&self, variant: &str, slot: i64) -> Vec<Tag> {
+        let mut tags = vec![
+            Tag::new("Data-Protocol", "ao"),
+            Tag::new("Variant", variant),
+            Tag::new("Type", "Message"),
+            Tag::new("Cron", "true"),
+            Tag::new(CRON_INTERVAL_TAG, &self.interval),
+            Tag::new("Cron-Slot", &slot.to_string()),
+        ];
+        tags.extend(self.tags.iter().cloned());
+        tags
+    }
+
+    /*
+        The slots due by now, oldest first. After a long
+        downtime only the latest max_catch_up are returned,
+        the older ones are skipped. The second value is the
+        next_run after all of them.
+    */
+    pub fn due_slots(&self, now: i64, max_catch_up: u32) -> (Vec<i64>, i64) {
+        if self.next_run > now || self.interval_ms < 1 {
+            return (vec![], self.next_run);
+        }
+        let missed = (now - self.next_run) / self.interval_ms + 1;
+        let taken = missed.min(i64::from(max_catch_up));
+        let first = self.next_run + (missed - taken) * self.interval_ms;
+        let slots: Vec<i64> = (0..taken).map(|i| first + i * self.interval_ms).collect();
+        (slots, self.next_run + missed * self.interval_ms)
+    }
+}
+
+// the Variant cron messages carry, the one of their process
+pub fn variant(process: &Process) -> String {
+    process
+        .tags
+        .iter()
+        .find(|tag| tag.name == "Variant")
+        .map(|tag| tag.value.clone())
+        .unwrap_or_else(|| "ao.TN.1".to_string())
+}
+
+/*
+    With CRON_MODE=virtual cron messages are not sequenced,
+    reads merge them in at their slots instead. They have
+    no epoch, nonce or signature and their id names the
+    interval and slot, so every read returns the same one.
+*/
+pub fn virtual_message(process: &Process, cron: &CronDefinition, slot: i64) -> Message {
+    let id = format!("cron-{}-{}", cron.interval, slot);
+    let assignment_tags = vec![
+        Tag::new("Process", &process.process_id),
+        Tag::new("Message", &id),
+        Tag::new("Timestamp", &slot.to_string()),
+        Tag::new("Cron", "true"),
+    ];
+    Message {
+        message: Some(MessageInner {
+            id: id.clone(),
+            owner: process.owner.clone(),
+            data: None,
+            tags: cron.message_tags(&variant(process), slot),
+            signature: String::new(),
+            anchor: None,
+            target: Some(process.process_id.clone()),
+        }),
+        assignment: AssignmentInner {
+            id,
+            owner: process.owner.clone(),
+            tags: assignment_tags,
+            signature: String::new(),
+            anchor: None,
+            target: None,
+        },
+    }
+}
+
+/*
+    The inclusive range of slots a page of messages covers.
+    A page with a next page ends at its last message, the
+    slots past it come with the next page.
+*/
+pub fn virtual_window(
+    from: Option<i64>,
+    to: Option<i64>,
+    from_timestamp: Option<i64>,
+    to_timestamp: Option<i64>,
+    sort: SortOrder,
+    page: &PaginatedMessages,
+    now: i64,
+) -> (i64, i64) {
+    let last = match page.page_info.has_next_page {
+        true => page.edges.last().and_then(|e| e.node.timestamp().ok()),
+        false => None,
+    };
+    let (mut low, mut high) = (from_timestamp.unwrap_or(i64::MIN), now);
+    if let Some(to_timestamp) = to_timestamp {
+        high = high.min(to_timestamp);
+    }
+    match sort {
+        // from is exclusive and to inclusive, in the order of the read
+        SortOrder::Asc => {
+            low = low.max(from.map_or(i64::MIN, |f| f.saturating_add(1)));
+            high = high
+                .min(to.unwrap_or(i64::MAX))
+                .min(last.unwrap_or(i64::MAX));
+        }
+        SortOrder::Desc => {
+            high = high.min(from.map_or(i64::MAX, |f| f.saturating_sub(1)));
+            low = low
+                .max(to.unwrap_or(i64::MIN))
+                .max(last.unwrap_or(i64::MIN));
+        }
+    }
+    (low, high)
+}
+
+// slots of a cron within the window, at most limit from the start of the read
+fn slots_within(
+    cron: &CronDefinition,
+    spawned_at: i64,
+    window: (i64, i64),
+    sort: SortOrder,
+    limit: usize,
+) -> Vec<i64> {
+    let (low, high) = window;
+    let interval = cron.interval_ms.max(1);
+    if high < low || high <= spawned_at {
+        return vec![];
+    }
+    let first = ((low.max(spawned_at) - spawned_at + interval - 1) / interval).max(1);
+    let last = (high - spawned_at) / interval;
+    if last < first {
+        return vec![];
+    }
+    let count = ((last - first + 1) as usize).min(limit);
+    (0..count as i64)
+        .map(|i| match sort {
+            SortOrder::Asc => spawned_at + (first + i) * interval,
+            SortOrder::Desc => spawned_at + (last - i) * interval,
+        })
+        .collect()
+}
+
+/*
+    Merges the virtual cron messages of the window into a
+    page of real ones. At the same timestamp the real
+    message comes first in ascending order, the page keeps
+    to limit and has a next page if any were left out.
+*/
+pub fn merge_virtual(
+    page: PaginatedMessages,
+    process: &Process,
+    crons: &[CronDefinition],
+    window: (i64, i64),
+    sort: SortOrder,
+    limit: usize,
+    filters: &[TagFilter],
+) -> PaginatedMessages {
+    let mut edges: Vec<(i64, bool, Edge)> = vec![];
+    let has_next_page = page.page_info.has_next_page;
+    for edge in page.edges.into_iter() {
+        let timestamp = edge.node.timestamp().unwrap_or(0);
+        edges.push((timestamp, false, edge));
+    }
+    for cron in crons.iter() {
+        for slot in slots_within(cron, process.timestamp, window, sort, limit) {
+            let message = virtual_message(process, cron, slot);
+            let tags = message
+                .message
+                .as_ref()
+                .map(|m| m.tags.clone())
+                .unwrap_or_default();
+            let matches = filters
+                .iter()
+                .all(|f| tags.iter().any(|t| t.name == f.name && t.value == f.value));
+            if matches {
+                edges.push((
+                    slot,
+                    true,
+                    Edge {
+                        node: message,
+                        cursor: slot.to_string(),
+                    },
+                ));
+            }
+        }
+    }
+
+    // a stable sort keeps the store order of real messages
+    match sort {
+        SortOrder::Asc => edges.sort_by_key(|(timestamp, cron, _)| (*timestamp, *cron)),
+        SortOrder::Desc => edges.sort_by_key(|(timestamp, cron, _)| (-*timestamp, !*cron)),
+    }
+    let truncated = edges.len() > limit;
+    PaginatedMessages {
+        page_info: PageInfo {
+            has_next_page: has_next_page || truncated,
+        },
+        edges: edges
+            .into_iter()
+            .take(limit)
+            .map(|(_, _, edge)| edge)
+            .collect(),
+    }
+}
+
+#[derive(Serialize)]
+pub struct CronStats {
+    pub runs: u64,
+    pub messages: u64,
+    // missed slots dropped beyond CRON_MAX_CATCH_UP
+    pub skipped: u64,
+    pub failures: u64,
+}
+
+/*
+    Sequences the cron messages of processes whose spawn
+    declared a Cron-Interval, with CRON_MODE=sequence. They
+    are signed by the su and go through the same queue as
+    any other message of the process.
+*/
+pub struct Crons {
+    runs: AtomicU64,
+    messages: AtomicU64,
+    skipped: AtomicU64,
+    failures: AtomicU64,
+}
+
+impl Default for Crons {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Crons {
+    pub fn new() -> Self {
+        Crons {
+            runs: AtomicU64::new(0),
+            messages: AtomicU64::new(0),
+            skipped: AtomicU64::new(0),
+            failures: AtomicU64::new(0),
+        }
+    }
+
+    pub fn stats(&self) -> CronStats {
+        CronStats {
+            runs: self.runs.load(Ordering::SeqCst),
+            messages: self.messages.load(Ordering::SeqCst),
+            skipped: self.skipped.load(Ordering::SeqCst),
+            failures: self.failures.load(Ordering::SeqCst),
+        }
+    }
+}
+
+async fn run_cron(deps: &Arc<Deps>, cron: &CronDefinition, now: i64) -> Result<u64, String> {
+    let (slots, next_run) = cron.due_slots(now, deps.config.cron_max_catch_up());
+    let skipped = (next_run - cron.next_run) / cron.interval_ms - slots.len() as i64;
+
+    let process = deps
+        .data_store
+        .get_process(&cron.process_id)
+        .await
+        .map_err(|e| format!("{:?}", e))?;
+    let variant = variant(&process);
+
+    /*
+        next_run moves past a slot in the transaction that
+        saves its message, a crash can neither sequence a
+        slot twice nor leave it out
+    */
+    let mut sequenced = 0;
+    for slot in slots.iter() {
+        flows::sequence_cron(
+            deps.clone(),
+            &cron.process_id,
+            &cron.interval,
+            cron.message_tags(&variant, *slot),
+            slot + cron.interval_ms,
+        )
+        .await
+        .map_err(|e| format!("{:?}", e))?;
+        deps.crons.messages.fetch_add(1, Ordering::SeqCst);
+        sequenced += 1;
+    }
+    deps.crons
+        .skipped
+        .fetch_add(skipped.max(0) as u64, Ordering::SeqCst);
+    // with CRON_MAX_CATCH_UP at 0 every missed slot is skipped
+    if slots.is_empty() {
+        deps.data_store
+            .update_cron_next_run(&cron.process_id, &cron.interval, next_run)
+            .await
+            .map_err(|e| format!("{:?}", e))?;
+    }
+    Ok(sequenced)
+}
+
+// one pass over the crons that are due
+pub async fn run_crons(deps: &Arc<Deps>) -> Result<u64, String> {
+    // a draining su admits no writes, the slots wait for the next start
+    if deps.runtime.read_only() || deps.admission.is_draining() {
+        return Ok(0);
+    }
+    let now = unix_ms() as i64;
+    let due = deps
+        .data_store
+        .get_due_crons(now, CRONS_PER_PASS)
+        .await
+        .map_err(|e| format!("{:?}", e))?;
+
+    let mut sequenced = 0;
+    for cron in due.iter() {
+        // in cluster mode the instance holding the lease runs the crons of a process
+        if let Some(leases) = deps.scheduler.leases() {
+            let leader = leases
+                .leader(&deps.data_store, &cron.process_id)
+                .await
+                .map_err(|e| format!("{:?}", e))?;
+            if leader.is_some() {
+                continue;
+            }
+        }
+        match run_cron(deps, cron, now).await {
+            Ok(count) => sequenced += count,
+            Err(e) => {
+                deps.crons.failures.fetch_add(1, Ordering::SeqCst);
+                deps.logger.error(format!(
+                    "cron {} of process {} failed - {}",
+                    cron.interval, cron.process_id, e
+                ));
+            }
+        }
+    }
+    deps.crons.runs.fetch_add(1, Ordering::SeqCst);
+    Ok(sequenced)
+}
+
+pub fn spawn_crons(deps: Arc<Deps>) {
+    let interval = Duration::from_millis(deps.config.cron_interval_ms().max(1));
+    tokio::spawn(async move {
+        loop {
+            sleep(interval).await;
+            if let Err(e) = run_crons(&deps).await {
+                deps.logger.error(format!("cron pass failed - {}", e));
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tag(name: &str, value: &str) -> Tag {
+        Tag::new(name, value)
+    }
+
+    #[test]
+    fn test_parse_interval() {
+        assert_eq!(parse_interval("10-seconds"), Ok(10_000));
+        assert_eq!(parse_interval("1-minute"), Ok(60_000));
+        assert_eq!(parse_interval("2-Hours"), Ok(7_200_000));
+        assert!(parse_interval("5-blocks").is_err());
+        assert!(parse_interval("0-minutes").is_err());
+        assert!(parse_interval("minutes").is_err());
+        assert!(parse_interval("5-fortnights").is_err());
+    }
+
+    #[test]
+    fn test_cron_definitions() {
+        let tags = vec![
+            tag("Type", "Process"),
+            tag("Cron-Interval", "1-minute"),
+            tag("Cron-Interval", "1-hour"),
+            tag("Cron-Tag-Action", "Tick"),
+        ];
+        let crons = CronDefinition::from_tags("p", 1000, &tags).unwrap();
+        assert_eq!(crons.len(), 2);
+        assert_eq!(crons[0].next_run, 61_000);
+        assert_eq!(crons[1].tags[0].name, "Action");
+
+        let message_tags = crons[0].message_tags("ao.TN.1", 61_000);
+        assert!(message_tags
+            .iter()
+            .any(|t| t.name == "Action" && t.value == "Tick"));
+
+        assert!(CronDefinition::from_tags("p", 0, &[tag("Cron-Interval", "x")]).is_err());
+        assert!(CronDefinition::from_tags("p", 0, &[tag("Type", "Process")])
+            .unwrap()
+            .is_empty());
+
+        assert!(CronDefinition::check_spawn(&tags, 60_000).is_ok());
+        assert!(CronDefinition::check_spawn(&tags, 60_001).is_err());
+        let fast = [tag("Cron-Interval", "10-milliseconds")];
+        assert!(CronDefinition::check_spawn(&fast, 1000).is_err());
+        assert!(CronDefinition::check_spawn(&fast, 0).is_ok());
+    }
+
+    #[test]
+    fn test_merge_virtual() {
+        let process: Process = serde_json::from_value(serde_json::json!({
+            "process_id": "p",
+            "block": "1",
+            "owner": { "address": "a", "key": "k" },
+            "tags": [{ "name": "Cron-Interval", "value": "10-milliseconds" }],
+            "timestamp": 1000,
+            "data": null,
+            "anchor": null,
+            "signature": null
+        }))
+        .unwrap();
+        let crons = CronDefinition::from_process(&process).unwrap();
+        let real = |timestamp: i64| Edge {
+            node: Message {
+                message: None,
+                assignment: AssignmentInner {
+                    id: timestamp.to_string(),
+                    owner: process.owner.clone(),
+                    tags: vec![tag("Timestamp", &timestamp.to_string())],
+                    signature: String::new(),
+                    anchor: None,
+                    target: None,
+                },
+            },
+            cursor: timestamp.to_string(),
+        };
+        let page = |timestamps: &[i64], has_next_page: bool| PaginatedMessages {
+            page_info: PageInfo { has_next_page },
+            edges: timestamps.iter().map(|t| real(*t)).collect(),
+        };
+        let ids = |merged: &PaginatedMessages| -> Vec<String> {
+            merged
+                .edges
+                .iter()
+                .map(|e| e.node.assignment.id.clone())
+                .collect()
+        };
+
+        // slots run up to now when the page is the last one
+        let read = page(&[1015], false);
+        let window = virtual_window(None, None, None, None, SortOrder::Asc, &read, 1030);
+        let merged = merge_virtual(read, &process, &crons, window, SortOrder::Asc, 10, &[]);
+        assert_eq!(
+            ids(&merged),
+            vec![
+                "cron-10-milliseconds-1010",
+                "1015",
+                "cron-10-milliseconds-1020",
+                "cron-10-milliseconds-1030"
+            ]
+        );
+        assert!(!merged.page_info.has_next_page);
+
+        // and stop at the last message of a page with a next page
+        let read = page(&[1005, 1020], true);
+        let window = virtual_window(None, None, None, None, SortOrder::Asc, &read, 5000);
+        let merged = merge_virtual(read, &process, &crons, window, SortOrder::Asc, 3, &[]);
+        assert_eq!(
+            ids(&merged),
+            vec!["1005", "cron-10-milliseconds-1010", "1020"]
+        );
+        assert!(merged.page_info.has_next_page);
+
+        let read = page(&[1015], false);
+        let window = virtual_window(Some(1020), None, None, None, SortOrder::Desc, &read, 5000);
+        let merged = merge_virtual(read, &process, &crons, window, SortOrder::Desc, 10, &[]);
+        assert_eq!(ids(&merged), vec!["1015", "cron-10-milliseconds-1010"]);
+
+        let filters = vec![TagFilter {
+            name: "Action".to_string(),
+            value: "Tick".to_string(),
+        }];
+        let read = page(&[], false);
+        let window = virtual_window(None, None, None, None, SortOrder::Asc, &read, 1030);
+        let merged = merge_virtual(read, &process, &crons, window, SortOrder::Asc, 10, &filters);
+        assert!(merged.edges.is_empty());
+    }
+
+    #[test]
+    fn test_due_slots() {
+        let cron = CronDefinition {
+            process_id: "p".to_string(),
+            interval: "1-second".to_string(),
+            interval_ms: 1000,
+            tags: vec![],
+            next_run: 1000,
+        };
+        assert_eq!(cron.due_slots(999, 10), (vec![], 1000));
+        assert_eq!(cron.due_slots(2500, 10), (vec![1000, 2000], 3000));
+
+        // after downtime only the latest slots are caught up
+        assert_eq!(
+            cron.due_slots(10_000, 3),
+            (vec![8000, 9000, 10_000], 11_000)
+        );
+    }
+}
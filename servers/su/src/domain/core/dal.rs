@@ -1,8 +1,19 @@
 use async_trait::async_trait;
-use serde::Deserialize;
+use bytes::Bytes;
+use serde::{Deserialize, Serialize};
 
-pub use super::json::{JsonErrorType, Message, PaginatedMessages, Process};
-pub use super::router::{ProcessScheduler, Scheduler};
+pub use super::assignments::{Assignment, ProcessSequence};
+pub use super::attestations::Attestation;
+pub use super::checkpoints::Checkpoint;
+pub use super::cron::CronDefinition;
+pub use super::events::{DomainEvent, EventBus};
+pub use super::json::{
+    JsonErrorType, Message, MessageQuery, PaginatedMessages, PaginatedProcesses, Process,
+    SortOrder, TagFilter,
+};
+pub use super::receipts::UploadReceipt;
+pub use super::router::{AssignmentDecision, ProcessScheduler, Scheduler};
+pub use super::tokens::ApiToken;
 
 /*
 Interfaces for core dependencies. Implement these traits
@@ -21,11 +32,27 @@ pub struct TxStatus {
     pub number_of_confirmations: i32,
 }
 
+// status and raw keep the &String of their first callers
+#[allow(clippy::ptr_arg)]
 #[async_trait]
 pub trait Gateway: Send + Sync {
     async fn check_head(&self, tx_id: String) -> Result<bool, String>;
     async fn network_info(&self) -> Result<NetworkInfo, String>;
     async fn status(&self, tx_id: &String) -> Result<TxStatus, String>;
+    // the data of a transaction or data item
+    async fn raw(&self, tx_id: &String) -> Result<Vec<u8>, String>;
+    // the gateway answers right now, not from cached state
+    async fn ping(&self) -> Result<(), String>;
+    // runs a graphql query and returns its data
+    async fn graphql(
+        &self,
+        query: &str,
+        variables: serde_json::Value,
+    ) -> Result<serde_json::Value, String>;
+    // the fee in winston to store a transaction of bytes data
+    async fn price(&self, bytes: usize) -> Result<u64, String>;
+    // the last_tx a new transaction is signed against
+    async fn tx_anchor(&self) -> Result<String, String>;
 }
 
 pub trait Wallet: Send + Sync {
@@ -44,11 +71,12 @@ pub trait Log: Send + Sync {
     fn error(&self, message: String);
 }
 
-pub trait ScheduleProvider {
+pub trait ScheduleProvider: Send + Sync {
     fn epoch(&self) -> String;
     fn nonce(&self) -> String;
     fn timestamp(&self) -> String;
     fn hash_chain(&self) -> String;
+    fn block_height(&self) -> String;
 }
 
 pub trait Config: Send + Sync {
@@ -57,6 +85,154 @@ pub trait Config: Send + Sync {
     fn gateway_url(&self) -> String;
     fn mode(&self) -> String;
     fn scheduler_list_path(&self) -> String;
+    fn ingest_queue_depth(&self) -> usize;
+    fn ingest_workers(&self) -> usize;
+    fn redirect_url_template(&self) -> Option<String>;
+    fn redirect_internal_url_template(&self) -> Option<String>;
+    fn redirect_internal_hosts(&self) -> Vec<String>;
+    fn redirect_port_override(&self) -> Option<u16>;
+    fn redirect_status(&self) -> u16;
+    fn redirect_status_routes(&self) -> Vec<String>;
+    fn redirect_body(&self) -> bool;
+    fn store_failover_grace_ms(&self) -> u64;
+    fn store_failover_queue_size(&self) -> usize;
+    fn write_restricted(&self) -> bool;
+    fn bind_address(&self) -> Option<String>;
+    fn trusted_proxies(&self) -> Vec<String>;
+    fn cache_max_entries(&self) -> usize;
+    fn event_stream_token(&self) -> Option<String>;
+    fn primary_events_url(&self) -> Option<String>;
+    fn rate_limit_per_second(&self) -> f64;
+    fn rate_limit_burst(&self) -> u32;
+    fn schedule_lock_deadline_ms(&self) -> u64;
+    fn schedule_lock_idle_ms(&self) -> u64;
+    fn spawn_allowed_owners(&self) -> Vec<String>;
+    fn spawn_denied_owners(&self) -> Vec<String>;
+    fn spawn_allowed_modules(&self) -> Vec<String>;
+    fn spawn_denied_modules(&self) -> Vec<String>;
+    fn request_deadline_ms(&self) -> u64;
+    fn max_item_size(&self) -> usize;
+    fn max_process_size(&self) -> usize;
+    fn auto_migrate(&self) -> bool;
+    fn max_inflight_writes(&self) -> usize;
+    fn write_queue_depth(&self) -> usize;
+    fn process_queue_depth(&self) -> usize;
+    fn shutdown_timeout_ms(&self) -> u64;
+    fn admin_token(&self) -> Option<String>;
+    fn backup_s3_bucket(&self) -> Option<String>;
+    fn backup_s3_endpoint(&self) -> Option<String>;
+    fn backup_s3_region(&self) -> String;
+    fn backup_s3_prefix(&self) -> String;
+    fn backup_s3_access_key(&self) -> Option<String>;
+    fn backup_s3_secret_key(&self) -> Option<String>;
+    fn backup_interval_ms(&self) -> u64;
+    fn backup_retain(&self) -> usize;
+    fn backup_restore_on_start(&self) -> bool;
+    fn webhook_urls(&self) -> Vec<String>;
+    fn webhook_secret(&self) -> Option<String>;
+    fn webhook_max_attempts(&self) -> u32;
+    fn webhook_timeout_ms(&self) -> u64;
+    fn event_publisher(&self) -> String;
+    fn event_publisher_url(&self) -> Option<String>;
+    fn event_publisher_topic(&self) -> String;
+    fn compress_responses(&self) -> bool;
+    fn accept_compressed_requests(&self) -> bool;
+    fn tag_policy_path(&self) -> Option<String>;
+    fn strict_scheduler_tag(&self) -> bool;
+    fn scheduler_location_owner(&self) -> Option<String>;
+    fn scheduler_location_url(&self) -> Option<String>;
+    fn scheduler_location_ttl_ms(&self) -> u64;
+    fn tenant_wallet_paths(&self) -> Vec<String>;
+    fn upload_verify_sample(&self) -> u64;
+    fn upload_verify_after_ms(&self) -> u64;
+    fn upload_verify_timeout_ms(&self) -> u64;
+    fn upload_repair_after_ms(&self) -> u64;
+    fn attestation_interval_ms(&self) -> u64;
+    fn signed_read_max_age_ms(&self) -> u64;
+    fn sign_reads(&self) -> bool;
+    fn log_level(&self) -> String;
+    fn route_cache_ttl_ms(&self) -> u64;
+    fn route_cache_max_entries(&self) -> usize;
+    fn assignment_strategy(&self) -> String;
+    fn hash_virtual_nodes(&self) -> u32;
+    fn scheduler_admin_token(&self) -> Option<String>;
+    fn rebalance_max_moves(&self) -> usize;
+    fn rebalance_idle_ms(&self) -> u64;
+    fn rebalance_tolerance(&self) -> f64;
+    fn max_import_size(&self) -> usize;
+    fn scheduler_stats_interval_ms(&self) -> u64;
+    fn read_only(&self) -> bool;
+    fn retention_days(&self) -> u64;
+    fn retention_max_messages(&self) -> u64;
+    fn retention_delete_rows(&self) -> bool;
+    fn retention_interval_ms(&self) -> u64;
+    fn cold_storage_after_days(&self) -> u64;
+    fn cold_segment_size(&self) -> u64;
+    fn cold_storage_interval_ms(&self) -> u64;
+    fn http_connect_timeout_ms(&self) -> u64;
+    fn http_request_timeout_ms(&self) -> u64;
+    fn http_pool_idle_timeout_ms(&self) -> u64;
+    fn http_pool_max_idle_per_host(&self) -> usize;
+    fn keep_alive_ms(&self) -> u64;
+    fn gateway_timeout_ms(&self) -> u64;
+    fn gateway_breaker_failures(&self) -> u32;
+    fn gateway_breaker_open_ms(&self) -> u64;
+    fn upload_timeout_ms(&self) -> u64;
+    fn upload_breaker_failures(&self) -> u32;
+    fn upload_breaker_open_ms(&self) -> u64;
+    fn scheduler_timeout_ms(&self) -> u64;
+    fn scheduler_breaker_failures(&self) -> u32;
+    fn scheduler_breaker_open_ms(&self) -> u64;
+    fn tls_cert_path(&self) -> Option<String>;
+    fn tls_key_path(&self) -> Option<String>;
+    fn tls_client_ca_path(&self) -> Option<String>;
+    fn scheduler_tls_ca_path(&self) -> Option<String>;
+    fn scheduler_tls_cert_path(&self) -> Option<String>;
+    fn scheduler_tls_key_path(&self) -> Option<String>;
+    fn cors_allowed_origins(&self) -> Vec<String>;
+    fn cors_allowed_methods(&self) -> Vec<String>;
+    fn cors_allowed_headers(&self) -> Vec<String>;
+    fn cors_max_age_secs(&self) -> u64;
+    fn boot_message_tags(&self) -> Vec<String>;
+    fn cron_mode(&self) -> String;
+    fn cron_interval_ms(&self) -> u64;
+    fn cron_max_catch_up(&self) -> u32;
+    fn module_validation(&self) -> bool;
+    fn module_required_tags(&self) -> Vec<String>;
+    fn module_cache_max_entries(&self) -> usize;
+    fn router_region_header(&self) -> String;
+    fn upload_receipt_wait_ms(&self) -> u64;
+    fn upload_mode(&self) -> String;
+    fn arweave_node_url(&self) -> Option<String>;
+    fn direct_bundle_max_items(&self) -> usize;
+    fn direct_bundle_max_bytes(&self) -> usize;
+    fn direct_bundle_interval_ms(&self) -> u64;
+    fn funding_warn_winston(&self) -> u64;
+    fn funding_stop_winston(&self) -> u64;
+    fn funding_check_interval_ms(&self) -> u64;
+    fn upload_chunked_above_bytes(&self) -> u64;
+    fn upload_chunk_bytes(&self) -> u64;
+    fn store_group_commit_ms(&self) -> u64;
+    fn store_group_commit_max(&self) -> usize;
+    fn min_nonce_wait_ms(&self) -> u64;
+    fn cluster_node_url(&self) -> Option<String>;
+    fn process_lease_ms(&self) -> u64;
+    fn outbox_max_attempts(&self) -> u32;
+    fn cron_min_interval_ms(&self) -> u64;
+    fn direct_queue_max_items(&self) -> usize;
+    fn proxy_protocol(&self) -> bool;
+    fn subscribe_max_per_process(&self) -> usize;
+    fn subscribe_max_total(&self) -> usize;
+    fn min_nonce_max_waiters(&self) -> usize;
+}
+
+/*
+    maps the url stored for a scheduler to the url
+    a client should be redirected to, request_host is
+    the host the client used to reach the router
+*/
+pub trait UrlResolver: Send + Sync {
+    fn resolve(&self, scheduler_url: &str, request_host: &str) -> Result<String, String>;
 }
 
 #[derive(Debug)]
@@ -70,8 +246,110 @@ impl From<UploaderErrorType> for String {
     }
 }
 
+#[async_trait]
 pub trait Uploader: Send + Sync {
-    fn upload(&self, tx: Vec<u8>) -> Result<(), UploaderErrorType>;
+    fn upload(&self, tx: Bytes) -> Result<(), UploaderErrorType>;
+    async fn ping(&self) -> Result<(), UploaderErrorType>;
+    // winston address can still spend on uploads
+    async fn balance(&self, address: &str) -> Result<u64, UploaderErrorType>;
+    // uploads handed off that have not finished yet
+    fn pending_uploads(&self) -> usize;
+    // uploads given up on since the start, kept in undelivered_uploads
+    fn given_up_uploads(&self) -> u64;
+}
+
+/*
+    why a flow failed, the http layer answers with
+    the status code that matches the variant
+*/
+#[derive(Debug, Clone, PartialEq)]
+pub enum FlowError {
+    // the request is malformed or breaks a rule, retrying it will not help
+    Validation(String),
+    NotFound(String),
+    // the item was already written or does not fit what is stored
+    Conflict(String),
+    // the api token or spawn policy does not allow the write
+    Forbidden(String),
+    RateLimited(String),
+    // the su is overloaded, shutting down or past the deadline, try again later
+    Unavailable(String),
+    // the gateway, upload node or signer failed
+    Upstream(String),
+    Internal(String),
+}
+
+impl FlowError {
+    pub fn message(&self) -> &str {
+        match self {
+            FlowError::Validation(m)
+            | FlowError::NotFound(m)
+            | FlowError::Conflict(m)
+            | FlowError::Forbidden(m)
+            | FlowError::RateLimited(m)
+            | FlowError::Unavailable(m)
+            | FlowError::Upstream(m)
+            | FlowError::Internal(m) => m,
+        }
+    }
+}
+
+impl std::fmt::Display for FlowError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.message())
+    }
+}
+
+impl From<FlowError> for String {
+    fn from(error: FlowError) -> Self {
+        error.message().to_string()
+    }
+}
+
+// errors nothing more is known about are the su's own
+impl From<String> for FlowError {
+    fn from(error: String) -> Self {
+        FlowError::Internal(error)
+    }
+}
+
+impl From<StoreErrorType> for FlowError {
+    fn from(error: StoreErrorType) -> Self {
+        match error {
+            StoreErrorType::NotFound(m) => FlowError::NotFound(m),
+            StoreErrorType::MessageExists(m) => FlowError::Conflict(m),
+            StoreErrorType::ConnectionError(m) => FlowError::Unavailable(m),
+            StoreErrorType::LeaseLost(m) => FlowError::Unavailable(m),
+            StoreErrorType::SlotTaken(m) => FlowError::Unavailable(m),
+            e => FlowError::Internal(format!("{:?}", e)),
+        }
+    }
+}
+
+impl From<JsonErrorType> for FlowError {
+    fn from(error: JsonErrorType) -> Self {
+        FlowError::Internal(error.into())
+    }
+}
+
+impl From<UploaderErrorType> for FlowError {
+    fn from(error: UploaderErrorType) -> Self {
+        FlowError::Upstream(error.into())
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub struct StoreStats {
+    pub process_count: i64,
+    pub message_count: i64,
+    pub scheduler_count: i64,
+    pub latest_message_timestamp: Option<i64>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct MessageCount {
+    pub count: i64,
+    pub max_nonce: Option<i32>,
 }
 
 #[derive(Debug)]
@@ -82,33 +360,336 @@ pub enum StoreErrorType {
     EnvVarError(String),
     IntError(String),
     MessageExists(String),
+    // the store could not be reached, the operation may succeed later
+    ConnectionError(String),
+    // another su instance of the cluster holds the lease of the process
+    LeaseLost(String),
+    // the slot was taken by another writer, the schedule has to be read again
+    SlotTaken(String),
+    // a stored bundle no longer matches the checksum it was saved with
+    IntegrityError(BundleRef),
+}
+
+/*
+    locates a stored bundle so a corrupted one
+    can be fetched again and written back
+*/
+#[derive(Debug, Clone)]
+pub enum BundleRef {
+    Process(String),
+    // row_id, older rows may not have an assignment id
+    Message(i32),
+    // a message by the id of its assignment
+    Assignment(String),
+}
+
+/*
+    keeps bundle binaries outside of the database,
+    the store picks the keys and saves them with the row
+*/
+pub trait BlobStore: Send + Sync {
+    fn put(&self, key: &str, data: &[u8]) -> Result<(), String>;
+    fn get(&self, key: &str) -> Result<Vec<u8>, String>;
+    // a key that does not exist is not an error
+    fn delete(&self, key: &str) -> Result<(), String>;
 }
 
+// messages moved from the database to one cold storage segment
+#[derive(Serialize, Debug, Clone)]
+pub struct ColdSegment {
+    pub process_id: String,
+    pub from_nonce: i32,
+    pub to_nonce: i32,
+    pub message_count: i32,
+    // compressed bytes written
+    pub segment_size: i64,
+}
+
+// a message whose bundle retention may prune
+#[derive(Debug, Clone)]
+pub struct PrunableMessage {
+    pub row_id: i32,
+    pub assignment_id: String,
+    // bytes of the bundle in the row, 0 when it was offloaded
+    pub stored_size: i64,
+    pub bundle_location: Option<String>,
+}
+
+#[async_trait]
 pub trait DataStore: Send + Sync {
-    fn save_process(&self, process: &Process, bundle_in: &[u8]) -> Result<String, StoreErrorType>;
-    fn get_process(&self, process_id_in: &str) -> Result<Process, StoreErrorType>;
-    fn save_message(&self, message: &Message, bundle_in: &[u8]) -> Result<String, StoreErrorType>;
-    fn get_messages(
+    async fn save_process(
+        &self,
+        process: &Process,
+        bundle_in: &Bytes,
+    ) -> Result<String, StoreErrorType>;
+    // a process with the boot message its spawn was sequenced as
+    async fn save_process_with_boot(
+        &self,
+        process: &Process,
+        bundle_in: &Bytes,
+        boot: &Message,
+        boot_bundle: &Bytes,
+    ) -> Result<String, StoreErrorType>;
+    async fn get_process(&self, process_id_in: &str) -> Result<Process, StoreErrorType>;
+    // the address of the wallet that signed the spawn of a process
+    async fn get_process_wallet(&self, process_id_in: &str) -> Result<String, StoreErrorType>;
+    async fn get_processes(
+        &self,
+        from: &Option<String>,
+        limit: &Option<i32>,
+        owner: &Option<String>,
+        module: &Option<String>,
+    ) -> Result<PaginatedProcesses, StoreErrorType>;
+    async fn save_message(
+        &self,
+        message: &Message,
+        bundle_in: &Bytes,
+    ) -> Result<String, StoreErrorType>;
+    async fn save_messages(
+        &self,
+        messages_in: &[(Message, Bytes)],
+    ) -> Result<String, StoreErrorType>;
+    // a cron message, moving the next_run of its interval in the same transaction
+    async fn save_cron_message(
+        &self,
+        message: &Message,
+        bundle_in: &Bytes,
+        interval_in: &str,
+        next_run_in: i64,
+    ) -> Result<String, StoreErrorType>;
+    // unverified, only used to repair a bundle
+    async fn get_stored_bundle(&self, bundle_ref: &BundleRef) -> Result<Vec<u8>, StoreErrorType>;
+    async fn restore_bundle(
+        &self,
+        bundle_ref: &BundleRef,
+        bundle_in: &[u8],
+    ) -> Result<String, StoreErrorType>;
+    async fn get_messages(
         &self,
         process_id_in: &str,
+        query: &MessageQuery,
+    ) -> Result<PaginatedMessages, StoreErrorType>;
+    async fn get_messages_by_owner(
+        &self,
+        owner_address: &str,
         from: &Option<String>,
-        to: &Option<String>,
         limit: &Option<i32>,
     ) -> Result<PaginatedMessages, StoreErrorType>;
-    fn get_message(&self, message_id_in: &str) -> Result<Message, StoreErrorType>;
-    fn get_latest_message(&self, process_id_in: &str) -> Result<Option<Message>, StoreErrorType>;
-    fn save_process_scheduler(
+    async fn get_message(&self, message_id_in: &str) -> Result<Message, StoreErrorType>;
+    async fn get_message_by_nonce(
+        &self,
+        process_id_in: &str,
+        epoch_in: &i32,
+        nonce_in: &i32,
+    ) -> Result<Message, StoreErrorType>;
+    async fn get_messages_by_nonce_range(
+        &self,
+        process_id_in: &str,
+        from_nonce_in: i32,
+        to_nonce_in: Option<i32>,
+        limit: i64,
+    ) -> Result<PaginatedMessages, StoreErrorType>;
+    async fn get_latest_message(
+        &self,
+        process_id_in: &str,
+    ) -> Result<Option<Message>, StoreErrorType>;
+    // None for a process without messages
+    async fn get_process_sequence(
+        &self,
+        process_id_in: &str,
+    ) -> Result<Option<ProcessSequence>, StoreErrorType>;
+    /*
+        takes the lease of a process for ttl_ms if it ran out
+        or holder already has it, returns who holds it now
+    */
+    async fn acquire_lease(
+        &self,
+        process_id_in: &str,
+        holder_in: &str,
+        ttl_ms: u64,
+    ) -> Result<String, StoreErrorType>;
+    // the holder of a lease that has not run out
+    async fn get_lease_holder(&self, process_id_in: &str)
+        -> Result<Option<String>, StoreErrorType>;
+    async fn release_leases(&self, holder_in: &str) -> Result<(), StoreErrorType>;
+    // the latest nonce where reads are served from, the replica if there is one
+    async fn get_read_nonce(&self, process_id_in: &str) -> Result<Option<i32>, StoreErrorType>;
+    async fn get_message_count(&self, process_id_in: &str) -> Result<MessageCount, StoreErrorType>;
+    // processes whose last message was sequenced before the timestamp
+    async fn get_idle_processes(
+        &self,
+        before: i64,
+        limit: i64,
+    ) -> Result<Vec<String>, StoreErrorType>;
+    // in schedule order after the (epoch, nonce) cursor, with their bundles
+    async fn get_message_bundles(
+        &self,
+        process_id_in: &str,
+        after: &Option<(i32, i32)>,
+        limit: i64,
+    ) -> Result<Vec<(Message, Vec<u8>)>, StoreErrorType>;
+    async fn save_process_scheduler(
         &self,
         process_scheduler: &ProcessScheduler,
     ) -> Result<String, StoreErrorType>;
-    fn get_process_scheduler(
+    async fn get_process_scheduler(
         &self,
         process_id_in: &str,
     ) -> Result<ProcessScheduler, StoreErrorType>;
-    fn save_scheduler(&self, scheduler: &Scheduler) -> Result<String, StoreErrorType>;
-    fn update_scheduler(&self, scheduler: &Scheduler) -> Result<String, StoreErrorType>;
-    fn get_scheduler(&self, row_id_in: &i32) -> Result<Scheduler, StoreErrorType>;
-    fn get_scheduler_by_url(&self, url_in: &String) -> Result<Scheduler, StoreErrorType>;
-    fn get_all_schedulers(&self) -> Result<Vec<Scheduler>, StoreErrorType>;
-    fn check_existing_message(&self, message: &Message) -> Result<(), StoreErrorType>;
+    // points the process at another scheduler and moves it between their counts
+    async fn move_process_scheduler(
+        &self,
+        process_id_in: &str,
+        from_row_id: &i32,
+        to_row_id: &i32,
+    ) -> Result<String, StoreErrorType>;
+    async fn save_assignment_decision(
+        &self,
+        decision: &AssignmentDecision,
+    ) -> Result<String, StoreErrorType>;
+    // latest first, only those of process_id when set
+    async fn get_assignment_decisions(
+        &self,
+        process_id_in: &Option<String>,
+        limit: i64,
+    ) -> Result<Vec<AssignmentDecision>, StoreErrorType>;
+    async fn save_scheduler(&self, scheduler: &Scheduler) -> Result<String, StoreErrorType>;
+    async fn update_scheduler(&self, scheduler: &Scheduler) -> Result<String, StoreErrorType>;
+    async fn get_scheduler(&self, row_id_in: &i32) -> Result<Scheduler, StoreErrorType>;
+    async fn get_scheduler_by_url(&self, url_in: &str) -> Result<Scheduler, StoreErrorType>;
+    async fn get_all_schedulers(&self) -> Result<Vec<Scheduler>, StoreErrorType>;
+    async fn check_existing_message(&self, message: &Message) -> Result<(), StoreErrorType>;
+    async fn get_store_stats(&self) -> Result<StoreStats, StoreErrorType>;
+    async fn get_storage_size(&self) -> Result<i64, StoreErrorType>;
+    async fn get_tierable_processes(
+        &self,
+        before: i64,
+        min_messages: i64,
+        limit: i64,
+    ) -> Result<Vec<String>, StoreErrorType>;
+    // None when the process has no full segment to tier yet
+    async fn tier_process(
+        &self,
+        process_id_in: &str,
+        before: i64,
+        segment_size: i64,
+    ) -> Result<Option<ColdSegment>, StoreErrorType>;
+    // indexes up to limit segments tiered before the id index, how many
+    async fn index_cold_segments(&self, limit: i64) -> Result<i64, StoreErrorType>;
+    /*
+        messages after row_id older than the timestamp or
+        beyond the newest keep_latest of their process, the
+        latest message of a process is never returned
+    */
+    async fn get_prunable_messages(
+        &self,
+        before: Option<i64>,
+        keep_latest: Option<i64>,
+        after: i32,
+        limit: i64,
+    ) -> Result<Vec<PrunableMessage>, StoreErrorType>;
+    // drops the bundles, or the whole rows, returns blobs that could not be deleted
+    async fn prune_messages(
+        &self,
+        messages: &[PrunableMessage],
+        delete_rows: bool,
+    ) -> Result<Vec<String>, StoreErrorType>;
+    async fn save_api_token(&self, api_token: &ApiToken) -> Result<String, StoreErrorType>;
+    async fn get_api_token(&self, token_hash_in: &str) -> Result<ApiToken, StoreErrorType>;
+    async fn delete_api_token(&self, token_hash_in: &str) -> Result<String, StoreErrorType>;
+    // a connection can be taken from the pool and queried
+    async fn ping(&self) -> Result<(), StoreErrorType>;
+    // only filled while an event publisher is configured
+    async fn get_outbox(&self, limit: i64) -> Result<Vec<OutboxEvent>, StoreErrorType>;
+    async fn delete_outbox(&self, row_ids: &[i32]) -> Result<(), StoreErrorType>;
+    // true when the event reached max_attempts and moved to event_outbox_dead
+    async fn fail_outbox(
+        &self,
+        row_id: i32,
+        max_attempts: u32,
+        error: &str,
+    ) -> Result<bool, StoreErrorType>;
+    // a bundle the uploader gave up on, kept until it is handed over again
+    async fn save_undelivered(
+        &self,
+        item_id: &str,
+        bundle: &[u8],
+        error: &str,
+    ) -> Result<(), StoreErrorType>;
+    async fn get_undelivered(&self, limit: i64) -> Result<Vec<(String, Vec<u8>)>, StoreErrorType>;
+    async fn delete_undelivered(&self, item_id: &str) -> Result<(), StoreErrorType>;
+    async fn save_checkpoint(&self, checkpoint: &Checkpoint) -> Result<String, StoreErrorType>;
+    // latest first, only those at or before the (epoch, nonce) cursor when set
+    async fn get_checkpoints(
+        &self,
+        process_id_in: &str,
+        before: &Option<(i32, i32)>,
+        limit: i64,
+    ) -> Result<Vec<Checkpoint>, StoreErrorType>;
+    // the schedule in order, only the slots after the (epoch, nonce) cursor when set
+    async fn get_assignments(
+        &self,
+        process_id_in: &str,
+        after: &Option<(i32, i32)>,
+        limit: i64,
+    ) -> Result<Vec<Assignment>, StoreErrorType>;
+    // the assignments of a message id or of an assignment id, the original first
+    async fn find_assignments(&self, id_in: &str) -> Result<Vec<Assignment>, StoreErrorType>;
+    async fn save_attestation(&self, attestation: &Attestation) -> Result<String, StoreErrorType>;
+    // a repeated upload of a bundle keeps its first receipt
+    async fn save_upload_receipt(&self, receipt: &UploadReceipt) -> Result<String, StoreErrorType>;
+    async fn get_upload_receipt(&self, bundle_id_in: &str)
+        -> Result<UploadReceipt, StoreErrorType>;
+    // latest first
+    async fn get_attestations(
+        &self,
+        process_id_in: &str,
+        limit: i64,
+    ) -> Result<Vec<Attestation>, StoreErrorType>;
+    // crons whose next_run is at or before now, the most overdue first
+    async fn get_due_crons(
+        &self,
+        now: i64,
+        limit: i64,
+    ) -> Result<Vec<CronDefinition>, StoreErrorType>;
+    // the crons of a process, shortest interval first
+    async fn get_crons(&self, process_id_in: &str) -> Result<Vec<CronDefinition>, StoreErrorType>;
+    async fn update_cron_next_run(
+        &self,
+        process_id_in: &str,
+        interval_in: &str,
+        next_run_in: i64,
+    ) -> Result<(), StoreErrorType>;
+}
+
+/*
+    a sequencing event saved with the row it is about,
+    event is the json of the DomainEvent
+*/
+#[derive(Debug, Clone)]
+pub struct OutboxEvent {
+    pub row_id: i32,
+    pub process_id: String,
+    pub event: String,
+}
+
+/*
+    an external stream such as kafka or nats that outbox
+    events are relayed to. publish only returns Ok once
+    the broker has every event, they are sent again
+    otherwise so consumers have to tolerate duplicates
+*/
+#[async_trait]
+pub trait EventPublisher: Send + Sync {
+    async fn publish(&self, events: &[OutboxEvent]) -> Result<(), String>;
+}
+
+/*
+    deliveries of bus events held in memory, such as the
+    webhook queues. On shutdown they get until the timeout
+    to send what they hold, anything left is lost
+*/
+#[async_trait]
+pub trait EventDelivery: Send + Sync {
+    async fn flush(&self, timeout: std::time::Duration) -> Result<(), String>;
 }
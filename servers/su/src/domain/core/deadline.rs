@@ -0,0 +1,98 @@
+use std::future::Future;
+use std::time::{Duration, Instant};
+
+tokio::task_local! {
+    static DEADLINE: Deadline;
+}
+
+/*
+    How long the client is still waiting for a request.
+    The http layer scopes it to the task serving the
+    request, flows check it between steps and the store
+    and gateway clients bound their own waits by it so
+    no work is done past the point the client gave up.
+*/
+#[derive(Clone, Copy, Debug)]
+pub struct Deadline {
+    expires: Option<Instant>,
+}
+
+impl Deadline {
+    pub fn none() -> Self {
+        Deadline { expires: None }
+    }
+
+    // 0 means no deadline
+    pub fn after_ms(ms: u64) -> Self {
+        match ms {
+            0 => Deadline::none(),
+            _ => Deadline {
+                expires: Some(Instant::now() + Duration::from_millis(ms)),
+            },
+        }
+    }
+
+    // the earlier of the two deadlines
+    pub fn min(self, other: Deadline) -> Self {
+        match (self.expires, other.expires) {
+            (Some(a), Some(b)) => Deadline {
+                expires: Some(a.min(b)),
+            },
+            (Some(_), None) => self,
+            _ => other,
+        }
+    }
+
+    pub fn remaining(&self) -> Option<Duration> {
+        self.expires
+            .map(|e| e.saturating_duration_since(Instant::now()))
+    }
+
+    pub fn check(&self) -> Result<(), String> {
+        match self.remaining() {
+            Some(r) if r.is_zero() => Err("Request deadline exceeded".to_string()),
+            _ => Ok(()),
+        }
+    }
+
+    // the deadline of the request this task is serving
+    pub fn current() -> Self {
+        DEADLINE.try_with(|d| *d).unwrap_or(Deadline::none())
+    }
+
+    pub async fn scope<F: Future>(self, f: F) -> F::Output {
+        DEADLINE.scope(self, f).await
+    }
+
+    // for work moved off the task, like blocking store calls
+    pub fn sync_scope<R>(self, f: impl FnOnce() -> R) -> R {
+        DEADLINE.sync_scope(self, f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_deadline_scope() {
+        assert!(Deadline::current().remaining().is_none());
+
+        let expired = Deadline::after_ms(1).min(Deadline::none());
+        tokio::time::sleep(Duration::from_millis(5)).await;
+        expired
+            .scope(async {
+                assert!(Deadline::current().check().is_err());
+            })
+            .await;
+
+        Deadline::after_ms(60000)
+            .scope(async {
+                assert!(Deadline::current().check().is_ok());
+                assert!(Deadline::current().remaining().is_some());
+            })
+            .await;
+
+        assert!(Deadline::after_ms(0).check().is_ok());
+    }
+}
@@ -0,0 +1,491 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::{broadcast, mpsc, watch};
+use tokio::time::timeout;
+
+use super::dal::{FlowError, Log};
+use super::json::{Message, Process};
+use bundlr_sdk::tags::Tag;
+
+/*
+    Events produced by the su. Flows and clients publish
+    these to the EventBus and anything that needs to react
+    to them (logging, subscriptions, webhooks, metrics etc...)
+    subscribes instead of being called directly.
+*/
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum DomainEvent {
+    MessageSequenced { message: Message },
+    ProcessCreated { process: Process },
+    // the upload node accepted a bundle, id is the bundle id
+    UploadConfirmed { id: String },
+    /*
+        router mode, the stats poll of a scheduler failed
+        after the one before it succeeded
+    */
+    SchedulerUnhealthy { url: String, reason: String },
+    // a process lock was held past its deadline and broken
+    LockForceReleased { process_id: String, held_ms: u64 },
+}
+
+pub struct EventBus {
+    sender: broadcast::Sender<DomainEvent>,
+}
+
+impl EventBus {
+    /*
+        capacity is how many events a slow subscriber
+        can fall behind before it starts missing them
+    */
+    pub fn new(capacity: usize) -> Self {
+        let (sender, _) = broadcast::channel(capacity.max(1));
+        EventBus { sender }
+    }
+
+    pub fn publish(&self, event: DomainEvent) {
+        // an error here only means nobody is subscribed
+        let _ = self.sender.send(event);
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<DomainEvent> {
+        self.sender.subscribe()
+    }
+}
+
+/*
+    subscriber that writes every event to the logger
+*/
+pub fn spawn_log_sink(bus: &EventBus, logger: Arc<dyn Log>) {
+    let mut receiver = bus.subscribe();
+    tokio::spawn(async move {
+        loop {
+            match receiver.recv().await {
+                Ok(DomainEvent::MessageSequenced { message }) => {
+                    logger.log(format!("saved message - {:?}", &message))
+                }
+                Ok(DomainEvent::ProcessCreated { process }) => {
+                    logger.log(format!("saved process - {:?}", &process))
+                }
+                Ok(DomainEvent::UploadConfirmed { id }) => {
+                    logger.log(format!("upload confirmed - {}", id))
+                }
+                Ok(DomainEvent::SchedulerUnhealthy { url, reason }) => {
+                    logger.error(format!("scheduler unhealthy - {} {}", url, reason))
+                }
+                Ok(DomainEvent::LockForceReleased {
+                    process_id,
+                    held_ms,
+                }) => logger.error(format!(
+                    "ALERT forced release of lock on {} held for {}ms",
+                    process_id, held_ms
+                )),
+                Err(RecvError::Lagged(skipped)) => {
+                    logger.error(format!("log sink skipped {} events", skipped))
+                }
+                Err(RecvError::Closed) => break,
+            }
+        }
+    });
+}
+
+/*
+    the events as json lines for an external subscriber
+    such as a standby. the feed ends if the subscriber
+    falls behind so it knows to reconnect and resync
+*/
+pub fn spawn_json_feed(bus: &EventBus, buffer: usize) -> mpsc::Receiver<String> {
+    let mut receiver = bus.subscribe();
+    let (sender, feed) = mpsc::channel(buffer.max(1));
+    tokio::spawn(async move {
+        loop {
+            let line = match receiver.recv().await {
+                Ok(event) => match serde_json::to_string(&event) {
+                    Ok(json) => format!("{}\n", json),
+                    Err(_) => continue,
+                },
+                Err(_) => break,
+            };
+            // the subscriber disconnected
+            if sender.send(line).await.is_err() {
+                break;
+            }
+        }
+    });
+    feed
+}
+
+/*
+    what a process subscriber is told about each
+    message, the full message is read from the su
+*/
+#[derive(Serialize)]
+struct SequencedMessage {
+    id: String,
+    assignment: String,
+    epoch: i32,
+    nonce: i32,
+    timestamp: i64,
+    tags: Vec<Tag>,
+}
+
+impl SequencedMessage {
+    fn from_message(message: &Message) -> Option<Self> {
+        Some(SequencedMessage {
+            id: message.message_id().ok()?,
+            assignment: message.assignment_id().ok()?,
+            epoch: message.epoch().ok()?,
+            nonce: message.nonce().ok()?,
+            timestamp: message.timestamp().ok()?,
+            tags: match &message.message {
+                Some(m) => m.tags.clone(),
+                None => vec![],
+            },
+        })
+    }
+}
+
+// messages a process feed holds for its slowest subscriber
+const FEED_BUFFER: usize = 1024;
+
+// subscribers hear from the su at least this often
+const FEED_KEEPALIVE: Duration = Duration::from_secs(15);
+
+#[derive(Clone)]
+enum FeedFrame {
+    Message(Arc<String>),
+    // the relay fell behind the event bus, every feed ends
+    Lagged(u64),
+}
+
+// counted against a limit until dropped
+struct Slot(Arc<AtomicUsize>);
+
+impl Slot {
+    fn take(count: &Arc<AtomicUsize>, max: usize) -> Option<Slot> {
+        count
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| {
+                (n < max).then_some(n + 1)
+            })
+            .ok()?;
+        Some(Slot(count.clone()))
+    }
+}
+
+impl Drop for Slot {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/*
+    server-sent events for the messages sequenced on a
+    process. One relay reads the event bus and sends each
+    message to the channel of its process, if anyone is
+    subscribed to it, so a subscriber only wakes up for
+    its own process. A process takes at most
+    SUBSCRIBE_MAX_PER_PROCESS subscribers and the su
+    SUBSCRIBE_MAX_TOTAL.
+*/
+pub struct ProcessFeeds {
+    channels: Arc<DashMap<String, broadcast::Sender<FeedFrame>>>,
+    subscribers: Arc<AtomicUsize>,
+    max_per_process: usize,
+    max_total: usize,
+}
+
+impl ProcessFeeds {
+    pub fn new(max_per_process: usize, max_total: usize) -> Self {
+        ProcessFeeds {
+            channels: Arc::new(DashMap::new()),
+            subscribers: Arc::new(AtomicUsize::new(0)),
+            max_per_process: max_per_process.max(1),
+            max_total,
+        }
+    }
+
+    pub fn spawn_relay(&self, bus: &EventBus) {
+        let mut receiver = bus.subscribe();
+        let channels = self.channels.clone();
+        tokio::spawn(async move {
+            loop {
+                let message = match receiver.recv().await {
+                    Ok(DomainEvent::MessageSequenced { message }) => message,
+                    Ok(_) => continue,
+                    // which processes missed a message is not known
+                    Err(RecvError::Lagged(skipped)) => {
+                        for channel in channels.iter() {
+                            let _ = channel.send(FeedFrame::Lagged(skipped));
+                        }
+                        continue;
+                    }
+                    Err(RecvError::Closed) => break,
+                };
+                let channel = match message.process_id() {
+                    Ok(id) => match channels.get(&id) {
+                        Some(channel) => channel.clone(),
+                        None => continue,
+                    },
+                    Err(_) => continue,
+                };
+                if let Some(json) = SequencedMessage::from_message(&message)
+                    .and_then(|m| serde_json::to_string(&m).ok())
+                {
+                    let frame = format!("event: message\ndata: {}\n\n", json);
+                    let _ = channel.send(FeedFrame::Message(Arc::new(frame)));
+                }
+            }
+        });
+    }
+
+    /*
+        a comment is sent when nothing happened for a while
+        so proxies keep the connection open, and a lagged
+        event ends the feed when the subscriber falls behind
+        so it reads the messages it missed
+    */
+    pub fn subscribe(&self, process_id: String) -> Result<mpsc::Receiver<String>, FlowError> {
+        let slot = Slot::take(&self.subscribers, self.max_total).ok_or_else(|| {
+            FlowError::Unavailable(
+                "Su has no room for another subscriber, try again later".to_string(),
+            )
+        })?;
+
+        let mut receiver = {
+            let channel = self
+                .channels
+                .entry(process_id.clone())
+                .or_insert_with(|| broadcast::channel(FEED_BUFFER).0);
+            if channel.receiver_count() >= self.max_per_process {
+                return Err(FlowError::RateLimited(format!(
+                    "Process {} has the most subscribers allowed, try again later",
+                    process_id
+                )));
+            }
+            channel.subscribe()
+        };
+
+        let channels = self.channels.clone();
+        let (sender, feed) = mpsc::channel(FEED_BUFFER);
+        tokio::spawn(async move {
+            let _slot = slot;
+            loop {
+                let frame = match timeout(FEED_KEEPALIVE, receiver.recv()).await {
+                    Ok(Ok(FeedFrame::Message(frame))) => frame.to_string(),
+                    Ok(Ok(FeedFrame::Lagged(skipped))) | Ok(Err(RecvError::Lagged(skipped))) => {
+                        let _ = sender
+                            .send(format!("event: lagged\ndata: {}\n\n", skipped))
+                            .await;
+                        break;
+                    }
+                    Ok(Err(RecvError::Closed)) => break,
+                    Err(_) => ": keepalive\n\n".to_string(),
+                };
+                // the subscriber disconnected
+                if sender.send(frame).await.is_err() {
+                    break;
+                }
+            }
+            drop(receiver);
+            channels.remove_if(&process_id, |_, channel| channel.receiver_count() == 0);
+        });
+        Ok(feed)
+    }
+
+    pub fn subscribers(&self) -> usize {
+        self.subscribers.load(Ordering::SeqCst)
+    }
+}
+
+/*
+    The nonce each process was last sequenced at, for the
+    reads waiting for a min-nonce. One relay reads the
+    event bus and only wakes the reads waiting on the
+    process of each message. At most MIN_NONCE_MAX_WAITERS
+    reads wait at once.
+*/
+pub struct NonceWaiters {
+    nonces: Arc<DashMap<String, watch::Sender<i32>>>,
+    waiters: Arc<AtomicUsize>,
+    max_waiters: usize,
+}
+
+pub struct NonceWatch {
+    process_id: String,
+    receiver: watch::Receiver<i32>,
+    nonces: Arc<DashMap<String, watch::Sender<i32>>>,
+    _slot: Slot,
+}
+
+impl NonceWaiters {
+    pub fn new(max_waiters: usize) -> Self {
+        NonceWaiters {
+            nonces: Arc::new(DashMap::new()),
+            waiters: Arc::new(AtomicUsize::new(0)),
+            max_waiters,
+        }
+    }
+
+    /*
+        a missed event only delays the waiters, they read
+        the store again now and then anyway
+    */
+    pub fn spawn_relay(&self, bus: &EventBus) {
+        let mut receiver = bus.subscribe();
+        let nonces = self.nonces.clone();
+        tokio::spawn(async move {
+            loop {
+                let message = match receiver.recv().await {
+                    Ok(DomainEvent::MessageSequenced { message }) => message,
+                    Ok(_) | Err(RecvError::Lagged(_)) => continue,
+                    Err(RecvError::Closed) => break,
+                };
+                let (process_id, nonce) = match (message.process_id(), message.nonce()) {
+                    (Ok(process_id), Ok(nonce)) => (process_id, nonce),
+                    _ => continue,
+                };
+                if let Some(sender) = nonces.get(&process_id) {
+                    sender.send_if_modified(|last| {
+                        let higher = nonce > *last;
+                        *last = (*last).max(nonce);
+                        higher
+                    });
+                }
+            }
+        });
+    }
+
+    // None when MIN_NONCE_MAX_WAITERS reads already wait
+    pub fn watch(&self, process_id: &str) -> Option<NonceWatch> {
+        let slot = Slot::take(&self.waiters, self.max_waiters)?;
+        let receiver = self
+            .nonces
+            .entry(process_id.to_string())
+            .or_insert_with(|| watch::channel(-1).0)
+            .subscribe();
+        Some(NonceWatch {
+            process_id: process_id.to_string(),
+            receiver,
+            nonces: self.nonces.clone(),
+            _slot: slot,
+        })
+    }
+
+    pub fn waiters(&self) -> usize {
+        self.waiters.load(Ordering::SeqCst)
+    }
+}
+
+impl NonceWatch {
+    // the nonce the process was sequenced at once it moves
+    pub async fn changed(&mut self) -> i32 {
+        if self.receiver.changed().await.is_err() {
+            std::future::pending::<()>().await;
+        }
+        *self.receiver.borrow_and_update()
+    }
+}
+
+impl Drop for NonceWatch {
+    // the receiver of this watch is still counted
+    fn drop(&mut self) {
+        self.nonces
+            .remove_if(&self.process_id, |_, sender| sender.receiver_count() <= 1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::core::json::{AssignmentInner, Owner};
+
+    fn sequenced(process_id: &str, nonce: i32) -> DomainEvent {
+        let tags = [
+            ("Process", process_id.to_string()),
+            ("Message", format!("message-{}", nonce)),
+            ("Epoch", "0".to_string()),
+            ("Nonce", nonce.to_string()),
+            ("Timestamp", "1700000000000".to_string()),
+        ];
+        DomainEvent::MessageSequenced {
+            message: Message {
+                message: None,
+                assignment: AssignmentInner {
+                    id: format!("assignment-{}", nonce),
+                    owner: Owner {
+                        address: String::new(),
+                        key: String::new(),
+                    },
+                    tags: tags.iter().map(|(n, v)| Tag::new(n, v)).collect(),
+                    signature: String::new(),
+                    anchor: None,
+                    target: None,
+                },
+            },
+        }
+    }
+
+    #[tokio::test]
+    async fn test_process_feeds() {
+        let bus = EventBus::new(16);
+        let feeds = ProcessFeeds::new(2, 3);
+        feeds.spawn_relay(&bus);
+
+        let mut first = feeds.subscribe("a".to_string()).unwrap();
+        let second = feeds.subscribe("a".to_string()).unwrap();
+        assert!(matches!(
+            feeds.subscribe("a".to_string()),
+            Err(FlowError::RateLimited(_))
+        ));
+        let mut other = feeds.subscribe("b".to_string()).unwrap();
+        assert!(matches!(
+            feeds.subscribe("c".to_string()),
+            Err(FlowError::Unavailable(_))
+        ));
+        assert_eq!(feeds.subscribers(), 3);
+
+        // each feed only hears from its own process
+        bus.publish(sequenced("b", 1));
+        bus.publish(sequenced("a", 2));
+        let frame = first.recv().await.unwrap();
+        assert!(frame.starts_with("event: message\n"));
+        assert!(frame.contains("\"nonce\":2"));
+        assert!(other.recv().await.unwrap().contains("\"nonce\":1"));
+
+        // a disconnected subscriber frees its slot on the next frame
+        drop(second);
+        bus.publish(sequenced("a", 3));
+        assert!(first.recv().await.unwrap().contains("\"nonce\":3"));
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(feeds.subscribers(), 2);
+        assert!(feeds.subscribe("c".to_string()).is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_nonce_waiters() {
+        let bus = EventBus::new(16);
+        let waiters = NonceWaiters::new(2);
+        waiters.spawn_relay(&bus);
+
+        let mut first = waiters.watch("a").unwrap();
+        let mut other = waiters.watch("b").unwrap();
+        assert!(waiters.watch("c").is_none());
+
+        bus.publish(sequenced("a", 4));
+        assert_eq!(first.changed().await, 4);
+        // only the waiters of the process are woken
+        let woken = timeout(Duration::from_millis(50), other.changed()).await;
+        assert!(woken.is_err());
+
+        drop(first);
+        assert_eq!(waiters.waiters(), 1);
+        assert!(!waiters.nonces.contains_key("a"));
+        assert!(waiters.nonces.contains_key("b"));
+        assert!(waiters.watch("c").is_some());
+    }
+}
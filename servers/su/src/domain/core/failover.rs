@@ -0,0 +1,105 @@
+use std::future::Future;
+use std::time::Duration;
+
+use tokio::sync::Semaphore;
+use tokio::time::{sleep, Instant};
+
+use super::dal::StoreErrorType;
+
+const RETRY_INTERVAL: Duration = Duration::from_millis(100);
+
+/*
+    Smooths over short store failovers. A write that fails
+    because the store is unreachable is held and retried
+    until the grace window runs out. Only queue_size writes
+    can be held at once, beyond that they fail right away
+    so a long outage doesn't pile up requests. A grace
+    window of 0 disables holding writes.
+*/
+pub struct StoreFailover {
+    grace: Duration,
+    queue: Semaphore,
+}
+
+impl StoreFailover {
+    pub fn new(grace_ms: u64, queue_size: usize) -> Self {
+        StoreFailover {
+            grace: Duration::from_millis(grace_ms),
+            queue: Semaphore::new(queue_size),
+        }
+    }
+
+    pub async fn retry<T, F, Fut>(&self, op: F) -> Result<T, StoreErrorType>
+    where
+        F: Fn() -> Fut,
+        Fut: Future<Output = Result<T, StoreErrorType>>,
+    {
+        let mut last_error = match op().await {
+            Err(StoreErrorType::ConnectionError(e)) if !self.grace.is_zero() => e,
+            result => return result,
+        };
+
+        let _permit = match self.queue.try_acquire() {
+            Ok(p) => p,
+            Err(_) => {
+                return Err(StoreErrorType::ConnectionError(format!(
+                    "store unavailable and failover queue is full: {}",
+                    last_error
+                )))
+            }
+        };
+
+        let deadline = Instant::now() + self.grace;
+        while Instant::now() < deadline {
+            sleep(RETRY_INTERVAL).await;
+            match op().await {
+                Err(StoreErrorType::ConnectionError(e)) => last_error = e,
+                result => return result,
+            }
+        }
+
+        Err(StoreErrorType::ConnectionError(format!(
+            "store unavailable after failover grace window: {}",
+            last_error
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[tokio::test]
+    async fn test_retry_until_store_recovers() {
+        let failover = StoreFailover::new(1000, 1);
+        let attempts = AtomicUsize::new(0);
+        let result = failover
+            .retry(|| {
+                let attempt = attempts.fetch_add(1, Ordering::SeqCst);
+                async move {
+                    match attempt {
+                        0 | 1 => Err(StoreErrorType::ConnectionError("down".to_string())),
+                        _ => Ok("saved".to_string()),
+                    }
+                }
+            })
+            .await;
+        assert_eq!(result.unwrap(), "saved");
+        assert_eq!(attempts.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn test_no_retry_for_other_errors() {
+        let failover = StoreFailover::new(1000, 1);
+        let attempts = AtomicUsize::new(0);
+        let result: Result<(), StoreErrorType> = failover
+            .retry(|| {
+                attempts.fetch_add(1, Ordering::SeqCst);
+                async { Err(StoreErrorType::MessageExists("exists".to_string())) }
+            })
+            .await;
+        assert!(matches!(result, Err(StoreErrorType::MessageExists(_))));
+        assert_eq!(attempts.load(Ordering::SeqCst), 1);
+    }
+}
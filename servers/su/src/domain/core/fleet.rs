@@ -0,0 +1,228 @@
+use std::collections::BTreeMap;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+use tokio::time::sleep;
+
+use super::dal::DomainEvent;
+use super::flows::Deps;
+
+fn unix_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+/*
+    What a su reports about itself on GET /admin/stats.
+    The lag is work accepted but not finished yet, writes
+    waiting to be sequenced and sequenced items the upload
+    node has not accepted.
+*/
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SchedulerStats {
+    pub messages_last_minute: u64,
+    pub storage_bytes: i64,
+    pub writes_in_flight: usize,
+    pub writes_waiting: usize,
+    pub pending_uploads: usize,
+}
+
+pub async fn scheduler_stats(deps: &Deps) -> Result<SchedulerStats, String> {
+    let storage_bytes = deps
+        .data_store
+        .get_storage_size()
+        .await
+        .map_err(|e| format!("{:?}", e))?;
+    let admission = deps.admission.stats();
+    Ok(SchedulerStats {
+        messages_last_minute: deps.throughput.last_minute(),
+        storage_bytes,
+        writes_in_flight: admission.writes_in_flight,
+        writes_waiting: admission.writes_waiting,
+        pending_uploads: deps.uploader.pending_uploads(),
+    })
+}
+
+// the outcome of the latest poll of one su
+#[derive(Serialize, Debug, Clone)]
+pub struct StatsReport {
+    pub polled_at: u64,
+    pub stats: Option<SchedulerStats>,
+    pub error: Option<String>,
+}
+
+/*
+    Router mode, the latest stats of every scheduler. The
+    router polls them every SCHEDULER_STATS_INTERVAL_MS,
+    a scheduler whose last poll failed is left out when
+    rebalancing.
+*/
+pub struct FleetStats {
+    reports: RwLock<BTreeMap<String, StatsReport>>,
+}
+
+impl Default for FleetStats {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FleetStats {
+    pub fn new() -> Self {
+        FleetStats {
+            reports: RwLock::new(BTreeMap::new()),
+        }
+    }
+
+    pub fn report(&self, url: &str) -> Option<StatsReport> {
+        match self.reports.read() {
+            Ok(reports) => reports.get(url).cloned(),
+            Err(_) => None,
+        }
+    }
+
+    // a scheduler not polled yet counts as reachable
+    pub fn reachable(&self, url: &str) -> bool {
+        self.report(url).map_or(true, |r| r.error.is_none())
+    }
+
+    // true when the scheduler was reachable until this poll failed
+    fn put(&self, url: &str, result: Result<SchedulerStats, String>) -> bool {
+        let failed = result.is_err() && self.reachable(url);
+        let report = match result {
+            Ok(stats) => StatsReport {
+                polled_at: unix_ms(),
+                stats: Some(stats),
+                error: None,
+            },
+            Err(e) => StatsReport {
+                polled_at: unix_ms(),
+                stats: None,
+                error: Some(e),
+            },
+        };
+        if let Ok(mut reports) = self.reports.write() {
+            reports.insert(url.to_string(), report);
+        }
+        failed
+    }
+
+    // schedulers no longer registered stop being reported
+    fn retain(&self, urls: &[String]) {
+        if let Ok(mut reports) = self.reports.write() {
+            reports.retain(|url, _| urls.contains(url));
+        }
+    }
+}
+
+async fn poll_scheduler(
+    deps: &Arc<Deps>,
+    url: &str,
+    token: &Option<String>,
+) -> Result<SchedulerStats, String> {
+    let breaker = deps.breakers.scheduler(url);
+    breaker.allow()?;
+    let mut request = deps
+        .scheduler_http
+        .get(format!("{}/admin/stats", url))
+        .timeout(Duration::from_millis(deps.config.scheduler_timeout_ms()));
+    if let Some(token) = token {
+        request = request.bearer_auth(token);
+    }
+    let response = request.send().await;
+    breaker.record(match &response {
+        Ok(r) => !r.status().is_server_error(),
+        Err(_) => false,
+    });
+    response
+        .and_then(|r| r.error_for_status())
+        .map_err(|e| format!("{}", e))?
+        .json()
+        .await
+        .map_err(|e| format!("{}", e))
+}
+
+pub async fn poll_schedulers(deps: &Arc<Deps>) -> Result<(), String> {
+    let schedulers = deps.data_store.get_all_schedulers().await?;
+    let token = deps.config.scheduler_admin_token();
+    for scheduler in schedulers.iter() {
+        let result = poll_scheduler(deps, &scheduler.url, &token).await;
+        let reason = match &result {
+            Ok(_) => None,
+            Err(e) => Some(e.clone()),
+        };
+        if deps.fleet.put(&scheduler.url, result) {
+            deps.events.publish(DomainEvent::SchedulerUnhealthy {
+                url: scheduler.url.clone(),
+                reason: reason.unwrap_or_default(),
+            });
+        } else if let Some(e) = reason {
+            deps.logger.error(format!(
+                "stats poll of scheduler {} failed - {}",
+                scheduler.url, e
+            ));
+        }
+    }
+    let urls: Vec<String> = schedulers.into_iter().map(|s| s.url).collect();
+    deps.fleet.retain(&urls);
+    Ok(())
+}
+
+pub fn spawn_stats_polling(deps: Arc<Deps>) {
+    let interval = Duration::from_millis(deps.config.scheduler_stats_interval_ms());
+    tokio::spawn(async move {
+        loop {
+            if let Err(e) = poll_schedulers(&deps).await {
+                deps.logger
+                    .error(format!("scheduler stats poll failed - {}", e));
+            }
+            sleep(interval).await;
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stats() -> SchedulerStats {
+        SchedulerStats {
+            messages_last_minute: 12,
+            storage_bytes: 4096,
+            writes_in_flight: 1,
+            writes_waiting: 0,
+            pending_uploads: 3,
+        }
+    }
+
+    #[test]
+    fn test_fleet_stats() {
+        let fleet = FleetStats::new();
+        assert!(fleet.reachable("https://su-1"));
+
+        assert!(!fleet.put("https://su-1", Ok(stats())));
+        assert!(fleet.put("https://su-2", Err("connection refused".to_string())));
+        assert!(fleet.reachable("https://su-1"));
+        assert!(!fleet.reachable("https://su-2"));
+        // only the poll that makes it unreachable reports it
+        assert!(!fleet.put("https://su-2", Err("connection refused".to_string())));
+        assert!(!fleet.put("https://su-2", Ok(stats())));
+        assert!(fleet.put("https://su-2", Err("timed out".to_string())));
+        assert_eq!(
+            fleet
+                .report("https://su-1")
+                .unwrap()
+                .stats
+                .unwrap()
+                .pending_uploads,
+            3
+        );
+
+        fleet.retain(&["https://su-1".to_string()]);
+        assert!(fleet.report("https://su-2").is_none());
+        assert!(fleet.reachable("https://su-2"));
+    }
+}
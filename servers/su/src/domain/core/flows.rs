@@ -1,14 +1,59 @@
+use std::collections::btree_map::Entry;
+use std::collections::{BTreeMap, HashMap};
 use std::sync::Arc;
-use std::time::{SystemTime, SystemTimeError, UNIX_EPOCH};
+use std::time::{Duration, Instant, SystemTime, SystemTimeError, UNIX_EPOCH};
 
+use bundlr_sdk::tags::Tag;
+use bytes::Bytes;
 use dotenv::dotenv;
+use reqwest::Client;
+use ring::constant_time::verify_slices_are_equal;
 use serde_json::json;
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio::sync::mpsc;
 
+use super::access::{self, ReadAccess};
+use super::admission::{WriteAdmission, WritePermit};
+use super::body::Body;
+use super::breaker::Breakers;
 use super::builder::Builder;
-use super::json::{Message, Process};
+use super::bulk::{BulkReadRequest, RangeRead};
+use super::bytes::DataItem;
+use super::cache::ReadCache;
+use super::checkpoints::CheckpointRequest;
+use super::confirmations::UploadConfirmations;
+use super::cron::{self, CronDefinition, Crons};
+use super::deadline::Deadline;
+use super::events::{self, NonceWaiters, ProcessFeeds};
+use super::failover::StoreFailover;
+use super::fleet::FleetStats;
+use super::funding::Funding;
+use super::inflight::{self, InFlightWrites, Joined, WriteLeader};
+use super::ingest;
+use super::json::{Message, MessageQuery, Process, SortOrder, TagFilter, WriteResult};
+use super::modules::Modules;
+use super::outbox::OutboxRelay;
+use super::policy::{SpawnPolicy, TagPolicy};
+use super::proxies::TrustedProxies;
+use super::ratelimit::RateLimiter;
+use super::receipts::{self, ReadReceipt, UploadReceipt};
+use super::resolver::RedirectPolicy;
+use super::retention::Retention;
+use super::routes::RouteCache;
+use super::runtime::Runtime;
 use super::scheduler;
+use super::sequencer::ProcessQueues;
+use super::tenants::{scheduler_tag, Tenants};
+use super::throughput::Throughput;
+use super::tiering::Tiering;
+use super::tokens;
+use super::validation::ValidationReport;
+use super::version::ApiVersion;
 
-use super::dal::{Config, DataStore, Gateway, Log, Signer, Uploader, Wallet};
+use super::dal::{
+    BundleRef, Checkpoint, Config, DataStore, DomainEvent, EventBus, EventDelivery, FlowError,
+    Gateway, Log, PaginatedMessages, Signer, StoreErrorType, Uploader, UrlResolver, Wallet,
+};
 
 pub struct Deps {
     pub data_store: Arc<dyn DataStore>,
@@ -18,6 +63,67 @@ pub struct Deps {
     pub signer: Arc<dyn Signer>,
     pub wallet: Arc<dyn Wallet>,
     pub uploader: Arc<dyn Uploader>,
+    pub events: Arc<EventBus>,
+
+    // the server-sent event feeds of processes
+    pub feeds: Arc<ProcessFeeds>,
+
+    // reads waiting for a process to reach a min-nonce
+    pub nonce_waiters: Arc<NonceWaiters>,
+    pub url_resolver: Arc<dyn UrlResolver>,
+    pub redirects: Arc<RedirectPolicy>,
+    pub failover: Arc<StoreFailover>,
+    pub proxies: Arc<TrustedProxies>,
+    pub cache: Arc<ReadCache>,
+    pub rate_limiter: Arc<RateLimiter>,
+
+    // pooled client for outgoing requests, connections are reused
+    pub http: Client,
+
+    // router mode, the client schedulers are called with, with their tls settings
+    pub scheduler_http: Client,
+
+    // circuit breakers of the gateway, upload node and schedulers
+    pub breakers: Arc<Breakers>,
+
+    // settings an operator can change without a restart
+    pub runtime: Arc<Runtime>,
+
+    // router mode, where each process was last routed
+    pub route_cache: Arc<RouteCache>,
+
+    // router mode, the stats each scheduler last reported
+    pub fleet: Arc<FleetStats>,
+
+    // messages sequenced over the last minute
+    pub throughput: Arc<Throughput>,
+
+    // operator tag rules checked on every write
+    pub tag_policy: Arc<TagPolicy>,
+    pub modules: Arc<Modules>,
+    pub in_flight: Arc<InFlightWrites>,
+
+    // the scheduler identities hosted by this su
+    pub tenants: Arc<Tenants>,
+
+    // sampled uploads looked up on the gateway
+    pub confirmations: Arc<UploadConfirmations>,
+
+    // what retention pruned so far
+    pub retention: Arc<Retention>,
+
+    // what was moved to cold storage so far
+    pub tiering: Arc<Tiering>,
+    pub crons: Arc<Crons>,
+
+    // the last check of the balance paying for uploads
+    pub funding: Arc<Funding>,
+
+    // relays the event outbox while an event publisher is configured
+    pub outbox: Option<Arc<OutboxRelay>>,
+
+    // the webhook queues while WEBHOOK_URLS is set
+    pub webhooks: Option<Arc<dyn EventDelivery>>,
 
     /*
         scheduler is part of the core but we initialize
@@ -26,69 +132,670 @@ pub struct Deps {
         dependencies injected.
     */
     pub scheduler: Arc<scheduler::ProcessScheduler>,
+
+    // bounded pool that parses incoming data items
+    pub ingest: Arc<ingest::IngestPool>,
+
+    // bounds the writes building and sequencing at once
+    pub admission: Arc<WriteAdmission>,
+
+    // ordered write queue per process
+    pub queues: Arc<ProcessQueues>,
 }
 
 /*
     flows.rs is the main business logic of the su
 */
 
-pub fn init_builder(deps: &Arc<Deps>) -> Result<Builder, String> {
+pub fn init_builder(deps: &Arc<Deps>) -> Result<Builder<'_>, String> {
     dotenv().ok();
     let builder = Builder::new(deps.gateway.clone(), deps.signer.clone(), &deps.logger)?;
-    return Ok(builder);
+    Ok(builder)
 }
 
-async fn upload(deps: &Arc<Deps>, build_result: Vec<u8>) -> Result<String, String> {
-    let uploaded_tx = &deps.uploader.upload(build_result)?;
-    let result = match serde_json::to_string(&uploaded_tx) {
-        Ok(r) => r,
-        Err(e) => return Err(format!("{:?}", e)),
+// signs with the wallet of the scheduler a process names
+pub fn init_builder_for<'a>(deps: &'a Arc<Deps>, scheduler: &str) -> Result<Builder<'a>, String> {
+    dotenv().ok();
+    let signer = deps.tenants.signer_for(scheduler);
+    let builder = Builder::new(deps.gateway.clone(), signer, &deps.logger)?;
+    Ok(builder)
+}
+
+// the wallet that signed the spawn of a process
+async fn process_wallet(deps: &Arc<Deps>, process_id: &str) -> Result<String, FlowError> {
+    if let Some(wallet) = deps.tenants.cached_wallet(process_id) {
+        return Ok(wallet);
+    }
+    let wallet = deps.data_store.get_process_wallet(process_id).await?;
+    deps.tenants.cache_wallet(process_id, &wallet);
+    Ok(wallet)
+}
+
+/*
+    the builder for writes to an existing process, signing
+    with the wallet its spawn was signed with. A process
+    whose wallet is gone from this su is not written to
+*/
+pub async fn process_builder<'a>(
+    deps: &'a Arc<Deps>,
+    process_id: &str,
+) -> Result<Builder<'a>, FlowError> {
+    let wallet = process_wallet(deps, process_id).await?;
+    let signer = deps
+        .tenants
+        .process_signer(&wallet, process_id)
+        .map_err(FlowError::Unavailable)?;
+    Ok(Builder::new(deps.gateway.clone(), signer, &deps.logger)?)
+}
+
+// attempts of an upload the uploader refused after its item was saved
+const UPLOAD_RETRIES: u32 = 10;
+
+/*
+    hands a saved bundle to the uploader and returns the
+    id it is uploaded under. The item is sequenced by the
+    time this runs, so an upload the uploader refuses is
+    retried in the background instead of failing the write
+*/
+async fn upload(
+    deps: &Arc<Deps>,
+    build_result: Bytes,
+    bundle_ref: BundleRef,
+) -> Result<String, FlowError> {
+    let (bundle_item, _) = DataItem::from_info_bytes(&build_result)
+        .map_err(|e| FlowError::Internal(format!("{:?}", e)))?;
+    let id = bundle_item.id();
+    deps.confirmations
+        .record(id.clone(), bundle_ref, Instant::now());
+    if let Err(e) = deps.uploader.upload(build_result.clone()) {
+        deps.logger.error(format!(
+            "upload of {} refused, retrying in the background - {:?}",
+            id, e
+        ));
+        retry_upload(deps.clone(), id.clone(), build_result);
+    }
+    Ok(id)
+}
+
+fn retry_upload(deps: Arc<Deps>, id: String, build_result: Bytes) {
+    tokio::spawn(async move {
+        for attempt in 1..=UPLOAD_RETRIES {
+            tokio::time::sleep(Duration::from_secs(1 << attempt.min(6))).await;
+            match deps.uploader.upload(build_result.clone()) {
+                Ok(()) => return,
+                Err(e) if attempt == UPLOAD_RETRIES => {
+                    let error = format!(
+                        "upload of {} given up after {} attempts - {:?}",
+                        id, attempt, e
+                    );
+                    deps.logger.error(error.clone());
+                    // handed over again by the redelivery
+                    let saved = deps
+                        .data_store
+                        .save_undelivered(&id, &build_result, &error)
+                        .await;
+                    if let Err(e) = saved {
+                        deps.logger
+                            .error(format!("upload of {} not saved - {:?}", id, e));
+                    }
+                }
+                Err(_) => (),
+            }
+        }
+    });
+}
+
+/*
+    With UPLOAD_RECEIPT_WAIT_MS a version 2 write waits up
+    to that long for the upload node to answer for each of
+    its bundles and adds the receipts to the result. It
+    runs once the write left the process queue so the next
+    write of the process is not held up.
+*/
+async fn attach_receipts(
+    deps: &Arc<Deps>,
+    result: String,
+    version: ApiVersion,
+) -> Result<String, FlowError> {
+    let wait = Duration::from_millis(deps.config.upload_receipt_wait_ms());
+    if version != ApiVersion::V2 || wait.is_zero() {
+        return Ok(result);
+    }
+    let mut json: serde_json::Value =
+        serde_json::from_str(&result).map_err(|e| FlowError::Internal(format!("{}", e)))?;
+    let until = Instant::now() + wait;
+    let mut confirmed = deps.events.subscribe();
+    if let Some(receipt) = wait_receipt(deps, &json["bundle"], &mut confirmed, until).await {
+        json["receipt"] = json!(receipt);
+    }
+    if let Some(items) = json["items"].as_array_mut() {
+        for item in items.iter_mut() {
+            let receipt = wait_receipt(deps, &item["bundle"], &mut confirmed, until).await;
+            if let Some(receipt) = receipt {
+                item["receipt"] = json!(receipt);
+            }
+        }
+    }
+    Ok(json.to_string())
+}
+
+// the uploader saves a receipt before it announces the upload
+async fn wait_receipt(
+    deps: &Arc<Deps>,
+    bundle: &serde_json::Value,
+    confirmed: &mut broadcast::Receiver<DomainEvent>,
+    until: Instant,
+) -> Option<UploadReceipt> {
+    let bundle = bundle.as_str()?;
+    loop {
+        if let Ok(receipt) = deps.data_store.get_upload_receipt(bundle).await {
+            return Some(receipt);
+        }
+        loop {
+            let left = until.saturating_duration_since(Instant::now());
+            match tokio::time::timeout(left, confirmed.recv()).await {
+                Ok(Ok(DomainEvent::UploadConfirmed { id })) if id == bundle => break,
+                Ok(Ok(_)) => continue,
+                Ok(Err(RecvError::Lagged(_))) => break,
+                Ok(Err(RecvError::Closed)) | Err(_) => return None,
+            }
+        }
+    }
+}
+
+/*
+    Writes are limited per owner address before anything
+    is built or uploaded so one wallet spamming the su
+    cannot starve everyone else of the sequencer. The
+    item's signature is verified first, an unsigned
+    owner would let anyone spend another wallet's limit.
+*/
+fn check_rate_limit(deps: &Arc<Deps>, data_item: &DataItem) -> Result<(), FlowError> {
+    let owner = data_item.owner_address();
+    match deps.rate_limiter.check(&owner) {
+        Ok(()) => Ok(()),
+        Err(retry_after) => {
+            deps.logger.log(format!("rate limited owner - {}", owner));
+            Err(FlowError::RateLimited(format!(
+                "Rate limit exceeded for {}, retry in {}ms",
+                owner,
+                retry_after.as_millis().max(1)
+            )))
+        }
+    }
+}
+
+/*
+    On a restricted su every write needs an api token
+    scoped to the owner or id of the target process. A
+    spawn passes its own owner since it is not stored yet.
+*/
+async fn authorize_write(
+    deps: &Arc<Deps>,
+    api_token: &Option<String>,
+    process_id: &str,
+    spawn_owner: Option<String>,
+) -> Result<(), FlowError> {
+    if !deps.config.write_restricted() {
+        return Ok(());
+    }
+
+    let process_owner = match spawn_owner {
+        Some(owner) => owner,
+        None => match deps.data_store.get_process(process_id).await {
+            Ok(process) => process.owner.address,
+            Err(StoreErrorType::NotFound(_)) => {
+                return Err(FlowError::Forbidden(
+                    "Api token is not allowed to write to this process".to_string(),
+                ))
+            }
+            Err(e) => return Err(e.into()),
+        },
     };
-    Ok(result)
+
+    tokens::authorize(&*deps.data_store, api_token, process_id, &process_owner).await
+}
+
+/*
+    Reads of a private process need a signed read from its
+    owner or one of its Reader addresses, see access.rs
+*/
+fn authorize_read(
+    deps: &Arc<Deps>,
+    process: &Process,
+    signed_read: &Option<String>,
+) -> Result<(), FlowError> {
+    let access = match ReadAccess::from_process(process) {
+        Some(access) => access,
+        None => return Ok(()),
+    };
+    let signed_read = signed_read.as_ref().ok_or_else(|| {
+        FlowError::Forbidden("Process is private, reads need an X-Signed-Read header".to_string())
+    })?;
+    let now = system_time_u64().map_err(|e| FlowError::Internal(format!("{:?}", e)))?;
+    let reader = access::verify_signed_read(
+        signed_read,
+        &process.process_id,
+        now as i64,
+        deps.config.signed_read_max_age_ms() as i64,
+    )
+    .map_err(FlowError::Forbidden)?;
+    if !access.allows(&reader) {
+        return Err(FlowError::Forbidden(format!(
+            "{} is not a reader of this process",
+            reader
+        )));
+    }
+    Ok(())
+}
+
+async fn cached_process(deps: &Arc<Deps>, process_id: &str) -> Result<Process, FlowError> {
+    match deps.cache.get_process(process_id) {
+        Some(process) => Ok(process),
+        None => Ok(deps.data_store.get_process(process_id).await?),
+    }
+}
+
+async fn authorize_process_read(
+    deps: &Arc<Deps>,
+    process_id: &str,
+    signed_read: &Option<String>,
+) -> Result<(), FlowError> {
+    authorize_read(deps, &cached_process(deps, process_id).await?, signed_read)
+}
+
+/*
+    for a route to check before it waits for a nonce or
+    answers from an etag, either would tell the caller
+    the latest slot of a private process. An id that is
+    not a process is left to the read itself
+*/
+pub async fn authorize_route_read(
+    deps: &Arc<Deps>,
+    id: &str,
+    signed_read: &Option<String>,
+) -> Result<(), FlowError> {
+    match authorize_process_read(deps, id, signed_read).await {
+        Err(FlowError::NotFound(_)) => Ok(()),
+        result => result,
+    }
+}
+
+/*
+    In strict mode a process can only be spawned on the
+    su its Scheduler tag names, either a wallet this su
+    hosts or the owner of the Scheduler-Location record
+    that points here, so a CU never finds a process on an
+    su it was not told to read from.
+*/
+fn check_scheduler_tag(deps: &Arc<Deps>, scheduler: &str) -> Result<(), FlowError> {
+    if !deps.config.strict_scheduler_tag() {
+        return Ok(());
+    }
+
+    let location_owner = deps.config.scheduler_location_owner();
+    if deps.tenants.hosts(scheduler) || location_owner.as_deref() == Some(scheduler) {
+        return Ok(());
+    }
+    let mut expected = deps.tenants.addresses();
+    expected.extend(location_owner);
+    Err(FlowError::Validation(format!(
+        "Scheduler tag {} does not name this su, expected one of {}",
+        scheduler,
+        expected.join(", ")
+    )))
+}
+
+// the base-layer and exclude params of an assignment
+pub struct AssignParams {
+    pub base_layer: Option<String>,
+    pub exclude: Option<String>,
 }
 
 async fn assignment_only(
     deps: Arc<Deps>,
     process_id: String,
     assign: String,
-    base_layer: Option<String>,
-    exclude: Option<String>,
-) -> Result<String, String> {
-    let builder = init_builder(&deps)?;
+    params: AssignParams,
+    api_token: Option<String>,
+    version: ApiVersion,
+    permit: WritePermit,
+) -> Result<String, FlowError> {
+    authorize_write(&deps, &api_token, &process_id, None).await?;
+
+    let job = sequence_assignment(deps.clone(), process_id.clone(), assign, params, version);
+    // the queue takes a slot again when the assignment's turn comes
+    drop(permit);
+    deps.queues.submit(&process_id, Box::pin(job)).await
+}
+
+async fn sequence_assignment(
+    deps: Arc<Deps>,
+    process_id: String,
+    assign: String,
+    params: AssignParams,
+    version: ApiVersion,
+) -> Result<String, FlowError> {
+    let builder = process_builder(&deps, &process_id).await?;
 
-    let locked_schedule_info = deps.scheduler.acquire_lock(process_id.clone()).await?;
-    let mut schedule_info = locked_schedule_info.lock().await;
+    let mut schedule_info = deps.scheduler.lock(process_id.clone()).await?;
+    Deadline::current()
+        .check()
+        .map_err(FlowError::Unavailable)?;
     let updated_info = deps
         .scheduler
-        .update_schedule_info(&mut *schedule_info, process_id.clone())
+        .update_schedule_info(&mut schedule_info, process_id.clone())
         .await?;
 
-    let process = deps.data_store.get_process(&process_id)?;
+    let process = deps.data_store.get_process(&process_id).await?;
     let build_result = builder
         .build_assignment(
             assign.clone(),
             &process,
             &*updated_info,
-            &base_layer,
-            &exclude,
+            &params.base_layer,
+            &params.exclude,
         )
         .await?;
 
     let message = Message::from_bundle(&build_result.bundle)?;
-    deps.data_store
-        .save_message(&message, &build_result.binary)?;
-    deps.logger.log(format!("saved message - {:?}", &message));
-    upload(&deps, build_result.binary.to_vec()).await?;
+    schedule_info.check_held().map_err(FlowError::Unavailable)?;
+    deps.failover
+        .retry(|| deps.data_store.save_message(&message, &build_result.binary))
+        .await?;
+    schedule_info.commit(&message.assignment_id()?)?;
+    deps.events.publish(DomainEvent::MessageSequenced {
+        message: message.clone(),
+    });
+    let bundle = upload(
+        &deps,
+        build_result.binary.clone(),
+        BundleRef::Assignment(message.assignment_id()?),
+    )
+    .await?;
     drop(schedule_info);
 
-    match system_time_u64() {
-        Ok(timestamp) => {
-            let response_json =
-                json!({ "timestamp": timestamp, "id": message.assignment.id.clone() });
-            Ok(response_json.to_string())
+    // an assignment is identified by its own id rather than the message
+    let result = WriteResult {
+        id: message.assignment_id()?,
+        ..WriteResult::from_message(&message)?
+    };
+    Ok(result.with_bundle(bundle).to_json(version)?)
+}
+
+// leads the write of an item once no other request is writing it
+async fn lead_write(deps: &Arc<Deps>, id: &str) -> WriteLeader {
+    loop {
+        match deps.in_flight.join(id) {
+            Joined::Leader(leader) => return leader,
+            Joined::Follower(follower) => {
+                inflight::wait(follower).await;
+            }
+        }
+    }
+}
+
+/*
+    the stored message of the data item, only a message
+    that contains the actual data item counts, an
+    assignment of this id does not
+*/
+async fn sequenced_message(deps: &Arc<Deps>, id: &str) -> Result<Option<Message>, FlowError> {
+    match deps.data_store.get_message(id).await {
+        Ok(message) if message.message.is_some() => Ok(Some(message)),
+        Ok(_) | Err(StoreErrorType::NotFound(_)) => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/*
+    The failover retries a save whose connection dropped,
+    when the store had committed it before the retry finds
+    the items already there. Ok when the stored items are
+    the ones built here, the write went through and is
+    carried on, otherwise the error of the save
+*/
+async fn saved_before(
+    deps: &Arc<Deps>,
+    built: &[&Message],
+    error: StoreErrorType,
+) -> Result<(), FlowError> {
+    if !matches!(error, StoreErrorType::MessageExists(_)) {
+        return Err(error.into());
+    }
+    for message in built.iter() {
+        let stored = sequenced_message(deps, &message.message_id()?).await?;
+        let assignment_id = message.assignment_id().ok();
+        if stored.map_or(true, |s| s.assignment_id().ok() != assignment_id) {
+            return Err(error.into());
+        }
+    }
+    Ok(())
+}
+
+/*
+    If a client retries a write after a timeout the data
+    item may already be sequenced. Look it up by id and
+    return the original result instead of assigning it
+    a second nonce.
+*/
+async fn existing_write_result(
+    deps: &Arc<Deps>,
+    id: &str,
+    version: ApiVersion,
+) -> Result<Option<String>, FlowError> {
+    match deps.data_store.get_process(id).await {
+        Ok(process) => return Ok(Some(WriteResult::from_process(&process).to_json(version)?)),
+        Err(StoreErrorType::NotFound(_)) => (),
+        Err(e) => return Err(e.into()),
+    }
+
+    match sequenced_message(deps, id).await? {
+        Some(message) => Ok(Some(WriteResult::from_message(&message)?.to_json(version)?)),
+        None => Ok(None),
+    }
+}
+
+/*
+    A bundle carries nested messages, possibly for several
+    processes. They are all validated up front, sequenced
+    under the locks of every target process and saved in
+    one transaction, so either all are sequenced or none.
+    Items sequenced before, by a retry of the bundle or
+    on their own, are answered from the store.
+*/
+async fn write_bundle(
+    deps: Arc<Deps>,
+    bundle_item: DataItem,
+    api_token: Option<String>,
+    version: ApiVersion,
+) -> Result<String, FlowError> {
+    let builder = init_builder(&deps)?;
+    let items = builder.parse_bundle(&bundle_item).await?;
+
+    // group by process keeping the order within the bundle
+    let mut by_process: BTreeMap<String, Vec<DataItem>> = BTreeMap::new();
+    for item in items.into_iter() {
+        by_process.entry(item.target()).or_default().push(item);
+    }
+
+    for process_id in by_process.keys() {
+        authorize_write(&deps, &api_token, process_id, None).await?;
+    }
+    for item in by_process.values().flatten() {
+        deps.tag_policy
+            .check(item.tags())
+            .map_err(FlowError::Validation)?;
+    }
+
+    /*
+        each nested item is led like a single write of it,
+        taken in id order so two bundles sharing items
+        cannot wait on each other. An item sequenced before
+        keeps its slot and its stored result is returned
+    */
+    let mut ids: Vec<String> = by_process.values().flatten().map(|i| i.id()).collect();
+    ids.sort();
+    ids.dedup();
+    let mut leaders = vec![];
+    for id in ids.into_iter() {
+        leaders.push((id.clone(), lead_write(&deps, &id).await));
+    }
+    let mut results: HashMap<String, WriteResult> = HashMap::new();
+    for (id, _) in leaders.iter() {
+        if let Some(message) = sequenced_message(&deps, id).await? {
+            results.insert(id.clone(), WriteResult::from_message(&message)?);
+        }
+    }
+
+    /*
+        the BTreeMap orders the process ids so concurrent
+        bundles always take the locks in the same order
+    */
+    let mut locks = vec![];
+    for process_id in by_process.keys() {
+        locks.push(deps.scheduler.lock(process_id.clone()).await?);
+    }
+    Deadline::current()
+        .check()
+        .map_err(FlowError::Unavailable)?;
+
+    let mut built = vec![];
+    for ((process_id, items), schedule_info) in by_process.iter().zip(locks.iter_mut()) {
+        let builder = process_builder(&deps, process_id).await?;
+        let updated_info = deps
+            .scheduler
+            .update_schedule_info(schedule_info, process_id.clone())
+            .await?;
+        for item in items.iter().filter(|i| !results.contains_key(&i.id())) {
+            let build_result = builder.build_message(item.clone(), &*updated_info).await?;
+            let message = Message::from_bundle(&build_result.bundle)?;
+            updated_info.advance(&message.assignment_id()?)?;
+            built.push((message, build_result.binary));
+        }
+    }
+
+    for lock in locks.iter() {
+        lock.check_held().map_err(FlowError::Unavailable)?;
+    }
+    let saved = deps
+        .failover
+        .retry(|| deps.data_store.save_messages(&built))
+        .await;
+    if let Err(e) = saved {
+        let messages: Vec<&Message> = built.iter().map(|(message, _)| message).collect();
+        saved_before(&deps, &messages, e).await?;
+    }
+    // each lock was advanced past its items while building
+    for lock in locks.iter_mut() {
+        lock.mark_synced();
+    }
+
+    for (message, binary) in built.into_iter() {
+        let result = WriteResult::from_message(&message)?;
+        let bundle_ref = BundleRef::Assignment(message.assignment_id()?);
+        deps.events
+            .publish(DomainEvent::MessageSequenced { message });
+        let bundle = upload(&deps, binary, bundle_ref).await?;
+        results.insert(result.id.clone(), result.with_bundle(bundle));
+    }
+    drop(locks);
+
+    // a single write of an item waiting on the bundle gets its result
+    for (id, leader) in leaders.into_iter() {
+        if let Some(result) = results.get(&id) {
+            leader.finish(&Ok(result.to_json(version)?), version);
         }
-        Err(e) => Err(format!("{:?}", e)),
     }
+    let items = by_process
+        .values()
+        .flatten()
+        .filter_map(|item| results.get(&item.id()).cloned())
+        .collect();
+
+    let timestamp = system_time_u64().map_err(|e| format!("{:?}", e))?;
+    let result = WriteResult {
+        id: bundle_item.id(),
+        timestamp: timestamp as i64,
+        items,
+        ..Default::default()
+    };
+    Ok(result.to_json(version)?)
+}
+
+/*
+    Oversized writes are turned away before anything is
+    built from them, returns the reason if input is too
+    large. Process items get their own, usually larger,
+    limit so the item is only parsed when its size falls
+    between the two.
+*/
+pub async fn check_item_size(deps: Arc<Deps>, input: &Bytes) -> Result<Option<String>, FlowError> {
+    let max_item_size = deps.config.max_item_size();
+    let max_process_size = deps.config.max_process_size();
+    if input.len() <= max_item_size {
+        return Ok(None);
+    }
+
+    let limit = match input.len() <= max_process_size {
+        true => {
+            let data_item = deps.ingest.parse(input.clone()).await?;
+            match data_item
+                .tags()
+                .iter()
+                .any(|tag| tag.name == "Type" && tag.value == "Process")
+            {
+                true => return Ok(None),
+                false => max_item_size,
+            }
+        }
+        false => max_item_size.max(max_process_size),
+    };
+
+    Ok(Some(format!(
+        "Data item of {} bytes exceeds the limit of {} bytes",
+        input.len(),
+        limit
+    )))
+}
+
+/*
+    In cluster mode, the instance a write is redirected
+    to when another one holds the lease of its process.
+    Only the header of the item is read, a spawn or an
+    item that does not parse is left to write_item.
+*/
+pub async fn cluster_redirect(
+    deps: &Arc<Deps>,
+    input: &Body,
+    process_id: &Option<String>,
+) -> Result<Option<String>, FlowError> {
+    let leases = match deps.scheduler.leases() {
+        Some(leases) => leases,
+        None => return Ok(None),
+    };
+    let target = match process_id {
+        Some(process_id) => process_id.clone(),
+        None => {
+            let length = match DataItem::info_length(&input.bytes) {
+                Ok(Some(length)) if length <= input.bytes.len() => length,
+                _ => return Ok(None),
+            };
+            let item = match DataItem::from_info_bytes(&input.bytes[..length]) {
+                Ok((item, _)) => item,
+                Err(_) => return Ok(None),
+            };
+            let spawns_process = item
+                .tags()
+                .iter()
+                .any(|tag| tag.name == "Type" && tag.value == "Process");
+            if spawns_process {
+                return Ok(None);
+            }
+            item.target()
+        }
+    };
+    Ok(leases.leader(&deps.data_store, &target).await?)
 }
 
 /*
@@ -100,29 +807,139 @@ async fn assignment_only(
 */
 pub async fn write_item(
     deps: Arc<Deps>,
-    input: Vec<u8>,
+    input: Body,
     process_id: Option<String>,
     assign: Option<String>,
-    base_layer: Option<String>,
-    exclude: Option<String>,
-) -> Result<String, String> {
+    params: AssignParams,
+    api_token: Option<String>,
+    version: ApiVersion,
+) -> Result<String, FlowError> {
+    if deps.runtime.read_only() {
+        return Err(FlowError::Unavailable(
+            "Su is in read-only mode for maintenance, try again later".to_string(),
+        ));
+    }
+
+    /*
+        held until the write returns, or until it is handed
+        to the queue of its process
+    */
+    let permit = deps.admission.admit().await?;
+
     // XOR, if we have one of these, we must have both.
     if process_id.is_some() ^ assign.is_some() {
-        return Err("If sending assign or process-id, you must send both.".to_string());
+        return Err(FlowError::Validation(
+            "If sending assign or process-id, you must send both.".to_string(),
+        ));
     } else if let (Some(process_id), Some(assign)) = (process_id, assign) {
-        return assignment_only(deps, process_id, assign, base_layer, exclude).await;
+        let result = assignment_only(
+            deps.clone(),
+            process_id,
+            assign,
+            params,
+            api_token,
+            version,
+            permit,
+        )
+        .await?;
+        return attach_receipts(&deps, result, version).await;
     }
 
-    let builder = init_builder(&deps)?;
+    let data_item = deps.ingest.parse_verified(input).await?;
+    Deadline::current()
+        .check()
+        .map_err(FlowError::Unavailable)?;
+
+    check_rate_limit(&deps, &data_item)?;
+
+    if data_item.is_bundle() {
+        let write = write_bundle(deps.clone(), data_item, api_token, version);
+        let result = run_to_completion(permit, write).await?;
+        return attach_receipts(&deps, result, version).await;
+    }
+
+    let spawns_process = data_item
+        .tags()
+        .iter()
+        .any(|tag| tag.name == "Type" && tag.value == "Process");
+    match spawns_process {
+        true => {
+            authorize_write(
+                &deps,
+                &api_token,
+                &data_item.id(),
+                Some(data_item.owner_address()),
+            )
+            .await?
+        }
+        false => authorize_write(&deps, &api_token, &data_item.target(), None).await?,
+    }
+
+    /*
+        the same item pushed again while it is sequenced
+        waits for that write instead of racing it
+    */
+    let id = data_item.id();
+    let leader = loop {
+        let follower = match deps.in_flight.join(&id) {
+            Joined::Leader(leader) => break leader,
+            Joined::Follower(follower) => follower,
+        };
+        match inflight::wait(follower).await {
+            Some((result, answered_in)) if answered_in == version => return result,
+            Some((Err(e), _)) => return Err(e),
+            // sequenced, read back in the version this client asked for
+            Some((Ok(_), _)) => {
+                if let Some(existing) = existing_write_result(&deps, &id, version).await? {
+                    return Ok(existing);
+                }
+            }
+            None => (),
+        }
+    };
 
-    let data_item = builder.parse_data_item(input.clone())?;
+    /*
+        the write goes on when the client of the leader goes
+        away, so its followers get the result instead of a
+        second write of the item racing the first
+    */
+    let write = {
+        let deps = deps.clone();
+        async move {
+            let result = write_single(deps, data_item, version, permit).await;
+            leader.finish(&result, version);
+            result
+        }
+    };
+    let deadline = Deadline::current();
+    let result = tokio::spawn(async move { deadline.scope(write).await })
+        .await
+        .map_err(|e| FlowError::Internal(format!("write task failed: {}", e)))?;
+    attach_receipts(&deps, result?, version).await
+}
+
+// a single message or process that no other request is writing
+async fn write_single(
+    deps: Arc<Deps>,
+    data_item: DataItem,
+    version: ApiVersion,
+    permit: WritePermit,
+) -> Result<String, FlowError> {
+    if let Some(existing_result) = existing_write_result(&deps, &data_item.id(), version).await? {
+        deps.logger
+            .log(format!("data item already sequenced - {}", data_item.id()));
+        return Ok(existing_result);
+    }
 
-    let tags = data_item.tags().clone();
+    let tags = data_item.tags();
     let type_tag = tags.iter().find(|tag| tag.name == "Type");
     let proto_tag_exists = tags.iter().any(|tag| tag.name == "Data-Protocol");
     if !proto_tag_exists {
-        return Err("Data-Protocol tag not present".to_string());
+        return Err(FlowError::Validation(
+            "Data-Protocol tag not present".to_string(),
+        ));
     }
+    deps.tag_policy.check(tags).map_err(FlowError::Validation)?;
 
     if let Some(type_tag) = type_tag {
         if type_tag.value == "Process" {
@@ -130,111 +947,1198 @@ pub async fn write_item(
             let sched_tag_exists = tags.iter().any(|tag| tag.name == "Scheduler");
 
             if !mod_tag_exists || !sched_tag_exists {
-                return Err(
+                return Err(FlowError::Validation(
                     "Required Module and Scheduler tags for Process type not present".to_string(),
-                );
+                ));
             }
 
-            /*
-                acquire the mutex locked scheduling info for the
-                process we are creating. So if a message is written
-                while the process is still being created it will wait
-            */
-            let locked_schedule_info = deps.scheduler.acquire_lock(data_item.id()).await?;
-            let mut schedule_info = locked_schedule_info.lock().await;
-            let updated_info = deps
-                .scheduler
-                .update_schedule_info(&mut *schedule_info, data_item.id())
-                .await?;
-
-            let build_result = builder.build_process(input, &*updated_info).await?;
-            upload(&deps, build_result.binary.to_vec()).await?;
-            let process = Process::from_bundle(&build_result.bundle)?;
-            deps.data_store
-                .save_process(&process, &build_result.binary)?;
-            deps.logger.log(format!("saved process - {:?}", &process));
-            drop(schedule_info);
-            match system_time_u64() {
-                Ok(timestamp) => {
-                    let response_json =
-                        json!({ "timestamp": timestamp, "id": process.process_id.clone() });
-                    Ok(response_json.to_string())
-                }
-                Err(e) => Err(format!("{:?}", e)),
-            }
+            let scheduler = scheduler_tag(tags);
+            check_scheduler_tag(&deps, &scheduler)?;
+
+            let module = tags
+                .iter()
+                .find(|tag| tag.name == "Module")
+                .map(|tag| tag.value.clone())
+                .unwrap_or_default();
+            SpawnPolicy::new(&*deps.config)
+                .check(&data_item.owner_address(), &module)
+                .map_err(FlowError::Forbidden)?;
+            CronDefinition::check_spawn(tags, deps.config.cron_min_interval_ms())
+                .map_err(FlowError::Validation)?;
+            deps.modules.check(&*deps.gateway, &module).await?;
+
+            run_to_completion(
+                permit,
+                spawn_process(deps.clone(), data_item, scheduler, version),
+            )
+            .await
         } else if type_tag.value == "Message" {
-            /*
-                acquire the mutex locked scheduling info for the
-                process we are writing a message to. this ensures
-                no conflicts in the schedule
-            */
-            let locked_schedule_info = deps.scheduler.acquire_lock(data_item.target()).await?;
-            let mut schedule_info = locked_schedule_info.lock().await;
-            let updated_info = deps
-                .scheduler
-                .update_schedule_info(&mut *schedule_info, data_item.target())
-                .await?;
-
-            let build_result = builder.build_message(input, &*updated_info).await?;
-            let message = Message::from_bundle(&build_result.bundle)?;
-            deps.data_store
-                .save_message(&message, &build_result.binary)?;
-            deps.logger.log(format!("saved message - {:?}", &message));
-            upload(&deps, build_result.binary.to_vec()).await?;
-            drop(schedule_info);
-            match system_time_u64() {
-                Ok(timestamp) => {
-                    let response_json =
-                        json!({ "timestamp": timestamp, "id": message.message_id()? });
-                    Ok(response_json.to_string())
-                }
-                Err(e) => Err(format!("{:?}", e)),
-            }
+            let target = data_item.target();
+            let job = sequence_message(deps.clone(), data_item, target.clone(), version);
+            drop(permit);
+            deps.queues.submit(&target, Box::pin(job)).await
         } else {
-            return Err("Type tag not present".to_string());
+            Err(FlowError::Validation("Type tag not present".to_string()))
         }
     } else {
-        return Err("Type tag not present".to_string());
+        Err(FlowError::Validation("Type tag not present".to_string()))
     }
 }
 
-pub async fn read_message_data(
-    deps: Arc<Deps>,
-    tx_id: String,
-    from: Option<String>,
-    to: Option<String>,
-    limit: Option<i32>,
-) -> Result<String, String> {
-    if let Ok(message) = deps.data_store.get_message(&tx_id) {
-        let result = match serde_json::to_string(&message) {
-            Ok(r) => r,
-            Err(e) => return Err(format!("{:?}", e)),
-        };
-        return Ok(result);
-    }
+/*
+    With BOOT_MESSAGE_TAGS set a spawn carrying one of those
+    tags, like On-Boot, is also sequenced as the first
+    message of its process so a CU evaluates it on boot.
+*/
+fn boots_on_spawn(deps: &Arc<Deps>, tags: &[Tag]) -> bool {
+    let boot_tags = deps.config.boot_message_tags();
+    tags.iter().any(|tag| boot_tags.contains(&tag.name))
+}
 
-    if let Ok(_) = deps.data_store.get_process(&tx_id) {
-        let messages = deps.data_store.get_messages(&tx_id, &from, &to, &limit)?;
-        let result = match serde_json::to_string(&messages) {
-            Ok(r) => r,
-            Err(e) => return Err(format!("{:?}", e)),
-        };
-        return Ok(result);
-    }
+/*
+    Runs a data item through the checks write_item makes
+    without sequencing or uploading it, so sdk developers
+    can debug their items without polluting a schedule.
+    Nothing is locked or saved and no rate limit is spent,
+    the report lists every check and why it failed.
+*/
+pub async fn validate_item(
+    deps: Arc<Deps>,
+    input: Bytes,
+    api_token: Option<String>,
+) -> Result<String, FlowError> {
+    let mut report = ValidationReport::default();
 
-    Err("Message or Process not found".to_string())
-}
+    match check_item_size(deps.clone(), &input).await {
+        Ok(too_large) => report.check("size", too_large.map_or(Ok(()), Err)),
+        Err(e) => report.check("size", Err(e)),
+    };
 
-pub async fn read_process(deps: Arc<Deps>, process_id: String) -> Result<String, String> {
-    let process = deps.data_store.get_process(&process_id)?;
-    let result = match serde_json::to_string(&process) {
-        Ok(r) => r,
-        Err(e) => return Err(format!("{:?}", e)),
+    let data_item = match deps.ingest.parse(input).await {
+        Ok(item) => item,
+        Err(FlowError::Validation(e)) => {
+            report.check("parse", Err(e));
+            return Ok(report.to_json());
+        }
+        Err(e) => return Err(e),
     };
-    Ok(result)
-}
+    report.check::<String>("parse", Ok(()));
+    report.check(
+        "signature",
+        data_item.verify_signature().map_err(|e| format!("{:?}", e)),
+    );
 
-fn system_time() -> Result<String, SystemTimeError> {
+    let tags = data_item.tags();
+    let type_tag = tags
+        .iter()
+        .find(|tag| tag.name == "Type")
+        .map(|tag| tag.value.clone());
+
+    if data_item.is_bundle() {
+        report.item_type = Some("Bundle".to_string());
+        let builder = init_builder(&deps)?;
+        match builder.parse_bundle(&data_item).await {
+            Ok(items) => {
+                report.check::<String>("bundle", Ok(()));
+                report.check(
+                    "tag_policy",
+                    items
+                        .iter()
+                        .try_for_each(|item| deps.tag_policy.check(item.tags())),
+                );
+                let targets: BTreeMap<String, ()> =
+                    items.iter().map(|item| (item.target(), ())).collect();
+                for target in targets.keys() {
+                    let exists = target_exists(&deps, target).await?;
+                    report.check("target_process", exists);
+                    if deps.config.write_restricted() {
+                        let authorized = authorize_write(&deps, &api_token, target, None).await;
+                        report.check("authorized", authorized);
+                    }
+                }
+            }
+            Err(e) => {
+                report.check("bundle", Err(String::from(e)));
+            }
+        }
+        report.item = Some(data_item);
+        return Ok(report.to_json());
+    }
+
+    report.check(
+        "data_protocol_tag",
+        match tags.iter().any(|tag| tag.name == "Data-Protocol") {
+            true => Ok(()),
+            false => Err("Data-Protocol tag not present"),
+        },
+    );
+    report.check("tag_policy", deps.tag_policy.check(tags));
+
+    match type_tag.as_deref() {
+        Some("Process") => {
+            let module = tags
+                .iter()
+                .find(|tag| tag.name == "Module")
+                .map(|tag| tag.value.clone());
+            let has_scheduler = tags.iter().any(|tag| tag.name == "Scheduler");
+            report.check(
+                "module_scheduler_tags",
+                match module.is_some() && has_scheduler {
+                    true => Ok(()),
+                    false => Err("Required Module and Scheduler tags for Process type not present"),
+                },
+            );
+            if deps.config.strict_scheduler_tag() {
+                report.check(
+                    "scheduler_tag",
+                    check_scheduler_tag(&deps, &scheduler_tag(tags)),
+                );
+            }
+            report.check(
+                "spawn_policy",
+                SpawnPolicy::new(&*deps.config).check(
+                    &data_item.owner_address(),
+                    module.as_deref().unwrap_or_default(),
+                ),
+            );
+            report.check(
+                "cron_interval",
+                CronDefinition::check_spawn(tags, deps.config.cron_min_interval_ms()),
+            );
+            if deps.config.module_validation() {
+                let valid = deps
+                    .modules
+                    .check(&*deps.gateway, module.as_deref().unwrap_or_default())
+                    .await;
+                report.check("module", valid.map_err(|e| e.message().to_string()));
+            }
+            if deps.config.write_restricted() {
+                let authorized = authorize_write(
+                    &deps,
+                    &api_token,
+                    &data_item.id(),
+                    Some(data_item.owner_address()),
+                )
+                .await;
+                report.check("authorized", authorized);
+            }
+        }
+        Some("Message") => {
+            let exists = target_exists(&deps, &data_item.target()).await?;
+            report.check("target_process", exists);
+            if deps.config.write_restricted() {
+                let authorized =
+                    authorize_write(&deps, &api_token, &data_item.target(), None).await;
+                report.check("authorized", authorized);
+            }
+        }
+        _ => {
+            report.check("type_tag", Err("Type tag not present"));
+        }
+    }
+    report.item_type = type_tag;
+    report.item = Some(data_item);
+    Ok(report.to_json())
+}
+
+// a message can only be sequenced to a process this su has
+async fn target_exists(deps: &Arc<Deps>, target: &String) -> Result<Result<(), String>, FlowError> {
+    if target.is_empty() {
+        return Ok(Err("Message has no target process".to_string()));
+    }
+    if deps.cache.get_process(target).is_some() {
+        return Ok(Ok(()));
+    }
+    match deps.data_store.get_process(target).await {
+        Ok(_) => Ok(Ok(())),
+        Err(StoreErrorType::NotFound(_)) => Ok(Err(format!(
+            "Process {} is not scheduled on this su",
+            target
+        ))),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/*
+    Sequences a new process and, when it boots, its first
+    message under the lock of the process id
+*/
+async fn spawn_process(
+    deps: Arc<Deps>,
+    data_item: DataItem,
+    scheduler: String,
+    version: ApiVersion,
+) -> Result<String, FlowError> {
+    let tags = data_item.tags();
+    let builder = init_builder_for(&deps, &scheduler)?;
+
+    /*
+        acquire the mutex locked scheduling info for the
+        process we are creating. So if a message is written
+        while the process is still being created it will wait
+    */
+    let mut schedule_info = deps.scheduler.lock(data_item.id()).await?;
+    Deadline::current()
+        .check()
+        .map_err(FlowError::Unavailable)?;
+    let updated_info = deps
+        .scheduler
+        .update_schedule_info(&mut schedule_info, data_item.id())
+        .await?;
+
+    let boots = boots_on_spawn(&deps, tags);
+    let build_result = builder
+        .build_process(data_item.clone(), &*updated_info)
+        .await?;
+    let process = Process::from_bundle(&build_result.bundle)?;
+    let process_ref = BundleRef::Process(process.process_id.clone());
+
+    // uploaded once saved, a spawn that failed to save is not on arweave
+    if !boots {
+        schedule_info.check_held().map_err(FlowError::Unavailable)?;
+        deps.failover
+            .retry(|| deps.data_store.save_process(&process, &build_result.binary))
+            .await?;
+        // a new process has no messages, the first slot is still next
+        schedule_info.mark_synced();
+        deps.events.publish(DomainEvent::ProcessCreated {
+            process: process.clone(),
+        });
+        let bundle = upload(&deps, build_result.binary.clone(), process_ref).await?;
+        drop(schedule_info);
+        return Ok(WriteResult::from_process(&process)
+            .with_bundle(bundle)
+            .to_json(version)?);
+    }
+
+    // the spawn item itself takes the first slot
+    let boot_result = builder
+        .build_boot_message(data_item.clone(), &*updated_info)
+        .await?;
+    let boot = Message::from_bundle(&boot_result.bundle)?;
+    schedule_info.check_held().map_err(FlowError::Unavailable)?;
+    deps.failover
+        .retry(|| {
+            deps.data_store.save_process_with_boot(
+                &process,
+                &build_result.binary,
+                &boot,
+                &boot_result.binary,
+            )
+        })
+        .await?;
+    schedule_info.commit(&boot.assignment_id()?)?;
+    deps.events.publish(DomainEvent::ProcessCreated {
+        process: process.clone(),
+    });
+    deps.events.publish(DomainEvent::MessageSequenced {
+        message: boot.clone(),
+    });
+    let bundle = upload(&deps, build_result.binary.clone(), process_ref).await?;
+    let boot_bundle = upload(
+        &deps,
+        boot_result.binary.clone(),
+        BundleRef::Assignment(boot.assignment_id()?),
+    )
+    .await?;
+    drop(schedule_info);
+
+    let result = WriteResult {
+        items: vec![WriteResult::from_message(&boot)?.with_bundle(boot_bundle)],
+        ..WriteResult::from_process(&process).with_bundle(bundle)
+    };
+    Ok(result.to_json(version)?)
+}
+
+/*
+    Writes sequenced outside the process queues run on a
+    task of their own and hold the write slot there. If
+    the client goes away the request future is dropped,
+    a save already handed to the blocking pool would then
+    still run after the schedule lock was given back
+*/
+async fn run_to_completion<F>(permit: WritePermit, write: F) -> Result<String, FlowError>
+where
+    F: std::future::Future<Output = Result<String, FlowError>> + Send + 'static,
+{
+    let deadline = Deadline::current();
+    tokio::spawn(async move {
+        let _permit = permit;
+        deadline.scope(write).await
+    })
+    .await
+    .map_err(|e| FlowError::Internal(format!("write task failed: {}", e)))?
+}
+
+/*
+    runs on the sequencing task of the target process,
+    the lock still guards against spawns and bundles
+    touching the same process
+*/
+async fn sequence_message(
+    deps: Arc<Deps>,
+    data_item: DataItem,
+    target: String,
+    version: ApiVersion,
+) -> Result<String, FlowError> {
+    let builder = process_builder(&deps, &target).await?;
+    let mut schedule_info = deps.scheduler.lock(target.clone()).await?;
+    Deadline::current()
+        .check()
+        .map_err(FlowError::Unavailable)?;
+    let updated_info = deps
+        .scheduler
+        .update_schedule_info(&mut schedule_info, target)
+        .await?;
+
+    let build_result = builder.build_message(data_item, &*updated_info).await?;
+    let message = Message::from_bundle(&build_result.bundle)?;
+    schedule_info.check_held().map_err(FlowError::Unavailable)?;
+    let saved = deps
+        .failover
+        .retry(|| deps.data_store.save_message(&message, &build_result.binary))
+        .await;
+    if let Err(e) = saved {
+        match saved_before(&deps, &[&message], e).await {
+            Ok(()) => (),
+            // sequenced by another write, answered with its slot
+            Err(FlowError::Conflict(e)) => {
+                return existing_write_result(&deps, &message.message_id()?, version)
+                    .await?
+                    .ok_or(FlowError::Conflict(e))
+            }
+            Err(e) => return Err(e),
+        }
+    }
+    schedule_info.commit(&message.assignment_id()?)?;
+    deps.events.publish(DomainEvent::MessageSequenced {
+        message: message.clone(),
+    });
+    let bundle = upload(
+        &deps,
+        build_result.binary.clone(),
+        BundleRef::Assignment(message.assignment_id()?),
+    )
+    .await?;
+    drop(schedule_info);
+    Ok(WriteResult::from_message(&message)?
+        .with_bundle(bundle)
+        .to_json(version)?)
+}
+
+/*
+    Sequences a cron message the su generated for a process,
+    through the process queue like any other write, so it
+    takes an admission slot when its turn comes. The
+    cron's next_run is saved with the message
+*/
+pub async fn sequence_cron(
+    deps: Arc<Deps>,
+    process_id: &str,
+    interval: &str,
+    tags: Vec<Tag>,
+    next_run: i64,
+) -> Result<String, FlowError> {
+    let job_deps = deps.clone();
+    let job_process_id = process_id.to_string();
+    let interval = interval.to_string();
+    let job = async move {
+        let builder = process_builder(&job_deps, &job_process_id).await?;
+        let mut schedule_info = job_deps.scheduler.lock(job_process_id.clone()).await?;
+        let updated_info = job_deps
+            .scheduler
+            .update_schedule_info(&mut schedule_info, job_process_id.clone())
+            .await?;
+
+        let build_result = builder
+            .build_cron_message(&job_process_id, tags, &*updated_info)
+            .await?;
+        let message = Message::from_bundle(&build_result.bundle)?;
+        schedule_info.check_held().map_err(FlowError::Unavailable)?;
+        let saved = job_deps
+            .failover
+            .retry(|| {
+                job_deps.data_store.save_cron_message(
+                    &message,
+                    &build_result.binary,
+                    &interval,
+                    next_run,
+                )
+            })
+            .await;
+        if let Err(e) = saved {
+            saved_before(&job_deps, &[&message], e).await?;
+        }
+        schedule_info.commit(&message.assignment_id()?)?;
+        job_deps.events.publish(DomainEvent::MessageSequenced {
+            message: message.clone(),
+        });
+        upload(
+            &job_deps,
+            build_result.binary.clone(),
+            BundleRef::Assignment(message.assignment_id()?),
+        )
+        .await?;
+        drop(schedule_info);
+        message.assignment_id().map_err(FlowError::from)
+    };
+    deps.queues.submit(process_id, Box::pin(job)).await
+}
+
+/*
+    A stored bundle failed its checksum. The caller still
+    gets the integrity error but the copy uploaded to
+    arweave is fetched in the background and written back
+    if it matches the original checksum.
+*/
+fn check_integrity<T>(
+    deps: &Arc<Deps>,
+    result: Result<T, StoreErrorType>,
+) -> Result<T, StoreErrorType> {
+    if let Err(StoreErrorType::IntegrityError(bundle_ref)) = &result {
+        let deps = deps.clone();
+        let bundle_ref = bundle_ref.clone();
+        deps.logger
+            .error(format!("bundle failed checksum - {:?}", bundle_ref));
+        tokio::spawn(async move {
+            match repair_bundle(&deps, &bundle_ref).await {
+                Ok(_) => deps
+                    .logger
+                    .log(format!("repaired bundle from arweave - {:?}", bundle_ref)),
+                Err(e) => deps
+                    .logger
+                    .error(format!("failed to repair bundle {:?} - {}", bundle_ref, e)),
+            }
+        });
+    }
+    result
+}
+
+/*
+    the stored binary is the signed bundle data item
+    that was uploaded, its header is kept and the data
+    is fetched again from the gateway
+*/
+async fn repair_bundle(deps: &Arc<Deps>, bundle_ref: &BundleRef) -> Result<(), String> {
+    let stored = deps.data_store.get_stored_bundle(bundle_ref).await?;
+    let mut bundle_item = DataItem::from_bytes(stored).map_err(|e| format!("{:?}", e))?;
+    let data = deps.gateway.raw(&bundle_item.id()).await?;
+    bundle_item.replace_data(data);
+    let repaired = bundle_item.as_bytes().map_err(|e| format!("{:?}", e))?;
+    deps.data_store
+        .restore_bundle(bundle_ref, &repaired)
+        .await?;
+    Ok(())
+}
+
+/*
+    The exact signed bundle the su uploaded for a message,
+    assignment or process, read from the store so a CU can
+    check signatures and the hash chain before arweave has
+    it. A message id gets the bundle of its first assignment.
+*/
+pub async fn read_bundle(
+    deps: Arc<Deps>,
+    tx_id: String,
+    signed_read: Option<String>,
+) -> Result<Vec<u8>, FlowError> {
+    let bundle_ref = match check_integrity(&deps, deps.data_store.get_message(&tx_id).await) {
+        Ok(message) => {
+            authorize_process_read(&deps, &message.process_id()?, &signed_read).await?;
+            BundleRef::Assignment(message.assignment_id()?)
+        }
+        Err(StoreErrorType::NotFound(_)) => {
+            let process = check_integrity(&deps, deps.data_store.get_process(&tx_id).await)?;
+            authorize_read(&deps, &process, &signed_read)?;
+            BundleRef::Process(tx_id)
+        }
+        Err(e) => return Err(e.into()),
+    };
+    Ok(deps.data_store.get_stored_bundle(&bundle_ref).await?)
+}
+
+// the query params of a read of a process's messages, parsed
+pub fn message_query(
+    from: Option<String>,
+    to: Option<String>,
+    limit: Option<i32>,
+    tag: Option<String>,
+    from_timestamp: Option<i64>,
+    to_timestamp: Option<i64>,
+    sort: Option<String>,
+) -> Result<MessageQuery, FlowError> {
+    Ok(MessageQuery {
+        from,
+        to,
+        limit,
+        tags: TagFilter::from_query(&tag).map_err(|e| FlowError::Validation(e.into()))?,
+        from_timestamp,
+        to_timestamp,
+        sort: SortOrder::from_query(&sort).map_err(|e| FlowError::Validation(e.into()))?,
+    })
+}
+
+pub async fn read_message_data(
+    deps: Arc<Deps>,
+    tx_id: String,
+    query: MessageQuery,
+    signed_read: Option<String>,
+    cron: Option<bool>,
+) -> Result<String, FlowError> {
+    match check_integrity(&deps, deps.data_store.get_message(&tx_id).await) {
+        Ok(message) => {
+            authorize_process_read(&deps, &message.process_id()?, &signed_read).await?;
+            let result = match serde_json::to_string(&message) {
+                Ok(r) => r,
+                Err(e) => return Err(FlowError::Internal(format!("{:?}", e))),
+            };
+            return Ok(result);
+        }
+        Err(e @ StoreErrorType::IntegrityError(_)) => return Err(e.into()),
+        Err(_) => (),
+    }
+
+    let process_result = check_integrity(&deps, deps.data_store.get_process(&tx_id).await);
+    if let Err(e @ StoreErrorType::IntegrityError(_)) = process_result {
+        return Err(e.into());
+    }
+
+    if let Ok(process) = process_result {
+        authorize_read(&deps, &process, &signed_read)?;
+        let messages = check_integrity(&deps, deps.data_store.get_messages(&tx_id, &query).await)?;
+        let messages = match virtual_crons(&deps, &process, cron) {
+            Some(crons) => {
+                let from = query.from.as_deref().and_then(|f| f.parse::<i64>().ok());
+                let to = query.to.as_deref().and_then(|t| t.parse::<i64>().ok());
+                let now = system_time_u64().map_err(|e| format!("{:?}", e))? as i64;
+                let window = cron::virtual_window(
+                    from,
+                    to,
+                    query.from_timestamp,
+                    query.to_timestamp,
+                    query.sort,
+                    &messages,
+                    now,
+                );
+                let limit = query.limit.unwrap_or(5000).max(0) as usize;
+                cron::merge_virtual(
+                    messages,
+                    &process,
+                    &crons,
+                    window,
+                    query.sort,
+                    limit,
+                    &query.tags,
+                )
+            }
+            None => messages,
+        };
+        let result = match serde_json::to_string(&messages) {
+            Ok(r) => r,
+            Err(e) => return Err(FlowError::Internal(format!("{:?}", e))),
+        };
+        return Ok(result);
+    }
+
+    Err(FlowError::NotFound(
+        "Message or Process not found".to_string(),
+    ))
+}
+
+/*
+    With CRON_MODE=virtual reads of a process that declared
+    Cron-Interval tags include its cron messages, unless
+    the read asks for cron=false
+*/
+fn virtual_crons(
+    deps: &Arc<Deps>,
+    process: &Process,
+    cron: Option<bool>,
+) -> Option<Vec<CronDefinition>> {
+    if deps.config.cron_mode() != "virtual" || !cron.unwrap_or(true) {
+        return None;
+    }
+    match CronDefinition::from_process(process) {
+        Ok(crons) if !crons.is_empty() => Some(crons),
+        _ => None,
+    }
+}
+
+/*
+    fetch a single message from the schedule of a
+    process by its position, for callers that know
+    exactly which slot they need
+*/
+pub async fn read_message_by_nonce(
+    deps: Arc<Deps>,
+    process_id: String,
+    epoch: i32,
+    nonce: i32,
+    signed_read: Option<String>,
+) -> Result<String, FlowError> {
+    authorize_process_read(&deps, &process_id, &signed_read).await?;
+    let message = check_integrity(
+        &deps,
+        deps.data_store
+            .get_message_by_nonce(&process_id, &epoch, &nonce)
+            .await,
+    )?;
+    let result = match serde_json::to_string(&message) {
+        Ok(r) => r,
+        Err(e) => return Err(FlowError::Internal(format!("{:?}", e))),
+    };
+    Ok(result)
+}
+
+/*
+    several processes in one request, so a CU evaluating
+    many of them does not make a round trip per process.
+    a read that fails carries its error in its result and
+    the others are still served
+*/
+pub async fn read_bulk(deps: Arc<Deps>, body: Vec<u8>) -> Result<String, FlowError> {
+    if deps.config.mode() == "router" {
+        return Err(FlowError::Validation(
+            "Bulk reads are served by the schedulers, find them with POST /locations".to_string(),
+        ));
+    }
+    let request = BulkReadRequest::from_body(&body)?;
+    let mut results = vec![];
+    for read in request.reads.iter() {
+        results.push(match read_range(&deps, read).await {
+            Ok(messages) => json!({ "process_id": read.process_id, "messages": messages }),
+            Err(e) => json!({ "process_id": read.process_id, "error": e.message() }),
+        });
+    }
+    Ok(json!({ "results": results }).to_string())
+}
+
+async fn read_range(deps: &Arc<Deps>, read: &RangeRead) -> Result<PaginatedMessages, FlowError> {
+    authorize_process_read(deps, &read.process_id, &read.signed_read).await?;
+    Ok(check_integrity(
+        deps,
+        deps.data_store
+            .get_messages_by_nonce_range(
+                &read.process_id,
+                read.from_nonce,
+                read.to_nonce,
+                read.limit(),
+            )
+            .await,
+    )?)
+}
+
+// how often a waiting read looks for a nonce sequenced elsewhere
+const NONCE_RECHECK: Duration = Duration::from_millis(500);
+
+/*
+    With a min-nonce a read waits up to MIN_NONCE_WAIT_MS
+    for the process to reach that nonce where reads are
+    served from, so a client reading from a replica or
+    another su instance sees its own write. The store is
+    read again when this su sequences the nonce, or every
+    NONCE_RECHECK for a write of another instance or one
+    the replica has not received yet. The cached latest
+    message is brought up to date too.
+*/
+pub async fn wait_for_nonce(
+    deps: &Arc<Deps>,
+    process_id: &str,
+    min_nonce: Option<i32>,
+) -> Result<(), FlowError> {
+    let min_nonce = match min_nonce {
+        Some(n) => n,
+        None => return Ok(()),
+    };
+    let reached = |nonce: Option<i32>| nonce.is_some_and(|n| n >= min_nonce);
+    if !reached(deps.data_store.get_read_nonce(process_id).await?) {
+        let mut nonces = deps.nonce_waiters.watch(process_id).ok_or_else(|| {
+            FlowError::Unavailable("Too many reads are waiting for a nonce, try again later".into())
+        })?;
+        let until = Instant::now() + Duration::from_millis(deps.config.min_nonce_wait_ms());
+        let mut check = true;
+        while !check || !reached(deps.data_store.get_read_nonce(process_id).await?) {
+            let left = match Deadline::current().remaining() {
+                Some(remaining) => remaining.min(until.saturating_duration_since(Instant::now())),
+                None => until.saturating_duration_since(Instant::now()),
+            };
+            if left.is_zero() {
+                return Err(FlowError::Unavailable(format!(
+                    "Process {} has not reached nonce {} yet, try again later",
+                    process_id, min_nonce
+                )));
+            }
+            check = match tokio::time::timeout(NONCE_RECHECK.min(left), nonces.changed()).await {
+                Ok(nonce) => nonce >= min_nonce,
+                Err(_) => true,
+            };
+        }
+    }
+
+    let cached = deps.cache.latest_message(process_id);
+    if cached.is_some_and(|m| m.nonce().is_ok_and(|n| n < min_nonce)) {
+        if let Some(m) = deps.data_store.get_latest_message(process_id).await? {
+            deps.cache.put_latest(&m);
+        }
+    }
+    Ok(())
+}
+
+/*
+    number of messages in the schedule of a process and
+    the highest nonce assigned, for tracking sync progress
+*/
+pub async fn read_message_count(
+    deps: Arc<Deps>,
+    process_id: String,
+    signed_read: Option<String>,
+) -> Result<String, FlowError> {
+    authorize_route_read(&deps, &process_id, &signed_read).await?;
+    let message_count = deps.data_store.get_message_count(&process_id).await?;
+    let response_json = json!({
+        "process_id": process_id,
+        "count": message_count.count,
+        "max_nonce": message_count.max_nonce,
+    });
+    Ok(response_json.to_string())
+}
+
+/*
+    list the processes this su schedules, optionally
+    only those spawned by an owner or from a module
+*/
+pub async fn read_processes(
+    deps: Arc<Deps>,
+    from: Option<String>,
+    limit: Option<i32>,
+    owner: Option<String>,
+    module: Option<String>,
+) -> Result<String, FlowError> {
+    let mut processes = check_integrity(
+        &deps,
+        deps.data_store
+            .get_processes(&from, &limit, &owner, &module)
+            .await,
+    )?;
+    // private processes are not listed
+    processes
+        .edges
+        .retain(|edge| ReadAccess::from_process(&edge.node).is_none());
+    let result = match serde_json::to_string(&processes) {
+        Ok(r) => r,
+        Err(e) => return Err(FlowError::Internal(format!("{:?}", e))),
+    };
+    Ok(result)
+}
+
+/*
+    everything an owner address has written to this su,
+    processes it spawned and messages it signed
+*/
+pub async fn read_owner_processes(
+    deps: Arc<Deps>,
+    owner: String,
+    from: Option<String>,
+    limit: Option<i32>,
+) -> Result<String, FlowError> {
+    read_processes(deps, from, limit, Some(owner), None).await
+}
+
+/*
+    processes spawned from a Module, so module authors can
+    see who runs their module and reach them on upgrades
+*/
+pub async fn read_module_processes(
+    deps: Arc<Deps>,
+    module: String,
+    from: Option<String>,
+    limit: Option<i32>,
+) -> Result<String, FlowError> {
+    read_processes(deps, from, limit, None, Some(module)).await
+}
+
+pub async fn read_owner_messages(
+    deps: Arc<Deps>,
+    owner: String,
+    from: Option<String>,
+    limit: Option<i32>,
+) -> Result<String, FlowError> {
+    let mut messages = check_integrity(
+        &deps,
+        deps.data_store
+            .get_messages_by_owner(&owner, &from, &limit)
+            .await,
+    )?;
+    // messages to private processes are not listed
+    let mut private: BTreeMap<String, bool> = BTreeMap::new();
+    for edge in messages.edges.iter() {
+        let process_id = edge.node.process_id()?;
+        if let Entry::Vacant(entry) = private.entry(process_id) {
+            let process = cached_process(&deps, entry.key()).await?;
+            entry.insert(ReadAccess::from_process(&process).is_some());
+        }
+    }
+    messages.edges.retain(|edge| match edge.node.process_id() {
+        Ok(process_id) => private.get(&process_id) == Some(&false),
+        Err(_) => false,
+    });
+    let result = match serde_json::to_string(&messages) {
+        Ok(r) => r,
+        Err(e) => return Err(FlowError::Internal(format!("{:?}", e))),
+    };
+    Ok(result)
+}
+
+pub async fn read_process(
+    deps: Arc<Deps>,
+    process_id: String,
+    signed_read: Option<String>,
+    version: ApiVersion,
+) -> Result<String, FlowError> {
+    let process = match deps.cache.get_process(&process_id) {
+        Some(p) => p,
+        None => {
+            let p = check_integrity(&deps, deps.data_store.get_process(&process_id).await)?;
+            deps.cache.put_process(&p);
+            p
+        }
+    };
+    authorize_read(&deps, &process, &signed_read)?;
+    if version < ApiVersion::V2 {
+        let result = match serde_json::to_string(&process) {
+            Ok(r) => r,
+            Err(e) => return Err(FlowError::Internal(format!("{:?}", e))),
+        };
+        return Ok(result);
+    }
+    let mut response_json =
+        serde_json::to_value(&process).map_err(|e| FlowError::Internal(format!("{:?}", e)))?;
+    response_json["schedule"] = schedule_summary(&deps, &process_id).await?;
+    Ok(response_json.to_string())
+}
+
+/*
+    what a client would otherwise put together from
+    several reads, the ends of the schedule, how many
+    messages it holds and the crons of the process
+*/
+async fn schedule_summary(
+    deps: &Arc<Deps>,
+    process_id: &str,
+) -> Result<serde_json::Value, FlowError> {
+    let first = deps
+        .data_store
+        .get_assignments(process_id, &None, 1)
+        .await?
+        .pop();
+    let message_count = deps.data_store.get_message_count(process_id).await?;
+    let crons = deps.data_store.get_crons(process_id).await?;
+    let last = match latest_message(deps, process_id).await? {
+        Some(m) => Some((m.nonce()?, m.timestamp()?, m.hash_chain()?)),
+        None => None,
+    };
+    let crons: Vec<serde_json::Value> = crons
+        .iter()
+        .map(|c| json!({ "interval": c.interval, "tags": c.tags, "next_run": c.next_run }))
+        .collect();
+    Ok(json!({
+        "first_nonce": first.map(|a| a.nonce),
+        "last_nonce": last.as_ref().map(|l| l.0),
+        "last_timestamp": last.as_ref().map(|l| l.1),
+        "last_hash_chain": last.map(|l| l.2),
+        "message_count": message_count.count,
+        "crons": crons,
+        "scheduler_url": deps.config.scheduler_location_url(),
+    }))
+}
+
+// the latest message of a process, from the cache when it is warm
+async fn latest_message(deps: &Arc<Deps>, process_id: &str) -> Result<Option<Message>, FlowError> {
+    match deps.cache.latest_message(process_id) {
+        Some(m) => Ok(Some(m)),
+        None => match deps.data_store.get_latest_message(process_id).await? {
+            Some(m) => {
+                deps.cache.put_latest(&m);
+                Ok(Some(m))
+            }
+            None => Ok(None),
+        },
+    }
+}
+
+pub async fn read_latest_message(
+    deps: Arc<Deps>,
+    process_id: String,
+    signed_read: Option<String>,
+) -> Result<String, FlowError> {
+    authorize_process_read(&deps, &process_id, &signed_read).await?;
+    let message = match latest_message(&deps, &process_id).await? {
+        Some(m) => m,
+        None => {
+            return Err(FlowError::NotFound(
+                "No messages found for process".to_string(),
+            ))
+        }
+    };
+    let result = match serde_json::to_string(&message) {
+        Ok(r) => r,
+        Err(e) => return Err(FlowError::Internal(format!("{:?}", e))),
+    };
+    Ok(result)
+}
+
+/*
+    With SIGN_READS a read response gets a receipt signed
+    by the scheduler of the process it was read from, the
+    su wallet when that is not known
+*/
+pub async fn sign_read(
+    deps: &Arc<Deps>,
+    process_id: Option<&String>,
+    body: &[u8],
+) -> Result<Option<ReadReceipt>, FlowError> {
+    if !deps.config.sign_reads() {
+        return Ok(None);
+    }
+    let signer = match process_id {
+        Some(id) if deps.tenants.is_multi() => match process_wallet(deps, id).await {
+            Ok(wallet) => deps
+                .tenants
+                .process_signer(&wallet, id)
+                .unwrap_or(deps.signer.clone()),
+            Err(_) => deps.signer.clone(),
+        },
+        _ => deps.signer.clone(),
+    };
+    let now = system_time_u64().map_err(|e| FlowError::Internal(format!("{:?}", e)))?;
+    let receipt = receipts::sign(&*signer, body, now)
+        .await
+        .map_err(FlowError::Upstream)?;
+    Ok(Some(receipt))
+}
+
+/*
+    An ETag for reads of a process and its messages made
+    from the latest slot of its schedule, so a polling CU
+    gets a 304 until something new is sequenced. It is
+    taken before the read, a body can be newer than its
+    tag but never older. None when id is not a process.
+*/
+pub async fn process_etag(deps: Arc<Deps>, id: String) -> Result<Option<String>, FlowError> {
+    if deps.cache.get_process(&id).is_none() {
+        match deps.data_store.get_process(&id).await {
+            Ok(process) => deps.cache.put_process(&process),
+            Err(StoreErrorType::NotFound(_)) => return Ok(None),
+            Err(e) => return Err(e.into()),
+        }
+    }
+    // virtual cron messages come due without a write
+    if let Some(process) = deps.cache.get_process(&id) {
+        if virtual_crons(&deps, &process, None).is_some() {
+            return Ok(None);
+        }
+    }
+    let tag = match latest_message(&deps, &id).await? {
+        Some(m) => format!("{}-{}-{}", m.epoch()?, m.nonce()?, m.hash_chain()?),
+        None => "empty".to_string(),
+    };
+    // weak, the same content may be sent with another encoding
+    Ok(Some(format!("W/\"{}\"", tag)))
+}
+
+/*
+    register a CU checkpoint of a process. The slot it was
+    taken at has to be sequenced already, on a restricted
+    su registering needs the same api token as a write
+*/
+pub async fn register_checkpoint(
+    deps: Arc<Deps>,
+    process_id: String,
+    body: Vec<u8>,
+    api_token: Option<String>,
+) -> Result<String, FlowError> {
+    let request = CheckpointRequest::from_body(&body)?;
+    if deps.cache.get_process(&process_id).is_none() {
+        deps.data_store.get_process(&process_id).await?;
+    }
+    authorize_write(&deps, &api_token, &process_id, None).await?;
+
+    let latest = match latest_message(&deps, &process_id).await? {
+        Some(m) => (m.epoch()?, m.nonce()?),
+        None => (-1, -1),
+    };
+    if (request.epoch, request.nonce) > latest {
+        return Err(FlowError::Validation(format!(
+            "Checkpoint at epoch {} nonce {} is past the latest message of the process",
+            request.epoch, request.nonce
+        )));
+    }
+
+    let timestamp = system_time_u64().map_err(|e| FlowError::Internal(format!("{:?}", e)))?;
+    let checkpoint = Checkpoint {
+        process_id,
+        epoch: request.epoch,
+        nonce: request.nonce,
+        checkpoint_id: request.checkpoint_id,
+        cu: request.cu,
+        timestamp: timestamp as i64,
+    };
+    deps.data_store.save_checkpoint(&checkpoint).await?;
+    serde_json::to_string(&checkpoint).map_err(|e| FlowError::Internal(format!("{:?}", e)))
+}
+
+/*
+    checkpoints of a process latest first, with a nonce
+    only those taken at or before it so a CU can find
+    the closest one to the message it needs to evaluate
+*/
+pub async fn read_checkpoints(
+    deps: Arc<Deps>,
+    process_id: String,
+    epoch: Option<i32>,
+    nonce: Option<i32>,
+    limit: Option<i32>,
+    signed_read: Option<String>,
+) -> Result<String, FlowError> {
+    authorize_process_read(&deps, &process_id, &signed_read).await?;
+    let before = nonce.map(|n| (epoch.unwrap_or(0), n));
+    let limit = limit.unwrap_or(100).clamp(1, 1000) as i64;
+    let checkpoints = deps
+        .data_store
+        .get_checkpoints(&process_id, &before, limit)
+        .await?;
+    let response_json = json!({ "process_id": process_id, "checkpoints": checkpoints });
+    Ok(response_json.to_string())
+}
+
+/*
+    the su's assignments of a process in schedule order,
+    with a nonce only those after it, so a CU can page
+    through and check the hash chain without the messages
+*/
+pub async fn read_assignments(
+    deps: Arc<Deps>,
+    process_id: String,
+    epoch: Option<i32>,
+    nonce: Option<i32>,
+    limit: Option<i32>,
+    signed_read: Option<String>,
+) -> Result<String, FlowError> {
+    authorize_process_read(&deps, &process_id, &signed_read).await?;
+    let after = nonce.map(|n| (epoch.unwrap_or(0), n));
+    let limit = limit.unwrap_or(100).clamp(1, 1000) as i64;
+    let assignments = deps
+        .data_store
+        .get_assignments(&process_id, &after, limit)
+        .await?;
+    let response_json = json!({ "process_id": process_id, "assignments": assignments });
+    Ok(response_json.to_string())
+}
+
+/*
+    the receipt the upload node gave for a bundle of this
+    su and whether the network reached its deadline, a
+    bundle missing from arweave after that was not
+    delivered as promised
+*/
+pub async fn read_upload_receipt(deps: Arc<Deps>, id: String) -> Result<String, FlowError> {
+    let receipt = deps.data_store.get_upload_receipt(&id).await?;
+    let height = deps
+        .gateway
+        .network_info()
+        .await
+        .ok()
+        .and_then(|info| info.height.parse::<u64>().ok());
+    let response_json = json!({
+        "receipt": receipt,
+        "network_height": height,
+        "deadline_passed": height.map(|h| h >= receipt.deadline_height),
+    });
+    Ok(response_json.to_string())
+}
+
+/*
+    where a message was assigned, found by its own id or
+    the id of one of its assignments, for a client that
+    does not know the process. The original assignment
+    comes first, those to private processes are left out
+    unless the signed read is for that process
+*/
+pub async fn locate_message(
+    deps: Arc<Deps>,
+    id: String,
+    signed_read: Option<String>,
+) -> Result<String, FlowError> {
+    let mut assignments = vec![];
+    for assignment in deps.data_store.find_assignments(&id).await?.into_iter() {
+        let process = cached_process(&deps, &assignment.process_id).await?;
+        if authorize_read(&deps, &process, &signed_read).is_ok() {
+            assignments.push(assignment);
+        }
+    }
+    if assignments.is_empty() {
+        return Err(FlowError::NotFound("Message not found".to_string()));
+    }
+    let response_json = json!({ "id": id, "assignments": assignments });
+    Ok(response_json.to_string())
+}
+
+/*
+    attestations of a process latest first, each names the
+    arweave tx of the signed record so an auditor can check
+    it was signed by the scheduler of the process
+*/
+pub async fn read_attestations(
+    deps: Arc<Deps>,
+    process_id: String,
+    limit: Option<i32>,
+    signed_read: Option<String>,
+) -> Result<String, FlowError> {
+    authorize_process_read(&deps, &process_id, &signed_read).await?;
+    let limit = limit.unwrap_or(100).clamp(1, 1000) as i64;
+    let attestations = deps.data_store.get_attestations(&process_id, limit).await?;
+    let response_json = json!({ "process_id": process_id, "attestations": attestations });
+    Ok(response_json.to_string())
+}
+
+/*
+    server-sent events for new messages on a process,
+    the process has to exist so a typo does not leave
+    a subscriber waiting forever
+*/
+pub async fn subscribe_process(
+    deps: Arc<Deps>,
+    process_id: String,
+    signed_read: Option<String>,
+) -> Result<mpsc::Receiver<String>, FlowError> {
+    authorize_process_read(&deps, &process_id, &signed_read).await?;
+    deps.feeds.subscribe(process_id)
+}
+
+/*
+    the live event feed for a standby, only served when
+    EVENT_STREAM_TOKEN is set and the caller presents it
+*/
+pub fn event_feed(
+    deps: Arc<Deps>,
+    token: Option<String>,
+) -> Result<mpsc::Receiver<String>, FlowError> {
+    match (deps.config.event_stream_token(), token) {
+        (Some(expected), Some(given))
+            if verify_slices_are_equal(expected.as_bytes(), given.as_bytes()).is_ok() =>
+        {
+            Ok(events::spawn_json_feed(&deps.events, 1024))
+        }
+        (None, _) => Err(FlowError::NotFound(
+            "Event stream is not enabled".to_string(),
+        )),
+        _ => Err(FlowError::Forbidden(
+            "Invalid event stream token".to_string(),
+        )),
+    }
+}
+
+fn system_time() -> Result<String, SystemTimeError> {
     let start_time = SystemTime::now();
     let duration = start_time.duration_since(UNIX_EPOCH)?;
     let millis = duration.as_secs() * 1000 + u64::from(duration.subsec_millis());
@@ -249,7 +2153,7 @@ fn system_time_u64() -> Result<u64, SystemTimeError> {
     Ok(millis)
 }
 
-pub async fn timestamp(deps: Arc<Deps>) -> Result<String, String> {
+pub async fn timestamp(deps: Arc<Deps>) -> Result<String, FlowError> {
     match system_time() {
         Ok(timestamp) => {
             let network_info = deps.gateway.network_info().await;
@@ -261,23 +2165,157 @@ pub async fn timestamp(deps: Arc<Deps>) -> Result<String, String> {
                         json!({ "timestamp": timestamp, "block_height": height_string });
                     Ok(response_json.to_string())
                 }
-                Err(e) => Err(format!("{:?}", e)),
+                Err(e) => Err(FlowError::Upstream(format!("{:?}", e))),
             }
         }
-        Err(e) => Err(format!("{:?}", e)),
+        Err(e) => Err(FlowError::Internal(format!("{:?}", e))),
     }
 }
 
-pub async fn health(deps: Arc<Deps>) -> Result<String, String> {
+// counters an operator can scrape and alert on
+pub fn metrics(deps: Arc<Deps>) -> Result<String, FlowError> {
+    let response_json = json!({
+        "scheduler": deps.scheduler.lock_stats(),
+        "writes": deps.admission.stats(),
+        "process_queues": deps.queues.queued_processes(),
+        "in_flight_writes": deps.in_flight.len(),
+        "subscribers": deps.feeds.subscribers(),
+        "nonce_waiters": deps.nonce_waiters.waiters(),
+        "uploads": deps.confirmations.stats(),
+        "uploads_given_up": deps.uploader.given_up_uploads(),
+        "funding": deps.funding.stats(),
+        "retention": deps.retention.stats(),
+        "cold_storage": deps.tiering.stats(),
+        "crons": deps.crons.stats(),
+        "modules": deps.modules.stats(),
+        "breakers": deps.breakers.stats(),
+    });
+    Ok(response_json.to_string())
+}
+
+/*
+    Called once the process is asked to stop, before the
+    http server goes down. New writes are turned away,
+    admitted writes finish sequencing and the uploads
+    they handed off are given the rest of the timeout so
+    a deploy does not leave sequenced but unuploaded items.
+*/
+pub async fn shutdown(deps: Arc<Deps>) {
+    let timeout = Duration::from_millis(deps.config.shutdown_timeout_ms());
+    let started = Instant::now();
+    deps.logger
+        .log("shutting down, draining in-flight writes".to_string());
+
+    if tokio::time::timeout(timeout, deps.admission.drain())
+        .await
+        .is_err()
+    {
+        deps.logger.error(format!(
+            "{} writes still in flight at shutdown",
+            deps.admission.stats().writes_in_flight
+        ));
+    }
+
+    while deps.uploader.pending_uploads() > 0 && started.elapsed() < timeout {
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    match deps.uploader.pending_uploads() {
+        0 => deps.logger.log("writes drained".to_string()),
+        pending => deps
+            .logger
+            .error(format!("{} uploads still pending at shutdown", pending)),
+    }
+
+    // the events of the drained writes are in the outbox by now
+    if let Some(outbox) = &deps.outbox {
+        let left = timeout.saturating_sub(started.elapsed());
+        if let Err(e) = outbox.flush(left).await {
+            deps.logger
+                .error(format!("event outbox not flushed at shutdown - {}", e));
+        }
+    }
+
+    if let Some(webhooks) = &deps.webhooks {
+        let left = timeout.saturating_sub(started.elapsed());
+        if let Err(e) = webhooks.flush(left).await {
+            deps.logger
+                .error(format!("webhooks not delivered at shutdown - {}", e));
+        }
+    }
+
+    if let Some(leases) = deps.scheduler.leases() {
+        if let Err(e) = leases.release_all(&deps.data_store).await {
+            deps.logger
+                .error(format!("Failed to release process leases: {:?}", e));
+        }
+    }
+}
+
+// how long readiness waits on all dependencies together
+const READINESS_TIMEOUT_MS: u64 = 5000;
+
+fn check_status(result: Result<(), String>) -> serde_json::Value {
+    match result {
+        Ok(()) => json!({ "ok": true }),
+        Err(e) => json!({ "ok": false, "error": e }),
+    }
+}
+
+/*
+    Readiness for load balancers. Everything a write
+    depends on is checked for real, returns whether the
+    su is ready along with the status of each dependency.
+    A su draining for shutdown is not ready.
+*/
+pub async fn readiness(deps: Arc<Deps>) -> (bool, String) {
+    let deadline = Deadline::after_ms(READINESS_TIMEOUT_MS).min(Deadline::current());
+    deadline
+        .scope(async {
+            let store = deps.data_store.ping().await.map_err(|e| format!("{:?}", e));
+            let gateway = deps.gateway.ping().await;
+            let upload_node = deps.uploader.ping().await.map_err(String::from);
+            let signer = match deps.signer.sign_tx(b"su readiness".to_vec()).await {
+                Ok(signature) if !signature.is_empty() => Ok(()),
+                Ok(_) => Err("Signer returned an empty signature".to_string()),
+                Err(e) => Err(e),
+            };
+            let accepting_writes = match deps.admission.is_draining() {
+                true => Err("Shutting down".to_string()),
+                false => Ok(()),
+            };
+
+            let ready = store.is_ok()
+                && gateway.is_ok()
+                && upload_node.is_ok()
+                && signer.is_ok()
+                && accepting_writes.is_ok();
+            // reads keep being served, so a read-only su stays ready
+            let response_json = json!({
+                "ready": ready,
+                "read_only": deps.runtime.read_only(),
+                "checks": {
+                    "store": check_status(store),
+                    "gateway": check_status(gateway),
+                    "upload_node": check_status(upload_node),
+                    "signer": check_status(signer),
+                    "accepting_writes": check_status(accepting_writes),
+                }
+            });
+            (ready, response_json.to_string())
+        })
+        .await
+}
+
+pub async fn health(deps: Arc<Deps>) -> Result<String, FlowError> {
     match system_time() {
         Ok(timestamp) => {
             let wallet_address = match deps.wallet.wallet_address() {
                 Ok(w) => w,
-                Err(e) => return Err(e),
+                Err(e) => return Err(e.into()),
             };
             let response_json = json!({ "timestamp": timestamp, "address": wallet_address });
             Ok(response_json.to_string())
         }
-        Err(e) => Err(format!("{:?}", e)),
+        Err(e) => Err(FlowError::Internal(format!("{:?}", e))),
     }
-}
\ No newline at end of file
+}
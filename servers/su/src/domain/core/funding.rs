@@ -0,0 +1,175 @@
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::Serialize;
+use tokio::time::sleep;
+
+use super::flows::Deps;
+use super::runtime::{self, RuntimeUpdate};
+
+fn unix_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Level {
+    Funded,
+    // under FUNDING_WARN_WINSTON
+    Low,
+    // under FUNDING_STOP_WINSTON
+    Exhausted,
+}
+
+// a threshold of 0 is off
+pub fn level(balance: u64, warn_below: u64, stop_below: u64) -> Level {
+    if balance < stop_below {
+        Level::Exhausted
+    } else if balance < warn_below {
+        Level::Low
+    } else {
+        Level::Funded
+    }
+}
+
+#[derive(Serialize, Clone, Default)]
+pub struct FundingStats {
+    // winston, None until a check succeeded
+    pub balance: Option<u64>,
+    pub checked_at: u64,
+    pub failed_checks: u64,
+    pub low: bool,
+    // the su was put in read-only mode by the monitor
+    pub stopped: bool,
+}
+
+/*
+    Uploads are paid by the su wallet in UPLOAD_MODE direct
+    or by its account on the upload node otherwise. Every
+    FUNDING_CHECK_INTERVAL_MS the balance is checked, under
+    FUNDING_WARN_WINSTON an alert is logged and under
+    FUNDING_STOP_WINSTON the su turns read-only so it stops
+    sequencing before uploads start to fail. Read-only is
+    lifted again once the balance is back above the stop
+    threshold. A su an operator made read-only is left so.
+*/
+pub struct Funding {
+    stats: Mutex<FundingStats>,
+}
+
+impl Default for Funding {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Funding {
+    pub fn new() -> Self {
+        Funding {
+            stats: Mutex::new(FundingStats::default()),
+        }
+    }
+
+    pub fn stats(&self) -> FundingStats {
+        match self.stats.lock() {
+            Ok(stats) => stats.clone(),
+            Err(poisoned) => poisoned.into_inner().clone(),
+        }
+    }
+
+    fn update(&self, change: impl FnOnce(&mut FundingStats)) {
+        if let Ok(mut stats) = self.stats.lock() {
+            change(&mut stats)
+        }
+    }
+}
+
+pub async fn check_funding(deps: &Arc<Deps>) -> Result<(), String> {
+    let address = deps.wallet.wallet_address()?;
+    let balance = match deps.uploader.balance(&address).await {
+        Ok(balance) => balance,
+        Err(e) => {
+            deps.funding.update(|stats| stats.failed_checks += 1);
+            return Err(format!("{:?}", e));
+        }
+    };
+    let warn_below = deps.config.funding_warn_winston();
+    let stop_below = deps.config.funding_stop_winston();
+    let level = level(balance, warn_below, stop_below);
+    let mut stopped = deps.funding.stats().stopped;
+
+    match level {
+        Level::Exhausted => deps.logger.error(format!(
+            "ALERT upload balance of {} is {} winston, under FUNDING_STOP_WINSTON {}",
+            address, balance, stop_below
+        )),
+        Level::Low => deps.logger.error(format!(
+            "ALERT upload balance of {} is {} winston, under FUNDING_WARN_WINSTON {}",
+            address, balance, warn_below
+        )),
+        Level::Funded => (),
+    }
+
+    let stop = level == Level::Exhausted;
+    if stop && !stopped && !deps.runtime.read_only() {
+        set_read_only(deps, true)?;
+        stopped = true;
+        deps.logger
+            .error("su is read-only until the upload balance is topped up".to_string());
+    } else if !stop && stopped {
+        if deps.runtime.read_only() {
+            set_read_only(deps, false)?;
+        }
+        stopped = false;
+        deps.logger
+            .log("upload balance topped up, su is writable again".to_string());
+    } else if stopped && !deps.runtime.read_only() {
+        // lifted by an operator, the next check stops it again
+        stopped = false;
+    }
+
+    deps.funding.update(|stats| {
+        stats.balance = Some(balance);
+        stats.checked_at = unix_ms();
+        stats.low = level != Level::Funded;
+        stats.stopped = stopped;
+    });
+    Ok(())
+}
+
+fn set_read_only(deps: &Deps, read_only: bool) -> Result<(), String> {
+    let update = RuntimeUpdate {
+        read_only: Some(read_only),
+        ..Default::default()
+    };
+    runtime::apply(deps, update)?;
+    Ok(())
+}
+
+pub fn spawn_funding_checks(deps: Arc<Deps>) {
+    let interval = Duration::from_millis(deps.config.funding_check_interval_ms());
+    tokio::spawn(async move {
+        loop {
+            if let Err(e) = check_funding(&deps).await {
+                deps.logger.error(format!("funding check failed - {}", e));
+            }
+            sleep(interval).await;
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_level() {
+        assert_eq!(level(100, 0, 0), Level::Funded);
+        assert_eq!(level(100, 500, 0), Level::Low);
+        assert_eq!(level(100, 500, 200), Level::Exhausted);
+        assert_eq!(level(200, 500, 200), Level::Low);
+        assert_eq!(level(500, 500, 200), Level::Funded);
+    }
+}
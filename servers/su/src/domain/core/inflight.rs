@@ -0,0 +1,127 @@
+use std::sync::Arc;
+
+use dashmap::mapref::entry::Entry;
+use dashmap::DashMap;
+use tokio::sync::watch;
+
+use super::dal::FlowError;
+use super::version::ApiVersion;
+
+// the result of a write and the version it was answered in
+type Outcome = Option<(Result<String, FlowError>, ApiVersion)>;
+
+/*
+    Writes of a data item that are still being sequenced,
+    by id. When two MUs race to push the same signed item
+    the first leads the write and the other waits for it,
+    so the item is sequenced once and both get its result.
+*/
+#[derive(Default)]
+pub struct InFlightWrites {
+    writes: Arc<DashMap<String, watch::Receiver<Outcome>>>,
+}
+
+pub enum Joined {
+    Leader(WriteLeader),
+    Follower(watch::Receiver<Outcome>),
+}
+
+impl InFlightWrites {
+    pub fn new() -> Self {
+        InFlightWrites {
+            writes: Arc::new(DashMap::new()),
+        }
+    }
+
+    pub fn join(&self, id: &str) -> Joined {
+        match self.writes.entry(id.to_string()) {
+            Entry::Occupied(entry) => Joined::Follower(entry.get().clone()),
+            Entry::Vacant(entry) => {
+                let (sender, receiver) = watch::channel(None);
+                entry.insert(receiver);
+                Joined::Leader(WriteLeader {
+                    id: id.to_string(),
+                    sender,
+                    writes: self.writes.clone(),
+                })
+            }
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.writes.len()
+    }
+}
+
+/*
+    Held by the write that sequences the item, on a task of
+    its own so it outlives the request that started it. It
+    leaves the map when dropped, a leader dropped without a
+    result (its write panicked) lets a follower take over.
+*/
+pub struct WriteLeader {
+    id: String,
+    sender: watch::Sender<Outcome>,
+    writes: Arc<DashMap<String, watch::Receiver<Outcome>>>,
+}
+
+impl WriteLeader {
+    pub fn finish(self, result: &Result<String, FlowError>, version: ApiVersion) {
+        self.sender.send_replace(Some((result.clone(), version)));
+    }
+}
+
+impl Drop for WriteLeader {
+    fn drop(&mut self) {
+        self.writes.remove(&self.id);
+    }
+}
+
+// None when the leader went away without a result
+pub async fn wait(mut follower: watch::Receiver<Outcome>) -> Outcome {
+    loop {
+        if let Some(outcome) = follower.borrow_and_update().clone() {
+            return Some(outcome);
+        }
+        if follower.changed().await.is_err() {
+            return follower.borrow().clone();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_in_flight_writes() {
+        let writes = InFlightWrites::new();
+        let leader = match writes.join("a") {
+            Joined::Leader(leader) => leader,
+            Joined::Follower(_) => panic!("the first write leads"),
+        };
+        let follower = match writes.join("a") {
+            Joined::Follower(follower) => follower,
+            Joined::Leader(_) => panic!("a second write follows"),
+        };
+        assert!(matches!(writes.join("b"), Joined::Leader(_)));
+
+        let waiting = tokio::spawn(wait(follower));
+        leader.finish(&Ok("result".to_string()), ApiVersion::V1);
+        assert_eq!(
+            waiting.await.unwrap(),
+            Some((Ok("result".to_string()), ApiVersion::V1))
+        );
+        assert_eq!(writes.len(), 0);
+
+        // a cancelled leader hands the write to whoever waits
+        let leader = writes.join("a");
+        let follower = match writes.join("a") {
+            Joined::Follower(follower) => follower,
+            Joined::Leader(_) => panic!("a second write follows"),
+        };
+        drop(leader);
+        assert_eq!(wait(follower).await, None);
+        assert!(matches!(writes.join("a"), Joined::Leader(_)));
+    }
+}
@@ -0,0 +1,161 @@
+use std::sync::Arc;
+
+use tokio::sync::mpsc::error::TrySendError;
+use tokio::sync::{mpsc, oneshot, Mutex};
+use tokio::task::JoinError;
+
+use super::body::Body;
+use super::bytes::DataItem;
+use super::dal::FlowError;
+
+/*
+    Parsing incoming data items is cpu bound work on
+    potentially very large bodies. IngestPool moves it off
+    of the http workers onto a fixed number of workers fed
+    by a bounded queue. When the queue is full new bodies
+    are rejected right away instead of piling up.
+*/
+
+struct IngestJob {
+    input: Body,
+    // the signature is checked on the worker too
+    verify: bool,
+    respond_to: oneshot::Sender<Result<DataItem, FlowError>>,
+}
+
+pub struct IngestPool {
+    sender: mpsc::Sender<IngestJob>,
+}
+
+impl IngestPool {
+    pub fn new(queue_depth: usize, workers: usize) -> Self {
+        let (sender, receiver) = mpsc::channel::<IngestJob>(queue_depth.max(1));
+        let receiver = Arc::new(Mutex::new(receiver));
+
+        for _ in 0..workers.max(1) {
+            let receiver = receiver.clone();
+            tokio::spawn(async move {
+                loop {
+                    // only the worker holding the lock waits on the queue
+                    let job = receiver.lock().await.recv().await;
+                    let IngestJob {
+                        input,
+                        verify,
+                        respond_to,
+                    } = match job {
+                        Some(j) => j,
+                        None => break,
+                    };
+
+                    let parsed = tokio::task::spawn_blocking(move || {
+                        let item = input.parse()?;
+                        if verify {
+                            item.verify_signature()
+                                .map_err(|e| format!("invalid signature {:?}", e))?;
+                        }
+                        Ok::<DataItem, String>(item)
+                    })
+                    .await;
+
+                    // the requester may have gone away, nothing to do then
+                    let _ = respond_to.send(job_result(parsed));
+                }
+            });
+        }
+
+        IngestPool { sender }
+    }
+
+    pub async fn parse(&self, input: impl Into<Body>) -> Result<DataItem, FlowError> {
+        self.submit(input.into(), false).await
+    }
+
+    // only items whose signature holds, their owner is who signed them
+    pub async fn parse_verified(&self, input: impl Into<Body>) -> Result<DataItem, FlowError> {
+        self.submit(input.into(), true).await
+    }
+
+    async fn submit(&self, input: Body, verify: bool) -> Result<DataItem, FlowError> {
+        let (respond_to, response) = oneshot::channel();
+
+        match self.sender.try_send(IngestJob {
+            input,
+            verify,
+            respond_to,
+        }) {
+            Ok(_) => (),
+            Err(TrySendError::Full(_)) => {
+                return Err(FlowError::Unavailable(
+                    "Ingest queue is full, try again later".to_string(),
+                ))
+            }
+            Err(TrySendError::Closed(_)) => {
+                return Err(FlowError::Internal("Ingest queue is closed".to_string()))
+            }
+        }
+
+        match response.await {
+            Ok(parsed) => parsed,
+            Err(e) => Err(FlowError::Internal(format!(
+                "ingest worker dropped request: {:?}",
+                e
+            ))),
+        }
+    }
+}
+
+// a body that does not parse is the client's fault, a panic while parsing is not
+fn job_result(parsed: Result<Result<DataItem, String>, JoinError>) -> Result<DataItem, FlowError> {
+    match parsed {
+        Ok(Ok(item)) => Ok(item),
+        Ok(Err(e)) => Err(FlowError::Validation(format!(
+            "error parsing data item: {}",
+            e
+        ))),
+        Err(e) => Err(FlowError::Internal(format!("ingest worker error: {:?}", e))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // an arweave signed item whose signature does not hold
+    fn badly_signed() -> Vec<u8> {
+        let mut item = DataItem::new(vec![], b"hello".to_vec(), vec![], vec![0xab; 512])
+            .expect("failed to build data item");
+        item.signature = vec![1; 512];
+        item.as_bytes().expect("failed to serialize")
+    }
+
+    #[tokio::test]
+    async fn test_full_queue_is_rejected() {
+        let pool = IngestPool::new(1, 1);
+        // the worker has not taken the first body when the second arrives
+        let (first, second) = tokio::join!(pool.parse(badly_signed()), pool.parse(badly_signed()));
+        assert!(first.is_ok());
+        assert!(matches!(second, Err(FlowError::Unavailable(_))));
+    }
+
+    #[tokio::test]
+    async fn test_bad_signature_is_a_validation_error() {
+        let pool = IngestPool::new(4, 1);
+        assert!(pool.parse(badly_signed()).await.is_ok());
+        assert!(matches!(
+            pool.parse_verified(badly_signed()).await,
+            Err(FlowError::Validation(_))
+        ));
+        assert!(matches!(
+            pool.parse(vec![1, 0, 7]).await,
+            Err(FlowError::Validation(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_panic_is_an_internal_error() {
+        let panicked =
+            tokio::task::spawn_blocking(|| -> Result<DataItem, String> { panic!("parser bug") })
+                .await;
+        assert!(matches!(job_result(panicked), Err(FlowError::Internal(_))));
+    }
+}
@@ -1,7 +1,7 @@
 use serde::{Deserialize, Serialize};
-use sha2::{Digest, Sha256};
 
 use super::bytes::{ByteErrorType, DataBundle, DataItem};
+use super::version::ApiVersion;
 use bundlr_sdk::tags::*;
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -96,6 +96,18 @@ pub struct PaginatedMessages {
     pub edges: Vec<Edge>,
 }
 
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PaginatedProcesses {
+    pub page_info: PageInfo,
+    pub edges: Vec<ProcessEdge>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ProcessEdge {
+    pub node: Process,
+    pub cursor: String,
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct PageInfo {
     pub has_next_page: bool,
@@ -107,11 +119,153 @@ pub struct Edge {
     pub cursor: String,
 }
 
-pub fn hash(data: &[u8]) -> Vec<u8> {
-    let mut hasher = Sha256::new();
-    hasher.update(data);
-    let result = hasher.finalize();
-    result.to_vec()
+/*
+    a name:value pair that a message must carry
+    in its tags to be returned from a read
+*/
+#[derive(Debug, Clone, PartialEq)]
+pub struct TagFilter {
+    pub name: String,
+    pub value: String,
+}
+
+impl TagFilter {
+    /*
+        parse the tag query param, a comma separated
+        list of name:value pairs ex. Action:Transfer
+    */
+    pub fn from_query(tag_query: &Option<String>) -> Result<Vec<TagFilter>, JsonErrorType> {
+        let mut filters = vec![];
+        if let Some(csv) = tag_query {
+            for pair in csv.split(',').filter(|p| !p.is_empty()) {
+                match pair.split_once(':') {
+                    Some((name, value)) if !name.is_empty() => filters.push(TagFilter {
+                        name: name.to_string(),
+                        value: value.to_string(),
+                    }),
+                    _ => {
+                        return Err(JsonErrorType::JsonError(format!(
+                            "Invalid tag filter {}, expected name:value",
+                            pair
+                        )))
+                    }
+                }
+            }
+        }
+        Ok(filters)
+    }
+}
+
+// order messages are returned in, by timestamp
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SortOrder {
+    Asc,
+    Desc,
+}
+
+impl SortOrder {
+    pub fn from_query(sort_query: &Option<String>) -> Result<SortOrder, JsonErrorType> {
+        match sort_query.as_deref() {
+            None | Some("asc") => Ok(SortOrder::Asc),
+            Some("desc") => Ok(SortOrder::Desc),
+            Some(other) => Err(JsonErrorType::JsonError(format!(
+                "Invalid sort {}, expected asc or desc",
+                other
+            ))),
+        }
+    }
+}
+
+/*
+    the page of a process's messages a read asks for,
+    from and to are the timestamp cursors of the page
+*/
+#[derive(Debug, Clone, PartialEq)]
+pub struct MessageQuery {
+    pub from: Option<String>,
+    pub to: Option<String>,
+    pub limit: Option<i32>,
+    pub tags: Vec<TagFilter>,
+    pub from_timestamp: Option<i64>,
+    pub to_timestamp: Option<i64>,
+    pub sort: SortOrder,
+}
+
+/*
+    returned from a write, the slot the item was
+    sequenced into so the client does not need to read
+    it back. bundle is the id of the bundle handed to
+    the upload node, the upload finishes in the background
+*/
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct WriteResult {
+    pub id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub process_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub assignment: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub epoch: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub nonce: Option<i32>,
+    pub timestamp: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hash_chain: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub block_height: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bundle: Option<String>,
+    // the results of the items of a bundle
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub items: Vec<WriteResult>,
+}
+
+impl WriteResult {
+    pub fn from_message(message: &Message) -> Result<Self, JsonErrorType> {
+        Ok(WriteResult {
+            id: message.message_id()?,
+            process_id: Some(message.process_id()?),
+            assignment: Some(message.assignment_id()?),
+            epoch: Some(message.epoch()?),
+            nonce: Some(message.nonce()?),
+            timestamp: message.timestamp()?,
+            hash_chain: Some(message.hash_chain()?),
+            block_height: Some(message.block_height()?),
+            ..Default::default()
+        })
+    }
+
+    pub fn from_process(process: &Process) -> Self {
+        WriteResult {
+            id: process.process_id.clone(),
+            process_id: Some(process.process_id.clone()),
+            timestamp: process.timestamp,
+            block_height: Some(process.block.clone()),
+            ..Default::default()
+        }
+    }
+
+    pub fn with_bundle(self, bundle: String) -> Self {
+        WriteResult {
+            bundle: Some(bundle),
+            ..self
+        }
+    }
+
+    // version 1 clients only know the id and timestamp
+    pub fn to_json(&self, version: ApiVersion) -> Result<String, JsonErrorType> {
+        match version {
+            ApiVersion::V1 => {
+                let mut legacy = serde_json::json!({ "timestamp": self.timestamp, "id": self.id });
+                if !self.items.is_empty() {
+                    let ids: Vec<&String> = self.items.iter().map(|item| &item.id).collect();
+                    legacy["items"] = serde_json::json!(ids);
+                }
+                Ok(legacy.to_string())
+            }
+            ApiVersion::V2 => Ok(serde_json::to_string(self)?),
+        }
+    }
 }
 
 impl Process {
@@ -123,9 +277,7 @@ impl Process {
         let data = data_bundle.items[0].data().clone();
         let anchor = data_bundle.items[0].anchor().clone();
 
-        let owner_bytes = base64_url::decode(&owner)?;
-        let address_hash = hash(&owner_bytes);
-        let address = base64_url::encode(&address_hash);
+        let address = data_bundle.items[0].owner_address();
 
         let bundle_tags = data_bundle.tags.clone();
 
@@ -143,7 +295,7 @@ impl Process {
         let timestamp = timestamp_tag.value.clone().parse::<i64>()?;
 
         let owner = Owner {
-            address: address,
+            address,
             key: owner,
         };
 
@@ -155,13 +307,13 @@ impl Process {
 
         Ok(Process {
             process_id: id,
-            block: block,
-            timestamp: timestamp,
-            owner: owner,
-            tags: tags,
+            block,
+            timestamp,
+            owner,
+            tags: tags.to_vec(),
             signature: Some(signature),
             anchor: anchor_r,
-            data: data,
+            data,
         })
     }
 }
@@ -169,7 +321,7 @@ impl Process {
 impl Message {
     pub fn from_bundle(data_bundle: &DataBundle) -> Result<Self, JsonErrorType> {
         let id = data_bundle.items[0].id().clone();
-        let tags = data_bundle.items[0].tags();
+        let tags = data_bundle.items[0].tags().to_vec();
         let owner = data_bundle.items[0].owner().clone();
         let target = data_bundle.items[0].target().clone();
         let signature = data_bundle.items[0].signature().clone();
@@ -181,12 +333,10 @@ impl Message {
             _ => Some(ac),
         };
 
-        let owner_bytes = base64_url::decode(&owner)?;
-        let address_hash = hash(&owner_bytes);
-        let address = base64_url::encode(&address_hash);
+        let address = data_bundle.items[0].owner_address();
 
         let owner = Owner {
-            address: address,
+            address,
             key: owner,
         };
 
@@ -205,7 +355,7 @@ impl Message {
             // bundle contains a message and an assignment
             2 => {
                 let id = data_bundle.items[1].id().clone();
-                let tags = data_bundle.items[1].tags();
+                let tags = data_bundle.items[1].tags().to_vec();
                 let owner = data_bundle.items[1].owner().clone();
                 let target = data_bundle.items[1].target().clone();
                 let signature = data_bundle.items[1].signature().clone();
@@ -218,12 +368,10 @@ impl Message {
                     _ => Some(ac),
                 };
 
-                let owner_bytes = base64_url::decode(&owner)?;
-                let address_hash = hash(&owner_bytes);
-                let address = base64_url::encode(&address_hash);
+                let address = data_bundle.items[1].owner_address();
 
                 let owner = Owner {
-                    address: address,
+                    address,
                     key: owner,
                 };
 
@@ -345,10 +493,10 @@ impl Message {
                     old message structure so we have to break
                     down the json by field
                 */
-                let old_message = extract_val(&value, "message")?;
-                let message_owner = serde_json::from_value(extract_val(&value, "owner")?)?;
-                let message_data = extract_option_str(&value, "data");
-                let message_target = extract_option_str(&value, "process_id");
+                let old_message = extract_val(value, "message")?;
+                let message_owner = serde_json::from_value(extract_val(value, "owner")?)?;
+                let message_data = extract_option_str(value, "data");
+                let message_target = extract_option_str(value, "process_id");
 
                 let message_id = str_val(&extract_val(&old_message, "id")?)?;
                 let message_tags = to_tags(&extract_val(&old_message, "tags")?)?;
@@ -369,9 +517,7 @@ impl Message {
                 let bundle_data_item = DataItem::from_bytes(bundle)?;
 
                 let owner = bundle_data_item.owner();
-                let owner_bytes = base64_url::decode(&owner)?;
-                let address_hash = hash(&owner_bytes);
-                let address = base64_url::encode(&address_hash);
+                let address = bundle_data_item.owner_address();
 
                 let anchor = match bundle_data_item.anchor().is_empty() {
                     true => None,
@@ -386,10 +532,10 @@ impl Message {
                 let assignment: AssignmentInner = AssignmentInner {
                     id: bundle_data_item.id(),
                     owner: Owner {
-                        address: address,
+                        address,
                         key: owner,
                     },
-                    tags: bundle_data_item.tags(),
+                    tags: bundle_data_item.tags().to_vec(),
                     signature: bundle_data_item.signature(),
                     anchor,
                     target,
@@ -433,7 +579,7 @@ fn to_tags(val: &serde_json::Value) -> Result<Vec<Tag>, JsonErrorType> {
         for tag in tags_array {
             if let (Some(name), Some(value)) = (tag.get("name"), tag.get("value")) {
                 if let (Some(name_str), Some(value_str)) = (name.as_str(), value.as_str()) {
-                    tags.push(Tag::new(&name_str, &value_str));
+                    tags.push(Tag::new(name_str, value_str));
                 }
             }
         }
@@ -468,6 +614,37 @@ impl PaginatedMessages {
 
         Ok(PaginatedMessages { page_info, edges })
     }
+
+    // for listings whose cursor is not the message timestamp
+    pub fn from_cursored_messages(messages: Vec<(Message, String)>, has_next_page: bool) -> Self {
+        let page_info = PageInfo { has_next_page };
+        let edges = messages
+            .into_iter()
+            .map(|(message, cursor)| Edge {
+                node: message,
+                cursor,
+            })
+            .collect();
+        PaginatedMessages { page_info, edges }
+    }
+}
+
+impl PaginatedProcesses {
+    /*
+        processes paired with the cursor to continue
+        from, which is their position in the store
+    */
+    pub fn from_processes(processes: Vec<(Process, String)>, has_next_page: bool) -> Self {
+        let page_info = PageInfo { has_next_page };
+        let edges = processes
+            .into_iter()
+            .map(|(process, cursor)| ProcessEdge {
+                node: process,
+                cursor,
+            })
+            .collect();
+        PaginatedProcesses { page_info, edges }
+    }
 }
 
 #[cfg(test)]
@@ -513,6 +690,58 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_write_result_from_message() {
+        let item_bytes = base64_url::decode(ITEM_STR).expect("failed to encode data item");
+        let assignment_item_bytes =
+            base64_url::decode(ASSIGNMENT_ITEM_STR).expect("failed to encode data item");
+        let mut data_bundle = DataBundle::new(vec![]);
+        data_bundle.add_item(DataItem::from_bytes(assignment_item_bytes).unwrap());
+        data_bundle.add_item(DataItem::from_bytes(item_bytes).unwrap());
+        let message = Message::from_bundle(&data_bundle).expect("failed to create message");
+
+        let result = WriteResult::from_message(&message).unwrap();
+        assert_eq!(result.id, "6oYAxVAnH8yKsZKpMgHSbRv7uVWey68PAqYuSXeZBbg");
+        assert_eq!(result.epoch, Some(0));
+        assert_eq!(result.nonce, Some(1));
+        assert_eq!(result.timestamp, 1711676638471);
+
+        let result = result.with_bundle("b".to_string());
+        let json: serde_json::Value =
+            serde_json::from_str(&result.to_json(ApiVersion::V2).unwrap()).unwrap();
+        assert_eq!(json["bundle"], "b");
+        assert_eq!(json["block_height"], "000001393008");
+        assert!(json.get("items").is_none());
+
+        let legacy: serde_json::Value =
+            serde_json::from_str(&result.to_json(ApiVersion::V1).unwrap()).unwrap();
+        assert_eq!(
+            legacy,
+            serde_json::json!({ "timestamp": 1711676638471i64, "id": result.id })
+        );
+    }
+
+    #[test]
+    fn test_tag_filter_from_query() {
+        let filters = TagFilter::from_query(&Some("Action:Transfer,Ref:a:b".to_string()))
+            .expect("failed to parse tag filters");
+        assert_eq!(
+            filters,
+            vec![
+                TagFilter {
+                    name: "Action".to_string(),
+                    value: "Transfer".to_string()
+                },
+                TagFilter {
+                    name: "Ref".to_string(),
+                    value: "a:b".to_string()
+                },
+            ]
+        );
+        assert!(TagFilter::from_query(&None).unwrap().is_empty());
+        assert!(TagFilter::from_query(&Some("Action".to_string())).is_err());
+    }
+
     #[test]
     fn test_process_from_bundle() {
         let d_item_string = PROCESS_ITEM_STR.to_string();
@@ -0,0 +1,130 @@
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use dashmap::DashMap;
+
+use super::dal::{DataStore, StoreErrorType};
+
+/*
+    the lease a bulk load holds a process under, followed
+    by its pid. It is not an url, a write to the process
+    is turned away instead of redirected
+*/
+pub const BULK_LOAD_HOLDER: &str = "bulk-load:";
+
+pub enum Lease {
+    /*
+        held by this instance, fresh when it was not held
+        a moment ago and another instance may have written
+        to the process since, so its schedule is read again
+    */
+    Held { fresh: bool },
+    // held by the instance at this url
+    Elsewhere(String),
+}
+
+/*
+    CLUSTER_NODE_URL, several su instances sharing one
+    database. A process is sequenced by the instance that
+    holds its lease, a row in process_leases that lasts
+    PROCESS_LEASE_MS and is renewed by the writes of its
+    holder once half of it is gone. A lease that ran out
+    is taken by the next instance writing to the process,
+    so when its holder dies the process is writable again
+    after at most PROCESS_LEASE_MS. The store checks in the
+    transaction saving a message that no other instance
+    holds the lease, a holder that stalled past its lease
+    cannot fork the schedule.
+*/
+pub struct Leases {
+    node_url: String,
+    ttl: Duration,
+    // the leases held and until when, by this instance's clock
+    held: DashMap<String, Instant>,
+    last_sweep: std::sync::Mutex<Instant>,
+}
+
+impl Leases {
+    pub fn new(node_url: String, ttl_ms: u64) -> Self {
+        Leases {
+            node_url,
+            ttl: Duration::from_millis(ttl_ms.max(1)),
+            held: DashMap::new(),
+            last_sweep: std::sync::Mutex::new(Instant::now()),
+        }
+    }
+
+    pub fn node_url(&self) -> &str {
+        &self.node_url
+    }
+
+    /*
+        take or renew the lease of a process. The local
+        expiry is counted from before the store was asked,
+        so it runs out ahead of the one in the store
+    */
+    pub async fn hold(
+        &self,
+        data_store: &Arc<dyn DataStore>,
+        process_id: &str,
+    ) -> Result<Lease, StoreErrorType> {
+        self.sweep();
+        let now = Instant::now();
+        let until = self.held.get(process_id).map(|u| *u);
+        if until.is_some_and(|u| u > now + self.ttl / 2) {
+            return Ok(Lease::Held { fresh: false });
+        }
+
+        let holder = data_store
+            .acquire_lease(process_id, &self.node_url, self.ttl.as_millis() as u64)
+            .await?;
+        if holder != self.node_url {
+            self.held.remove(process_id);
+            return Ok(Lease::Elsewhere(holder));
+        }
+        self.held.insert(process_id.to_string(), now + self.ttl);
+        Ok(Lease::Held {
+            fresh: until.map_or(true, |u| u <= now),
+        })
+    }
+
+    // the url of another instance holding the lease, without taking it
+    pub async fn leader(
+        &self,
+        data_store: &Arc<dyn DataStore>,
+        process_id: &str,
+    ) -> Result<Option<String>, StoreErrorType> {
+        let now = Instant::now();
+        if self.held.get(process_id).is_some_and(|u| *u > now) {
+            return Ok(None);
+        }
+        match data_store.get_lease_holder(process_id).await? {
+            Some(holder) if holder.starts_with(BULK_LOAD_HOLDER) => Ok(None),
+            Some(holder) if holder != self.node_url => Ok(Some(holder)),
+            _ => Ok(None),
+        }
+    }
+
+    // on shutdown, the other instances take over right away
+    pub async fn release_all(&self, data_store: &Arc<dyn DataStore>) -> Result<(), StoreErrorType> {
+        self.held.clear();
+        data_store.release_leases(&self.node_url).await
+    }
+
+    pub fn held(&self) -> usize {
+        let now = Instant::now();
+        self.held.iter().filter(|u| *u.value() > now).count()
+    }
+
+    // forgets leases that ran out, at most once per lease
+    fn sweep(&self) {
+        match self.last_sweep.try_lock() {
+            Ok(mut last_sweep) if last_sweep.elapsed() >= self.ttl => {
+                *last_sweep = Instant::now();
+            }
+            _ => return,
+        }
+        let now = Instant::now();
+        self.held.retain(|_, until| *until > now);
+    }
+}
@@ -0,0 +1,64 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::time::sleep;
+
+use super::flows::{init_builder_for, Deps};
+
+// wait before trying a failed publish again
+const RETRY_DELAY: Duration = Duration::from_secs(60);
+
+/*
+    Signs and uploads a Scheduler-Location record for
+    SCHEDULER_LOCATION_URL so the su announces itself
+    without a separate tool, one per hosted scheduler.
+    Returns the ids of the records.
+*/
+pub async fn publish_location(deps: &Arc<Deps>) -> Result<Vec<String>, String> {
+    let url = match deps.config.scheduler_location_url() {
+        Some(url) => url,
+        None => return Err("SCHEDULER_LOCATION_URL is not set".to_string()),
+    };
+
+    let mut ids = vec![];
+    for scheduler in deps.tenants.addresses().iter() {
+        let builder = init_builder_for(deps, scheduler)?;
+        let location = builder
+            .build_scheduler_location(&url, deps.config.scheduler_location_ttl_ms())
+            .await
+            .map_err(String::from)?;
+        let binary = location.as_bytes().map_err(|e| format!("{:?}", e))?;
+        deps.uploader.upload(binary.into())?;
+
+        deps.logger.log(format!(
+            "published scheduler location {} of {} for {}",
+            location.id(),
+            scheduler,
+            url
+        ));
+        ids.push(location.id());
+    }
+    Ok(ids)
+}
+
+/*
+    Publishes the record on startup and again every half
+    of its time to live, at most once a minute, so it is
+    refreshed well before clients consider it expired.
+*/
+pub fn spawn_location_refresh(deps: Arc<Deps>) {
+    let refresh =
+        Duration::from_millis(deps.config.scheduler_location_ttl_ms() / 2).max(RETRY_DELAY);
+    tokio::spawn(async move {
+        loop {
+            match publish_location(&deps).await {
+                Ok(_) => sleep(refresh).await,
+                Err(e) => {
+                    deps.logger
+                        .error(format!("scheduler location publish failed - {}", e));
+                    sleep(RETRY_DELAY).await;
+                }
+            }
+        }
+    });
+}
@@ -1,4 +1,5 @@
-mod bytes;
+// data item and bundle encoding
+pub mod bytes;
 // main tx building logic
 mod builder;
 // build json from raw data
@@ -7,11 +8,138 @@ mod json;
 // traits for injecting dependencies
 pub mod dal;
 
+// in process event bus
+pub mod events;
+
+// relays the store's event outbox to kafka or nats
+pub mod outbox;
+
+// bounded worker pool for parsing incoming items
+pub mod ingest;
+
+// write bodies read and hashed as they arrive
+pub mod body;
+
+// bounds how many writes run at once
+pub mod admission;
+
+// fails calls to an upstream fast while it keeps failing
+pub mod breaker;
+
+// checks that accepted uploads land on arweave
+pub mod confirmations;
+
+// arweave transactions for bundles posted without an upload node
+pub mod transaction;
+
+// watches the balance that pays for uploads
+pub mod funding;
+
+// holds writes while the store fails over
+pub mod failover;
+
 // mutex locked scheduling data
 pub mod scheduler;
 
+// which su instance of a cluster sequences each process
+pub mod leases;
+
+// ordered write queue and sequencing task per process
+pub mod sequencer;
+
 // main business logic
 pub mod flows;
 
+// dry run results of the checks a write makes
+pub mod validation;
+
+// real client addresses behind trusted proxies
+pub mod proxies;
+
+// read cache kept warm from the event stream
+pub mod cache;
+
+// the scheduler wallets hosted by this su
+pub mod tenants;
+
+// scoped api tokens for restricted writes
+pub mod tokens;
+
+// private processes and signed reads
+pub mod access;
+
+// signed receipts for read responses
+pub mod receipts;
+
+// CU checkpoints registered per process
+pub mod checkpoints;
+
+// several processes read in one request
+pub mod bulk;
+
+// the su's assignments, kept apart from the messages
+pub mod assignments;
+
+// su bulk-load, a new process sequenced from a directory of items
+pub mod bulkload;
+
+// signed merkle roots of the schedule of a process
+pub mod attestations;
+
+// prunes bundles already on arweave
+pub mod retention;
+
+// moves old message history to compressed segments
+pub mod tiering;
+
+// messages processes asked for with Cron-Interval tags
+pub mod cron;
+
+// per owner token buckets for writes
+pub mod ratelimit;
+
+// settings that can change without a restart
+pub mod runtime;
+
+// which owners and modules may spawn processes
+pub mod policy;
+
+// checks the Module of a spawn against the gateway
+pub mod modules;
+
+// writes of the same data item that arrive together
+pub mod inflight;
+
+// how long the client of a request is still waiting
+pub mod deadline;
+
+// response shapes a client can ask for
+pub mod version;
+
+// announces this su with a Scheduler-Location record
+pub mod location;
+
+// rebuilds a process from what was uploaded to arweave
+pub mod backfill;
+
 // router logic
 pub mod router;
+
+// process to scheduler urls the router has looked up
+pub mod routes;
+
+// stats a su reports and the router collects from its schedulers
+pub mod fleet;
+
+// messages sequenced per minute
+pub mod throughput;
+
+// consistent hashing of processes onto schedulers
+pub mod ring;
+
+// maps scheduler urls to redirect targets
+pub mod resolver;
+
+// stand-ins for the store and gateway in unit tests
+#[cfg(test)]
+pub mod testing;
@@ -0,0 +1,181 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use bundlr_sdk::tags::Tag;
+use dashmap::DashMap;
+use serde::Serialize;
+use serde_json::json;
+
+use super::dal::{Config, FlowError, Gateway};
+
+const MODULE_QUERY: &str = r#"
+query($ids: [ID!]) {
+  transactions(ids: $ids) {
+    edges { node { id tags { name value } } }
+  }
+}"#;
+
+// what an ao module carries when MODULE_REQUIRED_TAGS is not set
+const DEFAULT_REQUIRED_TAGS: [&str; 3] = ["Data-Protocol:ao", "Type:Module", "Module-Format"];
+
+#[derive(Serialize)]
+pub struct ModuleStats {
+    pub cached: usize,
+    pub hits: u64,
+    pub misses: u64,
+}
+
+/*
+    With MODULE_VALIDATION a spawn is only accepted if its
+    Module tag names a transaction the gateway knows, with
+    the tags MODULE_REQUIRED_TAGS lists. Each is a tag name
+    or name:value. Module headers never change so the tags
+    of a module are kept once fetched, a module the gateway
+    does not have yet is looked up again next time.
+*/
+pub struct Modules {
+    enabled: bool,
+    required: Vec<(String, Option<String>)>,
+    tags: DashMap<String, Vec<Tag>>,
+    max_entries: usize,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl Modules {
+    pub fn new(config: &dyn Config) -> Self {
+        let mut required = config.module_required_tags();
+        if required.is_empty() {
+            required = DEFAULT_REQUIRED_TAGS
+                .iter()
+                .map(|t| t.to_string())
+                .collect();
+        }
+        Modules {
+            enabled: config.module_validation(),
+            required: required
+                .iter()
+                .map(|tag| match tag.split_once(':') {
+                    Some((name, value)) => (name.to_string(), Some(value.to_string())),
+                    None => (tag.to_string(), None),
+                })
+                .collect(),
+            tags: DashMap::new(),
+            max_entries: config.module_cache_max_entries(),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    pub fn stats(&self) -> ModuleStats {
+        ModuleStats {
+            cached: self.tags.len(),
+            hits: self.hits.load(Ordering::SeqCst),
+            misses: self.misses.load(Ordering::SeqCst),
+        }
+    }
+
+    pub async fn check(&self, gateway: &dyn Gateway, module: &str) -> Result<(), FlowError> {
+        if !self.enabled {
+            return Ok(());
+        }
+        let tags = match self.tags.get(module) {
+            Some(tags) => {
+                self.hits.fetch_add(1, Ordering::SeqCst);
+                tags.clone()
+            }
+            None => {
+                self.misses.fetch_add(1, Ordering::SeqCst);
+                let tags = fetch_tags(gateway, module).await?;
+                if self.tags.len() < self.max_entries {
+                    self.tags.insert(module.to_string(), tags.clone());
+                }
+                tags
+            }
+        };
+        self.check_tags(module, &tags)
+            .map_err(FlowError::Validation)
+    }
+
+    fn check_tags(&self, module: &str, tags: &[Tag]) -> Result<(), String> {
+        for (name, value) in self.required.iter() {
+            let found = tags
+                .iter()
+                .any(|tag| &tag.name == name && value.as_ref().map_or(true, |v| &tag.value == v));
+            if !found {
+                return Err(match value {
+                    Some(value) => format!("Module {} is not tagged {}: {}", module, name, value),
+                    None => format!("Module {} has no {} tag", module, name),
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+async fn fetch_tags(gateway: &dyn Gateway, module: &str) -> Result<Vec<Tag>, FlowError> {
+    let data = gateway
+        .graphql(MODULE_QUERY, json!({ "ids": [module] }))
+        .await
+        .map_err(FlowError::Upstream)?;
+    let node = match data["transactions"]["edges"]
+        .as_array()
+        .and_then(|e| e.first())
+    {
+        Some(edge) => edge["node"].clone(),
+        None => {
+            return Err(FlowError::Validation(format!(
+                "Module {} not found on the gateway",
+                module
+            )))
+        }
+    };
+    Ok(node["tags"]
+        .as_array()
+        .map(|tags| {
+            tags.iter()
+                .map(|tag| {
+                    Tag::new(
+                        tag["name"].as_str().unwrap_or_default(),
+                        tag["value"].as_str().unwrap_or_default(),
+                    )
+                })
+                .collect()
+        })
+        .unwrap_or_default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tag(name: &str, value: &str) -> Tag {
+        Tag::new(name, value)
+    }
+
+    #[test]
+    fn test_check_tags() {
+        let modules = Modules {
+            enabled: true,
+            required: vec![
+                ("Type".to_string(), Some("Module".to_string())),
+                ("Module-Format".to_string(), None),
+            ],
+            tags: DashMap::new(),
+            max_entries: 10,
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        };
+        let tags = vec![
+            tag("Type", "Module"),
+            tag(
+                "Module-Format",
+                "wasm64-unknown-emscripten-draft_2024_02_15",
+            ),
+        ];
+        assert!(modules.check_tags("m", &tags).is_ok());
+        assert!(modules.check_tags("m", &tags[..1]).is_err());
+        assert!(modules
+            .check_tags("m", &[tag("Type", "Process"), tags[1].clone()])
+            .is_err());
+    }
+}
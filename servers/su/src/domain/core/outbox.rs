@@ -0,0 +1,120 @@
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use tokio::time::sleep;
+
+use super::dal::{DataStore, EventPublisher, Log};
+
+// events read from the outbox per publish
+const BATCH_SIZE: i64 = 500;
+const IDLE_POLL: Duration = Duration::from_millis(200);
+const MAX_BACKOFF_SECS: u64 = 30;
+
+/*
+    Relays the store's event outbox to the publisher.
+    Events are only deleted after the publisher confirmed
+    them, a failed or interrupted publish sends the batch
+    again, so delivery is at least once and in outbox order.
+    When a batch fails its oldest event is sent alone, an
+    event the publisher keeps rejecting is moved to
+    event_outbox_dead after OUTBOX_MAX_ATTEMPTS so the
+    events behind it are not held up forever.
+*/
+pub struct OutboxRelay {
+    data_store: Arc<dyn DataStore>,
+    publisher: Arc<dyn EventPublisher>,
+    logger: Arc<dyn Log>,
+    max_attempts: u32,
+}
+
+impl OutboxRelay {
+    pub fn new(
+        data_store: Arc<dyn DataStore>,
+        publisher: Arc<dyn EventPublisher>,
+        logger: Arc<dyn Log>,
+        max_attempts: u32,
+    ) -> Self {
+        OutboxRelay {
+            data_store,
+            publisher,
+            logger,
+            max_attempts: max_attempts.max(1),
+        }
+    }
+
+    pub fn spawn(self: Arc<Self>) {
+        tokio::spawn(async move {
+            let mut backoff = 1;
+            loop {
+                match self.relay_batch().await {
+                    Ok(0) => sleep(IDLE_POLL).await,
+                    Ok(_) => backoff = 1,
+                    Err(e) => {
+                        self.logger
+                            .error(format!("event outbox relay failed - {}", e));
+                        sleep(Duration::from_secs(backoff)).await;
+                        backoff = (backoff * 2).min(MAX_BACKOFF_SECS);
+                    }
+                }
+            }
+        });
+    }
+
+    /*
+        on shutdown, relays what is left until the outbox
+        is empty, a publish fails or the time is up. What
+        is left is relayed after the next start
+    */
+    pub async fn flush(&self, timeout: Duration) -> Result<(), String> {
+        let started = Instant::now();
+        while started.elapsed() < timeout {
+            let relay = tokio::time::timeout(timeout - started.elapsed(), self.relay_batch());
+            match relay.await {
+                Ok(Ok(0)) => return Ok(()),
+                Ok(Ok(_)) => (),
+                Ok(Err(e)) => return Err(e),
+                Err(_) => break,
+            }
+        }
+        Err("timed out with events left in the outbox".to_string())
+    }
+
+    async fn relay_batch(&self) -> Result<usize, String> {
+        let batch = self.data_store.get_outbox(BATCH_SIZE).await?;
+        let oldest = match batch.first() {
+            Some(oldest) => oldest,
+            None => return Ok(0),
+        };
+        let batch_error = match self.publisher.publish(&batch).await {
+            Ok(()) => {
+                let row_ids: Vec<i32> = batch.iter().map(|e| e.row_id).collect();
+                self.data_store.delete_outbox(&row_ids).await?;
+                return Ok(batch.len());
+            }
+            Err(e) => e,
+        };
+
+        // find out whether the oldest event is the one rejected
+        let error = match batch.len() > 1 {
+            true => match self.publisher.publish(std::slice::from_ref(oldest)).await {
+                Ok(()) => {
+                    self.data_store.delete_outbox(&[oldest.row_id]).await?;
+                    return Err(batch_error);
+                }
+                Err(e) => e,
+            },
+            false => batch_error,
+        };
+        let dead = self
+            .data_store
+            .fail_outbox(oldest.row_id, self.max_attempts, &error)
+            .await?;
+        if dead {
+            self.logger.error(format!(
+                "event {} of process {} moved to event_outbox_dead after {} attempts - {}",
+                oldest.row_id, oldest.process_id, self.max_attempts, error
+            ));
+        }
+        Err(error)
+    }
+}
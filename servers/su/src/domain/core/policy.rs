@@ -0,0 +1,182 @@
+use std::collections::BTreeMap;
+use std::fs;
+
+use bundlr_sdk::tags::Tag;
+use regex::Regex;
+use serde::Deserialize;
+
+use super::dal::Config;
+
+/*
+    Which processes this su agrees to spawn. A denylist
+    always wins, a non empty allowlist admits only what
+    is on it. Owners and Module ids are checked apart so
+    an operator can curate either or both.
+*/
+pub struct SpawnPolicy {
+    allowed_owners: Vec<String>,
+    denied_owners: Vec<String>,
+    allowed_modules: Vec<String>,
+    denied_modules: Vec<String>,
+}
+
+impl SpawnPolicy {
+    pub fn new(config: &dyn Config) -> Self {
+        SpawnPolicy {
+            allowed_owners: config.spawn_allowed_owners(),
+            denied_owners: config.spawn_denied_owners(),
+            allowed_modules: config.spawn_allowed_modules(),
+            denied_modules: config.spawn_denied_modules(),
+        }
+    }
+
+    pub fn check(&self, owner: &str, module: &str) -> Result<(), String> {
+        if !admits(&self.allowed_owners, &self.denied_owners, owner) {
+            return Err(format!("Owner {} is not allowed to spawn processes", owner));
+        }
+        if !admits(&self.allowed_modules, &self.denied_modules, module) {
+            return Err(format!(
+                "Module {} is not allowed on this scheduler",
+                module
+            ));
+        }
+        Ok(())
+    }
+}
+
+fn admits(allowed: &[String], denied: &[String], value: &str) -> bool {
+    if denied.iter().any(|d| d == value) {
+        return false;
+    }
+    allowed.is_empty() || allowed.iter().any(|a| a == value)
+}
+
+/*
+    Extra tag rules an operator enforces on every message
+    and process written, on top of the Data-Protocol, Type,
+    Module and Scheduler tags the su always needs. Read
+    from the json file at TAG_POLICY_PATH, e.g.
+
+    {
+        "required": ["App-Name"],
+        "forbidden": ["Cron-Interval"],
+        "patterns": { "Content-Type": "^text/" }
+    }
+
+    A pattern applies to every value of a tag present on
+    the item, require the tag as well to force it.
+*/
+#[derive(Deserialize, Default)]
+struct TagPolicyFile {
+    #[serde(default)]
+    required: Vec<String>,
+    #[serde(default)]
+    forbidden: Vec<String>,
+    #[serde(default)]
+    patterns: BTreeMap<String, String>,
+}
+
+#[derive(Default)]
+pub struct TagPolicy {
+    required: Vec<String>,
+    forbidden: Vec<String>,
+    patterns: Vec<(String, Regex)>,
+}
+
+impl TagPolicy {
+    // no path means no extra rules
+    pub fn from_path(path: &Option<String>) -> Result<Self, String> {
+        match path {
+            Some(path) => {
+                let contents = fs::read_to_string(path)
+                    .map_err(|e| format!("Failed to read tag policy {}: {}", path, e))?;
+                TagPolicy::parse(&contents)
+            }
+            None => Ok(TagPolicy::default()),
+        }
+    }
+
+    fn parse(contents: &str) -> Result<Self, String> {
+        let file: TagPolicyFile =
+            serde_json::from_str(contents).map_err(|e| format!("Invalid tag policy: {}", e))?;
+        let mut patterns = vec![];
+        for (name, pattern) in file.patterns.into_iter() {
+            let regex = Regex::new(&pattern)
+                .map_err(|e| format!("Invalid tag policy pattern for {}: {}", name, e))?;
+            patterns.push((name, regex));
+        }
+        Ok(TagPolicy {
+            required: file.required,
+            forbidden: file.forbidden,
+            patterns,
+        })
+    }
+
+    pub fn check(&self, tags: &[Tag]) -> Result<(), String> {
+        for name in self.required.iter() {
+            if !tags.iter().any(|tag| &tag.name == name) {
+                return Err(format!("{} tag required by this su is not present", name));
+            }
+        }
+        for tag in tags.iter() {
+            if self.forbidden.iter().any(|name| name == &tag.name) {
+                return Err(format!("{} tag is not allowed on this su", tag.name));
+            }
+            for (name, regex) in self.patterns.iter() {
+                if name == &tag.name && !regex.is_match(&tag.value) {
+                    return Err(format!(
+                        "{} tag value {} does not match {}",
+                        tag.name,
+                        tag.value,
+                        regex.as_str()
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_spawn_policy() {
+        let policy = SpawnPolicy {
+            allowed_owners: vec![],
+            denied_owners: vec!["spammer".to_string()],
+            allowed_modules: vec!["mod-a".to_string(), "mod-b".to_string()],
+            denied_modules: vec!["mod-b".to_string()],
+        };
+
+        assert!(policy.check("anyone", "mod-a").is_ok());
+        assert!(policy.check("spammer", "mod-a").is_err());
+        assert!(policy.check("anyone", "mod-b").is_err());
+        assert!(policy.check("anyone", "mod-c").is_err());
+    }
+
+    #[test]
+    fn test_tag_policy() {
+        let policy = TagPolicy::parse(
+            r#"{"required": ["App-Name"], "forbidden": ["Cron-Interval"], "patterns": {"Content-Type": "^text/"}}"#,
+        )
+        .unwrap();
+
+        let tag = |name: &str, value: &str| Tag::new(name, value);
+        assert!(policy.check(&[tag("App-Name", "aos")]).is_ok());
+        assert!(policy
+            .check(&[tag("App-Name", "aos"), tag("Content-Type", "text/plain")])
+            .is_ok());
+        assert!(policy.check(&[tag("Content-Type", "text/plain")]).is_err());
+        assert!(policy
+            .check(&[tag("App-Name", "aos"), tag("Cron-Interval", "1-minute")])
+            .is_err());
+        assert!(policy
+            .check(&[tag("App-Name", "aos"), tag("Content-Type", "image/png")])
+            .is_err());
+
+        assert!(TagPolicy::parse(r#"{"patterns": {"Content-Type": "("}}"#).is_err());
+        assert!(TagPolicy::from_path(&None).unwrap().check(&[]).is_ok());
+    }
+}
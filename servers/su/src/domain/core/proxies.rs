@@ -0,0 +1,356 @@
+use std::net::{IpAddr, SocketAddr};
+
+use dashmap::DashMap;
+
+// the start of a v2 PROXY header, a v1 header starts with "PROXY "
+const PROXY_V2_SIGNATURE: &[u8] = b"\r\n\r\n\0\r\nQUIT\n";
+
+// the longest v1 header, its line included
+const PROXY_V1_MAX_LEN: usize = 107;
+
+/*
+    Behind a load balancer the peer address of every
+    request is the balancer. X-Forwarded-For is only
+    believed when the peer is one of the configured
+    trusted proxies, otherwise any client could spoof
+    its own address.
+*/
+struct Cidr {
+    network: IpAddr,
+    prefix: u8,
+}
+
+impl Cidr {
+    fn parse(value: &str) -> Result<Self, String> {
+        let (address, prefix) = match value.split_once('/') {
+            Some((a, p)) => (a, Some(p)),
+            None => (value, None),
+        };
+
+        let network = normalize(
+            address
+                .parse::<IpAddr>()
+                .map_err(|_| format!("Invalid trusted proxy address: {}", value))?,
+        );
+        let max_prefix = match network {
+            IpAddr::V4(_) => 32,
+            IpAddr::V6(_) => 128,
+        };
+        let prefix = match prefix {
+            Some(p) => p
+                .parse::<u8>()
+                .ok()
+                .filter(|p| *p <= max_prefix)
+                .ok_or(format!("Invalid trusted proxy prefix: {}", value))?,
+            None => max_prefix,
+        };
+
+        Ok(Cidr { network, prefix })
+    }
+
+    fn contains(&self, ip: &IpAddr) -> bool {
+        match (self.network, normalize(*ip)) {
+            (IpAddr::V4(net), IpAddr::V4(ip)) => {
+                let mask = u32::MAX.checked_shl(32 - self.prefix as u32).unwrap_or(0);
+                u32::from(net) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V6(net), IpAddr::V6(ip)) => {
+                let mask = u128::MAX.checked_shl(128 - self.prefix as u32).unwrap_or(0);
+                u128::from(net) & mask == u128::from(ip) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+// a dual stack listener reports ipv4 peers as ::ffff:a.b.c.d
+fn normalize(ip: IpAddr) -> IpAddr {
+    match ip {
+        IpAddr::V6(v6) => match v6.to_ipv4_mapped() {
+            Some(v4) => IpAddr::V4(v4),
+            None => IpAddr::V6(v6),
+        },
+        v4 => v4,
+    }
+}
+
+pub struct TrustedProxies {
+    cidrs: Vec<Cidr>,
+
+    /*
+        with PROXY_PROTOCOL the peer of every request is
+        the su's own relay, the address each relayed
+        connection came from by the port of the relay
+    */
+    relayed: DashMap<u16, IpAddr>,
+}
+
+impl TrustedProxies {
+    pub fn new(cidrs: &[String]) -> Result<Self, String> {
+        let cidrs = cidrs
+            .iter()
+            .map(|c| Cidr::parse(c))
+            .collect::<Result<Vec<Cidr>, String>>()?;
+        Ok(TrustedProxies {
+            cidrs,
+            relayed: DashMap::new(),
+        })
+    }
+
+    // the address of a PROXY header is only believed from a trusted peer
+    pub fn relay(&self, port: u16, peer: IpAddr, source: Option<IpAddr>) {
+        let ip = match source {
+            Some(source) if self.is_trusted(&normalize(peer)) => source,
+            _ => peer,
+        };
+        self.relayed.insert(port, ip);
+    }
+
+    pub fn end_relay(&self, port: u16) {
+        self.relayed.remove(&port);
+    }
+
+    // the address a connection came from, before the su's relay
+    pub fn peer_ip(&self, peer: SocketAddr) -> IpAddr {
+        match peer.ip().is_loopback() {
+            true => self.relayed.get(&peer.port()).map_or(peer.ip(), |ip| *ip),
+            false => peer.ip(),
+        }
+    }
+
+    fn is_trusted(&self, ip: &IpAddr) -> bool {
+        self.cidrs.iter().any(|cidr| cidr.contains(ip))
+    }
+
+    /*
+        walk X-Forwarded-For from the nearest hop back,
+        the first address that is not a trusted proxy
+        is the client
+    */
+    pub fn client_ip(&self, peer: IpAddr, forwarded_for: Option<&str>) -> IpAddr {
+        let peer = normalize(peer);
+        if !self.is_trusted(&peer) {
+            return peer;
+        }
+
+        let hops = match forwarded_for {
+            Some(header) => header.split(',').rev(),
+            None => return peer,
+        };
+
+        let mut client = peer;
+        for hop in hops {
+            match hop.trim().parse::<IpAddr>() {
+                Ok(ip) => {
+                    client = normalize(ip);
+                    if !self.is_trusted(&client) {
+                        break;
+                    }
+                }
+                Err(_) => break,
+            }
+        }
+        client
+    }
+}
+
+/*
+    The PROXY protocol header at the start of a connection
+    from a load balancer, v1 or v2. None while more bytes
+    are needed, otherwise the length of the header and
+    the address of the client, which a LOCAL or UNKNOWN
+    header does not carry
+*/
+pub fn parse_proxy_header(buf: &[u8]) -> Result<Option<(usize, Option<SocketAddr>)>, String> {
+    let starts_like = |prefix: &[u8]| {
+        let len = prefix.len().min(buf.len());
+        prefix[..len] == buf[..len]
+    };
+    if starts_like(PROXY_V2_SIGNATURE) {
+        return parse_proxy_v2(buf);
+    }
+    if starts_like(b"PROXY ") {
+        return parse_proxy_v1(buf);
+    }
+    Err("connection does not start with a PROXY header".to_string())
+}
+
+// "PROXY TCP4 <source> <destination> <source port> <destination port>\r\n"
+fn parse_proxy_v1(buf: &[u8]) -> Result<Option<(usize, Option<SocketAddr>)>, String> {
+    let end = match buf.windows(2).position(|w| w == b"\r\n") {
+        Some(end) if end + 2 <= PROXY_V1_MAX_LEN => end,
+        None if buf.len() < PROXY_V1_MAX_LEN => return Ok(None),
+        _ => return Err("PROXY header is too long".to_string()),
+    };
+    let invalid = || "invalid PROXY header".to_string();
+    let line = std::str::from_utf8(&buf[..end]).map_err(|_| invalid())?;
+    let fields: Vec<&str> = line.split(' ').collect();
+    let source = match fields.get(1) {
+        Some(&"UNKNOWN") => None,
+        Some(&"TCP4") | Some(&"TCP6") if fields.len() == 6 => {
+            let ip = fields[2].parse::<IpAddr>().map_err(|_| invalid())?;
+            let port = fields[4].parse::<u16>().map_err(|_| invalid())?;
+            Some(SocketAddr::new(ip, port))
+        }
+        _ => return Err(invalid()),
+    };
+    Ok(Some((end + 2, source)))
+}
+
+/*
+    the signature, the version and command, the family,
+    the length of the rest and then the addresses, tlvs
+    after them are skipped
+*/
+fn parse_proxy_v2(buf: &[u8]) -> Result<Option<(usize, Option<SocketAddr>)>, String> {
+    if buf.len() < 16 {
+        return Ok(None);
+    }
+    if buf[12] >> 4 != 2 {
+        return Err("unsupported PROXY header version".to_string());
+    }
+    let len = 16 + u16::from_be_bytes([buf[14], buf[15]]) as usize;
+    if buf.len() < len {
+        return Ok(None);
+    }
+    let addresses = &buf[16..len];
+    let port = |at: usize| u16::from_be_bytes([addresses[at], addresses[at + 1]]);
+    let source = match (buf[12] & 0x0f, buf[13] >> 4) {
+        // a LOCAL connection, such as a health check of the balancer
+        (0, _) => None,
+        (1, 1) if addresses.len() >= 12 => {
+            let mut ip = [0u8; 4];
+            ip.copy_from_slice(&addresses[..4]);
+            Some(SocketAddr::from((ip, port(8))))
+        }
+        (1, 2) if addresses.len() >= 36 => {
+            let mut ip = [0u8; 16];
+            ip.copy_from_slice(&addresses[..16]);
+            Some(SocketAddr::from((ip, port(32))))
+        }
+        (1, 1) | (1, 2) => return Err("PROXY header addresses are cut off".to_string()),
+        // unix sockets and unspecified families have no client address
+        (1, _) => None,
+        _ => return Err("invalid PROXY header command".to_string()),
+    };
+    Ok(Some((len, source)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn test_untrusted_peer_ignores_header() {
+        let proxies = TrustedProxies::new(&["10.0.0.0/8".to_string()]).unwrap();
+        assert_eq!(
+            proxies.client_ip(ip("203.0.113.7"), Some("198.51.100.1")),
+            ip("203.0.113.7")
+        );
+    }
+
+    #[test]
+    fn test_trusted_chain() {
+        let proxies =
+            TrustedProxies::new(&["10.0.0.0/8".to_string(), "fd00::/8".to_string()]).unwrap();
+        assert_eq!(
+            proxies.client_ip(
+                ip("::ffff:10.0.0.2"),
+                Some("2001:db8::1, fd00::5, 10.1.1.1")
+            ),
+            ip("2001:db8::1")
+        );
+        assert_eq!(proxies.client_ip(ip("10.0.0.2"), None), ip("10.0.0.2"));
+        assert!(TrustedProxies::new(&["10.0.0.0/33".to_string()]).is_err());
+    }
+
+    #[test]
+    fn test_proxy_v1() {
+        let header = b"PROXY TCP4 203.0.113.7 10.0.0.1 51234 443\r\nGET /";
+        assert_eq!(
+            parse_proxy_header(header),
+            Ok(Some((43, Some("203.0.113.7:51234".parse().unwrap()))))
+        );
+        let header = b"PROXY TCP6 2001:db8::1 fd00::1 51234 443\r\n";
+        assert_eq!(
+            parse_proxy_header(header),
+            Ok(Some((42, Some("[2001:db8::1]:51234".parse().unwrap()))))
+        );
+        assert_eq!(
+            parse_proxy_header(b"PROXY UNKNOWN\r\n"),
+            Ok(Some((15, None)))
+        );
+        assert_eq!(parse_proxy_header(b""), Ok(None));
+        assert_eq!(parse_proxy_header(b"PRO"), Ok(None));
+        assert_eq!(parse_proxy_header(b"PROXY TCP4 203.0.113.7"), Ok(None));
+        assert!(parse_proxy_header(b"GET / HTTP/1.1\r\n").is_err());
+        assert!(parse_proxy_header(b"PROXY TCP4 nowhere 10.0.0.1 1 2\r\n").is_err());
+        assert!(parse_proxy_header(&[b'P'; 200]).is_err());
+    }
+
+    #[test]
+    fn test_proxy_v2() {
+        let mut header = PROXY_V2_SIGNATURE.to_vec();
+        header.extend([0x21, 0x11, 0, 12]);
+        header.extend([203, 0, 113, 7, 10, 0, 0, 1]);
+        header.extend([0xc8, 0x22, 0x01, 0xbb]);
+        header.extend(b"\x16\x03\x01");
+        assert_eq!(
+            parse_proxy_header(&header),
+            Ok(Some((28, Some("203.0.113.7:51234".parse().unwrap()))))
+        );
+        assert_eq!(parse_proxy_header(&header[..20]), Ok(None));
+        assert_eq!(parse_proxy_header(&header[..8]), Ok(None));
+
+        let mut local = PROXY_V2_SIGNATURE.to_vec();
+        local.extend([0x20, 0x00, 0, 0]);
+        assert_eq!(parse_proxy_header(&local), Ok(Some((16, None))));
+
+        let mut ipv6 = PROXY_V2_SIGNATURE.to_vec();
+        ipv6.extend([0x21, 0x21, 0, 36]);
+        ipv6.extend(
+            "2001:db8::1"
+                .parse::<std::net::Ipv6Addr>()
+                .unwrap()
+                .octets(),
+        );
+        ipv6.extend([0u8; 16]);
+        ipv6.extend([0xc8, 0x22, 0x01, 0xbb]);
+        assert_eq!(
+            parse_proxy_header(&ipv6),
+            Ok(Some((52, Some("[2001:db8::1]:51234".parse().unwrap()))))
+        );
+
+        let mut cut = PROXY_V2_SIGNATURE.to_vec();
+        cut.extend([0x21, 0x11, 0, 4, 203, 0, 113, 7]);
+        assert!(parse_proxy_header(&cut).is_err());
+        header[12] = 0x11;
+        assert!(parse_proxy_header(&header).is_err());
+    }
+
+    #[test]
+    fn test_relayed_peer() {
+        let proxies = TrustedProxies::new(&["10.0.0.0/8".to_string()]).unwrap();
+        let relay: SocketAddr = "127.0.0.1:40001".parse().unwrap();
+        assert_eq!(proxies.peer_ip(relay), ip("127.0.0.1"));
+
+        proxies.relay(40001, ip("10.0.0.2"), Some(ip("203.0.113.7")));
+        proxies.relay(40002, ip("198.51.100.1"), Some(ip("203.0.113.7")));
+        assert_eq!(proxies.peer_ip(relay), ip("203.0.113.7"));
+        assert_eq!(
+            proxies.peer_ip("127.0.0.1:40002".parse().unwrap()),
+            ip("198.51.100.1")
+        );
+        assert_eq!(
+            proxies.peer_ip("10.0.0.2:40001".parse().unwrap()),
+            ip("10.0.0.2")
+        );
+
+        proxies.end_relay(40001);
+        assert_eq!(proxies.peer_ip(relay), ip("127.0.0.1"));
+    }
+}
@@ -0,0 +1,137 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+use dashmap::DashMap;
+
+// once this many owners are tracked full buckets are dropped
+const PRUNE_THRESHOLD: usize = 100_000;
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+/*
+    Token bucket per owner address. Each owner may burst
+    up to `burst` writes, after that writes are admitted
+    at `per_second`. A rate of 0 disables the limiter.
+    Both can change at runtime, buckets keep their tokens
+    up to the new burst.
+*/
+pub struct RateLimiter {
+    buckets: DashMap<String, Bucket>,
+    // f64 bits so the limits can be swapped without a lock
+    per_second: AtomicU64,
+    burst: AtomicU64,
+}
+
+impl RateLimiter {
+    pub fn new(per_second: f64, burst: u32) -> Self {
+        let limiter = RateLimiter {
+            buckets: DashMap::new(),
+            per_second: AtomicU64::new(0),
+            burst: AtomicU64::new(0),
+        };
+        limiter.set_limits(per_second, burst);
+        limiter
+    }
+
+    pub fn set_limits(&self, per_second: f64, burst: u32) {
+        self.per_second
+            .store(per_second.to_bits(), Ordering::Relaxed);
+        self.burst
+            .store(f64::from(burst.max(1)).to_bits(), Ordering::Relaxed);
+    }
+
+    fn limits(&self) -> (f64, f64) {
+        (
+            f64::from_bits(self.per_second.load(Ordering::Relaxed)),
+            f64::from_bits(self.burst.load(Ordering::Relaxed)),
+        )
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.limits().0 > 0.0
+    }
+
+    /*
+        take a token for the owner, on failure returns
+        how long until the next token is available
+    */
+    pub fn check(&self, owner: &str) -> Result<(), Duration> {
+        self.check_at(owner, Instant::now())
+    }
+
+    fn check_at(&self, owner: &str, now: Instant) -> Result<(), Duration> {
+        let (per_second, burst) = self.limits();
+        if per_second <= 0.0 {
+            return Ok(());
+        }
+
+        if self.buckets.len() >= PRUNE_THRESHOLD {
+            self.prune(now, per_second, burst);
+        }
+
+        let mut bucket = self.buckets.entry(owner.to_string()).or_insert(Bucket {
+            tokens: burst,
+            updated: now,
+        });
+
+        let elapsed = now.saturating_duration_since(bucket.updated).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * per_second).min(burst);
+        bucket.updated = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((1.0 - bucket.tokens) / per_second))
+        }
+    }
+
+    // a bucket that has refilled is the same as no bucket
+    fn prune(&self, now: Instant, per_second: f64, burst: f64) {
+        self.buckets.retain(|_, bucket| {
+            let elapsed = now.saturating_duration_since(bucket.updated).as_secs_f64();
+            bucket.tokens + elapsed * per_second < burst
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_token_bucket() {
+        let limiter = RateLimiter::new(2.0, 3);
+        let start = Instant::now();
+
+        for _ in 0..3 {
+            assert!(limiter.check_at("owner", start).is_ok());
+        }
+        let wait = limiter.check_at("owner", start).unwrap_err();
+        assert_eq!(wait, Duration::from_millis(500));
+
+        // other owners have their own bucket
+        assert!(limiter.check_at("other", start).is_ok());
+
+        let later = start + Duration::from_millis(500);
+        assert!(limiter.check_at("owner", later).is_ok());
+        assert!(limiter.check_at("owner", later).is_err());
+
+        let disabled = RateLimiter::new(0.0, 1);
+        for _ in 0..10 {
+            assert!(disabled.check_at("owner", start).is_ok());
+        }
+
+        // lowering the limits applies to existing buckets
+        limiter.set_limits(1.0, 1);
+        let much_later = start + Duration::from_secs(10);
+        assert!(limiter.check_at("owner", much_later).is_ok());
+        assert_eq!(
+            limiter.check_at("owner", much_later).unwrap_err(),
+            Duration::from_secs(1)
+        );
+    }
+}
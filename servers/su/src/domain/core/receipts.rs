@@ -0,0 +1,187 @@
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use super::bytes::{deep_hash_sync, verify_arweave, DeepHashChunk};
+use super::dal::Signer;
+
+/*
+    A receipt for a read response. The su signs the sha256
+    digest of the body with the timestamp it was served at,
+    a CU keeps the body and its receipt to prove which
+    schedule it was given in case of a dispute. The
+    signature is the same RSA-PSS an arweave wallet makes,
+    over the utf-8 bytes of "<timestamp>:<digest>".
+*/
+pub struct ReadReceipt {
+    pub timestamp: u64,
+    // base64url sha256 of the body
+    pub digest: String,
+    pub signature: String,
+    pub address: String,
+    pub key: String,
+}
+
+impl ReadReceipt {
+    pub fn headers(&self) -> Vec<(&'static str, String)> {
+        vec![
+            ("X-Su-Timestamp", self.timestamp.to_string()),
+            ("X-Su-Digest", self.digest.clone()),
+            ("X-Su-Signature", self.signature.clone()),
+            ("X-Su-Address", self.address.clone()),
+            ("X-Su-Key", self.key.clone()),
+        ]
+    }
+}
+
+fn sha256(bytes: &[u8]) -> Vec<u8> {
+    let mut hasher = Sha256::new();
+    hasher.update(bytes);
+    hasher.finalize().to_vec()
+}
+
+pub fn payload(timestamp: u64, digest: &str) -> Vec<u8> {
+    format!("{}:{}", timestamp, digest).into_bytes()
+}
+
+/*
+    What the upload node answers for an accepted bundle, its
+    promise to have the bundle on arweave before the block
+    at deadline_height. public is the key of the upload node
+    and signature its RSA-PSS signature over the deep hash
+    of "Bundlr", version, id, deadline_height and timestamp.
+*/
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct UploadReceipt {
+    pub id: String,
+    pub timestamp: u64,
+    pub version: String,
+    pub public: String,
+    pub signature: String,
+    pub deadline_height: u64,
+}
+
+impl UploadReceipt {
+    pub fn verify(&self) -> Result<(), String> {
+        let message = deep_hash_sync(DeepHashChunk::Chunks(
+            [
+                "Bundlr".to_string(),
+                self.version.clone(),
+                self.id.clone(),
+                self.deadline_height.to_string(),
+                self.timestamp.to_string(),
+            ]
+            .into_iter()
+            .map(|field| DeepHashChunk::Chunk(field.into_bytes().into()))
+            .collect(),
+        ))
+        .map_err(|e| format!("{:?}", e))?;
+        let public = base64_url::decode(&self.public).map_err(|e| format!("public: {}", e))?;
+        let signature =
+            base64_url::decode(&self.signature).map_err(|e| format!("signature: {}", e))?;
+        match verify_arweave(&public, &message, &signature).map_err(|e| format!("{:?}", e))? {
+            true => Ok(()),
+            false => Err(format!("Invalid signature on the receipt of {}", self.id)),
+        }
+    }
+
+    // a deadline the network already reached cannot be met
+    pub fn check_deadline(&self, height: u64) -> Result<(), String> {
+        match self.deadline_height > height {
+            true => Ok(()),
+            false => Err(format!(
+                "Receipt of {} has deadline {} but the network is at {}",
+                self.id, self.deadline_height, height
+            )),
+        }
+    }
+}
+
+pub async fn sign(signer: &dyn Signer, body: &[u8], timestamp: u64) -> Result<ReadReceipt, String> {
+    let digest = base64_url::encode(&sha256(body));
+    let signature = signer.sign_tx(payload(timestamp, &digest)).await?;
+    let key = signer.get_public_key();
+    Ok(ReadReceipt {
+        timestamp,
+        signature: base64_url::encode(&signature),
+        address: base64_url::encode(&sha256(&key)),
+        key: base64_url::encode(&key),
+        digest,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_trait::async_trait;
+
+    // signs by echoing the payload back
+    struct EchoSigner;
+    #[async_trait]
+    impl Signer for EchoSigner {
+        async fn sign_tx(&self, buffer: Vec<u8>) -> Result<Vec<u8>, String> {
+            Ok(buffer)
+        }
+
+        fn get_public_key(&self) -> Vec<u8> {
+            vec![5, 6, 7, 8]
+        }
+    }
+
+    #[tokio::test]
+    async fn test_sign() {
+        let body = br#"{"edges":[]}"#;
+        let receipt = sign(&EchoSigner, body, 1000).await.unwrap();
+        assert_eq!(receipt.digest, base64_url::encode(&sha256(body)));
+        assert_eq!(
+            base64_url::decode(&receipt.signature).unwrap(),
+            format!("1000:{}", receipt.digest).into_bytes()
+        );
+        assert_eq!(receipt.address, base64_url::encode(&sha256(&[5, 6, 7, 8])));
+        assert_eq!(receipt.headers().len(), 5);
+    }
+
+    #[test]
+    fn test_upload_receipt() {
+        use openssl::hash::MessageDigest;
+        use openssl::pkey::PKey;
+        use openssl::rsa::{Padding, Rsa};
+        use openssl::sign::RsaPssSaltlen;
+
+        let rsa = Rsa::generate(2048).unwrap();
+        let public = base64_url::encode(&rsa.n().to_vec());
+        let key = PKey::from_rsa(rsa).unwrap();
+        let mut receipt = UploadReceipt {
+            id: "bundle".to_string(),
+            timestamp: 1700000000000,
+            version: "1.0.0".to_string(),
+            public,
+            signature: String::new(),
+            deadline_height: 1200,
+        };
+        let message = deep_hash_sync(DeepHashChunk::Chunks(
+            ["Bundlr", "1.0.0", "bundle", "1200", "1700000000000"]
+                .iter()
+                .map(|field| DeepHashChunk::Chunk(field.as_bytes().to_vec().into()))
+                .collect(),
+        ))
+        .unwrap();
+        let mut signer = openssl::sign::Signer::new(MessageDigest::sha256(), &key).unwrap();
+        signer.set_rsa_padding(Padding::PKCS1_PSS).unwrap();
+        signer
+            .set_rsa_pss_saltlen(RsaPssSaltlen::DIGEST_LENGTH)
+            .unwrap();
+        signer.update(&message).unwrap();
+        receipt.signature = base64_url::encode(&signer.sign_to_vec().unwrap());
+        assert!(receipt.verify().is_ok());
+
+        let tampered = UploadReceipt {
+            deadline_height: 1300,
+            ..receipt.clone()
+        };
+        assert!(tampered.verify().is_err());
+
+        assert!(receipt.check_deadline(1100).is_ok());
+        assert!(receipt.check_deadline(1200).is_err());
+    }
+}
@@ -0,0 +1,214 @@
+use reqwest::Url;
+
+use super::dal::{Config, UrlResolver};
+
+/*
+    Resolves redirect targets from configurable templates.
+    Templates can use {url}, {scheme}, {host} and {port}
+    which are filled in from the stored scheduler url, so
+    https://{host}.internal:{port} turns a stored
+    https://su-1.example.com into https://su-1.example.com.internal:443.
+
+    Clients that reached the router through one of the
+    internal hosts get the internal template, so the same
+    scheduler list serves both in-cluster and public clients.
+    The port override is applied last.
+*/
+pub struct TemplateResolver {
+    template: Option<String>,
+    internal_template: Option<String>,
+    internal_hosts: Vec<String>,
+    port_override: Option<u16>,
+}
+
+impl TemplateResolver {
+    pub fn new(config: &dyn Config) -> Self {
+        TemplateResolver {
+            template: config.redirect_url_template(),
+            internal_template: config.redirect_internal_url_template(),
+            internal_hosts: config.redirect_internal_hosts(),
+            port_override: config.redirect_port_override(),
+        }
+    }
+
+    fn is_internal(&self, request_host: &str) -> bool {
+        // compare without the port, the host header may carry one
+        let host = match request_host.rsplit_once(':') {
+            Some((h, p)) if p.chars().all(|c| c.is_ascii_digit()) => h,
+            _ => request_host,
+        };
+        self.internal_hosts.iter().any(|h| h == host)
+    }
+}
+
+fn render(template: &str, scheduler_url: &str) -> Result<String, String> {
+    let url = Url::parse(scheduler_url)
+        .map_err(|e| format!("Invalid scheduler url {}: {}", scheduler_url, e))?;
+    let port = match url.port_or_known_default() {
+        Some(p) => p.to_string(),
+        None => "".to_string(),
+    };
+    Ok(template
+        .replace("{url}", scheduler_url.trim_end_matches('/'))
+        .replace("{scheme}", url.scheme())
+        .replace("{host}", url.host_str().unwrap_or(""))
+        .replace("{port}", &port))
+}
+
+impl UrlResolver for TemplateResolver {
+    fn resolve(&self, scheduler_url: &str, request_host: &str) -> Result<String, String> {
+        let template = if self.is_internal(request_host) && self.internal_template.is_some() {
+            &self.internal_template
+        } else {
+            &self.template
+        };
+
+        let resolved = match template {
+            Some(t) => render(t, scheduler_url)?,
+            None => scheduler_url.to_string(),
+        };
+
+        match self.port_override {
+            Some(port) => {
+                let mut url = Url::parse(&resolved)
+                    .map_err(|e| format!("Invalid redirect url {}: {}", resolved, e))?;
+                url.set_port(Some(port))
+                    .map_err(|_| format!("Cannot set port on redirect url {}", resolved))?;
+                // the request uri is appended so drop the trailing slash
+                Ok(url.to_string().trim_end_matches('/').to_string())
+            }
+            None => Ok(resolved),
+        }
+    }
+}
+
+/*
+    How the router answers with a redirect. Every route
+    gets REDIRECT_STATUS unless REDIRECT_STATUS_ROUTES
+    lists it as METHOD:pattern=status, ex. GET:/{tx_id}=302.
+    301 and 302 let a client resend a POST as a GET without
+    its body, 307 and 308 keep the method and the body.
+    With REDIRECT_BODY the target is also sent as json for
+    clients that cannot follow cross-origin redirects.
+*/
+pub struct RedirectPolicy {
+    status: u16,
+    routes: Vec<(String, String, u16)>,
+    body: bool,
+}
+
+fn redirect_status(status: &str) -> Result<u16, String> {
+    match status.trim().parse::<u16>() {
+        Ok(s @ (301 | 302 | 307 | 308)) => Ok(s),
+        _ => Err(format!(
+            "Redirect status {} must be 301, 302, 307 or 308",
+            status
+        )),
+    }
+}
+
+impl RedirectPolicy {
+    pub fn new(config: &dyn Config) -> Result<Self, String> {
+        let mut routes = vec![];
+        for entry in config.redirect_status_routes().iter() {
+            let invalid = || {
+                format!(
+                    "Invalid redirect route {}, use METHOD:pattern=status",
+                    entry
+                )
+            };
+            let (route, status) = entry.rsplit_once('=').ok_or_else(invalid)?;
+            let (method, pattern) = route.split_once(':').ok_or_else(invalid)?;
+            routes.push((
+                method.trim().to_uppercase(),
+                pattern.trim().to_string(),
+                redirect_status(status)?,
+            ));
+        }
+        Ok(RedirectPolicy {
+            status: redirect_status(&config.redirect_status().to_string())?,
+            routes,
+            body: config.redirect_body(),
+        })
+    }
+
+    // pattern is the route pattern the request matched, ex. /{tx_id}
+    pub fn status(&self, method: &str, pattern: &str) -> u16 {
+        self.routes
+            .iter()
+            .find(|(m, p, _)| m == method && p == pattern)
+            .map_or(self.status, |(_, _, status)| *status)
+    }
+
+    pub fn body(&self) -> bool {
+        self.body
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn resolver(
+        template: Option<&str>,
+        internal_template: Option<&str>,
+        port_override: Option<u16>,
+    ) -> TemplateResolver {
+        TemplateResolver {
+            template: template.map(|t| t.to_string()),
+            internal_template: internal_template.map(|t| t.to_string()),
+            internal_hosts: vec!["router.internal".to_string()],
+            port_override,
+        }
+    }
+
+    #[test]
+    fn test_resolve_passthrough() {
+        let r = resolver(None, None, None);
+        assert_eq!(
+            r.resolve("https://su-1.example.com", "router.example.com")
+                .unwrap(),
+            "https://su-1.example.com"
+        );
+    }
+
+    #[test]
+    fn test_resolve_split_horizon() {
+        let r = resolver(
+            Some("{url}"),
+            Some("http://{host}.cluster.local:{port}"),
+            None,
+        );
+        assert_eq!(
+            r.resolve("https://su-1", "router.internal:9000").unwrap(),
+            "http://su-1.cluster.local:443"
+        );
+        assert_eq!(
+            r.resolve("https://su-1", "router.example.com").unwrap(),
+            "https://su-1"
+        );
+    }
+
+    #[test]
+    fn test_resolve_port_override() {
+        let r = resolver(None, None, Some(8443));
+        assert_eq!(
+            r.resolve("https://su-1.example.com", "router.example.com")
+                .unwrap(),
+            "https://su-1.example.com:8443"
+        );
+    }
+
+    #[test]
+    fn test_redirect_status() {
+        let policy = RedirectPolicy {
+            status: 307,
+            routes: vec![("GET".to_string(), "/{tx_id}".to_string(), 302)],
+            body: false,
+        };
+        assert_eq!(policy.status("GET", "/{tx_id}"), 302);
+        assert_eq!(policy.status("POST", "/"), 307);
+        assert!(redirect_status("308").is_ok());
+        assert!(redirect_status("200").is_err());
+    }
+}
@@ -0,0 +1,211 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::Serialize;
+use tokio::time::sleep;
+
+use super::confirmations::confirmed_ids;
+use super::flows::Deps;
+
+// candidates read from the store at a time
+const PRUNE_BATCH: i64 = 1000;
+// ids per gateway query
+const VERIFY_BATCH: usize = 100;
+
+const DAY_MS: u64 = 24 * 60 * 60 * 1000;
+
+fn unix_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+#[derive(Serialize, Default)]
+pub struct RetentionStats {
+    pub runs: u64,
+    pub last_run_at: u64,
+    pub pruned: u64,
+    pub deleted_rows: u64,
+    // bytes of bundles dropped from the database
+    pub reclaimed_bytes: u64,
+    // bundles deleted from BUNDLE_STORAGE
+    pub deleted_blobs: u64,
+    // candidates left alone because arweave does not have them yet
+    pub unverified: u64,
+}
+
+/*
+    Bundles of messages older than RETENTION_DAYS or past
+    the newest RETENTION_MAX_MESSAGES of their process are
+    dropped from the database and the bundle store, once
+    the gateway has their assignment in a block. With
+    RETENTION_DELETE_ROWS the rows go too, leaving their
+    ids for the duplicate check. The latest message of a
+    process is always kept, the next one is chained onto
+    it.
+*/
+pub struct Retention {
+    runs: AtomicU64,
+    last_run_at: AtomicU64,
+    pruned: AtomicU64,
+    deleted_rows: AtomicU64,
+    reclaimed_bytes: AtomicU64,
+    deleted_blobs: AtomicU64,
+    unverified: AtomicU64,
+}
+
+impl Default for Retention {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Retention {
+    pub fn new() -> Self {
+        Retention {
+            runs: AtomicU64::new(0),
+            last_run_at: AtomicU64::new(0),
+            pruned: AtomicU64::new(0),
+            deleted_rows: AtomicU64::new(0),
+            reclaimed_bytes: AtomicU64::new(0),
+            deleted_blobs: AtomicU64::new(0),
+            unverified: AtomicU64::new(0),
+        }
+    }
+
+    pub fn stats(&self) -> RetentionStats {
+        RetentionStats {
+            runs: self.runs.load(Ordering::SeqCst),
+            last_run_at: self.last_run_at.load(Ordering::SeqCst),
+            pruned: self.pruned.load(Ordering::SeqCst),
+            deleted_rows: self.deleted_rows.load(Ordering::SeqCst),
+            reclaimed_bytes: self.reclaimed_bytes.load(Ordering::SeqCst),
+            deleted_blobs: self.deleted_blobs.load(Ordering::SeqCst),
+            unverified: self.unverified.load(Ordering::SeqCst),
+        }
+    }
+}
+
+// the cutoff and per process limit, None when that policy is off
+pub fn policy(days: u64, max_messages: u64, now_ms: u64) -> (Option<i64>, Option<i64>) {
+    let before = match days {
+        0 => None,
+        d => Some(now_ms.saturating_sub(d.saturating_mul(DAY_MS)) as i64),
+    };
+    let keep_latest = match max_messages {
+        0 => None,
+        m => Some(m.min(i64::MAX as u64) as i64),
+    };
+    (before, keep_latest)
+}
+
+// one pass over every prunable message, returns how many were pruned
+pub async fn prune(deps: &Arc<Deps>) -> Result<u64, String> {
+    let (before, keep_latest) = policy(
+        deps.config.retention_days(),
+        deps.config.retention_max_messages(),
+        unix_ms(),
+    );
+    if before.is_none() && keep_latest.is_none() {
+        return Ok(0);
+    }
+    let delete_rows = deps.config.retention_delete_rows();
+    let retention = &deps.retention;
+
+    let mut after = 0;
+    let mut pruned_total = 0;
+    loop {
+        let candidates = deps
+            .data_store
+            .get_prunable_messages(before, keep_latest, after, PRUNE_BATCH)
+            .await
+            .map_err(|e| format!("{:?}", e))?;
+        let last = match candidates.last() {
+            Some(last) => last.row_id,
+            None => break,
+        };
+
+        for chunk in candidates.chunks(VERIFY_BATCH) {
+            let ids: Vec<String> = chunk.iter().map(|c| c.assignment_id.clone()).collect();
+            let confirmed = confirmed_ids(&*deps.gateway, &ids).await?;
+            let verified: Vec<_> = chunk
+                .iter()
+                .filter(|c| confirmed.contains(&c.assignment_id))
+                .cloned()
+                .collect();
+            retention
+                .unverified
+                .fetch_add((chunk.len() - verified.len()) as u64, Ordering::SeqCst);
+            if verified.is_empty() {
+                continue;
+            }
+
+            let blob_errors = deps
+                .data_store
+                .prune_messages(&verified, delete_rows)
+                .await
+                .map_err(|e| format!("{:?}", e))?;
+            for e in blob_errors.iter() {
+                deps.logger
+                    .error(format!("retention could not delete a bundle - {}", e));
+            }
+
+            let count = verified.len() as u64;
+            let offloaded = verified
+                .iter()
+                .filter(|v| v.bundle_location.is_some())
+                .count() as u64;
+            let reclaimed: i64 = verified.iter().map(|v| v.stored_size).sum();
+            retention.pruned.fetch_add(count, Ordering::SeqCst);
+            if delete_rows {
+                retention.deleted_rows.fetch_add(count, Ordering::SeqCst);
+            }
+            retention
+                .reclaimed_bytes
+                .fetch_add(reclaimed.max(0) as u64, Ordering::SeqCst);
+            retention.deleted_blobs.fetch_add(
+                offloaded.saturating_sub(blob_errors.len() as u64),
+                Ordering::SeqCst,
+            );
+            pruned_total += count;
+        }
+        after = last;
+    }
+
+    retention.runs.fetch_add(1, Ordering::SeqCst);
+    retention.last_run_at.store(unix_ms(), Ordering::SeqCst);
+    Ok(pruned_total)
+}
+
+pub fn spawn_retention(deps: Arc<Deps>) {
+    let interval = Duration::from_millis(deps.config.retention_interval_ms());
+    tokio::spawn(async move {
+        loop {
+            sleep(interval).await;
+            match prune(&deps).await {
+                Ok(0) => (),
+                Ok(pruned) => deps
+                    .logger
+                    .log(format!("retention pruned {} messages", pruned)),
+                Err(e) => deps.logger.error(format!("retention failed - {}", e)),
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_policy() {
+        let now = 100 * DAY_MS;
+        assert_eq!(policy(0, 0, now), (None, None));
+        assert_eq!(policy(30, 0, now), (Some((70 * DAY_MS) as i64), None));
+        assert_eq!(policy(0, 500, now), (None, Some(500)));
+        // a cutoff before the epoch prunes nothing by age
+        assert_eq!(policy(365, 10, now), (Some(0), Some(10)));
+    }
+}
@@ -0,0 +1,84 @@
+use sha2::{Digest, Sha256};
+
+fn point(key: &str) -> u64 {
+    let mut hasher = Sha256::new();
+    hasher.update(key.as_bytes());
+    let digest = hasher.finalize();
+    let mut bytes = [0u8; 8];
+    bytes.copy_from_slice(&digest[..8]);
+    u64::from_be_bytes(bytes)
+}
+
+/*
+    Consistent hash ring of scheduler urls. Each scheduler
+    gets virtual_nodes points on the ring, times its weight,
+    and a process belongs to the first point at or after its
+    own. Only the url list and weights go in, so the mapping
+    can be recomputed without the database, and adding a
+    scheduler only moves the processes that land on it.
+*/
+pub struct HashRing {
+    points: Vec<(u64, String)>,
+}
+
+impl HashRing {
+    // a weight of 0 keeps the scheduler off the ring
+    pub fn new(schedulers: &[(String, f64)], virtual_nodes: u32) -> Self {
+        let mut points = vec![];
+        for (url, weight) in schedulers.iter() {
+            let nodes = (f64::from(virtual_nodes) * weight).round() as u64;
+            for n in 0..nodes {
+                points.push((point(&format!("{}#{}", url, n)), url.clone()));
+            }
+        }
+        points.sort();
+        HashRing { points }
+    }
+
+    pub fn get(&self, process_id: &str) -> Option<&String> {
+        if self.points.is_empty() {
+            return None;
+        }
+        let key = point(process_id);
+        let index = self.points.partition_point(|(p, _)| *p < key);
+        let (_, url) = &self.points[index % self.points.len()];
+        Some(url)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ring(urls: &[&str]) -> HashRing {
+        let schedulers: Vec<(String, f64)> = urls.iter().map(|u| (u.to_string(), 1.0)).collect();
+        HashRing::new(&schedulers, 64)
+    }
+
+    #[test]
+    fn test_hash_ring() {
+        assert!(HashRing::new(&[], 64).get("process").is_none());
+        assert!(HashRing::new(&[("https://su-1".to_string(), 0.0)], 64)
+            .get("process")
+            .is_none());
+
+        let two = ring(&["https://su-1", "https://su-2"]);
+        let three = ring(&["https://su-1", "https://su-2", "https://su-3"]);
+        let ids: Vec<String> = (0..300).map(|n| format!("process-{}", n)).collect();
+
+        // the same inputs give the same mapping
+        for id in ids.iter() {
+            assert_eq!(two.get(id), ring(&["https://su-2", "https://su-1"]).get(id));
+        }
+
+        // adding a scheduler only moves processes onto it
+        let mut moved = 0;
+        for id in ids.iter() {
+            if two.get(id) != three.get(id) {
+                assert_eq!(three.get(id).unwrap(), "https://su-3");
+                moved += 1;
+            }
+        }
+        assert!(moved > 0 && moved < ids.len() / 2);
+    }
+}
@@ -1,7 +1,13 @@
-use crate::domain::core::dal::StoreErrorType;
-use crate::domain::flows::{init_builder, Deps};
-use serde::Deserialize;
+use crate::domain::core::dal::{FlowError, StoreErrorType};
+use crate::domain::core::ring::HashRing;
+use crate::domain::flows::Deps;
+use bytes::Bytes;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::BTreeMap;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use std::{fmt::Debug, sync::Arc};
+use tokio::task::JoinSet;
 use tokio::{fs::File, io::AsyncReadExt};
 
 /*
@@ -13,26 +19,59 @@ use tokio::{fs::File, io::AsyncReadExt};
     a file. It is a basic load balancer implementation
 */
 
+#[derive(Clone)]
 pub struct Scheduler {
     pub row_id: Option<i32>,
     pub url: String,
     pub process_count: i32,
 }
 
+#[derive(Clone)]
 pub struct ProcessScheduler {
     pub row_id: Option<i32>,
     pub process_id: String,
     pub scheduler_row_id: i32,
 }
 
+// a scheduler as the router saw it when assigning a process
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct DecisionCandidate {
+    pub url: String,
+    pub process_count: i32,
+    pub weight: f64,
+    pub region: Option<String>,
+}
+
+/*
+    Every time the router gives a process to a scheduler,
+    or an operator moves it, the choice is stored with the
+    schedulers it was picked from so a placement can be
+    explained later. strategy is the ASSIGNMENT_STRATEGY
+    used or "move", a move has no candidates.
+*/
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AssignmentDecision {
+    pub process_id: String,
+    pub scheduler_url: String,
+    pub strategy: String,
+    pub region: Option<String>,
+    pub candidates: Vec<DecisionCandidate>,
+    pub timestamp: i64,
+}
+
 #[derive(Deserialize, Debug)]
 struct SchedulerEntry {
     url: String,
+    // share of new processes, 0 drains the scheduler
+    weight: Option<f64>,
+    // new processes from requests in this region prefer the scheduler
+    region: Option<String>,
 }
 
 /*
     this runs at server startup in router mode to
-    initialize the schedulers if they dont exist
+    initialize the schedulers if they dont exist, and
+    again on a reload to pick up new ones and weights
 */
 pub async fn init_schedulers(deps: Arc<Deps>) -> Result<String, String> {
     let mut file = File::open(&deps.config.scheduler_list_path())
@@ -51,39 +90,98 @@ pub async fn init_schedulers(deps: Arc<Deps>) -> Result<String, String> {
         Iterate over the URLs and check each one
         if the scheduler doesnt exist yet create it
     */
+    let mut weights = BTreeMap::new();
+    let mut regions = BTreeMap::new();
     for entry in urls {
-        if let Err(StoreErrorType::NotFound(_)) = deps.data_store.get_scheduler_by_url(&entry.url) {
+        register_scheduler(&deps, &entry.url).await?;
+        if let Some(weight) = entry.weight {
+            if weight == 0.0 && deps.runtime.scheduler_weight(&entry.url) != 0.0 {
+                deps.route_cache.invalidate_scheduler(&entry.url);
+            }
+            weights.insert(entry.url.clone(), weight);
+        }
+        if let Some(region) = entry.region {
+            regions.insert(entry.url, region);
+        }
+    }
+    deps.runtime.replace_scheduler_weights(weights)?;
+    deps.runtime.replace_scheduler_regions(regions)?;
+
+    Ok("schedulers initialized".to_string())
+}
+
+// saves the scheduler unless it exists, returns whether it was new
+pub async fn register_scheduler(deps: &Arc<Deps>, url: &String) -> Result<bool, String> {
+    match deps.data_store.get_scheduler_by_url(url).await {
+        Err(StoreErrorType::NotFound(_)) => {
             let scheduler = Scheduler {
                 row_id: None,
-                url: entry.url.clone(),
+                url: url.clone(),
                 process_count: 0,
             };
-            deps.data_store.save_scheduler(&scheduler)?;
-            deps.logger
-                .log(format!("saved new scheduler: {}", entry.url));
+            deps.data_store.save_scheduler(&scheduler).await?;
+            deps.logger.log(format!("saved new scheduler: {}", url));
+            Ok(true)
         }
+        Err(e) => Err(format!("{:?}", e)),
+        Ok(_) => Ok(false),
     }
-
-    Ok("schedulers initialized".to_string())
 }
 
 // if this returns Ok(Some(String)) then the server should return a redirect to the String
 pub async fn redirect_process_id(
     deps: Arc<Deps>,
     process_id: Option<String>,
-) -> Result<Option<String>, String> {
+) -> Result<Option<String>, FlowError> {
     if deps.config.mode() != "router" {
         return Ok(None);
     }
 
-    let pid = process_id.ok_or("No process-id query parameter provided")?;
+    let pid = process_id.ok_or(FlowError::Validation(
+        "No process-id query parameter provided".to_string(),
+    ))?;
 
     // every other process_id, redirect
-    let process_scheduler = deps.data_store.get_process_scheduler(&pid)?;
+    Ok(Some(process_scheduler_url(&deps, &pid).await?))
+}
+
+// the url of the scheduler of a process, from the route cache while it is fresh
+async fn scheduler_url(deps: &Arc<Deps>, process_id: &str) -> Result<String, StoreErrorType> {
+    if let Some(url) = deps.route_cache.get(process_id) {
+        return Ok(url);
+    }
+    let process_scheduler = deps.data_store.get_process_scheduler(process_id).await?;
     let scheduler = deps
         .data_store
-        .get_scheduler(&process_scheduler.scheduler_row_id)?;
-    Ok(Some(scheduler.url))
+        .get_scheduler(&process_scheduler.scheduler_row_id)
+        .await?;
+    deps.route_cache.put(process_id, &scheduler.url);
+    Ok(scheduler.url)
+}
+
+/*
+    scheduler_url for an id known to be a process. With
+    consistent hashing a process the store has no record
+    of, ex. after the router database was lost, is found
+    on the ring instead
+*/
+async fn process_scheduler_url(deps: &Arc<Deps>, process_id: &str) -> Result<String, FlowError> {
+    match scheduler_url(deps, process_id).await {
+        Err(StoreErrorType::NotFound(_))
+            if deps.config.assignment_strategy() == "consistent-hash" =>
+        {
+            let schedulers = deps.data_store.get_all_schedulers().await?;
+            let url = scheduler_ring(deps, &schedulers)
+                .get(process_id)
+                .cloned()
+                .ok_or(FlowError::Unavailable(
+                    "Could not find a scheduler for the process".to_string(),
+                ))?;
+            deps.route_cache.put(process_id, &url);
+            Ok(url)
+        }
+        result => Ok(result?),
+    }
 }
 
 // if this returns Ok(Some(String)) then the server should return a redirect to the String
@@ -91,62 +189,131 @@ pub async fn redirect_tx_id(
     deps: Arc<Deps>,
     tx_id: String,
     process_id: Option<String>,
-) -> Result<Option<String>, String> {
+) -> Result<Option<String>, FlowError> {
     if deps.config.mode() != "router" {
         return Ok(None);
     }
 
-    let process_to_query = match deps.data_store.get_process_scheduler(&tx_id) {
-        Ok(_) => tx_id,
+    match scheduler_url(&deps, &tx_id).await {
+        Ok(url) => Ok(Some(url)),
         /*
             we didn't find a process scheduler based on the tx_id
-            so we need to try and find one based on process_id query param
+            so we need to try and find one based on process_id query param,
+            without one the schedulers are asked for the message
         */
-        Err(_) => process_id.ok_or("Unable to locate process, if this is a message id query be sure to pass the process-id query parameter")?,
-    };
+        Err(_) => {
+            if let Some(process_to_query) = process_id {
+                return Ok(Some(process_scheduler_url(&deps, &process_to_query).await?));
+            }
+            let url = message_scheduler_url(&deps, &tx_id).await?;
+            Ok(Some(url.ok_or(FlowError::NotFound("Unable to locate process or message, for a message to a private process pass the process-id query parameter".to_string()))?))
+        }
+    }
+}
 
-    let process_scheduler = deps.data_store.get_process_scheduler(&process_to_query)?;
-    let scheduler = deps
-        .data_store
-        .get_scheduler(&process_scheduler.scheduler_row_id)?;
-    Ok(Some(scheduler.url))
+/*
+    asks every scheduler at once where a message id was
+    assigned. The one holding the earliest assignment,
+    the original message, wins. None when no scheduler
+    knows the id
+*/
+async fn message_scheduler_url(deps: &Arc<Deps>, id: &str) -> Result<Option<String>, FlowError> {
+    let schedulers = deps.data_store.get_all_schedulers().await?;
+    let mut lookups = JoinSet::new();
+    for scheduler in schedulers.into_iter() {
+        let (deps, id) = (deps.clone(), id.to_string());
+        lookups.spawn(async move {
+            let found = first_assignment_on(&deps, &scheduler.url, &id).await;
+            (scheduler.url, found)
+        });
+    }
+
+    let mut earliest: Option<(i64, String)> = None;
+    while let Some(joined) = lookups.join_next().await {
+        let (url, found) = match joined {
+            Ok(lookup) => lookup,
+            Err(_) => continue,
+        };
+        match found {
+            Ok(Some(timestamp)) if earliest.as_ref().map_or(true, |(t, _)| timestamp < *t) => {
+                earliest = Some((timestamp, url))
+            }
+            Ok(_) => (),
+            Err(e) => deps.logger.error(format!(
+                "message lookup on scheduler {} failed - {}",
+                url, e
+            )),
+        }
+    }
+    Ok(earliest.map(|(_, url)| url))
+}
+
+// the timestamp of the first assignment of a message on one scheduler
+async fn first_assignment_on(deps: &Arc<Deps>, url: &str, id: &str) -> Result<Option<i64>, String> {
+    let breaker = deps.breakers.scheduler(url);
+    breaker.allow()?;
+    let response = deps
+        .scheduler_http
+        .get(format!("{}/messages/{}/assignments", url, id))
+        .timeout(Duration::from_millis(deps.config.scheduler_timeout_ms()))
+        .send()
+        .await;
+    breaker.record(match &response {
+        Ok(r) => !r.status().is_server_error(),
+        Err(_) => false,
+    });
+    let response = response.map_err(|e| format!("{}", e))?;
+    if response.status() == reqwest::StatusCode::NOT_FOUND {
+        return Ok(None);
+    }
+    let located: serde_json::Value = response
+        .error_for_status()
+        .map_err(|e| format!("{}", e))?
+        .json()
+        .await
+        .map_err(|e| format!("{}", e))?;
+    Ok(located["assignments"][0]["timestamp"].as_i64())
 }
 
 // if this returns Ok(Some(String)) then the server should return a redirect to the String
 pub async fn redirect_data_item(
     deps: Arc<Deps>,
-    input: Vec<u8>,
+    input: Bytes,
     process_id: Option<String>,
     assign: Option<String>,
-) -> Result<Option<String>, String> {
+    region: Option<String>,
+) -> Result<Option<String>, FlowError> {
     if deps.config.mode() != "router" {
         return Ok(None);
     }
 
     // XOR, if we have one of these, we must have both.
     if process_id.is_some() ^ assign.is_some() {
-        return Err("If sending assign or process-id, you must send both.".to_string());
+        return Err(FlowError::Validation(
+            "If sending assign or process-id, you must send both.".to_string(),
+        ));
     } else if let (Some(process_id), Some(_assign)) = (process_id, assign) {
-        match deps.data_store.get_process_scheduler(&process_id) {
-            Ok(process_scheduler) => {
-                let scheduler = deps
-                    .data_store
-                    .get_scheduler(&process_scheduler.scheduler_row_id)?;
-                return Ok(Some(scheduler.url));
+        match process_scheduler_url(&deps, &process_id).await {
+            Ok(url) => return Ok(Some(url)),
+            Err(FlowError::NotFound(_)) => {
+                return Err(FlowError::NotFound(
+                    "Unable to locate scheduler for process-id".to_string(),
+                ))
             }
-            Err(_) => return Err("Unable to locate scheduler for process-id".to_string()),
+            Err(e) => return Err(e),
         }
     }
 
-    let builder = init_builder(&deps)?;
-    let item = builder.parse_data_item(input.clone())?;
-    let tags = item.tags().clone();
+    let item = deps.ingest.parse(input).await?;
+    let tags = item.tags();
     let id = item.id().clone();
     let target = item.target().clone();
     let type_tag = tags
         .iter()
         .find(|tag| tag.name == "Type")
-        .ok_or("Cannot redirect data item, invalid Type Tag")?;
+        .ok_or(FlowError::Validation(
+            "Cannot redirect data item, invalid Type Tag".to_string(),
+        ))?;
 
     match type_tag.value.as_str() {
         "Process" => {
@@ -154,48 +321,267 @@ pub async fn redirect_data_item(
                 new process so we need to generate a
                 process_schedulers record and return the url
             */
-            let mut schedulers = deps.data_store.get_all_schedulers()?;
-            if let Some(min_scheduler) = schedulers.iter_mut().min_by_key(|s| s.process_count) {
-                min_scheduler.process_count += 1;
-                deps.data_store.update_scheduler(min_scheduler)?;
-
-                let scheduler_row_id = if let Some(min_scheduler_row_id) = min_scheduler.row_id {
-                    min_scheduler_row_id
-                } else {
-                    /*
-                        this should be unreachable but return an error
-                        just in case so the router doesn't crash
-                    */
-                    return Err("Missing id on scheduler".to_string());
-                };
-
-                let process_scheduler = ProcessScheduler {
-                    row_id: None,
-                    scheduler_row_id: scheduler_row_id,
-                    process_id: id,
-                };
-                deps.data_store.save_process_scheduler(&process_scheduler)?;
-
-                Ok(Some(min_scheduler.url.clone()))
-            } else {
-                Err("Could not find a scheduler to assign".to_string())
-            }
+            let mut schedulers = deps.data_store.get_all_schedulers().await?;
+            Ok(Some(
+                assign_process(&deps, id, &mut schedulers, region.as_deref()).await?,
+            ))
         }
         "Message" => {
             /*
                 otherwise, fetch the correct scheduler based
                 on the messages's target
             */
-            match deps.data_store.get_process_scheduler(&target) {
-                Ok(process_scheduler) => {
-                    let scheduler = deps
-                        .data_store
-                        .get_scheduler(&process_scheduler.scheduler_row_id)?;
-                    Ok(Some(scheduler.url))
-                }
-                Err(_) => Err("Unable to locate scheduler for message target".to_string()),
+            match process_scheduler_url(&deps, &target).await {
+                Ok(url) => Ok(Some(url)),
+                Err(FlowError::NotFound(_)) => Err(FlowError::NotFound(
+                    "Unable to locate scheduler for message target".to_string(),
+                )),
+                Err(e) => Err(e),
             }
         }
-        _ => Err("Cannot redirect data item, invalid Type Tag".to_string()),
+        _ => Err(FlowError::Validation(
+            "Cannot redirect data item, invalid Type Tag".to_string(),
+        )),
     }
 }
+
+/*
+    the scheduler with the fewest processes for its weight,
+    among those in region while one of them takes processes
+*/
+fn least_loaded(deps: &Arc<Deps>, schedulers: &[Scheduler], region: Option<&str>) -> Option<usize> {
+    let regional = region.and_then(|region| {
+        lightest(deps, schedulers, |s| {
+            deps.runtime
+                .scheduler_region(&s.url)
+                .is_some_and(|r| r.eq_ignore_ascii_case(region))
+        })
+    });
+    regional.or_else(|| lightest(deps, schedulers, |_| true))
+}
+
+fn lightest(
+    deps: &Arc<Deps>,
+    schedulers: &[Scheduler],
+    eligible: impl Fn(&Scheduler) -> bool,
+) -> Option<usize> {
+    schedulers
+        .iter()
+        .enumerate()
+        .filter(|(_, s)| eligible(s))
+        .filter_map(|(i, s)| {
+            let weight = deps.runtime.scheduler_weight(&s.url);
+            (weight > 0.0).then(|| (i, f64::from(s.process_count) / weight))
+        })
+        .min_by(|a, b| a.1.total_cmp(&b.1))
+        .map(|(i, _)| i)
+}
+
+fn scheduler_ring(deps: &Arc<Deps>, schedulers: &[Scheduler]) -> HashRing {
+    let weighted: Vec<(String, f64)> = schedulers
+        .iter()
+        .map(|s| (s.url.clone(), deps.runtime.scheduler_weight(&s.url)))
+        .collect();
+    HashRing::new(&weighted, deps.config.hash_virtual_nodes())
+}
+
+/*
+    give a new process to a scheduler, the least loaded one
+    or with ASSIGNMENT_STRATEGY consistent-hash the one the
+    ring puts it on. schedulers is updated in place so a
+    batch of assignments keeps balancing against the new
+    counts. A scheduler weighted 0 gets none. The region of
+    the request only steers least-loaded, the ring has to
+    stay the same for every request to find a process.
+*/
+async fn assign_process(
+    deps: &Arc<Deps>,
+    process_id: String,
+    schedulers: &mut [Scheduler],
+    region: Option<&str>,
+) -> Result<String, FlowError> {
+    let strategy = deps.config.assignment_strategy();
+    let candidates: Vec<DecisionCandidate> = schedulers
+        .iter()
+        .map(|s| DecisionCandidate {
+            url: s.url.clone(),
+            process_count: s.process_count,
+            weight: deps.runtime.scheduler_weight(&s.url),
+            region: deps.runtime.scheduler_region(&s.url),
+        })
+        .collect();
+    let chosen = match strategy.as_str() {
+        "consistent-hash" => {
+            let ring = scheduler_ring(deps, schedulers);
+            ring.get(&process_id)
+                .and_then(|url| schedulers.iter().position(|s| &s.url == url))
+        }
+        _ => least_loaded(deps, schedulers, region),
+    };
+    if let Some(scheduler) = chosen.map(|i| &mut schedulers[i]) {
+        scheduler.process_count += 1;
+        deps.data_store.update_scheduler(scheduler).await?;
+
+        let scheduler_row_id = if let Some(scheduler_row_id) = scheduler.row_id {
+            scheduler_row_id
+        } else {
+            /*
+                this should be unreachable but return an error
+                just in case so the router doesn't crash
+            */
+            return Err(FlowError::Internal("Missing id on scheduler".to_string()));
+        };
+
+        let process_scheduler = ProcessScheduler {
+            row_id: None,
+            scheduler_row_id,
+            process_id,
+        };
+        deps.data_store
+            .save_process_scheduler(&process_scheduler)
+            .await?;
+        deps.route_cache
+            .put(&process_scheduler.process_id, &scheduler.url);
+        record_decision(
+            deps,
+            AssignmentDecision {
+                process_id: process_scheduler.process_id,
+                scheduler_url: scheduler.url.clone(),
+                strategy,
+                region: region.map(|r| r.to_string()),
+                candidates,
+                timestamp: unix_ms(),
+            },
+        )
+        .await;
+
+        Ok(scheduler.url.clone())
+    } else {
+        Err(FlowError::Unavailable(
+            "Could not find a scheduler to assign".to_string(),
+        ))
+    }
+}
+
+/*
+    points the router at another su for a process, once it
+    was exported from its old su and imported into the new
+    one. Redirects follow the move right away.
+*/
+pub async fn move_process(
+    deps: &Arc<Deps>,
+    process_id: &str,
+    url: &String,
+) -> Result<bool, String> {
+    let process_scheduler = deps.data_store.get_process_scheduler(process_id).await?;
+    let target = match deps.data_store.get_scheduler_by_url(url).await {
+        Ok(scheduler) => scheduler,
+        Err(StoreErrorType::NotFound(_)) => {
+            return Err(format!("{} is not a registered scheduler", url))
+        }
+        Err(e) => return Err(format!("{:?}", e)),
+    };
+    let to_row_id = target.row_id.ok_or("Missing id on scheduler")?;
+    if to_row_id == process_scheduler.scheduler_row_id {
+        return Ok(false);
+    }
+
+    deps.data_store
+        .move_process_scheduler(process_id, &process_scheduler.scheduler_row_id, &to_row_id)
+        .await?;
+    deps.route_cache.invalidate(process_id);
+    record_decision(
+        deps,
+        AssignmentDecision {
+            process_id: process_id.to_string(),
+            scheduler_url: url.clone(),
+            strategy: "move".to_string(),
+            region: None,
+            candidates: vec![],
+            timestamp: unix_ms(),
+        },
+    )
+    .await;
+    Ok(true)
+}
+
+// the process is already placed, a lost audit entry only gets logged
+async fn record_decision(deps: &Arc<Deps>, decision: AssignmentDecision) {
+    if let Err(e) = deps.data_store.save_assignment_decision(&decision).await {
+        deps.logger.error(format!(
+            "failed to record the assignment of {}: {:?}",
+            decision.process_id, e
+        ));
+    }
+}
+
+fn unix_ms() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as i64)
+        .unwrap_or(0)
+}
+
+pub const MAX_LOCATE_BATCH: usize = 1000;
+
+/*
+    An MU starting up asks for the schedulers of all its
+    processes in one request instead of following one
+    redirect per process. With assign set unknown ids are
+    treated as new processes and assigned like a spawn,
+    otherwise they map to null.
+*/
+pub async fn locate_processes(
+    deps: Arc<Deps>,
+    process_ids: Vec<String>,
+    assign: bool,
+    region: Option<String>,
+) -> Result<String, String> {
+    if deps.config.mode() != "router" {
+        return Err("Process locations are only served in router mode".to_string());
+    }
+    if process_ids.len() > MAX_LOCATE_BATCH {
+        return Err(format!(
+            "At most {} process ids can be located at once",
+            MAX_LOCATE_BATCH
+        ));
+    }
+
+    let mut schedulers = deps.data_store.get_all_schedulers().await?;
+    let mut locations: BTreeMap<String, Option<String>> = BTreeMap::new();
+
+    for process_id in process_ids.into_iter() {
+        if locations.contains_key(&process_id) {
+            continue;
+        }
+
+        if let Some(url) = deps.route_cache.get(&process_id) {
+            locations.insert(process_id, Some(url));
+            continue;
+        }
+
+        let url = match deps.data_store.get_process_scheduler(&process_id).await {
+            Ok(process_scheduler) => schedulers
+                .iter()
+                .find(|s| s.row_id == Some(process_scheduler.scheduler_row_id))
+                .map(|s| {
+                    deps.route_cache.put(&process_id, &s.url);
+                    s.url.clone()
+                }),
+            Err(StoreErrorType::NotFound(_)) if assign => Some(
+                assign_process(
+                    &deps,
+                    process_id.clone(),
+                    &mut schedulers,
+                    region.as_deref(),
+                )
+                .await?,
+            ),
+            Err(StoreErrorType::NotFound(_)) => None,
+            Err(e) => return Err(format!("{:?}", e)),
+        };
+        locations.insert(process_id, url);
+    }
+
+    Ok(json!({ "locations": locations }).to_string())
+}
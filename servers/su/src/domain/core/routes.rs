@@ -0,0 +1,114 @@
+use std::time::{Duration, Instant};
+
+use dashmap::DashMap;
+
+/*
+    Process to scheduler url cache of the router so a
+    redirect is served from memory instead of two store
+    lookups. Entries expire after the ttl, which bounds
+    how stale a redirect can be. Moving a process or
+    draining a scheduler drops its entries right away.
+    A ttl of 0 turns the cache off.
+*/
+pub struct RouteCache {
+    entries: DashMap<String, (String, Instant)>,
+    ttl: Duration,
+    max_entries: usize,
+}
+
+impl RouteCache {
+    pub fn new(ttl_ms: u64, max_entries: usize) -> Self {
+        RouteCache {
+            entries: DashMap::new(),
+            ttl: Duration::from_millis(ttl_ms),
+            max_entries,
+        }
+    }
+
+    pub fn get(&self, process_id: &str) -> Option<String> {
+        self.get_at(process_id, Instant::now())
+    }
+
+    fn get_at(&self, process_id: &str, now: Instant) -> Option<String> {
+        if let Some(entry) = self.entries.get(process_id) {
+            if now.saturating_duration_since(entry.1) < self.ttl {
+                return Some(entry.0.clone());
+            }
+        }
+        self.entries.remove_if(process_id, |_, entry| {
+            now.saturating_duration_since(entry.1) >= self.ttl
+        });
+        None
+    }
+
+    pub fn put(&self, process_id: &str, url: &str) {
+        self.put_at(process_id, url, Instant::now())
+    }
+
+    // once full expired entries are dropped, if none are new routes are not cached
+    fn put_at(&self, process_id: &str, url: &str, now: Instant) {
+        if self.ttl.is_zero() || self.max_entries == 0 {
+            return;
+        }
+        if self.entries.len() >= self.max_entries && !self.entries.contains_key(process_id) {
+            let ttl = self.ttl;
+            self.entries
+                .retain(|_, entry| now.saturating_duration_since(entry.1) < ttl);
+            if self.entries.len() >= self.max_entries {
+                return;
+            }
+        }
+        self.entries
+            .insert(process_id.to_string(), (url.to_string(), now));
+    }
+
+    pub fn invalidate(&self, process_id: &str) {
+        self.entries.remove(process_id);
+    }
+
+    // every process routed to the scheduler is looked up again
+    pub fn invalidate_scheduler(&self, url: &str) {
+        self.entries.retain(|_, entry| entry.0 != url);
+    }
+
+    pub fn clear(&self) {
+        self.entries.clear();
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_route_cache() {
+        let cache = RouteCache::new(1000, 2);
+        let start = Instant::now();
+
+        cache.put_at("process-1", "https://su-1", start);
+        cache.put_at("process-2", "https://su-2", start);
+        // full, the third route is not cached
+        cache.put_at("process-3", "https://su-1", start);
+        assert_eq!(cache.len(), 2);
+        assert_eq!(
+            cache.get_at("process-1", start).as_deref(),
+            Some("https://su-1")
+        );
+
+        cache.invalidate_scheduler("https://su-1");
+        assert!(cache.get_at("process-1", start).is_none());
+        assert!(cache.get_at("process-2", start).is_some());
+
+        let later = start + Duration::from_millis(1000);
+        assert!(cache.get_at("process-2", later).is_none());
+        assert_eq!(cache.len(), 0);
+
+        let off = RouteCache::new(0, 10);
+        off.put_at("process-1", "https://su-1", start);
+        assert!(off.get_at("process-1", start).is_none());
+    }
+}
@@ -0,0 +1,285 @@
+use std::collections::BTreeMap;
+use std::str::FromStr;
+use std::sync::RwLock;
+
+use log::LevelFilter;
+use serde::{Deserialize, Serialize};
+
+use super::flows::Deps;
+
+/*
+    The settings that can change while the su is running,
+    through POST /admin/runtime or a reload on SIGHUP. The
+    rate limiter, the read cache and the router read them
+    on every request, a change never waits on or interrupts
+    a write that is being sequenced.
+*/
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RuntimeSettings {
+    pub rate_limit_per_second: f64,
+    pub rate_limit_burst: u32,
+    pub cache_max_entries: usize,
+    pub log_level: String,
+    // writes are rejected, reads keep working
+    pub read_only: bool,
+    // router mode, a scheduler without a weight has 1, 0 takes no new processes
+    pub scheduler_weights: BTreeMap<String, f64>,
+    // router mode, new processes prefer a scheduler in the region of the request
+    pub scheduler_regions: BTreeMap<String, String>,
+}
+
+// a partial change, unset fields keep their value
+#[derive(Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub struct RuntimeUpdate {
+    pub rate_limit_per_second: Option<f64>,
+    pub rate_limit_burst: Option<u32>,
+    pub cache_max_entries: Option<usize>,
+    pub log_level: Option<String>,
+    pub read_only: Option<bool>,
+    // merged into the current weights
+    pub scheduler_weights: Option<BTreeMap<String, f64>>,
+    // merged into the current regions, an empty region removes one
+    pub scheduler_regions: Option<BTreeMap<String, String>>,
+}
+
+impl RuntimeUpdate {
+    // the reloadable ones among the settings of a config file
+    pub fn from_settings(settings: &[(String, String)]) -> Result<Self, String> {
+        fn parse<T: FromStr>(name: &str, value: &str) -> Result<T, String> {
+            value
+                .parse()
+                .map_err(|_| format!("{}={} is not valid", name, value))
+        }
+
+        let mut update = RuntimeUpdate::default();
+        for (name, value) in settings.iter() {
+            match name.as_str() {
+                "RATE_LIMIT_PER_SECOND" => update.rate_limit_per_second = Some(parse(name, value)?),
+                "RATE_LIMIT_BURST" => update.rate_limit_burst = Some(parse(name, value)?),
+                "CACHE_MAX_ENTRIES" => update.cache_max_entries = Some(parse(name, value)?),
+                "LOG_LEVEL" => update.log_level = Some(value.clone()),
+                "READ_ONLY" => update.read_only = Some(parse(name, value)?),
+                _ => (),
+            }
+        }
+        Ok(update)
+    }
+}
+
+pub fn parse_level(level: &str) -> Result<LevelFilter, String> {
+    LevelFilter::from_str(level).map_err(|_| {
+        format!(
+            "{} is not a log level, use off, error, warn, info, debug or trace",
+            level
+        )
+    })
+}
+
+fn check_weight(url: &str, weight: f64) -> Result<(), String> {
+    if weight.is_finite() && weight >= 0.0 {
+        Ok(())
+    } else {
+        Err(format!("weight of {} must be 0 or more", url))
+    }
+}
+
+impl RuntimeSettings {
+    // the settings with update applied, nothing changes if any value is invalid
+    pub fn merged(&self, update: RuntimeUpdate) -> Result<Self, String> {
+        let mut next = self.clone();
+        if let Some(per_second) = update.rate_limit_per_second {
+            if !(per_second.is_finite() && per_second >= 0.0) {
+                return Err("rate_limit_per_second must be 0 or more".to_string());
+            }
+            next.rate_limit_per_second = per_second;
+        }
+        if let Some(burst) = update.rate_limit_burst {
+            if burst == 0 {
+                return Err("rate_limit_burst must be at least 1".to_string());
+            }
+            next.rate_limit_burst = burst;
+        }
+        if let Some(max_entries) = update.cache_max_entries {
+            next.cache_max_entries = max_entries;
+        }
+        if let Some(level) = update.log_level {
+            next.log_level = parse_level(&level)?.to_string().to_lowercase();
+        }
+        if let Some(read_only) = update.read_only {
+            next.read_only = read_only;
+        }
+        if let Some(weights) = update.scheduler_weights {
+            for (url, weight) in weights.into_iter() {
+                check_weight(&url, weight)?;
+                next.scheduler_weights.insert(url, weight);
+            }
+        }
+        if let Some(regions) = update.scheduler_regions {
+            for (url, region) in regions.into_iter() {
+                match region.is_empty() {
+                    true => next.scheduler_regions.remove(&url),
+                    false => next.scheduler_regions.insert(url, region),
+                };
+            }
+        }
+        Ok(next)
+    }
+}
+
+pub struct Runtime {
+    settings: RwLock<RuntimeSettings>,
+}
+
+impl Runtime {
+    pub fn new(settings: RuntimeSettings) -> Self {
+        Runtime {
+            settings: RwLock::new(settings),
+        }
+    }
+
+    pub fn settings(&self) -> RuntimeSettings {
+        match self.settings.read() {
+            Ok(settings) => settings.clone(),
+            Err(poisoned) => poisoned.into_inner().clone(),
+        }
+    }
+
+    pub fn read_only(&self) -> bool {
+        match self.settings.read() {
+            Ok(settings) => settings.read_only,
+            Err(poisoned) => poisoned.into_inner().read_only,
+        }
+    }
+
+    pub fn scheduler_weight(&self, url: &str) -> f64 {
+        match self.settings.read() {
+            Ok(settings) => settings.scheduler_weights.get(url).copied().unwrap_or(1.0),
+            Err(_) => 1.0,
+        }
+    }
+
+    // the weights of the scheduler list file replace the current ones
+    pub fn replace_scheduler_weights(&self, weights: BTreeMap<String, f64>) -> Result<(), String> {
+        for (url, weight) in weights.iter() {
+            check_weight(url, *weight)?;
+        }
+        let mut settings = self
+            .settings
+            .write()
+            .map_err(|_| "runtime settings unavailable".to_string())?;
+        settings.scheduler_weights = weights;
+        Ok(())
+    }
+
+    pub fn scheduler_region(&self, url: &str) -> Option<String> {
+        match self.settings.read() {
+            Ok(settings) => settings.scheduler_regions.get(url).cloned(),
+            Err(_) => None,
+        }
+    }
+
+    // the regions of the scheduler list file replace the current ones
+    pub fn replace_scheduler_regions(
+        &self,
+        regions: BTreeMap<String, String>,
+    ) -> Result<(), String> {
+        let mut settings = self
+            .settings
+            .write()
+            .map_err(|_| "runtime settings unavailable".to_string())?;
+        settings.scheduler_regions = regions;
+        Ok(())
+    }
+}
+
+/*
+    applies update to the rate limiter, the read cache and
+    the log level, returns the settings now in effect
+*/
+pub fn apply(deps: &Deps, update: RuntimeUpdate) -> Result<RuntimeSettings, String> {
+    let mut settings = deps
+        .runtime
+        .settings
+        .write()
+        .map_err(|_| "runtime settings unavailable".to_string())?;
+    let next = settings.merged(update)?;
+
+    // a scheduler being drained is about to hand its processes over
+    for (url, weight) in next.scheduler_weights.iter() {
+        if *weight == 0.0 && settings.scheduler_weights.get(url) != Some(weight) {
+            deps.route_cache.invalidate_scheduler(url);
+        }
+    }
+
+    deps.rate_limiter
+        .set_limits(next.rate_limit_per_second, next.rate_limit_burst);
+    deps.cache.set_max_entries(next.cache_max_entries);
+    log::set_max_level(parse_level(&next.log_level)?);
+
+    *settings = next.clone();
+    Ok(next)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings() -> RuntimeSettings {
+        RuntimeSettings {
+            rate_limit_per_second: 0.0,
+            rate_limit_burst: 20,
+            cache_max_entries: 10000,
+            log_level: "info".to_string(),
+            read_only: false,
+            scheduler_weights: BTreeMap::new(),
+            scheduler_regions: BTreeMap::from([("https://su-2".to_string(), "eu".to_string())]),
+        }
+    }
+
+    #[test]
+    fn test_merged() {
+        let update: RuntimeUpdate = serde_json::from_str(
+            r#"{"rate_limit_per_second": 5, "log_level": "DEBUG", "read_only": true, "scheduler_weights": {"https://su-1": 0}, "scheduler_regions": {"https://su-1": "us", "https://su-2": ""}}"#,
+        )
+        .unwrap();
+        let next = settings().merged(update).unwrap();
+        assert_eq!(next.rate_limit_per_second, 5.0);
+        assert_eq!(next.rate_limit_burst, 20);
+        assert_eq!(next.log_level, "debug");
+        assert!(next.read_only);
+        assert_eq!(next.scheduler_weights.get("https://su-1"), Some(&0.0));
+        assert_eq!(
+            next.scheduler_regions,
+            BTreeMap::from([("https://su-1".to_string(), "us".to_string())])
+        );
+
+        let invalid = RuntimeUpdate {
+            rate_limit_burst: Some(0),
+            ..Default::default()
+        };
+        assert!(settings().merged(invalid).is_err());
+        let invalid = RuntimeUpdate {
+            log_level: Some("loud".to_string()),
+            ..Default::default()
+        };
+        assert!(settings().merged(invalid).is_err());
+        assert!(serde_json::from_str::<RuntimeUpdate>(r#"{"mode": "router"}"#).is_err());
+    }
+
+    #[test]
+    fn test_from_settings() {
+        let file = vec![
+            ("CACHE_MAX_ENTRIES".to_string(), "500".to_string()),
+            ("READ_ONLY".to_string(), "true".to_string()),
+            ("SU_WALLET_PATH".to_string(), "wallet.json".to_string()),
+        ];
+        let update = RuntimeUpdate::from_settings(&file).unwrap();
+        assert_eq!(update.cache_max_entries, Some(500));
+        assert_eq!(update.read_only, Some(true));
+        assert!(update.rate_limit_per_second.is_none());
+
+        let file = vec![("RATE_LIMIT_BURST".to_string(), "many".to_string())];
+        assert!(RuntimeUpdate::from_settings(&file).is_err());
+    }
+}
@@ -1,16 +1,31 @@
+use std::ops::{Deref, DerefMut};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::sync::Arc;
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
 use base64_url;
-use dashmap::DashMap;
+use dashmap::{DashMap, DashSet};
+use serde::Serialize;
 use sha2::{Digest, Sha256};
-use tokio::sync::Mutex;
+use tokio::sync::{Mutex, OwnedMutexGuard};
 
-use crate::domain::core::dal::{DataStore, Log, ScheduleProvider};
+use crate::domain::core::dal::{
+    Assignment, DataStore, DomainEvent, EventBus, FlowError, Gateway, Log, Message,
+    ScheduleProvider,
+};
+use crate::domain::core::leases::{Lease, Leases, BULK_LOAD_HOLDER};
 
 pub struct SchedulerDeps {
     pub data_store: Arc<dyn DataStore>,
+    pub gateway: Arc<dyn Gateway>,
     pub logger: Arc<dyn Log>,
+    pub events: Arc<EventBus>,
+    // how long a lock may be held before a waiter breaks it, 0 never
+    pub lock_deadline_ms: u64,
+    // how long an unused lock is kept in memory, 0 forever
+    pub lock_idle_ms: u64,
+    // cluster mode, a process is only sequenced while its lease is held
+    pub leases: Option<Arc<Leases>>,
 }
 
 /*
@@ -22,10 +37,110 @@ pub struct ScheduleInfo {
     pub nonce: i32,
     pub timestamp: i64,
     pub hash_chain: String,
+    // the arweave height when the slot was taken, zero padded
+    pub block_height: String,
+    /*
+        true when epoch, nonce and hash_chain are the next
+        slot as stored, so the next write can skip the store
+    */
+    pub synced: bool,
+}
+
+impl ScheduleInfo {
+    /*
+        move to the next slot once an item is built, for
+        sequencing several items under one lock before
+        any of them are saved
+    */
+    pub fn advance(&mut self, assignment_id: &str) -> Result<(), String> {
+        self.nonce += 1;
+        self.hash_chain = gen_hash_chain(&self.hash_chain, Some(assignment_id))?;
+        Ok(())
+    }
+
+    // the item built on this slot was saved, move past it
+    pub fn commit(&mut self, assignment_id: &str) -> Result<(), String> {
+        self.advance(assignment_id)?;
+        self.synced = true;
+        Ok(())
+    }
+
+    // saved items were already advanced past, or nothing used the slot
+    pub fn mark_synced(&mut self) {
+        self.synced = true;
+    }
 }
 
 pub type LockedScheduleInfo = Arc<Mutex<ScheduleInfo>>;
 
+struct ProcessLock {
+    info: LockedScheduleInfo,
+    last_used: Instant,
+}
+
+// the task currently holding a process lock
+struct Holder {
+    since: Instant,
+    revoked: Arc<AtomicBool>,
+}
+
+/*
+    A held process lock. If it is held past the deadline
+    it is revoked and the process gets a fresh lock, the
+    stale holder must call check_held before saving so it
+    cannot write into a schedule that moved on without it.
+*/
+pub struct ScheduleGuard {
+    guard: OwnedMutexGuard<ScheduleInfo>,
+    id: String,
+    revoked: Arc<AtomicBool>,
+    holders: Arc<DashMap<String, Holder>>,
+}
+
+impl ScheduleGuard {
+    pub fn check_held(&self) -> Result<(), String> {
+        match self.revoked.load(Ordering::SeqCst) {
+            true => Err(format!(
+                "lock on {} was held past its deadline and released, retry the write",
+                self.id
+            )),
+            false => Ok(()),
+        }
+    }
+}
+
+impl Deref for ScheduleGuard {
+    type Target = ScheduleInfo;
+
+    fn deref(&self) -> &ScheduleInfo {
+        &self.guard
+    }
+}
+
+impl DerefMut for ScheduleGuard {
+    fn deref_mut(&mut self) -> &mut ScheduleInfo {
+        &mut self.guard
+    }
+}
+
+impl Drop for ScheduleGuard {
+    fn drop(&mut self) {
+        // a revoked guard no longer owns the holder entry
+        self.holders
+            .remove_if(&self.id, |_, h| Arc::ptr_eq(&h.revoked, &self.revoked));
+    }
+}
+
+#[derive(Serialize)]
+pub struct LockStats {
+    pub locks_tracked: usize,
+    pub locks_held: usize,
+    pub forced_lock_releases: u64,
+    pub idle_locks_evicted: u64,
+    pub processes_fenced: usize,
+    pub leases_held: usize,
+}
+
 /*
     ProcessScheduler provides a Mutex lock per process to
     ensure there are no conflicts or missing nonces in the sequence
@@ -35,7 +150,13 @@ pub struct ProcessScheduler {
         utilize DashMap to avoid locking up the
         top level data structure
     */
-    locks: Arc<DashMap<String, LockedScheduleInfo>>,
+    locks: Arc<DashMap<String, ProcessLock>>,
+    holders: Arc<DashMap<String, Holder>>,
+    // processes being moved to another su
+    fenced: DashSet<String>,
+    forced_releases: AtomicU64,
+    evictions: AtomicU64,
+    last_sweep: std::sync::Mutex<Instant>,
     deps: Arc<SchedulerDeps>,
 }
 
@@ -43,6 +164,11 @@ impl ProcessScheduler {
     pub fn new(deps: Arc<SchedulerDeps>) -> Self {
         ProcessScheduler {
             locks: Arc::new(DashMap::new()),
+            holders: Arc::new(DashMap::new()),
+            fenced: DashSet::new(),
+            forced_releases: AtomicU64::new(0),
+            evictions: AtomicU64::new(0),
+            last_sweep: std::sync::Mutex::new(Instant::now()),
             deps,
         }
     }
@@ -53,30 +179,209 @@ impl ProcessScheduler {
         build a valid item in the schedule
     */
     pub async fn acquire_lock(&self, id: String) -> Result<LockedScheduleInfo, String> {
+        self.evict_idle();
+
         let locked_schedule_info = {
-            self.locks
-                .entry(id.clone())
-                .or_insert_with(|| {
-                    Arc::new(Mutex::new(ScheduleInfo {
-                        epoch: 0,
-                        nonce: 0,
-                        timestamp: 0,
-                        hash_chain: String::new(),
-                    }))
-                })
-                .value()
-                .clone() // Clone the Arc here
+            let mut lock = self.locks.entry(id.clone()).or_insert_with(|| ProcessLock {
+                info: new_locked_schedule_info(),
+                last_used: Instant::now(),
+            });
+            lock.last_used = Instant::now();
+            lock.info.clone() // Clone the Arc here
         };
 
         Ok(locked_schedule_info)
     }
 
+    /*
+        drop the locks of processes not written to for
+        lock_idle_ms, at most once per lock_idle_ms. Only a
+        lock referenced by nothing but the map is dropped,
+        anyone holding or waiting on it keeps a clone of the
+        Arc, and the next write re-reads the schedule
+    */
+    fn evict_idle(&self) {
+        let idle = Duration::from_millis(self.deps.lock_idle_ms);
+        if idle.is_zero() {
+            return;
+        }
+        match self.last_sweep.try_lock() {
+            Ok(mut last_sweep) if last_sweep.elapsed() >= idle => {
+                *last_sweep = Instant::now();
+            }
+            // not due yet or another request is sweeping
+            _ => return,
+        }
+
+        let mut evicted = 0;
+        self.locks.retain(|_, lock| {
+            let keep = lock.last_used.elapsed() < idle || Arc::strong_count(&lock.info) > 1;
+            if !keep {
+                evicted += 1;
+            }
+            keep
+        });
+        self.evictions.fetch_add(evicted, Ordering::SeqCst);
+    }
+
+    /*
+        wait for the lock of a process. A waiter that finds
+        the holder past the deadline revokes it and swaps in
+        a fresh lock, the schedule info is re-read from the
+        store by update_schedule_info so nothing stale is
+        carried over. In cluster mode the lease of the
+        process is taken too, when it was not held until
+        now the schedule is read again.
+    */
+    pub async fn lock(&self, id: String) -> Result<ScheduleGuard, FlowError> {
+        self.lock_process(id, false).await
+    }
+
+    async fn lock_process(&self, id: String, fencing: bool) -> Result<ScheduleGuard, FlowError> {
+        let deadline = Duration::from_millis(self.deps.lock_deadline_ms);
+        loop {
+            let locked_schedule_info = self.acquire_lock(id.clone()).await?;
+            let acquired = match deadline.is_zero() {
+                true => Some(locked_schedule_info.clone().lock_owned().await),
+                false => tokio::time::timeout(deadline, locked_schedule_info.clone().lock_owned())
+                    .await
+                    .ok(),
+            };
+
+            match acquired {
+                Some(mut guard) => {
+                    // the lock may have been replaced while we waited
+                    let current = self.locks.get(&id).map(|l| l.info.clone());
+                    if !current.is_some_and(|l| Arc::ptr_eq(&l, &locked_schedule_info)) {
+                        continue;
+                    }
+                    if !fencing && self.fenced.contains(&id) {
+                        return Err(FlowError::Unavailable(format!(
+                            "Process {} is being moved to another su, retry the write later",
+                            id
+                        )));
+                    }
+                    if let Some(leases) = &self.deps.leases {
+                        match leases.hold(&self.deps.data_store, &id).await? {
+                            Lease::Held { fresh: true } => guard.synced = false,
+                            Lease::Held { fresh: false } => (),
+                            Lease::Elsewhere(url) if url.starts_with(BULK_LOAD_HOLDER) => {
+                                return Err(FlowError::Unavailable(format!(
+                                    "Process {} is being bulk loaded, retry the write later",
+                                    id
+                                )))
+                            }
+                            Lease::Elsewhere(url) => {
+                                return Err(FlowError::Unavailable(format!(
+                                    "Process {} is sequenced by {}, retry the write there",
+                                    id, url
+                                )))
+                            }
+                        }
+                    }
+                    let revoked = Arc::new(AtomicBool::new(false));
+                    self.holders.insert(
+                        id.clone(),
+                        Holder {
+                            since: Instant::now(),
+                            revoked: revoked.clone(),
+                        },
+                    );
+                    return Ok(ScheduleGuard {
+                        guard,
+                        id,
+                        revoked,
+                        holders: self.holders.clone(),
+                    });
+                }
+                None => self.force_release(&id, &locked_schedule_info, deadline),
+            }
+        }
+    }
+
+    /*
+        stop the process taking writes, for a move to
+        another su. Returns once the write holding its lock
+        is done, every write after it is turned away until
+        unfence, so an export taken now is the whole schedule
+    */
+    pub async fn fence(&self, id: &str) -> Result<(), FlowError> {
+        self.fenced.insert(id.to_string());
+        if let Err(e) = self.lock_process(id.to_string(), true).await {
+            self.fenced.remove(id);
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    // true when the process was fenced
+    pub fn unfence(&self, id: &str) -> bool {
+        self.fenced.remove(id).is_some()
+    }
+
+    fn force_release(&self, id: &String, stale: &LockedScheduleInfo, deadline: Duration) {
+        let held_for = match self.holders.get(id) {
+            Some(holder) if holder.since.elapsed() >= deadline => holder.since.elapsed(),
+            // still queued behind other writers within their deadline
+            _ => return,
+        };
+
+        let replaced = self
+            .locks
+            .get_mut(id)
+            .filter(|l| Arc::ptr_eq(&l.info, stale))
+            .map(|mut l| {
+                l.info = new_locked_schedule_info();
+            })
+            .is_some();
+        if !replaced {
+            return;
+        }
+
+        if let Some((_, holder)) = self.holders.remove(id) {
+            holder.revoked.store(true, Ordering::SeqCst);
+        }
+        self.forced_releases.fetch_add(1, Ordering::SeqCst);
+
+        self.deps.events.publish(DomainEvent::LockForceReleased {
+            process_id: id.clone(),
+            held_ms: held_for.as_millis() as u64,
+        });
+    }
+
+    pub fn lock_stats(&self) -> LockStats {
+        LockStats {
+            locks_tracked: self.locks.len(),
+            locks_held: self.holders.len(),
+            forced_lock_releases: self.forced_releases.load(Ordering::SeqCst),
+            idle_locks_evicted: self.evictions.load(Ordering::SeqCst),
+            processes_fenced: self.fenced.len(),
+            leases_held: self.deps.leases.as_ref().map_or(0, |l| l.held()),
+        }
+    }
+
+    pub fn leases(&self) -> Option<&Arc<Leases>> {
+        self.deps.leases.as_ref()
+    }
+
+    /*
+        after the first write the schedule is kept in memory
+        and the store is only read again when a write did
+        not commit, so whatever it left behind is unknown
+    */
     pub async fn update_schedule_info<'a>(
         &'a self,
         schedule_info: &'a mut ScheduleInfo,
         id: String,
-    ) -> Result<&mut ScheduleInfo, String> {
-        let (current_epoch, current_nonce, current_hash_chain, current_timestamp) =
+    ) -> Result<&'a mut ScheduleInfo, String> {
+        if schedule_info.synced {
+            schedule_info.synced = false;
+            schedule_info.timestamp = now_millis()?;
+            schedule_info.block_height = fetch_height(&self.deps).await?;
+            return Ok(schedule_info);
+        }
+
+        let (current_epoch, current_nonce, current_hash_chain, current_timestamp, current_height) =
             match fetch_values(self.deps.clone(), &id).await {
                 Ok(vals) => vals,
                 Err(e) => return Err(format!("error acquiring scheduler lock {}", e)),
@@ -85,13 +390,24 @@ impl ProcessScheduler {
         schedule_info.nonce = current_nonce;
         schedule_info.hash_chain = current_hash_chain;
         schedule_info.timestamp = current_timestamp;
+        schedule_info.block_height = current_height;
         Ok(schedule_info)
     }
 }
 
+fn new_locked_schedule_info() -> LockedScheduleInfo {
+    Arc::new(Mutex::new(ScheduleInfo {
+        epoch: 0,
+        nonce: 0,
+        timestamp: 0,
+        hash_chain: String::new(),
+        block_height: String::new(),
+        synced: false,
+    }))
+}
+
 pub trait DecodeHash: Sized {
     fn from(base64_url_string: &str) -> Result<Self, String>;
-    fn empty() -> Self;
 }
 
 impl DecodeHash for [u8; 32] {
@@ -104,32 +420,19 @@ impl DecodeHash for [u8; 32] {
                     .map_err(|_| format!("Length mismatch 32 - {base64_url_string}"))
             })
     }
-
-    fn empty() -> Self {
-        [0u8; 32]
-    }
 }
 
-fn gen_hash_chain(
+pub fn gen_hash_chain(
     previous_or_seed: &str,
     previous_message_id: Option<&str>,
 ) -> Result<String, String> {
     let mut hasher = Sha256::new();
 
-    let prev_bytes: [u8; 32] = match DecodeHash::from(previous_or_seed) {
-        Ok(pb) => pb,
-        Err(e) => return Err(e),
-    };
+    let prev_bytes: [u8; 32] = DecodeHash::from(previous_or_seed)?;
 
-    match previous_message_id {
-        Some(id) => {
-            let id_bytes: [u8; 32] = match DecodeHash::from(id) {
-                Ok(idb) => idb,
-                Err(e) => return Err(e),
-            };
-            hasher.update(id_bytes);
-        }
-        None => (),
+    if let Some(id) = previous_message_id {
+        let id_bytes: [u8; 32] = DecodeHash::from(id)?;
+        hasher.update(id_bytes);
     }
 
     hasher.update(prev_bytes);
@@ -139,44 +442,141 @@ fn gen_hash_chain(
     Ok(base64_url::encode(&result))
 }
 
-/*
-    retrieve the epoch, nonce, hash_chain and timestamp
-    increment the values here because this wont be called
-    again until the lock is released.
-*/
-async fn fetch_values(
-    deps: Arc<SchedulerDeps>,
-    process_id: &String,
-) -> Result<(i32, i32, String, i64), String> {
+fn now_millis() -> Result<i64, String> {
     let start_time = SystemTime::now();
     let duration = match start_time.duration_since(UNIX_EPOCH) {
         Ok(d) => d,
         Err(e) => return Err(format!("{:?}", e)),
     };
-    let millis: i64 = duration.as_secs() as i64 * 1000 + i64::from(duration.subsec_millis());
+    Ok(duration.as_secs() as i64 * 1000 + i64::from(duration.subsec_millis()))
+}
 
-    let latest_message = match deps.data_store.get_latest_message(process_id) {
-        Ok(m) => m,
+// the gateway client keeps the height cached, this does not wait on arweave
+async fn fetch_height(deps: &SchedulerDeps) -> Result<String, String> {
+    Ok(deps.gateway.network_info().await?.height)
+}
+
+/*
+    retrieve the epoch, nonce, hash_chain, timestamp and
+    block height. increment the values here because this
+    wont be called again until the lock is released. The
+    slot comes from the process_sequence snapshot, one
+    row per process. A process without a snapshot row,
+    such as one last written before the snapshot was
+    kept, continues from its latest message.
+*/
+async fn fetch_values(
+    deps: Arc<SchedulerDeps>,
+    process_id: &str,
+) -> Result<(i32, i32, String, i64, String), String> {
+    let millis = now_millis()?;
+    let height = fetch_height(&deps).await?;
+
+    let mut sequence = match deps.data_store.get_process_sequence(process_id).await {
+        Ok(s) => s,
         Err(e) => return Err(format!("{:?}", e)),
     };
+    if sequence.is_none() {
+        sequence = match deps.data_store.get_latest_message(process_id).await {
+            Ok(Some(latest)) => Some(Assignment::from_message(&latest)?.sequence()),
+            Ok(None) => None,
+            Err(e) => return Err(format!("{:?}", e)),
+        };
+    }
 
-    match latest_message {
-        Some(previous_message) => {
-            let epoch = previous_message.epoch().unwrap();
-            let nonce = previous_message.nonce().unwrap() + 1;
-            let hash_chain = gen_hash_chain(
-                &previous_message.hash_chain().unwrap(),
-                Some(&previous_message.assignment_id().unwrap()),
-            )?;
-            Ok((epoch, nonce, hash_chain, millis))
+    match sequence {
+        Some(previous) => {
+            let hash_chain = gen_hash_chain(&previous.hash_chain, Some(&previous.assignment_id))?;
+            Ok((
+                previous.epoch,
+                previous.nonce + 1,
+                hash_chain,
+                millis,
+                height,
+            ))
         }
         None => {
-            let hash_chain = gen_hash_chain(&process_id, None)?;
-            Ok((0, 0, hash_chain, millis))
+            let hash_chain = gen_hash_chain(process_id, None)?;
+            Ok((0, 0, hash_chain, millis, height))
         }
     }
 }
 
+/*
+    Walks a schedule being restored checking each message
+    follows the one before it, the nonces have no gaps
+    and the hash chain links up from the process id
+*/
+pub struct ChainCheck {
+    process_id: String,
+    // the epoch, nonce and hash chain the next message must have
+    next: (i32, i32, String),
+}
+
+impl ChainCheck {
+    pub fn new(process_id: &str) -> Result<Self, String> {
+        Ok(ChainCheck {
+            process_id: process_id.to_string(),
+            next: (0, 0, gen_hash_chain(process_id, None)?),
+        })
+    }
+
+    // continue after the latest stored message
+    pub fn resume(latest: &Message) -> Result<Self, String> {
+        let hash_chain = latest.hash_chain()?;
+        Ok(ChainCheck {
+            process_id: latest.process_id()?,
+            next: (
+                latest.epoch()?,
+                latest.nonce()? + 1,
+                gen_hash_chain(&hash_chain, Some(&latest.assignment_id()?))?,
+            ),
+        })
+    }
+
+    // the epoch and nonce the next message must have
+    pub fn next_position(&self) -> (i32, i32) {
+        (self.next.0, self.next.1)
+    }
+
+    pub fn check(&mut self, message: &Message) -> Result<(), String> {
+        self.check_assignment(&Assignment::from_message(message)?)
+    }
+
+    // the chain only needs the assignments, not the messages
+    pub fn check_assignment(&mut self, assignment: &Assignment) -> Result<(), String> {
+        let position = (assignment.epoch, assignment.nonce);
+        let hash_chain = &assignment.hash_chain;
+        if assignment.process_id != self.process_id {
+            return Err(format!(
+                "message {} belongs to another process",
+                assignment.message_id
+            ));
+        }
+
+        let (epoch, nonce, expected) = &self.next;
+        if (*epoch, *nonce) != position {
+            return Err(format!(
+                "expected epoch {} nonce {}, found epoch {} nonce {}",
+                epoch, nonce, position.0, position.1
+            ));
+        }
+        if expected != hash_chain {
+            return Err(format!(
+                "hash chain broken at epoch {} nonce {}",
+                epoch, nonce
+            ));
+        }
+
+        self.next = (
+            position.0,
+            position.1 + 1,
+            gen_hash_chain(hash_chain, Some(&assignment.assignment_id))?,
+        );
+        Ok(())
+    }
+}
+
 impl ScheduleProvider for ScheduleInfo {
     fn epoch(&self) -> String {
         self.epoch.to_string()
@@ -193,4 +593,129 @@ impl ScheduleProvider for ScheduleInfo {
     fn hash_chain(&self) -> String {
         self.hash_chain.to_string()
     }
+
+    fn block_height(&self) -> String {
+        self.block_height.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::domain::core::dal::ProcessSequence;
+    use crate::domain::core::testing::{process_scheduler, SequenceStore};
+
+    fn id(byte: u8) -> String {
+        base64_url::encode(&[byte; 32])
+    }
+
+    #[tokio::test]
+    async fn test_holder_past_deadline_is_revoked() {
+        let scheduler = Arc::new(process_scheduler(Arc::new(SequenceStore::default()), 50, 0));
+        let holder = scheduler.lock(id(1)).await.unwrap();
+        assert!(holder.check_held().is_ok());
+
+        let waiting = scheduler.clone();
+        let waiter = tokio::spawn(async move { waiting.lock(id(1)).await })
+            .await
+            .unwrap()
+            .unwrap();
+
+        assert!(holder.check_held().is_err());
+        assert!(waiter.check_held().is_ok());
+        assert_eq!(scheduler.lock_stats().forced_lock_releases, 1);
+    }
+
+    #[tokio::test]
+    async fn test_waiter_resyncs_after_a_forced_release() {
+        let store = Arc::new(SequenceStore::default());
+        let scheduler = Arc::new(process_scheduler(store.clone(), 50, 0));
+
+        let mut holder = scheduler.lock(id(1)).await.unwrap();
+        scheduler
+            .update_schedule_info(&mut holder, id(1))
+            .await
+            .unwrap();
+        assert_eq!(holder.nonce, 0);
+        holder.commit(&id(2)).unwrap();
+        drop(holder);
+
+        // released normally the next write goes on from memory
+        let mut holder = scheduler.lock(id(1)).await.unwrap();
+        scheduler
+            .update_schedule_info(&mut holder, id(1))
+            .await
+            .unwrap();
+        assert_eq!((holder.nonce, store.reads()), (1, 1));
+
+        // another write saved slot 4 while the holder stalled
+        *store.sequence.lock().unwrap() = Some(ProcessSequence {
+            process_id: id(1),
+            epoch: 0,
+            nonce: 4,
+            hash_chain: id(3),
+            assignment_id: id(4),
+        });
+        let waiting = scheduler.clone();
+        let mut waiter = tokio::spawn(async move { waiting.lock(id(1)).await })
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(!waiter.synced);
+        scheduler
+            .update_schedule_info(&mut waiter, id(1))
+            .await
+            .unwrap();
+        assert_eq!((waiter.nonce, store.reads()), (5, 2));
+        drop(holder);
+    }
+
+    #[tokio::test]
+    async fn test_evicted_lock_reloads() {
+        let store = Arc::new(SequenceStore::default());
+        let scheduler = process_scheduler(store.clone(), 0, 20);
+
+        let mut guard = scheduler.lock(id(1)).await.unwrap();
+        scheduler
+            .update_schedule_info(&mut guard, id(1))
+            .await
+            .unwrap();
+        guard.commit(&id(2)).unwrap();
+        drop(guard);
+
+        tokio::time::sleep(Duration::from_millis(40)).await;
+        let mut guard = scheduler.lock(id(1)).await.unwrap();
+        assert!(!guard.synced);
+        scheduler
+            .update_schedule_info(&mut guard, id(1))
+            .await
+            .unwrap();
+        assert_eq!(store.reads(), 2);
+        assert_eq!(guard.nonce, 0);
+        assert_eq!(scheduler.lock_stats().idle_locks_evicted, 1);
+    }
+
+    #[tokio::test]
+    async fn test_fence() {
+        let scheduler = Arc::new(process_scheduler(Arc::new(SequenceStore::default()), 0, 0));
+        let holder = scheduler.lock(id(1)).await.unwrap();
+
+        // the fence waits for the write in flight
+        let fencing = scheduler.clone();
+        let fence = tokio::spawn(async move { fencing.fence(&id(1)).await });
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert!(!fence.is_finished());
+        drop(holder);
+        fence.await.unwrap().unwrap();
+
+        assert!(matches!(
+            scheduler.lock(id(1)).await,
+            Err(FlowError::Unavailable(_))
+        ));
+        assert!(scheduler.lock(id(2)).await.is_ok());
+        assert!(scheduler.unfence(&id(1)));
+        assert!(scheduler.lock(id(1)).await.is_ok());
+    }
 }
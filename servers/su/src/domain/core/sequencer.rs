@@ -0,0 +1,241 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use dashmap::DashMap;
+use tokio::sync::mpsc::error::TrySendError;
+use tokio::sync::{mpsc, oneshot};
+use tokio::time::{timeout, Duration};
+
+use super::admission::WriteAdmission;
+use super::dal::FlowError;
+use super::deadline::Deadline;
+
+pub type SequenceJob = Pin<Box<dyn Future<Output = Result<String, FlowError>> + Send>>;
+
+struct QueuedJob {
+    job: SequenceJob,
+    deadline: Deadline,
+    respond_to: oneshot::Sender<Result<String, FlowError>>,
+}
+
+struct ProcessQueue {
+    sender: mpsc::Sender<QueuedJob>,
+    generation: u64,
+}
+
+// a sequencing task with nothing to do for this long exits
+const IDLE_TIMEOUT: Duration = Duration::from_secs(30);
+
+// sequencing tasks started for one write before giving up on it
+const MAX_RESTARTS: usize = 3;
+
+/*
+    Writes to one process go through an ordered queue
+    drained by a single sequencing task for that process,
+    instead of every request contending on the process
+    lock. Requests are sequenced in arrival order, a busy
+    process rejects writes once its queue is full, and
+    a write that started sequencing finishes even if its
+    client goes away. A write takes its admission slot
+    when its turn comes, so the writes queued behind a
+    busy process do not hold slots other processes need.
+*/
+pub struct ProcessQueues {
+    queues: Arc<DashMap<String, ProcessQueue>>,
+    generations: AtomicU64,
+    depth: usize,
+    admission: Arc<WriteAdmission>,
+}
+
+impl ProcessQueues {
+    pub fn new(depth: usize, admission: Arc<WriteAdmission>) -> Self {
+        ProcessQueues {
+            queues: Arc::new(DashMap::new()),
+            generations: AtomicU64::new(0),
+            depth: depth.max(1),
+            admission,
+        }
+    }
+
+    pub async fn submit(&self, process_id: &str, job: SequenceJob) -> Result<String, FlowError> {
+        let (respond_to, response) = oneshot::channel();
+        let mut queued = QueuedJob {
+            job,
+            deadline: Deadline::current(),
+            respond_to,
+        };
+
+        let mut restarts = 0;
+        loop {
+            let (sender, generation) = self.sender(process_id);
+            match sender.try_send(queued) {
+                Ok(_) => break,
+                Err(TrySendError::Full(_)) => {
+                    return Err(FlowError::Unavailable(format!(
+                        "Write queue for process {} is full, try again later",
+                        process_id
+                    )))
+                }
+                /*
+                    the task went idle and exited or panicked,
+                    it is replaced unless another write already
+                    did. A task that keeps dying fails the write
+                */
+                Err(TrySendError::Closed(returned)) => {
+                    self.queues
+                        .remove_if(process_id, |_, q| q.generation == generation);
+                    restarts += 1;
+                    if restarts > MAX_RESTARTS {
+                        return Err(FlowError::Internal(format!(
+                            "Sequencing task for process {} keeps exiting",
+                            process_id
+                        )));
+                    }
+                    queued = returned;
+                }
+            }
+        }
+
+        match response.await {
+            Ok(result) => result,
+            Err(_) => Err(FlowError::Internal(
+                "sequencing task dropped the write".to_string(),
+            )),
+        }
+    }
+
+    pub fn queued_processes(&self) -> usize {
+        self.queues.len()
+    }
+
+    fn sender(&self, process_id: &str) -> (mpsc::Sender<QueuedJob>, u64) {
+        let queue = self
+            .queues
+            .entry(process_id.to_string())
+            .or_insert_with(|| {
+                let generation = self.generations.fetch_add(1, Ordering::SeqCst);
+                let (sender, receiver) = mpsc::channel(self.depth);
+                spawn_sequencing_task(
+                    self.queues.clone(),
+                    self.admission.clone(),
+                    process_id.to_string(),
+                    generation,
+                    receiver,
+                );
+                ProcessQueue { sender, generation }
+            });
+        (queue.sender.clone(), queue.generation)
+    }
+}
+
+fn spawn_sequencing_task(
+    queues: Arc<DashMap<String, ProcessQueue>>,
+    admission: Arc<WriteAdmission>,
+    process_id: String,
+    generation: u64,
+    mut receiver: mpsc::Receiver<QueuedJob>,
+) {
+    tokio::spawn(async move {
+        loop {
+            match timeout(IDLE_TIMEOUT, receiver.recv()).await {
+                Ok(Some(queued)) => run(&admission, queued).await,
+                Ok(None) => break,
+                Err(_) => {
+                    queues.remove_if(&process_id, |_, q| q.generation == generation);
+                    // anything sent before the queue closed still runs
+                    receiver.close();
+                    while let Some(queued) = receiver.recv().await {
+                        run(&admission, queued).await;
+                    }
+                    break;
+                }
+            }
+        }
+    });
+}
+
+async fn run(admission: &WriteAdmission, queued: QueuedJob) {
+    let QueuedJob {
+        job,
+        deadline,
+        respond_to,
+    } = queued;
+    // the requester may have gone away, nothing to do then
+    let _ = match admission.start().await {
+        Ok(_permit) => respond_to.send(deadline.scope(job).await),
+        Err(e) => respond_to.send(Err(e)),
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    #[tokio::test]
+    async fn test_writes_run_in_order() {
+        let queues = Arc::new(ProcessQueues::new(16, Arc::new(WriteAdmission::new(1, 0))));
+        let order = Arc::new(Mutex::new(vec![]));
+
+        let mut handles = vec![];
+        for i in 0..5 {
+            let order = order.clone();
+            let job: SequenceJob = Box::pin(async move {
+                tokio::task::yield_now().await;
+                order.lock().unwrap().push(i);
+                Ok(i.to_string())
+            });
+            let queues = queues.clone();
+            handles.push(tokio::spawn(
+                async move { queues.submit("process", job).await },
+            ));
+            // submissions arrive one after another
+            tokio::task::yield_now().await;
+        }
+        for handle in handles {
+            assert!(handle.await.unwrap().is_ok());
+        }
+
+        assert_eq!(*order.lock().unwrap(), vec![0, 1, 2, 3, 4]);
+        assert_eq!(queues.queued_processes(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_panicked_task_is_replaced() {
+        let queues = ProcessQueues::new(16, Arc::new(WriteAdmission::new(1, 0)));
+
+        let job: SequenceJob = Box::pin(async { panic!("sequencing failed") });
+        assert!(matches!(
+            queues.submit("process", job).await,
+            Err(FlowError::Internal(_))
+        ));
+
+        let job: SequenceJob = Box::pin(async { Ok("sequenced".to_string()) });
+        assert_eq!(queues.submit("process", job).await.unwrap(), "sequenced");
+    }
+
+    #[tokio::test]
+    async fn test_slot_taken_when_the_write_starts() {
+        let admission = Arc::new(WriteAdmission::new(1, 0));
+        let queues = Arc::new(ProcessQueues::new(16, admission.clone()));
+        let slot = admission.admit().await.unwrap();
+
+        // queued behind the held slot, not turned away by the admission queue
+        let queued = {
+            let queues = queues.clone();
+            tokio::spawn(async move {
+                let job: SequenceJob = Box::pin(async { Ok("sequenced".to_string()) });
+                queues.submit("process", job).await
+            })
+        };
+        while admission.stats().writes_waiting == 0 {
+            tokio::task::yield_now().await;
+        }
+        assert!(!queued.is_finished());
+
+        drop(slot);
+        assert_eq!(queued.await.unwrap().unwrap(), "sequenced");
+    }
+}
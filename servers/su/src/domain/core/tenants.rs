@@ -0,0 +1,173 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use bundlr_sdk::tags::Tag;
+use dashmap::DashMap;
+use sha2::Digest;
+
+use super::dal::Signer;
+
+// processes whose wallet is kept in memory, past that it is read again
+const MAX_CACHED_WALLETS: usize = 100_000;
+
+/*
+    The scheduler identities this su hosts, its own wallet
+    and any extra ones in TENANT_WALLET_PATHS. Each has its
+    own address a Process can name in its Scheduler tag.
+    The wallet that signs a spawn is saved with the process
+    in process_wallets, keyed by (scheduler, process), and
+    signs every later assignment of it. A write to a process
+    whose wallet this su no longer has is refused rather
+    than signed by another scheduler mid schedule.
+*/
+pub struct Tenants {
+    default_address: String,
+    signers: HashMap<String, Arc<dyn Signer>>,
+    // process id to the wallet read from process_wallets
+    wallets: DashMap<String, String>,
+}
+
+impl Tenants {
+    pub fn new(default: Arc<dyn Signer>, others: Vec<Arc<dyn Signer>>) -> Result<Self, String> {
+        let default_address = signer_address(&*default);
+        let mut signers = HashMap::new();
+        signers.insert(default_address.clone(), default);
+        for signer in others.into_iter() {
+            let address = signer_address(&*signer);
+            if signers.insert(address.clone(), signer).is_some() {
+                return Err(format!("Wallet {} is configured more than once", address));
+            }
+        }
+        Ok(Tenants {
+            default_address,
+            signers,
+            wallets: DashMap::new(),
+        })
+    }
+
+    /*
+        the signer of a spawn, a scheduler this su does not
+        host falls back to its own wallet
+    */
+    pub fn signer_for(&self, scheduler: &str) -> Arc<dyn Signer> {
+        match self.signers.get(scheduler) {
+            Some(signer) => signer.clone(),
+            None => self.signers[&self.default_address].clone(),
+        }
+    }
+
+    // the signer of a write to a process spawned under wallet
+    pub fn process_signer(
+        &self,
+        wallet: &str,
+        process_id: &str,
+    ) -> Result<Arc<dyn Signer>, String> {
+        self.signers.get(wallet).cloned().ok_or_else(|| {
+            format!(
+                "Process {} is scheduled by wallet {} which this su does not hold",
+                process_id, wallet
+            )
+        })
+    }
+
+    pub fn cached_wallet(&self, process_id: &str) -> Option<String> {
+        self.wallets.get(process_id).map(|w| w.clone())
+    }
+
+    pub fn cache_wallet(&self, process_id: &str, wallet: &str) {
+        if self.wallets.len() >= MAX_CACHED_WALLETS {
+            self.wallets.clear();
+        }
+        self.wallets
+            .insert(process_id.to_string(), wallet.to_string());
+    }
+
+    pub fn address_for(&self, scheduler: &str) -> String {
+        match self.hosts(scheduler) {
+            true => scheduler.to_string(),
+            false => self.default_address.clone(),
+        }
+    }
+
+    pub fn hosts(&self, scheduler: &str) -> bool {
+        self.signers.contains_key(scheduler)
+    }
+
+    // the su's own wallet first
+    pub fn addresses(&self) -> Vec<String> {
+        let mut others: Vec<String> = self
+            .signers
+            .keys()
+            .filter(|a| **a != self.default_address)
+            .cloned()
+            .collect();
+        others.sort();
+        let mut addresses = vec![self.default_address.clone()];
+        addresses.append(&mut others);
+        addresses
+    }
+
+    pub fn is_multi(&self) -> bool {
+        self.signers.len() > 1
+    }
+}
+
+pub fn scheduler_tag(tags: &[Tag]) -> String {
+    tags.iter()
+        .find(|tag| tag.name == "Scheduler")
+        .map(|tag| tag.value.clone())
+        .unwrap_or_default()
+}
+
+// an arweave address is the hash of the key modulus
+fn signer_address(signer: &dyn Signer) -> String {
+    let mut context = sha2::Sha256::new();
+    context.update(signer.get_public_key());
+    base64_url::encode(&context.finalize().to_vec())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_trait::async_trait;
+
+    struct MockSigner(Vec<u8>);
+    #[async_trait]
+    impl Signer for MockSigner {
+        async fn sign_tx(&self, _buffer: Vec<u8>) -> Result<Vec<u8>, String> {
+            Ok(self.0.clone())
+        }
+
+        fn get_public_key(&self) -> Vec<u8> {
+            self.0.clone()
+        }
+    }
+
+    #[test]
+    fn test_tenants() {
+        let own: Arc<dyn Signer> = Arc::new(MockSigner(vec![1]));
+        let other: Arc<dyn Signer> = Arc::new(MockSigner(vec![2]));
+        let own_address = signer_address(&*own);
+        let other_address = signer_address(&*other);
+
+        let tenants = Tenants::new(own.clone(), vec![other.clone()]).unwrap();
+        assert!(tenants.is_multi());
+        assert_eq!(
+            tenants.addresses(),
+            vec![own_address.clone(), other_address.clone()]
+        );
+        assert_eq!(tenants.signer_for(&other_address).get_public_key(), vec![2]);
+        assert_eq!(tenants.signer_for("unknown").get_public_key(), vec![1]);
+        assert_eq!(tenants.address_for("unknown"), own_address);
+
+        // a process keeps the wallet it was spawned under
+        let signer = tenants.process_signer(&other_address, "p").unwrap();
+        assert_eq!(signer.get_public_key(), vec![2]);
+        assert!(tenants.process_signer("removed", "p").is_err());
+        assert!(tenants.cached_wallet("p").is_none());
+        tenants.cache_wallet("p", &other_address);
+        assert_eq!(tenants.cached_wallet("p"), Some(other_address.clone()));
+
+        assert!(Tenants::new(own, vec![other.clone(), other]).is_err());
+    }
+}
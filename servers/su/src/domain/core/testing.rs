@@ -0,0 +1,516 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use bytes::Bytes;
+
+use super::dal::*;
+use super::scheduler::{self, SchedulerDeps};
+
+/*
+    Stand-ins for the unit tests of code that sequences.
+    SequenceStore only holds the schedule snapshot of a
+    process and counts how often it was read, every other
+    call panics. The process has no messages otherwise.
+*/
+#[derive(Default)]
+pub struct SequenceStore {
+    pub sequence: Mutex<Option<ProcessSequence>>,
+    pub reads: AtomicUsize,
+}
+
+impl SequenceStore {
+    pub fn reads(&self) -> usize {
+        self.reads.load(Ordering::SeqCst)
+    }
+}
+
+#[async_trait]
+impl DataStore for SequenceStore {
+    async fn save_process(
+        &self,
+        _process: &Process,
+        _bundle_in: &Bytes,
+    ) -> Result<String, StoreErrorType> {
+        unimplemented!()
+    }
+
+    async fn save_process_with_boot(
+        &self,
+        _process: &Process,
+        _bundle_in: &Bytes,
+        _boot: &Message,
+        _boot_bundle: &Bytes,
+    ) -> Result<String, StoreErrorType> {
+        unimplemented!()
+    }
+
+    async fn get_process(&self, _process_id_in: &str) -> Result<Process, StoreErrorType> {
+        unimplemented!()
+    }
+
+    async fn get_process_wallet(&self, _process_id_in: &str) -> Result<String, StoreErrorType> {
+        unimplemented!()
+    }
+
+    async fn get_processes(
+        &self,
+        _from: &Option<String>,
+        _limit: &Option<i32>,
+        _owner: &Option<String>,
+        _module: &Option<String>,
+    ) -> Result<PaginatedProcesses, StoreErrorType> {
+        unimplemented!()
+    }
+
+    async fn save_message(
+        &self,
+        _message: &Message,
+        _bundle_in: &Bytes,
+    ) -> Result<String, StoreErrorType> {
+        unimplemented!()
+    }
+
+    async fn save_messages(
+        &self,
+        _messages_in: &[(Message, Bytes)],
+    ) -> Result<String, StoreErrorType> {
+        unimplemented!()
+    }
+
+    async fn save_cron_message(
+        &self,
+        _message: &Message,
+        _bundle_in: &Bytes,
+        _interval_in: &str,
+        _next_run_in: i64,
+    ) -> Result<String, StoreErrorType> {
+        unimplemented!()
+    }
+
+    async fn get_stored_bundle(&self, _bundle_ref: &BundleRef) -> Result<Vec<u8>, StoreErrorType> {
+        unimplemented!()
+    }
+
+    async fn restore_bundle(
+        &self,
+        _bundle_ref: &BundleRef,
+        _bundle_in: &[u8],
+    ) -> Result<String, StoreErrorType> {
+        unimplemented!()
+    }
+
+    async fn get_messages(
+        &self,
+        _process_id_in: &str,
+        _query: &MessageQuery,
+    ) -> Result<PaginatedMessages, StoreErrorType> {
+        unimplemented!()
+    }
+
+    async fn get_messages_by_owner(
+        &self,
+        _owner_address: &str,
+        _from: &Option<String>,
+        _limit: &Option<i32>,
+    ) -> Result<PaginatedMessages, StoreErrorType> {
+        unimplemented!()
+    }
+
+    async fn get_message(&self, _message_id_in: &str) -> Result<Message, StoreErrorType> {
+        unimplemented!()
+    }
+
+    async fn get_message_by_nonce(
+        &self,
+        _process_id_in: &str,
+        _epoch_in: &i32,
+        _nonce_in: &i32,
+    ) -> Result<Message, StoreErrorType> {
+        unimplemented!()
+    }
+
+    async fn get_messages_by_nonce_range(
+        &self,
+        _process_id_in: &str,
+        _from_nonce_in: i32,
+        _to_nonce_in: Option<i32>,
+        _limit: i64,
+    ) -> Result<PaginatedMessages, StoreErrorType> {
+        unimplemented!()
+    }
+
+    async fn get_latest_message(
+        &self,
+        _process_id_in: &str,
+    ) -> Result<Option<Message>, StoreErrorType> {
+        Ok(None)
+    }
+
+    async fn get_process_sequence(
+        &self,
+        _process_id_in: &str,
+    ) -> Result<Option<ProcessSequence>, StoreErrorType> {
+        self.reads.fetch_add(1, Ordering::SeqCst);
+        Ok(self.sequence.lock().unwrap().clone())
+    }
+
+    /*
+        takes the lease of a process for ttl_ms if it ran out
+        or holder already has it, returns who holds it now
+    */
+    async fn acquire_lease(
+        &self,
+        _process_id_in: &str,
+        _holder_in: &str,
+        _ttl_ms: u64,
+    ) -> Result<String, StoreErrorType> {
+        unimplemented!()
+    }
+
+    async fn get_lease_holder(
+        &self,
+        _process_id_in: &str,
+    ) -> Result<Option<String>, StoreErrorType> {
+        unimplemented!()
+    }
+
+    async fn release_leases(&self, _holder_in: &str) -> Result<(), StoreErrorType> {
+        unimplemented!()
+    }
+
+    async fn get_read_nonce(&self, _process_id_in: &str) -> Result<Option<i32>, StoreErrorType> {
+        unimplemented!()
+    }
+
+    async fn get_message_count(
+        &self,
+        _process_id_in: &str,
+    ) -> Result<MessageCount, StoreErrorType> {
+        unimplemented!()
+    }
+
+    async fn get_idle_processes(
+        &self,
+        _before: i64,
+        _limit: i64,
+    ) -> Result<Vec<String>, StoreErrorType> {
+        unimplemented!()
+    }
+
+    async fn get_message_bundles(
+        &self,
+        _process_id_in: &str,
+        _after: &Option<(i32, i32)>,
+        _limit: i64,
+    ) -> Result<Vec<(Message, Vec<u8>)>, StoreErrorType> {
+        unimplemented!()
+    }
+
+    async fn save_process_scheduler(
+        &self,
+        _process_scheduler: &ProcessScheduler,
+    ) -> Result<String, StoreErrorType> {
+        unimplemented!()
+    }
+
+    async fn get_process_scheduler(
+        &self,
+        _process_id_in: &str,
+    ) -> Result<ProcessScheduler, StoreErrorType> {
+        unimplemented!()
+    }
+
+    async fn move_process_scheduler(
+        &self,
+        _process_id_in: &str,
+        _from_row_id: &i32,
+        _to_row_id: &i32,
+    ) -> Result<String, StoreErrorType> {
+        unimplemented!()
+    }
+
+    async fn save_assignment_decision(
+        &self,
+        _decision: &AssignmentDecision,
+    ) -> Result<String, StoreErrorType> {
+        unimplemented!()
+    }
+
+    async fn get_assignment_decisions(
+        &self,
+        _process_id_in: &Option<String>,
+        _limit: i64,
+    ) -> Result<Vec<AssignmentDecision>, StoreErrorType> {
+        unimplemented!()
+    }
+
+    async fn save_scheduler(&self, _scheduler: &Scheduler) -> Result<String, StoreErrorType> {
+        unimplemented!()
+    }
+
+    async fn update_scheduler(&self, _scheduler: &Scheduler) -> Result<String, StoreErrorType> {
+        unimplemented!()
+    }
+
+    async fn get_scheduler(&self, _row_id_in: &i32) -> Result<Scheduler, StoreErrorType> {
+        unimplemented!()
+    }
+
+    async fn get_scheduler_by_url(&self, _url_in: &str) -> Result<Scheduler, StoreErrorType> {
+        unimplemented!()
+    }
+
+    async fn get_all_schedulers(&self) -> Result<Vec<Scheduler>, StoreErrorType> {
+        unimplemented!()
+    }
+
+    async fn check_existing_message(&self, _message: &Message) -> Result<(), StoreErrorType> {
+        unimplemented!()
+    }
+
+    async fn get_store_stats(&self) -> Result<StoreStats, StoreErrorType> {
+        unimplemented!()
+    }
+
+    async fn get_storage_size(&self) -> Result<i64, StoreErrorType> {
+        unimplemented!()
+    }
+
+    async fn get_tierable_processes(
+        &self,
+        _before: i64,
+        _min_messages: i64,
+        _limit: i64,
+    ) -> Result<Vec<String>, StoreErrorType> {
+        unimplemented!()
+    }
+
+    async fn tier_process(
+        &self,
+        _process_id_in: &str,
+        _before: i64,
+        _segment_size: i64,
+    ) -> Result<Option<ColdSegment>, StoreErrorType> {
+        unimplemented!()
+    }
+
+    async fn index_cold_segments(&self, _limit: i64) -> Result<i64, StoreErrorType> {
+        unimplemented!()
+    }
+
+    /*
+        messages after row_id older than the timestamp or
+        beyond the newest keep_latest of their process, the
+        latest message of a process is never returned
+    */
+    async fn get_prunable_messages(
+        &self,
+        _before: Option<i64>,
+        _keep_latest: Option<i64>,
+        _after: i32,
+        _limit: i64,
+    ) -> Result<Vec<PrunableMessage>, StoreErrorType> {
+        unimplemented!()
+    }
+
+    async fn prune_messages(
+        &self,
+        _messages: &[PrunableMessage],
+        _delete_rows: bool,
+    ) -> Result<Vec<String>, StoreErrorType> {
+        unimplemented!()
+    }
+
+    async fn save_api_token(&self, _api_token: &ApiToken) -> Result<String, StoreErrorType> {
+        unimplemented!()
+    }
+
+    async fn get_api_token(&self, _token_hash_in: &str) -> Result<ApiToken, StoreErrorType> {
+        unimplemented!()
+    }
+
+    async fn delete_api_token(&self, _token_hash_in: &str) -> Result<String, StoreErrorType> {
+        unimplemented!()
+    }
+
+    async fn ping(&self) -> Result<(), StoreErrorType> {
+        unimplemented!()
+    }
+
+    async fn get_outbox(&self, _limit: i64) -> Result<Vec<OutboxEvent>, StoreErrorType> {
+        unimplemented!()
+    }
+
+    async fn delete_outbox(&self, _row_ids: &[i32]) -> Result<(), StoreErrorType> {
+        unimplemented!()
+    }
+
+    async fn fail_outbox(
+        &self,
+        _row_id: i32,
+        _max_attempts: u32,
+        _error: &str,
+    ) -> Result<bool, StoreErrorType> {
+        unimplemented!()
+    }
+
+    async fn save_undelivered(
+        &self,
+        _item_id: &str,
+        _bundle: &[u8],
+        _error: &str,
+    ) -> Result<(), StoreErrorType> {
+        unimplemented!()
+    }
+
+    async fn get_undelivered(&self, _limit: i64) -> Result<Vec<(String, Vec<u8>)>, StoreErrorType> {
+        unimplemented!()
+    }
+
+    async fn delete_undelivered(&self, _item_id: &str) -> Result<(), StoreErrorType> {
+        unimplemented!()
+    }
+
+    async fn save_checkpoint(&self, _checkpoint: &Checkpoint) -> Result<String, StoreErrorType> {
+        unimplemented!()
+    }
+
+    async fn get_checkpoints(
+        &self,
+        _process_id_in: &str,
+        _before: &Option<(i32, i32)>,
+        _limit: i64,
+    ) -> Result<Vec<Checkpoint>, StoreErrorType> {
+        unimplemented!()
+    }
+
+    async fn get_assignments(
+        &self,
+        _process_id_in: &str,
+        _after: &Option<(i32, i32)>,
+        _limit: i64,
+    ) -> Result<Vec<Assignment>, StoreErrorType> {
+        unimplemented!()
+    }
+
+    async fn find_assignments(&self, _id_in: &str) -> Result<Vec<Assignment>, StoreErrorType> {
+        unimplemented!()
+    }
+
+    async fn save_attestation(&self, _attestation: &Attestation) -> Result<String, StoreErrorType> {
+        unimplemented!()
+    }
+
+    async fn save_upload_receipt(
+        &self,
+        _receipt: &UploadReceipt,
+    ) -> Result<String, StoreErrorType> {
+        unimplemented!()
+    }
+
+    async fn get_upload_receipt(
+        &self,
+        _bundle_id_in: &str,
+    ) -> Result<UploadReceipt, StoreErrorType> {
+        unimplemented!()
+    }
+
+    async fn get_attestations(
+        &self,
+        _process_id_in: &str,
+        _limit: i64,
+    ) -> Result<Vec<Attestation>, StoreErrorType> {
+        unimplemented!()
+    }
+
+    async fn get_due_crons(
+        &self,
+        _now: i64,
+        _limit: i64,
+    ) -> Result<Vec<CronDefinition>, StoreErrorType> {
+        unimplemented!()
+    }
+
+    async fn get_crons(&self, _process_id_in: &str) -> Result<Vec<CronDefinition>, StoreErrorType> {
+        unimplemented!()
+    }
+
+    async fn update_cron_next_run(
+        &self,
+        _process_id_in: &str,
+        _interval_in: &str,
+        _next_run_in: i64,
+    ) -> Result<(), StoreErrorType> {
+        unimplemented!()
+    }
+}
+
+// answers network_info with a fixed height
+pub struct HeightGateway;
+
+#[async_trait]
+impl Gateway for HeightGateway {
+    async fn check_head(&self, _tx_id: String) -> Result<bool, String> {
+        unimplemented!()
+    }
+
+    async fn network_info(&self) -> Result<NetworkInfo, String> {
+        Ok(NetworkInfo {
+            height: "000001000000".to_string(),
+            current: String::new(),
+        })
+    }
+
+    async fn status(&self, _tx_id: &String) -> Result<TxStatus, String> {
+        unimplemented!()
+    }
+
+    async fn raw(&self, _tx_id: &String) -> Result<Vec<u8>, String> {
+        unimplemented!()
+    }
+
+    async fn ping(&self) -> Result<(), String> {
+        Ok(())
+    }
+
+    async fn graphql(
+        &self,
+        _query: &str,
+        _variables: serde_json::Value,
+    ) -> Result<serde_json::Value, String> {
+        unimplemented!()
+    }
+
+    async fn price(&self, _bytes: usize) -> Result<u64, String> {
+        unimplemented!()
+    }
+
+    async fn tx_anchor(&self) -> Result<String, String> {
+        unimplemented!()
+    }
+}
+
+pub struct NullLog;
+
+impl Log for NullLog {
+    fn log(&self, _message: String) {}
+    fn error(&self, _message: String) {}
+}
+
+// a process scheduler over the store, without leases
+pub fn process_scheduler(
+    store: Arc<SequenceStore>,
+    lock_deadline_ms: u64,
+    lock_idle_ms: u64,
+) -> scheduler::ProcessScheduler {
+    scheduler::ProcessScheduler::new(Arc::new(SchedulerDeps {
+        data_store: store,
+        gateway: Arc::new(HeightGateway),
+        logger: Arc::new(NullLog),
+        events: Arc::new(EventBus::new(16)),
+        lock_deadline_ms,
+        lock_idle_ms,
+        leases: None,
+    }))
+}
@@ -0,0 +1,111 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use tokio::sync::broadcast::error::RecvError;
+
+use super::events::{DomainEvent, EventBus};
+
+// seconds of history kept, the rate is over the whole window
+const WINDOW_SECS: u64 = 60;
+
+fn unix_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/*
+    Messages sequenced over the last minute, counted per
+    second so the total slides instead of resetting.
+*/
+pub struct Throughput {
+    buckets: Mutex<VecDeque<(u64, u64)>>,
+}
+
+impl Default for Throughput {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Throughput {
+    pub fn new() -> Self {
+        Throughput {
+            buckets: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    pub fn record(&self) {
+        self.record_at(unix_secs())
+    }
+
+    fn record_at(&self, second: u64) {
+        if let Ok(mut buckets) = self.buckets.lock() {
+            match buckets.back_mut() {
+                Some((s, count)) if *s == second => *count += 1,
+                _ => buckets.push_back((second, 1)),
+            }
+            while buckets.len() as u64 > WINDOW_SECS {
+                buckets.pop_front();
+            }
+        }
+    }
+
+    pub fn last_minute(&self) -> u64 {
+        self.last_minute_at(unix_secs())
+    }
+
+    fn last_minute_at(&self, now: u64) -> u64 {
+        match self.buckets.lock() {
+            Ok(buckets) => buckets
+                .iter()
+                .filter(|(s, _)| s + WINDOW_SECS > now)
+                .map(|(_, count)| count)
+                .sum(),
+            Err(_) => 0,
+        }
+    }
+}
+
+/*
+    subscriber that counts every sequenced message
+*/
+pub fn spawn_throughput_sink(bus: &EventBus, throughput: Arc<Throughput>) {
+    let mut receiver = bus.subscribe();
+    tokio::spawn(async move {
+        loop {
+            match receiver.recv().await {
+                Ok(DomainEvent::MessageSequenced { .. }) => throughput.record(),
+                Ok(_) | Err(RecvError::Lagged(_)) => (),
+                Err(RecvError::Closed) => break,
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_throughput() {
+        let throughput = Throughput::new();
+        assert_eq!(throughput.last_minute_at(1000), 0);
+
+        throughput.record_at(1000);
+        throughput.record_at(1000);
+        throughput.record_at(1030);
+        assert_eq!(throughput.last_minute_at(1030), 3);
+
+        // the first second slides out of the window
+        assert_eq!(throughput.last_minute_at(1060), 1);
+        assert_eq!(throughput.last_minute_at(1090), 0);
+
+        for second in 2000..2100 {
+            throughput.record_at(second);
+        }
+        assert_eq!(throughput.last_minute_at(2099), WINDOW_SECS);
+    }
+}
@@ -0,0 +1,155 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::Serialize;
+use tokio::time::sleep;
+
+use super::flows::Deps;
+
+// processes looked at per pass, the next pass picks up the rest
+const PROCESSES_PER_PASS: i64 = 100;
+
+const DAY_MS: u64 = 24 * 60 * 60 * 1000;
+
+fn unix_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+#[derive(Serialize)]
+pub struct TieringStats {
+    pub runs: u64,
+    pub segments: u64,
+    pub messages: u64,
+    // compressed bytes written to cold storage
+    pub segment_bytes: u64,
+}
+
+/*
+    Moves the history of busy processes out of the
+    database. Messages older than COLD_STORAGE_AFTER_DAYS
+    are written COLD_SEGMENT_SIZE at a time to compressed
+    segments in BUNDLE_STORAGE, reads merge them back in.
+*/
+pub struct Tiering {
+    runs: AtomicU64,
+    segments: AtomicU64,
+    messages: AtomicU64,
+    segment_bytes: AtomicU64,
+}
+
+impl Default for Tiering {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Tiering {
+    pub fn new() -> Self {
+        Tiering {
+            runs: AtomicU64::new(0),
+            segments: AtomicU64::new(0),
+            messages: AtomicU64::new(0),
+            segment_bytes: AtomicU64::new(0),
+        }
+    }
+
+    pub fn stats(&self) -> TieringStats {
+        TieringStats {
+            runs: self.runs.load(Ordering::SeqCst),
+            segments: self.segments.load(Ordering::SeqCst),
+            messages: self.messages.load(Ordering::SeqCst),
+            segment_bytes: self.segment_bytes.load(Ordering::SeqCst),
+        }
+    }
+}
+
+// one pass, every process tiers segments until it has no full one left
+pub async fn tier(deps: &Arc<Deps>) -> Result<u64, String> {
+    let days = deps.config.cold_storage_after_days();
+    let segment_size = deps.config.cold_segment_size().max(1) as i64;
+    let before = unix_ms().saturating_sub(days.saturating_mul(DAY_MS)) as i64;
+
+    // one more than a segment so the segment never holds the latest message
+    let processes = deps
+        .data_store
+        .get_tierable_processes(before, segment_size + 1, PROCESSES_PER_PASS)
+        .await
+        .map_err(|e| format!("{:?}", e))?;
+
+    let mut written = 0;
+    for process_id in processes.iter() {
+        loop {
+            let segment = match deps
+                .data_store
+                .tier_process(process_id, before, segment_size)
+                .await
+            {
+                Ok(Some(segment)) => segment,
+                Ok(None) => break,
+                Err(e) => {
+                    deps.logger.error(format!(
+                        "cold storage of process {} failed - {:?}",
+                        process_id, e
+                    ));
+                    break;
+                }
+            };
+            deps.tiering.segments.fetch_add(1, Ordering::SeqCst);
+            deps.tiering
+                .messages
+                .fetch_add(segment.message_count as u64, Ordering::SeqCst);
+            deps.tiering
+                .segment_bytes
+                .fetch_add(segment.segment_size as u64, Ordering::SeqCst);
+            written += 1;
+        }
+    }
+    deps.tiering.runs.fetch_add(1, Ordering::SeqCst);
+    Ok(written)
+}
+
+/*
+    segments tiered before their ids were indexed are
+    rewritten first, until then a retried write of one
+    of their messages is not seen as a duplicate
+*/
+async fn index_segments(deps: &Arc<Deps>) {
+    loop {
+        match deps
+            .data_store
+            .index_cold_segments(PROCESSES_PER_PASS)
+            .await
+        {
+            Ok(0) => return,
+            Ok(indexed) => deps
+                .logger
+                .log(format!("cold storage indexed {} segments", indexed)),
+            Err(e) => {
+                deps.logger
+                    .error(format!("cold storage indexing failed - {:?}", e));
+                return;
+            }
+        }
+    }
+}
+
+pub fn spawn_tiering(deps: Arc<Deps>) {
+    let interval = Duration::from_millis(deps.config.cold_storage_interval_ms());
+    tokio::spawn(async move {
+        index_segments(&deps).await;
+        loop {
+            sleep(interval).await;
+            match tier(&deps).await {
+                Ok(0) => (),
+                Ok(written) => deps
+                    .logger
+                    .log(format!("cold storage wrote {} segments", written)),
+                Err(e) => deps.logger.error(format!("cold storage failed - {}", e)),
+            }
+        }
+    });
+}
@@ -0,0 +1,134 @@
+use ring::rand::{SecureRandom, SystemRandom};
+use sha2::{Digest, Sha256};
+
+use super::dal::{DataStore, FlowError, StoreErrorType};
+
+/*
+    Api tokens grant write access on a restricted su. A
+    token is bound to an owner address, a set of process
+    ids or both. Only the hash of a token is stored, the
+    token itself is shown once when it is issued.
+*/
+#[derive(Clone)]
+pub struct ApiToken {
+    pub row_id: Option<i32>,
+    pub token_hash: String,
+    pub owner: Option<String>,
+    pub process_ids: Vec<String>,
+}
+
+impl ApiToken {
+    // can this token write to the process with this id and owner
+    pub fn allows(&self, process_id: &str, process_owner: &str) -> bool {
+        let owner_match = match &self.owner {
+            Some(owner) => owner == process_owner,
+            None => false,
+        };
+        owner_match || self.process_ids.iter().any(|p| p == process_id)
+    }
+}
+
+pub fn hash_token(token: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(token.as_bytes());
+    base64_url::encode(&hasher.finalize().to_vec())
+}
+
+fn generate_token() -> Result<String, String> {
+    let mut randoms: [u8; 32] = [0; 32];
+    SystemRandom::new()
+        .fill(&mut randoms)
+        .map_err(|e| format!("failed to generate token: {}", e))?;
+    Ok(base64_url::encode(&randoms))
+}
+
+/*
+    create a token scoped to an owner and/or processes,
+    returns the token which is not recoverable later
+*/
+pub async fn issue_token(
+    data_store: &dyn DataStore,
+    owner: Option<String>,
+    process_ids: Vec<String>,
+) -> Result<String, String> {
+    if owner.is_none() && process_ids.is_empty() {
+        return Err("An api token needs an owner or at least one process id".to_string());
+    }
+
+    let token = generate_token()?;
+    let api_token = ApiToken {
+        row_id: None,
+        token_hash: hash_token(&token),
+        owner,
+        process_ids,
+    };
+    data_store.save_api_token(&api_token).await?;
+    Ok(token)
+}
+
+pub async fn revoke_token(data_store: &dyn DataStore, token: &str) -> Result<String, String> {
+    data_store.delete_api_token(&hash_token(token)).await?;
+    Ok("api token revoked".to_string())
+}
+
+/*
+    check a token presented with a write against the
+    process being written to
+*/
+pub async fn authorize(
+    data_store: &dyn DataStore,
+    token: &Option<String>,
+    process_id: &str,
+    process_owner: &str,
+) -> Result<(), FlowError> {
+    let token = match token {
+        Some(t) => t,
+        None => {
+            return Err(FlowError::Forbidden(
+                "This su requires an api token to write".to_string(),
+            ))
+        }
+    };
+
+    let api_token = match data_store.get_api_token(&hash_token(token)).await {
+        Ok(t) => t,
+        Err(StoreErrorType::NotFound(_)) => {
+            return Err(FlowError::Forbidden("Invalid api token".to_string()))
+        }
+        Err(e) => return Err(e.into()),
+    };
+
+    if api_token.allows(process_id, process_owner) {
+        Ok(())
+    } else {
+        Err(FlowError::Forbidden(
+            "Api token is not allowed to write to this process".to_string(),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_token_scope() {
+        let owner_token = ApiToken {
+            row_id: None,
+            token_hash: hash_token("a"),
+            owner: Some("owner-1".to_string()),
+            process_ids: vec![],
+        };
+        assert!(owner_token.allows("process-1", "owner-1"));
+        assert!(!owner_token.allows("process-1", "owner-2"));
+
+        let process_token = ApiToken {
+            row_id: None,
+            token_hash: hash_token("b"),
+            owner: None,
+            process_ids: vec!["process-1".to_string()],
+        };
+        assert!(process_token.allows("process-1", "owner-2"));
+        assert!(!process_token.allows("process-2", "owner-2"));
+    }
+}
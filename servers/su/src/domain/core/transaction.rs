@@ -0,0 +1,412 @@
+use bundlr_sdk::tags::Tag;
+use serde_json::json;
+use sha2::{Digest, Sha256};
+
+use super::bytes::{deep_hash_sync, DeepHashChunk};
+use super::dal::Signer;
+
+const MAX_CHUNK_SIZE: usize = 256 * 1024;
+const MIN_CHUNK_SIZE: usize = 32 * 1024;
+
+fn sha256(parts: &[&[u8]]) -> Vec<u8> {
+    let mut hasher = Sha256::new();
+    for part in parts.iter() {
+        hasher.update(part);
+    }
+    hasher.finalize().to_vec()
+}
+
+// an offset in the merkle tree, 32 bytes big endian
+fn note(offset: usize) -> Vec<u8> {
+    let mut buffer = vec![0u8; 32];
+    buffer[24..].copy_from_slice(&(offset as u64).to_be_bytes());
+    buffer
+}
+
+/*
+    where the chunks of data of size bytes start and end.
+    Chunks are 256KiB, the last two are evened out when
+    the last would be under 32KiB. Like arweave-js a size
+    that is a multiple of 256KiB ends with an empty chunk,
+    it counts towards the data_root but is never uploaded.
+*/
+fn chunk_ranges(size: usize) -> Vec<(usize, usize)> {
+    let mut ranges = vec![];
+    let mut cursor = 0;
+    let mut rest = size;
+    while rest >= MAX_CHUNK_SIZE {
+        let mut length = MAX_CHUNK_SIZE;
+        let next = rest - MAX_CHUNK_SIZE;
+        if next > 0 && next < MIN_CHUNK_SIZE {
+            length = rest.div_ceil(2);
+        }
+        ranges.push((cursor, cursor + length));
+        cursor += length;
+        rest -= length;
+    }
+    ranges.push((cursor, cursor + rest));
+    ranges
+}
+
+enum Node {
+    Leaf {
+        id: Vec<u8>,
+        data_hash: Vec<u8>,
+        max: usize,
+    },
+    Branch {
+        id: Vec<u8>,
+        left: Box<Node>,
+        right: Box<Node>,
+        max: usize,
+    },
+}
+
+impl Node {
+    fn leaf(data_hash: Vec<u8>, max: usize) -> Node {
+        Node::Leaf {
+            id: sha256(&[&sha256(&[&data_hash]), &sha256(&[&note(max)])]),
+            data_hash,
+            max,
+        }
+    }
+
+    fn branch(left: Node, right: Node) -> Node {
+        Node::Branch {
+            id: sha256(&[
+                &sha256(&[left.id()]),
+                &sha256(&[right.id()]),
+                &sha256(&[&note(left.max())]),
+            ]),
+            max: right.max(),
+            left: Box::new(left),
+            right: Box::new(right),
+        }
+    }
+
+    fn id(&self) -> &[u8] {
+        match self {
+            Node::Leaf { id, .. } | Node::Branch { id, .. } => id,
+        }
+    }
+
+    fn max(&self) -> usize {
+        match self {
+            Node::Leaf { max, .. } | Node::Branch { max, .. } => *max,
+        }
+    }
+
+    // the path from the root to every leaf, in order
+    fn proofs(&self, path: Vec<u8>, proofs: &mut Vec<Vec<u8>>) {
+        match self {
+            Node::Leaf { data_hash, max, .. } => {
+                proofs.push([path, data_hash.clone(), note(*max)].concat())
+            }
+            Node::Branch { left, right, .. } => {
+                let path = [
+                    path,
+                    left.id().to_vec(),
+                    right.id().to_vec(),
+                    note(left.max()),
+                ]
+                .concat();
+                left.proofs(path.clone(), proofs);
+                right.proofs(path, proofs);
+            }
+        }
+    }
+}
+
+// pairs up each layer, an odd node out moves up as is
+fn build_tree(mut nodes: Vec<Node>) -> Node {
+    while nodes.len() > 1 {
+        let mut layer = vec![];
+        let mut rest = nodes.into_iter();
+        while let Some(left) = rest.next() {
+            match rest.next() {
+                Some(right) => layer.push(Node::branch(left, right)),
+                None => layer.push(left),
+            }
+        }
+        nodes = layer;
+    }
+    nodes.remove(0)
+}
+
+pub struct Chunk {
+    pub start: usize,
+    pub end: usize,
+    // proves the chunk against the data_root
+    pub data_path: Vec<u8>,
+}
+
+pub fn chunk_data(data: &[u8]) -> (Vec<u8>, Vec<Chunk>) {
+    let ranges = chunk_ranges(data.len());
+    let leaves = ranges
+        .iter()
+        .map(|(start, end)| Node::leaf(sha256(&[&data[*start..*end]]), *end))
+        .collect();
+    let root = build_tree(leaves);
+    let mut proofs = vec![];
+    root.proofs(vec![], &mut proofs);
+    let chunks = ranges
+        .into_iter()
+        .zip(proofs)
+        .filter(|((start, end), _)| end > start)
+        .map(|((start, end), data_path)| Chunk {
+            start,
+            end,
+            data_path,
+        })
+        .collect();
+    (root.id().to_vec(), chunks)
+}
+
+/*
+    A format 2 arweave transaction, the su posts its own
+    bundles with these in UPLOAD_MODE direct. The owner is
+    the modulus of the signing wallet and last_tx the
+    anchor the gateway gave. The data travels in chunks
+    proven against data_root unless it fits in one.
+*/
+pub struct Transaction {
+    pub owner: Vec<u8>,
+    pub last_tx: Vec<u8>,
+    pub tags: Vec<Tag>,
+    pub reward: u64,
+    pub data: Vec<u8>,
+    pub data_root: Vec<u8>,
+    pub chunks: Vec<Chunk>,
+    pub signature: Vec<u8>,
+}
+
+impl Transaction {
+    pub fn new(
+        owner: Vec<u8>,
+        last_tx: Vec<u8>,
+        tags: Vec<Tag>,
+        reward: u64,
+        data: Vec<u8>,
+    ) -> Self {
+        let (data_root, chunks) = chunk_data(&data);
+        Transaction {
+            owner,
+            last_tx,
+            tags,
+            reward,
+            data,
+            data_root,
+            chunks,
+            signature: vec![],
+        }
+    }
+
+    fn signature_data(&self) -> Result<Vec<u8>, String> {
+        let field = |bytes: Vec<u8>| DeepHashChunk::Chunk(bytes.into());
+        let tags = self
+            .tags
+            .iter()
+            .map(|tag| {
+                DeepHashChunk::Chunks(vec![
+                    field(tag.name.clone().into_bytes()),
+                    field(tag.value.clone().into_bytes()),
+                ])
+            })
+            .collect();
+        let message = deep_hash_sync(DeepHashChunk::Chunks(vec![
+            field(b"2".to_vec()),
+            field(self.owner.clone()),
+            field(vec![]),
+            field(b"0".to_vec()),
+            field(self.reward.to_string().into_bytes()),
+            field(self.last_tx.clone()),
+            DeepHashChunk::Chunks(tags),
+            field(self.data.len().to_string().into_bytes()),
+            field(self.data_root.clone()),
+        ]))
+        .map_err(|e| format!("{:?}", e))?;
+        Ok(message.to_vec())
+    }
+
+    pub async fn sign(&mut self, signer: &dyn Signer) -> Result<(), String> {
+        self.signature = signer.sign_tx(self.signature_data()?).await?;
+        Ok(())
+    }
+
+    pub fn id(&self) -> String {
+        base64_url::encode(&sha256(&[&self.signature]))
+    }
+
+    // a transaction of one chunk carries its data in the body
+    pub fn inline(&self) -> bool {
+        self.chunks.len() <= 1
+    }
+
+    // the body for POST /tx
+    pub fn to_json(&self) -> serde_json::Value {
+        let tags: Vec<serde_json::Value> = self
+            .tags
+            .iter()
+            .map(|tag| {
+                json!({
+                    "name": base64_url::encode(&tag.name),
+                    "value": base64_url::encode(&tag.value),
+                })
+            })
+            .collect();
+        let data = match self.inline() {
+            true => base64_url::encode(&self.data),
+            false => String::new(),
+        };
+        json!({
+            "format": 2,
+            "id": self.id(),
+            "last_tx": base64_url::encode(&self.last_tx),
+            "owner": base64_url::encode(&self.owner),
+            "tags": tags,
+            "target": "",
+            "quantity": "0",
+            "data": data,
+            "data_size": self.data.len().to_string(),
+            "data_root": base64_url::encode(&self.data_root),
+            "reward": self.reward.to_string(),
+            "signature": base64_url::encode(&self.signature),
+        })
+    }
+
+    // the body for POST /chunk
+    pub fn chunk_json(&self, chunk: &Chunk) -> serde_json::Value {
+        json!({
+            "data_root": base64_url::encode(&self.data_root),
+            "data_size": self.data.len().to_string(),
+            "data_path": base64_url::encode(&chunk.data_path),
+            "offset": (chunk.end - 1).to_string(),
+            "chunk": base64_url::encode(&self.data[chunk.start..chunk.end]),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_trait::async_trait;
+
+    // checks a data_path the way an arweave node does
+    fn validate(id: &[u8], offset: usize, path: &[u8]) -> bool {
+        if path.len() == 64 {
+            let (data_hash, max) = path.split_at(32);
+            return sha256(&[&sha256(&[data_hash]), &sha256(&[max])]) == id;
+        }
+        let (left, rest) = path.split_at(32);
+        let (right, rest) = rest.split_at(32);
+        let (split, rest) = rest.split_at(32);
+        if sha256(&[&sha256(&[left]), &sha256(&[right]), &sha256(&[split])]) != id {
+            return false;
+        }
+        let split = u64::from_be_bytes(split[24..].try_into().unwrap()) as usize;
+        match offset < split {
+            true => validate(left, offset, rest),
+            false => validate(right, offset, rest),
+        }
+    }
+
+    #[test]
+    fn test_chunk_ranges() {
+        assert_eq!(chunk_ranges(10), vec![(0, 10)]);
+        assert_eq!(
+            chunk_ranges(MAX_CHUNK_SIZE),
+            vec![(0, MAX_CHUNK_SIZE), (MAX_CHUNK_SIZE, MAX_CHUNK_SIZE)]
+        );
+        // a short last chunk is evened out with the one before
+        let size = MAX_CHUNK_SIZE + 1000;
+        let half = size.div_ceil(2);
+        assert_eq!(chunk_ranges(size), vec![(0, half), (half, size)]);
+    }
+
+    #[test]
+    fn test_chunk_proofs() {
+        let data: Vec<u8> = (0..MAX_CHUNK_SIZE * 3 + 5000)
+            .map(|i| (i % 251) as u8)
+            .collect();
+        let (data_root, chunks) = chunk_data(&data);
+        assert_eq!(chunks.len(), 4);
+        assert_eq!(chunks.last().unwrap().end, data.len());
+        for chunk in chunks.iter() {
+            assert!(validate(&data_root, chunk.end - 1, &chunk.data_path));
+        }
+        assert!(!validate(&data_root, 0, &chunks[1].data_path));
+    }
+
+    /*
+        data_root and signature data worked out with a node
+        port of merkle.ts and deepHash from arweave-js, for
+        the bytes i % 251 and the transaction of test_sign
+    */
+    #[tokio::test]
+    async fn test_arweave_js_vectors() {
+        let data = |size: usize| -> Vec<u8> { (0..size).map(|i| (i % 251) as u8).collect() };
+        let vectors = [
+            (10, "D5bQrIpRdeMLrxkfP7F-Hwikv8tpKvZiKmXtTrCoZ0s"),
+            (
+                MAX_CHUNK_SIZE,
+                "gty7KB2baLFp7OGxuV2wBeX3NippS1tNVlMOZryIq5o",
+            ),
+            (
+                MAX_CHUNK_SIZE + 1000,
+                "bFtxR6l6BRJt4Y7utAH1mGDg4Ps969jw17_NfjGzB_Q",
+            ),
+            (
+                MAX_CHUNK_SIZE * 3 + 5000,
+                "uhp0On7sSLWQAmgwI3a_qrkUQSl8165hvOoQx6y6Lro",
+            ),
+        ];
+        for (size, data_root) in vectors {
+            assert_eq!(base64_url::encode(&chunk_data(&data(size)).0), data_root);
+        }
+
+        let tags = vec![Tag::new("Bundle-Format", "binary")];
+        let tx = Transaction::new(vec![5, 6, 7, 8], vec![1; 48], tags, 1000, data(100));
+        assert_eq!(
+            base64_url::encode(&tx.signature_data().unwrap()),
+            "SPz64_kcNTJEX1z6HvZnR5y_aiv39oAxIHzRufyBU1YywEz1defepmzX2I10PbMs"
+        );
+    }
+
+    // signs by echoing the payload back
+    struct EchoSigner;
+    #[async_trait]
+    impl Signer for EchoSigner {
+        async fn sign_tx(&self, buffer: Vec<u8>) -> Result<Vec<u8>, String> {
+            Ok(buffer)
+        }
+
+        fn get_public_key(&self) -> Vec<u8> {
+            vec![5, 6, 7, 8]
+        }
+    }
+
+    #[tokio::test]
+    async fn test_sign() {
+        let tags = vec![Tag::new("Bundle-Format", "binary")];
+        let mut tx = Transaction::new(
+            vec![5, 6, 7, 8],
+            vec![1; 48],
+            tags.clone(),
+            1000,
+            vec![9; 100],
+        );
+        tx.sign(&EchoSigner).await.unwrap();
+        assert_eq!(tx.signature, tx.signature_data().unwrap());
+        assert!(tx.inline());
+
+        let json = tx.to_json();
+        assert_eq!(json["id"], tx.id());
+        assert_eq!(json["data_size"], "100");
+        assert_eq!(json["tags"][0]["name"], base64_url::encode("Bundle-Format"));
+
+        // the reward is part of what is signed
+        let mut cheaper = Transaction::new(vec![5, 6, 7, 8], vec![1; 48], tags, 999, vec![9; 100]);
+        cheaper.sign(&EchoSigner).await.unwrap();
+        assert_ne!(cheaper.id(), tx.id());
+    }
+}
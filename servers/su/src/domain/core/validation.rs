@@ -0,0 +1,74 @@
+use serde::Serialize;
+use serde_json::json;
+
+use super::bytes::DataItem;
+
+#[derive(Serialize, Debug)]
+pub struct ValidationCheck {
+    pub check: String,
+    pub ok: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/*
+    The result of running a data item through the checks
+    a write would make without sequencing or uploading
+    it. Every check that can run is reported, not just
+    the first failure, so an sdk developer sees all that
+    is wrong with an item at once.
+*/
+#[derive(Default)]
+pub struct ValidationReport {
+    pub item: Option<DataItem>,
+    pub item_type: Option<String>,
+    pub checks: Vec<ValidationCheck>,
+}
+
+impl ValidationReport {
+    // records the check, returns whether it passed
+    pub fn check<E: ToString>(&mut self, name: &str, result: Result<(), E>) -> bool {
+        let error = result.err().map(|e| e.to_string());
+        self.checks.push(ValidationCheck {
+            check: name.to_string(),
+            ok: error.is_none(),
+            error,
+        });
+        self.checks.last().is_some_and(|c| c.ok)
+    }
+
+    pub fn valid(&self) -> bool {
+        self.checks.iter().all(|c| c.ok)
+    }
+
+    pub fn to_json(&self) -> String {
+        json!({
+            "valid": self.valid(),
+            "id": self.item.as_ref().map(|i| i.id()),
+            "owner": self.item.as_ref().map(|i| i.owner_address()),
+            "target": self.item.as_ref().map(|i| i.target()).filter(|t| !t.is_empty()),
+            "type": self.item_type,
+            "checks": self.checks,
+        })
+        .to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_report() {
+        let mut report = ValidationReport::default();
+        assert!(report.check::<String>("size", Ok(())));
+        assert!(!report.check("signature", Err("invalid signature")));
+        assert!(!report.valid());
+
+        let json: serde_json::Value = serde_json::from_str(&report.to_json()).unwrap();
+        assert_eq!(json["valid"], false);
+        assert!(json["id"].is_null());
+        assert_eq!(json["checks"][0], json!({ "check": "size", "ok": true }));
+        assert_eq!(json["checks"][1]["error"], "invalid signature");
+    }
+}
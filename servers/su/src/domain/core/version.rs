@@ -0,0 +1,59 @@
+use super::dal::FlowError;
+
+/*
+    The shape of su responses. A client asks for one
+    with Accept-Version and every response says which
+    one it got in SU-Version, so a response shape can
+    change under a new version while existing CU and MU
+    clients, which send no header, keep the one they
+    were built against.
+*/
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub enum ApiVersion {
+    // write results are only the id and timestamp
+    #[default]
+    V1,
+    // write results carry the sequenced slot and bundle, process reads a schedule summary
+    V2,
+}
+
+impl ApiVersion {
+    pub const LATEST: ApiVersion = ApiVersion::V2;
+
+    pub fn from_header(header: Option<&str>) -> Result<ApiVersion, FlowError> {
+        match header.map(str::trim) {
+            None | Some("") => Ok(ApiVersion::default()),
+            Some("1") => Ok(ApiVersion::V1),
+            Some("2") => Ok(ApiVersion::V2),
+            Some("latest") => Ok(ApiVersion::LATEST),
+            Some(v) => Err(FlowError::Validation(format!(
+                "Unsupported Accept-Version {}, supported versions are 1 to {}",
+                v,
+                ApiVersion::LATEST.number()
+            ))),
+        }
+    }
+
+    pub fn number(&self) -> u32 {
+        match self {
+            ApiVersion::V1 => 1,
+            ApiVersion::V2 => 2,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_header() {
+        assert_eq!(ApiVersion::from_header(None), Ok(ApiVersion::V1));
+        assert_eq!(ApiVersion::from_header(Some(" 2 ")), Ok(ApiVersion::V2));
+        assert_eq!(
+            ApiVersion::from_header(Some("latest")),
+            Ok(ApiVersion::LATEST)
+        );
+        assert!(ApiVersion::from_header(Some("3")).is_err());
+    }
+}
@@ -1,6 +1,7 @@
 use std::sync::Arc;
 
 use env_logger::Env;
+use log::LevelFilter;
 use log::{error, info};
 
 use crate::domain::Log;
@@ -10,12 +11,15 @@ pub struct SuLog;
 /*
 Logging instance, using an instance of this
 instead of the env_logger macros throughout
-the code
+the code. RUST_LOG still filters per module,
+the level caps it and can be changed while
+running with log::set_max_level
 */
 
 impl SuLog {
-    pub fn init() -> Arc<dyn Log> {
-        env_logger::init_from_env(Env::default().default_filter_or("info"));
+    pub fn init(level: LevelFilter) -> Arc<dyn Log> {
+        env_logger::init_from_env(Env::default().default_filter_or("trace"));
+        log::set_max_level(level);
         Arc::new(SuLog {})
     }
 }
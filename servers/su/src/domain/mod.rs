@@ -1,63 +1,474 @@
+use std::collections::BTreeMap;
 use std::sync::Arc;
+use std::time::Duration;
 
+mod admin;
+mod archive;
 mod clients;
 mod config;
 mod core;
 mod logger;
+mod rebalance;
+mod support;
 
 use clients::{
-    gateway::ArweaveGateway, signer::ArweaveSigner, store::StoreClient, uploader::UploaderClient,
+    direct::DirectUploader,
+    gateway::ArweaveGateway,
+    signer::ArweaveSigner,
+    store::StoreClient,
+    uploader::{UploadDeps, UploaderClient},
     wallet::FileWallet,
 };
 use config::AoConfig;
-use core::dal::{Config, Gateway, Log};
+use core::dal::{Config, EventBus, Gateway, Log, Signer, Uploader, UrlResolver};
 use logger::SuLog;
 
+pub use admin::{AdminApi, AdminError};
+pub use archive::{audit_assignments, audit_process, export_process, import_process};
+pub use clients::listener::listen;
+pub use clients::tls::server_tls;
+pub use config::{apply_layers as apply_config_layers, check_config, check_cors};
+pub use core::body::{Body, BodyReader};
+pub use core::dal::FlowError;
+pub use core::deadline::Deadline;
 pub use core::flows;
 pub use core::router;
+pub use core::runtime::RuntimeUpdate;
+pub use core::version::ApiVersion;
 pub use flows::Deps;
+pub use rebalance::RebalanceRequest;
+pub use support::generate_support_bundle;
+
+/*
+    api tokens are managed by the operator from the
+    command line, the store is migrated first in case
+    the server has not run yet
+*/
+pub async fn issue_api_token(
+    owner: Option<String>,
+    process_ids: Vec<String>,
+) -> Result<String, String> {
+    let data_store = StoreClient::new().map_err(|e| format!("{:?}", e))?;
+    data_store.run_migrations()?;
+    core::tokens::issue_token(&data_store, owner, process_ids).await
+}
+
+pub async fn revoke_api_token(token: &str) -> Result<String, String> {
+    let data_store = StoreClient::new().map_err(|e| format!("{:?}", e))?;
+    core::tokens::revoke_token(&data_store, token).await
+}
+
+/*
+    applies pending migrations for deployments running
+    with AUTO_MIGRATE off, refusing a store migrated by
+    a newer version just like startup does
+*/
+pub fn migrate_store() -> Result<String, String> {
+    let data_store = StoreClient::new().map_err(|e| format!("{:?}", e))?;
+    Ok(data_store.check_compatibility(true)?)
+}
+
+// the addresses this su signs with, its own wallet first
+pub fn wallet_addresses() -> Result<String, String> {
+    let config = AoConfig::new(Some("su".to_string())).map_err(|e| format!("{:?}", e))?;
+    let signer = Arc::new(ArweaveSigner::new(&config.su_wallet_path)?);
+    let tenants = init_tenants(&config, signer)?;
+    Ok(tenants.addresses().join("\n"))
+}
+
+fn init_tenants(
+    config: &AoConfig,
+    signer: Arc<dyn Signer>,
+) -> Result<core::tenants::Tenants, String> {
+    let mut tenant_signers: Vec<Arc<dyn Signer>> = vec![];
+    for path in config.tenant_wallet_paths().iter() {
+        tenant_signers.push(Arc::new(ArweaveSigner::new(path)?));
+    }
+    core::tenants::Tenants::new(signer, tenant_signers)
+}
+
+/*
+    su bulk-load, wired like the server with nothing
+    running in the background besides the uploads
+*/
+pub async fn bulk_load(dir: &str) -> Result<String, String> {
+    let config = AoConfig::new(Some("su".to_string())).map_err(|e| format!("{:?}", e))?;
+    // the builder logs every item, only errors are worth printing here
+    let logger = SuLog::init(log::LevelFilter::Error);
+    let lease_holder = format!("{}{}", core::leases::BULK_LOAD_HOLDER, std::process::id());
+    let data_store = Arc::new(
+        StoreClient::new()
+            .map_err(|e| format!("{:?}", e))?
+            .with_lease_holder(lease_holder.clone()),
+    );
+    data_store.check_compatibility(config.auto_migrate())?;
+
+    let signer: Arc<dyn Signer> = Arc::new(ArweaveSigner::new(&config.su_wallet_path)?);
+    let tenants = init_tenants(&config, signer.clone())?;
+    let http = clients::http::http_client(&config)?;
+    let breakers = core::breaker::Breakers::new(&config);
+    let gateway: Arc<dyn Gateway> = Arc::new(
+        ArweaveGateway::new(
+            http.clone(),
+            breakers.gateway.clone(),
+            Duration::from_millis(config.gateway_timeout_ms()),
+        )
+        .await?,
+    );
+    let uploader = init_uploader(
+        &config,
+        signer,
+        UploadDeps {
+            logger: logger.clone(),
+            events: Arc::new(EventBus::new(1024)),
+            data_store: data_store.clone(),
+            gateway: gateway.clone(),
+            client: http,
+            breaker: breakers.uploader.clone(),
+            timeout: Duration::from_millis(config.upload_timeout_ms()),
+        },
+    )?;
+
+    let deps = core::bulkload::BulkLoadDeps {
+        data_store,
+        gateway,
+        uploader,
+        tenants,
+        logger,
+        boot_message_tags: config.boot_message_tags(),
+        lease_holder,
+        lease_ttl_ms: config.process_lease_ms(),
+    };
+    core::bulkload::bulk_load(deps, dir).await
+}
 
 pub async fn init_deps(mode: Option<String>) -> Arc<Deps> {
-    let logger: Arc<dyn Log> = SuLog::init();
+    let config = Arc::new(AoConfig::new(mode).expect("Failed to read configuration"));
+
+    let logger: Arc<dyn Log> =
+        SuLog::init(core::runtime::parse_level(&config.log_level()).expect("Invalid LOG_LEVEL"));
+    for problem in config::invalid_settings() {
+        logger.error(problem);
+    }
 
     let data_store = Arc::new(StoreClient::new().expect("Failed to create StoreClient"));
 
-    match data_store.run_migrations() {
+    let backups =
+        clients::backup::Backups::new(&*config, config.database_url.clone(), logger.clone())
+            .expect("Invalid backup configuration");
+
+    // a fresh database is seeded from the latest backup before it is migrated
+    if let Some(b) = &backups {
+        if config.backup_restore_on_start() {
+            match data_store.is_empty() {
+                Ok(true) => match b.restore_latest().await {
+                    Ok(m) => logger.log(m),
+                    Err(e) => {
+                        logger.error(e);
+                        panic!("Failed to restore the database from backup");
+                    }
+                },
+                Ok(false) => logger.log("Store is not empty, skipping restore".to_string()),
+                Err(e) => logger.error(format!("{:?}", e)),
+            }
+        }
+    }
+
+    // an incompatible store stops the server before anything is written
+    match data_store.check_compatibility(config.auto_migrate()) {
         Ok(m) => logger.log(m),
-        Err(e) => logger.log(format!("{:?}", e)),
+        Err(e) => {
+            logger.error(format!("{:?}", e));
+            panic!("Refusing to start against an incompatible store");
+        }
     }
 
-    let config = Arc::new(AoConfig::new(mode).expect("Failed to read configuration"));
+    let events = Arc::new(EventBus::new(1024));
+    core::events::spawn_log_sink(&events, logger.clone());
+
+    let feeds = Arc::new(core::events::ProcessFeeds::new(
+        config.subscribe_max_per_process(),
+        config.subscribe_max_total(),
+    ));
+    feeds.spawn_relay(&events);
+
+    let nonce_waiters = Arc::new(core::events::NonceWaiters::new(
+        config.min_nonce_max_waiters(),
+    ));
+    nonce_waiters.spawn_relay(&events);
+
+    let ingest = Arc::new(core::ingest::IngestPool::new(
+        config.ingest_queue_depth(),
+        config.ingest_workers(),
+    ));
+
+    let admission = Arc::new(core::admission::WriteAdmission::new(
+        config.max_inflight_writes(),
+        config.write_queue_depth(),
+    ));
+
+    let queues = Arc::new(core::sequencer::ProcessQueues::new(
+        config.process_queue_depth(),
+        admission.clone(),
+    ));
+
+    let http = clients::http::http_client(&*config).expect("Invalid http client settings");
+    let scheduler_http =
+        clients::http::scheduler_client(&*config).expect("Invalid scheduler tls settings");
+    let breakers = Arc::new(core::breaker::Breakers::new(&*config));
+
+    let gateway: Arc<dyn Gateway> = Arc::new(
+        ArweaveGateway::new(
+            http.clone(),
+            breakers.gateway.clone(),
+            Duration::from_millis(config.gateway_timeout_ms()),
+        )
+        .await
+        .expect("Failed to initialize gateway"),
+    );
 
     let scheduler_deps = Arc::new(core::scheduler::SchedulerDeps {
         data_store: data_store.clone(),
+        gateway: gateway.clone(),
         logger: logger.clone(),
+        events: events.clone(),
+        lock_deadline_ms: config.schedule_lock_deadline_ms(),
+        lock_idle_ms: config.schedule_lock_idle_ms(),
+        leases: config.cluster_node_url().map(|node_url| {
+            Arc::new(core::leases::Leases::new(
+                node_url,
+                config.process_lease_ms(),
+            ))
+        }),
     });
     let scheduler = Arc::new(core::scheduler::ProcessScheduler::new(scheduler_deps));
 
-    let gateway: Arc<dyn Gateway> = Arc::new(
-        ArweaveGateway::new()
-            .await
-            .expect("Failed to initialize gateway"),
-    );
-
     let signer =
         Arc::new(ArweaveSigner::new(&config.su_wallet_path).expect("Invalid su wallet path"));
 
     let wallet = Arc::new(FileWallet);
 
-    let uploader = Arc::new(
-        UploaderClient::new(&config.upload_node_url, logger.clone()).expect("Invalid uploader url"),
+    let tenants =
+        Arc::new(init_tenants(&config, signer.clone()).expect("Invalid TENANT_WALLET_PATHS"));
+
+    let uploader = init_uploader(
+        &config,
+        signer.clone(),
+        UploadDeps {
+            logger: logger.clone(),
+            events: events.clone(),
+            data_store: data_store.clone(),
+            gateway: gateway.clone(),
+            client: http.clone(),
+            breaker: breakers.uploader.clone(),
+            timeout: Duration::from_millis(config.upload_timeout_ms()),
+        },
+    )
+    .expect("Failed to initialize uploader");
+
+    let confirmations = Arc::new(core::confirmations::UploadConfirmations::new(
+        config.upload_verify_sample(),
+        config.upload_verify_after_ms(),
+        config.upload_repair_after_ms(),
+        config.upload_verify_timeout_ms(),
+    ));
+
+    let failover = Arc::new(core::failover::StoreFailover::new(
+        config.store_failover_grace_ms(),
+        config.store_failover_queue_size(),
+    ));
+
+    let url_resolver: Arc<dyn UrlResolver> =
+        Arc::new(core::resolver::TemplateResolver::new(&*config));
+
+    let redirects = Arc::new(
+        core::resolver::RedirectPolicy::new(&*config).expect("Invalid redirect configuration"),
     );
 
-    Arc::new(Deps {
+    let cache = Arc::new(core::cache::ReadCache::new(config.cache_max_entries()));
+    core::cache::spawn_cache_sink(&events, cache.clone());
+
+    let throughput = Arc::new(core::throughput::Throughput::new());
+    core::throughput::spawn_throughput_sink(&events, throughput.clone());
+
+    // a standby also follows the primary so its cache stays warm
+    if let Some(events_url) = config.primary_events_url() {
+        clients::standby::spawn_standby_feed(
+            &events_url,
+            config.event_stream_token(),
+            cache.clone(),
+            logger.clone(),
+        )
+        .expect("Invalid PRIMARY_EVENTS_URL");
+    }
+
+    let webhooks = clients::webhooks::spawn_webhooks(&events, &*config, logger.clone())
+        .expect("Invalid WEBHOOK_URLS");
+
+    let outbox = clients::publisher::from_config(&*config)
+        .expect("Invalid event publisher configuration")
+        .map(|publisher| {
+            let relay = Arc::new(core::outbox::OutboxRelay::new(
+                data_store.clone(),
+                publisher,
+                logger.clone(),
+                config.outbox_max_attempts(),
+            ));
+            relay.clone().spawn();
+            relay
+        });
+
+    if let Some(b) = backups {
+        b.spawn(config.backup_interval_ms());
+    }
+
+    let rate_limiter = Arc::new(core::ratelimit::RateLimiter::new(
+        config.rate_limit_per_second(),
+        config.rate_limit_burst(),
+    ));
+
+    let runtime = Arc::new(core::runtime::Runtime::new(
+        core::runtime::RuntimeSettings {
+            rate_limit_per_second: config.rate_limit_per_second(),
+            rate_limit_burst: config.rate_limit_burst().max(1),
+            cache_max_entries: config.cache_max_entries(),
+            log_level: log::max_level().to_string().to_lowercase(),
+            read_only: config.read_only(),
+            scheduler_weights: BTreeMap::new(),
+            scheduler_regions: BTreeMap::new(),
+        },
+    ));
+
+    let proxies = Arc::new(
+        core::proxies::TrustedProxies::new(&config.trusted_proxies())
+            .expect("Invalid TRUSTED_PROXIES"),
+    );
+
+    let tag_policy = Arc::new(
+        core::policy::TagPolicy::from_path(&config.tag_policy_path())
+            .expect("Invalid TAG_POLICY_PATH"),
+    );
+
+    let modules = Arc::new(core::modules::Modules::new(&*config));
+
+    let route_cache = Arc::new(core::routes::RouteCache::new(
+        config.route_cache_ttl_ms(),
+        config.route_cache_max_entries(),
+    ));
+
+    let deps = Arc::new(Deps {
         data_store,
         logger,
         config,
         scheduler,
+        ingest,
+        admission,
+        queues,
         gateway,
         signer,
         wallet,
         uploader,
-    })
+        events,
+        feeds,
+        nonce_waiters,
+        url_resolver,
+        redirects,
+        failover,
+        proxies,
+        cache,
+        rate_limiter,
+        http,
+        scheduler_http,
+        breakers,
+        runtime,
+        route_cache,
+        fleet: Arc::new(core::fleet::FleetStats::new()),
+        throughput,
+        tag_policy,
+        modules,
+        in_flight: Arc::new(core::inflight::InFlightWrites::new()),
+        tenants,
+        confirmations,
+        retention: Arc::new(core::retention::Retention::new()),
+        tiering: Arc::new(core::tiering::Tiering::new()),
+        crons: Arc::new(core::cron::Crons::new()),
+        funding: Arc::new(core::funding::Funding::new()),
+        outbox,
+        webhooks,
+    });
+
+    core::confirmations::spawn_confirmation_checks(deps.clone());
+    core::attestations::spawn_attestations(deps.clone());
+
+    if deps.config.mode() == "su" {
+        core::confirmations::spawn_redelivery(deps.clone());
+    }
+
+    if deps.config.mode() == "su" && deps.config.scheduler_location_url().is_some() {
+        core::location::spawn_location_refresh(deps.clone());
+    }
+
+    if deps.config.mode() == "su"
+        && (deps.config.retention_days() > 0 || deps.config.retention_max_messages() > 0)
+    {
+        core::retention::spawn_retention(deps.clone());
+    }
+
+    if deps.config.mode() == "su" && deps.config.cold_storage_after_days() > 0 {
+        core::tiering::spawn_tiering(deps.clone());
+    }
+
+    if deps.config.mode() == "su" && deps.config.cron_mode() == "sequence" {
+        core::cron::spawn_crons(deps.clone());
+    }
+
+    if deps.config.mode() == "su"
+        && (deps.config.funding_warn_winston() > 0 || deps.config.funding_stop_winston() > 0)
+    {
+        core::funding::spawn_funding_checks(deps.clone());
+    }
+
+    if deps.config.mode() == "router" && deps.config.scheduler_stats_interval_ms() > 0 {
+        core::fleet::spawn_stats_polling(deps.clone());
+    }
+
+    deps
+}
+
+// the uploader UPLOAD_MODE picks, for the server and su bulk-load
+fn init_uploader(
+    config: &AoConfig,
+    signer: Arc<dyn Signer>,
+    deps: UploadDeps,
+) -> Result<Arc<dyn Uploader>, String> {
+    let uploader: Arc<dyn Uploader> = match config.upload_mode().as_str() {
+        "direct" => Arc::new(
+            DirectUploader::new(config, signer, deps)
+                .map_err(|e| format!("Invalid ARWEAVE_NODE_URL: {:?}", e))?,
+        ),
+        _ => Arc::new(
+            UploaderClient::new(
+                &config.upload_node_url,
+                deps,
+                config.upload_chunked_above_bytes() as usize,
+                config.upload_chunk_bytes() as usize,
+            )
+            .map_err(|e| format!("Invalid uploader url: {:?}", e))?,
+        ),
+    };
+    Ok(uploader)
+}
+
+/*
+    re-reads the settings that can change at runtime from
+    the config file and, in router mode, the scheduler list
+    with its weights. Runs on SIGHUP and from the admin api
+*/
+pub async fn reload_runtime(deps: Arc<Deps>) -> Result<String, String> {
+    let update = core::runtime::RuntimeUpdate::from_settings(&config::config_file_settings()?)?;
+    core::runtime::apply(&deps, update)?;
+    if deps.config.mode() == "router" {
+        router::init_schedulers(deps.clone()).await?;
+    }
+    serde_json::to_string(&deps.runtime.settings()).map_err(|e| format!("{}", e))
 }
@@ -0,0 +1,347 @@
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use reqwest::header::CONTENT_TYPE;
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use super::core::flows::Deps;
+use super::core::router::{self, Scheduler};
+
+// how long a su has for one step of a move, exports of big processes take a while
+const STEP_TIMEOUT: Duration = Duration::from_secs(300);
+
+// idle processes asked for per planned move, some may already be gone
+const CANDIDATES_PER_MOVE: usize = 4;
+
+#[derive(Deserialize, Default, Debug)]
+pub struct RebalanceRequest {
+    // defaults to REBALANCE_MAX_MOVES
+    pub max_moves: Option<usize>,
+    // only returns the planned moves
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct PlannedMove {
+    pub from: String,
+    pub to: String,
+}
+
+/*
+    Each scheduler's share of the processes is its part of
+    the total weight. One process at a time moves from the
+    scheduler furthest above its share by more than the
+    tolerance to the one furthest below it, until max_moves
+    or nothing is over. A drained scheduler has a share of
+    0 so all of its processes are planned out.
+*/
+pub fn plan_moves(
+    loads: &[(String, i64, f64)],
+    tolerance: f64,
+    max_moves: usize,
+) -> Vec<PlannedMove> {
+    let total: i64 = loads.iter().map(|(_, count, _)| count).sum();
+    let total_weight: f64 = loads.iter().map(|(_, _, weight)| weight).sum();
+    if total_weight <= 0.0 {
+        return vec![];
+    }
+    let share: Vec<f64> = loads
+        .iter()
+        .map(|(_, _, weight)| total as f64 * weight / total_weight)
+        .collect();
+    let mut counts: Vec<f64> = loads.iter().map(|(_, count, _)| *count as f64).collect();
+
+    let mut moves = vec![];
+    while moves.len() < max_moves {
+        let over = (0..loads.len())
+            .filter(|&i| counts[i] >= 1.0 && counts[i] > share[i] * (1.0 + tolerance))
+            .max_by(|&a, &b| (counts[a] - share[a]).total_cmp(&(counts[b] - share[b])));
+        let under = (0..loads.len())
+            .filter(|&i| loads[i].2 > 0.0 && counts[i] + 1.0 <= share[i] * (1.0 + tolerance))
+            .max_by(|&a, &b| (share[a] - counts[a]).total_cmp(&(share[b] - counts[b])));
+        match (over, under) {
+            (Some(from), Some(to)) if from != to => {
+                counts[from] -= 1.0;
+                counts[to] += 1.0;
+                moves.push(PlannedMove {
+                    from: loads[from].0.clone(),
+                    to: loads[to].0.clone(),
+                });
+            }
+            _ => break,
+        }
+    }
+    moves
+}
+
+/*
+    Router mode. Plans the moves, then for each one takes
+    a process that has been idle for REBALANCE_IDLE_MS on
+    the overloaded su, fences and exports it there, imports
+    it on the underloaded su and routes it there. Calls to the sus
+    use their admin api with SCHEDULER_ADMIN_TOKEN. A su
+    whose last stats poll failed is left out entirely.
+*/
+pub async fn rebalance(deps: &Arc<Deps>, request: RebalanceRequest) -> Result<String, String> {
+    if deps.config.mode() != "router" {
+        return Err("Rebalancing only runs on a router".to_string());
+    }
+    let schedulers = deps.data_store.get_all_schedulers().await?;
+    let loads: Vec<(String, i64, f64)> = schedulers
+        .iter()
+        .filter(|s| deps.fleet.reachable(&s.url))
+        .map(|s| {
+            (
+                s.url.clone(),
+                i64::from(s.process_count),
+                deps.runtime.scheduler_weight(&s.url),
+            )
+        })
+        .collect();
+    let max_moves = request
+        .max_moves
+        .unwrap_or(deps.config.rebalance_max_moves());
+    let plan = plan_moves(&loads, deps.config.rebalance_tolerance(), max_moves);
+    if request.dry_run {
+        return Ok(json!({ "planned": plan }).to_string());
+    }
+
+    let token = deps
+        .config
+        .scheduler_admin_token()
+        .ok_or("SCHEDULER_ADMIN_TOKEN is needed to move processes between sus")?;
+    let client = &deps.scheduler_http;
+
+    let mut candidates: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    let mut moved = vec![];
+    let mut failed = vec![];
+    for planned in plan.iter() {
+        if !candidates.contains_key(&planned.from) {
+            let wanted = plan.iter().filter(|m| m.from == planned.from).count();
+            let found =
+                match idle_processes(deps, client, &token, &schedulers, planned, wanted).await {
+                    Ok(found) => found,
+                    Err(e) => {
+                        deps.logger.error(format!(
+                            "rebalance could not list the idle processes of {} - {}",
+                            planned.from, e
+                        ));
+                        vec![]
+                    }
+                };
+            candidates.insert(planned.from.clone(), found);
+        }
+        let process_id = match candidates.get_mut(&planned.from).and_then(|c| c.pop()) {
+            Some(process_id) => process_id,
+            None => {
+                failed.push(json!({
+                    "from": planned.from,
+                    "to": planned.to,
+                    "error": "no idle process left to move",
+                }));
+                continue;
+            }
+        };
+        match move_process(deps, client, &token, &process_id, planned).await {
+            Ok(()) => {
+                deps.logger.log(format!(
+                    "rebalance moved {} from {} to {}",
+                    process_id, planned.from, planned.to
+                ));
+                moved.push(json!({
+                    "process_id": process_id,
+                    "from": planned.from,
+                    "to": planned.to,
+                }));
+            }
+            Err(e) => {
+                deps.logger.error(format!(
+                    "rebalance failed to move {} from {} to {} - {}",
+                    process_id, planned.from, planned.to, e
+                ));
+                failed.push(json!({
+                    "process_id": process_id,
+                    "from": planned.from,
+                    "to": planned.to,
+                    "error": e,
+                }));
+            }
+        }
+    }
+    Ok(json!({ "moved": moved, "failed": failed }).to_string())
+}
+
+// idle processes of the source su the router also routes there
+async fn idle_processes(
+    deps: &Arc<Deps>,
+    client: &Client,
+    token: &str,
+    schedulers: &[Scheduler],
+    planned: &PlannedMove,
+    wanted: usize,
+) -> Result<Vec<String>, String> {
+    let source_row_id = schedulers
+        .iter()
+        .find(|s| s.url == planned.from)
+        .and_then(|s| s.row_id);
+    let response: Value = client
+        .get(format!("{}/admin/processes/idle", planned.from))
+        .bearer_auth(token)
+        .timeout(STEP_TIMEOUT)
+        .query(&[
+            ("idle_ms", deps.config.rebalance_idle_ms().to_string()),
+            ("limit", (wanted * CANDIDATES_PER_MOVE).to_string()),
+        ])
+        .send()
+        .await
+        .and_then(|r| r.error_for_status())
+        .map_err(|e| format!("{}", e))?
+        .json()
+        .await
+        .map_err(|e| format!("{}", e))?;
+
+    let mut found = vec![];
+    for process_id in response["process_ids"].as_array().into_iter().flatten() {
+        let process_id = match process_id.as_str() {
+            Some(p) => p.to_string(),
+            None => continue,
+        };
+        match deps.data_store.get_process_scheduler(&process_id).await {
+            Ok(ps) if Some(ps.scheduler_row_id) == source_row_id => found.push(process_id),
+            _ => (),
+        }
+    }
+    Ok(found)
+}
+
+/*
+    the source is fenced first so nothing is sequenced
+    there after the export, then the process is exported,
+    imported on the target and routed to it. It stays
+    fenced on the source once routed away, a move that
+    fails before that releases it
+*/
+async fn move_process(
+    deps: &Arc<Deps>,
+    client: &Client,
+    token: &str,
+    process_id: &str,
+    planned: &PlannedMove,
+) -> Result<(), String> {
+    let fence_url = format!("{}/admin/processes/{}/fence", planned.from, process_id);
+    let fenced: Value = client
+        .post(&fence_url)
+        .bearer_auth(token)
+        .timeout(STEP_TIMEOUT)
+        .send()
+        .await
+        .and_then(|r| r.error_for_status())
+        .map_err(|e| format!("fence failed: {}", e))?
+        .json()
+        .await
+        .map_err(|e| format!("fence failed: {}", e))?;
+
+    let result = export_and_route(deps, client, token, process_id, planned, &fenced).await;
+    if result.is_err() {
+        let released = client
+            .delete(&fence_url)
+            .bearer_auth(token)
+            .timeout(STEP_TIMEOUT)
+            .send()
+            .await
+            .and_then(|r| r.error_for_status());
+        if let Err(e) = released {
+            deps.logger.error(format!(
+                "rebalance could not release the fence of {} on {} - {}",
+                process_id, planned.from, e
+            ));
+        }
+    }
+    result
+}
+
+async fn export_and_route(
+    deps: &Arc<Deps>,
+    client: &Client,
+    token: &str,
+    process_id: &str,
+    planned: &PlannedMove,
+    fenced: &Value,
+) -> Result<(), String> {
+    let archive = client
+        .get(format!(
+            "{}/admin/processes/{}/export",
+            planned.from, process_id
+        ))
+        .bearer_auth(token)
+        .timeout(STEP_TIMEOUT)
+        .send()
+        .await
+        .and_then(|r| r.error_for_status())
+        .map_err(|e| format!("export failed: {}", e))?
+        .bytes()
+        .await
+        .map_err(|e| format!("export failed: {}", e))?;
+    // the first line is the process, the rest are its messages
+    let exported = archive
+        .split(|b| *b == b'\n')
+        .filter(|l| !l.is_empty())
+        .count()
+        .saturating_sub(1);
+    // nothing can be sequenced on the source after its fence
+    if let Some(count) = fenced["count"].as_u64().filter(|c| *c as usize > exported) {
+        return Err(format!(
+            "export has {} messages but the fenced source counted {}",
+            exported, count
+        ));
+    }
+
+    client
+        .post(format!("{}/admin/processes/import", planned.to))
+        .bearer_auth(token)
+        .header(CONTENT_TYPE, "application/x-ndjson")
+        .body(archive)
+        .timeout(STEP_TIMEOUT)
+        .send()
+        .await
+        .and_then(|r| r.error_for_status())
+        .map_err(|e| format!("import failed: {}", e))?;
+
+    router::move_process(deps, process_id, &planned.to).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn load(url: &str, count: i64, weight: f64) -> (String, i64, f64) {
+        (url.to_string(), count, weight)
+    }
+
+    #[test]
+    fn test_plan_moves() {
+        let balanced = vec![load("su-1", 10, 1.0), load("su-2", 11, 1.0)];
+        assert!(plan_moves(&balanced, 0.1, 10).is_empty());
+
+        let skewed = vec![load("su-1", 30, 1.0), load("su-2", 10, 1.0)];
+        let plan = plan_moves(&skewed, 0.1, 100);
+        assert_eq!(plan.len(), 8);
+        assert!(plan.iter().all(|m| m.from == "su-1" && m.to == "su-2"));
+        assert_eq!(plan_moves(&skewed, 0.1, 3).len(), 3);
+
+        // a drained scheduler hands everything over
+        let drained = vec![
+            load("su-1", 4, 0.0),
+            load("su-2", 4, 1.0),
+            load("su-3", 4, 1.0),
+        ];
+        let plan = plan_moves(&drained, 0.0, 100);
+        assert_eq!(plan.len(), 4);
+        assert!(plan.iter().all(|m| m.from == "su-1"));
+    }
+}
@@ -0,0 +1,95 @@
+use std::fs;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use reqwest::Client;
+use serde_json::json;
+
+use super::clients::store::StoreClient;
+use super::config::AoConfig;
+
+// how much of the end of the log file goes into a bundle
+const LOG_TAIL_LINES: usize = 500;
+
+// how long the running su has to answer for its metrics
+const METRICS_TIMEOUT: Duration = Duration::from_secs(10);
+
+/*
+    Builds a sanitized support bundle operators can attach
+    to bug reports. It contains version info, the effective
+    configuration minus secrets, store statistics, the
+    tail of the log file and the /metrics of the running
+    su at su_url if they are provided. It is written as a
+    single json document to out_path.
+*/
+pub async fn generate_support_bundle(
+    out_path: &str,
+    log_path: Option<&str>,
+    su_url: Option<&str>,
+) -> Result<String, String> {
+    let config = AoConfig::new(Some("su".to_string())).map_err(|e| format!("{:?}", e))?;
+
+    let generated_at = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_err(|e| format!("{:?}", e))?
+        .as_millis() as u64;
+
+    /*
+        a broken database is a common reason to need a bundle
+        so record the error instead of failing the whole thing
+    */
+    let store_stats = match StoreClient::new().and_then(|store| store.get_store_stats()) {
+        Ok(stats) => json!(stats),
+        Err(e) => json!({ "error": format!("{:?}", e) }),
+    };
+
+    let logs = match log_path {
+        Some(path) => match fs::read_to_string(path) {
+            Ok(contents) => {
+                let lines: Vec<&str> = contents.lines().collect();
+                let start = lines.len().saturating_sub(LOG_TAIL_LINES);
+                json!(lines[start..])
+            }
+            Err(e) => json!({ "error": format!("failed to read log file: {}", e) }),
+        },
+        None => json!(null),
+    };
+
+    let metrics = match su_url {
+        Some(url) => match fetch_metrics(url).await {
+            Ok(metrics) => metrics,
+            Err(e) => json!({ "error": format!("failed to read metrics: {}", e) }),
+        },
+        None => json!(null),
+    };
+
+    let bundle = json!({
+        "generated_at": generated_at,
+        "version": {
+            "name": env!("CARGO_PKG_NAME"),
+            "version": env!("CARGO_PKG_VERSION"),
+        },
+        "config": config.redacted(),
+        "store_stats": store_stats,
+        "metrics": metrics,
+        "logs": logs,
+    });
+
+    let contents = serde_json::to_string_pretty(&bundle).map_err(|e| format!("{:?}", e))?;
+    fs::write(out_path, contents).map_err(|e| format!("failed to write support bundle: {}", e))?;
+
+    Ok(format!("support bundle written to {}", out_path))
+}
+
+async fn fetch_metrics(su_url: &str) -> Result<serde_json::Value, String> {
+    let url = format!("{}/metrics", su_url.trim_end_matches('/'));
+    Client::new()
+        .get(&url)
+        .timeout(METRICS_TIMEOUT)
+        .send()
+        .await
+        .and_then(|r| r.error_for_status())
+        .map_err(|e| format!("{}", e))?
+        .json()
+        .await
+        .map_err(|e| format!("{}", e))
+}
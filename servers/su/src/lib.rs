@@ -1 +1,7 @@
+// the older unit tests build their tags from owned strings
+#![cfg_attr(
+    test,
+    allow(clippy::bool_assert_comparison, clippy::unnecessary_to_owned)
+)]
+
 pub mod domain;
@@ -1,17 +1,38 @@
+use std::convert::Infallible;
 use std::env;
 use std::io::{self, Error, ErrorKind};
+use std::pin::Pin;
 use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Duration;
 
 use actix_cors::Cors;
 use actix_web::{
-    http::header::LOCATION, middleware::Logger, web, App, HttpRequest, HttpResponse, HttpServer,
-    Responder,
+    body::{BodySize, MessageBody},
+    dev::{Decompress, Service, ServiceRequest},
+    error::InternalError,
+    http::header::{
+        ContentEncoding, HeaderName, HeaderValue, AUTHORIZATION, CONTENT_ENCODING, CONTENT_LENGTH,
+        ETAG, IF_NONE_MATCH, LOCATION, VARY,
+    },
+    http::StatusCode,
+    middleware::{Compress, Condition, Logger},
+    rt::signal::unix::{signal, SignalKind},
+    web, App, HttpMessage, HttpRequest, HttpResponse, HttpServer, Responder, ResponseError,
 };
 
+use futures_util::StreamExt;
 use serde::Deserialize;
 use serde_json::json;
+use tokio::sync::mpsc;
 
-use su::domain::{flows, init_deps, router, Deps};
+use su::domain::{
+    apply_config_layers, audit_assignments, audit_process, bulk_load, check_config, check_cors,
+    export_process, flows, generate_support_bundle, import_process, init_deps, issue_api_token,
+    listen, migrate_store, reload_runtime, revoke_api_token, router, server_tls, wallet_addresses,
+    AdminApi, AdminError, ApiVersion, Body, BodyReader, Deadline, Deps, FlowError,
+    RebalanceRequest, RuntimeUpdate,
+};
 
 #[derive(Deserialize)]
 struct FromTo {
@@ -20,6 +41,66 @@ struct FromTo {
     limit: Option<i32>,
     #[serde(rename = "process-id")]
     process_id: Option<String>,
+    // comma separated name:value pairs ex. Action:Transfer
+    tag: Option<String>,
+    // inclusive wall clock window in milliseconds
+    #[serde(rename = "from-timestamp")]
+    from_timestamp: Option<i64>,
+    #[serde(rename = "to-timestamp")]
+    to_timestamp: Option<i64>,
+    // asc (default) or desc
+    sort: Option<String>,
+    // false leaves out virtual cron messages
+    cron: Option<bool>,
+    // waits for the process to reach this nonce
+    #[serde(rename = "min-nonce")]
+    min_nonce: Option<i32>,
+}
+
+#[derive(Deserialize)]
+struct MinNonce {
+    #[serde(rename = "min-nonce")]
+    min_nonce: Option<i32>,
+}
+
+#[derive(Deserialize)]
+struct OwnerPath {
+    owner: String,
+}
+
+#[derive(Deserialize)]
+struct OwnerList {
+    from: Option<String>,
+    limit: Option<i32>,
+}
+
+#[derive(Deserialize)]
+struct LocateRequest {
+    process_ids: Vec<String>,
+    // assign unknown ids as new processes
+    #[serde(default)]
+    assign: bool,
+}
+
+#[derive(Deserialize)]
+struct ModulePath {
+    module: String,
+}
+
+#[derive(Deserialize)]
+struct ModuleList {
+    from: Option<String>,
+    limit: Option<i32>,
+}
+
+#[derive(Deserialize)]
+struct ProcessList {
+    from: Option<String>,
+    limit: Option<i32>,
+    // owner address of the processes
+    owner: Option<String>,
+    // Module tag value of the processes
+    module: Option<String>,
 }
 
 #[derive(Deserialize)]
@@ -38,6 +119,46 @@ struct ProcessIdRequired {
     process_id: String,
 }
 
+#[derive(Deserialize)]
+struct IdleQuery {
+    idle_ms: Option<u64>,
+    limit: Option<i64>,
+}
+
+#[derive(Deserialize)]
+struct DecisionQuery {
+    process_id: Option<String>,
+    limit: Option<i64>,
+}
+
+#[derive(Deserialize)]
+struct CheckpointQuery {
+    epoch: Option<i32>,
+    nonce: Option<i32>,
+    limit: Option<i32>,
+}
+
+#[derive(Deserialize)]
+struct AssignmentQuery {
+    epoch: Option<i32>,
+    nonce: Option<i32>,
+    limit: Option<i32>,
+    #[serde(rename = "min-nonce")]
+    min_nonce: Option<i32>,
+}
+
+#[derive(Deserialize)]
+struct AttestationQuery {
+    limit: Option<i32>,
+}
+
+#[derive(Deserialize)]
+struct ProcessNonce {
+    process_id: String,
+    epoch: i32,
+    nonce: i32,
+}
+
 #[derive(Deserialize)]
 struct OptionalAssign {
     #[serde(rename = "process-id")]
@@ -49,6 +170,44 @@ struct OptionalAssign {
     exclude: Option<String>,
 }
 
+/*
+    browser clients calling the su directly, by default any
+    origin may call it with any method and header
+*/
+fn cors(deps: &Arc<Deps>) -> Cors {
+    let origins = deps.config.cors_allowed_origins();
+    let methods = deps.config.cors_allowed_methods();
+    let headers = deps.config.cors_allowed_headers();
+
+    let mut cors = match origins.is_empty() || origins.iter().any(|o| o == "*") {
+        true => Cors::default().allow_any_origin(),
+        false => origins
+            .iter()
+            .fold(Cors::default(), |cors, origin| cors.allowed_origin(origin)),
+    };
+    cors = match methods.is_empty() || methods.iter().any(|m| m == "*") {
+        true => cors.allow_any_method(),
+        false => cors.allowed_methods(methods.iter().map(|m| m.as_str())),
+    };
+    cors = match headers.is_empty() || headers.iter().any(|h| h == "*") {
+        true => cors.allow_any_header(),
+        false => cors.allowed_headers(headers.iter().map(|h| h.as_str())),
+    };
+    let max_age = match deps.config.cors_max_age_secs() {
+        0 => None,
+        secs => Some(secs as usize),
+    };
+    cors.max_age(max_age).expose_headers(vec![
+        "SU-Version",
+        "ETag",
+        "X-Su-Timestamp",
+        "X-Su-Digest",
+        "X-Su-Signature",
+        "X-Su-Address",
+        "X-Su-Key",
+    ])
+}
+
 fn err_response(err: String) -> HttpResponse {
     let error_json = json!({ "error": err });
     HttpResponse::BadRequest()
@@ -56,6 +215,233 @@ fn err_response(err: String) -> HttpResponse {
         .body(error_json.to_string())
 }
 
+/*
+    flow errors carry what went wrong, clients and load
+    balancers can tell a bad request from an overloaded su
+*/
+fn flow_err_response(err: FlowError) -> HttpResponse {
+    let mut response = match err {
+        FlowError::Validation(_) => HttpResponse::BadRequest(),
+        FlowError::NotFound(_) => HttpResponse::NotFound(),
+        FlowError::Conflict(_) => HttpResponse::Conflict(),
+        FlowError::Forbidden(_) => HttpResponse::Forbidden(),
+        FlowError::RateLimited(_) => HttpResponse::TooManyRequests(),
+        FlowError::Unavailable(_) => HttpResponse::ServiceUnavailable(),
+        FlowError::Upstream(_) => HttpResponse::BadGateway(),
+        FlowError::Internal(_) => HttpResponse::InternalServerError(),
+    };
+    let error_json = json!({ "error": err.message() });
+    response
+        .content_type("application/json")
+        .body(error_json.to_string())
+}
+
+// lets middleware turn a flow error into its response
+#[derive(Debug)]
+struct FlowErrorResponse(FlowError);
+
+impl std::fmt::Display for FlowErrorResponse {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl ResponseError for FlowErrorResponse {
+    fn error_response(&self) -> HttpResponse {
+        flow_err_response(self.0.clone())
+    }
+}
+
+/*
+    a 304 when the client already has the read tagged
+    etag, If-None-Match compares weak tags
+*/
+fn not_modified_response(req: &HttpRequest, etag: &Option<String>) -> Option<HttpResponse> {
+    let etag = etag.as_ref()?;
+    let given = req.headers().get(IF_NONE_MATCH)?.to_str().ok()?;
+    let matches = given
+        .split(',')
+        .map(str::trim)
+        .any(|tag| tag == "*" || tag.trim_start_matches("W/") == etag.trim_start_matches("W/"));
+    match matches {
+        true => Some(
+            HttpResponse::NotModified()
+                .insert_header((ETAG, etag.clone()))
+                .finish(),
+        ),
+        false => None,
+    }
+}
+
+/*
+    a json read response, with the headers of its receipt
+    when SIGN_READS is on
+*/
+async fn read_response(
+    deps: &Arc<Deps>,
+    process_id: Option<&String>,
+    etag: Option<String>,
+    body: String,
+) -> HttpResponse {
+    let receipt = match flows::sign_read(deps, process_id, body.as_bytes()).await {
+        Ok(receipt) => receipt,
+        Err(err) => return flow_err_response(err),
+    };
+    let mut response = HttpResponse::Ok();
+    if let Some(etag) = etag {
+        response.insert_header((ETAG, etag));
+    }
+    for header in receipt.map(|r| r.headers()).unwrap_or_default() {
+        response.insert_header(header);
+    }
+    response.content_type("application/json").body(body)
+}
+
+fn too_large_response(err: String) -> HttpResponse {
+    let error_json = json!({ "error": err });
+    HttpResponse::PayloadTooLarge()
+        .content_type("application/json")
+        .body(error_json.to_string())
+}
+
+/*
+    redirect to the scheduler, the stored url is
+    resolved to the target for this client first and
+    the status is the one configured for the route
+*/
+fn redirect_response(deps: &Arc<Deps>, redirect_url: String, req: &HttpRequest) -> HttpResponse {
+    let request_host = req.connection_info().host().to_string();
+    let resolved_url = match deps.url_resolver.resolve(&redirect_url, &request_host) {
+        Ok(resolved_url) => resolved_url,
+        Err(err) => return err_response(err),
+    };
+
+    let target_url = format!("{}{}", resolved_url, req.uri());
+    let pattern = req
+        .match_pattern()
+        .unwrap_or_else(|| req.path().to_string());
+    let status = deps.redirects.status(req.method().as_str(), &pattern);
+    let mut response =
+        HttpResponse::build(StatusCode::from_u16(status).unwrap_or(StatusCode::TEMPORARY_REDIRECT));
+    response.insert_header((LOCATION, target_url.clone()));
+    match deps.redirects.body() {
+        true => response
+            .content_type("application/json")
+            .body(json!({ "location": target_url }).to_string()),
+        false => response.finish(),
+    }
+}
+
+// the region a router request says it comes from, in ROUTER_REGION_HEADER
+fn request_region(deps: &Arc<Deps>, req: &HttpRequest) -> Option<String> {
+    req.headers()
+        .get(deps.config.router_region_header().as_str())
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.trim().to_string())
+        .filter(|v| !v.is_empty())
+}
+
+/*
+    the real client address, X-Forwarded-For and PROXY
+    headers are only used when the peer is one of
+    TRUSTED_PROXIES
+*/
+fn client_ip(deps: &Arc<Deps>, req: &ServiceRequest) -> String {
+    let forwarded_for = req
+        .headers()
+        .get("X-Forwarded-For")
+        .and_then(|v| v.to_str().ok());
+    match req.peer_addr() {
+        Some(peer) => deps
+            .proxies
+            .client_ip(deps.proxies.peer_ip(peer), forwarded_for)
+            .to_string(),
+        None => "-".to_string(),
+    }
+}
+
+/*
+    the configured deadline, shortened by the client with
+    X-Request-Deadline-Ms. if the client disconnects actix
+    drops the request future so the work stops there too
+*/
+fn request_deadline(deps: &Arc<Deps>, req: &ServiceRequest) -> Deadline {
+    let configured = Deadline::after_ms(deps.config.request_deadline_ms());
+    match req
+        .headers()
+        .get("X-Request-Deadline-Ms")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<u64>().ok())
+    {
+        Some(ms) if ms > 0 => configured.min(Deadline::after_ms(ms)),
+        _ => configured,
+    }
+}
+
+/*
+    the response shape asked for with Accept-Version,
+    every response names the one it got in SU-Version
+*/
+fn negotiate_version(req: &ServiceRequest) -> Result<ApiVersion, FlowError> {
+    ApiVersion::from_header(
+        req.headers()
+            .get("Accept-Version")
+            .and_then(|v| v.to_str().ok()),
+    )
+}
+
+fn api_version(req: &HttpRequest) -> ApiVersion {
+    req.extensions()
+        .get::<ApiVersion>()
+        .copied()
+        .unwrap_or_default()
+}
+
+/*
+    actix decompresses gzip, brotli and zstd request
+    bodies before the size limits apply, unless the su
+    is set to turn compressed bodies away
+*/
+fn check_content_encoding(deps: &Arc<Deps>, req: &ServiceRequest) -> Result<(), actix_web::Error> {
+    if deps.config.accept_compressed_requests() {
+        return Ok(());
+    }
+    match req
+        .headers()
+        .get(CONTENT_ENCODING)
+        .and_then(|v| v.to_str().ok())
+    {
+        None | Some("identity") => Ok(()),
+        Some(encoding) => {
+            let error_json =
+                json!({ "error": format!("Content-Encoding {} is not accepted", encoding) });
+            let response = HttpResponse::UnsupportedMediaType()
+                .content_type("application/json")
+                .body(error_json.to_string());
+            Err(InternalError::from_response("compressed request body", response).into())
+        }
+    }
+}
+
+// Authorization: Bearer <token>, only used on a restricted su
+fn bearer_token(req: &HttpRequest) -> Option<String> {
+    req.headers()
+        .get(AUTHORIZATION)?
+        .to_str()
+        .ok()?
+        .strip_prefix("Bearer ")
+        .map(|t| t.trim().to_string())
+}
+
+// a signed read for a private process, see access.rs
+fn signed_read(req: &HttpRequest) -> Option<String> {
+    req.headers()
+        .get("X-Signed-Read")?
+        .to_str()
+        .ok()
+        .map(|s| s.trim().to_string())
+}
+
 async fn base(
     deps: web::Data<Arc<Deps>>,
     query_params: web::Query<ProcessId>,
@@ -64,21 +450,16 @@ async fn base(
     let process_id = query_params.process_id.clone();
 
     match router::redirect_process_id(deps.get_ref().clone(), process_id).await {
-        Ok(Some(redirect_url)) => {
-            let target_url = format!("{}{}", redirect_url, req.uri());
-            return HttpResponse::TemporaryRedirect()
-                .insert_header((LOCATION, target_url))
-                .finish();
-        }
+        Ok(Some(redirect_url)) => return redirect_response(&deps, redirect_url, &req),
         Ok(None) => (),
-        Err(err) => return err_response(err.to_string()),
+        Err(err) => return flow_err_response(err),
     }
 
     match flows::health(deps.get_ref().clone()).await {
         Ok(processed_str) => HttpResponse::Ok()
             .content_type("application/json")
             .body(processed_str),
-        Err(err) => err_response(err.to_string()),
+        Err(err) => flow_err_response(err),
     }
 }
 
@@ -90,62 +471,123 @@ async fn timestamp_route(
     let process_id = query_params.process_id.clone();
 
     match router::redirect_process_id(deps.get_ref().clone(), process_id).await {
-        Ok(Some(redirect_url)) => {
-            let target_url = format!("{}{}", redirect_url, req.uri());
-            return HttpResponse::TemporaryRedirect()
-                .insert_header((LOCATION, target_url))
-                .finish();
-        }
+        Ok(Some(redirect_url)) => return redirect_response(&deps, redirect_url, &req),
         Ok(None) => (),
-        Err(err) => return err_response(err.to_string()),
+        Err(err) => return flow_err_response(err),
     }
 
     match flows::timestamp(deps.get_ref().clone()).await {
         Ok(processed_str) => HttpResponse::Ok()
             .content_type("application/json")
             .body(processed_str),
-        Err(err) => err_response(err.to_string()),
+        Err(err) => flow_err_response(err),
+    }
+}
+
+/*
+    the body of a write, read and decompressed as it
+    arrives so an item over its limit is turned away
+    without waiting for the rest of it
+*/
+async fn read_write_body(
+    deps: &Arc<Deps>,
+    req: &HttpRequest,
+    payload: web::Payload,
+) -> Result<Body, HttpResponse> {
+    // the length of a compressed body says nothing about the item
+    let expected = match req.headers().contains_key(CONTENT_ENCODING) {
+        true => None,
+        false => req
+            .headers()
+            .get(CONTENT_LENGTH)
+            .and_then(|length| length.to_str().ok())
+            .and_then(|length| length.parse::<usize>().ok()),
+    };
+    let mut reader = BodyReader::new(&*deps.config, expected).map_err(too_large_response)?;
+    let mut payload = Decompress::from_headers(payload, req.headers());
+    while let Some(chunk) = payload.next().await {
+        let chunk = chunk.map_err(|e| err_response(format!("Failed to read body: {}", e)))?;
+        reader.push(&chunk).map_err(too_large_response)?;
     }
+    Ok(reader.finish())
 }
 
 async fn main_post_route(
     deps: web::Data<Arc<Deps>>,
-    req_body: web::Bytes,
+    payload: web::Payload,
     req: HttpRequest,
     query_params: web::Query<OptionalAssign>,
 ) -> impl Responder {
+    let req_body = match read_write_body(&deps, &req, payload).await {
+        Ok(body) => body,
+        Err(response) => return response,
+    };
+
     match router::redirect_data_item(
         deps.get_ref().clone(),
-        req_body.to_vec(),
+        req_body.bytes.clone(),
         query_params.process_id.clone(),
         query_params.assign.clone(),
+        request_region(&deps, &req),
     )
     .await
     {
-        Ok(Some(redirect_url)) => {
-            let target_url = format!("{}{}", redirect_url, req.uri());
-            return HttpResponse::TemporaryRedirect()
-                .insert_header((LOCATION, target_url))
-                .finish();
-        }
+        Ok(Some(redirect_url)) => return redirect_response(&deps, redirect_url, &req),
+        Ok(None) => (),
+        Err(err) => return flow_err_response(err),
+    }
+
+    match flows::cluster_redirect(&deps, &req_body, &query_params.process_id).await {
+        Ok(Some(leader_url)) => return redirect_response(&deps, leader_url, &req),
         Ok(None) => (),
-        Err(err) => return err_response(err.to_string()),
+        Err(err) => return flow_err_response(err),
     }
 
     match flows::write_item(
         deps.get_ref().clone(),
-        req_body.to_vec(),
+        req_body,
         query_params.process_id.clone(),
         query_params.assign.clone(),
-        query_params.base_layer.clone(),
-        query_params.exclude.clone(),
+        flows::AssignParams {
+            base_layer: query_params.base_layer.clone(),
+            exclude: query_params.exclude.clone(),
+        },
+        bearer_token(&req),
+        api_version(&req),
     )
     .await
     {
         Ok(processed_str) => HttpResponse::Ok()
             .content_type("application/json")
             .body(processed_str),
-        Err(err) => err_response(err.to_string()),
+        Err(err) => flow_err_response(err),
+    }
+}
+
+async fn validate_route(
+    deps: web::Data<Arc<Deps>>,
+    req_body: web::Bytes,
+    req: HttpRequest,
+) -> impl Responder {
+    match router::redirect_data_item(
+        deps.get_ref().clone(),
+        req_body.clone(),
+        None,
+        None,
+        request_region(&deps, &req),
+    )
+    .await
+    {
+        Ok(Some(redirect_url)) => return redirect_response(&deps, redirect_url, &req),
+        Ok(None) => (),
+        Err(err) => return flow_err_response(err),
+    }
+
+    match flows::validate_item(deps.get_ref().clone(), req_body, bearer_token(&req)).await {
+        Ok(report) => HttpResponse::Ok()
+            .content_type("application/json")
+            .body(report),
+        Err(err) => flow_err_response(err),
     }
 }
 
@@ -156,121 +598,1149 @@ async fn main_get_route(
     query_params: web::Query<FromTo>,
 ) -> impl Responder {
     let tx_id = path.tx_id.clone();
-    let from_sort_key = query_params.from.clone();
-    let to_sort_key = query_params.to.clone();
-    let limit = query_params.limit.clone();
     let process_id = query_params.process_id.clone();
+    let cron = query_params.cron;
 
     match router::redirect_tx_id(deps.get_ref().clone(), tx_id.clone(), process_id.clone()).await {
-        Ok(Some(redirect_url)) => {
-            let target_url = format!("{}{}", redirect_url, req.uri());
-            return HttpResponse::TemporaryRedirect()
-                .insert_header((LOCATION, target_url))
-                .finish();
-        }
+        Ok(Some(redirect_url)) => return redirect_response(&deps, redirect_url, &req),
         Ok(None) => (),
-        Err(err) => return err_response(err.to_string()),
+        Err(err) => return flow_err_response(err),
+    }
+
+    let signed = signed_read(&req);
+    let read_process_id = process_id.clone().unwrap_or(tx_id.clone());
+    if let Err(err) = flows::authorize_route_read(&deps, &read_process_id, &signed).await {
+        return flow_err_response(err);
+    }
+    // the etag is taken from tx_id, which can be another process
+    if read_process_id != tx_id {
+        if let Err(err) = flows::authorize_route_read(&deps, &tx_id, &signed).await {
+            return flow_err_response(err);
+        }
+    }
+    if let Err(err) = flows::wait_for_nonce(&deps, &read_process_id, query_params.min_nonce).await {
+        return flow_err_response(err);
+    }
+
+    let etag = match flows::process_etag(deps.get_ref().clone(), tx_id.clone()).await {
+        Ok(etag) => etag,
+        Err(err) => return flow_err_response(err),
+    };
+    if let Some(response) = not_modified_response(&req, &etag) {
+        return response;
+    }
+
+    let query = match flows::message_query(
+        query_params.from.clone(),
+        query_params.to.clone(),
+        query_params.limit,
+        query_params.tag.clone(),
+        query_params.from_timestamp,
+        query_params.to_timestamp,
+        query_params.sort.clone(),
+    ) {
+        Ok(query) => query,
+        Err(err) => return flow_err_response(err),
+    };
+    let result =
+        flows::read_message_data(deps.get_ref().clone(), tx_id.clone(), query, signed, cron).await;
+
+    match result {
+        Ok(processed_str) => {
+            let process_id = process_id.unwrap_or(tx_id);
+            read_response(&deps, Some(&process_id), etag, processed_str).await
+        }
+        Err(err) => flow_err_response(err),
     }
+}
 
-    let result = flows::read_message_data(
+async fn read_processes_route(
+    deps: web::Data<Arc<Deps>>,
+    query_params: web::Query<ProcessList>,
+) -> impl Responder {
+    match flows::read_processes(
         deps.get_ref().clone(),
-        tx_id,
-        from_sort_key,
-        to_sort_key,
-        limit,
+        query_params.from.clone(),
+        query_params.limit,
+        query_params.owner.clone(),
+        query_params.module.clone(),
     )
-    .await;
+    .await
+    {
+        Ok(processed_str) => HttpResponse::Ok()
+            .content_type("application/json")
+            .body(processed_str),
+        Err(err) => flow_err_response(err),
+    }
+}
 
-    match result {
+async fn read_owner_processes_route(
+    deps: web::Data<Arc<Deps>>,
+    path: web::Path<OwnerPath>,
+    query_params: web::Query<OwnerList>,
+) -> impl Responder {
+    match flows::read_owner_processes(
+        deps.get_ref().clone(),
+        path.owner.clone(),
+        query_params.from.clone(),
+        query_params.limit,
+    )
+    .await
+    {
         Ok(processed_str) => HttpResponse::Ok()
             .content_type("application/json")
             .body(processed_str),
-        Err(err) => err_response(err.to_string()),
+        Err(err) => flow_err_response(err),
     }
 }
 
-async fn read_process_route(
+async fn locate_processes_route(
     deps: web::Data<Arc<Deps>>,
     req: HttpRequest,
-    path: web::Path<ProcessIdRequired>,
+    body: web::Json<LocateRequest>,
 ) -> impl Responder {
-    let process_id = path.process_id.clone();
-
-    match router::redirect_process_id(deps.get_ref().clone(), Some(process_id.clone())).await {
-        Ok(Some(redirect_url)) => {
-            let target_url = format!("{}{}", redirect_url, req.uri());
-            return HttpResponse::TemporaryRedirect()
-                .insert_header((LOCATION, target_url))
-                .finish();
-        }
-        Ok(None) => (),
-        Err(err) => return err_response(err.to_string()),
+    let body = body.into_inner();
+    match router::locate_processes(
+        deps.get_ref().clone(),
+        body.process_ids,
+        body.assign,
+        request_region(&deps, &req),
+    )
+    .await
+    {
+        Ok(processed_str) => HttpResponse::Ok()
+            .content_type("application/json")
+            .body(processed_str),
+        Err(err) => err_response(err.to_string()),
     }
+}
 
-    match flows::read_process(deps.get_ref().clone(), process_id).await {
+async fn read_module_processes_route(
+    deps: web::Data<Arc<Deps>>,
+    path: web::Path<ModulePath>,
+    query_params: web::Query<ModuleList>,
+) -> impl Responder {
+    match flows::read_module_processes(
+        deps.get_ref().clone(),
+        path.module.clone(),
+        query_params.from.clone(),
+        query_params.limit,
+    )
+    .await
+    {
         Ok(processed_str) => HttpResponse::Ok()
             .content_type("application/json")
             .body(processed_str),
-        Err(err) => err_response(err.to_string()),
+        Err(err) => flow_err_response(err),
     }
 }
 
-async fn health_check() -> impl Responder {
-    HttpResponse::Ok()
+async fn read_owner_messages_route(
+    deps: web::Data<Arc<Deps>>,
+    path: web::Path<OwnerPath>,
+    query_params: web::Query<OwnerList>,
+) -> impl Responder {
+    match flows::read_owner_messages(
+        deps.get_ref().clone(),
+        path.owner.clone(),
+        query_params.from.clone(),
+        query_params.limit,
+    )
+    .await
+    {
+        Ok(processed_str) => HttpResponse::Ok()
+            .content_type("application/json")
+            .body(processed_str),
+        Err(err) => flow_err_response(err),
+    }
 }
 
-#[actix_web::main]
-async fn main() -> io::Result<()> {
-    let args: Vec<String> = env::args().collect();
-    let mode = match args.get(1) {
-        Some(m) => Some(m.clone()),
-        None => None,
-    };
+async fn read_bundle_route(
+    deps: web::Data<Arc<Deps>>,
+    req: HttpRequest,
+    path: web::Path<TxId>,
+    query_params: web::Query<ProcessId>,
+) -> impl Responder {
+    let tx_id = path.tx_id.clone();
 
-    let port = match args.get(2) {
-        Some(port_str) => match port_str.parse::<u16>() {
-            Ok(num) => num,
-            Err(_) => {
-                let err = Error::new(ErrorKind::InvalidInput, "Port number is not valid");
-                return Err(err);
-            }
-        },
-        None => {
-            let err = Error::new(ErrorKind::InvalidInput, "Port argument not provided");
-            return Err(err);
-        }
+    match router::redirect_tx_id(
+        deps.get_ref().clone(),
+        tx_id.clone(),
+        query_params.process_id.clone(),
+    )
+    .await
+    {
+        Ok(Some(redirect_url)) => return redirect_response(&deps, redirect_url, &req),
+        Ok(None) => (),
+        Err(err) => return flow_err_response(err),
+    }
+
+    let signed = signed_read(&req);
+    // a signed bundle never changes once sequenced, shared caches keep only public ones
+    let cache_control = match signed {
+        Some(_) => "private, max-age=31536000, immutable",
+        None => "public, max-age=31536000, immutable",
     };
+    match flows::read_bundle(deps.get_ref().clone(), tx_id, signed).await {
+        Ok(bundle) => HttpResponse::Ok()
+            .content_type("application/octet-stream")
+            .insert_header(("Cache-Control", cache_control))
+            .body(bundle),
+        Err(err) => flow_err_response(err),
+    }
+}
 
-    let wrapped = web::Data::new(init_deps(mode).await);
+async fn locate_message_route(
+    deps: web::Data<Arc<Deps>>,
+    req: HttpRequest,
+    path: web::Path<TxId>,
+) -> impl Responder {
+    let tx_id = path.tx_id.clone();
 
-    let run_deps = wrapped.get_ref().clone();
+    match router::redirect_tx_id(deps.get_ref().clone(), tx_id.clone(), None).await {
+        Ok(Some(redirect_url)) => return redirect_response(&deps, redirect_url, &req),
+        Ok(None) => (),
+        Err(err) => return flow_err_response(err),
+    }
 
-    if run_deps.config.mode() == "router" {
+    match flows::locate_message(deps.get_ref().clone(), tx_id, signed_read(&req)).await {
+        Ok(processed_str) => HttpResponse::Ok()
+            .content_type("application/json")
+            .body(processed_str),
+        Err(err) => flow_err_response(err),
+    }
+}
+
+async fn read_upload_receipt_route(
+    deps: web::Data<Arc<Deps>>,
+    path: web::Path<TxId>,
+) -> impl Responder {
+    match flows::read_upload_receipt(deps.get_ref().clone(), path.tx_id.clone()).await {
+        Ok(processed_str) => HttpResponse::Ok()
+            .content_type("application/json")
+            .body(processed_str),
+        Err(err) => flow_err_response(err),
+    }
+}
+
+async fn read_process_route(
+    deps: web::Data<Arc<Deps>>,
+    req: HttpRequest,
+    path: web::Path<ProcessIdRequired>,
+) -> impl Responder {
+    let process_id = path.process_id.clone();
+
+    match router::redirect_process_id(deps.get_ref().clone(), Some(process_id.clone())).await {
+        Ok(Some(redirect_url)) => return redirect_response(&deps, redirect_url, &req),
+        Ok(None) => (),
+        Err(err) => return flow_err_response(err),
+    }
+
+    let signed = signed_read(&req);
+    if let Err(err) = flows::authorize_route_read(&deps, &process_id, &signed).await {
+        return flow_err_response(err);
+    }
+    let etag = match flows::process_etag(deps.get_ref().clone(), process_id.clone()).await {
+        Ok(etag) => etag,
+        Err(err) => return flow_err_response(err),
+    };
+    if let Some(response) = not_modified_response(&req, &etag) {
+        return response;
+    }
+
+    match flows::read_process(
+        deps.get_ref().clone(),
+        process_id.clone(),
+        signed,
+        api_version(&req),
+    )
+    .await
+    {
+        Ok(processed_str) => read_response(&deps, Some(&process_id), etag, processed_str).await,
+        Err(err) => flow_err_response(err),
+    }
+}
+
+async fn read_message_by_nonce_route(
+    deps: web::Data<Arc<Deps>>,
+    req: HttpRequest,
+    path: web::Path<ProcessNonce>,
+) -> impl Responder {
+    let process_id = path.process_id.clone();
+
+    match router::redirect_process_id(deps.get_ref().clone(), Some(process_id.clone())).await {
+        Ok(Some(redirect_url)) => return redirect_response(&deps, redirect_url, &req),
+        Ok(None) => (),
+        Err(err) => return flow_err_response(err),
+    }
+
+    match flows::read_message_by_nonce(
+        deps.get_ref().clone(),
+        process_id.clone(),
+        path.epoch,
+        path.nonce,
+        signed_read(&req),
+    )
+    .await
+    {
+        Ok(processed_str) => read_response(&deps, Some(&process_id), None, processed_str).await,
+        Err(err) => flow_err_response(err),
+    }
+}
+
+async fn read_message_count_route(
+    deps: web::Data<Arc<Deps>>,
+    req: HttpRequest,
+    path: web::Path<ProcessIdRequired>,
+    query_params: web::Query<MinNonce>,
+) -> impl Responder {
+    let process_id = path.process_id.clone();
+
+    match router::redirect_process_id(deps.get_ref().clone(), Some(process_id.clone())).await {
+        Ok(Some(redirect_url)) => return redirect_response(&deps, redirect_url, &req),
+        Ok(None) => (),
+        Err(err) => return flow_err_response(err),
+    }
+    let signed = signed_read(&req);
+    if let Err(err) = flows::authorize_route_read(&deps, &process_id, &signed).await {
+        return flow_err_response(err);
+    }
+    if let Err(err) = flows::wait_for_nonce(&deps, &process_id, query_params.min_nonce).await {
+        return flow_err_response(err);
+    }
+
+    match flows::read_message_count(deps.get_ref().clone(), process_id, signed).await {
+        Ok(processed_str) => HttpResponse::Ok()
+            .content_type("application/json")
+            .body(processed_str),
+        Err(err) => flow_err_response(err),
+    }
+}
+
+async fn read_checkpoints_route(
+    deps: web::Data<Arc<Deps>>,
+    req: HttpRequest,
+    path: web::Path<ProcessIdRequired>,
+    query_params: web::Query<CheckpointQuery>,
+) -> impl Responder {
+    let process_id = path.process_id.clone();
+
+    match router::redirect_process_id(deps.get_ref().clone(), Some(process_id.clone())).await {
+        Ok(Some(redirect_url)) => return redirect_response(&deps, redirect_url, &req),
+        Ok(None) => (),
+        Err(err) => return flow_err_response(err),
+    }
+
+    match flows::read_checkpoints(
+        deps.get_ref().clone(),
+        process_id,
+        query_params.epoch,
+        query_params.nonce,
+        query_params.limit,
+        signed_read(&req),
+    )
+    .await
+    {
+        Ok(processed_str) => HttpResponse::Ok()
+            .content_type("application/json")
+            .body(processed_str),
+        Err(err) => flow_err_response(err),
+    }
+}
+
+async fn register_checkpoint_route(
+    deps: web::Data<Arc<Deps>>,
+    req: HttpRequest,
+    path: web::Path<ProcessIdRequired>,
+    req_body: web::Bytes,
+) -> impl Responder {
+    let process_id = path.process_id.clone();
+
+    match router::redirect_process_id(deps.get_ref().clone(), Some(process_id.clone())).await {
+        Ok(Some(redirect_url)) => return redirect_response(&deps, redirect_url, &req),
+        Ok(None) => (),
+        Err(err) => return flow_err_response(err),
+    }
+
+    match flows::register_checkpoint(
+        deps.get_ref().clone(),
+        process_id,
+        req_body.to_vec(),
+        bearer_token(&req),
+    )
+    .await
+    {
+        Ok(processed_str) => HttpResponse::Ok()
+            .content_type("application/json")
+            .body(processed_str),
+        Err(err) => flow_err_response(err),
+    }
+}
+
+async fn read_bulk_route(deps: web::Data<Arc<Deps>>, req_body: web::Bytes) -> impl Responder {
+    match flows::read_bulk(deps.get_ref().clone(), req_body.to_vec()).await {
+        Ok(processed_str) => read_response(&deps, None, None, processed_str).await,
+        Err(err) => flow_err_response(err),
+    }
+}
+
+async fn read_assignments_route(
+    deps: web::Data<Arc<Deps>>,
+    req: HttpRequest,
+    path: web::Path<ProcessIdRequired>,
+    query_params: web::Query<AssignmentQuery>,
+) -> impl Responder {
+    let process_id = path.process_id.clone();
+
+    match router::redirect_process_id(deps.get_ref().clone(), Some(process_id.clone())).await {
+        Ok(Some(redirect_url)) => return redirect_response(&deps, redirect_url, &req),
+        Ok(None) => (),
+        Err(err) => return flow_err_response(err),
+    }
+    let signed = signed_read(&req);
+    if let Err(err) = flows::authorize_route_read(&deps, &process_id, &signed).await {
+        return flow_err_response(err);
+    }
+    if let Err(err) = flows::wait_for_nonce(&deps, &process_id, query_params.min_nonce).await {
+        return flow_err_response(err);
+    }
+
+    match flows::read_assignments(
+        deps.get_ref().clone(),
+        process_id,
+        query_params.epoch,
+        query_params.nonce,
+        query_params.limit,
+        signed,
+    )
+    .await
+    {
+        Ok(processed_str) => HttpResponse::Ok()
+            .content_type("application/json")
+            .body(processed_str),
+        Err(err) => flow_err_response(err),
+    }
+}
+
+async fn read_attestations_route(
+    deps: web::Data<Arc<Deps>>,
+    req: HttpRequest,
+    path: web::Path<ProcessIdRequired>,
+    query_params: web::Query<AttestationQuery>,
+) -> impl Responder {
+    let process_id = path.process_id.clone();
+
+    match router::redirect_process_id(deps.get_ref().clone(), Some(process_id.clone())).await {
+        Ok(Some(redirect_url)) => return redirect_response(&deps, redirect_url, &req),
+        Ok(None) => (),
+        Err(err) => return flow_err_response(err),
+    }
+
+    match flows::read_attestations(
+        deps.get_ref().clone(),
+        process_id,
+        query_params.limit,
+        signed_read(&req),
+    )
+    .await
+    {
+        Ok(processed_str) => HttpResponse::Ok()
+            .content_type("application/json")
+            .body(processed_str),
+        Err(err) => flow_err_response(err),
+    }
+}
+
+async fn read_latest_message_route(
+    deps: web::Data<Arc<Deps>>,
+    req: HttpRequest,
+    path: web::Path<ProcessIdRequired>,
+    query_params: web::Query<MinNonce>,
+) -> impl Responder {
+    let process_id = path.process_id.clone();
+
+    match router::redirect_process_id(deps.get_ref().clone(), Some(process_id.clone())).await {
+        Ok(Some(redirect_url)) => return redirect_response(&deps, redirect_url, &req),
+        Ok(None) => (),
+        Err(err) => return flow_err_response(err),
+    }
+    let signed = signed_read(&req);
+    if let Err(err) = flows::authorize_route_read(&deps, &process_id, &signed).await {
+        return flow_err_response(err);
+    }
+    if let Err(err) = flows::wait_for_nonce(&deps, &process_id, query_params.min_nonce).await {
+        return flow_err_response(err);
+    }
+
+    match flows::read_latest_message(deps.get_ref().clone(), process_id.clone(), signed).await {
+        Ok(processed_str) => read_response(&deps, Some(&process_id), None, processed_str).await,
+        Err(err) => flow_err_response(err),
+    }
+}
+
+/*
+    streams the json lines of the event feed, the
+    feed stops once the client goes away and this
+    body is dropped
+*/
+struct EventFeedBody {
+    feed: tokio::sync::mpsc::Receiver<String>,
+}
+
+impl MessageBody for EventFeedBody {
+    type Error = Infallible;
+
+    fn size(&self) -> BodySize {
+        BodySize::Stream
+    }
+
+    fn poll_next(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<web::Bytes, Self::Error>>> {
+        self.feed
+            .poll_recv(cx)
+            .map(|line| line.map(|l| Ok(web::Bytes::from(l))))
+    }
+}
+
+async fn events_route(deps: web::Data<Arc<Deps>>, req: HttpRequest) -> impl Responder {
+    match flows::event_feed(deps.get_ref().clone(), bearer_token(&req)) {
+        Ok(feed) => HttpResponse::Ok()
+            .content_type("application/x-ndjson")
+            // compressing would hold events back in the encoder
+            .insert_header(ContentEncoding::Identity)
+            .body(EventFeedBody { feed }),
+        Err(err) => flow_err_response(err),
+    }
+}
+
+async fn subscribe_route(
+    deps: web::Data<Arc<Deps>>,
+    req: HttpRequest,
+    path: web::Path<ProcessIdRequired>,
+) -> impl Responder {
+    let process_id = path.process_id.clone();
+
+    match router::redirect_process_id(deps.get_ref().clone(), Some(process_id.clone())).await {
+        Ok(Some(redirect_url)) => return redirect_response(&deps, redirect_url, &req),
+        Ok(None) => (),
+        Err(err) => return flow_err_response(err),
+    }
+
+    match flows::subscribe_process(deps.get_ref().clone(), process_id, signed_read(&req)).await {
+        Ok(feed) => HttpResponse::Ok()
+            .content_type("text/event-stream")
+            .insert_header(("Cache-Control", "no-cache"))
+            .insert_header(ContentEncoding::Identity)
+            .body(EventFeedBody { feed }),
+        Err(err) => flow_err_response(err),
+    }
+}
+
+async fn metrics_route(deps: web::Data<Arc<Deps>>) -> impl Responder {
+    match flows::metrics(deps.get_ref().clone()) {
+        Ok(processed_str) => HttpResponse::Ok()
+            .content_type("application/json")
+            .body(processed_str),
+        Err(err) => flow_err_response(err),
+    }
+}
+
+#[derive(Deserialize)]
+struct RegisterScheduler {
+    url: String,
+}
+
+fn admin_response(result: Result<String, AdminError>) -> HttpResponse {
+    match result {
+        Ok(processed_str) => HttpResponse::Ok()
+            .content_type("application/json")
+            .body(processed_str),
+        Err(AdminError::Unauthorized(err)) => HttpResponse::Unauthorized()
+            .content_type("application/json")
+            .body(json!({ "error": err }).to_string()),
+        Err(AdminError::Failed(err)) => err_response(err),
+    }
+}
+
+async fn admin_config_route(admin: web::Data<AdminApi>, req: HttpRequest) -> impl Responder {
+    admin_response(admin.config(bearer_token(&req)))
+}
+
+async fn admin_register_scheduler_route(
+    admin: web::Data<AdminApi>,
+    req: HttpRequest,
+    body: web::Json<RegisterScheduler>,
+) -> impl Responder {
+    admin_response(
+        admin
+            .register_scheduler(bearer_token(&req), body.url.clone())
+            .await,
+    )
+}
+
+async fn admin_move_process_route(
+    admin: web::Data<AdminApi>,
+    req: HttpRequest,
+    path: web::Path<ProcessIdRequired>,
+    body: web::Json<RegisterScheduler>,
+) -> impl Responder {
+    admin_response(
+        admin
+            .move_process(
+                bearer_token(&req),
+                path.process_id.clone(),
+                body.url.clone(),
+            )
+            .await,
+    )
+}
+
+async fn admin_export_process_route(
+    admin: web::Data<AdminApi>,
+    req: HttpRequest,
+    path: web::Path<ProcessIdRequired>,
+) -> impl Responder {
+    match admin
+        .export_process(bearer_token(&req), path.process_id.clone())
+        .await
+    {
+        Ok(archive) => HttpResponse::Ok()
+            .content_type("application/x-ndjson")
+            .body(archive),
+        Err(err) => admin_response(Err(err)),
+    }
+}
+
+async fn admin_fence_process_route(
+    admin: web::Data<AdminApi>,
+    req: HttpRequest,
+    path: web::Path<ProcessIdRequired>,
+) -> impl Responder {
+    admin_response(
+        admin
+            .fence_process(bearer_token(&req), path.process_id.clone())
+            .await,
+    )
+}
+
+async fn admin_unfence_process_route(
+    admin: web::Data<AdminApi>,
+    req: HttpRequest,
+    path: web::Path<ProcessIdRequired>,
+) -> impl Responder {
+    admin_response(admin.unfence_process(bearer_token(&req), path.process_id.clone()))
+}
+
+async fn admin_import_process_route(
+    admin: web::Data<AdminApi>,
+    req: HttpRequest,
+    body: web::Bytes,
+) -> impl Responder {
+    admin_response(
+        admin
+            .import_process(bearer_token(&req), body.to_vec())
+            .await,
+    )
+}
+
+async fn admin_idle_processes_route(
+    admin: web::Data<AdminApi>,
+    req: HttpRequest,
+    query: web::Query<IdleQuery>,
+) -> impl Responder {
+    admin_response(
+        admin
+            .idle_processes(
+                bearer_token(&req),
+                query.idle_ms.unwrap_or(3600000),
+                query.limit.unwrap_or(100),
+            )
+            .await,
+    )
+}
+
+async fn admin_rebalance_route(
+    admin: web::Data<AdminApi>,
+    req: HttpRequest,
+    body: Option<web::Json<RebalanceRequest>>,
+) -> impl Responder {
+    let request = body.map(|b| b.into_inner()).unwrap_or_default();
+    admin_response(admin.rebalance(bearer_token(&req), request).await)
+}
+
+async fn admin_stats_route(admin: web::Data<AdminApi>, req: HttpRequest) -> impl Responder {
+    admin_response(admin.stats(bearer_token(&req)).await)
+}
+
+async fn admin_fleet_route(admin: web::Data<AdminApi>, req: HttpRequest) -> impl Responder {
+    admin_response(admin.fleet(bearer_token(&req)).await)
+}
+
+async fn admin_assignment_decisions_route(
+    admin: web::Data<AdminApi>,
+    req: HttpRequest,
+    query: web::Query<DecisionQuery>,
+) -> impl Responder {
+    let query = query.into_inner();
+    admin_response(
+        admin
+            .assignment_decisions(
+                bearer_token(&req),
+                query.process_id,
+                query.limit.unwrap_or(100),
+            )
+            .await,
+    )
+}
+
+async fn admin_flush_cache_route(admin: web::Data<AdminApi>, req: HttpRequest) -> impl Responder {
+    admin_response(admin.flush_cache(bearer_token(&req)))
+}
+
+async fn admin_backfill_route(
+    admin: web::Data<AdminApi>,
+    req: HttpRequest,
+    path: web::Path<ProcessIdRequired>,
+) -> impl Responder {
+    admin_response(admin.backfill(bearer_token(&req), path.process_id.clone()))
+}
+
+async fn admin_publish_location_route(
+    admin: web::Data<AdminApi>,
+    req: HttpRequest,
+) -> impl Responder {
+    admin_response(admin.publish_location(bearer_token(&req)).await)
+}
+
+async fn admin_unconfirmed_uploads_route(
+    admin: web::Data<AdminApi>,
+    req: HttpRequest,
+) -> impl Responder {
+    admin_response(admin.unconfirmed_uploads(bearer_token(&req)))
+}
+
+async fn admin_runtime_route(admin: web::Data<AdminApi>, req: HttpRequest) -> impl Responder {
+    admin_response(admin.runtime(bearer_token(&req)))
+}
+
+async fn admin_update_runtime_route(
+    admin: web::Data<AdminApi>,
+    req: HttpRequest,
+    body: web::Json<RuntimeUpdate>,
+) -> impl Responder {
+    admin_response(admin.update_runtime(bearer_token(&req), body.into_inner()))
+}
+
+async fn admin_reload_runtime_route(
+    admin: web::Data<AdminApi>,
+    req: HttpRequest,
+) -> impl Responder {
+    admin_response(admin.reload_runtime(bearer_token(&req)).await)
+}
+
+async fn admin_audit_route(admin: web::Data<AdminApi>, req: HttpRequest) -> impl Responder {
+    admin_response(admin.audit(bearer_token(&req)))
+}
+
+// liveness, the process is up and serving requests
+async fn health_check() -> impl Responder {
+    HttpResponse::Ok()
+}
+
+// readiness, 503 until every dependency checks out
+async fn readiness_route(deps: web::Data<Arc<Deps>>) -> impl Responder {
+    let (ready, status) = flows::readiness(deps.get_ref().clone()).await;
+    let mut response = match ready {
+        true => HttpResponse::Ok(),
+        false => HttpResponse::ServiceUnavailable(),
+    };
+    response.content_type("application/json").body(status)
+}
+
+// receives on every SIGTERM or SIGINT
+fn shutdown_signals() -> io::Result<mpsc::Receiver<()>> {
+    let (tx, rx) = mpsc::channel::<()>(2);
+    for kind in [SignalKind::terminate(), SignalKind::interrupt()] {
+        let mut stream = signal(kind)?;
+        let tx = tx.clone();
+        actix_web::rt::spawn(async move {
+            while stream.recv().await.is_some() {
+                let _ = tx.send(()).await;
+            }
+        });
+    }
+    Ok(rx)
+}
+
+// reloads the runtime settings on every SIGHUP
+fn spawn_reload_signal(deps: Arc<Deps>) -> io::Result<()> {
+    let mut stream = signal(SignalKind::hangup())?;
+    actix_web::rt::spawn(async move {
+        while stream.recv().await.is_some() {
+            match reload_runtime(deps.clone()).await {
+                Ok(settings) => deps
+                    .logger
+                    .log(format!("reloaded runtime settings {}", settings)),
+                Err(e) => deps.logger.error(format!("reload failed - {}", e)),
+            }
+        }
+    });
+    Ok(())
+}
+
+const USAGE: &str = "Usage:
+  su start <su|router> <port>
+  su audit <process-id> [assignments]
+  su export <process-id> <out-file>
+  su import <in-file>
+  su bulk-load <items-dir>
+  su migrate-db
+  su wallet address
+  su config check [su|router]
+  su api-token issue <owner|processes> <value>
+  su api-token revoke <token>
+  su support-bundle <out-file> [log-file] [su-url]
+Any command takes --config <toml-file> and --<setting> <value> flags";
+
+/*
+    the operator commands, they read the same environment
+    as the server and print their result. None when the
+    arguments are for starting the server
+*/
+async fn run_command(args: &[String]) -> Option<Result<String, String>> {
+    let arg = |i: usize| args.get(i).map(|a| a.as_str());
+    let result = match arg(1)? {
+        // the url of the running su is told from the log file by its scheme
+        "support-bundle" => match arg(2) {
+            Some(out_path) => {
+                let is_url = |a: &str| a.starts_with("http://") || a.starts_with("https://");
+                let optional: Vec<&str> = [arg(3), arg(4)].into_iter().flatten().collect();
+                let su_url = optional.iter().find(|a| is_url(a)).copied();
+                let log_path = optional.iter().find(|a| !is_url(a)).copied();
+                generate_support_bundle(out_path, log_path, su_url).await
+            }
+            None => Err("Usage: su support-bundle <out-file> [log-file] [su-url]".to_string()),
+        },
+        "audit" => match (arg(2), arg(3)) {
+            (Some(process_id), None) => audit_process(process_id),
+            (Some(process_id), Some("assignments")) => audit_assignments(process_id),
+            _ => Err("Usage: su audit <process-id> [assignments]".to_string()),
+        },
+        "export" => match (arg(2), arg(3)) {
+            (Some(process_id), Some(out_path)) => export_process(process_id, out_path).await,
+            _ => Err("Usage: su export <process-id> <out-file>".to_string()),
+        },
+        "import" => match arg(2) {
+            Some(in_path) => import_process(in_path).await,
+            None => Err("Usage: su import <in-file>".to_string()),
+        },
+        "bulk-load" => match arg(2) {
+            Some(dir) => bulk_load(dir).await,
+            None => Err("Usage: su bulk-load <items-dir>".to_string()),
+        },
+        "migrate-db" => migrate_store(),
+        "wallet" => match arg(2) {
+            Some("address") => wallet_addresses(),
+            _ => Err("Usage: su wallet address".to_string()),
+        },
+        "config" => match arg(2) {
+            Some("check") => check_config(arg(3)),
+            _ => Err("Usage: su config check [su|router]".to_string()),
+        },
+        // su api-token issue owner <address> | su api-token issue processes <id,id>
+        // su api-token revoke <token>
+        "api-token" => match (arg(2), arg(3), args.get(4)) {
+            (Some("issue"), Some("owner"), Some(owner)) => {
+                issue_api_token(Some(owner.clone()), vec![]).await
+            }
+            (Some("issue"), Some("processes"), Some(ids)) => {
+                issue_api_token(
+                    None,
+                    ids.split(',')
+                        .map(|id| id.trim().to_string())
+                        .filter(|id| !id.is_empty())
+                        .collect(),
+                )
+                .await
+            }
+            (Some("revoke"), Some(token), None) => revoke_api_token(token).await,
+            _ => Err(
+                "Usage: su api-token issue <owner|processes> <value> | su api-token revoke <token>"
+                    .to_string(),
+            ),
+        },
+        "help" | "--help" | "-h" => Ok(USAGE.to_string()),
+        _ => return None,
+    };
+    Some(result)
+}
+
+#[actix_web::main]
+async fn main() -> io::Result<()> {
+    let mut args = match apply_config_layers(env::args().collect()) {
+        Ok(args) => args,
+        Err(e) => return Err(Error::new(ErrorKind::InvalidInput, e)),
+    };
+
+    // su start <su|router> <port>, deployments still run su <su|router> <port>
+    if args.get(1).map(|a| a.as_str()) == Some("start") {
+        args.remove(1);
+    }
+
+    match run_command(&args).await {
+        Some(Ok(m)) => {
+            println!("{}", m);
+            return Ok(());
+        }
+        Some(Err(e)) => return Err(Error::other(e)),
+        None => (),
+    }
+
+    let mode = args.get(1).cloned();
+
+    let port = match args.get(2) {
+        Some(port_str) => match port_str.parse::<u16>() {
+            Ok(num) => num,
+            Err(_) => {
+                let err = Error::new(ErrorKind::InvalidInput, "Port number is not valid");
+                return Err(err);
+            }
+        },
+        None => {
+            let err = Error::new(
+                ErrorKind::InvalidInput,
+                format!("Port argument not provided\n{}", USAGE),
+            );
+            return Err(err);
+        }
+    };
+
+    let wrapped = web::Data::new(init_deps(mode).await);
+
+    let run_deps = wrapped.get_ref().clone();
+
+    if run_deps.config.mode() == "router" {
         match router::init_schedulers(run_deps.clone()).await {
-            Err(e) => run_deps.logger.log(format!("{}", e)),
-            Ok(m) => run_deps.logger.log(format!("{}", m)),
+            Err(e) => run_deps.logger.log(e.to_string()),
+            Ok(m) => run_deps.logger.log(m.to_string()),
         };
     }
 
-    HttpServer::new(move || {
+    let listener = listen(
+        &*run_deps.config,
+        port,
+        run_deps.proxies.clone(),
+        run_deps.logger.clone(),
+    )?;
+
+    // the largest body any write may have, the rest is checked per item
+    let payload_limit = run_deps
+        .config
+        .max_item_size()
+        .max(run_deps.config.max_process_size());
+
+    let admin = web::Data::new(AdminApi::new(run_deps.clone()));
+    let max_import_size = run_deps.config.max_import_size();
+
+    // gzip, brotli or zstd as the client accepts it
+    let compress_responses = run_deps.config.compress_responses();
+
+    // CUs polling often reuse their connections instead of reconnecting
+    let keep_alive = Duration::from_millis(run_deps.config.keep_alive_ms());
+
+    let tls = server_tls(&*run_deps.config).map_err(|e| Error::new(ErrorKind::InvalidInput, e))?;
+    check_cors(&*run_deps.config).map_err(|e| Error::new(ErrorKind::InvalidInput, e))?;
+
+    let server = HttpServer::new(move || {
+        let log_deps = wrapped.get_ref().clone();
+        let deadline_deps = wrapped.get_ref().clone();
+        let encoding_deps = wrapped.get_ref().clone();
+        let cors_deps = wrapped.get_ref().clone();
         App::new()
+            .wrap_fn(move |req, srv| request_deadline(&deadline_deps, &req).scope(srv.call(req)))
+            .wrap_fn(move |req, srv| {
+                let checked = check_content_encoding(&encoding_deps, &req).map(|_| srv.call(req));
+                async move { checked?.await }
+            })
+            .wrap_fn(|req, srv| {
+                let negotiated = negotiate_version(&req).map(|version| {
+                    req.extensions_mut().insert(version);
+                    (version, srv.call(req))
+                });
+                async move {
+                    let (version, call) = negotiated.map_err(FlowErrorResponse)?;
+                    let mut res = call.await?;
+                    res.headers_mut().insert(
+                        HeaderName::from_static("su-version"),
+                        HeaderValue::from(version.number()),
+                    );
+                    // the same url answers in another shape under another version
+                    res.headers_mut()
+                        .append(VARY, HeaderValue::from_static("Accept-Version"));
+                    Ok(res)
+                }
+            })
+            .wrap(cors(&cors_deps))
+            .wrap(Condition::new(compress_responses, Compress::default()))
             .wrap(
-                Cors::default()
-                    .allow_any_origin()
-                    .allow_any_method()
-                    .allow_any_header(),
+                Logger::new(r#"%{client_ip}xi "%r" %s %b "%{Referer}i" "%{User-Agent}i" %T"#)
+                    .custom_request_replace("client_ip", move |req| client_ip(&log_deps, req)),
             )
-            .wrap(Logger::default())
             .app_data(wrapped.clone())
-            .app_data(web::PayloadConfig::new(10485760))
+            .app_data(admin.clone())
+            .app_data(web::PayloadConfig::new(payload_limit))
             .route("/", web::get().to(base))
             .route("/", web::post().to(main_post_route))
+            .route("/validate", web::post().to(validate_route))
             .route("/timestamp", web::get().to(timestamp_route))
             .route("/health", web::get().to(health_check))
+            .route("/health/live", web::get().to(health_check))
+            .route("/health/ready", web::get().to(readiness_route))
+            .route("/events", web::get().to(events_route))
+            .route("/metrics", web::get().to(metrics_route))
+            .route("/locations", web::post().to(locate_processes_route))
+            .route("/messages/bulk", web::post().to(read_bulk_route))
+            .route(
+                "/messages/{tx_id}/assignments",
+                web::get().to(locate_message_route),
+            )
+            .route(
+                "/receipts/{tx_id}",
+                web::get().to(read_upload_receipt_route),
+            )
+            .route("/admin/config", web::get().to(admin_config_route))
+            .route(
+                "/admin/schedulers",
+                web::post().to(admin_register_scheduler_route),
+            )
+            .route(
+                "/admin/cache/flush",
+                web::post().to(admin_flush_cache_route),
+            )
+            .route("/admin/audit", web::get().to(admin_audit_route))
+            .route("/admin/stats", web::get().to(admin_stats_route))
+            .route("/admin/fleet", web::get().to(admin_fleet_route))
+            .route(
+                "/admin/assignments",
+                web::get().to(admin_assignment_decisions_route),
+            )
+            .route("/admin/runtime", web::get().to(admin_runtime_route))
+            .route("/admin/runtime", web::post().to(admin_update_runtime_route))
+            .route(
+                "/admin/runtime/reload",
+                web::post().to(admin_reload_runtime_route),
+            )
+            .route(
+                "/admin/uploads/unconfirmed",
+                web::get().to(admin_unconfirmed_uploads_route),
+            )
+            .route(
+                "/admin/scheduler-location",
+                web::post().to(admin_publish_location_route),
+            )
+            .route(
+                "/admin/processes/idle",
+                web::get().to(admin_idle_processes_route),
+            )
+            .service(
+                web::resource("/admin/processes/import")
+                    .app_data(web::PayloadConfig::new(max_import_size))
+                    .route(web::post().to(admin_import_process_route)),
+            )
+            .route(
+                "/admin/processes/{process_id}/export",
+                web::get().to(admin_export_process_route),
+            )
+            .route(
+                "/admin/processes/{process_id}/fence",
+                web::post().to(admin_fence_process_route),
+            )
+            .route(
+                "/admin/processes/{process_id}/fence",
+                web::delete().to(admin_unfence_process_route),
+            )
+            .route("/admin/rebalance", web::post().to(admin_rebalance_route))
+            .route(
+                "/admin/processes/{process_id}/scheduler",
+                web::post().to(admin_move_process_route),
+            )
+            .route(
+                "/admin/processes/{process_id}/backfill",
+                web::post().to(admin_backfill_route),
+            )
+            .route("/processes", web::get().to(read_processes_route))
             .route("/{tx_id}", web::get().to(main_get_route))
+            .route("/{tx_id}/bundle", web::get().to(read_bundle_route))
             .route("/processes/{process_id}", web::get().to(read_process_route))
+            .route(
+                "/processes/{process_id}/count",
+                web::get().to(read_message_count_route),
+            )
+            .route(
+                "/processes/{process_id}/latest",
+                web::get().to(read_latest_message_route),
+            )
+            .route(
+                "/processes/{process_id}/subscribe",
+                web::get().to(subscribe_route),
+            )
+            .route(
+                "/processes/{process_id}/checkpoints",
+                web::get().to(read_checkpoints_route),
+            )
+            .route(
+                "/processes/{process_id}/checkpoints",
+                web::post().to(register_checkpoint_route),
+            )
+            .route(
+                "/processes/{process_id}/assignments",
+                web::get().to(read_assignments_route),
+            )
+            .route(
+                "/processes/{process_id}/attestations",
+                web::get().to(read_attestations_route),
+            )
+            .route(
+                "/processes/{process_id}/{epoch}/{nonce}",
+                web::get().to(read_message_by_nonce_route),
+            )
+            .route(
+                "/owners/{owner}/processes",
+                web::get().to(read_owner_processes_route),
+            )
+            .route(
+                "/owners/{owner}/messages",
+                web::get().to(read_owner_messages_route),
+            )
+            .route(
+                "/modules/{module}/processes",
+                web::get().to(read_module_processes_route),
+            )
     })
-    .bind(("0.0.0.0", port))?
-    .run()
-    .await
+    .keep_alive(keep_alive);
+
+    let server = match tls {
+        Some(tls) => server.listen_openssl(listener, tls)?,
+        None => server.listen_auto_h2c(listener)?,
+    }
+    .disable_signals()
+    .run();
+
+    /*
+        writes are drained while the workers still run,
+        their uploads are tasks on the worker runtimes
+        and would be dropped along with them
+    */
+    let handle = server.handle();
+    spawn_reload_signal(run_deps.clone())?;
+    let mut signals = shutdown_signals()?;
+    actix_web::rt::spawn(async move {
+        signals.recv().await;
+        flows::shutdown(run_deps).await;
+        handle.stop(true).await;
+    });
+
+    server.await
 }